@@ -0,0 +1,344 @@
+//! Compilation and execution times of representative programs.
+//!
+//! Each workload gets its own group, measuring how long the program takes to compile,
+//! and then to run once compiled, with the arithmetic on words lowered as 256-bit
+//! integers, the default, and as 64-bit limbs. With the `revm` feature, the group also
+//! measures revm running the same code, as a transaction calling it.
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use evm_mlir::{
+    db::InMemoryStorage,
+    env::{Address, Env},
+    executor::Executor,
+    options::{CompileOptions, WordRepr},
+    program::{Operation, Program},
+    spec::SpecId,
+    syscall::{SyscallContext, U256},
+};
+use num_bigint::BigUint;
+
+const GAS: u64 = 100_000_000;
+
+const SENDER: Address = [0x10; 20];
+const CONTRACT: Address = [0x20; 20];
+
+/// `keccak256("Transfer(address,address,uint256)")`
+const TRANSFER_TOPIC: [u8; 32] = [
+    0xdd, 0xf2, 0x52, 0xad, 0x1b, 0xe2, 0xc8, 0x9b, 0x69, 0xc2, 0xb0, 0x68, 0xfc, 0x37, 0x8d, 0xaa,
+    0x95, 0x2b, 0xa7, 0xf1, 0x63, 0xc4, 0xa1, 0x16, 0x28, 0xf5, 0x5a, 0x4d, 0xf5, 0x23, 0xb3, 0xef,
+];
+
+/// A program run by [`SENDER`] as the code of [`CONTRACT`]
+struct Workload {
+    name: &'static str,
+    program: Program,
+    calldata: Vec<u8>,
+    /// The storage of the contract before running it
+    storage: Vec<(U256, U256)>,
+}
+
+impl Workload {
+    fn new(name: &'static str, operations: Vec<Operation>, calldata: Vec<u8>) -> Self {
+        Self {
+            name,
+            program: Program::from(operations),
+            calldata,
+            storage: Vec::new(),
+        }
+    }
+
+    fn syscall_context(&self) -> SyscallContext {
+        let mut env = Env::default();
+        env.call.address = CONTRACT;
+        env.call.caller = SENDER;
+        env.tx.origin = SENDER;
+        env.tx.gas_limit = GAS;
+        let storage: InMemoryStorage = self
+            .storage
+            .iter()
+            .map(|(key, value)| (CONTRACT, *key, *value))
+            .collect();
+        SyscallContext::new(env, self.calldata.clone(), Box::new(storage))
+    }
+}
+
+/// Returns the calldata holding the given words
+fn calldata(words: &[U256]) -> Vec<u8> {
+    words.iter().flat_map(U256::to_be_bytes).collect()
+}
+
+fn push(value: u8) -> Operation {
+    Operation::Push(BigUint::from(value))
+}
+
+/// Computes the Nth Fibonacci number, with N given in the calldata
+fn fibonacci(n: u128) -> Workload {
+    let operations = vec![
+        // [00] n
+        Operation::Push0,
+        Operation::CallDataLoad,
+        // [02] n, a = 0, b = 1
+        Operation::Push0,
+        push(1),
+        // [05] while n != 0
+        Operation::Jumpdest { pc: 5 },
+        Operation::Dup(3),
+        Operation::IsZero,
+        push(23),
+        Operation::Jumpi,
+        // [11] a, b = b, a + b
+        Operation::Swap(1),
+        Operation::Dup(2),
+        Operation::Add,
+        // [14] n -= 1
+        Operation::Swap(2),
+        push(1),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Swap(2),
+        // [20]
+        push(5),
+        Operation::Jump,
+        // [23] return a
+        Operation::Jumpdest { pc: 23 },
+        Operation::Pop,
+        Operation::Push0,
+        Operation::Mstore,
+        push(32),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    Workload::new("fibonacci", operations, calldata(&[U256::from(n)]))
+}
+
+/// Computes the factorial of N, given in the calldata, wrapping on overflow
+fn factorial(n: u128) -> Workload {
+    let operations = vec![
+        // [00] n, acc = 1
+        Operation::Push0,
+        Operation::CallDataLoad,
+        push(1),
+        // [04] while n != 0
+        Operation::Jumpdest { pc: 4 },
+        Operation::Dup(2),
+        Operation::IsZero,
+        push(21),
+        Operation::Jumpi,
+        // [10] acc *= n
+        Operation::Dup(2),
+        Operation::Mul,
+        // [12] n -= 1
+        Operation::Swap(1),
+        push(1),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Swap(1),
+        // [18]
+        push(4),
+        Operation::Jump,
+        // [21] return acc
+        Operation::Jumpdest { pc: 21 },
+        Operation::Push0,
+        Operation::Mstore,
+        push(32),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    Workload::new("factorial", operations, calldata(&[U256::from(n)]))
+}
+
+/// Hashes the first word of memory into itself N times, with N given in the calldata
+fn keccak_loop(n: u128) -> Workload {
+    let operations = vec![
+        // [00] n
+        Operation::Push0,
+        Operation::CallDataLoad,
+        // [02] while n != 0
+        Operation::Jumpdest { pc: 2 },
+        Operation::Dup(1),
+        Operation::IsZero,
+        push(21),
+        Operation::Jumpi,
+        // [08] memory[0..32] = keccak256(memory[0..32])
+        push(32),
+        Operation::Push0,
+        Operation::Keccak256,
+        Operation::Push0,
+        Operation::Mstore,
+        // [14] n -= 1
+        push(1),
+        Operation::Swap(1),
+        Operation::Sub,
+        // [18]
+        push(2),
+        Operation::Jump,
+        // [21] return memory[0..32]
+        Operation::Jumpdest { pc: 21 },
+        push(32),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    Workload::new("keccak_loop", operations, calldata(&[U256::from(n)]))
+}
+
+/// Moves an amount between the balances of the caller and a recipient, both given in
+/// the calldata, emitting a `Transfer` event like an ERC-20 token.
+/// Balances are stored under the address of their owner
+fn erc20_transfer() -> Workload {
+    let operations = vec![
+        // [00] balance, amount
+        Operation::Caller,
+        Operation::Sload,
+        push(32),
+        Operation::CallDataLoad,
+        // [05] revert if balance < amount
+        Operation::Dup(1),
+        Operation::Dup(3),
+        Operation::Lt,
+        push(68),
+        Operation::Jumpi,
+        // [11] balances[caller] = balance - amount
+        Operation::Swap(1),
+        Operation::Dup(2),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Caller,
+        Operation::Sstore,
+        // [17] balances[to] += amount
+        Operation::Push0,
+        Operation::CallDataLoad,
+        Operation::Dup(1),
+        Operation::Sload,
+        Operation::Dup(3),
+        Operation::Add,
+        Operation::Swap(1),
+        Operation::Sstore,
+        // [25] emit Transfer(caller, to, amount)
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push0,
+        Operation::CallDataLoad,
+        Operation::Caller,
+        Operation::Push(BigUint::from_bytes_be(&TRANSFER_TOPIC)),
+        push(32),
+        Operation::Push0,
+        Operation::Log(3),
+        // [67]
+        Operation::Stop,
+        // [68]
+        Operation::Jumpdest { pc: 68 },
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ];
+    let recipient = U256::from([0x30; 20]);
+    let mut workload = Workload::new(
+        "erc20_transfer",
+        operations,
+        calldata(&[recipient, U256::from(1000)]),
+    );
+    workload.storage = vec![
+        (U256::from(SENDER), U256::from(1_000_000)),
+        (recipient, U256::from(5)),
+    ];
+    workload
+}
+
+fn workloads() -> Vec<Workload> {
+    vec![
+        fibonacci(1000),
+        factorial(1000),
+        keccak_loop(1000),
+        erc20_transfer(),
+    ]
+}
+
+fn bench_workloads(c: &mut Criterion) {
+    for workload in workloads() {
+        let mut group = c.benchmark_group(workload.name);
+
+        group.bench_function("compile", |b| {
+            b.iter(|| Executor::compile(&workload.program, SpecId::default()).unwrap())
+        });
+
+        let executor = Executor::compile(&workload.program, SpecId::default()).unwrap();
+        group.bench_function("evm_mlir", |b| {
+            b.iter_batched(
+                || workload.syscall_context(),
+                |mut context| executor.execute(&mut context, GAS),
+                BatchSize::SmallInput,
+            )
+        });
+
+        // The same code, with the arithmetic on words lowered by 64-bit limbs
+        let options = CompileOptions {
+            word_repr: WordRepr::Limbs64,
+            ..Default::default()
+        };
+        let executor =
+            Executor::compile_with_options(&workload.program, SpecId::default(), &options).unwrap();
+        group.bench_function("evm_mlir_limbs64", |b| {
+            b.iter_batched(
+                || workload.syscall_context(),
+                |mut context| executor.execute(&mut context, GAS),
+                BatchSize::SmallInput,
+            )
+        });
+
+        #[cfg(feature = "revm")]
+        {
+            let mut evm = revm_runner::evm(&workload);
+            group.bench_function("revm", |b| b.iter(|| evm.transact().unwrap()));
+        }
+
+        group.finish();
+    }
+}
+
+#[cfg(feature = "revm")]
+mod revm_runner {
+    use revm::{
+        db::{CacheDB, EmptyDB},
+        primitives::{AccountInfo, Address, Bytecode, Bytes, TxKind, U256},
+        Evm,
+    };
+
+    use super::{Workload, CONTRACT, GAS, SENDER};
+
+    fn to_revm_u256(value: &evm_mlir::syscall::U256) -> U256 {
+        U256::from_be_bytes(value.to_be_bytes())
+    }
+
+    /// Returns an EVM calling the workload's code as a transaction, which isn't
+    /// committed, so it can be run again from the same state
+    pub fn evm(workload: &Workload) -> Evm<'static, (), CacheDB<EmptyDB>> {
+        let mut db = CacheDB::new(EmptyDB::default());
+        let code = Bytecode::new_raw(Bytes::from(workload.program.to_bytecode()));
+        let info = AccountInfo {
+            code_hash: code.hash_slow(),
+            code: Some(code),
+            ..Default::default()
+        };
+        db.insert_account_info(Address::from(CONTRACT), info);
+        for (key, value) in &workload.storage {
+            db.insert_account_storage(
+                Address::from(CONTRACT),
+                to_revm_u256(key),
+                to_revm_u256(value),
+            )
+            .unwrap();
+        }
+        let calldata = workload.calldata.clone();
+        Evm::builder()
+            .with_db(db)
+            .modify_tx_env(|tx| {
+                tx.caller = Address::from(SENDER);
+                tx.transact_to = TxKind::Call(Address::from(CONTRACT));
+                tx.data = Bytes::from(calldata);
+                tx.gas_limit = GAS;
+            })
+            .build()
+    }
+}
+
+criterion_group!(benches, bench_workloads);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+fn main() {
+    // Runs the programs whose stack overflows are caught by a guard page
+    println!("cargo:rerun-if-changed=src/stack_guard.c");
+    cc::Build::new()
+        .file("src/stack_guard.c")
+        .compile("evm_mlir_stack_guard");
+
+    // The shared libraries loaded by the tests find the syscalls in the test binaries
+    println!("cargo:rustc-link-arg-tests=-rdynamic");
+}
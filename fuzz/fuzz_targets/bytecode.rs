@@ -0,0 +1,23 @@
+//! Runs arbitrary bytecode as the code of the contract called by a transaction, both
+//! compiled and through revm, failing when they don't agree.
+//!
+//! Compiling and running the code must not panic for any input. The bytes which aren't
+//! opcodes halt the program only if they're executed, as they do in revm.
+#![no_main]
+
+use evm_mlir::differential::DifferentialTest;
+use libfuzzer_sys::fuzz_target;
+
+/// Low enough to keep loops from slowing down the fuzzing
+const GAS_LIMIT: u64 = 100_000;
+
+fuzz_target!(|data: &[u8]| {
+    let test = DifferentialTest::call(data.to_vec(), Vec::new(), GAS_LIMIT);
+    if let Err(divergence) = test.run() {
+        let mut report = Vec::new();
+        divergence
+            .write_report(&mut report)
+            .expect("writing to a vector can't fail");
+        panic!("{}", String::from_utf8_lossy(&report));
+    }
+});
@@ -0,0 +1,218 @@
+//! # Stack-height analysis
+//!
+//! Finds, for each basic block of a [`ControlFlowGraph`], the stack heights it runs
+//! with, so the compiler can check the stack once at the entry of the block rather than
+//! before each of its operations, or not at all.
+//!
+//! A block needs some items on the stack at its entry for none of its operations to
+//! underflow, and grows the stack by at most some items above that height. The heights
+//! a block is entered with flow from the entry of the program, at height 0, along
+//! fallthroughs and jumps to pushed offsets. Blocks reachable through the jumptable may
+//! be entered with any height.
+//!
+//! The stack holds [`MAX_STACK_SIZE`] items, as in the EVM, unless analyzed with another
+//! limit by [`StackAnalysis::with_stack_limit`].
+use std::ops::RangeInclusive;
+
+use crate::{
+    cfg::{ControlFlowGraph, Exit, JumpTarget},
+    constants::MAX_STACK_SIZE,
+    program::Operation,
+};
+
+/// How the stack of a basic block is checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StackCheck {
+    /// Every height the block may be entered with is valid for it, so it isn't checked
+    None,
+    /// The height is checked once at the entry of the block
+    Entry,
+    /// Each operation checks the height before running, since the block needs as many
+    /// items as the stack can hold, or grows it as much
+    EachOperation,
+}
+
+/// The stack usage of a basic block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockStack {
+    /// The items the block needs on the stack at its entry
+    pub required: usize,
+    /// How far the stack grows above its height at the entry, at most
+    pub growth: usize,
+    /// How much the stack grows by the end of the block, negative if it shrinks
+    pub difference: isize,
+    /// The heights the block may be entered with, or `None` if it's unreachable
+    pub entry: Option<RangeInclusive<usize>>,
+    pub check: StackCheck,
+}
+
+/// The stack usage of each basic block of a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StackAnalysis {
+    blocks: Vec<BlockStack>,
+    /// The most items the stack holds
+    stack_limit: usize,
+}
+
+impl StackAnalysis {
+    /// Analyzes the blocks of the graph, following the heights they're entered with
+    pub fn new(cfg: &ControlFlowGraph) -> Self {
+        Self::with_stack_limit(cfg, MAX_STACK_SIZE)
+    }
+
+    /// Like [`Self::new`], but with a stack holding at most `stack_limit` items
+    pub fn with_stack_limit(cfg: &ControlFlowGraph, stack_limit: usize) -> Self {
+        let operations = cfg.program().operations();
+        let mut blocks: Vec<BlockStack> = cfg
+            .blocks()
+            .iter()
+            .map(|block| {
+                let (mut required, mut growth, mut difference) = (0, 0, 0_isize);
+                for operation in &operations[block.operations.clone()] {
+                    let effect = operation.stack_effect();
+                    required = required.max(effect.inputs as isize - difference);
+                    difference += effect.difference();
+                    growth = growth.max(difference);
+                }
+                BlockStack {
+                    required: required as usize,
+                    growth: growth as usize,
+                    difference,
+                    entry: None,
+                    check: StackCheck::EachOperation,
+                }
+            })
+            .collect();
+
+        // Blocks starting with a JUMPDEST may be entered from the jumptable
+        let jumps_are_dynamic = cfg.blocks().iter().any(|block| {
+            matches!(
+                block.exit,
+                Exit::Jump(JumpTarget::Dynamic)
+                    | Exit::Branch {
+                        target: JumpTarget::Dynamic,
+                        ..
+                    }
+            )
+        });
+        let mut pending = Vec::new();
+        for (index, block) in cfg.blocks().iter().enumerate() {
+            let is_jumpdest = matches!(
+                operations[block.operations.start],
+                Operation::Jumpdest { .. }
+            );
+            if jumps_are_dynamic && is_jumpdest {
+                blocks[index].entry = Some(0..=stack_limit);
+                pending.push(index);
+            }
+        }
+        if !blocks.is_empty() && enter(&mut blocks[0], 0..=0, stack_limit) {
+            pending.push(0);
+        }
+
+        while let Some(index) = pending.pop() {
+            let block = &blocks[index];
+            let Some(entry) = &block.entry else {
+                continue;
+            };
+            // Only the heights passing the checks go on past the block
+            let lowest = *entry.start().max(&block.required);
+            let highest = *entry.end().min(&stack_limit.saturating_sub(block.growth));
+            if lowest > highest {
+                continue;
+            }
+            let exit = (lowest as isize + block.difference) as usize
+                ..=(highest as isize + block.difference) as usize;
+
+            let (targets, fallthrough) = cfg.blocks()[index].exit.successors();
+            let successors = targets.into_iter().filter_map(block_of).chain(fallthrough);
+            for successor in successors {
+                if enter(&mut blocks[successor], exit.clone(), stack_limit) {
+                    pending.push(successor);
+                }
+            }
+        }
+
+        for block in &mut blocks {
+            block.check = if block.required >= stack_limit || block.growth >= stack_limit {
+                StackCheck::EachOperation
+            } else {
+                // Unreachable blocks are checked as if they could be entered with any height
+                let entry = block.entry.clone().unwrap_or(0..=stack_limit);
+                if *entry.start() >= block.required && *entry.end() + block.growth <= stack_limit {
+                    StackCheck::None
+                } else {
+                    StackCheck::Entry
+                }
+            };
+        }
+
+        Self {
+            blocks,
+            stack_limit,
+        }
+    }
+
+    /// Returns the stack usage of each block, in the order of the graph's blocks
+    pub fn blocks(&self) -> &[BlockStack] {
+        &self.blocks
+    }
+
+    /// Returns the heights each operation of the graph's program may run with, given the
+    /// operations before it in its block didn't halt, or `None` if it never runs.
+    /// Operations of unreachable blocks may run with any height
+    pub fn operation_heights(&self, cfg: &ControlFlowGraph) -> Vec<Option<RangeInclusive<usize>>> {
+        let operations = cfg.program().operations();
+        let mut heights = Vec::with_capacity(operations.len());
+        for (block, stack) in cfg.blocks().iter().zip(&self.blocks) {
+            let entry = stack.entry.clone().unwrap_or(0..=self.stack_limit);
+            let (mut required, mut growth, mut difference) = (0, 0, 0_isize);
+            for operation in &operations[block.operations.clone()] {
+                let lowest = (*entry.start() as isize).max(required) + difference;
+                let highest =
+                    (*entry.end() as isize).min(self.stack_limit as isize - growth) + difference;
+                heights.push((lowest <= highest).then(|| lowest as usize..=highest as usize));
+
+                let effect = operation.stack_effect();
+                required = required.max(effect.inputs as isize - difference);
+                difference += effect.difference();
+                growth = growth.max(difference);
+            }
+        }
+        heights
+    }
+}
+
+fn block_of(target: JumpTarget) -> Option<usize> {
+    match target {
+        JumpTarget::Block(block) => Some(block),
+        JumpTarget::Invalid | JumpTarget::Dynamic => None,
+    }
+}
+
+/// Adds the heights to the ones the block may be entered with, returning whether they
+/// changed. Heights growing again are widened to any height up to the stack limit, so
+/// loops settle quickly
+fn enter(block: &mut BlockStack, heights: RangeInclusive<usize>, stack_limit: usize) -> bool {
+    let joined = match &block.entry {
+        None => heights,
+        Some(entry) if entry.contains(heights.start()) && entry.contains(heights.end()) => {
+            return false;
+        }
+        Some(entry) => {
+            let start = if heights.start() < entry.start() {
+                0
+            } else {
+                *entry.start()
+            };
+            let end = if heights.end() > entry.end() {
+                stack_limit
+            } else {
+                *entry.end()
+            };
+            start..=end
+        }
+    };
+    block.entry = Some(joined);
+    true
+}
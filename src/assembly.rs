@@ -0,0 +1,245 @@
+//! # Assembly
+//!
+//! A parser for EVM assembly written with mnemonics, like:
+//!
+//! ```text
+//! // Counts down from 3
+//!     PUSH1 0x03
+//! loop:
+//!     PUSH1 1
+//!     SWAP1
+//!     SUB
+//!     DUP1
+//!     PUSH @loop
+//!     JUMPI
+//!     STOP
+//! ```
+//!
+//! Mnemonics are the opcode names, in any case, and are separated by whitespace or
+//! newlines. `PUSHN` takes the value on the same line, in decimal or in hex with a
+//! `0x` prefix, which must fit in N bytes. A bare `PUSH` takes any value up to 32 bytes.
+//!
+//! A name followed by a colon, like `loop:`, is a label, and places a JUMPDEST.
+//! Pushing `@loop` pushes the offset of that JUMPDEST, wherever the label is defined.
+//! Comments start with `//` and run until the end of the line.
+//!
+//! A bare `PUSH` is encoded with the smallest PUSHN able to hold its value, as with
+//! [`Program::to_bytecode`], while `PUSHN` keeps its N bytes even if the value has
+//! leading zeros. The offsets of labels account for that.
+use std::{collections::HashMap, sync::OnceLock};
+
+use num_bigint::BigUint;
+
+use crate::{
+    errors::AssemblyError,
+    program::{Opcode, Operation, Program},
+};
+
+/// Parses the assembly into a program, resolving its labels
+pub fn assemble(source: &str) -> Result<Program, AssemblyError> {
+    let mut items = Vec::new();
+    let mut labels = HashMap::new();
+
+    for (index, text) in source.lines().enumerate() {
+        let line = index + 1;
+        let code = text.split("//").next().unwrap_or_default();
+        let mut tokens = code.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if let Some(label) = token.strip_suffix(':') {
+                if label.is_empty() {
+                    return Err(AssemblyError::UnknownMnemonic {
+                        mnemonic: token.to_string(),
+                        line,
+                    });
+                }
+                if labels.insert(label.to_string(), labels.len()).is_some() {
+                    return Err(AssemblyError::DuplicateLabel {
+                        label: label.to_string(),
+                        line,
+                    });
+                }
+                items.push(Item::Label(labels.len() - 1));
+                continue;
+            }
+
+            let mnemonic = token.to_ascii_uppercase();
+            let Some(width) = push_width(&mnemonic) else {
+                let operation = operation_by_mnemonic(&mnemonic).ok_or_else(|| {
+                    AssemblyError::UnknownMnemonic {
+                        mnemonic: token.to_string(),
+                        line,
+                    }
+                })?;
+                items.push(Item::Operation(operation));
+                continue;
+            };
+
+            let immediate = tokens
+                .next()
+                .ok_or_else(|| AssemblyError::MissingImmediate {
+                    mnemonic: token.to_string(),
+                    line,
+                })?;
+            if let Some(label) = immediate.strip_prefix('@') {
+                items.push(Item::PushLabel {
+                    label: label.to_string(),
+                    width,
+                    line,
+                });
+                continue;
+            }
+            let value = parse_value(immediate).ok_or_else(|| AssemblyError::InvalidImmediate {
+                immediate: immediate.to_string(),
+                line,
+            })?;
+            let size = width.unwrap_or(32);
+            if value.to_bytes_be().len() > size {
+                return Err(AssemblyError::ImmediateTooLarge {
+                    immediate: immediate.to_string(),
+                    size,
+                    line,
+                });
+            }
+            items.push(Item::Operation(push(value, width)));
+        }
+    }
+
+    // Every label pushed must be defined, before laying them out
+    for item in &items {
+        if let Item::PushLabel { label, line, .. } = item {
+            if !labels.contains_key(label) {
+                return Err(AssemblyError::UndefinedLabel {
+                    label: label.clone(),
+                    line: *line,
+                });
+            }
+        }
+    }
+
+    let offsets = lay_out(&items, &labels);
+    let mut pc = 0;
+    let mut operations = Vec::with_capacity(items.len());
+    for item in items {
+        let operation = match item {
+            Item::Operation(Operation::PC { .. }) => Operation::PC { pc },
+            Item::Operation(Operation::Jumpdest { .. }) => Operation::Jumpdest { pc },
+            Item::Operation(operation) => operation,
+            Item::PushLabel { label, width, line } => {
+                let offset = BigUint::from(offsets[labels[&label]]);
+                let size = width.unwrap_or(32);
+                if offset.to_bytes_be().len() > size {
+                    return Err(AssemblyError::ImmediateTooLarge {
+                        immediate: format!("@{label}"),
+                        size,
+                        line,
+                    });
+                }
+                push(offset, width)
+            }
+            Item::Label(label) => Operation::Jumpdest { pc: offsets[label] },
+        };
+        pc += operation.to_bytecode().len();
+        operations.push(operation);
+    }
+    Ok(Program::from(operations))
+}
+
+/// An assembled operation, before knowing where the labels land
+#[derive(Debug)]
+enum Item {
+    Operation(Operation),
+    /// Pushes the offset of the label, which must fit in `width` bytes if given
+    PushLabel {
+        label: String,
+        width: Option<usize>,
+        line: usize,
+    },
+    /// A JUMPDEST, with the index of its label
+    Label(usize),
+}
+
+/// Returns the offset of each label, by index.
+///
+/// Pushing the offset of a label takes more bytes the further it is, which moves the
+/// labels after it. Offsets only grow, so this settles
+fn lay_out(items: &[Item], labels: &HashMap<String, usize>) -> Vec<usize> {
+    let mut offsets = vec![0; labels.len()];
+    loop {
+        let mut pc = 0;
+        let mut moved = false;
+        for item in items {
+            pc += match item {
+                Item::Operation(operation) => operation.to_bytecode().len(),
+                Item::PushLabel { label, width, .. } => {
+                    push(BigUint::from(offsets[labels[label]]), *width)
+                        .to_bytecode()
+                        .len()
+                }
+                Item::Label(label) => {
+                    moved |= offsets[*label] != pc;
+                    offsets[*label] = pc;
+                    1
+                }
+            };
+        }
+        if !moved {
+            return offsets;
+        }
+    }
+}
+
+/// Returns the PUSH of the value, with an immediate of `width` bytes if given, or of the
+/// fewest bytes holding the value
+fn push(value: BigUint, width: Option<usize>) -> Operation {
+    match width {
+        Some(width) if value.to_bytes_be().len() < width => Operation::PushN(width as u8, value),
+        _ => Operation::Push(value),
+    }
+}
+
+/// Returns the width of the immediate of a PUSH mnemonic: N bytes for PUSHN, and none
+/// for a bare PUSH, which takes as many as its value needs, up to 32. PUSH0 has no
+/// immediate, so it isn't one of them
+fn push_width(mnemonic: &str) -> Option<Option<usize>> {
+    let digits = mnemonic.strip_prefix("PUSH")?;
+    if digits.is_empty() {
+        return Some(None);
+    }
+    if digits.starts_with('0') || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    digits
+        .parse()
+        .ok()
+        .filter(|size| (1..=32).contains(size))
+        .map(Some)
+}
+
+fn parse_value(immediate: &str) -> Option<BigUint> {
+    match immediate
+        .strip_prefix("0x")
+        .or_else(|| immediate.strip_prefix("0X"))
+    {
+        Some(hex) => BigUint::parse_bytes(hex.as_bytes(), 16),
+        None => BigUint::parse_bytes(immediate.as_bytes(), 10),
+    }
+}
+
+/// Returns the operation with the mnemonic, which must be uppercase.
+/// JUMPDESTs and PCs are given a `pc` of 0, to be fixed once laid out
+fn operation_by_mnemonic(mnemonic: &str) -> Option<Operation> {
+    static OPCODES: OnceLock<HashMap<String, u8>> = OnceLock::new();
+    let opcodes = OPCODES.get_or_init(|| {
+        let mut opcodes: HashMap<String, u8> = (0..=u8::MAX)
+            .filter(|byte| Opcode::from(*byte) != Opcode::UNUSED)
+            .map(|byte| (format!("{:?}", Opcode::from(byte)), byte))
+            .collect();
+        // The names these opcodes had before being renamed
+        opcodes.insert("SHA3".to_string(), Opcode::KECCAK256 as u8);
+        opcodes.insert("DIFFICULTY".to_string(), Opcode::PREVRANDAO as u8);
+        opcodes
+    });
+    let opcode = *opcodes.get(mnemonic)?;
+    let program = Program::from_bytecode(&[opcode]).ok()?;
+    program.operations.into_iter().next()
+}
@@ -0,0 +1,101 @@
+//! # Block execution
+//!
+//! [`execute_block`] runs the transactions of a block one after the other, on the same
+//! state, as a node does when importing it. Each transaction sees the changes made by the
+//! ones before it, and gets a [`Receipt`] with the gas used by the block up to it, while
+//! the block's coinbase collects the priority fees of all of them.
+use std::{cell::RefCell, mem, rc::Rc};
+
+use crate::{
+    cache::ContractCache,
+    db::Host,
+    env::{Env, TxEnv},
+    errors::BlockError,
+    receipt::{Bloom, Receipt},
+    syscall::{SyscallContext, TransactionResult, U256},
+};
+
+/// The outcome of a block executed with [`execute_block`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockResult {
+    /// The results of the transactions, in order
+    pub results: Vec<TransactionResult>,
+    /// The receipts of the transactions, in order
+    pub receipts: Vec<Receipt>,
+    /// The gas used by all the transactions
+    pub gas_used: u64,
+    /// The priority fees paid to the block's coinbase by all the transactions
+    pub coinbase_fees: U256,
+    /// The bloom filter of the logs of all the transactions
+    pub bloom: Bloom,
+}
+
+/// Executes the transactions in order on the state held by `db`, in the block and following
+/// the hardfork of `env`, whose own transaction is ignored. The code of the contracts they
+/// call is compiled once for the whole block.
+///
+/// A transaction that's rejected, or that doesn't fit in the gas left in the block, makes
+/// the whole block invalid. The changes made by the transactions before it are then rolled
+/// back, if the host keeps a journal of its changes.
+pub fn execute_block<DB: Host + Default + 'static>(
+    env: &Env,
+    transactions: &[TxEnv],
+    db: &mut DB,
+) -> Result<BlockResult, BlockError> {
+    // The contexts of the transactions share the host, which is given back once they're done
+    let host = Rc::new(RefCell::new(mem::take(db)));
+    let checkpoint = host.borrow_mut().checkpoint();
+    let result = execute_transactions(env, transactions, &host);
+    if let (Err(_), Some(checkpoint)) = (&result, checkpoint) {
+        host.borrow_mut().revert(checkpoint);
+    }
+    *db = Rc::try_unwrap(host)
+        .expect("the contexts of the transactions were dropped")
+        .into_inner();
+    result
+}
+
+fn execute_transactions<DB: Host + 'static>(
+    env: &Env,
+    transactions: &[TxEnv],
+    host: &Rc<RefCell<DB>>,
+) -> Result<BlockResult, BlockError> {
+    let cache = Rc::new(RefCell::new(ContractCache::new()));
+    let basefee = env.block.basefee;
+    let mut block = BlockResult::default();
+
+    for (index, tx) in transactions.iter().enumerate() {
+        let gas_limit = block.gas_used.saturating_add(tx.gas_limit);
+        if U256::from(gas_limit as u128) > env.block.gas_limit {
+            return Err(BlockError::GasLimitReached { index });
+        }
+
+        let tx_env = Env {
+            tx: tx.clone(),
+            ..env.clone()
+        };
+        let mut context = SyscallContext::with_host(tx_env, Vec::new(), host.clone());
+        context.set_contract_cache(cache.clone());
+        let result = context
+            .transact()
+            .map_err(|error| BlockError::InvalidTransaction { index, error })?;
+
+        // The effective gas price is at least the base fee, or the transaction is rejected
+        let priority_fee = tx
+            .effective_gas_price(&basefee)
+            .checked_sub(&basefee)
+            .unwrap_or_default();
+        let fee = priority_fee
+            .checked_mul(&U256::from(result.gas_used as u128))
+            .and_then(|fee| block.coinbase_fees.checked_add(&fee))
+            .expect("fees are bounded by the total supply");
+        block.coinbase_fees = fee;
+
+        let receipt = Receipt::new(&result, block.gas_used);
+        block.gas_used = receipt.cumulative_gas;
+        block.bloom.accrue_bloom(&receipt.bloom);
+        block.receipts.push(receipt);
+        block.results.push(result);
+    }
+    Ok(block)
+}
@@ -0,0 +1,65 @@
+//! # Breakpoints
+//!
+//! Tracing every opcode is too slow for long-running programs, so breakpoints can be
+//! registered instead. They're given by program counter before compiling the program
+//! (see [`Executor::compile_with_breakpoints`](crate::executor::Executor::compile_with_breakpoints)),
+//! and the generated code checks a bitmap before each opcode, calling
+//! [`Inspector::breakpoint`](crate::inspector::Inspector::breakpoint) when its bit is set.
+//!
+//! The breakpoints only apply to the compiled program, and not to the code run by
+//! its nested calls.
+use std::collections::BTreeSet;
+
+/// The program counters to stop at, as offsets in the bytecode
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints {
+    pcs: BTreeSet<usize>,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a breakpoint at `pc`, returning whether it wasn't already set.
+    /// Breakpoints past the end of the code, or in the middle of a PUSH, are never hit
+    pub fn insert(&mut self, pc: usize) -> bool {
+        self.pcs.insert(pc)
+    }
+
+    /// Removes the breakpoint at `pc`, returning whether it was set
+    pub fn remove(&mut self, pc: usize) -> bool {
+        self.pcs.remove(&pc)
+    }
+
+    pub fn contains(&self, pc: usize) -> bool {
+        self.pcs.contains(&pc)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pcs.is_empty()
+    }
+
+    /// Returns the program counters of the breakpoints, in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pcs.iter().copied()
+    }
+
+    /// Returns the bitmap checked by the generated code, with the bit `pc % 8` of the
+    /// byte `pc / 8` set for each breakpoint. It covers `code_size` bytes of code
+    pub(crate) fn to_bitmap(&self, code_size: usize) -> Vec<u8> {
+        let mut bitmap = vec![0; code_size / 8 + 1];
+        for pc in self.iter().take_while(|pc| *pc < code_size) {
+            bitmap[pc / 8] |= 1 << (pc % 8);
+        }
+        bitmap
+    }
+}
+
+impl FromIterator<usize> for Breakpoints {
+    fn from_iter<T: IntoIterator<Item = usize>>(iter: T) -> Self {
+        Self {
+            pcs: iter.into_iter().collect(),
+        }
+    }
+}
@@ -0,0 +1,429 @@
+//! # Program builder
+//!
+//! [`ProgramBuilder`] builds a [`Program`] by chaining its operations, without writing
+//! out each [`Operation`] nor computing any offset:
+//!
+//! ```
+//! use evm_mlir::builder::ProgramBuilder;
+//!
+//! // Counts down from 3
+//! let program = ProgramBuilder::new()
+//!     .push(3_u8)
+//!     .label("loop")
+//!     .push(1_u8)
+//!     .swap(1)
+//!     .sub()
+//!     .dup(1)
+//!     .jumpi_to("loop")
+//!     .stop()
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! The offsets of JUMPDESTs and PCs are computed when building the program, and its
+//! labels are resolved, as [`Program::resolve_labels`] does.
+use num_bigint::BigUint;
+
+use crate::{
+    errors::ProgramError,
+    program::{Operation, Program},
+};
+
+/// Builds a program, one operation after another
+#[derive(Debug, Clone, Default)]
+pub struct ProgramBuilder {
+    operations: Vec<Operation>,
+}
+
+impl ProgramBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the operation
+    pub fn op(mut self, operation: Operation) -> Self {
+        self.operations.push(operation);
+        self
+    }
+
+    /// Appends the operations, in order
+    pub fn ops(mut self, operations: impl IntoIterator<Item = Operation>) -> Self {
+        self.operations.extend(operations);
+        self
+    }
+
+    /// Appends a PUSH of the value, with the smallest PUSHN holding it. A value wider
+    /// than a word makes the program invalid, see [`Program::validate`]
+    pub fn push(self, value: impl Into<BigUint>) -> Self {
+        self.op(Operation::Push(value.into()))
+    }
+
+    pub fn push0(self) -> Self {
+        self.op(Operation::Push0)
+    }
+
+    /// Appends a DUPN, duplicating the nth item from the top, starting at 1
+    pub fn dup(self, nth: u32) -> Self {
+        self.op(Operation::Dup(nth))
+    }
+
+    /// Appends a SWAPN, swapping the top item with the one `nth` items below it
+    pub fn swap(self, nth: u32) -> Self {
+        self.op(Operation::Swap(nth))
+    }
+
+    /// Appends a LOGN, with `topics` topics
+    pub fn log(self, topics: u8) -> Self {
+        self.op(Operation::Log(topics))
+    }
+
+    /// Appends a JUMPDEST, whose offset is computed when building the program
+    pub fn jumpdest(self) -> Self {
+        self.op(Operation::Jumpdest { pc: 0 })
+    }
+
+    /// Appends a PC, whose offset is computed when building the program
+    pub fn pc(self) -> Self {
+        self.op(Operation::PC { pc: 0 })
+    }
+
+    /// Appends a JUMPDEST named `label`
+    pub fn label(self, label: impl Into<String>) -> Self {
+        self.op(Operation::Label(label.into()))
+    }
+
+    /// Appends a PUSH of the offset of the label
+    pub fn push_label(self, label: impl Into<String>) -> Self {
+        self.op(Operation::PushLabel(label.into()))
+    }
+
+    /// Appends a jump to the label
+    pub fn jump_to(self, label: impl Into<String>) -> Self {
+        self.push_label(label).jump()
+    }
+
+    /// Appends a jump to the label, taken if the top item isn't zero
+    pub fn jumpi_to(self, label: impl Into<String>) -> Self {
+        self.push_label(label).jumpi()
+    }
+
+    /// Returns the program built, with its offsets computed and its labels resolved, or an
+    /// error if a label is defined twice, or jumped to but never defined
+    pub fn build(self) -> Result<Program, ProgramError> {
+        let program = Program::from(self.operations).resolve_labels()?;
+        Ok(program.with_computed_pcs())
+    }
+}
+
+impl ProgramBuilder {
+    pub fn stop(self) -> Self {
+        self.op(Operation::Stop)
+    }
+
+    pub fn add(self) -> Self {
+        self.op(Operation::Add)
+    }
+
+    pub fn mul(self) -> Self {
+        self.op(Operation::Mul)
+    }
+
+    pub fn sub(self) -> Self {
+        self.op(Operation::Sub)
+    }
+
+    pub fn div(self) -> Self {
+        self.op(Operation::Div)
+    }
+
+    pub fn sdiv(self) -> Self {
+        self.op(Operation::Sdiv)
+    }
+
+    /// Appends a MOD, whose name is a keyword
+    pub fn modulo(self) -> Self {
+        self.op(Operation::Mod)
+    }
+
+    pub fn smod(self) -> Self {
+        self.op(Operation::SMod)
+    }
+
+    pub fn addmod(self) -> Self {
+        self.op(Operation::Addmod)
+    }
+
+    pub fn mulmod(self) -> Self {
+        self.op(Operation::Mulmod)
+    }
+
+    pub fn exp(self) -> Self {
+        self.op(Operation::Exp)
+    }
+
+    pub fn sign_extend(self) -> Self {
+        self.op(Operation::SignExtend)
+    }
+
+    pub fn lt(self) -> Self {
+        self.op(Operation::Lt)
+    }
+
+    pub fn gt(self) -> Self {
+        self.op(Operation::Gt)
+    }
+
+    pub fn slt(self) -> Self {
+        self.op(Operation::Slt)
+    }
+
+    pub fn sgt(self) -> Self {
+        self.op(Operation::Sgt)
+    }
+
+    pub fn eq(self) -> Self {
+        self.op(Operation::Eq)
+    }
+
+    pub fn is_zero(self) -> Self {
+        self.op(Operation::IsZero)
+    }
+
+    pub fn and(self) -> Self {
+        self.op(Operation::And)
+    }
+
+    pub fn or(self) -> Self {
+        self.op(Operation::Or)
+    }
+
+    pub fn xor(self) -> Self {
+        self.op(Operation::Xor)
+    }
+
+    #[allow(clippy::should_implement_trait)]
+    pub fn not(self) -> Self {
+        self.op(Operation::Not)
+    }
+
+    pub fn byte(self) -> Self {
+        self.op(Operation::Byte)
+    }
+
+    pub fn shl(self) -> Self {
+        self.op(Operation::Shl)
+    }
+
+    pub fn shr(self) -> Self {
+        self.op(Operation::Shr)
+    }
+
+    pub fn sar(self) -> Self {
+        self.op(Operation::Sar)
+    }
+
+    pub fn keccak256(self) -> Self {
+        self.op(Operation::Keccak256)
+    }
+
+    pub fn pop(self) -> Self {
+        self.op(Operation::Pop)
+    }
+
+    pub fn jump(self) -> Self {
+        self.op(Operation::Jump)
+    }
+
+    pub fn jumpi(self) -> Self {
+        self.op(Operation::Jumpi)
+    }
+
+    pub fn gas(self) -> Self {
+        self.op(Operation::Gas)
+    }
+
+    pub fn mload(self) -> Self {
+        self.op(Operation::Mload)
+    }
+
+    pub fn mstore(self) -> Self {
+        self.op(Operation::Mstore)
+    }
+
+    pub fn mstore8(self) -> Self {
+        self.op(Operation::Mstore8)
+    }
+
+    pub fn msize(self) -> Self {
+        self.op(Operation::Msize)
+    }
+
+    pub fn mcopy(self) -> Self {
+        self.op(Operation::Mcopy)
+    }
+
+    pub fn sload(self) -> Self {
+        self.op(Operation::Sload)
+    }
+
+    pub fn sstore(self) -> Self {
+        self.op(Operation::Sstore)
+    }
+
+    pub fn tload(self) -> Self {
+        self.op(Operation::Tload)
+    }
+
+    pub fn tstore(self) -> Self {
+        self.op(Operation::Tstore)
+    }
+
+    pub fn call_data_load(self) -> Self {
+        self.op(Operation::CallDataLoad)
+    }
+
+    pub fn call_data_size(self) -> Self {
+        self.op(Operation::CallDataSize)
+    }
+
+    pub fn call_data_copy(self) -> Self {
+        self.op(Operation::CallDataCopy)
+    }
+
+    pub fn codesize(self) -> Self {
+        self.op(Operation::Codesize)
+    }
+
+    pub fn codecopy(self) -> Self {
+        self.op(Operation::Codecopy)
+    }
+
+    pub fn returndatasize(self) -> Self {
+        self.op(Operation::Returndatasize)
+    }
+
+    pub fn returndatacopy(self) -> Self {
+        self.op(Operation::Returndatacopy)
+    }
+
+    pub fn address(self) -> Self {
+        self.op(Operation::Address)
+    }
+
+    pub fn balance(self) -> Self {
+        self.op(Operation::Balance)
+    }
+
+    pub fn origin(self) -> Self {
+        self.op(Operation::Origin)
+    }
+
+    pub fn caller(self) -> Self {
+        self.op(Operation::Caller)
+    }
+
+    pub fn callvalue(self) -> Self {
+        self.op(Operation::Callvalue)
+    }
+
+    pub fn gasprice(self) -> Self {
+        self.op(Operation::Gasprice)
+    }
+
+    pub fn coinbase(self) -> Self {
+        self.op(Operation::Coinbase)
+    }
+
+    pub fn timestamp(self) -> Self {
+        self.op(Operation::Timestamp)
+    }
+
+    pub fn number(self) -> Self {
+        self.op(Operation::Number)
+    }
+
+    pub fn prevrandao(self) -> Self {
+        self.op(Operation::Prevrandao)
+    }
+
+    pub fn gaslimit(self) -> Self {
+        self.op(Operation::Gaslimit)
+    }
+
+    pub fn chainid(self) -> Self {
+        self.op(Operation::Chainid)
+    }
+
+    pub fn selfbalance(self) -> Self {
+        self.op(Operation::Selfbalance)
+    }
+
+    pub fn basefee(self) -> Self {
+        self.op(Operation::Basefee)
+    }
+
+    pub fn blockhash(self) -> Self {
+        self.op(Operation::Blockhash)
+    }
+
+    pub fn blobhash(self) -> Self {
+        self.op(Operation::Blobhash)
+    }
+
+    pub fn blobbasefee(self) -> Self {
+        self.op(Operation::Blobbasefee)
+    }
+
+    pub fn extcodesize(self) -> Self {
+        self.op(Operation::Extcodesize)
+    }
+
+    pub fn extcodecopy(self) -> Self {
+        self.op(Operation::Extcodecopy)
+    }
+
+    pub fn extcodehash(self) -> Self {
+        self.op(Operation::Extcodehash)
+    }
+
+    pub fn call(self) -> Self {
+        self.op(Operation::Call)
+    }
+
+    pub fn callcode(self) -> Self {
+        self.op(Operation::Callcode)
+    }
+
+    pub fn delegatecall(self) -> Self {
+        self.op(Operation::Delegatecall)
+    }
+
+    pub fn staticcall(self) -> Self {
+        self.op(Operation::Staticcall)
+    }
+
+    pub fn create(self) -> Self {
+        self.op(Operation::Create)
+    }
+
+    pub fn create2(self) -> Self {
+        self.op(Operation::Create2)
+    }
+
+    /// Appends a RETURN, whose name is a keyword
+    pub fn return_(self) -> Self {
+        self.op(Operation::Return)
+    }
+
+    pub fn revert(self) -> Self {
+        self.op(Operation::Revert)
+    }
+
+    pub fn invalid(self) -> Self {
+        self.op(Operation::Invalid)
+    }
+
+    pub fn selfdestruct(self) -> Self {
+        self.op(Operation::Selfdestruct)
+    }
+}
@@ -0,0 +1,445 @@
+//! # Compiled contract caches
+//!
+//! Compiling a contract takes far longer than running it. A [`ContractCache`] keeps the
+//! [`Executor`] of each contract it compiles, keyed by the hash of its code, so running
+//! the same code again reuses it.
+//!
+//! A cache given to [`SyscallContext::set_contract_cache`] is used for all the code run
+//! by the context, including the one of nested calls and contract creations. The same
+//! cache can be shared by the contexts of many transactions, like the ones of a block.
+//!
+//! An [`ArtifactCache`] keeps shared libraries compiled ahead of time in a directory
+//! instead, so they outlive the process. Each library is checked before being handed
+//! out, and compiled again when it was built by another version of the compiler, for
+//! another target, or got corrupted.
+//!
+//! [`SyscallContext::set_contract_cache`]: crate::syscall::SyscallContext::set_contract_cache
+use std::{
+    collections::{BTreeSet, HashMap},
+    ffi::CStr,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use llvm_sys::{
+    core::LLVMDisposeMessage,
+    target_machine::{LLVMGetDefaultTargetTriple, LLVMGetHostCPUName},
+};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    compile_to_object, constants::SHARED_LIBRARY_ABI_VERSION, context::Context,
+    errors::CompileError, executor::Executor, get_platform_library_ext, link_shared_lib,
+    program::Program, spec::SpecId, syscall::U256,
+};
+
+/// What a compiled contract depends on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    code_hash: U256,
+    spec_id: SpecId,
+    instrumented: bool,
+}
+
+impl CacheKey {
+    fn new(code: &[u8], spec_id: SpecId, instrumented: bool) -> Self {
+        Self {
+            code_hash: U256::from_be_bytes(Keccak256::digest(code).into()),
+            spec_id,
+            instrumented,
+        }
+    }
+}
+
+/// The executors of compiled contracts, by the hash of their code
+#[derive(Default)]
+pub struct ContractCache {
+    executors: HashMap<CacheKey, Rc<Executor>>,
+    hits: usize,
+    misses: usize,
+}
+
+impl fmt::Debug for ContractCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ContractCache")
+            .field("len", &self.len())
+            .field("hits", &self.hits)
+            .field("misses", &self.misses)
+            .finish()
+    }
+}
+
+impl ContractCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the executor of the code for the given hardfork, compiling it the first
+    /// time it's requested. Instrumented executors are kept apart from the others.
+    ///
+    /// Code that can't be compiled isn't cached, so asking for it again fails again.
+    pub fn get_or_compile(
+        &mut self,
+        code: &[u8],
+        spec_id: SpecId,
+        instrumented: bool,
+    ) -> Result<Rc<Executor>, CompileError> {
+        let key = CacheKey::new(code, spec_id, instrumented);
+        if let Some(executor) = self.executors.get(&key) {
+            self.hits += 1;
+            return Ok(executor.clone());
+        }
+        self.misses += 1;
+        let executor = Rc::new(compile_code(code, spec_id, instrumented)?);
+        self.executors.insert(key, executor.clone());
+        Ok(executor)
+    }
+
+    /// Compiles the codes missing from the cache for the given hardfork, in parallel
+    /// (see [`Executor::compile_many`]), so they're ready before they're requested.
+    /// This doesn't count as requesting them.
+    ///
+    /// Returns the errors of the codes that couldn't be compiled, along with the index of
+    /// each code in `codes`. They aren't cached, so requesting them fails again.
+    pub fn warm<C: AsRef<[u8]>>(
+        &mut self,
+        codes: impl IntoIterator<Item = C>,
+        spec_id: SpecId,
+    ) -> Vec<(usize, CompileError)> {
+        let mut errors = Vec::new();
+        let mut indices = Vec::new();
+        let mut keys = Vec::new();
+        let mut programs = Vec::new();
+        for (index, code) in codes.into_iter().enumerate() {
+            let key = CacheKey::new(code.as_ref(), spec_id, false);
+            if self.executors.contains_key(&key) || keys.contains(&key) {
+                continue;
+            }
+            match Program::from_bytecode(code.as_ref()) {
+                Ok(program) => {
+                    indices.push(index);
+                    keys.push(key);
+                    programs.push(program);
+                }
+                Err(error) => errors.push((index, error.into())),
+            }
+        }
+        let executors = Executor::compile_many(&programs, spec_id);
+        for ((index, key), executor) in indices.into_iter().zip(keys).zip(executors) {
+            match executor {
+                Ok(executor) => {
+                    self.executors.insert(key, Rc::new(executor));
+                }
+                Err(error) => errors.push((index, error.into())),
+            }
+        }
+        errors.sort_by_key(|(index, _)| *index);
+        errors
+    }
+
+    /// Returns the number of executors kept
+    pub fn len(&self) -> usize {
+        self.executors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.executors.is_empty()
+    }
+
+    /// Returns how many requests were answered with an executor already compiled
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    /// Returns how many requests had to compile the code
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    /// Drops all the executors kept. The ones in use stay alive until they're done
+    pub fn clear(&mut self) {
+        self.executors.clear();
+    }
+}
+
+/// Compiles the code for the given hardfork, instrumented or not
+pub(crate) fn compile_code(
+    code: &[u8],
+    spec_id: SpecId,
+    instrumented: bool,
+) -> Result<Executor, CompileError> {
+    let program = Program::from_bytecode(code)?;
+    let executor = if instrumented {
+        Executor::compile_instrumented(&program, spec_id)?
+    } else {
+        Executor::compile(&program, spec_id)?
+    };
+    Ok(executor)
+}
+
+/// The extension of the file describing each library of an [`ArtifactCache`]
+const METADATA_EXTENSION: &str = "meta";
+
+/// A directory of shared libraries compiled from contracts, as done by
+/// [`compile_shared_library`](crate::compile_shared_library), to be loaded by other
+/// processes.
+///
+/// Each library is stored along with a metadata file with the `meta` extension, which
+/// records what it was built from and for: the hash of the code, the hardfork, the
+/// version of the compiler and of the interface with the syscalls
+/// ([`SHARED_LIBRARY_ABI_VERSION`]), and the target triple and CPU. It also records the size and
+/// hash of the library, to catch libraries that were partly written or modified.
+/// A library is only handed out when all of it matches.
+///
+/// The libraries export the entrypoint described in
+/// [`compile_shared_library`](crate::compile_shared_library), and find the syscalls in
+/// the process loading them.
+#[derive(Debug, Clone)]
+pub struct ArtifactCache {
+    dir: PathBuf,
+}
+
+impl ArtifactCache {
+    /// Opens the cache kept in `dir`, creating the directory if it doesn't exist
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Returns the directory holding the cache
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the library compiled from the code for the given hardfork,
+    /// if it's in the cache and valid for the running compiler
+    pub fn get(&self, code: &[u8], spec_id: SpecId) -> Option<PathBuf> {
+        let metadata = ArtifactMetadata::new(code, spec_id);
+        let stem = metadata.stem();
+        self.is_valid(&stem, &metadata)
+            .then(|| self.library_path(&stem))
+    }
+
+    /// Returns the path of the library compiled from the code for the given hardfork,
+    /// compiling it when it's not in the cache, or replacing it when it's stale
+    pub fn get_or_compile(&self, code: &[u8], spec_id: SpecId) -> Result<PathBuf, CompileError> {
+        let mut metadata = ArtifactMetadata::new(code, spec_id);
+        let stem = metadata.stem();
+        let library = self.library_path(&stem);
+        if self.is_valid(&stem, &metadata) {
+            return Ok(library);
+        }
+        self.remove(&stem)?;
+
+        let program = Program::from_bytecode(code)?;
+        // Compiling leaves intermediate files next to the library, so it's done in a
+        // directory of its own. Both files are moved in place once they're complete,
+        // with the metadata last, so no other process sees them half written
+        let build_dir = tempfile::tempdir_in(&self.dir)?;
+        let output_file = build_dir.path().join(&stem);
+        let module = Context::new().compile_with_spec(&program, &output_file, spec_id)?;
+        let object_file = compile_to_object(&module, &output_file)?;
+        let built_library = link_shared_lib(&[object_file], &output_file)?;
+
+        let bytes = fs::read(&built_library)?;
+        metadata.library_size = bytes.len() as u64;
+        metadata.library_hash = hex_hash(&bytes);
+        let built_metadata = output_file.with_extension(METADATA_EXTENSION);
+        fs::write(&built_metadata, metadata.to_string())?;
+
+        fs::rename(built_library, &library)?;
+        fs::rename(built_metadata, self.metadata_path(&stem))?;
+        Ok(library)
+    }
+
+    /// Removes the libraries that can't be handed out: the ones built by another
+    /// version of the compiler or for another target, the corrupted ones, and the ones
+    /// without metadata. Returns how many were removed
+    pub fn prune(&self) -> io::Result<usize> {
+        let library_extension = get_platform_library_ext();
+        let mut stems = BTreeSet::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            let stem = path.file_stem().and_then(|stem| stem.to_str());
+            if let (true, Some(extension), Some(stem)) = (path.is_file(), extension, stem) {
+                if [library_extension, METADATA_EXTENSION].contains(&extension) {
+                    stems.insert(stem.to_string());
+                }
+            }
+        }
+
+        let current = ArtifactMetadata::new(&[], SpecId::default());
+        let mut removed = 0;
+        for stem in stems {
+            let is_valid = self.read_metadata(&stem).is_some_and(|metadata| {
+                // The library must have been built from the code and for the hardfork
+                // in its name, by the running compiler
+                let expected = ArtifactMetadata {
+                    spec: metadata.spec.clone(),
+                    code_hash: metadata.code_hash.clone(),
+                    ..current.clone()
+                };
+                expected.stem() == stem && self.is_valid(&stem, &expected)
+            });
+            if !is_valid {
+                self.remove(&stem)?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn library_path(&self, stem: &str) -> PathBuf {
+        self.dir
+            .join(stem)
+            .with_extension(get_platform_library_ext())
+    }
+
+    fn metadata_path(&self, stem: &str) -> PathBuf {
+        self.dir.join(stem).with_extension(METADATA_EXTENSION)
+    }
+
+    fn read_metadata(&self, stem: &str) -> Option<ArtifactMetadata> {
+        let metadata = fs::read_to_string(self.metadata_path(stem)).ok()?;
+        ArtifactMetadata::parse(&metadata)
+    }
+
+    /// Returns whether the library was built as expected, and is still the same size
+    /// and hash it was then
+    fn is_valid(&self, stem: &str, expected: &ArtifactMetadata) -> bool {
+        let Some(metadata) = self.read_metadata(stem) else {
+            return false;
+        };
+        metadata.is_same_build(expected)
+            && fs::read(self.library_path(stem)).is_ok_and(|bytes| {
+                bytes.len() as u64 == metadata.library_size
+                    && hex_hash(&bytes) == metadata.library_hash
+            })
+    }
+
+    /// Removes the library and its metadata, if they exist
+    fn remove(&self, stem: &str) -> io::Result<()> {
+        // The metadata goes first, so the library is never handed out in between
+        for path in [self.metadata_path(stem), self.library_path(stem)] {
+            match fs::remove_file(path) {
+                Err(error) if error.kind() != io::ErrorKind::NotFound => return Err(error),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a library in an [`ArtifactCache`] was built from and for
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ArtifactMetadata {
+    /// The version of the compiler that built it
+    version: String,
+    /// The version of the interface with the syscalls it was built for
+    abi: String,
+    /// The target triple it was built for
+    target: String,
+    /// The CPU it was built for. The code may use any of its features
+    cpu: String,
+    /// The hardfork followed by the code
+    spec: String,
+    /// The Keccak-256 hash of the code, in hex
+    code_hash: String,
+    library_size: u64,
+    /// The Keccak-256 hash of the library, in hex
+    library_hash: String,
+}
+
+impl ArtifactMetadata {
+    /// Returns the metadata of a library built from the code by the running compiler,
+    /// with an unknown size and hash
+    fn new(code: &[u8], spec_id: SpecId) -> Self {
+        let (target, cpu) = host_target();
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            abi: SHARED_LIBRARY_ABI_VERSION.to_string(),
+            target,
+            cpu,
+            spec: format!("{spec_id:?}"),
+            code_hash: hex_hash(code),
+            ..Default::default()
+        }
+    }
+
+    /// Returns whether both describe a library built from the same code, for the same
+    /// hardfork, by the same compiler and for the same target
+    fn is_same_build(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.abi == other.abi
+            && self.target == other.target
+            && self.cpu == other.cpu
+            && self.spec == other.spec
+            && self.code_hash == other.code_hash
+    }
+
+    /// Returns the name of the library, without its extension
+    fn stem(&self) -> String {
+        format!("{}-{}", self.code_hash, self.spec)
+    }
+
+    /// Parses the metadata from lines of `key value`, as written by `to_string`
+    fn parse(text: &str) -> Option<Self> {
+        let mut metadata = Self::default();
+        for line in text.lines() {
+            let (key, value) = line.split_once(' ')?;
+            let value = value.to_string();
+            match key {
+                "version" => metadata.version = value,
+                "abi" => metadata.abi = value,
+                "target" => metadata.target = value,
+                "cpu" => metadata.cpu = value,
+                "spec" => metadata.spec = value,
+                "code_hash" => metadata.code_hash = value,
+                "library_size" => metadata.library_size = value.parse().ok()?,
+                "library_hash" => metadata.library_hash = value,
+                _ => return None,
+            }
+        }
+        Some(metadata)
+    }
+}
+
+impl fmt::Display for ArtifactMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "version {}", self.version)?;
+        writeln!(f, "abi {}", self.abi)?;
+        writeln!(f, "target {}", self.target)?;
+        writeln!(f, "cpu {}", self.cpu)?;
+        writeln!(f, "spec {}", self.spec)?;
+        writeln!(f, "code_hash {}", self.code_hash)?;
+        writeln!(f, "library_size {}", self.library_size)?;
+        writeln!(f, "library_hash {}", self.library_hash)
+    }
+}
+
+/// Returns the Keccak-256 hash of the bytes, in hex
+fn hex_hash(bytes: &[u8]) -> String {
+    Keccak256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// Returns the target triple and the CPU the code is compiled for, which are the ones
+/// of the host (see [`compile_to_object`])
+fn host_target() -> (String, String) {
+    unsafe {
+        let triple = LLVMGetDefaultTargetTriple();
+        let cpu = LLVMGetHostCPUName();
+        let target = (
+            CStr::from_ptr(triple).to_string_lossy().into_owned(),
+            CStr::from_ptr(cpu).to_string_lossy().into_owned(),
+        );
+        LLVMDisposeMessage(triple);
+        LLVMDisposeMessage(cpu);
+        target
+    }
+}
@@ -0,0 +1,348 @@
+//! # Control-flow graph
+//!
+//! Recovers the basic blocks of a [`Program`] and the ways control moves between them,
+//! and writes them as a [Graphviz](https://graphviz.org) DOT graph.
+//!
+//! A block starts at each JUMPDEST, and after each operation leaving it: a JUMP, a JUMPI,
+//! or an operation halting the program. A jump whose target is pushed right before it
+//! goes straight to the block of that JUMPDEST, while any other jump goes through the
+//! jumptable, which may lead to any JUMPDEST. This is how the compiler lowers them.
+//!
+//! The relative jumps of EOF code always go straight to their target, which starts a
+//! block whatever the operation there. A RETF or a JUMPF leaves the code section, so to
+//! the graph of the section it's like a halt, while a CALLF comes back to the next
+//! operation.
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    ops::Range,
+};
+
+use num_bigint::BigUint;
+
+use crate::program::{Operation, Program};
+
+/// The basic blocks of a program, in the order of their operations
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlFlowGraph<'p> {
+    program: &'p Program,
+    /// The offset of each operation in the bytecode
+    pcs: Vec<usize>,
+    blocks: Vec<BasicBlock>,
+}
+
+/// A run of operations that always execute one after the other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// The indices of the block's operations in the program
+    pub operations: Range<usize>,
+    /// Where control goes after the last operation
+    pub exit: Exit,
+}
+
+/// How control leaves a basic block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Exit {
+    /// Goes on into the block with the given index
+    Fallthrough(usize),
+    /// A JUMP or an RJUMP
+    Jump(JumpTarget),
+    /// A JUMPI or an RJUMPI, falling through into the block with the given index when the condition
+    /// is zero, or stopping if the program ends there
+    Branch {
+        target: JumpTarget,
+        fallthrough: Option<usize>,
+    },
+    /// An RJUMPV, jumping to the target at the index on top of the stack, or falling
+    /// through into the block with the given index past the last one
+    Switch {
+        targets: Vec<JumpTarget>,
+        fallthrough: Option<usize>,
+    },
+    /// Halts the program, by an operation or by reaching its end, or leaves the EOF code
+    /// section
+    Halt,
+}
+
+/// Where a jump leads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JumpTarget {
+    /// The block starting with the JUMPDEST at the pushed offset, or with the operation
+    /// a relative jump lands on
+    Block(usize),
+    /// A pushed offset without a JUMPDEST, or a relative jump landing out of the
+    /// operations, which makes the program revert
+    Invalid,
+    /// An offset computed at runtime, looked up in the jumptable
+    Dynamic,
+}
+
+impl<'p> ControlFlowGraph<'p> {
+    /// Splits the program into basic blocks, and links them
+    pub fn new(program: &'p Program) -> Self {
+        let operations = program.operations();
+        let pcs = program.pcs();
+        let relative_targets: HashSet<usize> = operations
+            .iter()
+            .zip(&pcs)
+            .flat_map(|(operation, pc)| operation.relative_jump_targets(*pc))
+            .flatten()
+            .collect();
+
+        let mut ranges = Vec::new();
+        let mut start = 0;
+        for (index, operation) in operations.iter().enumerate() {
+            let starts_block = matches!(operation, Operation::Jumpdest { .. })
+                || relative_targets.contains(&pcs[index]);
+            if starts_block && start < index {
+                ranges.push(start..index);
+                start = index;
+            }
+            if ends_block(operation) {
+                ranges.push(start..index + 1);
+                start = index + 1;
+            }
+        }
+        if start < operations.len() {
+            ranges.push(start..operations.len());
+        }
+
+        let jumpdest_blocks: HashMap<usize, usize> = ranges
+            .iter()
+            .enumerate()
+            .filter_map(|(block, range)| match operations[range.start] {
+                Operation::Jumpdest { pc } => Some((pc, block)),
+                _ => None,
+            })
+            .collect();
+        let target = |range: &Range<usize>| {
+            // The target is constant if it's pushed right before the jump
+            let pushed = match range.len() {
+                0 | 1 => None,
+                _ => match &operations[range.end - 2] {
+                    Operation::Push0 => Some(BigUint::from(0_u8)),
+                    Operation::Push(value) | Operation::PushN(_, value) => Some(value.clone()),
+                    _ => None,
+                },
+            };
+            match pushed {
+                None => JumpTarget::Dynamic,
+                Some(value) => usize::try_from(&value)
+                    .ok()
+                    .and_then(|pc| jumpdest_blocks.get(&pc))
+                    .map_or(JumpTarget::Invalid, |block| JumpTarget::Block(*block)),
+            }
+        };
+        let block_starts: HashMap<usize, usize> = ranges
+            .iter()
+            .enumerate()
+            .map(|(block, range)| (pcs[range.start], block))
+            .collect();
+        let relative_target_blocks = |range: &Range<usize>| -> Vec<JumpTarget> {
+            let index = range.end - 1;
+            operations[index]
+                .relative_jump_targets(pcs[index])
+                .into_iter()
+                .map(|pc| {
+                    pc.and_then(|pc| block_starts.get(&pc))
+                        .map_or(JumpTarget::Invalid, |block| JumpTarget::Block(*block))
+                })
+                .collect()
+        };
+
+        let blocks = ranges
+            .iter()
+            .enumerate()
+            .map(|(index, range)| {
+                let next = (index + 1 < ranges.len()).then_some(index + 1);
+                let exit = match operations[range.end - 1] {
+                    Operation::Jump => Exit::Jump(target(range)),
+                    Operation::Jumpi => Exit::Branch {
+                        target: target(range),
+                        fallthrough: next,
+                    },
+                    Operation::Rjump(_) => Exit::Jump(relative_target_blocks(range)[0]),
+                    Operation::Rjumpi(_) => Exit::Branch {
+                        target: relative_target_blocks(range)[0],
+                        fallthrough: next,
+                    },
+                    Operation::Rjumpv(_) => Exit::Switch {
+                        targets: relative_target_blocks(range),
+                        fallthrough: next,
+                    },
+                    ref operation if ends_block(operation) => Exit::Halt,
+                    _ => next.map_or(Exit::Halt, Exit::Fallthrough),
+                };
+                BasicBlock {
+                    operations: range.clone(),
+                    exit,
+                }
+            })
+            .collect();
+
+        Self {
+            program,
+            pcs,
+            blocks,
+        }
+    }
+
+    /// Returns the program the graph is of
+    pub fn program(&self) -> &'p Program {
+        self.program
+    }
+
+    /// Returns the basic blocks, in the order of their operations
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    /// Returns the offset in the bytecode of the block's first operation
+    pub fn block_pc(&self, block: usize) -> usize {
+        self.pcs[self.blocks[block].operations.start]
+    }
+
+    /// Returns whether each block can run, following the exits from the first block. The
+    /// blocks starting with a JUMPDEST can all run if any block that can jumps through
+    /// the jumptable. The others are dead code, like the bytes after a halt
+    pub fn reachable_blocks(&self) -> Vec<bool> {
+        let operations = self.program.operations();
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut pending = Vec::new();
+        if !self.blocks.is_empty() {
+            reachable[0] = true;
+            pending.push(0);
+        }
+        let mut reached_jumptable = false;
+
+        while let Some(index) = pending.pop() {
+            let (targets, next) = self.blocks[index].exit.successors();
+            let mut successors: Vec<usize> = next.into_iter().collect();
+            for target in targets {
+                match target {
+                    JumpTarget::Block(block) => successors.push(block),
+                    JumpTarget::Dynamic if !reached_jumptable => {
+                        reached_jumptable = true;
+                        successors.extend(self.blocks.iter().enumerate().filter_map(
+                            |(block, basic_block)| {
+                                matches!(
+                                    operations[basic_block.operations.start],
+                                    Operation::Jumpdest { .. }
+                                )
+                                .then_some(block)
+                            },
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            for successor in successors {
+                if !reachable[successor] {
+                    reachable[successor] = true;
+                    pending.push(successor);
+                }
+            }
+        }
+        reachable
+    }
+
+    /// Writes the graph in the DOT language, with a node listing the operations of each
+    /// block. Dynamic jumps go through a `jumptable` node, and jumps to offsets without
+    /// a JUMPDEST to an `invalid_jump` node
+    pub fn write_dot(&self, mut writer: impl io::Write) -> io::Result<()> {
+        let operations = self.program.operations();
+        writeln!(writer, "digraph cfg {{")?;
+        writeln!(writer, "    node [shape=box, fontname=monospace];")?;
+
+        for (index, block) in self.blocks.iter().enumerate() {
+            let label: String = block
+                .operations
+                .clone()
+                .map(|operation| {
+                    format!("{:04x}: {}\\l", self.pcs[operation], operations[operation])
+                })
+                .collect();
+            writeln!(writer, "    block_{index} [label=\"{label}\"];")?;
+        }
+
+        let mut edges = Vec::new();
+        for (from, block) in self.blocks.iter().enumerate() {
+            if let Exit::Fallthrough(to) = block.exit {
+                edges.push((from, JumpTarget::Block(to), ""));
+                continue;
+            }
+            let (targets, fallthrough) = block.exit.successors();
+            edges.extend(targets.into_iter().map(|target| (from, target, "jump")));
+            if let Some(to) = fallthrough {
+                edges.push((from, JumpTarget::Block(to), "fallthrough"));
+            }
+        }
+        for (from, target, label) in &edges {
+            let (to, style) = match target {
+                JumpTarget::Block(to) => (format!("block_{to}"), ""),
+                JumpTarget::Invalid => ("invalid_jump".to_string(), ""),
+                JumpTarget::Dynamic => ("jumptable".to_string(), ", style=dashed"),
+            };
+            writeln!(
+                writer,
+                "    block_{from} -> {to} [label=\"{label}\"{style}];"
+            )?;
+        }
+
+        let reaches = |node| edges.iter().any(|(_, target, _)| *target == node);
+        if reaches(JumpTarget::Dynamic) {
+            writeln!(writer, "    jumptable [shape=diamond];")?;
+            for (to, block) in self.blocks.iter().enumerate() {
+                if let Operation::Jumpdest { .. } = operations[block.operations.start] {
+                    writeln!(writer, "    jumptable -> block_{to} [style=dashed];")?;
+                }
+            }
+        }
+        if reaches(JumpTarget::Invalid) {
+            writeln!(
+                writer,
+                "    invalid_jump [shape=octagon, label=\"invalid jump\"];"
+            )?;
+        }
+        writeln!(writer, "}}")
+    }
+}
+
+impl Exit {
+    /// Returns the targets the exit may jump to, and the block it may fall through into
+    pub fn successors(&self) -> (Vec<JumpTarget>, Option<usize>) {
+        match self {
+            Exit::Fallthrough(next) => (Vec::new(), Some(*next)),
+            Exit::Jump(target) => (vec![*target], None),
+            Exit::Branch {
+                target,
+                fallthrough,
+            } => (vec![*target], *fallthrough),
+            Exit::Switch {
+                targets,
+                fallthrough,
+            } => (targets.clone(), *fallthrough),
+            Exit::Halt => (Vec::new(), None),
+        }
+    }
+}
+
+/// Returns whether the operation is the last of its block
+fn ends_block(operation: &Operation) -> bool {
+    matches!(
+        operation,
+        Operation::Stop
+            | Operation::Jump
+            | Operation::Jumpi
+            | Operation::Return
+            | Operation::Revert
+            | Operation::Invalid
+            | Operation::Undefined(_)
+            | Operation::Selfdestruct
+            | Operation::Rjump(_)
+            | Operation::Rjumpi(_)
+            | Operation::Rjumpv(_)
+            | Operation::Retf
+            | Operation::Jumpf(..)
+    )
+}
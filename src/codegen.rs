@@ -1,4 +1,6 @@
 pub mod context;
 pub(crate) mod operations;
 mod pass_manager;
-pub use pass_manager::run_pass_manager;
+pub(crate) mod stack_promotion;
+pub(crate) mod word;
+pub use pass_manager::{run_debug_info_pass, run_pass_manager, run_passes_one_by_one};
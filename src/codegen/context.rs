@@ -1,12 +1,16 @@
 use std::collections::BTreeMap;
 
 use melior::{
-    dialect::cf,
-    ir::{Block, BlockRef, Location, Value},
+    dialect::{arith, cf},
+    ir::{
+        attribute::IntegerAttribute, r#type::IntegerType, Block, BlockRef, Location, Region, Value,
+    },
     Context as MeliorContext,
 };
 
-use crate::{errors::CodegenError, program::Program, syscall};
+use crate::{
+    errors::CodegenError, options::WordRepr, program::Program, spec::SpecId, syscall, utils,
+};
 
 #[derive(Debug, Clone)]
 pub(crate) struct OperationCtx<'c> {
@@ -14,6 +18,17 @@ pub(crate) struct OperationCtx<'c> {
     pub mlir_context: &'c MeliorContext,
     /// The program IR.
     pub program: &'c Program,
+    /// The data section of the EOF container the program is a code section of, or empty.
+    pub data: &'c [u8],
+    /// The hardfork the program is compiled for.
+    pub spec_id: SpecId,
+    /// How the arithmetic on words is lowered.
+    pub word_repr: WordRepr,
+    /// The most words the stack holds.
+    pub stack_limit: usize,
+    /// Whether pushing past the stack limit faults on a guard page, so the stack isn't
+    /// checked for space.
+    pub stack_guarded: bool,
     /// The syscall context to be passed to syscalls.
     pub syscall_ctx: Value<'c, 'c>,
     /// Reference to the revert block.
@@ -25,6 +40,29 @@ pub(crate) struct OperationCtx<'c> {
     pub jumptable_block: BlockRef<'c, 'c>,
     /// Blocks to jump to. These are registered dynamically as JUMPDESTs are processed.
     pub jumpdest_blocks: BTreeMap<usize, BlockRef<'c, 'c>>,
+    /// The first block generated for each basic block, by the offset of its first
+    /// operation. These are registered as the basic blocks are generated.
+    pub block_entries: BTreeMap<usize, BlockRef<'c, 'c>>,
+    /// Blocks the jumps whose target is known go to, by the offset of the target: a
+    /// pushed JUMPDEST, or the operation a relative jump lands on. Each one branches to
+    /// the entry of its basic block once the program is generated.
+    pub static_jump_blocks: BTreeMap<usize, BlockRef<'c, 'c>>,
+    /// The offset of the JUMPDEST the JUMP or JUMPI being generated goes to, if it's
+    /// pushed right before it, so the jump doesn't go through the jump table. For an
+    /// RJUMP or RJUMPI, the offset it lands on, unless it's out of the program.
+    pub jump_target: Option<usize>,
+    /// The offsets the RJUMPV being generated lands on, for each index, or `None` for
+    /// the ones out of the program.
+    pub switch_targets: Vec<Option<usize>>,
+    /// The location given to the MLIR operations of the operation being generated.
+    /// Unknown unless debug info is emitted.
+    pub location: Location<'c>,
+    /// Whether the stack is known to hold the items of the operation being generated,
+    /// having been checked at the entry of its basic block, so it doesn't check it again.
+    pub stack_checked: bool,
+    /// Whether the static gas of the operation being generated was charged along with the
+    /// operations around it in its basic block, so it doesn't charge it again.
+    pub gas_charged: bool,
 }
 
 impl<'c> OperationCtx<'c> {
@@ -34,6 +72,34 @@ impl<'c> OperationCtx<'c> {
         self.jumpdest_blocks.insert(pc, block);
     }
 
+    /// Registers the first block generated for the basic block starting at `pc`
+    pub(crate) fn register_block_entry(&mut self, pc: usize, block: BlockRef<'c, 'c>) {
+        self.block_entries.insert(pc, block);
+    }
+
+    /// Returns the block going to the basic block at `pc`, for the jumps known to land on
+    /// it. It's linked to the entry of the basic block by [`Self::link_static_jumps`]
+    pub(crate) fn static_jump_block(
+        &mut self,
+        region: &'c Region<'c>,
+        pc: usize,
+    ) -> BlockRef<'c, 'c> {
+        *self
+            .static_jump_blocks
+            .entry(pc)
+            .or_insert_with(|| region.append_block(Block::new(&[])))
+    }
+
+    /// Makes the blocks of the jumps whose target is known branch to the entry of its
+    /// basic block, once every basic block is registered
+    pub(crate) fn link_static_jumps(&self) {
+        for (pc, block) in &self.static_jump_blocks {
+            // Like the jump table, offsets without code revert
+            let target = self.block_entries.get(pc).unwrap_or(&self.revert_block);
+            block.append_operation(cf::br(target, &[], Location::unknown(self.mlir_context)));
+        }
+    }
+
     /// Registers a block as a valid jump destination.
     // TODO: move into jumptable module
     #[allow(dead_code)]
@@ -48,6 +114,63 @@ impl<'c> OperationCtx<'c> {
     }
 }
 
+// Stack checks
+impl<'c> OperationCtx<'c> {
+    /// Returns a flag telling whether the stack holds at least `element_count` items,
+    /// which is constant if it was checked at the entry of the basic block
+    pub(crate) fn check_stack_has_at_least(
+        &'c self,
+        block: &'c Block,
+        element_count: u32,
+    ) -> Result<Value, CodegenError> {
+        if self.stack_checked {
+            return self.check_passed(block);
+        }
+        utils::check_stack_has_at_least(self.mlir_context, block, element_count)
+    }
+
+    /// Returns a flag telling whether the stack has space for `element_count` more items,
+    /// which is constant if it was checked at the entry of the basic block, or if
+    /// overflows fault on a guard page
+    pub(crate) fn check_stack_has_space_for(
+        &'c self,
+        block: &'c Block,
+        element_count: u32,
+    ) -> Result<Value, CodegenError> {
+        if self.stack_checked || self.stack_guarded {
+            return self.check_passed(block);
+        }
+        utils::check_stack_has_space_for(self.mlir_context, block, element_count, self.stack_limit)
+    }
+
+    fn check_passed(&'c self, block: &'c Block) -> Result<Value, CodegenError> {
+        let flag = block
+            .append_operation(arith::constant(
+                self.mlir_context,
+                IntegerAttribute::new(IntegerType::new(self.mlir_context, 1).into(), 1).into(),
+                self.location,
+            ))
+            .result(0)?;
+        Ok(flag.into())
+    }
+}
+
+// Gas
+impl<'c> OperationCtx<'c> {
+    /// Consumes the static gas of the operation, returning a flag telling whether there
+    /// was enough gas left, which is constant if it was charged before
+    pub(crate) fn consume_gas(
+        &'c self,
+        block: &'c Block,
+        amount: i64,
+    ) -> Result<Value, CodegenError> {
+        if self.gas_charged {
+            return self.check_passed(block);
+        }
+        utils::consume_gas(self.mlir_context, block, amount)
+    }
+}
+
 // Syscall MLIR wrappers
 impl<'c> OperationCtx<'c> {
     pub(crate) fn write_result_syscall(
@@ -55,6 +178,8 @@ impl<'c> OperationCtx<'c> {
         block: &Block,
         offset: Value,
         size: Value,
+        remaining_gas: Value,
+        execution_result: Value,
         location: Location,
     ) {
         syscall::mlir::write_result_syscall(
@@ -63,6 +188,56 @@ impl<'c> OperationCtx<'c> {
             block,
             offset,
             size,
+            remaining_gas,
+            execution_result,
+            location,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn trace_step_syscall(
+        &self,
+        block: &Block,
+        pc: Value,
+        opcode: Value,
+        gas: Value,
+        stack_base: Value,
+        stack_top: Value,
+        location: Location,
+    ) {
+        syscall::mlir::trace_step_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            pc,
+            opcode,
+            gas,
+            stack_base,
+            stack_top,
+            location,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn breakpoint_syscall(
+        &self,
+        block: &Block,
+        pc: Value,
+        opcode: Value,
+        gas: Value,
+        stack_base: Value,
+        stack_top: Value,
+        location: Location,
+    ) {
+        syscall::mlir::breakpoint_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            pc,
+            opcode,
+            gas,
+            stack_base,
+            stack_top,
             location,
         )
     }
@@ -81,4 +256,656 @@ impl<'c> OperationCtx<'c> {
             location,
         )
     }
+
+    pub(crate) fn keccak256_syscall(
+        &self,
+        block: &Block,
+        offset: Value,
+        size: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::keccak256_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            offset,
+            size,
+            hash_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_address_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_address_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_origin_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_origin_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_caller_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_caller_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_callvalue_syscall(
+        &self,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_callvalue_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_gasprice_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_gasprice_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_coinbase_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_coinbase_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_timestamp_syscall(
+        &self,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_timestamp_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_number_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_number_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_prevrandao_syscall(
+        &self,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_prevrandao_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_gaslimit_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_gaslimit_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_chainid_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_chainid_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_basefee_syscall(&self, block: &Block, value_ptr: Value, location: Location) {
+        syscall::mlir::get_basefee_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_blobbasefee_syscall(
+        &self,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_blobbasefee_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_self_balance_syscall(
+        &self,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_self_balance_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_blob_hash_syscall(
+        &self,
+        block: &Block,
+        index_ptr: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_blob_hash_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            index_ptr,
+            hash_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_block_hash_syscall(
+        &self,
+        block: &Block,
+        number_ptr: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_block_hash_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            number_ptr,
+            hash_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn calldata_load_syscall(
+        &self,
+        block: &Block,
+        offset: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::calldata_load_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            offset,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_calldata_size_syscall(
+        &'c self,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::get_calldata_size_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            location,
+        )
+    }
+
+    pub(crate) fn copy_calldata_to_memory_syscall(
+        &self,
+        block: &Block,
+        calldata_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        syscall::mlir::copy_calldata_to_memory_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            calldata_offset,
+            size,
+            dest_offset,
+            location,
+        )
+    }
+
+    pub(crate) fn get_code_size_syscall(
+        &'c self,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::get_code_size_syscall(self.mlir_context, self.syscall_ctx, block, location)
+    }
+
+    pub(crate) fn copy_code_to_memory_syscall(
+        &self,
+        block: &Block,
+        code_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        syscall::mlir::copy_code_to_memory_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            code_offset,
+            size,
+            dest_offset,
+            location,
+        )
+    }
+
+    pub(crate) fn get_return_data_size_syscall(
+        &'c self,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::get_return_data_size_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            location,
+        )
+    }
+
+    pub(crate) fn copy_return_data_to_memory_syscall(
+        &self,
+        block: &Block,
+        return_data_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        syscall::mlir::copy_return_data_to_memory_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            return_data_offset,
+            size,
+            dest_offset,
+            location,
+        )
+    }
+
+    pub(crate) fn get_extcode_size_syscall(
+        &'c self,
+        block: &'c Block,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::get_extcode_size_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            address_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_extcode_hash_syscall(
+        &self,
+        block: &Block,
+        address_ptr: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_extcode_hash_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            address_ptr,
+            hash_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn get_balance_syscall(
+        &self,
+        block: &Block,
+        address_ptr: Value,
+        balance_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::get_balance_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            address_ptr,
+            balance_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn copy_extcode_to_memory_syscall(
+        &self,
+        block: &Block,
+        address_ptr: Value,
+        code_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        syscall::mlir::copy_extcode_to_memory_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            address_ptr,
+            code_offset,
+            size,
+            dest_offset,
+            location,
+        )
+    }
+
+    pub(crate) fn storage_read_syscall(
+        &self,
+        block: &Block,
+        key_ptr: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::storage_read_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            key_ptr,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn storage_write_syscall(
+        &'c self,
+        block: &'c Block,
+        key_ptr: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        remaining_gas: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::storage_write_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            key_ptr,
+            value_ptr,
+            remaining_gas,
+            location,
+        )
+    }
+
+    pub(crate) fn access_storage_slot_syscall(
+        &'c self,
+        block: &'c Block,
+        key_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::access_storage_slot_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            key_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn access_account_syscall(
+        &'c self,
+        block: &'c Block,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::access_account_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            address_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn call_new_account_cost_syscall(
+        &'c self,
+        block: &'c Block,
+        address_ptr: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::call_new_account_cost_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            address_ptr,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn transient_storage_read_syscall(
+        &self,
+        block: &Block,
+        key_ptr: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::transient_storage_read_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            key_ptr,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn transient_storage_write_syscall(
+        &self,
+        block: &Block,
+        key_ptr: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::transient_storage_write_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            key_ptr,
+            value_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn append_log_syscall(
+        &self,
+        block: &Block,
+        offset: Value,
+        size: Value,
+        location: Location,
+    ) {
+        syscall::mlir::append_log_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            offset,
+            size,
+            location,
+        )
+    }
+
+    pub(crate) fn append_log_topic_syscall(
+        &self,
+        block: &Block,
+        topic_ptr: Value,
+        location: Location,
+    ) {
+        syscall::mlir::append_log_topic_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            topic_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn is_static_syscall(
+        &'c self,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::is_static_syscall(self.mlir_context, self.syscall_ctx, block, location)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn call_syscall(
+        &'c self,
+        block: &'c Block,
+        call_type: Value<'c, 'c>,
+        gas_limit: Value<'c, 'c>,
+        address_ptr: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        args_offset: Value<'c, 'c>,
+        args_size: Value<'c, 'c>,
+        ret_offset: Value<'c, 'c>,
+        ret_size: Value<'c, 'c>,
+        remaining_gas_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::call_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            call_type,
+            gas_limit,
+            address_ptr,
+            value_ptr,
+            args_offset,
+            args_size,
+            ret_offset,
+            ret_size,
+            remaining_gas_ptr,
+            location,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_syscall(
+        &'c self,
+        block: &'c Block,
+        offset: Value<'c, 'c>,
+        size: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        gas_limit: Value<'c, 'c>,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::create_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            offset,
+            size,
+            value_ptr,
+            gas_limit,
+            address_ptr,
+            location,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create2_syscall(
+        &'c self,
+        block: &'c Block,
+        offset: Value<'c, 'c>,
+        size: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        salt_ptr: Value<'c, 'c>,
+        gas_limit: Value<'c, 'c>,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::create2_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            offset,
+            size,
+            value_ptr,
+            salt_ptr,
+            gas_limit,
+            address_ptr,
+            location,
+        )
+    }
+
+    pub(crate) fn selfdestruct_syscall(
+        &'c self,
+        block: &'c Block,
+        beneficiary_ptr: Value<'c, 'c>,
+        remaining_gas: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value, CodegenError> {
+        syscall::mlir::selfdestruct_syscall(
+            self.mlir_context,
+            self.syscall_ctx,
+            block,
+            beneficiary_ptr,
+            remaining_gas,
+            location,
+        )
+    }
 }
@@ -1,20 +1,30 @@
 use melior::{
     dialect::{arith, cf, func, llvm, llvm::r#type::pointer, llvm::LoadStoreOptions, ods},
     ir::{
-        attribute::IntegerAttribute, r#type::IntegerType, Attribute, Block, BlockRef, Location,
-        Region,
+        attribute::{FlatSymbolRefAttribute, IntegerAttribute},
+        r#type::IntegerType,
+        Attribute, Block, BlockRef, Region, Value,
     },
 };
 
-use super::context::OperationCtx;
+use super::{context::OperationCtx, word::WordBuilder};
 use crate::{
-    constants::gas_cost,
+    constants::{
+        gas_cost, CallType, ExitStatusCode, CODE_SECTION_SYMBOL_PREFIX, DATA_SECTION_GLOBAL,
+        MAX_INITCODE_SIZE, MAX_RETURN_STACK_DEPTH, RETF_STATUS, RETURN_STACK_DEPTH_GLOBAL,
+    },
+    eof::SectionType,
     errors::CodegenError,
-    program::Operation,
+    program::{exchanged_depths, Operation},
+    spec::SpecId,
     utils::{
-        check_if_zero, check_is_greater_than, check_stack_has_at_least, check_stack_has_space_for,
-        constant_value_from_i64, consume_gas, extend_memory, get_nth_from_stack, get_remaining_gas,
-        integer_constant_from_i64, integer_constant_from_i8, stack_pop, stack_push,
+        all_but_one_64th, allocate_and_store_u256, allocate_u256, allocate_u64, check_if_zero,
+        check_is_greater_than, check_not_static, check_stack_has_space_for,
+        constant_value_from_i64, consume_all_gas, consume_gas, consume_gas_as_value,
+        consume_memory_expansion_gas, extend_memory, get_gas_counter, get_memory_size,
+        get_nth_from_stack, get_remaining_gas, integer_constant_from_i64, llvm_mlir,
+        memory_segment, restore_program_state, return_unused_gas, saturating_trunc_to_u32,
+        save_program_state, signed_division_safe_denominator, stack_pop, stack_push,
         swap_stack_elements,
     },
 };
@@ -27,10 +37,14 @@ pub fn generate_code_for_op<'c>(
     region: &'c Region<'c>,
     op: Operation,
 ) -> Result<(BlockRef<'c, 'c>, BlockRef<'c, 'c>), CodegenError> {
+    // Opcodes introduced after the hardfork being compiled for are undefined
+    if !op_ctx.spec_id.includes(op.introduced_in()) {
+        return codegen_invalid(op_ctx, region);
+    }
     match op {
         Operation::Stop => codegen_stop(op_ctx, region),
         Operation::Push0 => codegen_push(op_ctx, region, BigUint::ZERO, true),
-        Operation::Push(x) => codegen_push(op_ctx, region, x, false),
+        Operation::Push(x) | Operation::PushN(_, x) => codegen_push(op_ctx, region, x, false),
         Operation::Add => codegen_add(op_ctx, region),
         Operation::Mul => codegen_mul(op_ctx, region),
         Operation::Sub => codegen_sub(op_ctx, region),
@@ -51,6 +65,7 @@ pub fn generate_code_for_op<'c>(
         Operation::And => codegen_and(op_ctx, region),
         Operation::Or => codegen_or(op_ctx, region),
         Operation::Xor => codegen_xor(op_ctx, region),
+        Operation::Not => codegen_not(op_ctx, region),
         Operation::Byte => codegen_byte(op_ctx, region),
         Operation::Shr => codegen_shr(op_ctx, region),
         Operation::Shl => codegen_shl(op_ctx, region),
@@ -58,14 +73,79 @@ pub fn generate_code_for_op<'c>(
         Operation::Pop => codegen_pop(op_ctx, region),
         Operation::Jump => codegen_jump(op_ctx, region),
         Operation::Jumpi => codegen_jumpi(op_ctx, region),
+        Operation::Rjump(_) => codegen_rjump(op_ctx, region),
+        Operation::Rjumpi(_) => codegen_rjumpi(op_ctx, region),
+        Operation::Rjumpv(_) => codegen_rjumpv(op_ctx, region),
+        Operation::Callf(section, section_type) => {
+            codegen_callf(op_ctx, region, section, section_type)
+        }
+        Operation::Retf => codegen_retf(op_ctx, region),
+        Operation::Jumpf(section, section_type) => {
+            codegen_jumpf(op_ctx, region, section, section_type)
+        }
+        Operation::Dataload => codegen_dataload(op_ctx, region),
+        Operation::Dataloadn(offset) => codegen_dataloadn(op_ctx, region, offset),
+        Operation::Datasize => codegen_datasize(op_ctx, region),
+        Operation::Datacopy => codegen_datacopy(op_ctx, region),
         Operation::PC { pc } => codegen_pc(op_ctx, region, pc),
         Operation::Gas => codegen_gas(op_ctx, region),
         Operation::Jumpdest { pc } => codegen_jumpdest(op_ctx, region, pc),
+        Operation::Label(_) | Operation::PushLabel(_) => {
+            unreachable!("labels are resolved before generating code")
+        }
         Operation::Dup(x) => codegen_dup(op_ctx, region, x),
         Operation::Swap(x) => codegen_swap(op_ctx, region, x),
+        Operation::Dupn(immediate) => codegen_dup(op_ctx, region, immediate as u32 + 1),
+        Operation::Swapn(immediate) => codegen_swap(op_ctx, region, immediate as u32 + 1),
+        Operation::Exchange(immediate) => codegen_exchange(op_ctx, region, immediate),
         Operation::Return => codegen_return(op_ctx, region),
+        Operation::Revert => codegen_revert(op_ctx, region),
+        Operation::Invalid | Operation::Undefined(_) => codegen_invalid(op_ctx, region),
+        Operation::Sload => codegen_sload(op_ctx, region),
+        Operation::Sstore => codegen_sstore(op_ctx, region),
+        Operation::Tload => codegen_tload(op_ctx, region),
+        Operation::Tstore => codegen_tstore(op_ctx, region),
+        Operation::Log(x) => codegen_log(op_ctx, region, x),
+        Operation::Address => codegen_address(op_ctx, region),
+        Operation::Origin => codegen_origin(op_ctx, region),
+        Operation::Caller => codegen_caller(op_ctx, region),
+        Operation::Callvalue => codegen_callvalue(op_ctx, region),
+        Operation::Gasprice => codegen_gasprice(op_ctx, region),
+        Operation::Coinbase => codegen_coinbase(op_ctx, region),
+        Operation::Timestamp => codegen_timestamp(op_ctx, region),
+        Operation::Number => codegen_number(op_ctx, region),
+        Operation::Prevrandao => codegen_prevrandao(op_ctx, region),
+        Operation::Gaslimit => codegen_gaslimit(op_ctx, region),
+        Operation::Chainid => codegen_chainid(op_ctx, region),
+        Operation::Basefee => codegen_basefee(op_ctx, region),
+        Operation::Blockhash => codegen_blockhash(op_ctx, region),
+        Operation::Extcodesize => codegen_extcodesize(op_ctx, region),
+        Operation::Extcodecopy => codegen_extcodecopy(op_ctx, region),
+        Operation::Extcodehash => codegen_extcodehash(op_ctx, region),
+        Operation::Balance => codegen_balance(op_ctx, region),
+        Operation::Selfbalance => codegen_selfbalance(op_ctx, region),
+        Operation::Returndatasize => codegen_returndatasize(op_ctx, region),
+        Operation::Returndatacopy => codegen_returndatacopy(op_ctx, region),
+        Operation::Blobhash => codegen_blobhash(op_ctx, region),
+        Operation::Blobbasefee => codegen_blobbasefee(op_ctx, region),
+        Operation::Call => codegen_call(op_ctx, region, CallType::Call),
+        Operation::Callcode => codegen_call(op_ctx, region, CallType::CallCode),
+        Operation::Delegatecall => codegen_call(op_ctx, region, CallType::DelegateCall),
+        Operation::Staticcall => codegen_call(op_ctx, region, CallType::StaticCall),
+        Operation::Create => codegen_create(op_ctx, region, false),
+        Operation::Create2 => codegen_create(op_ctx, region, true),
+        Operation::Selfdestruct => codegen_selfdestruct(op_ctx, region),
         Operation::Mstore => codegen_mstore(op_ctx, region),
         Operation::Mstore8 => codegen_mstore8(op_ctx, region),
+        Operation::Mload => codegen_mload(op_ctx, region),
+        Operation::Msize => codegen_msize(op_ctx, region),
+        Operation::Mcopy => codegen_mcopy(op_ctx, region),
+        Operation::Keccak256 => codegen_keccak256(op_ctx, region),
+        Operation::CallDataLoad => codegen_calldataload(op_ctx, region),
+        Operation::CallDataSize => codegen_calldatasize(op_ctx, region),
+        Operation::CallDataCopy => codegen_calldatacopy(op_ctx, region),
+        Operation::Codesize => codegen_codesize(op_ctx, region),
+        Operation::Codecopy => codegen_codecopy(op_ctx, region),
     }
 }
 
@@ -75,11 +155,11 @@ fn codegen_exp<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::EXP)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::EXP)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -96,17 +176,92 @@ fn codegen_exp<'c, 'r>(
         location,
     ));
 
-    let lhs = stack_pop(context, &ok_block)?;
-    let rhs = stack_pop(context, &ok_block)?;
+    let base = stack_pop(context, &ok_block)?;
+    let exponent = stack_pop(context, &ok_block)?;
 
-    let result = ok_block
-        .append_operation(ods::math::ipowi(context, rhs, lhs, location).into())
+    // Compute the dynamic gas cost, which depends on the byte size of the exponent
+    // dynamic_gas_cost = EXP_BYTE * ceil((256 - leading_zeros(exponent)) / 8)
+    let uint64 = IntegerType::new(context, 64);
+    let uint256 = IntegerType::new(context, 256);
+
+    let leading_zeros = ok_block
+        .append_operation(llvm::intr_ctlz(
+            context,
+            exponent,
+            false,
+            uint256.into(),
+            location,
+        ))
         .result(0)?
         .into();
 
-    stack_push(context, &ok_block, result)?;
+    let max_bits = constant_value_from_i64(context, &ok_block, 256)?;
+    let exponent_bits = ok_block
+        .append_operation(arith::subi(max_bits, leading_zeros, location))
+        .result(0)?
+        .into();
 
-    Ok((start_block, ok_block))
+    let seven = constant_value_from_i64(context, &ok_block, 7)?;
+    let bits_per_byte = constant_value_from_i64(context, &ok_block, 8)?;
+    let exponent_bits_rounded_up = ok_block
+        .append_operation(arith::addi(exponent_bits, seven, location))
+        .result(0)?
+        .into();
+    let exponent_bytes = ok_block
+        .append_operation(arith::divui(
+            exponent_bits_rounded_up,
+            bits_per_byte,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let exponent_bytes = ok_block
+        .append_operation(arith::trunci(exponent_bytes, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let exp_byte_gas = if op_ctx.spec_id.includes(SpecId::SpuriousDragon) {
+        gas_cost::EXP_BYTE
+    } else {
+        gas_cost::EXP_BYTE_PRE_SPURIOUS_DRAGON
+    };
+    let gas_per_byte = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), exp_byte_gas).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(exponent_bytes, gas_per_byte, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let result = end_block
+        .append_operation(ods::math::ipowi(context, base, exponent, location).into())
+        .result(0)?
+        .into();
+
+    stack_push(context, &end_block, result)?;
+
+    Ok((start_block, end_block))
 }
 
 fn codegen_iszero<'c, 'r>(
@@ -115,11 +270,11 @@ fn codegen_iszero<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 1)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::ISZERO)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::ISZERO)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -187,11 +342,11 @@ fn codegen_and<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::AND)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::AND)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -212,10 +367,8 @@ fn codegen_and<'c, 'r>(
     let lhs = stack_pop(context, &ok_block)?;
     let rhs = stack_pop(context, &ok_block)?;
 
-    let result = ok_block
-        .append_operation(arith::andi(lhs, rhs, location))
-        .result(0)?
-        .into();
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.and(lhs, rhs)?;
 
     stack_push(context, &ok_block, result)?;
 
@@ -228,11 +381,11 @@ fn codegen_gt<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::GT)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::GT)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -249,17 +402,16 @@ fn codegen_gt<'c, 'r>(
         location,
     ));
 
-    let rhs = stack_pop(context, &ok_block)?;
     let lhs = stack_pop(context, &ok_block)?;
+    let rhs = stack_pop(context, &ok_block)?;
 
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.greater_than(lhs, rhs)?;
+
+    // Extend the 1-bit result to 256 bits
+    let uint256 = IntegerType::new(context, 256);
     let result = ok_block
-        .append_operation(arith::cmpi(
-            context,
-            arith::CmpiPredicate::Ugt,
-            lhs,
-            rhs,
-            location,
-        ))
+        .append_operation(arith::extui(result, uint256.into(), location))
         .result(0)?
         .into();
 
@@ -274,11 +426,11 @@ fn codegen_or<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::OR)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::OR)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -299,10 +451,8 @@ fn codegen_or<'c, 'r>(
     let lhs = stack_pop(context, &ok_block)?;
     let rhs = stack_pop(context, &ok_block)?;
 
-    let result = ok_block
-        .append_operation(arith::ori(lhs, rhs, location))
-        .result(0)?
-        .into();
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.or(lhs, rhs)?;
 
     stack_push(context, &ok_block, result)?;
 
@@ -315,11 +465,11 @@ fn codegen_lt<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::LT)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::LT)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -340,14 +490,13 @@ fn codegen_lt<'c, 'r>(
     let lhs = stack_pop(context, &ok_block)?;
     let rhs = stack_pop(context, &ok_block)?;
 
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.less_than(lhs, rhs)?;
+
+    // Extend the 1-bit result to 256 bits
+    let uint256 = IntegerType::new(context, 256);
     let result = ok_block
-        .append_operation(arith::cmpi(
-            context,
-            arith::CmpiPredicate::Ult,
-            lhs,
-            rhs,
-            location,
-        ))
+        .append_operation(arith::extui(result, uint256.into(), location))
         .result(0)?
         .into();
 
@@ -362,11 +511,11 @@ fn codegen_sgt<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SGT)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SGT)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -398,6 +547,13 @@ fn codegen_sgt<'c, 'r>(
         .result(0)?
         .into();
 
+    // Extend the 1-bit result to 256 bits
+    let uint256 = IntegerType::new(context, 256);
+    let result = ok_block
+        .append_operation(arith::extui(result, uint256.into(), location))
+        .result(0)?
+        .into();
+
     stack_push(context, &ok_block, result)?;
 
     Ok((start_block, ok_block))
@@ -409,11 +565,11 @@ fn codegen_eq<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::EQ)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::EQ)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -433,14 +589,13 @@ fn codegen_eq<'c, 'r>(
     let lhs = stack_pop(context, &ok_block)?;
     let rhs = stack_pop(context, &ok_block)?;
 
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.equal(lhs, rhs)?;
+
+    // Extend the 1-bit result to 256 bits
+    let uint256 = IntegerType::new(context, 256);
     let result = ok_block
-        .append_operation(arith::cmpi(
-            context,
-            arith::CmpiPredicate::Eq,
-            lhs,
-            rhs,
-            location,
-        ))
+        .append_operation(arith::extui(result, uint256.into(), location))
         .result(0)?
         .into();
 
@@ -457,16 +612,16 @@ fn codegen_push<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough space in stack
-    let flag = check_stack_has_space_for(context, &start_block, 1)?;
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
     let gas_cost = if is_zero {
         gas_cost::PUSH0
     } else {
         gas_cost::PUSHN
     };
-    let gas_flag = consume_gas(context, &start_block, gas_cost)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -500,15 +655,16 @@ fn codegen_dup<'c, 'r>(
     region: &'r Region<'c>,
     nth: u32,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
-    debug_assert!(nth > 0 && nth <= 16);
+    // DUPN and SWAPN reach up to 256 items deep
+    debug_assert!(nth > 0 && nth <= 256);
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, nth)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, nth)?;
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::DUPN)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::DUPN)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -538,15 +694,16 @@ fn codegen_swap<'c, 'r>(
     region: &'r Region<'c>,
     nth: u32,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
-    debug_assert!(nth > 0 && nth <= 16);
+    // DUPN and SWAPN reach up to 256 items deep
+    debug_assert!(nth > 0 && nth <= 256);
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, nth + 1)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, nth + 1)?;
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SWAPN)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SWAPN)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -570,18 +727,55 @@ fn codegen_swap<'c, 'r>(
     Ok((start_block, ok_block))
 }
 
+fn codegen_exchange<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+    immediate: u8,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let (first, second) = exchanged_depths(immediate);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, second)?;
+
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::EXCHANGE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    swap_stack_elements(context, &ok_block, first, second)?;
+
+    Ok((start_block, ok_block))
+}
+
 fn codegen_add<'c, 'r>(
     op_ctx: &mut OperationCtx<'c>,
     region: &'r Region<'c>,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::ADD)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::ADD)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -603,10 +797,8 @@ fn codegen_add<'c, 'r>(
     let lhs = stack_pop(context, &ok_block)?;
     let rhs = stack_pop(context, &ok_block)?;
 
-    let result = ok_block
-        .append_operation(arith::addi(lhs, rhs, location))
-        .result(0)?
-        .into();
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.add(lhs, rhs)?;
 
     stack_push(context, &ok_block, result)?;
 
@@ -619,12 +811,12 @@ fn codegen_sub<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SUB)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SUB)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -646,10 +838,8 @@ fn codegen_sub<'c, 'r>(
     let lhs = stack_pop(context, &ok_block)?;
     let rhs = stack_pop(context, &ok_block)?;
 
-    let result = ok_block
-        .append_operation(arith::subi(lhs, rhs, location))
-        .result(0)?
-        .into();
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.sub(lhs, rhs)?;
 
     stack_push(context, &ok_block, result)?;
 
@@ -662,13 +852,13 @@ fn codegen_div<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let stack_size_flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let stack_size_flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
 
     // Check there's enough gas to compute the operation
-    let gas_flag = consume_gas(context, &start_block, gas_cost::DIV)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::DIV)?;
 
     let ok_flag = start_block
         .append_operation(arith::andi(stack_size_flag, gas_flag, location))
@@ -729,11 +919,11 @@ fn codegen_sdiv<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let stack_size_flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SDIV)?;
+    let stack_size_flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SDIV)?;
 
     let ok_flag = start_block
         .append_operation(arith::andi(stack_size_flag, gas_flag, location))
@@ -765,6 +955,9 @@ fn codegen_sdiv<'c, 'r>(
     den_zero_bloq.append_operation(cf::br(&return_block, &[], location));
 
     // Denominator is not zero path
+    // MIN_I256 / -1 overflows, so we divide by 1 instead, which yields the
+    // expected MIN_I256 result
+    let den = signed_division_safe_denominator(context, &den_not_zero_bloq, num, den)?;
     let result = den_not_zero_bloq
         .append_operation(ods::llvm::sdiv(context, num, den, location).into())
         .result(0)?
@@ -793,12 +986,12 @@ fn codegen_mul<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let stack_size_flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let stack_size_flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
     // Check there's enough gas to compute the operation
-    let gas_flag = consume_gas(context, &start_block, gas_cost::MUL)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MUL)?;
 
     let ok_flag = start_block
         .append_operation(arith::andi(stack_size_flag, gas_flag, location))
@@ -836,11 +1029,11 @@ fn codegen_mod<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::MOD)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MOD)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -907,11 +1100,11 @@ fn codegen_smod<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SMOD)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SMOD)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -950,6 +1143,9 @@ fn codegen_smod<'c, 'r>(
 
     den_zero_bloq.append_operation(cf::br(&return_block, &[], location));
 
+    // MIN_I256 % -1 overflows, so we compute the remainder with 1 instead, which
+    // yields the expected 0 result
+    let den = signed_division_safe_denominator(context, &den_not_zero_bloq, num, den)?;
     let mod_result = den_not_zero_bloq
         .append_operation(ods::llvm::srem(context, num, den, location).into())
         .result(0)?
@@ -978,11 +1174,11 @@ fn codegen_addmod<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 3)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::ADDMOD)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::ADDMOD)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -1073,11 +1269,11 @@ fn codegen_mulmod<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 3)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::MULMOD)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MULMOD)?;
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
@@ -1167,12 +1363,12 @@ fn codegen_xor<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::XOR)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::XOR)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -1194,11 +1390,49 @@ fn codegen_xor<'c, 'r>(
     let lhs = stack_pop(context, &ok_block)?;
     let rhs = stack_pop(context, &ok_block)?;
 
-    let result = ok_block
-        .append_operation(arith::xori(lhs, rhs, location))
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.xor(lhs, rhs)?;
+
+    stack_push(context, &ok_block, result)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_not<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::NOT)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
         .into();
 
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value = stack_pop(context, &ok_block)?;
+
+    let word = WordBuilder::new(context, op_ctx.word_repr, &ok_block, location);
+    let result = word.not(value)?;
+
     stack_push(context, &ok_block, result)?;
 
     Ok((start_block, ok_block))
@@ -1210,13 +1444,13 @@ fn codegen_shr<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
     let uint256 = IntegerType::new(context, 256);
 
     // Check there's enough elements in stack
-    let mut flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let mut flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
 
-    let gas_flag = consume_gas(context, &start_block, 3)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SHR)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -1297,13 +1531,13 @@ fn codegen_shl<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
     let uint256 = IntegerType::new(context, 256);
 
     // Check there's enough elements in stack
-    let mut flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let mut flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SHL)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SHL)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -1384,12 +1618,12 @@ fn codegen_pop<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's at least 1 element in stack
-    let flag = check_stack_has_at_least(context, &start_block, 1)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
 
-    let gas_flag = consume_gas(context, &start_block, gas_cost::POP)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::POP)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -1419,12 +1653,12 @@ fn codegen_sar<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
     // Check there's enough gas
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SAR)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SAR)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -1479,12 +1713,12 @@ fn codegen_byte<'c, 'r>(
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
     // Check there's enough gas
-    let gas_flag = consume_gas(context, &start_block, gas_cost::BYTE)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::BYTE)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -1625,10 +1859,10 @@ fn codegen_jumpdest<'c>(
 ) -> Result<(BlockRef<'c, 'c>, BlockRef<'c, 'c>), CodegenError> {
     let landing_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let location = op_ctx.location;
 
     // Check there's enough gas to compute the operation
-    let gas_flag = consume_gas(context, &landing_block, gas_cost::JUMPDEST)?;
+    let gas_flag = op_ctx.consume_gas(&landing_block, gas_cost::JUMPDEST)?;
 
     let ok_block = region.append_block(Block::new(&[]));
 
@@ -1653,17 +1887,24 @@ fn codegen_jumpi<'c, 'r: 'c>(
     region: &'r Region<'c>,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
-    let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::JUMPI)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
 
     let ok_block = region.append_block(Block::new(&[]));
 
     start_block.append_operation(cf::cond_br(
         context,
-        flag,
+        condition,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -1697,12 +1938,19 @@ fn codegen_jumpi<'c, 'r: 'c>(
         ))
         .result(0)?;
 
+    // The jump goes straight to its JUMPDEST if the target is known, and through the
+    // jump table otherwise
+    let jumptable_args = [pc];
+    let (target_block, target_args): (_, &[Value]) = match op_ctx.jump_target {
+        Some(target) => (op_ctx.static_jump_block(region, target), &[]),
+        None => (op_ctx.jumptable_block, &jumptable_args),
+    };
     ok_block.append_operation(cf::cond_br(
         context,
         condition.into(),
-        &op_ctx.jumptable_block,
+        &target_block,
         &false_block,
-        &[pc],
+        target_args,
         &[],
         location,
     ));
@@ -1718,13 +1966,13 @@ fn codegen_jump<'c, 'r: 'c>(
     // The error is generated even if the JUMP would not have been done
 
     let start_block = region.append_block(Block::new(&[]));
-    let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 1)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
     // Check there's enough gas
-    let gas_flag = consume_gas(context, &start_block, gas_cost::JUMP)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::JUMP)?;
 
     let ok_block = region.append_block(Block::new(&[]));
 
@@ -1745,10 +1993,17 @@ fn codegen_jump<'c, 'r: 'c>(
 
     let pc = stack_pop(context, &ok_block)?;
 
-    // appends operation to ok_block to jump to the `jump table block``
-    // in the jump table block the pc is checked and if its ok
-    // then it jumps to the block associated with that pc
-    op_ctx.add_jump_op(ok_block, pc, location);
+    match op_ctx.jump_target {
+        // The target is known, so the jump goes straight to its JUMPDEST
+        Some(target) => {
+            let target_block = op_ctx.static_jump_block(region, target);
+            ok_block.append_operation(cf::br(&target_block, &[], location));
+        }
+        // appends operation to ok_block to jump to the `jump table block``
+        // in the jump table block the pc is checked and if its ok
+        // then it jumps to the block associated with that pc
+        None => op_ctx.add_jump_op(ok_block, pc, location),
+    }
 
     // TODO: we are creating an empty block that won't ever be reached
     // probably there's a better way to do this
@@ -1756,20 +2011,63 @@ fn codegen_jump<'c, 'r: 'c>(
     Ok((start_block, empty_block))
 }
 
-fn codegen_pc<'c>(
+/// Returns the block a relative jump goes to: the one of its target, or the revert block if
+/// it lands out of the program
+fn relative_jump_block<'c>(
     op_ctx: &mut OperationCtx<'c>,
     region: &'c Region<'c>,
-    pc: usize,
-) -> Result<(BlockRef<'c, 'c>, BlockRef<'c, 'c>), CodegenError> {
+    target: Option<usize>,
+) -> BlockRef<'c, 'c> {
+    match target {
+        Some(target) => op_ctx.static_jump_block(region, target),
+        None => op_ctx.revert_block,
+    }
+}
+
+fn codegen_rjump<'c, 'r: 'c>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
-    let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
 
-    let stack_size_flag = check_stack_has_space_for(context, &start_block, 1)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::PC)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::RJUMP)?;
 
-    let ok_flag = start_block
-        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
+    // The target is known, so the jump goes straight to it
+    let target = op_ctx.jump_target;
+    let target_block = relative_jump_block(op_ctx, region, target);
+    start_block.append_operation(cf::cond_br(
+        context,
+        gas_flag,
+        &target_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Like after a JUMP, the block left unterminated is never reached
+    let empty_block = region.append_block(Block::new(&[]));
+    Ok((start_block, empty_block))
+}
+
+fn codegen_rjumpi<'c, 'r: 'c>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::RJUMPI)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
         .into();
 
@@ -1777,7 +2075,7 @@ fn codegen_pc<'c>(
 
     start_block.append_operation(cf::cond_br(
         context,
-        ok_flag,
+        condition,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -1785,38 +2083,58 @@ fn codegen_pc<'c>(
         location,
     ));
 
-    let pc_value = ok_block
-        .append_operation(arith::constant(
+    let condition = stack_pop(context, &ok_block)?;
+    let zero = constant_value_from_i64(context, &ok_block, 0)?;
+    let condition = ok_block
+        .append_operation(arith::cmpi(
             context,
-            integer_constant_from_i64(context, pc as i64).into(),
+            arith::CmpiPredicate::Ne,
+            condition,
+            zero,
             location,
         ))
-        .result(0)?
-        .into();
+        .result(0)?;
 
-    stack_push(context, &ok_block, pc_value)?;
+    let false_block = region.append_block(Block::new(&[]));
+    let target = op_ctx.jump_target;
+    let target_block = relative_jump_block(op_ctx, region, target);
+    ok_block.append_operation(cf::cond_br(
+        context,
+        condition.into(),
+        &target_block,
+        &false_block,
+        &[],
+        &[],
+        location,
+    ));
 
-    Ok((start_block, ok_block))
+    Ok((start_block, false_block))
 }
 
-fn codegen_return<'c>(
+fn codegen_rjumpv<'c, 'r: 'c>(
     op_ctx: &mut OperationCtx<'c>,
-    region: &'c Region<'c>,
-) -> Result<(BlockRef<'c, 'c>, BlockRef<'c, 'c>), CodegenError> {
-    // TODO: compute gas cost for memory expansion
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
     let context = op_ctx.mlir_context;
-    let location = Location::unknown(context);
-
+    let location = op_ctx.location;
     let uint32 = IntegerType::new(context, 32);
 
-    let start_block = region.append_block(Block::new(&[]));
-    let ok_block = region.append_block(Block::new(&[]));
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::RJUMPV)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
 
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let ok_block = region.append_block(Block::new(&[]));
 
     start_block.append_operation(cf::cond_br(
         context,
-        flag,
+        condition,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -1824,73 +2142,183 @@ fn codegen_return<'c>(
         location,
     ));
 
-    let offset_u256 = stack_pop(context, &ok_block)?;
-    let size_u256 = stack_pop(context, &ok_block)?;
-
-    // NOTE: for simplicity, we're truncating both offset and size to 32 bits here.
-    // If any of them were bigger than a u32, we would have ran out of gas before here.
-    let offset = ok_block
-        .append_operation(arith::trunci(offset_u256, uint32.into(), location))
-        .result(0)
-        .unwrap()
+    // Indices past the last target fall through, so the index is only switched on as a
+    // 32-bit integer once it's known to be in range, like in the jump table
+    let index = stack_pop(context, &ok_block)?;
+    let targets = std::mem::take(&mut op_ctx.switch_targets);
+    let last_index = constant_value_from_i64(context, &ok_block, targets.len() as i64 - 1)?;
+    let in_range = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            index,
+            last_index,
+            location,
+        ))
+        .result(0)?
         .into();
 
-    let size = ok_block
-        .append_operation(arith::trunci(size_u256, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    let switch_block = region.append_block(Block::new(&[]));
+    let false_block = region.append_block(Block::new(&[]));
+    ok_block.append_operation(cf::cond_br(
+        context,
+        in_range,
+        &switch_block,
+        &false_block,
+        &[],
+        &[],
+        location,
+    ));
 
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, size, location))
+    let index = switch_block
+        .append_operation(arith::trunci(index, uint32.into(), location))
         .result(0)?
         .into();
+    let case_values: Vec<i64> = (0..targets.len() as i64).collect();
+    let target_blocks: Vec<BlockRef> = targets
+        .into_iter()
+        .map(|target| relative_jump_block(op_ctx, region, target))
+        .collect();
+    let case_destinations: Vec<(&Block, &[Value])> = target_blocks
+        .iter()
+        .map(|block| {
+            let destination: (&Block, &[Value]) = (block, &[]);
+            destination
+        })
+        .collect();
+    switch_block.append_operation(cf::switch(
+        context,
+        &case_values,
+        index,
+        uint32.into(),
+        (&false_block, &[]),
+        &case_destinations,
+        location,
+    )?);
 
-    extend_memory(op_ctx, &ok_block, required_size)?;
+    Ok((start_block, false_block))
+}
 
-    op_ctx.write_result_syscall(&ok_block, offset, size, location);
+/// Returns a flag telling whether the stack holds the inputs of the code section, and
+/// has space for the items it pushes past them, as CALLF and JUMPF check (EIP-5450).
+/// The space is checked even if the basic block was, since it depends on the section
+fn check_section_stack<'c>(
+    op_ctx: &'c OperationCtx<'c>,
+    block: &'c Block,
+    section_type: SectionType,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let inputs = section_type.inputs as u32;
+    let growth = (section_type.max_stack_height as u32).saturating_sub(inputs);
 
-    Ok((start_block, ok_block))
+    let has_inputs = op_ctx.check_stack_has_at_least(block, inputs)?;
+    if op_ctx.stack_guarded || growth == 0 {
+        return Ok(has_inputs);
+    }
+    let has_space = if growth as usize >= op_ctx.stack_limit {
+        // The section can never run
+        block
+            .append_operation(arith::constant(
+                context,
+                IntegerAttribute::new(IntegerType::new(context, 1).into(), 0).into(),
+                location,
+            ))
+            .result(0)?
+            .into()
+    } else {
+        check_stack_has_space_for(context, block, growth, op_ctx.stack_limit)?
+    };
+    Ok(block
+        .append_operation(arith::andi(has_inputs, has_space, location))
+        .result(0)?
+        .into())
 }
 
-fn codegen_stop<'c, 'r>(
+/// Calls the function of the code section, returning its status, which is the one of
+/// the program if it halted, or [`RETF_STATUS`]
+fn call_section<'c>(
+    op_ctx: &OperationCtx<'c>,
+    block: &'c Block,
+    section: u16,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let symbol = format!("{CODE_SECTION_SYMBOL_PREFIX}{section}");
+    Ok(block
+        .append_operation(func::call(
+            context,
+            FlatSymbolRefAttribute::new(context, &symbol),
+            &[op_ctx.syscall_ctx],
+            &[IntegerType::new(context, 8).into()],
+            op_ctx.location,
+        ))
+        .result(0)?
+        .into())
+}
+
+fn codegen_callf<'c, 'r: 'c>(
     op_ctx: &mut OperationCtx<'c>,
     region: &'r Region<'c>,
+    section: u16,
+    section_type: SectionType,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
-    let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let ptr_type = pointer(context, 0);
+    let uint8 = IntegerType::new(context, 8);
+    let uint32 = IntegerType::new(context, 32);
 
-    let zero = start_block
+    // Check the stack holds the inputs, and has space for the section to run
+    let flag = check_section_stack(op_ctx, &start_block, section_type)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CALLF)?;
+
+    // Check the return stack isn't full
+    let depth_ptr = start_block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            RETURN_STACK_DEPTH_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+    let depth = start_block
+        .append_operation(llvm::load(
+            context,
+            depth_ptr,
+            uint32.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+    let max_depth = start_block
         .append_operation(arith::constant(
             context,
-            integer_constant_from_i8(context, 0).into(),
+            IntegerAttribute::new(uint32.into(), MAX_RETURN_STACK_DEPTH as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let depth_flag = start_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ult,
+            depth,
+            max_depth,
             location,
         ))
         .result(0)?
         .into();
 
-    start_block.append_operation(func::r#return(&[zero], location));
-    let empty_block = region.append_block(Block::new(&[]));
-
-    Ok((start_block, empty_block))
-}
-
-fn codegen_signextend<'c, 'r>(
-    op_ctx: &mut OperationCtx<'c>,
-    region: &'r Region<'c>,
-) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
-    let start_block = region.append_block(Block::new(&[]));
-    let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
-
-    // Check there's enough elements in stack
-    let stack_size_flag = check_stack_has_at_least(context, &start_block, 2)?;
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SIGNEXTEND)?;
-
-    // Check there's enough gas to perform the operation
-    let ok_flag = start_block
-        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+    let condition = start_block
+        .append_operation(arith::andi(condition, depth_flag, location))
         .result(0)?
         .into();
 
@@ -1898,7 +2326,7 @@ fn codegen_signextend<'c, 'r>(
 
     start_block.append_operation(cf::cond_br(
         context,
-        ok_flag,
+        condition,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -1906,84 +2334,88 @@ fn codegen_signextend<'c, 'r>(
         location,
     ));
 
-    let byte_size = stack_pop(context, &ok_block)?;
-    let value_to_extend = stack_pop(context, &ok_block)?;
-
-    // Constant definition
-    let max_byte_size = constant_value_from_i64(context, &ok_block, 31)?;
-    let bits_per_byte = constant_value_from_i64(context, &ok_block, 8)?;
-    let sign_bit_position_on_byte = constant_value_from_i64(context, &ok_block, 7)?;
-    let max_bits = constant_value_from_i64(context, &ok_block, 255)?;
-
-    // byte_size = min(max_byte_size, byte_size)
-    let byte_size = ok_block
-        .append_operation(arith::minui(byte_size, max_byte_size, location))
-        .result(0)?
-        .into();
-
-    // bits_to_shift = max_bits - byte_size * bits_per_byte + sign_bit_position_on_byte
-    let byte_number_in_bits = ok_block
-        .append_operation(arith::muli(byte_size, bits_per_byte, location))
-        .result(0)?
-        .into();
-
-    let value_size_in_bits = ok_block
-        .append_operation(arith::addi(
-            byte_number_in_bits,
-            sign_bit_position_on_byte,
+    // The depth is restored once the section returns, or once it halts
+    let one = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 1).into(),
             location,
         ))
         .result(0)?
         .into();
-
-    let bits_to_shift = ok_block
-        .append_operation(arith::subi(max_bits, value_size_in_bits, location))
+    let new_depth = ok_block
+        .append_operation(arith::addi(depth, one, location))
         .result(0)?
         .into();
+    ok_block.append_operation(llvm::store(
+        context,
+        new_depth,
+        depth_ptr,
+        location,
+        LoadStoreOptions::default(),
+    ));
+    let status = call_section(op_ctx, &ok_block, section)?;
+    ok_block.append_operation(llvm::store(
+        context,
+        depth,
+        depth_ptr,
+        location,
+        LoadStoreOptions::default(),
+    ));
 
-    // value_to_extend << bits_to_shift
-    let left_shifted_value = ok_block
-        .append_operation(ods::llvm::shl(context, value_to_extend, bits_to_shift, location).into())
+    // The program goes on if the section returned, and halts with it otherwise
+    let retf_status = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), RETF_STATUS as i64).into(),
+            location,
+        ))
         .result(0)?
         .into();
-
-    // value_to_extend >> bits_to_shift  (sign extended)
-    let result = ok_block
-        .append_operation(
-            ods::llvm::ashr(context, left_shifted_value, bits_to_shift, location).into(),
-        )
+    let returned = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Eq,
+            status,
+            retf_status,
+            location,
+        ))
         .result(0)?
         .into();
 
-    stack_push(context, &ok_block, result)?;
+    let halt_block = region.append_block(Block::new(&[]));
+    let return_block = region.append_block(Block::new(&[]));
+    ok_block.append_operation(cf::cond_br(
+        context,
+        returned,
+        &return_block,
+        &halt_block,
+        &[],
+        &[],
+        location,
+    ));
+    halt_block.append_operation(func::r#return(&[status], location));
 
-    Ok((start_block, ok_block))
+    Ok((start_block, return_block))
 }
 
-fn codegen_gas<'c, 'r>(
+fn codegen_retf<'c, 'r>(
     op_ctx: &mut OperationCtx<'c>,
     region: &'r Region<'c>,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
-    let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
-
-    // Check there's at least space for one element in the stack
-    let stack_size_flag = check_stack_has_space_for(context, &start_block, 1)?;
-
-    // Check there's enough gas to compute the operation
-    let gas_flag = consume_gas(context, &start_block, gas_cost::GAS)?;
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint8 = IntegerType::new(context, 8);
 
-    let ok_flag = start_block
-        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
-        .result(0)?
-        .into();
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::RETF)?;
 
     let ok_block = region.append_block(Block::new(&[]));
 
     start_block.append_operation(cf::cond_br(
         context,
-        ok_flag,
+        gas_flag,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -1991,29 +2423,38 @@ fn codegen_gas<'c, 'r>(
         location,
     ));
 
-    let gas = get_remaining_gas(context, &ok_block)?;
-
-    stack_push(context, &ok_block, gas)?;
+    // The outputs are left on the stack for the caller
+    let retf_status = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), RETF_STATUS as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    ok_block.append_operation(func::r#return(&[retf_status], location));
 
-    Ok((start_block, ok_block))
+    let empty_block = region.append_block(Block::new(&[]));
+    Ok((start_block, empty_block))
 }
 
-fn codegen_slt<'c, 'r>(
+fn codegen_jumpf<'c, 'r: 'c>(
     op_ctx: &mut OperationCtx<'c>,
     region: &'r Region<'c>,
+    section: u16,
+    section_type: SectionType,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
-    let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
-
-    // Check there's enough elements in stack
-    let stack_size_flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
 
-    // Check there's enough gas to compute the operation
-    let gas_flag = consume_gas(context, &start_block, gas_cost::SLT)?;
+    // Check the stack holds the inputs, and has space for the section to run
+    let flag = check_section_stack(op_ctx, &start_block, section_type)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::JUMPF)?;
 
-    let ok_flag = start_block
-        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
         .result(0)?
         .into();
 
@@ -2021,7 +2462,7 @@ fn codegen_slt<'c, 'r>(
 
     start_block.append_operation(cf::cond_br(
         context,
-        ok_flag,
+        condition,
         &ok_block,
         &op_ctx.revert_block,
         &[],
@@ -2029,40 +2470,31 @@ fn codegen_slt<'c, 'r>(
         location,
     ));
 
-    let lhs = stack_pop(context, &ok_block)?;
-    let rhs = stack_pop(context, &ok_block)?;
-
-    let result = ok_block
-        .append_operation(arith::cmpi(
-            context,
-            arith::CmpiPredicate::Slt,
-            lhs,
-            rhs,
-            location,
-        ))
-        .result(0)?
-        .into();
-
-    stack_push(context, &ok_block, result)?;
+    // The section returns in place of this one, so its status is returned as it is. Being
+    // in tail position, the call is turned into a jump when optimizing
+    let status = call_section(op_ctx, &ok_block, section)?;
+    ok_block.append_operation(func::r#return(&[status], location));
 
-    Ok((start_block, ok_block))
+    let empty_block = region.append_block(Block::new(&[]));
+    Ok((start_block, empty_block))
 }
 
-fn codegen_mstore<'c, 'r>(
+fn codegen_dataload<'c, 'r>(
     op_ctx: &mut OperationCtx<'c>,
     region: &'r Region<'c>,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
-    let uint8 = IntegerType::new(context, 8);
+    let location = op_ctx.location;
     let ptr_type = pointer(context, 0);
+    let uint8 = IntegerType::new(context, 8);
+    let uint32 = IntegerType::new(context, 32);
+    let uint256 = IntegerType::new(context, 256);
 
     // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
     // Check there's enough gas
-    let gas_flag = consume_gas(context, &start_block, gas_cost::MSTORE)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::DATALOAD)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -2082,40 +2514,39 @@ fn codegen_mstore<'c, 'r>(
     ));
 
     let offset = stack_pop(context, &ok_block)?;
-    let value = stack_pop(context, &ok_block)?;
 
-    // truncate offset to 32 bits
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)
-        .unwrap()
-        .into();
+    // Offsets past the end of the data read zeros, so saturating is safe here
+    let offset = saturating_trunc_to_u32(context, &ok_block, offset)?;
 
-    let value_width_in_bytes = 32;
-    // value_size = 32
-    let value_size = ok_block
+    let data_size = ok_block
         .append_operation(arith::constant(
             context,
-            IntegerAttribute::new(uint32.into(), value_width_in_bytes).into(),
+            IntegerAttribute::new(uint32.into(), op_ctx.data.len() as i64).into(),
             location,
         ))
         .result(0)?
         .into();
 
-    // required_size = offset + value_size
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, value_size, location))
+    // The data is followed by a word of zeros, which the offsets past its end read
+    let offset = ok_block
+        .append_operation(arith::minui(offset, data_size, location))
         .result(0)?
         .into();
 
-    // maybe we could check if there is already enough memory before extending it
-    let memory_ptr = extend_memory(op_ctx, &ok_block, required_size)?;
+    let data_ptr = ok_block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            DATA_SECTION_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
 
-    // memory_destination = memory_ptr + offset
-    let memory_destination = ok_block
+    let word_ptr = ok_block
         .append_operation(llvm::get_element_ptr_dynamic(
             context,
-            memory_ptr,
+            data_ptr,
             &[offset],
             uint8.into(),
             ptr_type,
@@ -2124,48 +2555,46 @@ fn codegen_mstore<'c, 'r>(
         .result(0)?
         .into();
 
-    let uint256 = IntegerType::new(context, 256);
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            word_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::new()
+                .align(IntegerAttribute::new(IntegerType::new(context, 64).into(), 1).into()),
+        ))
+        .result(0)?
+        .into();
 
-    // check system endianness before storing the value
+    // The data is big endian, like the stack items
     let value = if cfg!(target_endian = "little") {
-        // if the system is little endian, we convert the value to big endian
         ok_block
             .append_operation(llvm::intr_bswap(value, uint256.into(), location))
             .result(0)?
             .into()
     } else {
-        // if the system is big endian, there is no need to convert the value
         value
     };
 
-    // store the value in the memory
-    ok_block.append_operation(llvm::store(
-        context,
-        value,
-        memory_destination,
-        location,
-        LoadStoreOptions::new()
-            .align(IntegerAttribute::new(IntegerType::new(context, 64).into(), 1).into()),
-    ));
+    stack_push(context, &ok_block, value)?;
 
     Ok((start_block, ok_block))
 }
 
-fn codegen_mstore8<'c, 'r>(
+fn codegen_dataloadn<'c, 'r>(
     op_ctx: &mut OperationCtx<'c>,
     region: &'r Region<'c>,
+    offset: u16,
 ) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
     let start_block = region.append_block(Block::new(&[]));
     let context = &op_ctx.mlir_context;
-    let location = Location::unknown(context);
-    let uint32 = IntegerType::new(context, 32);
-    let uint8 = IntegerType::new(context, 8);
-    let ptr_type = pointer(context, 0);
+    let location = op_ctx.location;
 
-    // Check there's enough elements in stack
-    let flag = check_stack_has_at_least(context, &start_block, 2)?;
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
     // Check there's enough gas
-    let gas_flag = consume_gas(context, &start_block, gas_cost::MSTORE8)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::DATALOADN)?;
 
     let condition = start_block
         .append_operation(arith::andi(gas_flag, flag, location))
@@ -2184,52 +2613,187 @@ fn codegen_mstore8<'c, 'r>(
         location,
     ));
 
-    let offset = stack_pop(context, &ok_block)?;
-    let value = stack_pop(context, &ok_block)?;
+    // The offset is known, so the word is too. Validated containers don't read past the
+    // end of the data, but the programs built by hand read zeros there
+    let mut word = [0_u8; 32];
+    let bytes = op_ctx.data.get(offset as usize..).unwrap_or_default();
+    let read = bytes.len().min(word.len());
+    word[..read].copy_from_slice(&bytes[..read]);
+    let value = BigUint::from_bytes_be(&word);
 
-    // truncate value to the least significative byte of the 32-byte value
-    let value = ok_block
-        .append_operation(arith::trunci(
-            value,
-            r#IntegerType::new(context, 8).into(),
+    let constant_value = Attribute::parse(context, &format!("{} : i256", value)).unwrap();
+    let constant_value = ok_block
+        .append_operation(arith::constant(context, constant_value, location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, constant_value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_datasize<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::DATASIZE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let data_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint256.into(), op_ctx.data.len() as i64).into(),
             location,
         ))
         .result(0)?
         .into();
 
-    // truncate offset to 32 bits
-    let offset = ok_block
-        .append_operation(arith::trunci(offset, uint32.into(), location))
-        .result(0)
-        .unwrap()
+    stack_push(context, &ok_block, data_size)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_datacopy<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let ptr_type = pointer(context, 0);
+    let uint1 = IntegerType::new(context, 1);
+    let uint8 = IntegerType::new(context, 8);
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::DATACOPY)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let dest_offset = stack_pop(context, &ok_block)?;
+    let data_offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    let segment = memory_segment(context, &ok_block, dest_offset, size)?;
+    let (dest_offset, size) = (segment.offset, segment.size);
+
+    // Offsets past the end of the data copy zeros, so saturating is safe here
+    let data_offset = saturating_trunc_to_u32(context, &ok_block, data_offset)?;
+
+    // dynamic_gas_cost = ceil(size / 32) * COPY_WORD
+    let word_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
         .into();
 
-    let value_width_in_bytes = 1;
-    // value_size = 1
-    let value_size = ok_block
+    let size_in_words = ok_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_word = ok_block
         .append_operation(arith::constant(
             context,
-            IntegerAttribute::new(uint32.into(), value_width_in_bytes).into(),
+            IntegerAttribute::new(uint64.into(), gas_cost::COPY_WORD).into(),
             location,
         ))
         .result(0)?
         .into();
 
-    // required_size = offset + size
-    let required_size = ok_block
-        .append_operation(arith::addi(offset, value_size, location))
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
         .result(0)?
         .into();
 
-    // maybe we could check if there is already enough memory before extending it
-    let memory_ptr = extend_memory(op_ctx, &ok_block, required_size)?;
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
 
-    // memory_destination = memory_ptr + offset
-    let memory_destination = ok_block
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let memory_ptr = extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // destination = memory_ptr + dest_offset
+    let destination = memory_block
         .append_operation(llvm::get_element_ptr_dynamic(
             context,
             memory_ptr,
-            &[offset],
+            &[dest_offset],
             uint8.into(),
             ptr_type,
             location,
@@ -2237,14 +2801,3883 @@ fn codegen_mstore8<'c, 'r>(
         .result(0)?
         .into();
 
-    ok_block.append_operation(llvm::store(
+    // The bytes past the end of the data are zeros, so the whole range is cleared first
+    let zero_byte = memory_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    memory_block.append_operation(
+        ods::llvm::intr_memset(
+            context,
+            destination,
+            zero_byte,
+            size,
+            IntegerAttribute::new(uint1.into(), 0),
+            location,
+        )
+        .into(),
+    );
+
+    // copied_size = min(size, data_size - min(data_offset, data_size))
+    let data_size = memory_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), op_ctx.data.len() as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let data_offset = memory_block
+        .append_operation(arith::minui(data_offset, data_size, location))
+        .result(0)?
+        .into();
+
+    let data_left = memory_block
+        .append_operation(arith::subi(data_size, data_offset, location))
+        .result(0)?
+        .into();
+
+    let copied_size = memory_block
+        .append_operation(arith::minui(size, data_left, location))
+        .result(0)?
+        .into();
+
+    let data_ptr = memory_block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            DATA_SECTION_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // source = data_ptr + data_offset
+    let source = memory_block
+        .append_operation(llvm::get_element_ptr_dynamic(
+            context,
+            data_ptr,
+            &[data_offset],
+            uint8.into(),
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    memory_block.append_operation(
+        ods::llvm::intr_memcpy(
+            context,
+            destination,
+            source,
+            copied_size,
+            IntegerAttribute::new(uint1.into(), 0),
+            location,
+        )
+        .into(),
+    );
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_pc<'c>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'c Region<'c>,
+    pc: usize,
+) -> Result<(BlockRef<'c, 'c>, BlockRef<'c, 'c>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    let stack_size_flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::PC)?;
+
+    let ok_flag = start_block
+        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
         context,
-        value,
-        memory_destination,
+        ok_flag,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
         location,
-        LoadStoreOptions::new()
-            .align(IntegerAttribute::new(IntegerType::new(context, 64).into(), 1).into()),
     ));
 
+    let pc_value = ok_block
+        .append_operation(arith::constant(
+            context,
+            integer_constant_from_i64(context, pc as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, pc_value)?;
+
     Ok((start_block, ok_block))
 }
+
+fn codegen_return<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    codegen_halt_with_output(op_ctx, region, ExitStatusCode::Return)
+}
+
+fn codegen_revert<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    codegen_halt_with_output(op_ctx, region, ExitStatusCode::Revert)
+}
+
+/// Generates the code for RETURN and REVERT, which only differ in the exit status.
+/// Both read the output's offset and size from the stack, and halt the execution.
+fn codegen_halt_with_output<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+    exit_status: ExitStatusCode,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    let uint8 = IntegerType::new(context, 8);
+
+    let start_block = region.append_block(Block::new(&[]));
+    let ok_block = region.append_block(Block::new(&[]));
+
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        flag,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let offset_u256 = stack_pop(context, &ok_block)?;
+    let size_u256 = stack_pop(context, &ok_block)?;
+
+    // An empty output doesn't touch the memory, so it doesn't expand it
+    let segment = memory_segment(context, &ok_block, offset_u256, size_u256)?;
+    let (offset, size) = (segment.offset, segment.size);
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &ok_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    let exit_code = memory_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), exit_status.to_u8() as _).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let remaining_gas = get_gas_counter(context, &memory_block)?;
+
+    op_ctx.write_result_syscall(
+        &memory_block,
+        offset,
+        size,
+        remaining_gas,
+        exit_code,
+        location,
+    );
+
+    memory_block.append_operation(func::r#return(&[exit_code], location));
+    let empty_block = region.append_block(Block::new(&[]));
+
+    Ok((start_block, empty_block))
+}
+
+fn codegen_stop<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint8 = IntegerType::new(context, 8);
+    let uint32 = IntegerType::new(context, 32);
+
+    let exit_code = start_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), ExitStatusCode::Stop.to_u8() as _).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // STOP has no output, but the remaining gas is kept for the caller
+    let zero = start_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let remaining_gas = get_gas_counter(context, &start_block)?;
+
+    op_ctx.write_result_syscall(&start_block, zero, zero, remaining_gas, exit_code, location);
+
+    start_block.append_operation(func::r#return(&[exit_code], location));
+    let empty_block = region.append_block(Block::new(&[]));
+
+    Ok((start_block, empty_block))
+}
+
+fn codegen_invalid<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    // INVALID is an exceptional halt that consumes all the remaining gas
+    consume_all_gas(context, &start_block)?;
+
+    start_block.append_operation(cf::br(&op_ctx.revert_block, &[], location));
+    let empty_block = region.append_block(Block::new(&[]));
+
+    Ok((start_block, empty_block))
+}
+
+fn codegen_signextend<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    // Check there's enough elements in stack
+    let stack_size_flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SIGNEXTEND)?;
+
+    // Check there's enough gas to perform the operation
+    let ok_flag = start_block
+        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        ok_flag,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let byte_size = stack_pop(context, &ok_block)?;
+    let value_to_extend = stack_pop(context, &ok_block)?;
+
+    // Constant definition
+    let max_byte_size = constant_value_from_i64(context, &ok_block, 31)?;
+    let bits_per_byte = constant_value_from_i64(context, &ok_block, 8)?;
+    let sign_bit_position_on_byte = constant_value_from_i64(context, &ok_block, 7)?;
+    let max_bits = constant_value_from_i64(context, &ok_block, 255)?;
+
+    // byte_size = min(max_byte_size, byte_size)
+    let byte_size = ok_block
+        .append_operation(arith::minui(byte_size, max_byte_size, location))
+        .result(0)?
+        .into();
+
+    // bits_to_shift = max_bits - byte_size * bits_per_byte + sign_bit_position_on_byte
+    let byte_number_in_bits = ok_block
+        .append_operation(arith::muli(byte_size, bits_per_byte, location))
+        .result(0)?
+        .into();
+
+    let value_size_in_bits = ok_block
+        .append_operation(arith::addi(
+            byte_number_in_bits,
+            sign_bit_position_on_byte,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let bits_to_shift = ok_block
+        .append_operation(arith::subi(max_bits, value_size_in_bits, location))
+        .result(0)?
+        .into();
+
+    // value_to_extend << bits_to_shift
+    let left_shifted_value = ok_block
+        .append_operation(ods::llvm::shl(context, value_to_extend, bits_to_shift, location).into())
+        .result(0)?
+        .into();
+
+    // value_to_extend >> bits_to_shift  (sign extended)
+    let result = ok_block
+        .append_operation(
+            ods::llvm::ashr(context, left_shifted_value, bits_to_shift, location).into(),
+        )
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, result)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_gas<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    // Check there's at least space for one element in the stack
+    let stack_size_flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+
+    // Check there's enough gas to compute the operation
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::GAS)?;
+
+    let ok_flag = start_block
+        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        ok_flag,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let gas = get_remaining_gas(context, &ok_block)?;
+
+    stack_push(context, &ok_block, gas)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_slt<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    // Check there's enough elements in stack
+    let stack_size_flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+
+    // Check there's enough gas to compute the operation
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SLT)?;
+
+    let ok_flag = start_block
+        .append_operation(arith::andi(stack_size_flag, gas_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        ok_flag,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let lhs = stack_pop(context, &ok_block)?;
+    let rhs = stack_pop(context, &ok_block)?;
+
+    let result = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Slt,
+            lhs,
+            rhs,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // Extend the 1-bit result to 256 bits
+    let uint256 = IntegerType::new(context, 256);
+    let result = ok_block
+        .append_operation(arith::extui(result, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, result)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_mstore<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint8 = IntegerType::new(context, 8);
+    let ptr_type = pointer(context, 0);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MSTORE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let offset = stack_pop(context, &ok_block)?;
+    let value = stack_pop(context, &ok_block)?;
+
+    let value_size = constant_value_from_i64(context, &ok_block, 32)?;
+    let segment = memory_segment(context, &ok_block, offset, value_size)?;
+    let offset = segment.offset;
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &ok_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let memory_ptr = extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // memory_destination = memory_ptr + offset
+    let memory_destination = memory_block
+        .append_operation(llvm::get_element_ptr_dynamic(
+            context,
+            memory_ptr,
+            &[offset],
+            uint8.into(),
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let uint256 = IntegerType::new(context, 256);
+
+    // check system endianness before storing the value
+    let value = if cfg!(target_endian = "little") {
+        // if the system is little endian, we convert the value to big endian
+        memory_block
+            .append_operation(llvm::intr_bswap(value, uint256.into(), location))
+            .result(0)?
+            .into()
+    } else {
+        // if the system is big endian, there is no need to convert the value
+        value
+    };
+
+    // store the value in the memory
+    memory_block.append_operation(llvm::store(
+        context,
+        value,
+        memory_destination,
+        location,
+        LoadStoreOptions::new()
+            .align(IntegerAttribute::new(IntegerType::new(context, 64).into(), 1).into()),
+    ));
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_mstore8<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint8 = IntegerType::new(context, 8);
+    let ptr_type = pointer(context, 0);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MSTORE8)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let offset = stack_pop(context, &ok_block)?;
+    let value = stack_pop(context, &ok_block)?;
+
+    // truncate value to the least significative byte of the 32-byte value
+    let value = ok_block
+        .append_operation(arith::trunci(
+            value,
+            r#IntegerType::new(context, 8).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let value_size = constant_value_from_i64(context, &ok_block, 1)?;
+    let segment = memory_segment(context, &ok_block, offset, value_size)?;
+    let offset = segment.offset;
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &ok_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let memory_ptr = extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // memory_destination = memory_ptr + offset
+    let memory_destination = memory_block
+        .append_operation(llvm::get_element_ptr_dynamic(
+            context,
+            memory_ptr,
+            &[offset],
+            uint8.into(),
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    memory_block.append_operation(llvm::store(
+        context,
+        value,
+        memory_destination,
+        location,
+        LoadStoreOptions::new()
+            .align(IntegerAttribute::new(IntegerType::new(context, 64).into(), 1).into()),
+    ));
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_mload<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint8 = IntegerType::new(context, 8);
+    let uint256 = IntegerType::new(context, 256);
+    let ptr_type = pointer(context, 0);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MLOAD)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let offset = stack_pop(context, &ok_block)?;
+
+    let value_size = constant_value_from_i64(context, &ok_block, 32)?;
+    let segment = memory_segment(context, &ok_block, offset, value_size)?;
+    let offset = segment.offset;
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &ok_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let memory_ptr = extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // memory_source = memory_ptr + offset
+    let memory_source = memory_block
+        .append_operation(llvm::get_element_ptr_dynamic(
+            context,
+            memory_ptr,
+            &[offset],
+            uint8.into(),
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // load the value from the memory
+    let value = memory_block
+        .append_operation(llvm::load(
+            context,
+            memory_source,
+            uint256.into(),
+            location,
+            LoadStoreOptions::new()
+                .align(IntegerAttribute::new(IntegerType::new(context, 64).into(), 1).into()),
+        ))
+        .result(0)?
+        .into();
+
+    // check system endianness after loading the value
+    let value = if cfg!(target_endian = "little") {
+        // if the system is little endian, we convert the value from big endian
+        memory_block
+            .append_operation(llvm::intr_bswap(value, uint256.into(), location))
+            .result(0)?
+            .into()
+    } else {
+        // if the system is big endian, there is no need to convert the value
+        value
+    };
+
+    stack_push(context, &memory_block, value)?;
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_msize<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let stack_flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MSIZE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, stack_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // The memory size is always a multiple of 32 bytes
+    let memory_size = get_memory_size(context, &ok_block)?;
+
+    let memory_size = ok_block
+        .append_operation(arith::extui(memory_size, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, memory_size)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_mcopy<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint1 = IntegerType::new(context, 1);
+    let uint8 = IntegerType::new(context, 8);
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+    let ptr_type = pointer(context, 0);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::MCOPY)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let dest_offset = stack_pop(context, &ok_block)?;
+    let offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    // Both the source and the destination have to fit in memory
+    let dest_segment = memory_segment(context, &ok_block, dest_offset, size)?;
+    let src_segment = memory_segment(context, &ok_block, offset, size)?;
+    let (dest_offset, offset, size) = (dest_segment.offset, src_segment.offset, dest_segment.size);
+
+    // dynamic_gas_cost = ceil(size / 32) * COPY_WORD
+    let word_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_word = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::COPY_WORD).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[dest_segment, src_segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let memory_ptr = extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // source = memory_ptr + offset
+    let source = memory_block
+        .append_operation(llvm::get_element_ptr_dynamic(
+            context,
+            memory_ptr,
+            &[offset],
+            uint8.into(),
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // destination = memory_ptr + dest_offset
+    let destination = memory_block
+        .append_operation(llvm::get_element_ptr_dynamic(
+            context,
+            memory_ptr,
+            &[dest_offset],
+            uint8.into(),
+            ptr_type,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // The source and destination ranges may overlap, so we use memmove
+    memory_block.append_operation(
+        ods::llvm::intr_memmove(
+            context,
+            destination,
+            source,
+            size,
+            IntegerAttribute::new(uint1.into(), 0),
+            location,
+        )
+        .into(),
+    );
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_keccak256<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::KECCAK256)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    let segment = memory_segment(context, &ok_block, offset, size)?;
+    let (offset, size) = (segment.offset, segment.size);
+
+    // dynamic_gas_cost = ceil(size / 32) * KECCAK256_WORD
+    let word_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_word = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::KECCAK256_WORD).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // Allocate space for the syscall to write the hash into
+    let hash_ptr = allocate_u256(context, &memory_block)?;
+
+    op_ctx.keccak256_syscall(&memory_block, offset, size, hash_ptr, location);
+
+    let hash = memory_block
+        .append_operation(llvm::load(
+            context,
+            hash_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &memory_block, hash)?;
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_calldataload<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CALLDATALOAD)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let offset = stack_pop(context, &ok_block)?;
+
+    // Offsets past the end of the calldata read zeros, so saturating is safe here
+    let offset = saturating_trunc_to_u32(context, &ok_block, offset)?;
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.calldata_load_syscall(&ok_block, offset, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_calldatasize<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CALLDATASIZE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let calldata_size = op_ctx.get_calldata_size_syscall(&ok_block, location)?;
+
+    let calldata_size = ok_block
+        .append_operation(arith::extui(calldata_size, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, calldata_size)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_calldatacopy<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CALLDATACOPY)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let dest_offset = stack_pop(context, &ok_block)?;
+    let calldata_offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    let segment = memory_segment(context, &ok_block, dest_offset, size)?;
+    let (dest_offset, size) = (segment.offset, segment.size);
+
+    // Offsets past the end of the calldata copy zeros, so saturating is safe here
+    let calldata_offset = saturating_trunc_to_u32(context, &ok_block, calldata_offset)?;
+
+    // dynamic_gas_cost = ceil(size / 32) * COPY_WORD
+    let word_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_word = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::COPY_WORD).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    op_ctx.copy_calldata_to_memory_syscall(
+        &memory_block,
+        calldata_offset,
+        size,
+        dest_offset,
+        location,
+    );
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_codesize<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CODESIZE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let code_size = op_ctx.get_code_size_syscall(&ok_block, location)?;
+
+    let code_size = ok_block
+        .append_operation(arith::extui(code_size, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, code_size)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_codecopy<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CODECOPY)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let dest_offset = stack_pop(context, &ok_block)?;
+    let code_offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    let segment = memory_segment(context, &ok_block, dest_offset, size)?;
+    let (dest_offset, size) = (segment.offset, segment.size);
+
+    // Offsets past the end of the code copy zeros, so saturating is safe here
+    let code_offset = saturating_trunc_to_u32(context, &ok_block, code_offset)?;
+
+    // dynamic_gas_cost = ceil(size / 32) * COPY_WORD
+    let word_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_word = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::COPY_WORD).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    op_ctx.copy_code_to_memory_syscall(&memory_block, code_offset, size, dest_offset, location);
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_sload<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, Operation::Sload.static_gas(op_ctx.spec_id))?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let key = stack_pop(context, &ok_block)?;
+
+    let key_ptr = allocate_and_store_u256(context, &ok_block, key)?;
+
+    // The first access to the slot costs more (EIP-2929)
+    let access_cost = op_ctx.access_storage_slot_syscall(&ok_block, key_ptr, location)?;
+    let access_gas_flag = consume_gas_as_value(context, &ok_block, access_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        access_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &end_block)?;
+
+    op_ctx.storage_read_syscall(&end_block, key_ptr, value_ptr, location);
+
+    let value = end_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &end_block, value)?;
+
+    Ok((start_block, end_block))
+}
+
+fn codegen_sstore<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint64 = IntegerType::new(context, 64);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+
+    // Since Istanbul, check the gas left is above the sentry (EIP-2200), running out of gas
+    // otherwise. The gas is given back right away, as the actual cost depends on the slot's values
+    let sentry_gas = if op_ctx.spec_id.includes(SpecId::Istanbul) {
+        gas_cost::SSTORE_SENTRY + 1
+    } else {
+        0
+    };
+    let gas_flag = consume_gas(context, &start_block, sentry_gas)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    // Check the state can be modified
+    let static_flag = check_not_static(op_ctx, &start_block)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(condition, static_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let sentry = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), sentry_gas).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    return_unused_gas(context, &ok_block, sentry)?;
+
+    let key = stack_pop(context, &ok_block)?;
+    let value = stack_pop(context, &ok_block)?;
+
+    let key_ptr = allocate_and_store_u256(context, &ok_block, key)?;
+    let value_ptr = allocate_and_store_u256(context, &ok_block, value)?;
+
+    // The syscall only writes the value if the remaining gas can pay for it
+    let remaining_gas = get_gas_counter(context, &ok_block)?;
+    let gas_cost =
+        op_ctx.storage_write_syscall(&ok_block, key_ptr, value_ptr, remaining_gas, location)?;
+
+    let gas_flag = consume_gas_as_value(context, &ok_block, gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    Ok((start_block, end_block))
+}
+
+fn codegen_tload<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::TLOAD)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let key = stack_pop(context, &ok_block)?;
+
+    let key_ptr = allocate_and_store_u256(context, &ok_block, key)?;
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.transient_storage_read_syscall(&ok_block, key_ptr, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_tstore<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::TSTORE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    // Check the state can be modified
+    let static_flag = check_not_static(op_ctx, &start_block)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(condition, static_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let key = stack_pop(context, &ok_block)?;
+    let value = stack_pop(context, &ok_block)?;
+
+    let key_ptr = allocate_and_store_u256(context, &ok_block, key)?;
+    let value_ptr = allocate_and_store_u256(context, &ok_block, value)?;
+
+    op_ctx.transient_storage_write_syscall(&ok_block, key_ptr, value_ptr, location);
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_log<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+    nth: u8,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    debug_assert!(nth <= 4);
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint64 = IntegerType::new(context, 64);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 2 + nth as u32)?;
+    // Check there's enough gas
+    let static_gas_cost = gas_cost::LOG + gas_cost::LOG_TOPIC * nth as i64;
+    let gas_flag = op_ctx.consume_gas(&start_block, static_gas_cost)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    // Check the state can be modified
+    let static_flag = check_not_static(op_ctx, &start_block)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(condition, static_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    let segment = memory_segment(context, &ok_block, offset, size)?;
+    let (offset, size) = (segment.offset, segment.size);
+
+    // dynamic_gas_cost = size * LOG_DATA_BYTE
+    let size_as_u64 = ok_block
+        .append_operation(arith::extui(size, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_byte = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::LOG_DATA_BYTE).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_as_u64, gas_per_byte, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    op_ctx.append_log_syscall(&memory_block, offset, size, location);
+
+    // The topics are popped in order, the first one being at the top of the stack
+    for _ in 0..nth {
+        let topic = stack_pop(context, &memory_block)?;
+        let topic_ptr = allocate_and_store_u256(context, &memory_block, topic)?;
+        op_ctx.append_log_topic_syscall(&memory_block, topic_ptr, location);
+    }
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_address<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::ADDRESS)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_address_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_origin<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::ORIGIN)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_origin_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_caller<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CALLER)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_caller_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_callvalue<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CALLVALUE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_callvalue_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_gasprice<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::GASPRICE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_gasprice_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_coinbase<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::COINBASE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_coinbase_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_timestamp<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::TIMESTAMP)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_timestamp_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_number<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::NUMBER)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_number_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_prevrandao<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::PREVRANDAO)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_prevrandao_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_gaslimit<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::GASLIMIT)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_gaslimit_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_chainid<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CHAINID)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_chainid_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_basefee<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::BASEFEE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_basefee_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_selfbalance<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::SELFBALANCE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_self_balance_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_blockhash<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::BLOCKHASH)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let block_number = stack_pop(context, &ok_block)?;
+
+    let current_block_number_ptr = allocate_u256(context, &ok_block)?;
+    op_ctx.get_number_syscall(&ok_block, current_block_number_ptr, location);
+
+    let current_block_number = ok_block
+        .append_operation(llvm::load(
+            context,
+            current_block_number_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    // Only the hashes of the last 256 blocks are available, excluding the current one
+    let is_previous_block = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ult,
+            block_number,
+            current_block_number,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let distance = ok_block
+        .append_operation(arith::subi(current_block_number, block_number, location))
+        .result(0)?
+        .into();
+
+    let max_distance = constant_value_from_i64(context, &ok_block, 256)?;
+
+    let is_recent_block = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            distance,
+            max_distance,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let is_available = ok_block
+        .append_operation(arith::andi(is_previous_block, is_recent_block, location))
+        .result(0)?
+        .into();
+
+    let zero = constant_value_from_i64(context, &ok_block, 0)?;
+
+    let hash_block = region.append_block(Block::new(&[]));
+    let end_block = region.append_block(Block::new(&[(uint256.into(), location)]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        is_available,
+        &hash_block,
+        &end_block,
+        &[],
+        &[zero],
+        location,
+    ));
+
+    let block_number_ptr = allocate_and_store_u256(context, &hash_block, block_number)?;
+    let hash_ptr = allocate_u256(context, &hash_block)?;
+
+    op_ctx.get_block_hash_syscall(&hash_block, block_number_ptr, hash_ptr, location);
+
+    let hash = hash_block
+        .append_operation(llvm::load(
+            context,
+            hash_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    hash_block.append_operation(cf::br(&end_block, &[hash], location));
+
+    let hash = end_block.argument(0)?.into();
+
+    stack_push(context, &end_block, hash)?;
+
+    Ok((start_block, end_block))
+}
+
+fn codegen_extcodesize<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(
+        &start_block,
+        Operation::Extcodesize.static_gas(op_ctx.spec_id),
+    )?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let address = stack_pop(context, &ok_block)?;
+    let address_ptr = allocate_and_store_u256(context, &ok_block, address)?;
+
+    // The first access to the account costs more (EIP-2929)
+    let access_cost = op_ctx.access_account_syscall(&ok_block, address_ptr, location)?;
+    let access_gas_flag = consume_gas_as_value(context, &ok_block, access_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        access_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let code_size = op_ctx.get_extcode_size_syscall(&end_block, address_ptr, location)?;
+
+    let code_size = end_block
+        .append_operation(arith::extui(code_size, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &end_block, code_size)?;
+
+    Ok((start_block, end_block))
+}
+
+fn codegen_extcodecopy<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 4)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(
+        &start_block,
+        Operation::Extcodecopy.static_gas(op_ctx.spec_id),
+    )?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let address = stack_pop(context, &ok_block)?;
+    let dest_offset = stack_pop(context, &ok_block)?;
+    let code_offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    let segment = memory_segment(context, &ok_block, dest_offset, size)?;
+    let (dest_offset, size) = (segment.offset, segment.size);
+
+    // Offsets past the end of the code copy zeros, so saturating is safe here
+    let code_offset = saturating_trunc_to_u32(context, &ok_block, code_offset)?;
+
+    // dynamic_gas_cost = ceil(size / 32) * COPY_WORD
+    let word_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_word = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::COPY_WORD).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    // The first access to the account costs more (EIP-2929)
+    let address_ptr = allocate_and_store_u256(context, &ok_block, address)?;
+    let access_cost = op_ctx.access_account_syscall(&ok_block, address_ptr, location)?;
+    let access_gas_flag = consume_gas_as_value(context, &ok_block, access_cost)?;
+
+    let dynamic_gas_flag = ok_block
+        .append_operation(arith::andi(dynamic_gas_flag, access_gas_flag, location))
+        .result(0)?
+        .into();
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    op_ctx.copy_extcode_to_memory_syscall(
+        &memory_block,
+        address_ptr,
+        code_offset,
+        size,
+        dest_offset,
+        location,
+    );
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_extcodehash<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(
+        &start_block,
+        Operation::Extcodehash.static_gas(op_ctx.spec_id),
+    )?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let address = stack_pop(context, &ok_block)?;
+    let address_ptr = allocate_and_store_u256(context, &ok_block, address)?;
+
+    // The first access to the account costs more (EIP-2929)
+    let access_cost = op_ctx.access_account_syscall(&ok_block, address_ptr, location)?;
+    let access_gas_flag = consume_gas_as_value(context, &ok_block, access_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        access_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let hash_ptr = allocate_u256(context, &end_block)?;
+
+    op_ctx.get_extcode_hash_syscall(&end_block, address_ptr, hash_ptr, location);
+
+    let hash = end_block
+        .append_operation(llvm::load(
+            context,
+            hash_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &end_block, hash)?;
+
+    Ok((start_block, end_block))
+}
+
+fn codegen_balance<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag =
+        op_ctx.consume_gas(&start_block, Operation::Balance.static_gas(op_ctx.spec_id))?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let address = stack_pop(context, &ok_block)?;
+    let address_ptr = allocate_and_store_u256(context, &ok_block, address)?;
+
+    // The first access to the account costs more (EIP-2929)
+    let access_cost = op_ctx.access_account_syscall(&ok_block, address_ptr, location)?;
+    let access_gas_flag = consume_gas_as_value(context, &ok_block, access_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        access_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let balance_ptr = allocate_u256(context, &end_block)?;
+
+    op_ctx.get_balance_syscall(&end_block, address_ptr, balance_ptr, location);
+
+    let balance = end_block
+        .append_operation(llvm::load(
+            context,
+            balance_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &end_block, balance)?;
+
+    Ok((start_block, end_block))
+}
+
+fn codegen_returndatasize<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::RETURNDATASIZE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let return_data_size = op_ctx.get_return_data_size_syscall(&ok_block, location)?;
+
+    let return_data_size = ok_block
+        .append_operation(arith::extui(return_data_size, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, return_data_size)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_returndatacopy<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::RETURNDATACOPY)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let dest_offset = stack_pop(context, &ok_block)?;
+    let return_data_offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+
+    // Reading past the end of the return data is an exceptional halt.
+    // To avoid overflows, we check `offset <= len && size <= len - offset`
+    let return_data_size = op_ctx.get_return_data_size_syscall(&ok_block, location)?;
+
+    let return_data_size = ok_block
+        .append_operation(arith::extui(return_data_size, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    let offset_in_bounds = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            return_data_offset,
+            return_data_size,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let remaining_size = ok_block
+        .append_operation(arith::subi(return_data_size, return_data_offset, location))
+        .result(0)?
+        .into();
+
+    let size_in_bounds = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            size,
+            remaining_size,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let in_bounds = ok_block
+        .append_operation(arith::andi(offset_in_bounds, size_in_bounds, location))
+        .result(0)?
+        .into();
+
+    let in_bounds_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        in_bounds,
+        &in_bounds_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let segment = memory_segment(context, &in_bounds_block, dest_offset, size)?;
+    let (dest_offset, size) = (segment.offset, segment.size);
+
+    // The return data offset was already checked to be inside the return data
+    let return_data_offset = in_bounds_block
+        .append_operation(arith::trunci(return_data_offset, uint32.into(), location))
+        .result(0)?
+        .into();
+
+    // dynamic_gas_cost = ceil(size / 32) * COPY_WORD
+    let word_size = in_bounds_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let size_in_words = in_bounds_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = in_bounds_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let gas_per_word = in_bounds_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::COPY_WORD).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = in_bounds_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &in_bounds_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    in_bounds_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    op_ctx.copy_return_data_to_memory_syscall(
+        &memory_block,
+        return_data_offset,
+        size,
+        dest_offset,
+        location,
+    );
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_blobhash<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::BLOBHASH)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let index = stack_pop(context, &ok_block)?;
+    let index_ptr = allocate_and_store_u256(context, &ok_block, index)?;
+    let hash_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_blob_hash_syscall(&ok_block, index_ptr, hash_ptr, location);
+
+    let hash = ok_block
+        .append_operation(llvm::load(
+            context,
+            hash_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, hash)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_blobbasefee<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough space in stack
+    let flag = op_ctx.check_stack_has_space_for(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::BLOBBASEFEE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value_ptr = allocate_u256(context, &ok_block)?;
+
+    op_ctx.get_blobbasefee_syscall(&ok_block, value_ptr, location);
+
+    let value = ok_block
+        .append_operation(llvm::load(
+            context,
+            value_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &ok_block, value)?;
+
+    Ok((start_block, ok_block))
+}
+
+fn codegen_call<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+    call_type: CallType,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint8 = IntegerType::new(context, 8);
+    let uint64 = IntegerType::new(context, 64);
+    let uint256 = IntegerType::new(context, 256);
+
+    // DELEGATECALL and STATICCALL don't take a value
+    let takes_value = matches!(call_type, CallType::Call | CallType::CallCode);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 6 + takes_value as u32)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, Operation::Call.static_gas(op_ctx.spec_id))?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let gas = stack_pop(context, &ok_block)?;
+    let address = stack_pop(context, &ok_block)?;
+    // NOTE: DELEGATECALL keeps the value of the current call, but doesn't transfer it,
+    // so it's handled as a zero value here
+    let value = if takes_value {
+        stack_pop(context, &ok_block)?
+    } else {
+        constant_value_from_i64(context, &ok_block, 0)?
+    };
+    let args_offset = stack_pop(context, &ok_block)?;
+    let args_size = stack_pop(context, &ok_block)?;
+    let ret_offset = stack_pop(context, &ok_block)?;
+    let ret_size = stack_pop(context, &ok_block)?;
+
+    // The memory must hold both the arguments and the output
+    let args_segment = memory_segment(context, &ok_block, args_offset, args_size)?;
+    let ret_segment = memory_segment(context, &ok_block, ret_offset, ret_size)?;
+    let (args_offset, args_size) = (args_segment.offset, args_segment.size);
+    let (ret_offset, ret_size) = (ret_segment.offset, ret_segment.size);
+
+    // Transferring value has an extra cost
+    let zero_value = constant_value_from_i64(context, &ok_block, 0)?;
+
+    let value_is_zero = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Eq,
+            value,
+            zero_value,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let zero_gas = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let value_transfer_gas = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::CALL_VALUE_TRANSFER).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::select(
+            value_is_zero,
+            zero_gas,
+            value_transfer_gas,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    // The first access to the callee costs more (EIP-2929)
+    let address_ptr = allocate_and_store_u256(context, &ok_block, address)?;
+    let access_cost = op_ctx.access_account_syscall(&ok_block, address_ptr, location)?;
+    let access_gas_flag = consume_gas_as_value(context, &ok_block, access_cost)?;
+
+    let dynamic_gas_flag = ok_block
+        .append_operation(arith::andi(dynamic_gas_flag, access_gas_flag, location))
+        .result(0)?
+        .into();
+
+    let value_ptr = allocate_and_store_u256(context, &ok_block, value)?;
+
+    // Creating the callee's account has an extra cost. Only CALL can, as CALLCODE
+    // sends the value to the caller itself
+    let dynamic_gas_flag = if call_type == CallType::Call {
+        let new_account_cost =
+            op_ctx.call_new_account_cost_syscall(&ok_block, address_ptr, value_ptr, location)?;
+        let new_account_gas_flag = consume_gas_as_value(context, &ok_block, new_account_cost)?;
+
+        ok_block
+            .append_operation(arith::andi(
+                dynamic_gas_flag,
+                new_account_gas_flag,
+                location,
+            ))
+            .result(0)?
+            .into()
+    } else {
+        dynamic_gas_flag
+    };
+
+    // Transferring value with a CALL modifies the state, so it's forbidden inside a static call
+    let condition = if call_type == CallType::Call {
+        let static_flag = check_not_static(op_ctx, &ok_block)?;
+
+        let value_allowed = ok_block
+            .append_operation(arith::ori(value_is_zero, static_flag, location))
+            .result(0)?
+            .into();
+
+        ok_block
+            .append_operation(arith::andi(dynamic_gas_flag, value_allowed, location))
+            .result(0)?
+            .into()
+    } else {
+        dynamic_gas_flag
+    };
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[args_segment, ret_segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // The callee gets the requested gas, capped to all but one 64th
+    // of the remaining gas (EIP-150)
+    let remaining_gas = get_gas_counter(context, &memory_block)?;
+    let available_gas = if op_ctx.spec_id.includes(SpecId::TangerineWhistle) {
+        all_but_one_64th(context, &memory_block, remaining_gas)?
+    } else {
+        remaining_gas
+    };
+
+    let available_gas = memory_block
+        .append_operation(arith::extui(available_gas, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    // Before Tangerine Whistle, requesting more gas than is left runs out of gas
+    let memory_block = if op_ctx.spec_id.includes(SpecId::TangerineWhistle) {
+        memory_block
+    } else {
+        let gas_available_flag = memory_block
+            .append_operation(arith::cmpi(
+                context,
+                arith::CmpiPredicate::Ule,
+                gas,
+                available_gas,
+                location,
+            ))
+            .result(0)?
+            .into();
+
+        let gas_block = region.append_block(Block::new(&[]));
+
+        memory_block.append_operation(cf::cond_br(
+            context,
+            gas_available_flag,
+            &gas_block,
+            &op_ctx.revert_block,
+            &[],
+            &[],
+            location,
+        ));
+        gas_block
+    };
+
+    let gas_limit = memory_block
+        .append_operation(arith::minui(gas, available_gas, location))
+        .result(0)?
+        .into();
+
+    let gas_limit = memory_block
+        .append_operation(arith::trunci(gas_limit, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    // The gas given to the callee is always available, so this can't fail
+    consume_gas_as_value(context, &memory_block, gas_limit)?;
+
+    // Transferring value gives the callee a stipend, on top of the forwarded gas
+    let stipend = memory_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::CALL_STIPEND).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let stipend = memory_block
+        .append_operation(arith::select(value_is_zero, zero_gas, stipend, location))
+        .result(0)?
+        .into();
+
+    let gas_limit = memory_block
+        .append_operation(arith::addi(gas_limit, stipend, location))
+        .result(0)?
+        .into();
+
+    let call_type = memory_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), call_type.to_u8() as _).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let remaining_gas_ptr = allocate_u64(context, &memory_block)?;
+
+    let state = save_program_state(context, &memory_block)?;
+    let success = op_ctx.call_syscall(
+        &memory_block,
+        call_type,
+        gas_limit,
+        address_ptr,
+        value_ptr,
+        args_offset,
+        args_size,
+        ret_offset,
+        ret_size,
+        remaining_gas_ptr,
+        location,
+    )?;
+    restore_program_state(context, &memory_block, &state)?;
+
+    // The gas left unused by the callee is taken back, including any unused stipend
+    let unused_gas = memory_block
+        .append_operation(llvm::load(
+            context,
+            remaining_gas_ptr,
+            uint64.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    return_unused_gas(context, &memory_block, unused_gas)?;
+
+    let success = memory_block
+        .append_operation(arith::extui(success, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    stack_push(context, &memory_block, success)?;
+
+    Ok((start_block, memory_block))
+}
+
+/// Generates the code for CREATE and CREATE2, which only differ in the salt
+/// the latter takes for deriving the new contract's address.
+fn codegen_create<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+    is_create2: bool,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+    let uint256 = IntegerType::new(context, 256);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 3 + is_create2 as u32)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(&start_block, gas_cost::CREATE)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    // Check the state can be modified
+    let static_flag = check_not_static(op_ctx, &start_block)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(condition, static_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let value = stack_pop(context, &ok_block)?;
+    let offset = stack_pop(context, &ok_block)?;
+    let size = stack_pop(context, &ok_block)?;
+    let salt = if is_create2 {
+        Some(stack_pop(context, &ok_block)?)
+    } else {
+        None
+    };
+
+    // The initcode can't be bigger than the limit (EIP-3860).
+    // Before Shanghai, it's only limited by the memory expansion cost
+    let max_initcode_size = if op_ctx.spec_id.includes(SpecId::Shanghai) {
+        MAX_INITCODE_SIZE as i64
+    } else {
+        u32::MAX as i64
+    };
+    let max_initcode_size = constant_value_from_i64(context, &ok_block, max_initcode_size)?;
+
+    let size_flag = ok_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            size,
+            max_initcode_size,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let segment = memory_segment(context, &ok_block, offset, size)?;
+    let (offset, size) = (segment.offset, segment.size);
+
+    // dynamic_gas_cost = ceil(size / 32) * gas_per_word
+    // CREATE2 also hashes the initcode for computing the address
+    let word_size = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::ceildivui(size, word_size, location))
+        .result(0)?
+        .into();
+
+    let size_in_words = ok_block
+        .append_operation(arith::extui(size_in_words, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    // Charging for the initcode's words was introduced in Shanghai (EIP-3860)
+    let initcode_word_cost = if op_ctx.spec_id.includes(SpecId::Shanghai) {
+        gas_cost::INITCODE_WORD
+    } else {
+        0
+    };
+    let gas_per_word = if is_create2 {
+        initcode_word_cost + gas_cost::KECCAK256_WORD
+    } else {
+        initcode_word_cost
+    };
+
+    let gas_per_word = ok_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_per_word).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_cost = ok_block
+        .append_operation(arith::muli(size_in_words, gas_per_word, location))
+        .result(0)?
+        .into();
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let condition = ok_block
+        .append_operation(arith::andi(dynamic_gas_flag, size_flag, location))
+        .result(0)?
+        .into();
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // Check there's enough gas to expand the memory
+    let (memory_gas_flag, required_size) =
+        consume_memory_expansion_gas(context, &end_block, &[segment])?;
+
+    let memory_block = region.append_block(Block::new(&[]));
+
+    end_block.append_operation(cf::cond_br(
+        context,
+        memory_gas_flag,
+        &memory_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    extend_memory(op_ctx, &memory_block, required_size)?;
+
+    // The initcode gets all but one 64th of the remaining gas (EIP-150), or all of it
+    // before Tangerine Whistle, and the gas it leaves unused is taken back
+    let remaining_gas = get_gas_counter(context, &memory_block)?;
+    let gas_limit = if op_ctx.spec_id.includes(SpecId::TangerineWhistle) {
+        all_but_one_64th(context, &memory_block, remaining_gas)?
+    } else {
+        remaining_gas
+    };
+
+    // The gas given to the initcode is always available, so this can't fail
+    consume_gas_as_value(context, &memory_block, gas_limit)?;
+
+    let value_ptr = allocate_and_store_u256(context, &memory_block, value)?;
+    let address_ptr = allocate_u256(context, &memory_block)?;
+
+    let state = save_program_state(context, &memory_block)?;
+    let unused_gas = match salt {
+        Some(salt) => {
+            let salt_ptr = allocate_and_store_u256(context, &memory_block, salt)?;
+            op_ctx.create2_syscall(
+                &memory_block,
+                offset,
+                size,
+                value_ptr,
+                salt_ptr,
+                gas_limit,
+                address_ptr,
+                location,
+            )?
+        }
+        None => op_ctx.create_syscall(
+            &memory_block,
+            offset,
+            size,
+            value_ptr,
+            gas_limit,
+            address_ptr,
+            location,
+        )?,
+    };
+    restore_program_state(context, &memory_block, &state)?;
+
+    return_unused_gas(context, &memory_block, unused_gas)?;
+
+    let address = memory_block
+        .append_operation(llvm::load(
+            context,
+            address_ptr,
+            uint256.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    stack_push(context, &memory_block, address)?;
+
+    Ok((start_block, memory_block))
+}
+
+fn codegen_selfdestruct<'c, 'r>(
+    op_ctx: &mut OperationCtx<'c>,
+    region: &'r Region<'c>,
+) -> Result<(BlockRef<'c, 'r>, BlockRef<'c, 'r>), CodegenError> {
+    let start_block = region.append_block(Block::new(&[]));
+    let context = &op_ctx.mlir_context;
+    let location = op_ctx.location;
+    let uint8 = IntegerType::new(context, 8);
+    let uint32 = IntegerType::new(context, 32);
+
+    // Check there's enough elements in stack
+    let flag = op_ctx.check_stack_has_at_least(&start_block, 1)?;
+    // Check there's enough gas
+    let gas_flag = op_ctx.consume_gas(
+        &start_block,
+        Operation::Selfdestruct.static_gas(op_ctx.spec_id),
+    )?;
+
+    let condition = start_block
+        .append_operation(arith::andi(gas_flag, flag, location))
+        .result(0)?
+        .into();
+
+    // Check the state can be modified
+    let static_flag = check_not_static(op_ctx, &start_block)?;
+
+    let condition = start_block
+        .append_operation(arith::andi(condition, static_flag, location))
+        .result(0)?
+        .into();
+
+    let ok_block = region.append_block(Block::new(&[]));
+
+    start_block.append_operation(cf::cond_br(
+        context,
+        condition,
+        &ok_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let beneficiary = stack_pop(context, &ok_block)?;
+    let beneficiary_ptr = allocate_and_store_u256(context, &ok_block, beneficiary)?;
+
+    // The syscall only sends the balance if the remaining gas can pay for it
+    let remaining_gas = get_gas_counter(context, &ok_block)?;
+    let dynamic_gas_cost =
+        op_ctx.selfdestruct_syscall(&ok_block, beneficiary_ptr, remaining_gas, location)?;
+
+    let dynamic_gas_flag = consume_gas_as_value(context, &ok_block, dynamic_gas_cost)?;
+
+    let end_block = region.append_block(Block::new(&[]));
+
+    ok_block.append_operation(cf::cond_br(
+        context,
+        dynamic_gas_flag,
+        &end_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    // SELFDESTRUCT halts the execution like a STOP
+    let exit_code = end_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), ExitStatusCode::Stop.to_u8() as _).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let zero = end_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let remaining_gas = get_gas_counter(context, &end_block)?;
+
+    op_ctx.write_result_syscall(&end_block, zero, zero, remaining_gas, exit_code, location);
+
+    end_block.append_operation(func::r#return(&[exit_code], location));
+    let empty_block = region.append_block(Block::new(&[]));
+
+    Ok((start_block, empty_block))
+}
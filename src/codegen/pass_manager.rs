@@ -1,21 +1,98 @@
 use melior::{
     ir::Module as MeliorModule,
-    pass::{self, PassManager},
+    pass::{self, Pass, PassManager},
     Context, Error,
 };
 
-pub fn run_pass_manager(context: &Context, module: &mut MeliorModule) -> Result<(), Error> {
+use crate::options::Passes;
+
+/// The passes lowering the generated module to the LLVM dialect, in order, with the
+/// names they're registered with
+fn lowering_passes() -> [(&'static str, fn() -> Pass); 10] {
+    [
+        ("canonicalize", pass::transform::create_canonicalizer),
+        (
+            "convert-scf-to-cf",
+            pass::conversion::create_scf_to_control_flow,
+        ),
+        (
+            "convert-arith-to-llvm",
+            pass::conversion::create_arith_to_llvm,
+        ),
+        (
+            "convert-math-to-llvm",
+            pass::conversion::create_math_to_llvm,
+        ),
+        (
+            "convert-math-to-funcs",
+            pass::conversion::create_math_to_funcs,
+        ),
+        (
+            "convert-cf-to-llvm",
+            pass::conversion::create_control_flow_to_llvm,
+        ),
+        (
+            "convert-index-to-llvm",
+            pass::conversion::create_index_to_llvm,
+        ),
+        (
+            "finalize-memref-to-llvm",
+            pass::conversion::create_finalize_mem_ref_to_llvm,
+        ),
+        (
+            "convert-func-to-llvm",
+            pass::conversion::create_func_to_llvm,
+        ),
+        (
+            "reconcile-unrealized-casts",
+            pass::conversion::create_reconcile_unrealized_casts,
+        ),
+    ]
+}
+
+/// Returns the lowering passes chosen to run, with their names
+fn chosen_passes(passes: &Passes) -> impl Iterator<Item = (&'static str, fn() -> Pass)> + '_ {
+    lowering_passes()
+        .into_iter()
+        .filter(|(name, _)| passes.canonicalize || *name != "canonicalize")
+}
+
+pub fn run_pass_manager(
+    context: &Context,
+    module: &mut MeliorModule,
+    passes: &Passes,
+) -> Result<(), Error> {
     let pass_manager = PassManager::new(context);
     pass_manager.enable_verifier(true);
-    pass_manager.add_pass(pass::transform::create_canonicalizer());
-    pass_manager.add_pass(pass::conversion::create_scf_to_control_flow());
-    pass_manager.add_pass(pass::conversion::create_arith_to_llvm());
-    pass_manager.add_pass(pass::conversion::create_math_to_llvm());
-    pass_manager.add_pass(pass::conversion::create_math_to_funcs());
-    pass_manager.add_pass(pass::conversion::create_control_flow_to_llvm());
-    pass_manager.add_pass(pass::conversion::create_index_to_llvm());
-    pass_manager.add_pass(pass::conversion::create_finalize_mem_ref_to_llvm());
-    pass_manager.add_pass(pass::conversion::create_func_to_llvm());
-    pass_manager.add_pass(pass::conversion::create_reconcile_unrealized_casts());
+    for (_, create_pass) in chosen_passes(passes) {
+        pass_manager.add_pass(create_pass());
+    }
     pass_manager.run(module)
 }
+
+/// Gives each function of the lowered module a debug info scope, so the locations of
+/// its operations are translated to debug info
+pub fn run_debug_info_pass(context: &Context, module: &mut MeliorModule) -> Result<(), Error> {
+    let pass_manager = PassManager::new(context);
+    pass_manager.add_pass(pass::llvm::create_di_scope_for_llvm_func_op());
+    pass_manager.run(module)
+}
+
+/// Like [`run_pass_manager`], but running the passes one at a time, calling `after_pass`
+/// with the position of each pass in the pipeline (starting at 1), its name, and the
+/// module it left
+pub fn run_passes_one_by_one<E: From<Error>>(
+    context: &Context,
+    module: &mut MeliorModule,
+    passes: &Passes,
+    mut after_pass: impl FnMut(usize, &str, &MeliorModule) -> Result<(), E>,
+) -> Result<(), E> {
+    for (index, (name, create_pass)) in chosen_passes(passes).enumerate() {
+        let pass_manager = PassManager::new(context);
+        pass_manager.enable_verifier(true);
+        pass_manager.add_pass(create_pass());
+        pass_manager.run(module)?;
+        after_pass(index + 1, name, module)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,288 @@
+//! # Stack-to-SSA promotion
+//!
+//! Generates code for runs of operations only moving words around the stack or computing
+//! on them, like `PUSH1 1 DUP3 ADD SWAP1 POP`, keeping the words they push as SSA values
+//! rather than storing them on the stack. An operation of the run taking a word pushed
+//! earlier in the run uses its value, and only the words on the stack before the run are
+//! loaded. The words the run leaves are stored on the stack at its end, so the stack is
+//! whole at the boundaries of basic blocks, and before any other operation.
+//!
+//! Each operation still checks the stack and consumes its gas before running, as it
+//! would on its own, counting the words held as values.
+use melior::{
+    dialect::{
+        arith::{self, CmpiPredicate},
+        cf, llvm,
+        llvm::LoadStoreOptions,
+    },
+    ir::{r#type::IntegerType, Attribute, Block, BlockRef, Location, Region, Value},
+    Context as MeliorContext,
+};
+
+use super::{context::OperationCtx, word::WordBuilder};
+use crate::{
+    errors::CodegenError,
+    program::Operation,
+    spec::SpecId,
+    utils::{get_nth_from_stack, stack_pop, stack_push, swap_stack_elements},
+};
+
+/// Returns whether the operation can be part of a run whose words are SSA values
+pub(crate) fn is_promotable(operation: &Operation, spec_id: SpecId) -> bool {
+    if !spec_id.includes(operation.introduced_in()) {
+        return false;
+    }
+    match operation {
+        Operation::Push(value) | Operation::PushN(_, value) => value.bits() <= 256,
+        Operation::Push0
+        | Operation::Pop
+        | Operation::Dup(_)
+        | Operation::Swap(_)
+        | Operation::Dupn(_)
+        | Operation::Swapn(_)
+        | Operation::Add
+        | Operation::Sub
+        | Operation::Mul
+        | Operation::And
+        | Operation::Or
+        | Operation::Xor
+        | Operation::Not
+        | Operation::IsZero
+        | Operation::Eq
+        | Operation::Lt
+        | Operation::Gt
+        | Operation::Slt
+        | Operation::Sgt => true,
+        _ => false,
+    }
+}
+
+/// Generates code for the run of operations, each one located at its location, and
+/// returns both the starting block and the unterminated last block of the code
+pub(crate) fn generate_code_for_run<'c>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'c Region<'c>,
+    operations: &[Operation],
+    locations: &[Location<'c>],
+) -> Result<(BlockRef<'c, 'c>, BlockRef<'c, 'c>), CodegenError> {
+    let context = op_ctx.mlir_context;
+    let uint256 = IntegerType::new(context, 256);
+
+    // Each operation checks it can run at the end of the block computing the previous one
+    let blocks: Vec<BlockRef<'c, 'c>> = (0..=operations.len())
+        .map(|_| region.append_block(Block::new(&[])))
+        .collect();
+    let mut words = Words::new(context);
+
+    for (index, (operation, location)) in operations.iter().zip(locations).enumerate() {
+        let check_block = &blocks[index];
+        let block = &blocks[index + 1];
+        let location = *location;
+
+        let effect = operation.stack_effect();
+        let held = words.held();
+        let mut flag = op_ctx.consume_gas(check_block, operation.static_gas(op_ctx.spec_id))?;
+        if effect.inputs > held {
+            let has_items =
+                op_ctx.check_stack_has_at_least(check_block, (effect.inputs - held) as u32)?;
+            flag = check_block
+                .append_operation(arith::andi(flag, has_items, location))
+                .result(0)?
+                .into();
+        }
+        if effect.outputs > effect.inputs {
+            let growth = effect.outputs - effect.inputs;
+            let has_space =
+                op_ctx.check_stack_has_space_for(check_block, (held + growth) as u32)?;
+            flag = check_block
+                .append_operation(arith::andi(flag, has_space, location))
+                .result(0)?
+                .into();
+        }
+        check_block.append_operation(cf::cond_br(
+            context,
+            flag,
+            block,
+            &op_ctx.revert_block,
+            &[],
+            &[],
+            location,
+        ));
+
+        let word = WordBuilder::new(context, op_ctx.word_repr, block, location);
+        match operation {
+            Operation::Push0 | Operation::Push(_) | Operation::PushN(..) => {
+                let value = match operation {
+                    Operation::Push(value) | Operation::PushN(_, value) => value.to_string(),
+                    _ => "0".to_string(),
+                };
+                let constant = Attribute::parse(context, &format!("{value} : i256")).unwrap();
+                let value = block
+                    .append_operation(arith::constant(context, constant, location))
+                    .result(0)?
+                    .into();
+                words.push(value);
+            }
+            Operation::Pop => {
+                words.pop(block)?;
+            }
+            Operation::Dup(nth) => {
+                let value = words.nth(block, *nth as usize)?;
+                words.push(value);
+            }
+            Operation::Dupn(immediate) => {
+                let value = words.nth(block, *immediate as usize + 1)?;
+                words.push(value);
+            }
+            Operation::Swap(nth) => words.swap(block, *nth as usize, location)?,
+            Operation::Swapn(immediate) => words.swap(block, *immediate as usize + 1, location)?,
+            Operation::Not | Operation::IsZero => {
+                let value = words.pop(block)?;
+                let result = match operation {
+                    Operation::Not => word.not(value)?,
+                    _ => {
+                        let zero = block
+                            .append_operation(arith::constant(
+                                context,
+                                Attribute::parse(context, "0 : i256").unwrap(),
+                                location,
+                            ))
+                            .result(0)?
+                            .into();
+                        let is_zero = word.equal(value, zero)?;
+                        block
+                            .append_operation(arith::extui(is_zero, uint256.into(), location))
+                            .result(0)?
+                            .into()
+                    }
+                };
+                words.push(result);
+            }
+            _ => {
+                let lhs = words.pop(block)?;
+                let rhs = words.pop(block)?;
+                let result = match operation {
+                    Operation::Add => word.add(lhs, rhs)?,
+                    Operation::Sub => word.sub(lhs, rhs)?,
+                    Operation::Mul => block
+                        .append_operation(arith::muli(lhs, rhs, location))
+                        .result(0)?
+                        .into(),
+                    Operation::And => word.and(lhs, rhs)?,
+                    Operation::Or => word.or(lhs, rhs)?,
+                    Operation::Xor => word.xor(lhs, rhs)?,
+                    // Comparisons push 1 or 0
+                    _ => {
+                        let flag = match operation {
+                            Operation::Eq => word.equal(lhs, rhs)?,
+                            Operation::Lt => word.less_than(lhs, rhs)?,
+                            Operation::Gt => word.greater_than(lhs, rhs)?,
+                            _ => {
+                                let predicate = match operation {
+                                    Operation::Slt => CmpiPredicate::Slt,
+                                    _ => CmpiPredicate::Sgt,
+                                };
+                                block
+                                    .append_operation(arith::cmpi(
+                                        context, predicate, lhs, rhs, location,
+                                    ))
+                                    .result(0)?
+                                    .into()
+                            }
+                        };
+                        block
+                            .append_operation(arith::extui(flag, uint256.into(), location))
+                            .result(0)?
+                            .into()
+                    }
+                };
+                words.push(result);
+            }
+        }
+    }
+
+    let end_block = &blocks[operations.len()];
+    words.store(end_block)?;
+    Ok((blocks[0], *end_block))
+}
+
+/// The words pushed by the operations of a run and not stored on the stack yet, from
+/// the lowest one. They're above the words on the stack
+struct Words<'a> {
+    context: &'a MeliorContext,
+    values: Vec<Value<'a, 'a>>,
+}
+
+impl<'a> Words<'a> {
+    fn new(context: &'a MeliorContext) -> Self {
+        Self {
+            context,
+            values: Vec::new(),
+        }
+    }
+
+    /// Returns how many words are held as values
+    fn held(&self) -> usize {
+        self.values.len()
+    }
+
+    fn push(&mut self, value: Value<'a, 'a>) {
+        self.values.push(value);
+    }
+
+    /// Takes the top word, from the stack if no word is held
+    fn pop(&mut self, block: &'a Block) -> Result<Value<'a, 'a>, CodegenError> {
+        match self.values.pop() {
+            Some(value) => Ok(value),
+            None => stack_pop(self.context, block),
+        }
+    }
+
+    /// Returns the nth word from the top, starting at 1
+    fn nth(&self, block: &'a Block, nth: usize) -> Result<Value<'a, 'a>, CodegenError> {
+        let held = self.held();
+        if nth <= held {
+            return Ok(self.values[held - nth]);
+        }
+        let (value, _) = get_nth_from_stack(self.context, block, (nth - held) as u32)?;
+        Ok(value)
+    }
+
+    /// Swaps the top word with the one `nth` words below it
+    fn swap(
+        &mut self,
+        block: &'a Block,
+        nth: usize,
+        location: Location<'a>,
+    ) -> Result<(), CodegenError> {
+        let held = self.held();
+        if nth < held {
+            self.values.swap(held - 1, held - 1 - nth);
+        } else if held == 0 {
+            swap_stack_elements(self.context, block, 1, nth as u32 + 1)?;
+        } else {
+            // The top word is held, and the other one is on the stack
+            let (value, address) =
+                get_nth_from_stack(self.context, block, (nth + 1 - held) as u32)?;
+            let top = self.values[held - 1];
+            let res = block.append_operation(llvm::store(
+                self.context,
+                top,
+                address.into(),
+                location,
+                LoadStoreOptions::default(),
+            ));
+            assert!(res.verify());
+            self.values[held - 1] = value;
+        }
+        Ok(())
+    }
+
+    /// Stores the words held onto the stack
+    fn store(self, block: &'a Block) -> Result<(), CodegenError> {
+        for value in self.values {
+            stack_push(self.context, block, value)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,272 @@
+//! # Word arithmetic
+//!
+//! Lowers the arithmetic on the 256-bit words of the stack in the [`WordRepr`] chosen for
+//! the compilation. The words are stored on the stack as `i256` either way, so only the
+//! operations on them change: with [`WordRepr::I256`] each is a single `i256` operation,
+//! left for LLVM to legalize, and with [`WordRepr::Limbs64`] the words are split into
+//! four `i64` limbs, operated on one at a time, carrying between them, and joined back.
+//!
+//! Only the operations working limb by limb are lowered this way: additions,
+//! subtractions, bitwise operations and unsigned comparisons. The others stay on `i256`.
+use melior::{
+    dialect::arith::{self, CmpiPredicate},
+    ir::{
+        attribute::IntegerAttribute, r#type::IntegerType, Attribute, Block, Location, Operation,
+        Type, Value,
+    },
+    Context as MeliorContext,
+};
+
+use crate::{errors::CodegenError, options::WordRepr};
+
+const LIMBS: usize = 4;
+const LIMB_BITS: u32 = 64;
+
+/// Appends the operations on words to a block
+pub(crate) struct WordBuilder<'c> {
+    context: &'c MeliorContext,
+    block: &'c Block<'c>,
+    repr: WordRepr,
+    location: Location<'c>,
+}
+
+impl<'c> WordBuilder<'c> {
+    pub(crate) fn new(
+        context: &'c MeliorContext,
+        repr: WordRepr,
+        block: &'c Block<'c>,
+        location: Location<'c>,
+    ) -> Self {
+        Self {
+            context,
+            block,
+            repr,
+            location,
+        }
+    }
+
+    /// Returns `lhs + rhs`, wrapping around
+    pub(crate) fn add(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        if self.repr == WordRepr::I256 {
+            return self.append(arith::addi(lhs, rhs, self.location));
+        }
+        let mut limbs = Vec::with_capacity(LIMBS);
+        let mut carry = None;
+        for (lhs, rhs) in self.split(lhs)?.into_iter().zip(self.split(rhs)?) {
+            let mut sum = self.append(arith::addi(lhs, rhs, self.location))?;
+            let mut carry_out = self.compare_limbs(CmpiPredicate::Ult, sum, lhs)?;
+            if let Some(carry) = carry {
+                let carry = self.append(arith::extui(carry, self.limb_type(), self.location))?;
+                let carried = self.append(arith::addi(sum, carry, self.location))?;
+                let overflows = self.compare_limbs(CmpiPredicate::Ult, carried, sum)?;
+                carry_out = self.append(arith::ori(carry_out, overflows, self.location))?;
+                sum = carried;
+            }
+            limbs.push(sum);
+            carry = Some(carry_out);
+        }
+        self.join(&limbs)
+    }
+
+    /// Returns `lhs - rhs`, wrapping around
+    pub(crate) fn sub(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        if self.repr == WordRepr::I256 {
+            return self.append(arith::subi(lhs, rhs, self.location));
+        }
+        let mut limbs = Vec::with_capacity(LIMBS);
+        let mut borrow = None;
+        for (lhs, rhs) in self.split(lhs)?.into_iter().zip(self.split(rhs)?) {
+            let mut difference = self.append(arith::subi(lhs, rhs, self.location))?;
+            let mut borrow_out = self.compare_limbs(CmpiPredicate::Ult, lhs, rhs)?;
+            if let Some(borrow) = borrow {
+                let borrow = self.append(arith::extui(borrow, self.limb_type(), self.location))?;
+                let borrowed = self.append(arith::subi(difference, borrow, self.location))?;
+                let underflows = self.compare_limbs(CmpiPredicate::Ult, difference, borrow)?;
+                borrow_out = self.append(arith::ori(borrow_out, underflows, self.location))?;
+                difference = borrowed;
+            }
+            limbs.push(difference);
+            borrow = Some(borrow_out);
+        }
+        self.join(&limbs)
+    }
+
+    pub(crate) fn and(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        self.bitwise(arith::andi, lhs, rhs)
+    }
+
+    pub(crate) fn or(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        self.bitwise(arith::ori, lhs, rhs)
+    }
+
+    pub(crate) fn xor(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        self.bitwise(arith::xori, lhs, rhs)
+    }
+
+    /// Returns the word with each of its bits flipped
+    pub(crate) fn not(&self, value: Value<'c, 'c>) -> Result<Value<'c, 'c>, CodegenError> {
+        // Integer attributes built from an i64 are zero-extended, so it's parsed
+        let all_ones = self.append(arith::constant(
+            self.context,
+            Attribute::parse(self.context, "-1 : i256").unwrap(),
+            self.location,
+        ))?;
+        self.xor(value, all_ones)
+    }
+
+    /// Returns a flag telling whether `lhs == rhs`
+    pub(crate) fn equal(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        if self.repr == WordRepr::I256 {
+            return self.compare_limbs(CmpiPredicate::Eq, lhs, rhs);
+        }
+        let mut equal = None;
+        for (lhs, rhs) in self.split(lhs)?.into_iter().zip(self.split(rhs)?) {
+            let limb_equal = self.compare_limbs(CmpiPredicate::Eq, lhs, rhs)?;
+            equal = Some(match equal {
+                Some(equal) => self.append(arith::andi(equal, limb_equal, self.location))?,
+                None => limb_equal,
+            });
+        }
+        Ok(equal.expect("words have limbs"))
+    }
+
+    /// Returns a flag telling whether `lhs < rhs`, as unsigned words
+    pub(crate) fn less_than(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        if self.repr == WordRepr::I256 {
+            return self.compare_limbs(CmpiPredicate::Ult, lhs, rhs);
+        }
+        // The highest limb that differs decides, so the flag is built from the lowest up
+        let mut less = None;
+        for (lhs, rhs) in self.split(lhs)?.into_iter().zip(self.split(rhs)?) {
+            let limb_less = self.compare_limbs(CmpiPredicate::Ult, lhs, rhs)?;
+            less = Some(match less {
+                Some(less) => {
+                    let limb_equal = self.compare_limbs(CmpiPredicate::Eq, lhs, rhs)?;
+                    let lower_less = self.append(arith::andi(limb_equal, less, self.location))?;
+                    self.append(arith::ori(limb_less, lower_less, self.location))?
+                }
+                None => limb_less,
+            });
+        }
+        Ok(less.expect("words have limbs"))
+    }
+
+    /// Returns a flag telling whether `lhs > rhs`, as unsigned words
+    pub(crate) fn greater_than(
+        &self,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        self.less_than(rhs, lhs)
+    }
+
+    fn bitwise(
+        &self,
+        operation: fn(Value<'c, 'c>, Value<'c, 'c>, Location<'c>) -> Operation<'c>,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        if self.repr == WordRepr::I256 {
+            return self.append(operation(lhs, rhs, self.location));
+        }
+        let limbs = self
+            .split(lhs)?
+            .into_iter()
+            .zip(self.split(rhs)?)
+            .map(|(lhs, rhs)| self.append(operation(lhs, rhs, self.location)))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.join(&limbs)
+    }
+
+    /// Compares two values of the same type, limbs or words
+    fn compare_limbs(
+        &self,
+        predicate: CmpiPredicate,
+        lhs: Value<'c, 'c>,
+        rhs: Value<'c, 'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        self.append(arith::cmpi(
+            self.context,
+            predicate,
+            lhs,
+            rhs,
+            self.location,
+        ))
+    }
+
+    /// Splits the word into its limbs, from the least significant one
+    fn split(&self, word: Value<'c, 'c>) -> Result<[Value<'c, 'c>; LIMBS], CodegenError> {
+        let word_type = IntegerType::new(self.context, 256).into();
+        let mut limbs = [word; LIMBS];
+        for (index, limb) in limbs.iter_mut().enumerate().skip(1) {
+            let shift = self.constant(word_type, (index as u32 * LIMB_BITS).into())?;
+            *limb = self.append(arith::shrui(word, shift, self.location))?;
+        }
+        for limb in &mut limbs {
+            *limb = self.append(arith::trunci(*limb, self.limb_type(), self.location))?;
+        }
+        Ok(limbs)
+    }
+
+    /// Joins the limbs back into a word, from the least significant one
+    fn join(&self, limbs: &[Value<'c, 'c>]) -> Result<Value<'c, 'c>, CodegenError> {
+        let word_type: Type = IntegerType::new(self.context, 256).into();
+        let mut word = None;
+        for (index, limb) in limbs.iter().enumerate() {
+            let mut part = self.append(arith::extui(*limb, word_type, self.location))?;
+            if index > 0 {
+                let shift = self.constant(word_type, (index as u32 * LIMB_BITS).into())?;
+                part = self.append(arith::shli(part, shift, self.location))?;
+            }
+            word = Some(match word {
+                Some(word) => self.append(arith::ori(word, part, self.location))?,
+                None => part,
+            });
+        }
+        Ok(word.expect("words have limbs"))
+    }
+
+    fn limb_type(&self) -> Type<'c> {
+        IntegerType::new(self.context, LIMB_BITS).into()
+    }
+
+    fn constant(&self, r#type: Type<'c>, value: i64) -> Result<Value<'c, 'c>, CodegenError> {
+        self.append(arith::constant(
+            self.context,
+            IntegerAttribute::new(r#type, value).into(),
+            self.location,
+        ))
+    }
+
+    fn append(&self, operation: Operation<'c>) -> Result<Value<'c, 'c>, CodegenError> {
+        Ok(self.block.append_operation(operation).result(0)?.into())
+    }
+}
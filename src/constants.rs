@@ -1,20 +1,245 @@
 pub const MAX_STACK_SIZE: usize = 1024;
+/// The maximum depth of nested calls, counting the outermost one as depth zero
+pub const MAX_CALL_DEPTH: usize = 1024;
+/// The maximum number of CALLFs not returned from yet in EOF code (EIP-4750)
+pub const MAX_RETURN_STACK_DEPTH: usize = 1024;
+/// The maximum size of a contract's code, in bytes (EIP-170)
+pub const MAX_CODE_SIZE: usize = 24576;
+/// The maximum size of the initcode given to CREATE and CREATE2, in bytes (EIP-3860)
+pub const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE;
+/// The largest memory a program can have, in bytes, so that its offsets fit in a signed
+/// 32-bit index. No program could pay for it anyway, as it costs over 2^43 gas
+pub const MAX_MEMORY_SIZE: i64 = i32::MAX as i64 & !31;
 pub const GAS_COUNTER_GLOBAL: &str = "emv_mlir__gas_counter";
 pub const STACK_BASEPTR_GLOBAL: &str = "emv_mlir__stack_baseptr";
 pub const STACK_PTR_GLOBAL: &str = "emv_mlir__stack_ptr";
 pub const MEMORY_PTR_GLOBAL: &str = "emv_mlir__memory_ptr";
 pub const MEMORY_SIZE_GLOBAL: &str = "emv_mlir__memory_size";
+/// The bitmap of the breakpoints the program was compiled with, one bit for each byte of code
+pub const BREAKPOINTS_GLOBAL: &str = "emv_mlir__breakpoints";
+/// The symbol of the program's entrypoint, with the signature of
+/// [`MainFunc`](crate::syscall::MainFunc)
 pub const MAIN_ENTRYPOINT: &str = "main";
+/// The symbol of the entrypoint exported by shared libraries, with the signature of
+/// [`SharedEntrypoint`](crate::syscall::SharedEntrypoint)
+pub const SHARED_ENTRYPOINT: &str = "evm_mlir_execute";
+/// The version of the interface between shared libraries and this crate, made of their
+/// entrypoint and the syscalls they call. It changes along with any of them
+pub const SHARED_LIBRARY_ABI_VERSION: u32 = 2;
+/// The number of CALLFs not returned from yet, in programs made of EOF code sections
+pub const RETURN_STACK_DEPTH_GLOBAL: &str = "emv_mlir__return_stack_depth";
+/// The symbol of the function of an EOF code section, followed by the index of the section
+pub const CODE_SECTION_SYMBOL_PREFIX: &str = "emv_mlir__code_section_";
+/// The status the function of an EOF code section returns with on RETF, which is never
+/// the exit status of a program. Any other status means the program halted, and the
+/// callers return it too
+pub const RETF_STATUS: u8 = u8::MAX;
+/// The data section of the EOF container the program's code sections are of, followed by
+/// a word of zeros for the reads past its end
+pub const DATA_SECTION_GLOBAL: &str = "emv_mlir__data_section";
 
-pub const REVERT_EXIT_CODE: u8 = 255;
+/// The exit status of an execution, returned by the main entrypoint of the generated code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ExitStatusCode {
+    /// The program halted with a RETURN
+    Return = 0,
+    /// The program halted with a STOP, or by reaching the end of the bytecode
+    Stop,
+    /// The program halted with a REVERT
+    Revert,
+    /// The program halted with an exceptional error (e.g. stack underflow)
+    Error,
+    /// The program halted because it didn't have enough gas to continue
+    OutOfGas,
+    /// Placeholder for unknown exit codes
+    Default,
+}
+
+impl ExitStatusCode {
+    #[inline(always)]
+    pub const fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            x if x == Self::Return.to_u8() => Self::Return,
+            x if x == Self::Stop.to_u8() => Self::Stop,
+            x if x == Self::Revert.to_u8() => Self::Revert,
+            x if x == Self::Error.to_u8() => Self::Error,
+            x if x == Self::OutOfGas.to_u8() => Self::OutOfGas,
+            _ => Self::Default,
+        }
+    }
+}
+
+/// The kind of call made by one of the CALL* opcodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CallType {
+    /// CALL: runs the callee's code in the callee's context
+    Call = 0,
+    /// CALLCODE: runs the callee's code in the caller's context
+    CallCode,
+    /// DELEGATECALL: like CALLCODE, also keeping the caller's sender and value
+    DelegateCall,
+    /// STATICCALL: like CALL, but without value and forbidding any state modification
+    StaticCall,
+}
+
+impl CallType {
+    #[inline(always)]
+    pub const fn to_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        match value {
+            x if x == Self::Call.to_u8() => Self::Call,
+            x if x == Self::CallCode.to_u8() => Self::CallCode,
+            x if x == Self::DelegateCall.to_u8() => Self::DelegateCall,
+            x if x == Self::StaticCall.to_u8() => Self::StaticCall,
+            _ => unreachable!("invalid call type: {value}"),
+        }
+    }
+}
 
 /// Contains the gas costs of the EVM instructions
 pub mod gas_cost {
+    /// The base cost of every transaction
+    pub const TX: i64 = 21000;
+    pub const TX_DATA_ZERO_BYTE: i64 = 4;
+    pub const TX_DATA_NONZERO_BYTE: i64 = 16;
+    /// [`TX_DATA_NONZERO_BYTE`] before Istanbul (EIP-2028)
+    pub const TX_DATA_NONZERO_BYTE_PRE_ISTANBUL: i64 = 68;
+    /// Charged for each address in the transaction's access list (EIP-2930)
+    pub const TX_ACCESS_LIST_ADDRESS: i64 = 2400;
+    /// Charged for each storage key in the transaction's access list (EIP-2930)
+    pub const TX_ACCESS_LIST_STORAGE_KEY: i64 = 1900;
     pub const MSTORE: i64 = 3;
     pub const MSTORE8: i64 = 3;
+    pub const MLOAD: i64 = 3;
+    pub const MSIZE: i64 = 2;
+    pub const MCOPY: i64 = 3;
+    /// The linear cost of each word of memory
+    pub const MEMORY_WORD: i64 = 3;
+    /// The squared memory size in words is divided by this, giving its quadratic cost
+    pub const MEMORY_QUADRATIC_DIVISOR: i64 = 512;
+    pub const COPY_WORD: i64 = 3;
+    pub const KECCAK256: i64 = 30;
+    pub const KECCAK256_WORD: i64 = 6;
+    pub const CALLDATALOAD: i64 = 3;
+    pub const CALLDATASIZE: i64 = 2;
+    pub const CALLDATACOPY: i64 = 3;
+    pub const CODESIZE: i64 = 2;
+    pub const CODECOPY: i64 = 3;
+    /// Charged for reading a storage slot already accessed in the transaction
+    pub const SLOAD: i64 = 100;
+    /// Charged instead of [`SLOAD`] for the first access to a storage slot in the
+    /// transaction, since Berlin (EIP-2929). SSTORE charges it on top of its cost
+    pub const COLD_SLOAD: i64 = 2100;
+    /// [`SLOAD`] before Berlin, since Istanbul (EIP-1884)
+    pub const SLOAD_PRE_BERLIN: i64 = 800;
+    /// [`SLOAD`] before Istanbul, since Tangerine Whistle (EIP-150)
+    pub const SLOAD_PRE_ISTANBUL: i64 = 200;
+    /// [`SLOAD`] before Tangerine Whistle
+    pub const SLOAD_PRE_TANGERINE_WHISTLE: i64 = 50;
+    /// Charged when SSTORE leaves the slot unchanged, or changes an already modified slot
+    pub const SSTORE: i64 = 100;
+    /// Charged when SSTORE changes an unmodified slot from zero
+    pub const SSTORE_SET: i64 = 20000;
+    /// Charged when SSTORE changes an unmodified nonzero slot
+    pub const SSTORE_RESET: i64 = 2900;
+    /// [`SSTORE`] before Berlin, since Istanbul (EIP-2200)
+    pub const SSTORE_PRE_BERLIN: i64 = 800;
+    /// [`SSTORE_RESET`] before Berlin, and the cost of any SSTORE not setting a slot
+    /// from zero before Istanbul
+    pub const SSTORE_RESET_PRE_BERLIN: i64 = 5000;
+    /// SSTORE halts if the gas left is at most this (EIP-2200)
+    pub const SSTORE_SENTRY: i64 = 2300;
+    /// Refunded when SSTORE clears a slot that was nonzero at the start of the transaction
+    pub const SSTORE_CLEAR_REFUND: i64 = 4800;
+    /// [`SSTORE_CLEAR_REFUND`] before London
+    pub const SSTORE_CLEAR_REFUND_PRE_LONDON: i64 = 15000;
+    /// The refund is capped at the gas used by the transaction divided by this (EIP-3529)
+    pub const MAX_REFUND_QUOTIENT: i64 = 5;
+    /// [`MAX_REFUND_QUOTIENT`] before London
+    pub const MAX_REFUND_QUOTIENT_PRE_LONDON: i64 = 2;
+    pub const TLOAD: i64 = 100;
+    pub const TSTORE: i64 = 100;
+    pub const LOG: i64 = 375;
+    pub const LOG_TOPIC: i64 = 375;
+    pub const LOG_DATA_BYTE: i64 = 8;
+    pub const ADDRESS: i64 = 2;
+    pub const ORIGIN: i64 = 2;
+    pub const CALLER: i64 = 2;
+    pub const CALLVALUE: i64 = 2;
+    pub const GASPRICE: i64 = 2;
+    pub const COINBASE: i64 = 2;
+    pub const TIMESTAMP: i64 = 2;
+    pub const NUMBER: i64 = 2;
+    pub const PREVRANDAO: i64 = 2;
+    pub const GASLIMIT: i64 = 2;
+    pub const CHAINID: i64 = 2;
+    pub const BASEFEE: i64 = 2;
+    pub const SELFBALANCE: i64 = 5;
+    pub const BLOCKHASH: i64 = 20;
+    pub const BLOBHASH: i64 = 3;
+    pub const BLOBBASEFEE: i64 = 2;
+    pub const RETURNDATASIZE: i64 = 2;
+    pub const RETURNDATACOPY: i64 = 3;
+    /// Charged for accessing an account already accessed in the transaction
+    pub const WARM_ACCOUNT_ACCESS: i64 = 100;
+    /// Charged instead of [`WARM_ACCOUNT_ACCESS`] for the first access to an account in the
+    /// transaction, since Berlin (EIP-2929)
+    pub const COLD_ACCOUNT_ACCESS: i64 = 2600;
+    pub const EXTCODESIZE: i64 = WARM_ACCOUNT_ACCESS;
+    pub const EXTCODECOPY: i64 = WARM_ACCOUNT_ACCESS;
+    pub const EXTCODEHASH: i64 = WARM_ACCOUNT_ACCESS;
+    /// [`EXTCODESIZE`] and [`EXTCODECOPY`] before Berlin, since Tangerine Whistle (EIP-150)
+    pub const EXTCODE_PRE_BERLIN: i64 = 700;
+    /// [`EXTCODESIZE`] and [`EXTCODECOPY`] before Tangerine Whistle
+    pub const EXTCODE_PRE_TANGERINE_WHISTLE: i64 = 20;
+    /// [`EXTCODEHASH`] before Berlin, since Istanbul (EIP-1884)
+    pub const EXTCODEHASH_PRE_BERLIN: i64 = 700;
+    /// [`EXTCODEHASH`] before Istanbul, since Constantinople, which brings it (EIP-1052)
+    pub const EXTCODEHASH_PRE_ISTANBUL: i64 = 400;
+    pub const BALANCE: i64 = WARM_ACCOUNT_ACCESS;
+    /// [`BALANCE`] before Berlin, since Istanbul (EIP-1884)
+    pub const BALANCE_PRE_BERLIN: i64 = 700;
+    /// [`BALANCE`] before Istanbul, since Tangerine Whistle (EIP-150)
+    pub const BALANCE_PRE_ISTANBUL: i64 = 400;
+    /// [`BALANCE`] before Tangerine Whistle
+    pub const BALANCE_PRE_TANGERINE_WHISTLE: i64 = 20;
+    /// Shared by all the CALL* opcodes, which charge [`COLD_ACCOUNT_ACCESS`] instead
+    /// for a cold callee
+    pub const CALL: i64 = WARM_ACCOUNT_ACCESS;
+    /// [`CALL`] before Berlin, since Tangerine Whistle (EIP-150)
+    pub const CALL_PRE_BERLIN: i64 = 700;
+    /// [`CALL`] before Tangerine Whistle
+    pub const CALL_PRE_TANGERINE_WHISTLE: i64 = 40;
+    pub const CALL_VALUE_TRANSFER: i64 = 9000;
+    /// Charged when CALL creates the callee's account by sending it value
+    pub const CALL_NEW_ACCOUNT: i64 = 25000;
+    pub const CALL_STIPEND: i64 = 2300;
+    /// Shared by CREATE and CREATE2
+    pub const CREATE: i64 = 32000;
+    pub const INITCODE_WORD: i64 = 2;
+    pub const CODE_DEPOSIT_BYTE: i64 = 200;
+    /// Charged on top of [`COLD_ACCOUNT_ACCESS`] for a cold beneficiary
+    pub const SELFDESTRUCT: i64 = 5000;
+    /// Charged when SELFDESTRUCT sends value to an empty account, or to a missing one
+    /// before Spurious Dragon. SELFDESTRUCT costs nothing before Tangerine Whistle (EIP-150)
+    pub const SELFDESTRUCT_NEW_ACCOUNT: i64 = 25000;
+    /// Refunded for each account SELFDESTRUCT destroys, before London (EIP-3529)
+    pub const SELFDESTRUCT_REFUND_PRE_LONDON: i64 = 24000;
     pub const ADD: i64 = 3;
     pub const AND: i64 = 3;
     pub const EXP: i64 = 10;
+    pub const EXP_BYTE: i64 = 50;
+    /// [`EXP_BYTE`] before Spurious Dragon (EIP-160)
+    pub const EXP_BYTE_PRE_SPURIOUS_DRAGON: i64 = 10;
     pub const LT: i64 = 3;
     pub const SGT: i64 = 3;
     pub const GT: i64 = 3;
@@ -31,8 +256,10 @@ pub mod gas_cost {
     pub const MULMOD: i64 = 8;
     pub const SIGNEXTEND: i64 = 5;
     pub const SHL: i64 = 3;
+    pub const SHR: i64 = 3;
     pub const SLT: i64 = 3;
     pub const XOR: i64 = 3;
+    pub const NOT: i64 = 3;
     pub const SAR: i64 = 3;
     pub const POP: i64 = 2;
     pub const PC: i64 = 2;
@@ -41,7 +268,40 @@ pub mod gas_cost {
     pub const PUSH0: i64 = 2;
     pub const PUSHN: i64 = 3;
     pub const JUMP: i64 = 8;
+    pub const JUMPI: i64 = 10;
+    pub const RJUMP: i64 = 2;
+    pub const RJUMPI: i64 = 4;
+    pub const RJUMPV: i64 = 4;
+    pub const CALLF: i64 = 5;
+    pub const RETF: i64 = 3;
+    pub const JUMPF: i64 = 5;
+    pub const DATALOAD: i64 = 4;
+    pub const DATALOADN: i64 = 3;
+    pub const DATASIZE: i64 = 2;
+    pub const DATACOPY: i64 = 3;
     pub const DUPN: i64 = 3;
     pub const SWAPN: i64 = 3;
+    pub const EXCHANGE: i64 = 3;
     pub const BYTE: i64 = 3;
+    /// Charged by the precompiled contracts, see [`crate::precompiles`]
+    pub const ECRECOVER: i64 = 3000;
+    pub const SHA256: i64 = 60;
+    pub const SHA256_WORD: i64 = 12;
+    pub const RIPEMD160: i64 = 600;
+    pub const RIPEMD160_WORD: i64 = 120;
+    pub const IDENTITY: i64 = 15;
+    pub const IDENTITY_WORD: i64 = 3;
+    /// The least a modexp costs since Berlin (EIP-2565)
+    pub const MODEXP: i64 = 200;
+    /// The BN254 precompiles, whose costs were lowered in Istanbul (EIP-1108)
+    pub const EC_ADD: i64 = 150;
+    pub const EC_ADD_PRE_ISTANBUL: i64 = 500;
+    pub const EC_MUL: i64 = 6000;
+    pub const EC_MUL_PRE_ISTANBUL: i64 = 40000;
+    pub const EC_PAIRING: i64 = 45000;
+    pub const EC_PAIRING_PRE_ISTANBUL: i64 = 100000;
+    pub const EC_PAIRING_PAIR: i64 = 34000;
+    pub const EC_PAIRING_PAIR_PRE_ISTANBUL: i64 = 80000;
+    pub const BLAKE2F_ROUND: i64 = 1;
+    pub const POINT_EVALUATION: i64 = 50000;
 }
@@ -1,13 +1,10 @@
 use llvm_sys::{
-    core::LLVMDisposeMessage,
     target::{
-        LLVM_InitializeAllAsmPrinters, LLVM_InitializeAllTargetInfos, LLVM_InitializeAllTargetMCs,
-        LLVM_InitializeAllTargets,
+        LLVMCopyStringRepOfTargetData, LLVMDisposeTargetData, LLVM_InitializeAllAsmPrinters,
+        LLVM_InitializeAllTargetInfos, LLVM_InitializeAllTargetMCs, LLVM_InitializeAllTargets,
     },
     target_machine::{
-        LLVMCodeGenOptLevel, LLVMCodeModel, LLVMCreateTargetMachine, LLVMGetDefaultTargetTriple,
-        LLVMGetHostCPUFeatures, LLVMGetHostCPUName, LLVMGetTargetFromTriple, LLVMRelocMode,
-        LLVMTargetRef,
+        LLVMCreateTargetDataLayout, LLVMDisposeTargetMachine, LLVMGetDefaultTargetTriple,
     },
 };
 use melior::{
@@ -17,33 +14,50 @@ use melior::{
         DialectRegistry,
     },
     ir::{
-        attribute::{IntegerAttribute, StringAttribute, TypeAttribute},
+        attribute::{
+            DenseElementsAttribute, DenseI32ArrayAttribute, FlatSymbolRefAttribute,
+            IntegerAttribute, StringAttribute, TypeAttribute,
+        },
         operation::OperationBuilder,
-        r#type::{FunctionType, IntegerType},
-        Attribute, Block, Identifier, Location, Module as MeliorModule, Region, Value,
+        r#type::{FunctionType, IntegerType, RankedTensorType},
+        Attribute, Block, BlockRef, Identifier, Location, Module as MeliorModule, Region, Value,
     },
     utility::{register_all_dialects, register_all_llvm_translations, register_all_passes},
     Context as MeliorContext,
 };
 use std::{
-    ffi::CStr,
-    mem::MaybeUninit,
+    ops::Range,
     path::Path,
-    ptr::{addr_of_mut, null_mut},
-    sync::OnceLock,
+    sync::{Once, OnceLock},
 };
 
 use crate::{
-    codegen::{context::OperationCtx, operations::generate_code_for_op, run_pass_manager},
+    analysis::{StackAnalysis, StackCheck},
+    breakpoint::Breakpoints,
+    cfg::{ControlFlowGraph, Exit, JumpTarget},
+    codegen::{
+        context::OperationCtx,
+        operations::generate_code_for_op,
+        run_debug_info_pass, run_pass_manager, run_passes_one_by_one,
+        stack_promotion::{generate_code_for_run, is_promotable},
+    },
     constants::{
-        GAS_COUNTER_GLOBAL, MAIN_ENTRYPOINT, MAX_STACK_SIZE, MEMORY_PTR_GLOBAL, MEMORY_SIZE_GLOBAL,
-        STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL,
+        ExitStatusCode, BREAKPOINTS_GLOBAL, CODE_SECTION_SYMBOL_PREFIX, DATA_SECTION_GLOBAL,
+        GAS_COUNTER_GLOBAL, MAIN_ENTRYPOINT, MEMORY_PTR_GLOBAL, MEMORY_SIZE_GLOBAL,
+        RETURN_STACK_DEPTH_GLOBAL, SHARED_ENTRYPOINT, STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL,
     },
-    errors::CodegenError,
+    create_target_machine,
+    errors::{CodegenError, ProgramError},
     module::MLIRModule,
+    optimizer::optimize_with_stack_limit,
+    options::{CompileOptions, IrStage, OptLevel, OverflowCheck, StackConfig, Target},
     program::{Operation, Program},
-    syscall,
-    utils::{generate_revert_block, llvm_mlir, stack_pop},
+    spec::SpecId,
+    syscall, take_string,
+    utils::{
+        check_stack_has_at_least, check_stack_has_space_for, consume_gas, generate_revert_block,
+        get_gas_counter, llvm_mlir,
+    },
 };
 
 #[derive(Debug, Eq, PartialEq)]
@@ -66,11 +80,166 @@ impl Context {
         Self { melior_context }
     }
 
+    /// Compiles the program following the latest supported hardfork.
+    /// See [`Self::compile_with_spec`]
     pub fn compile(
         &self,
         program: &Program,
         output_file: impl AsRef<Path>,
     ) -> Result<MLIRModule, CodegenError> {
+        self.compile_with_spec(program, output_file, SpecId::default())
+    }
+
+    /// Compiles the program following the given hardfork.
+    /// The opcodes introduced after it are undefined, halting like INVALID.
+    ///
+    /// The MLIR code, before and after lowering it, is written next to `output_file`
+    /// with the `mlir` and `after-pass.mlir` extensions.
+    pub fn compile_with_spec(
+        &self,
+        program: &Program,
+        output_file: impl AsRef<Path>,
+        spec_id: SpecId,
+    ) -> Result<MLIRModule, CodegenError> {
+        self.compile_module(
+            program,
+            &[],
+            &[],
+            Some(output_file.as_ref()),
+            spec_id,
+            false,
+            None,
+            &CompileOptions::default(),
+        )
+    }
+
+    /// Compiles the program following the given hardfork, to be executed by an
+    /// [`Executor`](crate::executor::Executor) without touching the filesystem.
+    /// Unlike [`Self::compile_with_spec`], no intermediate files are written
+    pub fn compile_jit(
+        &self,
+        program: &Program,
+        spec_id: SpecId,
+    ) -> Result<MLIRModule, CodegenError> {
+        self.compile_module(
+            program,
+            &[],
+            &[],
+            None,
+            spec_id,
+            false,
+            None,
+            &CompileOptions::default(),
+        )
+    }
+
+    /// Like [`Self::compile_jit`], but in instrumented mode: the generated code reports
+    /// each opcode to the [`SyscallContext`](crate::syscall::SyscallContext) before
+    /// executing it, along with the gas left and the stack. This slows down the execution,
+    /// so it's only meant for tracing or inspecting it.
+    pub fn compile_jit_instrumented(
+        &self,
+        program: &Program,
+        spec_id: SpecId,
+    ) -> Result<MLIRModule, CodegenError> {
+        self.compile_module(
+            program,
+            &[],
+            &[],
+            None,
+            spec_id,
+            true,
+            None,
+            &CompileOptions::default(),
+        )
+    }
+
+    /// Like [`Self::compile_jit`], but checking the given breakpoints before each opcode.
+    /// When one is hit, the generated code calls into the
+    /// [`SyscallContext`](crate::syscall::SyscallContext), which reports it to its
+    /// [`Inspector`](crate::inspector::Inspector). The opcodes without a breakpoint only
+    /// pay for the check, which is much cheaper than reporting them all
+    pub fn compile_jit_with_breakpoints(
+        &self,
+        program: &Program,
+        spec_id: SpecId,
+        breakpoints: &Breakpoints,
+    ) -> Result<MLIRModule, CodegenError> {
+        self.compile_module(
+            program,
+            &[],
+            &[],
+            None,
+            spec_id,
+            false,
+            Some(breakpoints),
+            &CompileOptions::default(),
+        )
+    }
+
+    /// Like [`Self::compile_jit`], but following the given options, which may dump the
+    /// module at stages of the pipeline
+    pub fn compile_with_options(
+        &self,
+        program: &Program,
+        spec_id: SpecId,
+        options: &CompileOptions,
+    ) -> Result<MLIRModule, CodegenError> {
+        self.compile_module(program, &[], &[], None, spec_id, false, None, options)
+    }
+
+    /// Compiles the code sections of an EOF container, as
+    /// [`Container::programs`](crate::eof::Container::programs) decodes them, to be executed
+    /// by an [`Executor`](crate::executor::Executor). The program starts with the first one,
+    /// and the sections it calls with CALLF or JUMPF are compiled into functions, which
+    /// return with RETF. The [data](crate::eof::Container::data) of the container is
+    /// embedded in the module, for the data instructions to read. There must be at least
+    /// one section
+    pub fn compile_code_sections(
+        &self,
+        sections: &[Program],
+        data: &[u8],
+        spec_id: SpecId,
+    ) -> Result<MLIRModule, CodegenError> {
+        let Some(first_section) = sections.first() else {
+            return Err(ProgramError::NoCodeSections.into());
+        };
+        self.compile_module(
+            first_section,
+            sections,
+            data,
+            None,
+            spec_id,
+            false,
+            None,
+            &CompileOptions::default(),
+        )
+    }
+
+    /// Compiles the program into a module lowered to the LLVM dialect, writing
+    /// the intermediate MLIR code next to `output_file` if given.
+    /// If `instrumented` is set, each opcode is reported before executing it.
+    /// If `breakpoints` are given, the ones hit are reported.
+    /// The EOF code sections the program calls are taken from `sections`, and the data
+    /// they read from `data`
+    #[allow(clippy::too_many_arguments)]
+    fn compile_module(
+        &self,
+        program: &Program,
+        sections: &[Program],
+        data: &[u8],
+        output_file: Option<&Path>,
+        spec_id: SpecId,
+        instrumented: bool,
+        breakpoints: Option<&Breakpoints>,
+        options: &CompileOptions,
+    ) -> Result<MLIRModule, CodegenError> {
+        // Labels are resolved to offsets before anything else looks at the program
+        let program = program.resolved()?;
+        let program = program.as_ref();
+        // Immediates wider than a word have no encoding, nor a constant to push
+        program.validate()?;
+
         static INITIALIZED: OnceLock<()> = OnceLock::new();
         INITIALIZED.get_or_init(|| unsafe {
             LLVM_InitializeAllTargets();
@@ -79,7 +248,10 @@ impl Context {
             LLVM_InitializeAllAsmPrinters();
         });
 
-        let target_triple = get_target_triple();
+        let target_triple = match &options.target.triple {
+            Some(triple) => triple.clone(),
+            None => get_target_triple(),
+        };
 
         let context = &self.melior_context;
 
@@ -89,10 +261,27 @@ impl Context {
 
         module_region.append_block(module_block);
 
-        let data_layout_ret = &get_data_layout_rep()?;
+        let data_layout_ret = &get_data_layout_rep(&options.target)?;
+
+        // Instrumented programs report the operations as written
+        let optimized = (options.passes.peephole && !instrumented && breakpoints.is_none())
+            .then(|| optimize_with_stack_limit(program, spec_id, options.stack.limit));
+        let (program, extra_gas) = match &optimized {
+            Some(optimized) => (&optimized.program, optimized.extra_gas.as_slice()),
+            None => (program, [].as_slice()),
+        };
+
+        // The debug info of the module and its functions is located in the listing too
+        let module_location = match &options.debug_info {
+            Some(debug_info) => {
+                debug_info.write_listing(program)?;
+                debug_info.location(context, 0)
+            }
+            None => Location::unknown(context),
+        };
 
         // build main module
-        let op = OperationBuilder::new("builtin.module", Location::unknown(context))
+        let op = OperationBuilder::new("builtin.module", module_location)
             .add_attributes(&[
                 (
                     Identifier::new(context, "llvm.target_triple"),
@@ -105,19 +294,104 @@ impl Context {
             ])
             .add_regions([module_region])
             .build()?;
-        assert!(op.verify(), "module operation is not valid");
+        if !op.verify() {
+            return Err(CodegenError::VerificationFailed("module operation"));
+        }
 
-        let mut melior_module = MeliorModule::from_operation(op).expect("module failed to create");
+        let mut melior_module = MeliorModule::from_operation(op)
+            .ok_or(CodegenError::VerificationFailed("module operation"))?;
 
-        compile_program(context, &melior_module, program)?;
+        let called_sections = called_sections(program, sections)?;
+        let reads_data = std::iter::once(program)
+            .chain(called_sections.iter().map(|section| &sections[*section]))
+            .flat_map(|program| &program.operations)
+            .any(|operation| matches!(operation, Operation::Dataload | Operation::Datacopy));
+        if reads_data {
+            generate_data_section_global(context, &melior_module, data)?;
+        }
 
-        assert!(melior_module.as_operation().verify());
+        compile_program(
+            context,
+            &melior_module,
+            program,
+            extra_gas,
+            data,
+            spec_id,
+            instrumented,
+            breakpoints,
+            options,
+            Function::Main,
+        )?;
+        generate_shared_entrypoint(context, &melior_module)?;
+
+        // The code sections are neither instrumented nor located in the listing, which is
+        // only of the program
+        let section_options = CompileOptions {
+            debug_info: None,
+            ..options.clone()
+        };
+        for section in called_sections {
+            let section_program = sections[section].resolved()?;
+            let section_program = section_program.as_ref();
+            section_program.validate()?;
+            let optimized = options
+                .passes
+                .peephole
+                .then(|| optimize_with_stack_limit(section_program, spec_id, options.stack.limit));
+            let (section_program, extra_gas) = match &optimized {
+                Some(optimized) => (&optimized.program, optimized.extra_gas.as_slice()),
+                None => (section_program, [].as_slice()),
+            };
+            compile_program(
+                context,
+                &melior_module,
+                section_program,
+                extra_gas,
+                data,
+                spec_id,
+                false,
+                None,
+                &section_options,
+                Function::CodeSection(section as u16),
+            )?;
+        }
 
-        let filename = output_file.as_ref().with_extension("mlir");
-        std::fs::write(filename, melior_module.as_operation().to_string())?;
+        if !melior_module.as_operation().verify() {
+            return Err(CodegenError::VerificationFailed("module"));
+        }
+
+        if let Some(output_file) = output_file {
+            let filename = output_file.with_extension("mlir");
+            std::fs::write(filename, melior_module.as_operation().to_string())?;
+        }
+
+        if let Some(dump) = &options.dump {
+            dump.dump(&IrStage::BeforeLowering, &melior_module)?;
+        }
 
         // TODO: Add proper error handling.
-        run_pass_manager(context, &mut melior_module)?;
+        match options.dump.as_ref().filter(|dump| dump.after_each_pass) {
+            // The passes are run one at a time to dump the module between them
+            Some(dump) => {
+                let passes = &options.passes;
+                run_passes_one_by_one(
+                    context,
+                    &mut melior_module,
+                    passes,
+                    |index, name, module| {
+                        let stage = IrStage::AfterPass {
+                            index,
+                            name: name.to_string(),
+                        };
+                        dump.dump(&stage, module).map_err(CodegenError::from)
+                    },
+                )?
+            }
+            None => run_pass_manager(context, &mut melior_module, &options.passes)?,
+        }
+        if options.debug_info.is_some() {
+            run_debug_info_pass(context, &mut melior_module)?;
+        }
 
         // The func to llvm pass has a bug where it sets the data layout string to ""
         // This works around it by setting it again.
@@ -130,10 +404,15 @@ impl Context {
         }
 
         // Output MLIR
-        let filename = output_file.as_ref().with_extension("after-pass.mlir");
-        std::fs::write(filename, melior_module.as_operation().to_string())?;
+        if let Some(output_file) = output_file {
+            let filename = output_file.with_extension("after-pass.mlir");
+            std::fs::write(filename, melior_module.as_operation().to_string())?;
+        }
+        if let Some(dump) = &options.dump {
+            dump.dump(&IrStage::Lowered, &melior_module)?;
+        }
 
-        Ok(MLIRModule::new(melior_module))
+        Ok(MLIRModule::with_options(melior_module, options.clone()))
     }
 }
 
@@ -146,87 +425,120 @@ pub fn initialize_mlir() -> MeliorContext {
         registry
     });
     context.load_all_available_dialects();
-    register_all_passes();
+    // The passes are registered globally, which isn't safe to do from many threads at once
+    static REGISTER_PASSES: Once = Once::new();
+    REGISTER_PASSES.call_once(register_all_passes);
     register_all_llvm_translations(&context);
     context
 }
 
 pub fn get_target_triple() -> String {
-    let target_triple = unsafe {
-        let value = LLVMGetDefaultTargetTriple();
-        CStr::from_ptr(value).to_string_lossy().into_owned()
-    };
-    target_triple
+    unsafe { take_string(LLVMGetDefaultTargetTriple()) }
 }
 
-pub fn get_data_layout_rep() -> Result<String, CodegenError> {
+/// Returns the data layout of the target, as a string
+pub fn get_data_layout_rep(target: &Target) -> Result<String, CodegenError> {
+    let machine = create_target_machine(target, OptLevel::O0)?;
     unsafe {
-        let mut null = null_mut();
-        let error_buffer = addr_of_mut!(null);
-
-        let target_triple = LLVMGetDefaultTargetTriple();
-        let target_cpu = LLVMGetHostCPUName();
-        let target_cpu_features = LLVMGetHostCPUFeatures();
+        let data_layout = LLVMCreateTargetDataLayout(machine);
+        let data_layout_str = take_string(LLVMCopyStringRepOfTargetData(data_layout));
+        LLVMDisposeTargetData(data_layout);
+        LLVMDisposeTargetMachine(machine);
+        Ok(data_layout_str)
+    }
+}
 
-        let mut target: MaybeUninit<LLVMTargetRef> = MaybeUninit::uninit();
+/// The function a program is compiled into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    /// The entrypoint of the module, which sets up the stack, the memory and the gas
+    Main,
+    /// The function of the EOF code section with the given index, called with CALLF and
+    /// JUMPF. It takes the syscall context, and returns a status like the entrypoint does,
+    /// or [`RETF_STATUS`](crate::constants::RETF_STATUS) if it returned with RETF
+    CodeSection(u16),
+}
 
-        if LLVMGetTargetFromTriple(target_triple, target.as_mut_ptr(), error_buffer) != 0 {
-            let error = CStr::from_ptr(*error_buffer);
-            let err = error.to_string_lossy().to_string();
-            dbg!(err.clone());
-            LLVMDisposeMessage(*error_buffer);
-            return Err(CodegenError::LLVMCompileError(err))?;
-        }
-        if !(*error_buffer).is_null() {
-            LLVMDisposeMessage(*error_buffer);
+/// Returns the indices of the EOF code sections the program calls or jumps to, directly
+/// or from the sections it calls, in order
+fn called_sections(program: &Program, sections: &[Program]) -> Result<Vec<usize>, ProgramError> {
+    let callees = |program: &Program| -> Vec<u16> {
+        program
+            .operations
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Callf(section, _) | Operation::Jumpf(section, _) => Some(*section),
+                _ => None,
+            })
+            .collect()
+    };
+    let mut called = vec![false; sections.len()];
+    let mut pending = callees(program);
+    while let Some(section) = pending.pop() {
+        let Some(section_program) = sections.get(section as usize) else {
+            return Err(ProgramError::UndefinedCodeSection { section });
+        };
+        if !std::mem::replace(&mut called[section as usize], true) {
+            pending.extend(callees(section_program));
         }
-
-        let target = target.assume_init();
-
-        let machine = LLVMCreateTargetMachine(
-            target,
-            target_triple.cast(),
-            target_cpu.cast(),
-            target_cpu_features.cast(),
-            LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
-            LLVMRelocMode::LLVMRelocDefault,
-            LLVMCodeModel::LLVMCodeModelDefault,
-        );
-
-        let data_layout = llvm_sys::target_machine::LLVMCreateTargetDataLayout(machine);
-        let data_layout_str =
-            CStr::from_ptr(llvm_sys::target::LLVMCopyStringRepOfTargetData(data_layout));
-        Ok(data_layout_str.to_string_lossy().into_owned())
     }
+    Ok((0..sections.len())
+        .filter(|section| called[*section])
+        .collect())
 }
 
+#[allow(clippy::too_many_arguments)]
 fn compile_program(
     context: &MeliorContext,
     module: &MeliorModule,
     program: &Program,
+    extra_gas: &[i64],
+    data: &[u8],
+    spec_id: SpecId,
+    instrumented: bool,
+    breakpoints: Option<&Breakpoints>,
+    options: &CompileOptions,
+    function: Function,
 ) -> Result<(), CodegenError> {
     let location = Location::unknown(context);
+    let debug_info = options.debug_info.as_ref();
     let ptr_type = pointer(context, 0);
     let uint8 = IntegerType::new(context, 8).into();
+    let uint32 = IntegerType::new(context, 32).into();
     let uint64 = IntegerType::new(context, 64).into();
 
-    // Build the main function
+    // Build the main function, or the one of the code section
+    let (name, arguments, attributes) = match function {
+        Function::Main => (
+            MAIN_ENTRYPOINT.to_string(),
+            vec![ptr_type, uint64],
+            vec![
+                (
+                    Identifier::new(context, "sym_visibility"),
+                    StringAttribute::new(context, "public").into(),
+                ),
+                (
+                    Identifier::new(context, "llvm.emit_c_interface"),
+                    Attribute::unit(context),
+                ),
+            ],
+        ),
+        Function::CodeSection(section) => (
+            format!("{CODE_SECTION_SYMBOL_PREFIX}{section}"),
+            vec![ptr_type],
+            vec![(
+                Identifier::new(context, "sym_visibility"),
+                StringAttribute::new(context, "private").into(),
+            )],
+        ),
+    };
     let main_func = func::func(
         context,
-        StringAttribute::new(context, MAIN_ENTRYPOINT),
-        TypeAttribute::new(FunctionType::new(context, &[ptr_type, uint64], &[uint8]).into()),
+        StringAttribute::new(context, &name),
+        TypeAttribute::new(FunctionType::new(context, &arguments, &[uint8]).into()),
         Region::new(),
-        &[
-            (
-                Identifier::new(context, "sym_visibility"),
-                StringAttribute::new(context, "public").into(),
-            ),
-            (
-                Identifier::new(context, "llvm.emit_c_interface"),
-                Attribute::unit(context),
-            ),
-        ],
-        location,
+        &attributes,
+        debug_info.map_or(location, |debug_info| debug_info.location(context, 0)),
     );
 
     let main_region = main_func.region(0).unwrap();
@@ -235,14 +547,29 @@ fn compile_program(
     // PERF: avoid generating unneeded setup blocks
     let setup_block = main_region.append_block(Block::new(&[]));
     let syscall_ctx = setup_block.add_argument(ptr_type, location);
-    let initial_gas = setup_block.add_argument(uint64, location);
 
-    // Append setup code to be run at the start
-    generate_stack_setup_code(context, module, &setup_block)?;
-    generate_memory_setup_code(context, module, &setup_block)?;
-    generate_gas_counter_setup_code(context, module, &setup_block, initial_gas)?;
+    // Append setup code to be run at the start. The code sections share all of it with
+    // the program which calls them
+    if function == Function::Main {
+        let initial_gas = setup_block.add_argument(uint64, location);
+        generate_stack_setup_code(context, module, &setup_block, syscall_ctx, &options.stack)?;
+        generate_memory_setup_code(context, module, &setup_block)?;
+        generate_gas_counter_setup_code(context, module, &setup_block, initial_gas)?;
+
+        syscall::mlir::declare_syscalls(context, module);
+
+        let calls_sections = program
+            .operations
+            .iter()
+            .any(|operation| matches!(operation, Operation::Callf(..) | Operation::Jumpf(..)));
+        if calls_sections {
+            generate_return_stack_setup_code(context, module, &setup_block)?;
+        }
+    }
 
-    syscall::mlir::declare_syscalls(context, module);
+    if let Some(breakpoints) = breakpoints {
+        generate_breakpoints_global(context, module, program, breakpoints)?;
+    }
 
     // Generate helper blocks
     let revert_block = main_region.append_block(generate_revert_block(context)?);
@@ -251,43 +578,685 @@ fn compile_program(
     let mut op_ctx = OperationCtx {
         mlir_context: context,
         program,
+        data,
+        spec_id,
+        word_repr: options.word_repr,
+        stack_limit: options.stack.limit,
+        stack_guarded: options.stack.overflow_check == OverflowCheck::GuardPage,
         syscall_ctx,
         revert_block,
         jumptable_block,
         jumpdest_blocks: Default::default(),
+        block_entries: Default::default(),
+        static_jump_blocks: Default::default(),
+        jump_target: None,
+        switch_targets: Vec::new(),
+        location,
+        stack_checked: false,
+        gas_charged: false,
+    };
+
+    // Instrumented programs report each operation before it checks the stack, so their
+    // checks stay where they are
+    let cfg = ControlFlowGraph::new(program);
+    let stack_analysis =
+        (options.passes.elide_stack_checks && !instrumented && breakpoints.is_none())
+            .then(|| StackAnalysis::with_stack_limit(&cfg, options.stack.limit));
+    let mut stack_blocks = cfg
+        .blocks()
+        .iter()
+        .zip(stack_analysis.iter().flat_map(|analysis| analysis.blocks()))
+        .peekable();
+
+    // No code is generated for the blocks which can't run
+    let mut live = vec![false; program.operations.len()];
+    for (block, reachable) in cfg.blocks().iter().zip(cfg.reachable_blocks()) {
+        live[block.operations.clone()].fill(reachable);
+    }
+    // The jumps to a pushed JUMPDEST, and the relative jumps, go straight to their target
+    let target_pc = |target: &JumpTarget| match target {
+        JumpTarget::Block(block) => Some(cfg.block_pc(*block)),
+        JumpTarget::Invalid | JumpTarget::Dynamic => None,
     };
+    let mut jump_targets = vec![None; program.operations.len()];
+    let mut switch_targets = vec![Vec::new(); program.operations.len()];
+    let mut block_pcs = vec![None; program.operations.len()];
+    for (index, block) in cfg.blocks().iter().enumerate() {
+        let last = block.operations.end - 1;
+        match &block.exit {
+            Exit::Jump(target) | Exit::Branch { target, .. } => {
+                jump_targets[last] = target_pc(target);
+            }
+            Exit::Switch { targets, .. } => {
+                switch_targets[last] = targets.iter().map(target_pc).collect();
+            }
+            Exit::Fallthrough(_) | Exit::Halt => {}
+        }
+        block_pcs[block.operations.start] = Some(cfg.block_pc(index));
+    }
+
+    // Instrumented programs report the gas left before each operation, so they're
+    // charged one at a time
+    let batched_gas = (options.passes.batch_gas && !instrumented && breakpoints.is_none())
+        .then(|| batch_gas(&cfg, extra_gas, spec_id));
+    op_ctx.gas_charged = batched_gas.is_some();
+    // The gas charged before each operation, besides its own if it isn't batched
+    let charges: Vec<i64> = match &batched_gas {
+        Some(batched_gas) => batched_gas.clone(),
+        // The gas of the operations the optimizer rewrote away is charged first
+        None => (0..program.operations.len())
+            .map(|index| extra_gas.get(index).copied().unwrap_or(0))
+            .collect(),
+    };
+
+    // Instrumented programs report the stack before each operation, so it's kept whole
+    let mut runs = (options.passes.promote_stack && !instrumented && breakpoints.is_none())
+        .then(|| promoted_runs(&cfg, &charges, spec_id))
+        .unwrap_or_default()
+        .into_iter()
+        .peekable();
+    let mut run_end = 0;
 
     let mut last_block = setup_block;
+    let mut pc = 0;
 
     // Generate code for the program
-    for op in &op_ctx.program.operations {
-        let (block_start, block_end) = generate_code_for_op(&mut op_ctx, &main_region, op.clone())?;
+    for (index, op) in op_ctx.program.operations.iter().enumerate() {
+        // The operations of a promoted run are generated along with its first one
+        if index < run_end {
+            continue;
+        }
+        if let Some(debug_info) = debug_info {
+            op_ctx.location = debug_info.location(context, index);
+        }
+        // Starting a basic block, its operations are left unchecked if the whole block is
+        // checked at its entry, or needs no check
+        let block_stack = stack_blocks.next_if(|(block, _)| block.operations.start == index);
+        if let Some((_, stack)) = block_stack {
+            op_ctx.stack_checked = stack.check != StackCheck::EachOperation;
+        }
+        // Live code after dead code starts with a JUMPDEST, so the offsets of the
+        // instrumented operations stay right
+        if !live[index] {
+            continue;
+        }
+        op_ctx.jump_target = jump_targets[index];
+        op_ctx.switch_targets = std::mem::take(&mut switch_targets[index]);
+        let (mut block_start, block_end) = match runs.next_if(|run| run.start == index) {
+            Some(run) => {
+                let locations: Vec<Location> = run
+                    .clone()
+                    .map(|index| {
+                        debug_info
+                            .map_or(location, |debug_info| debug_info.location(context, index))
+                    })
+                    .collect();
+                run_end = run.end;
+                generate_code_for_run(&op_ctx, &main_region, &program.operations[run], &locations)?
+            }
+            None => generate_code_for_op(&mut op_ctx, &main_region, op.clone())?,
+        };
+
+        let gas = charges[index];
+        if gas > 0 {
+            block_start = generate_gas_block(&op_ctx, &main_region, gas, block_start)?;
+            // Jumps land on the charge
+            if let Operation::Jumpdest { pc } = op {
+                op_ctx.register_jump_destination(*pc, block_start);
+            }
+        }
+
+        // With a guard page, only the underflows are checked
+        let checked_stack = block_stack
+            .filter(|(_, stack)| stack.check == StackCheck::Entry)
+            .map(|(_, stack)| {
+                let growth = if op_ctx.stack_guarded {
+                    0
+                } else {
+                    stack.growth
+                };
+                (stack.required, growth)
+            })
+            .filter(|(required, growth)| *required > 0 || *growth > 0);
+        if let Some((required, growth)) = checked_stack {
+            block_start =
+                generate_stack_check_block(&op_ctx, &main_region, required, growth, block_start)?;
+            // Jumps land on the check
+            if let Operation::Jumpdest { pc } = op {
+                op_ctx.register_jump_destination(*pc, block_start);
+            }
+        }
+
+        if instrumented || breakpoints.is_some() {
+            // The offsets recorded while parsing are kept, in case any PUSH wasn't minimal
+            if let Operation::Jumpdest { pc: op_pc } | Operation::PC { pc: op_pc } = op {
+                pc = *op_pc;
+            }
+            let bytecode = op.to_bytecode();
+            if instrumented {
+                let trace_block = generate_trace_block(&op_ctx, &main_region, pc, bytecode[0])?;
+                trace_block.append_operation(cf::br(&block_start, &[], location));
+                block_start = trace_block;
+            }
+            if breakpoints.is_some() {
+                block_start = generate_breakpoint_check_block(
+                    &op_ctx,
+                    &main_region,
+                    pc,
+                    bytecode[0],
+                    block_start,
+                )?;
+            }
+            // Jumps land on the first block added, so the JUMPDEST is reported
+            if let Operation::Jumpdest { pc } = op {
+                op_ctx.register_jump_destination(*pc, block_start);
+            }
+            pc += bytecode.len();
+        }
+
+        // The jumps whose target is known land on the first block added
+        if let Some(block_pc) = block_pcs[index] {
+            op_ctx.register_block_entry(block_pc, block_start);
+        }
 
         last_block.append_operation(cf::br(&block_start, &[], location));
         last_block = block_end;
     }
 
-    populate_jumptable(&op_ctx)?;
+    op_ctx.link_static_jumps();
+    populate_jumptable(&op_ctx, &main_region)?;
 
     let return_block = main_region.append_block(Block::new(&[]));
     last_block.append_operation(cf::br(&return_block, &[], location));
 
     // Setup return operation
-    // This returns the last element of the stack
-    // TODO: this should return nothing
-    let stack_top = stack_pop(context, &return_block)?;
-    // Truncate the value to 8 bits.
-    // NOTE: this is due to amd64 using two registers (128 bits) for return values.
+    // Reaching the end of the program is equivalent to a STOP
     let exit_code = return_block
-        .append_operation(arith::trunci(stack_top, uint8, location))
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8, ExitStatusCode::Stop.to_u8() as _).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let zero = return_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32, 0).into(),
+            location,
+        ))
         .result(0)?
         .into();
+    let remaining_gas = get_gas_counter(context, &return_block)?;
+    op_ctx.write_result_syscall(
+        &return_block,
+        zero,
+        zero,
+        remaining_gas,
+        exit_code,
+        location,
+    );
     return_block.append_operation(func::r#return(&[exit_code], location));
 
     module.body().append_operation(main_func);
     Ok(())
 }
 
+/// Generates a block reporting the opcode at `pc` to the syscall context, along with
+/// the gas left and the stack
+fn generate_trace_block<'c>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'c Region<'c>,
+    pc: usize,
+    opcode: u8,
+) -> Result<BlockRef<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = Location::unknown(context);
+
+    let block = region.append_block(Block::new(&[]));
+
+    let [pc, opcode, gas, stack_base, stack_top] =
+        generate_step_values(context, &block, pc, opcode)?;
+    op_ctx.trace_step_syscall(&block, pc, opcode, gas, stack_base, stack_top, location);
+
+    Ok(block)
+}
+
+/// Declares the bitmap checked before each opcode when compiling with breakpoints,
+/// covering the whole code of the program
+fn generate_breakpoints_global(
+    context: &MeliorContext,
+    module: &MeliorModule,
+    program: &Program,
+    breakpoints: &Breakpoints,
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let uint8 = IntegerType::new(context, 8).into();
+
+    let bitmap = breakpoints.to_bitmap(program.to_bytecode().len());
+    let bytes: Vec<Attribute> = bitmap
+        .iter()
+        .map(|byte| IntegerAttribute::new(uint8, *byte as i8 as i64).into())
+        .collect();
+    let value = DenseElementsAttribute::new(
+        RankedTensorType::new(&[bitmap.len() as u64], uint8, None).into(),
+        &bytes,
+    )?;
+
+    let bitmap_type = llvm::r#type::array(uint8, bitmap.len() as u32);
+
+    let body = module.body();
+    let res = body.append_operation(llvm_mlir::initialized_global(
+        context,
+        BREAKPOINTS_GLOBAL,
+        bitmap_type,
+        value.into(),
+        location,
+    ));
+    assert!(res.verify());
+    Ok(())
+}
+
+/// Generates the entrypoint exported by shared libraries, which runs the main function
+/// with a new syscall context, see [`SharedEntrypoint`](syscall::SharedEntrypoint)
+fn generate_shared_entrypoint(
+    context: &MeliorContext,
+    module: &MeliorModule,
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+    let uint8 = IntegerType::new(context, 8).into();
+    let uint64 = IntegerType::new(context, 64).into();
+
+    // env, calldata, calldata_len, gas, output, output_capacity, output_len
+    let arguments = [
+        ptr_type, ptr_type, uint64, ptr_type, ptr_type, uint64, ptr_type,
+    ];
+    let entrypoint = func::func(
+        context,
+        StringAttribute::new(context, SHARED_ENTRYPOINT),
+        TypeAttribute::new(FunctionType::new(context, &arguments, &[uint8]).into()),
+        Region::new(),
+        &[(
+            Identifier::new(context, "sym_visibility"),
+            StringAttribute::new(context, "public").into(),
+        )],
+        location,
+    );
+    let region = entrypoint.region(0)?;
+    let block = region.append_block(Block::new(&arguments.map(|argument| (argument, location))));
+    let env = block.argument(0)?.into();
+    let calldata = block.argument(1)?.into();
+    let calldata_len = block.argument(2)?.into();
+    let gas_ptr = block.argument(3)?.into();
+    let output_ptr = block.argument(4)?.into();
+    let output_capacity = block.argument(5)?.into();
+    let output_len_ptr = block.argument(6)?.into();
+
+    let syscall_ctx =
+        syscall::mlir::new_context_syscall(context, &block, env, calldata, calldata_len, location)?;
+    let initial_gas = block
+        .append_operation(llvm::load(
+            context,
+            gas_ptr,
+            uint64,
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+    let exit_code = block
+        .append_operation(func::call(
+            context,
+            FlatSymbolRefAttribute::new(context, MAIN_ENTRYPOINT),
+            &[syscall_ctx, initial_gas],
+            &[uint8],
+            location,
+        ))
+        .result(0)?
+        .into();
+    syscall::mlir::finish_context_syscall(
+        context,
+        syscall_ctx,
+        &block,
+        gas_ptr,
+        output_ptr,
+        output_capacity,
+        output_len_ptr,
+        location,
+    );
+    block.append_operation(func::r#return(&[exit_code], location));
+
+    module.body().append_operation(entrypoint);
+    Ok(())
+}
+
+/// Embeds the data section of the EOF container in the module, followed by a word of
+/// zeros, so a DATALOAD past its end reads them without checking the offset
+fn generate_data_section_global(
+    context: &MeliorContext,
+    module: &MeliorModule,
+    data: &[u8],
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let uint8 = IntegerType::new(context, 8).into();
+
+    let bytes: Vec<Attribute> = data
+        .iter()
+        .chain(&[0; 32])
+        .map(|byte| IntegerAttribute::new(uint8, *byte as i8 as i64).into())
+        .collect();
+    let value = DenseElementsAttribute::new(
+        RankedTensorType::new(&[bytes.len() as u64], uint8, None).into(),
+        &bytes,
+    )?;
+
+    let data_type = llvm::r#type::array(uint8, bytes.len() as u32);
+
+    let body = module.body();
+    let res = body.append_operation(llvm_mlir::initialized_global(
+        context,
+        DATA_SECTION_GLOBAL,
+        data_type,
+        value.into(),
+        location,
+    ));
+    assert!(res.verify());
+    Ok(())
+}
+
+/// Generates a block checking the bit of `pc` in the breakpoints bitmap, and
+/// reporting the opcode at `pc` to the syscall context when it's set, before
+/// continuing to `next_block`
+fn generate_breakpoint_check_block<'c>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'c Region<'c>,
+    pc: usize,
+    opcode: u8,
+    next_block: BlockRef<'c, 'c>,
+) -> Result<BlockRef<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+    let uint8 = IntegerType::new(context, 8).into();
+
+    let check_block = region.append_block(Block::new(&[]));
+    let hit_block = region.append_block(Block::new(&[]));
+
+    // Load the byte holding the bit of the opcode
+    let bitmap_ptr = check_block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            BREAKPOINTS_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+    let byte_ptr = check_block
+        .append_operation(llvm::get_element_ptr(
+            context,
+            bitmap_ptr.into(),
+            DenseI32ArrayAttribute::new(context, &[(pc / 8) as i32]),
+            uint8,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+    let byte = check_block
+        .append_operation(llvm::load(
+            context,
+            byte_ptr.into(),
+            uint8,
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    let mask = check_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8, (1_u8 << (pc % 8)) as i8 as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let bit = check_block
+        .append_operation(arith::andi(byte, mask, location))
+        .result(0)?
+        .into();
+    let zero = check_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8, 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let is_set = check_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ne,
+            bit,
+            zero,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    check_block.append_operation(cf::cond_br(
+        context,
+        is_set,
+        &hit_block,
+        &next_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let [pc, opcode, gas, stack_base, stack_top] =
+        generate_step_values(context, &hit_block, pc, opcode)?;
+    op_ctx.breakpoint_syscall(&hit_block, pc, opcode, gas, stack_base, stack_top, location);
+    hit_block.append_operation(cf::br(&next_block, &[], location));
+
+    Ok(check_block)
+}
+
+/// Generates a block checking the stack before the basic block starting at `next_block`,
+/// which needs `required` items on the stack, and grows it by at most `growth` items.
+/// The block goes on to `next_block` if the stack can hold them, and reverts otherwise
+fn generate_stack_check_block<'c>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'c Region<'c>,
+    required: usize,
+    growth: usize,
+    next_block: BlockRef<'c, 'c>,
+) -> Result<BlockRef<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = op_ctx.location;
+
+    let check_block = region.append_block(Block::new(&[]));
+    let mut flag = None;
+    if required > 0 {
+        flag = Some(check_stack_has_at_least(
+            context,
+            &check_block,
+            required as u32,
+        )?);
+    }
+    if growth > 0 {
+        let has_space =
+            check_stack_has_space_for(context, &check_block, growth as u32, op_ctx.stack_limit)?;
+        flag = Some(match flag {
+            Some(has_items) => check_block
+                .append_operation(arith::andi(has_items, has_space, location))
+                .result(0)?
+                .into(),
+            None => has_space,
+        });
+    }
+    let flag = flag.expect("blocks not changing the stack aren't checked");
+
+    check_block.append_operation(cf::cond_br(
+        context,
+        flag,
+        &next_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    Ok(check_block)
+}
+
+/// Generates a block consuming `gas` before going on to `next_block`, which reverts if
+/// there isn't enough gas left
+fn generate_gas_block<'c>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'c Region<'c>,
+    gas: i64,
+    next_block: BlockRef<'c, 'c>,
+) -> Result<BlockRef<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+
+    let gas_block = region.append_block(Block::new(&[]));
+    let flag = consume_gas(context, &gas_block, gas)?;
+    gas_block.append_operation(cf::cond_br(
+        context,
+        flag,
+        &next_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        op_ctx.location,
+    ));
+
+    Ok(gas_block)
+}
+
+/// Returns the gas charged before each operation of the program when it's batched: the
+/// static gas of a run of operations of a basic block, and the gas the optimizer moved
+/// to them, is charged before the first one, and none before the others.
+///
+/// A run ends after an operation depending on the gas left once it's charged, so no gas
+/// of the operations after it is charged before it, and after an undefined operation,
+/// which halts the program
+fn batch_gas(cfg: &ControlFlowGraph, extra_gas: &[i64], spec_id: SpecId) -> Vec<i64> {
+    let operations = cfg.program().operations();
+    let mut gas = vec![0; operations.len()];
+    for block in cfg.blocks() {
+        let mut start = block.operations.start;
+        for index in block.operations.clone() {
+            let operation = &operations[index];
+            let defined = spec_id.includes(operation.introduced_in());
+            if defined {
+                gas[start] += operation.static_gas(spec_id);
+            }
+            gas[start] += extra_gas.get(index).copied().unwrap_or(0);
+            if !defined || reads_gas_left(operation) {
+                start = index + 1;
+            }
+        }
+    }
+    gas
+}
+
+/// Returns the runs of at least two operations whose words are kept as SSA values, in
+/// the order of the program. A run is within a basic block, and its operations after
+/// the first aren't charged any gas but their own, so they need no block of their own
+fn promoted_runs(cfg: &ControlFlowGraph, charges: &[i64], spec_id: SpecId) -> Vec<Range<usize>> {
+    let operations = cfg.program().operations();
+    let mut runs = Vec::new();
+    for block in cfg.blocks() {
+        let mut start = block.operations.start;
+        for index in block.operations.clone() {
+            let promotable = is_promotable(&operations[index], spec_id);
+            if !promotable || charges[index] > 0 {
+                if index - start > 1 {
+                    runs.push(start..index);
+                }
+                start = if promotable { index } else { index + 1 };
+            }
+        }
+        if block.operations.end - start > 1 {
+            runs.push(start..block.operations.end);
+        }
+    }
+    runs
+}
+
+/// Returns whether the operation depends on the gas left when it runs: GAS pushes it,
+/// SSTORE checks it's above the sentry, the calls and creations pass it on, and so do
+/// CALLF and JUMPF to the code section they run
+fn reads_gas_left(operation: &Operation) -> bool {
+    matches!(
+        operation,
+        Operation::Gas
+            | Operation::Sstore
+            | Operation::Call
+            | Operation::Callcode
+            | Operation::Delegatecall
+            | Operation::Staticcall
+            | Operation::Create
+            | Operation::Create2
+            | Operation::Callf(..)
+            | Operation::Jumpf(..)
+    )
+}
+
+/// Appends to the block the values reporting the opcode at `pc`: the program counter,
+/// the opcode, the gas left, and the pointers to the bottom and top of the stack
+fn generate_step_values<'a>(
+    context: &'a MeliorContext,
+    block: &'a Block,
+    pc: usize,
+    opcode: u8,
+) -> Result<[Value<'a, 'a>; 5], CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+    let uint8 = IntegerType::new(context, 8).into();
+    let uint64 = IntegerType::new(context, 64).into();
+
+    let pc = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64, pc as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let opcode = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8, opcode as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let gas = get_gas_counter(context, block)?;
+
+    let mut stack_pointers = Vec::new();
+    for global in [STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL] {
+        let global_ptr = block
+            .append_operation(llvm_mlir::addressof(context, global, ptr_type, location))
+            .result(0)?;
+        let stack_pointer = block
+            .append_operation(llvm::load(
+                context,
+                global_ptr.into(),
+                ptr_type,
+                location,
+                LoadStoreOptions::default(),
+            ))
+            .result(0)?
+            .into();
+        stack_pointers.push(stack_pointer);
+    }
+
+    Ok([pc, opcode, gas, stack_pointers[0], stack_pointers[1]])
+}
+
 fn generate_gas_counter_setup_code<'c>(
     context: &'c MeliorContext,
     module: &'c MeliorModule,
@@ -330,10 +1299,63 @@ fn generate_gas_counter_setup_code<'c>(
     Ok(())
 }
 
+/// Sets up the depth of the return stack of the EOF code sections, which starts empty
+fn generate_return_stack_setup_code<'c>(
+    context: &'c MeliorContext,
+    module: &'c MeliorModule,
+    block: &'c Block<'c>,
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+    let uint32 = IntegerType::new(context, 32).into();
+
+    let body = module.body();
+    let res = body.append_operation(llvm_mlir::global(
+        context,
+        RETURN_STACK_DEPTH_GLOBAL,
+        uint32,
+        location,
+    ));
+    assert!(res.verify());
+
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32, 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let depth_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            RETURN_STACK_DEPTH_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+
+    let res = block.append_operation(llvm::store(
+        context,
+        zero,
+        depth_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+    assert!(res.verify());
+
+    Ok(())
+}
+
+/// Sets up the stack, holding `stack.limit` words. It's allocated on the native stack,
+/// unless it faults on a guard page, where it's the one mapped for the call
 fn generate_stack_setup_code<'c>(
     context: &'c MeliorContext,
     module: &'c MeliorModule,
     block: &'c Block<'c>,
+    syscall_ctx: Value<'c, 'c>,
+    stack: &StackConfig,
 ) -> Result<(), CodegenError> {
     let location = Location::unknown(context);
     let ptr_type = pointer(context, 0);
@@ -357,25 +1379,33 @@ fn generate_stack_setup_code<'c>(
 
     let uint256 = IntegerType::new(context, 256);
 
-    // Allocate stack memory
-    let stack_size = block
-        .append_operation(arith::constant(
-            context,
-            IntegerAttribute::new(uint256.into(), MAX_STACK_SIZE as i64).into(),
-            location,
-        ))
-        .result(0)?
-        .into();
-
-    let stack_baseptr = block
-        .append_operation(llvm::alloca(
-            context,
-            stack_size,
-            ptr_type,
-            location,
-            AllocaOptions::new().elem_type(Some(TypeAttribute::new(uint256.into()))),
-        ))
-        .result(0)?;
+    let stack_baseptr: Value = match stack.overflow_check {
+        OverflowCheck::Branch => {
+            // Allocate stack memory
+            let stack_size = block
+                .append_operation(arith::constant(
+                    context,
+                    IntegerAttribute::new(uint256.into(), stack.limit as i64).into(),
+                    location,
+                ))
+                .result(0)?
+                .into();
+
+            block
+                .append_operation(llvm::alloca(
+                    context,
+                    stack_size,
+                    ptr_type,
+                    location,
+                    AllocaOptions::new().elem_type(Some(TypeAttribute::new(uint256.into()))),
+                ))
+                .result(0)?
+                .into()
+        }
+        OverflowCheck::GuardPage => {
+            syscall::mlir::get_stack_syscall(context, syscall_ctx, block, location)?
+        }
+    };
 
     // Populate the globals with the allocated stack memory
     let stack_baseptr_ptr = block
@@ -389,7 +1419,7 @@ fn generate_stack_setup_code<'c>(
 
     let res = block.append_operation(llvm::store(
         context,
-        stack_baseptr.into(),
+        stack_baseptr,
         stack_baseptr_ptr.into(),
         location,
         LoadStoreOptions::default(),
@@ -407,7 +1437,7 @@ fn generate_stack_setup_code<'c>(
 
     let res = block.append_operation(llvm::store(
         context,
-        stack_baseptr.into(),
+        stack_baseptr,
         stackptr_ptr.into(),
         location,
         LoadStoreOptions::default(),
@@ -483,25 +1513,65 @@ fn create_jumptable_landing_block(context: &MeliorContext) -> Block {
 
 /// Populate the jumptable block with a dynamic dispatch according to the
 /// received PC.
-fn populate_jumptable(op_ctx: &OperationCtx) -> Result<(), CodegenError> {
+///
+/// The PC is switched on as a 32-bit integer, once checked to be at most the offset of
+/// the last JUMPDEST. This lets LLVM lower the switch to a table lookup, or to a binary
+/// search where the JUMPDESTs are sparse, rather than comparing 256-bit integers.
+fn populate_jumptable<'c>(
+    op_ctx: &OperationCtx<'c>,
+    region: &'c Region<'c>,
+) -> Result<(), CodegenError> {
     let context = op_ctx.mlir_context;
-    let program = op_ctx.program;
     let start_block = op_ctx.jumptable_block;
 
     let location = Location::unknown(context);
     let uint256 = IntegerType::new(context, 256);
+    let uint32 = IntegerType::new(context, 32);
+
+    // Only the JUMPDESTs code was generated for are registered, as the others can't run
+    let Some(last_pc) = op_ctx.jumpdest_blocks.keys().last() else {
+        start_block.append_operation(cf::br(&op_ctx.revert_block, &[], location));
+        return Ok(());
+    };
 
     // The block receives a single argument: the value to switch on
-    let jumpdest_pcs: Vec<i64> = program
-        .operations
-        .iter()
-        .filter_map(|op| match op {
-            Operation::Jumpdest { pc } => Some(*pc as i64),
-            _ => None,
-        })
-        .collect();
+    let arg = start_block.argument(0)?.into();
 
-    let arg = start_block.argument(0).unwrap();
+    let last_pc = start_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint256.into(), *last_pc as i64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let in_range = start_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            arg,
+            last_pc,
+            location,
+        ))
+        .result(0)?
+        .into();
+    let switch_block = region.append_block(Block::new(&[]));
+    start_block.append_operation(cf::cond_br(
+        context,
+        in_range,
+        &switch_block,
+        &op_ctx.revert_block,
+        &[],
+        &[],
+        location,
+    ));
+
+    let pc = switch_block
+        .append_operation(arith::trunci(arg, uint32.into(), location))
+        .result(0)?
+        .into();
+
+    let jumpdest_pcs: Vec<i64> = op_ctx.jumpdest_blocks.keys().map(|pc| *pc as i64).collect();
 
     let case_destinations: Vec<_> = op_ctx
         .jumpdest_blocks
@@ -512,19 +1582,15 @@ fn populate_jumptable(op_ctx: &OperationCtx) -> Result<(), CodegenError> {
         })
         .collect();
 
-    let op = start_block.append_operation(
-        cf::switch(
-            context,
-            &jumpdest_pcs,
-            arg.into(),
-            uint256.into(),
-            (&op_ctx.revert_block, &[]),
-            &case_destinations,
-            location,
-        )
-        // TODO
-        .unwrap(),
-    );
+    let op = switch_block.append_operation(cf::switch(
+        context,
+        &jumpdest_pcs,
+        pc,
+        uint32.into(),
+        (&op_ctx.revert_block, &[]),
+        &case_destinations,
+        location,
+    )?);
 
     assert!(op.verify());
 
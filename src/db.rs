@@ -0,0 +1,470 @@
+//! # Storage backends for the EVM
+//!
+//! The persistent storage of the accounts is accessed by the generated code
+//! through syscalls, which forward each read and write to a [`Storage`] implementation.
+//! This allows plugging in any backend, like [`InMemoryStorage`] for testing.
+//!
+//! In the same way, the hashes of previous blocks are provided by a [`BlockHashProvider`],
+//! and other accounts are read through an [`AccountProvider`].
+//!
+//! A node or test framework can instead supply all the state through a single [`Host`].
+//! [`InMemoryDB`] is a host keeping a journal of its changes, so that the changes made
+//! by a failed call can be rolled back.
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    rc::Rc,
+};
+
+use sha3::{Digest, Keccak256};
+
+use crate::{env::Address, syscall::U256};
+
+/// A backend for the persistent storage of the accounts
+///
+/// Each account has its own storage, so slots are identified by the
+/// account's address along with the key.
+pub trait Storage: Debug {
+    /// Returns the value stored at `key` in the storage of the account at `address`.
+    /// Slots that were never written are zero.
+    fn read(&self, address: &Address, key: &U256) -> U256;
+    /// Stores `value` at `key` in the storage of the account at `address`.
+    fn write(&mut self, address: Address, key: U256, value: U256);
+    /// Zeroes all the storage of the account at `address`, as when it's deleted.
+    fn clear(&mut self, address: &Address);
+}
+
+/// A [`Storage`] kept in memory, useful for testing
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InMemoryStorage {
+    slots: HashMap<(Address, U256), U256>,
+}
+
+impl InMemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FromIterator<(Address, U256, U256)> for InMemoryStorage {
+    fn from_iter<T: IntoIterator<Item = (Address, U256, U256)>>(iter: T) -> Self {
+        let mut storage = Self::new();
+        for (address, key, value) in iter {
+            storage.write(address, key, value);
+        }
+        storage
+    }
+}
+
+impl Storage for InMemoryStorage {
+    fn read(&self, address: &Address, key: &U256) -> U256 {
+        self.slots
+            .get(&(*address, *key))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn write(&mut self, address: Address, key: U256, value: U256) {
+        // Zero is the default value, so there's no need to keep it
+        if value == U256::default() {
+            self.slots.remove(&(address, key));
+        } else {
+            self.slots.insert((address, key), value);
+        }
+    }
+
+    fn clear(&mut self, address: &Address) {
+        self.slots
+            .retain(|(slot_address, _), _| slot_address != address);
+    }
+}
+
+/// A provider for the hashes of previous blocks, used by BLOCKHASH
+///
+/// Only the hashes of the last 256 blocks are requested, as the range
+/// is checked by the generated code.
+pub trait BlockHashProvider: Debug {
+    /// Returns the hash of the block with the given number, or zero if it's unknown.
+    fn block_hash(&self, number: &U256) -> U256;
+}
+
+/// A [`BlockHashProvider`] kept in memory, useful for testing
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InMemoryBlockHashes {
+    hashes: HashMap<U256, U256>,
+}
+
+impl InMemoryBlockHashes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the hash of the block with the given number
+    pub fn insert(&mut self, number: U256, hash: U256) {
+        self.hashes.insert(number, hash);
+    }
+}
+
+impl FromIterator<(U256, U256)> for InMemoryBlockHashes {
+    fn from_iter<T: IntoIterator<Item = (U256, U256)>>(iter: T) -> Self {
+        Self {
+            hashes: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl BlockHashProvider for InMemoryBlockHashes {
+    fn block_hash(&self, number: &U256) -> U256 {
+        self.hashes.get(number).copied().unwrap_or_default()
+    }
+}
+
+/// The state of an account, as seen by the executing contract
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountInfo {
+    /// The account's balance, in wei
+    pub balance: U256,
+    /// The account's nonce
+    pub nonce: u64,
+    /// The account's code. It's empty for externally owned accounts
+    pub code: Vec<u8>,
+}
+
+impl AccountInfo {
+    /// Returns whether the account is empty, as defined in EIP-161:
+    /// it has no code, zero nonce and zero balance
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty() && self.nonce == 0 && self.balance == U256::default()
+    }
+
+    /// Returns the Keccak-256 hash of the account's code
+    pub fn code_hash(&self) -> U256 {
+        U256::from_be_bytes(Keccak256::digest(&self.code).into())
+    }
+}
+
+/// A provider for the state of the accounts, used by the EXTCODE*, CALL* and CREATE* opcodes
+pub trait AccountProvider: Debug {
+    /// Returns the account at the given address, or [`None`] if it doesn't exist.
+    fn account(&self, address: &Address) -> Option<AccountInfo>;
+    /// Sets the account at the given address, creating it if it doesn't exist.
+    /// This is used for updating balances when value is transferred,
+    /// and for deploying the code of created contracts.
+    fn set_account(&mut self, address: Address, account: AccountInfo);
+}
+
+/// An [`AccountProvider`] kept in memory, useful for testing
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InMemoryAccounts {
+    accounts: HashMap<Address, AccountInfo>,
+}
+
+impl InMemoryAccounts {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the account at the given address
+    pub fn insert(&mut self, address: Address, account: AccountInfo) {
+        self.accounts.insert(address, account);
+    }
+}
+
+impl FromIterator<(Address, AccountInfo)> for InMemoryAccounts {
+    fn from_iter<T: IntoIterator<Item = (Address, AccountInfo)>>(iter: T) -> Self {
+        Self {
+            accounts: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl AccountProvider for InMemoryAccounts {
+    fn account(&self, address: &Address) -> Option<AccountInfo> {
+        self.accounts.get(address).cloned()
+    }
+
+    fn set_account(&mut self, address: Address, account: AccountInfo) {
+        self.insert(address, account);
+    }
+}
+
+/// A single backend for all the state accessed by a program: the storage and state of
+/// the accounts, and the hashes of previous blocks
+///
+/// The generated code reaches the host through the syscalls, which are C-ABI functions
+/// forwarding each access to it. Calls and contract creations are executed by the
+/// [`SyscallContext`](crate::syscall::SyscallContext), which reads and updates the
+/// accounts involved through the host.
+/// See [`SyscallContext::with_host`](crate::syscall::SyscallContext::with_host).
+pub trait Host: Debug {
+    /// Returns the value stored at `key` in the storage of the account at `address`.
+    /// Slots that were never written are zero.
+    fn sload(&self, address: &Address, key: &U256) -> U256;
+    /// Stores `value` at `key` in the storage of the account at `address`.
+    fn sstore(&mut self, address: Address, key: U256, value: U256);
+    /// Zeroes all the storage of the account at `address`, as when it's deleted.
+    fn clear_storage(&mut self, address: &Address);
+    /// Returns the account at the given address, or [`None`] if it doesn't exist.
+    fn account(&self, address: &Address) -> Option<AccountInfo>;
+    /// Sets the account at the given address, creating it if it doesn't exist.
+    fn set_account(&mut self, address: Address, account: AccountInfo);
+    /// Returns the hash of the block with the given number, or zero if it's unknown.
+    fn block_hash(&self, number: &U256) -> U256;
+
+    /// Returns the balance of the account at the given address, which is zero if it
+    /// doesn't exist.
+    fn balance(&self, address: &Address) -> U256 {
+        self.account(address)
+            .map(|account| account.balance)
+            .unwrap_or_default()
+    }
+
+    /// Returns the code of the account at the given address, which is empty if it
+    /// doesn't exist.
+    fn code(&self, address: &Address) -> Vec<u8> {
+        self.account(address)
+            .map(|account| account.code)
+            .unwrap_or_default()
+    }
+
+    /// Marks the current state, so that the changes made from now on can be rolled back
+    /// with [`Host::revert`]. Returns [`None`] if the host doesn't keep a journal of its
+    /// changes, which is the default.
+    fn checkpoint(&mut self) -> Option<Checkpoint> {
+        None
+    }
+
+    /// Rolls back the changes made since `checkpoint` was taken, discarding the
+    /// checkpoints taken after it.
+    fn revert(&mut self, _checkpoint: Checkpoint) {}
+}
+
+/// A point in the journal of a [`Host`], which its state can be rolled back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Checkpoint(usize);
+
+/// A change made to an [`InMemoryDB`], holding what's needed for undoing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JournalEntry {
+    /// The account at `address` was set, replacing `previous`
+    AccountChanged {
+        address: Address,
+        previous: Option<StoredAccount>,
+    },
+    /// The slot at `key` in the storage of the account at `address` was written,
+    /// replacing `previous`
+    StorageChanged {
+        address: Address,
+        key: U256,
+        previous: U256,
+    },
+}
+
+/// An account as kept by an [`InMemoryDB`], which stores the code apart, by its hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct StoredAccount {
+    balance: U256,
+    nonce: u64,
+    code_hash: U256,
+}
+
+/// A [`Host`] keeping the accounts, storage and code in memory, along with a journal
+/// of the changes made to them
+///
+/// The journal allows taking a [`Checkpoint`] before running a call, and rolling back
+/// everything the call changed if it fails. Changes are journaled until
+/// [`InMemoryDB::commit`] is called, which makes them final.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct InMemoryDB {
+    accounts: HashMap<Address, StoredAccount>,
+    storage: InMemoryStorage,
+    /// The code of the accounts, by its hash
+    contracts: HashMap<U256, Vec<u8>>,
+    block_hashes: InMemoryBlockHashes,
+    journal: Vec<JournalEntry>,
+}
+
+impl InMemoryDB {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the account at the given address, without journaling the change
+    pub fn insert_account(&mut self, address: Address, account: AccountInfo) {
+        let account = self.store_account(account);
+        self.accounts.insert(address, account);
+    }
+
+    /// Sets the value at `key` in the storage of the account at `address`,
+    /// without journaling the change
+    pub fn insert_storage(&mut self, address: Address, key: U256, value: U256) {
+        self.storage.write(address, key, value);
+    }
+
+    /// Sets the hash of the block with the given number
+    pub fn insert_block_hash(&mut self, number: U256, hash: U256) {
+        self.block_hashes.insert(number, hash);
+    }
+
+    /// Returns the code with the given hash, if any account has had it
+    pub fn code_by_hash(&self, code_hash: &U256) -> Option<&[u8]> {
+        self.contracts.get(code_hash).map(Vec::as_slice)
+    }
+
+    /// Makes all the changes made so far final, discarding the journal.
+    /// The checkpoints taken until now can't be reverted to anymore.
+    pub fn commit(&mut self) {
+        self.journal.clear();
+    }
+
+    /// Returns the addresses of the accounts set since the last [`Self::commit`].
+    /// The changes that were reverted aren't included
+    pub fn changed_accounts(&self) -> HashSet<Address> {
+        self.journal
+            .iter()
+            .filter_map(|entry| match entry {
+                JournalEntry::AccountChanged { address, .. } => Some(*address),
+                JournalEntry::StorageChanged { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Returns the storage slots written since the last [`Self::commit`], by address and key.
+    /// The changes that were reverted aren't included
+    pub fn changed_storage(&self) -> HashSet<(Address, U256)> {
+        self.journal
+            .iter()
+            .filter_map(|entry| match entry {
+                JournalEntry::StorageChanged { address, key, .. } => Some((*address, *key)),
+                JournalEntry::AccountChanged { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Keeps the account's code apart, returning the account to store
+    fn store_account(&mut self, account: AccountInfo) -> StoredAccount {
+        let code_hash = account.code_hash();
+        self.contracts.entry(code_hash).or_insert(account.code);
+        StoredAccount {
+            balance: account.balance,
+            nonce: account.nonce,
+            code_hash,
+        }
+    }
+}
+
+impl Host for InMemoryDB {
+    fn sload(&self, address: &Address, key: &U256) -> U256 {
+        self.storage.read(address, key)
+    }
+
+    fn sstore(&mut self, address: Address, key: U256, value: U256) {
+        let previous = self.storage.read(&address, &key);
+        self.journal.push(JournalEntry::StorageChanged {
+            address,
+            key,
+            previous,
+        });
+        self.storage.write(address, key, value);
+    }
+
+    fn clear_storage(&mut self, address: &Address) {
+        for ((slot_address, key), value) in &self.storage.slots {
+            if slot_address == address {
+                self.journal.push(JournalEntry::StorageChanged {
+                    address: *address,
+                    key: *key,
+                    previous: *value,
+                });
+            }
+        }
+        self.storage.clear(address);
+    }
+
+    fn account(&self, address: &Address) -> Option<AccountInfo> {
+        let account = self.accounts.get(address)?;
+        Some(AccountInfo {
+            balance: account.balance,
+            nonce: account.nonce,
+            code: self.contracts[&account.code_hash].clone(),
+        })
+    }
+
+    fn set_account(&mut self, address: Address, account: AccountInfo) {
+        let account = self.store_account(account);
+        let previous = self.accounts.insert(address, account);
+        self.journal
+            .push(JournalEntry::AccountChanged { address, previous });
+    }
+
+    fn block_hash(&self, number: &U256) -> U256 {
+        self.block_hashes.block_hash(number)
+    }
+
+    fn checkpoint(&mut self) -> Option<Checkpoint> {
+        Some(Checkpoint(self.journal.len()))
+    }
+
+    fn revert(&mut self, checkpoint: Checkpoint) {
+        // Committed changes are final, so they're kept
+        let start = checkpoint.0.min(self.journal.len());
+        // The changes are undone from the latest one
+        for entry in self.journal.drain(start..).rev() {
+            match entry {
+                JournalEntry::AccountChanged {
+                    address,
+                    previous: Some(account),
+                } => {
+                    self.accounts.insert(address, account);
+                }
+                JournalEntry::AccountChanged {
+                    address,
+                    previous: None,
+                } => {
+                    self.accounts.remove(&address);
+                }
+                JournalEntry::StorageChanged {
+                    address,
+                    key,
+                    previous,
+                } => self.storage.write(address, key, previous),
+            }
+        }
+    }
+}
+
+/// A [`Host`] shared by the [`Storage`], [`AccountProvider`] and [`BlockHashProvider`]
+/// of a context, which forward to it
+#[derive(Debug)]
+pub(crate) struct SharedHost<H: Host>(pub(crate) Rc<RefCell<H>>);
+
+impl<H: Host> Storage for SharedHost<H> {
+    fn read(&self, address: &Address, key: &U256) -> U256 {
+        self.0.borrow().sload(address, key)
+    }
+
+    fn write(&mut self, address: Address, key: U256, value: U256) {
+        self.0.borrow_mut().sstore(address, key, value);
+    }
+
+    fn clear(&mut self, address: &Address) {
+        self.0.borrow_mut().clear_storage(address);
+    }
+}
+
+impl<H: Host> AccountProvider for SharedHost<H> {
+    fn account(&self, address: &Address) -> Option<AccountInfo> {
+        self.0.borrow().account(address)
+    }
+
+    fn set_account(&mut self, address: Address, account: AccountInfo) {
+        self.0.borrow_mut().set_account(address, account);
+    }
+}
+
+impl<H: Host> BlockHashProvider for SharedHost<H> {
+    fn block_hash(&self, number: &U256) -> U256 {
+        self.0.borrow().block_hash(number)
+    }
+}
@@ -0,0 +1,178 @@
+//! # Step debugger
+//!
+//! A [`Debugger`] is an [`Inspector`] stopping before each opcode of an instrumented
+//! program, and reading commands until told to go on. While stopped, the stack,
+//! memory and storage of the call can be inspected.
+//!
+//! The commands are read from any [`BufRead`], and everything is written to any
+//! [`Write`], so the debugger can run interactively on the terminal
+//! (see `evm-mlir debug <path>`) or be driven by a script.
+use std::{
+    fmt,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    db::Storage,
+    inspector::{CallInputs, CallOutcome, Inspector},
+    syscall::{SyscallContext, U256},
+    trace::TraceStep,
+};
+
+const PROMPT: &str = "(evm-mlir) ";
+
+const HELP: &str = "\
+Commands:
+  step, s            execute the next opcode (also an empty line)
+  continue, c        run until the program halts
+  stack              print the stack, from the top
+  memory, mem        print the memory, in 32-byte words
+  storage, sload KEY print the value at KEY in the storage of the executing account
+  help, h            print this message";
+
+/// An [`Inspector`] stopping before each opcode to read commands
+pub struct Debugger<R, W> {
+    input: R,
+    output: W,
+    /// Whether to stop before the next opcode
+    stepping: bool,
+}
+
+impl<R, W> fmt::Debug for Debugger<R, W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debugger")
+            .field("stepping", &self.stepping)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    /// Creates a debugger reading commands from `input` and writing to `output`.
+    /// It stops before the first opcode
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            stepping: true,
+        }
+    }
+
+    /// Returns where the debugger writes to
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    /// Reads and runs commands until one of them resumes the execution.
+    /// Running out of commands resumes it until the program halts
+    fn prompt(&mut self, step: &TraceStep, context: &SyscallContext) -> io::Result<()> {
+        writeln!(
+            self.output,
+            "pc {}: {} (gas: {}, depth: {})",
+            step.pc,
+            step.op_name(),
+            step.gas,
+            step.depth
+        )?;
+        loop {
+            write!(self.output, "{PROMPT}")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                self.stepping = false;
+                return Ok(());
+            }
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None | Some("step" | "s") => return Ok(()),
+                Some("continue" | "c") => {
+                    self.stepping = false;
+                    return Ok(());
+                }
+                Some("stack") => self.print_stack(step)?,
+                Some("memory" | "mem") => self.print_memory(context)?,
+                Some("storage" | "sload") => match words.next().and_then(parse_word) {
+                    Some(key) => {
+                        let address = context.env().call.address;
+                        let value = context.storage().read(&address, &key);
+                        writeln!(self.output, "{value:#x}")?;
+                    }
+                    None => writeln!(self.output, "usage: storage KEY")?,
+                },
+                Some("help" | "h") => writeln!(self.output, "{HELP}")?,
+                Some(command) => writeln!(self.output, "unknown command `{command}`, see `help`")?,
+            }
+        }
+    }
+
+    fn print_stack(&mut self, step: &TraceStep) -> io::Result<()> {
+        if step.stack.is_empty() {
+            return writeln!(self.output, "empty stack");
+        }
+        for (index, value) in step.stack.iter().rev().enumerate() {
+            writeln!(self.output, "{index}: {value:#x}")?;
+        }
+        Ok(())
+    }
+
+    fn print_memory(&mut self, context: &SyscallContext) -> io::Result<()> {
+        let memory = context.memory();
+        if memory.is_empty() {
+            return writeln!(self.output, "empty memory");
+        }
+        for (index, word) in memory.chunks(32).enumerate() {
+            let hex: String = word.iter().map(|byte| format!("{byte:02x}")).collect();
+            writeln!(self.output, "{:#06x}: {hex}", index * 32)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> Inspector for Debugger<R, W> {
+    fn step(&mut self, step: &TraceStep, context: &SyscallContext) {
+        // Failing to read commands or to write leaves the program running
+        if self.stepping && self.prompt(step, context).is_err() {
+            self.stepping = false;
+        }
+    }
+
+    fn call(&mut self, inputs: &CallInputs, _context: &SyscallContext) {
+        if self.stepping {
+            let address: String = inputs.address.iter().map(|b| format!("{b:02x}")).collect();
+            let _ = writeln!(self.output, "entering call to 0x{address}");
+        }
+    }
+
+    fn call_end(&mut self, _inputs: &CallInputs, outcome: &CallOutcome, _context: &SyscallContext) {
+        if self.stepping {
+            let result = if outcome.success {
+                "succeeded"
+            } else {
+                "failed"
+            };
+            let _ = writeln!(
+                self.output,
+                "call {result}, leaving {} gas",
+                outcome.gas_left
+            );
+        }
+    }
+}
+
+/// Parses a 256-bit word, given in decimal or as hex with a `0x` prefix
+fn parse_word(word: &str) -> Option<U256> {
+    let Some(hex) = word.strip_prefix("0x") else {
+        return word.parse::<u128>().ok().map(U256::from);
+    };
+    if hex.is_empty() || hex.len() > 64 {
+        return None;
+    }
+    let (hi, lo) = hex.split_at(hex.len().saturating_sub(32));
+    Some(U256 {
+        hi: if hi.is_empty() {
+            0
+        } else {
+            u128::from_str_radix(hi, 16).ok()?
+        },
+        lo: u128::from_str_radix(lo, 16).ok()?,
+    })
+}
@@ -0,0 +1,429 @@
+//! # Differential execution against revm
+//!
+//! A [`DifferentialTest`] runs the same transaction, on the same state, through revm and
+//! through the code generated by this compiler, comparing how both went: whether they
+//! succeeded, their output, the gas used, the events emitted and the changes made to the
+//! state, down to the [`StateDiff`] of each. The first difference found is returned as a
+//! [`Divergence`], along with the EIP-3155 trace of the compiled execution for tracking
+//! it down.
+//!
+//! This is only meant for testing the compiler, and is enabled by the `differential`
+//! feature. Note that revm rejects transactions going over the block's gas limit, and
+//! makes up the hashes of the blocks not given in [`DifferentialTest::block_hashes`].
+use std::{cell::RefCell, collections::BTreeSet, convert::Infallible, fmt::Debug, io, rc::Rc};
+
+use revm::{
+    db::{CacheDB, EmptyDB},
+    primitives::{
+        Account, AccountInfo as RevmAccountInfo, Address as RevmAddress, Bytecode, Bytes, EVMError,
+        Env as RevmEnv, ExecutionResult as RevmExecutionResult, ResultAndState,
+        SpecId as RevmSpecId, TxKind, B256, U256 as RevmU256,
+    },
+    Evm,
+};
+use thiserror::Error;
+
+use crate::{
+    db::{AccountInfo, Host, InMemoryDB},
+    env::{Address, Env, TxEnv},
+    revm_db::{from_revm_u256, to_revm_u256},
+    spec::SpecId,
+    state_diff::StateDiff,
+    syscall::{Log, SyscallContext, U256},
+    trace::{write_trace, TraceStep},
+};
+
+/// A transaction to run through both revm and the compiled code, along with the
+/// state it runs on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DifferentialTest {
+    /// The environment, including the transaction to run
+    pub env: Env,
+    /// The accounts existing before the transaction
+    pub accounts: Vec<(Address, AccountInfo)>,
+    /// The storage before the transaction, as the address, key and value of each slot
+    pub storage: Vec<(Address, U256, U256)>,
+    /// The hashes of the previous blocks, by number
+    pub block_hashes: Vec<(U256, U256)>,
+}
+
+impl DifferentialTest {
+    /// The sender of the transactions created by [`Self::call`]
+    pub const SENDER: Address = [0x10; 20];
+    /// The contract called by the transactions created by [`Self::call`]
+    pub const CONTRACT: Address = [0x20; 20];
+
+    /// Returns a test calling a contract with the given code and calldata, with all the
+    /// other accounts empty. The gas is free, and the block's gas limit is the
+    /// transaction's
+    pub fn call(code: Vec<u8>, calldata: Vec<u8>, gas_limit: u64) -> Self {
+        let mut env = Env::default();
+        env.block.gas_limit = U256::from(gas_limit as u128);
+        env.tx = TxEnv {
+            origin: Self::SENDER,
+            to: Some(Self::CONTRACT),
+            data: calldata,
+            gas_limit,
+            ..Default::default()
+        };
+        let contract = AccountInfo {
+            code,
+            ..Default::default()
+        };
+        Self {
+            env,
+            accounts: vec![(Self::CONTRACT, contract)],
+            ..Default::default()
+        }
+    }
+
+    /// Runs the transaction through revm and through the compiled code, each on its own
+    /// copy of the state, returning the first difference found between them
+    pub fn run(&self) -> Result<(), Divergence> {
+        let pre_state = self.in_memory_db();
+        let host = Rc::new(RefCell::new(pre_state.clone()));
+        let mut context = SyscallContext::with_host(self.env.clone(), Vec::new(), host.clone());
+        context.enable_tracing();
+        let mlir_result = context.transact();
+
+        let diverged = |kind, revm: &dyn Debug, mlir: &dyn Debug| Divergence {
+            kind,
+            revm: format!("{revm:?}"),
+            mlir: format!("{mlir:?}"),
+            trace: context.trace().to_vec(),
+        };
+
+        let (mlir_result, revm_result) = match (mlir_result, self.run_revm()) {
+            (Ok(mlir_result), Ok(revm_result)) => (mlir_result, revm_result),
+            // Both rejected the transaction, maybe for different reasons
+            (Err(_), Err(_)) => return Ok(()),
+            (mlir_result, revm_result) => {
+                return Err(diverged(
+                    DivergenceKind::Validity,
+                    &revm_result.map(|_| ()),
+                    &mlir_result.map(|_| ()),
+                ))
+            }
+        };
+
+        let (revm_output, revm_logs) = match &revm_result.result {
+            RevmExecutionResult::Success { output, logs, .. } => {
+                let logs = logs
+                    .iter()
+                    .map(|log| Log {
+                        address: log.address.into(),
+                        data: log.data.data.to_vec(),
+                        topics: log
+                            .data
+                            .topics()
+                            .iter()
+                            .map(|topic| U256::from_be_bytes(topic.0))
+                            .collect(),
+                    })
+                    .collect();
+                (output.data().to_vec(), logs)
+            }
+            RevmExecutionResult::Revert { output, .. } => (output.to_vec(), Vec::new()),
+            RevmExecutionResult::Halt { .. } => (Vec::new(), Vec::new()),
+        };
+        let revm_success = revm_result.result.is_success();
+        if revm_success != mlir_result.success {
+            return Err(diverged(
+                DivergenceKind::Success,
+                &revm_result.result,
+                &mlir_result,
+            ));
+        }
+        if revm_output != mlir_result.output {
+            return Err(diverged(
+                DivergenceKind::Output,
+                &revm_output,
+                &mlir_result.output,
+            ));
+        }
+        let revm_gas_used = revm_result.result.gas_used();
+        if revm_gas_used != mlir_result.gas_used {
+            return Err(diverged(
+                DivergenceKind::GasUsed,
+                &revm_gas_used,
+                &mlir_result.gas_used,
+            ));
+        }
+        if revm_logs != context.logs() {
+            return Err(diverged(DivergenceKind::Logs, &revm_logs, &context.logs()));
+        }
+
+        // The state is compared on everything changed by either of them
+        let host = host.borrow();
+        let mut addresses: BTreeSet<Address> = host.changed_accounts().into_iter().collect();
+        addresses.extend(
+            revm_result
+                .state
+                .iter()
+                .filter(|(_, account)| account.is_touched())
+                .map(|(address, _)| address.0 .0),
+        );
+        for address in addresses {
+            let revm_account = revm_account(&revm_result, &pre_state, &address);
+            let mlir_account = host.account(&address).unwrap_or_default();
+            if revm_account != mlir_account {
+                return Err(diverged(
+                    DivergenceKind::Account(address),
+                    &revm_account,
+                    &mlir_account,
+                ));
+            }
+        }
+
+        let mut slots: Vec<(Address, U256)> = host.changed_storage().into_iter().collect();
+        for (address, account) in &revm_result.state {
+            slots.extend(
+                account
+                    .storage
+                    .keys()
+                    .map(|key| (address.0 .0, from_revm_u256(key))),
+            );
+        }
+        slots.sort_by_key(|(address, key)| (*address, key.to_be_bytes()));
+        slots.dedup();
+        for (address, key) in slots {
+            let revm_value = revm_storage(&revm_result, &pre_state, &address, &key);
+            let mlir_value = host.sload(&address, &key);
+            if revm_value != mlir_value {
+                return Err(diverged(
+                    DivergenceKind::Storage(address, key),
+                    &revm_value,
+                    &mlir_value,
+                ));
+            }
+        }
+
+        let revm_state_diff = revm_state_diff(&revm_result, &pre_state);
+        if revm_state_diff != mlir_result.state_diff {
+            return Err(diverged(
+                DivergenceKind::StateDiff,
+                &revm_state_diff,
+                &mlir_result.state_diff,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the state before the transaction, as held by the compiled code
+    fn in_memory_db(&self) -> InMemoryDB {
+        let mut db = InMemoryDB::new();
+        for (address, account) in &self.accounts {
+            db.insert_account(*address, account.clone());
+        }
+        for (address, key, value) in &self.storage {
+            db.insert_storage(*address, *key, *value);
+        }
+        for (number, hash) in &self.block_hashes {
+            db.insert_block_hash(*number, *hash);
+        }
+        db
+    }
+
+    fn run_revm(&self) -> Result<ResultAndState, EVMError<Infallible>> {
+        let mut db = CacheDB::new(EmptyDB::default());
+        for (address, account) in &self.accounts {
+            let code = Bytecode::new_raw(Bytes::from(account.code.clone()));
+            let info = RevmAccountInfo {
+                balance: to_revm_u256(&account.balance),
+                nonce: account.nonce,
+                code_hash: code.hash_slow(),
+                code: Some(code),
+            };
+            db.insert_account_info(RevmAddress::from(*address), info);
+        }
+        for (address, key, value) in &self.storage {
+            db.insert_account_storage(
+                RevmAddress::from(*address),
+                to_revm_u256(key),
+                to_revm_u256(value),
+            )
+            .map_err(EVMError::Database)?;
+        }
+        for (number, hash) in &self.block_hashes {
+            db.block_hashes
+                .insert(to_revm_u256(number), B256::from(hash.to_be_bytes()));
+        }
+
+        let mut evm = Evm::builder()
+            .with_db(db)
+            .with_env(Box::new(revm_env(&self.env)))
+            .with_spec_id(revm_spec_id(self.env.spec_id))
+            .build();
+        evm.transact()
+    }
+}
+
+/// What the executions of a [`DifferentialTest`] differed on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivergenceKind {
+    /// Only one of them rejected the transaction
+    Validity,
+    /// Only one of them succeeded
+    Success,
+    Output,
+    /// The gas paid by the sender, net of the refund
+    GasUsed,
+    Logs,
+    /// The state of the account at the given address, after the transaction
+    Account(Address),
+    /// The value of a storage slot after the transaction, by address and key
+    Storage(Address, U256),
+    /// The changes to the state. Once the accounts and storage agree, they can only
+    /// differ on the accounts created or destroyed
+    StateDiff,
+}
+
+/// The first difference found between the executions of a [`DifferentialTest`]
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("executions diverged on {kind:?}: revm gave {revm}, but evm_mlir gave {mlir}")]
+pub struct Divergence {
+    pub kind: DivergenceKind,
+    /// What revm gave, formatted for debugging
+    pub revm: String,
+    /// What the compiled code gave, formatted for debugging
+    pub mlir: String,
+    /// The steps executed by the compiled code, including the ones of nested calls
+    pub trace: Vec<TraceStep>,
+}
+
+impl Divergence {
+    /// Writes what diverged, followed by the trace of the compiled code as EIP-3155 JSON lines
+    pub fn write_report(&self, mut writer: impl io::Write) -> io::Result<()> {
+        writeln!(writer, "{self}")?;
+        write_trace(&self.trace, writer)
+    }
+}
+
+/// Returns the account at `address` after revm ran the transaction.
+/// Accounts revm didn't touch are left as they were before it
+fn revm_account(result: &ResultAndState, pre_state: &InMemoryDB, address: &Address) -> AccountInfo {
+    let pre_account = pre_state.account(address).unwrap_or_default();
+    match result.state.get(&RevmAddress::from(*address)) {
+        Some(account) if account.is_selfdestructed() => AccountInfo::default(),
+        Some(account) if account.is_touched() => AccountInfo {
+            balance: from_revm_u256(&account.info.balance),
+            nonce: account.info.nonce,
+            code: account
+                .info
+                .code
+                .as_ref()
+                .map(|code| code.original_bytes().to_vec())
+                .unwrap_or(pre_account.code),
+        },
+        _ => pre_account,
+    }
+}
+
+/// Returns the changes revm made to the state, as the compiled code reports them
+fn revm_state_diff(result: &ResultAndState, pre_state: &InMemoryDB) -> StateDiff {
+    let mut diff = StateDiff::default();
+    for (address, account) in &result.state {
+        let address = address.0 .0;
+        let before = pre_state.account(&address).unwrap_or_default();
+        let after = revm_account(result, pre_state, &address);
+        diff.record_account(address, &before, &after);
+        for key in account.storage.keys() {
+            let key = from_revm_u256(key);
+            let value = revm_storage(result, pre_state, &address, &key);
+            diff.record_storage(address, key, pre_state.sload(&address, &key), value);
+        }
+        let created = account.is_created() && !after.is_empty();
+        let destroyed = account.is_selfdestructed() && !before.is_empty() && after.is_empty();
+        diff.record_lifecycle(address, created, destroyed);
+    }
+    diff
+}
+
+/// Returns the value at `key` in the storage of the account at `address`, after revm
+/// ran the transaction
+fn revm_storage(
+    result: &ResultAndState,
+    pre_state: &InMemoryDB,
+    address: &Address,
+    key: &U256,
+) -> U256 {
+    let account: Option<&Account> = result.state.get(&RevmAddress::from(*address));
+    match account {
+        Some(account) if account.is_selfdestructed() => U256::default(),
+        Some(account) => account
+            .storage
+            .get(&to_revm_u256(key))
+            .map(|slot| from_revm_u256(&slot.present_value))
+            .unwrap_or_else(|| pre_state.sload(address, key)),
+        None => pre_state.sload(address, key),
+    }
+}
+
+fn revm_env(env: &Env) -> RevmEnv {
+    let mut revm_env = RevmEnv::default();
+    revm_env.cfg.chain_id = env.chain_id;
+
+    let block = &mut revm_env.block;
+    block.number = to_revm_u256(&env.block.number);
+    block.coinbase = RevmAddress::from(env.block.coinbase);
+    block.timestamp = to_revm_u256(&env.block.timestamp);
+    block.gas_limit = to_revm_u256(&env.block.gas_limit);
+    block.basefee = to_revm_u256(&env.block.basefee);
+    block.prevrandao = Some(B256::from(env.block.prevrandao.to_be_bytes()));
+
+    let tx = &mut revm_env.tx;
+    tx.caller = RevmAddress::from(env.tx.origin);
+    tx.transact_to = match env.tx.to {
+        Some(to) => TxKind::Call(RevmAddress::from(to)),
+        None => TxKind::Create,
+    };
+    tx.value = to_revm_u256(&env.tx.value);
+    tx.data = Bytes::from(env.tx.data.clone());
+    tx.gas_limit = env.tx.gas_limit;
+    // revm takes the max fee of a fee market transaction as its gas price
+    tx.gas_price = to_revm_u256(&env.tx.max_fee_per_gas.unwrap_or(env.tx.gas_price));
+    tx.gas_priority_fee = env
+        .tx
+        .max_fee_per_gas
+        .map(|_| to_revm_u256(&env.tx.max_priority_fee_per_gas));
+    tx.access_list = env
+        .tx
+        .access_list
+        .iter()
+        .map(|(address, keys)| {
+            let keys = keys.iter().map(to_revm_u256).collect();
+            (RevmAddress::from(*address), keys)
+        })
+        .collect();
+    tx.blob_hashes = env
+        .tx
+        .blob_hashes
+        .iter()
+        .map(|hash| B256::from(hash.to_be_bytes()))
+        .collect();
+    if !tx.blob_hashes.is_empty() {
+        // The blob gas isn't charged by the compiled code, so it's made as cheap as possible
+        tx.max_fee_per_blob_gas = Some(RevmU256::from(1));
+    }
+    revm_env
+}
+
+fn revm_spec_id(spec_id: SpecId) -> RevmSpecId {
+    match spec_id {
+        SpecId::Frontier => RevmSpecId::FRONTIER,
+        SpecId::Homestead => RevmSpecId::HOMESTEAD,
+        SpecId::TangerineWhistle => RevmSpecId::TANGERINE,
+        SpecId::SpuriousDragon => RevmSpecId::SPURIOUS_DRAGON,
+        SpecId::Byzantium => RevmSpecId::BYZANTIUM,
+        SpecId::Constantinople => RevmSpecId::CONSTANTINOPLE,
+        SpecId::Petersburg => RevmSpecId::PETERSBURG,
+        SpecId::Istanbul => RevmSpecId::ISTANBUL,
+        SpecId::Berlin => RevmSpecId::BERLIN,
+        SpecId::London => RevmSpecId::LONDON,
+        SpecId::Merge => RevmSpecId::MERGE,
+        SpecId::Shanghai => RevmSpecId::SHANGHAI,
+        SpecId::Cancun => RevmSpecId::CANCUN,
+        // revm follows no hardfork after Prague, and only runs legacy code here
+        SpecId::Prague | SpecId::Osaka => RevmSpecId::PRAGUE,
+    }
+}
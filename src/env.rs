@@ -0,0 +1,137 @@
+//! # Execution environment
+//!
+//! The values a program can query about the context it's executed in, like the
+//! sender of the call or the transaction's origin. The generated code reads them
+//! through syscalls, from the [`Env`] held in the
+//! [`SyscallContext`](crate::syscall::SyscallContext).
+use crate::{constants::gas_cost, spec::SpecId, syscall::U256};
+
+/// A 20-byte account address
+pub type Address = [u8; 20];
+
+/// The environment in which a program is executed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Env {
+    /// The hardfork whose rules the execution follows
+    pub spec_id: SpecId,
+    /// The ID of the chain the program is executed in, returned by CHAINID
+    pub chain_id: u64,
+    /// The block the transaction is included in
+    pub block: BlockEnv,
+    /// The transaction that started the execution
+    pub tx: TxEnv,
+    /// The call being executed
+    pub call: CallEnv,
+}
+
+/// Information about the block being executed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BlockEnv {
+    /// The block's number, returned by NUMBER
+    pub number: U256,
+    /// The address receiving the block's fees, returned by COINBASE
+    pub coinbase: Address,
+    /// The block's timestamp, in seconds since the Unix epoch, returned by TIMESTAMP
+    pub timestamp: U256,
+    /// The block's gas limit, returned by GASLIMIT
+    pub gas_limit: U256,
+    /// The block's base fee per gas (EIP-1559), returned by BASEFEE
+    pub basefee: U256,
+    /// The randomness beacon output of the previous block (EIP-4399), returned by PREVRANDAO
+    pub prevrandao: U256,
+    /// The block's base fee per blob gas (EIP-4844), returned by BLOBBASEFEE
+    pub blob_basefee: U256,
+}
+
+/// Information about the transaction being executed
+///
+/// Only [`Self::origin`] and [`Self::blob_hashes`] are read by the generated code. The rest
+/// are used when the whole transaction is executed with
+/// [`SyscallContext::transact`](crate::syscall::SyscallContext::transact).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TxEnv {
+    /// The sender of the transaction, returned by ORIGIN
+    pub origin: Address,
+    /// The versioned hashes of the transaction's blobs (EIP-4844), returned by BLOBHASH
+    pub blob_hashes: Vec<U256>,
+    /// The account called by the transaction, or [`None`] if it creates a contract
+    pub to: Option<Address>,
+    /// The value transferred by the transaction, in wei
+    pub value: U256,
+    /// The calldata of the call, or the initcode of the contract being created
+    pub data: Vec<u8>,
+    /// The maximum gas the transaction can use, including its intrinsic gas
+    pub gas_limit: u64,
+    /// The price paid for each unit of gas by a legacy transaction, in wei
+    pub gas_price: U256,
+    /// The most paid for each unit of gas by a fee market transaction (EIP-1559), base fee
+    /// included, or [`None`] for a legacy transaction, which pays [`Self::gas_price`]
+    pub max_fee_per_gas: Option<U256>,
+    /// The most paid to the block's coinbase for each unit of gas on top of the base fee,
+    /// by a fee market transaction
+    pub max_priority_fee_per_gas: U256,
+    /// The accounts and storage slots the transaction declares it accesses (EIP-2930)
+    pub access_list: Vec<(Address, Vec<U256>)>,
+}
+
+impl TxEnv {
+    /// Returns the price actually paid for each unit of gas, returned by GASPRICE. A fee
+    /// market transaction pays the base fee plus its priority fee, up to its max fee
+    pub fn effective_gas_price(&self, basefee: &U256) -> U256 {
+        match self.max_fee_per_gas {
+            Some(max_fee) => basefee
+                .checked_add(&self.max_priority_fee_per_gas)
+                .map_or(max_fee, |price| price.min(max_fee)),
+            None => self.gas_price,
+        }
+    }
+
+    /// Returns the gas charged before executing any code: a base cost, plus a cost for
+    /// each byte of data, cheaper since Istanbul (EIP-2028), and each entry of the access
+    /// list. Creating a contract is charged as well since Homestead (EIP-2), along with
+    /// each word of the initcode since Shanghai (EIP-3860)
+    pub fn intrinsic_gas(&self, spec_id: SpecId) -> u64 {
+        let nonzero_byte_cost = if spec_id.includes(SpecId::Istanbul) {
+            gas_cost::TX_DATA_NONZERO_BYTE
+        } else {
+            gas_cost::TX_DATA_NONZERO_BYTE_PRE_ISTANBUL
+        };
+        let data_cost: i64 = self
+            .data
+            .iter()
+            .map(|byte| match byte {
+                0 => gas_cost::TX_DATA_ZERO_BYTE,
+                _ => nonzero_byte_cost,
+            })
+            .sum();
+        let access_list_cost: i64 = self
+            .access_list
+            .iter()
+            .map(|(_, keys)| {
+                gas_cost::TX_ACCESS_LIST_ADDRESS
+                    + keys.len() as i64 * gas_cost::TX_ACCESS_LIST_STORAGE_KEY
+            })
+            .sum();
+        let create_cost = match self.to {
+            Some(_) => 0,
+            None if spec_id.includes(SpecId::Shanghai) => {
+                let initcode_words = self.data.len().div_ceil(32) as i64;
+                gas_cost::CREATE + initcode_words * gas_cost::INITCODE_WORD
+            }
+            None if spec_id.includes(SpecId::Homestead) => gas_cost::CREATE,
+            None => 0,
+        };
+        (gas_cost::TX + data_cost + access_list_cost + create_cost) as u64
+    }
+}
+
+/// Information about the call being executed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CallEnv {
+    /// The address of the executing contract, returned by ADDRESS
+    pub address: Address,
+    /// The sender of the call, returned by CALLER
+    pub caller: Address,
+    /// The value transferred with the call, in wei, returned by CALLVALUE
+    pub value: U256,
+}
@@ -0,0 +1,764 @@
+//! # EOF containers
+//!
+//! Parses and validates code in the EVM Object Format (EOF), as specified by EIP-3540 and
+//! the EIPs building on it: code validation (EIP-3670), static relative jumps (EIP-4200),
+//! functions (EIP-4750), stack validation (EIP-5450), deeper stack access (EIP-663) and
+//! the data section (EIP-7480).
+//!
+//! A container starts with a header listing the size of its sections:
+//!
+//! ```text
+//! magic (0xEF00), version (0x01)
+//! 0x01, types size (u16)
+//! 0x02, number of code sections (u16), size of each one (u16)
+//! 0x03, number of subcontainers (u16), size of each one (u32)    optional
+//! 0xFF, data size (u16)
+//! 0x00
+//! ```
+//!
+//! The sections follow it in the same order. The types section holds, for each code
+//! section, the stack items it takes, the ones it returns, or [`NON_RETURNING`], and the
+//! highest the stack grows while it runs.
+//!
+//! Validating a container checks the code can't misbehave in ways legacy code checks at
+//! runtime: each instruction is defined, its immediate is whole, each jump lands on an
+//! instruction of its section, and the stack never underflows nor overflows, having the
+//! same height whenever an instruction is reached. Since every jump is relative and
+//! known, code passing the validation has no dynamic jumps at all.
+use std::ops::RangeInclusive;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::MAX_STACK_SIZE,
+    errors::EofError,
+    program::{decode_operation, Operation, Program},
+};
+
+pub const MAGIC: [u8; 2] = [0xEF, 0x00];
+pub const VERSION: u8 = 0x01;
+/// The outputs in the type of a code section which never returns to its caller
+pub const NON_RETURNING: u8 = 0x80;
+
+const KIND_TYPES: u8 = 0x01;
+const KIND_CODE: u8 = 0x02;
+const KIND_CONTAINER: u8 = 0x03;
+const KIND_DATA: u8 = 0xFF;
+const TERMINATOR: u8 = 0x00;
+
+const MAX_CODE_SECTIONS: usize = 1024;
+const MAX_CONTAINER_SECTIONS: usize = 256;
+/// The most items a code section can take or return
+const MAX_IO: u8 = 0x7F;
+/// The most items the stack can hold while a code section runs, as declared in its type
+const MAX_STACK_HEIGHT: u16 = 1023;
+
+/// The opcodes only defined in EOF code
+pub mod opcodes {
+    pub const DATALOAD: u8 = 0xD0;
+    pub const DATALOADN: u8 = 0xD1;
+    pub const DATASIZE: u8 = 0xD2;
+    pub const DATACOPY: u8 = 0xD3;
+    pub const RJUMP: u8 = 0xE0;
+    pub const RJUMPI: u8 = 0xE1;
+    pub const RJUMPV: u8 = 0xE2;
+    pub const CALLF: u8 = 0xE3;
+    pub const RETF: u8 = 0xE4;
+    pub const JUMPF: u8 = 0xE5;
+    pub const DUPN: u8 = 0xE6;
+    pub const SWAPN: u8 = 0xE7;
+    pub const EXCHANGE: u8 = 0xE8;
+    pub const EOFCREATE: u8 = 0xEC;
+    pub const RETURNCONTRACT: u8 = 0xEE;
+}
+
+use opcodes::*;
+
+/// Returns whether the bytecode is an EOF container, rather than legacy code
+pub fn is_eof(bytecode: &[u8]) -> bool {
+    bytecode.starts_with(&MAGIC)
+}
+
+/// A code section, with its type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeSection {
+    /// The stack items the section takes from its caller
+    pub inputs: u8,
+    /// The stack items the section returns to its caller, or [`NON_RETURNING`]
+    pub outputs: u8,
+    /// The most items the stack holds while the section runs, counting its inputs
+    pub max_stack_height: u16,
+    pub code: Vec<u8>,
+}
+
+impl CodeSection {
+    /// Returns whether the section returns to its caller
+    pub fn is_returning(&self) -> bool {
+        self.outputs != NON_RETURNING
+    }
+
+    /// Returns the type of the section, without its code
+    pub fn section_type(&self) -> SectionType {
+        SectionType {
+            inputs: self.inputs,
+            outputs: self.outputs,
+            max_stack_height: self.max_stack_height,
+        }
+    }
+}
+
+/// The type of a code section, which CALLF and JUMPF check the stack against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SectionType {
+    pub inputs: u8,
+    /// The stack items returned, or [`NON_RETURNING`]
+    pub outputs: u8,
+    pub max_stack_height: u16,
+}
+
+/// An EOF container, with its sections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    code_sections: Vec<CodeSection>,
+    containers: Vec<Vec<u8>>,
+    data: Vec<u8>,
+}
+
+impl Container {
+    /// Returns a container of the sections, which isn't validated
+    pub fn new(code_sections: Vec<CodeSection>, containers: Vec<Vec<u8>>, data: Vec<u8>) -> Self {
+        Self {
+            code_sections,
+            containers,
+            data,
+        }
+    }
+
+    /// Parses the container and validates its code
+    pub fn from_bytecode(bytecode: &[u8]) -> Result<Self, EofError> {
+        let container = Self::parse(bytecode)?;
+        container.validate()?;
+        Ok(container)
+    }
+
+    /// Parses the sections of the container, checking its header and types but not its
+    /// code, which [`Container::validate`] does
+    pub fn parse(bytecode: &[u8]) -> Result<Self, EofError> {
+        let mut header = Reader::new(bytecode);
+        if !is_eof(bytecode) {
+            return Err(EofError::InvalidMagic);
+        }
+        header.offset = MAGIC.len();
+        let version = header.u8()?;
+        if version != VERSION {
+            return Err(EofError::UnsupportedVersion(version));
+        }
+
+        header.expect_kind(KIND_TYPES)?;
+        let types_size = header.u16()?;
+        header.expect_kind(KIND_CODE)?;
+        let code_sizes = header.sizes(MAX_CODE_SECTIONS, Reader::u16)?;
+        if types_size != code_sizes.len() * 4 {
+            return Err(EofError::InvalidHeader(
+                "the types don't match the code sections",
+            ));
+        }
+        let container_sizes = match header.peek()? {
+            KIND_CONTAINER => {
+                header.offset += 1;
+                header.sizes(MAX_CONTAINER_SECTIONS, Reader::u32)?
+            }
+            _ => Vec::new(),
+        };
+        header.expect_kind(KIND_DATA)?;
+        let data_size = header.u16()?;
+        header.expect_kind(TERMINATOR)?;
+
+        let expected = header.offset
+            + types_size
+            + code_sizes.iter().sum::<usize>()
+            + container_sizes.iter().sum::<usize>()
+            + data_size;
+        if bytecode.len() != expected {
+            return Err(EofError::InvalidSize {
+                expected,
+                actual: bytecode.len(),
+            });
+        }
+
+        let mut body = &bytecode[header.offset..];
+        let mut take = |size: usize| {
+            let (section, rest) = body.split_at(size);
+            body = rest;
+            section
+        };
+        let types = take(types_size).to_vec();
+        let mut code_sections = Vec::with_capacity(code_sizes.len());
+        for (section, (code_size, type_)) in code_sizes.iter().zip(types.chunks(4)).enumerate() {
+            let code_section = CodeSection {
+                inputs: type_[0],
+                outputs: type_[1],
+                max_stack_height: u16::from_be_bytes([type_[2], type_[3]]),
+                code: take(*code_size).to_vec(),
+            };
+            validate_type(section, &code_section)?;
+            code_sections.push(code_section);
+        }
+        let containers = container_sizes
+            .iter()
+            .map(|size| take(*size).to_vec())
+            .collect();
+        let data = take(data_size).to_vec();
+
+        Ok(Self {
+            code_sections,
+            containers,
+            data,
+        })
+    }
+
+    /// Validates the code of each section: its instructions and their immediates, its
+    /// jumps and calls, and how it uses the stack. Every section must be reachable from
+    /// the first one
+    pub fn validate(&self) -> Result<(), EofError> {
+        let callees = (0..self.code_sections.len())
+            .map(|section| self.validate_code(section))
+            .collect::<Result<Vec<_>, _>>()?;
+        // Sections only called from unreachable ones are unreachable too
+        let mut reachable = vec![false; self.code_sections.len()];
+        let mut pending = vec![0];
+        while let Some(section) = pending.pop() {
+            if !std::mem::replace(&mut reachable[section], true) {
+                pending.extend(&callees[section]);
+            }
+        }
+        match reachable.iter().position(|reachable| !reachable) {
+            Some(section) => Err(EofError::UnreachableSection { section }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn code_sections(&self) -> &[CodeSection] {
+        &self.code_sections
+    }
+
+    /// Returns the containers nested in this one, as they were found
+    pub fn containers(&self) -> &[Vec<u8>] {
+        &self.containers
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Encodes the container back into bytecode
+    pub fn to_bytecode(&self) -> Vec<u8> {
+        let mut bytecode = MAGIC.to_vec();
+        bytecode.push(VERSION);
+        bytecode.push(KIND_TYPES);
+        bytecode.extend((self.code_sections.len() as u16 * 4).to_be_bytes());
+        bytecode.push(KIND_CODE);
+        bytecode.extend((self.code_sections.len() as u16).to_be_bytes());
+        for section in &self.code_sections {
+            bytecode.extend((section.code.len() as u16).to_be_bytes());
+        }
+        if !self.containers.is_empty() {
+            bytecode.push(KIND_CONTAINER);
+            bytecode.extend((self.containers.len() as u16).to_be_bytes());
+            for container in &self.containers {
+                bytecode.extend((container.len() as u32).to_be_bytes());
+            }
+        }
+        bytecode.push(KIND_DATA);
+        bytecode.extend((self.data.len() as u16).to_be_bytes());
+        bytecode.push(TERMINATOR);
+
+        for section in &self.code_sections {
+            bytecode.extend([section.inputs, section.outputs]);
+            bytecode.extend(section.max_stack_height.to_be_bytes());
+        }
+        for section in &self.code_sections {
+            bytecode.extend(&section.code);
+        }
+        for container in &self.containers {
+            bytecode.extend(container);
+        }
+        bytecode.extend(&self.data);
+        bytecode
+    }
+
+    /// Decodes each code section into a program, in order. See [`Container::program`]
+    pub fn programs(&self) -> Result<Vec<Program>, EofError> {
+        (0..self.code_sections.len())
+            .map(|section| self.program(section))
+            .collect()
+    }
+
+    /// Decodes the code section into a program, with offsets relative to the section. The
+    /// container is expected to be valid, and the program to be compiled for
+    /// [`SpecId::Osaka`](crate::spec::SpecId::Osaka), which brings EOF, along with the
+    /// sections it calls and the [data](Container::data) its instructions read. Panics if
+    /// there's no such section
+    pub fn program(&self, section: usize) -> Result<Program, EofError> {
+        let code = &self.code_sections[section].code;
+        let offsets = |offsets: &[u8]| -> Vec<i16> {
+            offsets
+                .chunks(2)
+                .map(|offset| i16::from_be_bytes([offset[0], offset[1]]))
+                .collect()
+        };
+        let operations = decode(section, code)?
+            .into_iter()
+            .map(|instruction| {
+                let unsupported = EofError::UnsupportedInstruction {
+                    section,
+                    pc: instruction.pc,
+                    opcode: instruction.opcode,
+                };
+                match instruction.opcode {
+                    RJUMP => Ok(Operation::Rjump(offsets(instruction.immediate)[0])),
+                    RJUMPI => Ok(Operation::Rjumpi(offsets(instruction.immediate)[0])),
+                    RJUMPV => Ok(Operation::Rjumpv(offsets(&instruction.immediate[1..]))),
+                    CALLF | JUMPF => {
+                        let callee = instruction.u16_immediate();
+                        let Some(callee_section) = self.code_sections.get(callee as usize) else {
+                            return Err(EofError::InvalidSectionIndex {
+                                section,
+                                pc: instruction.pc,
+                            });
+                        };
+                        let section_type = callee_section.section_type();
+                        Ok(match instruction.opcode {
+                            CALLF => Operation::Callf(callee, section_type),
+                            _ => Operation::Jumpf(callee, section_type),
+                        })
+                    }
+                    RETF => Ok(Operation::Retf),
+                    DATALOAD => Ok(Operation::Dataload),
+                    DATALOADN => Ok(Operation::Dataloadn(instruction.u16_immediate())),
+                    DATASIZE => Ok(Operation::Datasize),
+                    DATACOPY => Ok(Operation::Datacopy),
+                    DUPN => Ok(Operation::Dupn(instruction.immediate[0])),
+                    SWAPN => Ok(Operation::Swapn(instruction.immediate[0])),
+                    EXCHANGE => Ok(Operation::Exchange(instruction.immediate[0])),
+                    EOFCREATE | RETURNCONTRACT => Err(unsupported),
+                    // The instructions shared with legacy code decode the same way
+                    _ => Ok(decode_operation(code, instruction.pc).0),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Program::from(operations))
+    }
+
+    /// Validates the code of the section, returning the sections it calls or jumps to
+    fn validate_code(&self, section: usize) -> Result<Vec<usize>, EofError> {
+        let code_section = &self.code_sections[section];
+        let code = &code_section.code;
+        let instructions = decode(section, code)?;
+        let mut starts = vec![false; code.len()];
+        for instruction in &instructions {
+            starts[instruction.pc] = true;
+        }
+        let last = instructions.last().expect("code sections aren't empty");
+        if info(last.opcode).is_some_and(|info| info.falls_through) {
+            return Err(EofError::MissingTerminator { section });
+        }
+
+        // The immediates must reference things that exist
+        let mut callees = Vec::new();
+        let mut returns = false;
+        for instruction in &instructions {
+            let pc = instruction.pc;
+            for target in instruction.jump_targets() {
+                let lands = usize::try_from(target)
+                    .is_ok_and(|target| target < code.len() && starts[target]);
+                if !lands {
+                    return Err(EofError::InvalidJumpDestination { section, pc });
+                }
+            }
+            match instruction.opcode {
+                CALLF | JUMPF => {
+                    let callee = instruction.u16_immediate() as usize;
+                    let Some(callee_section) = self.code_sections.get(callee) else {
+                        return Err(EofError::InvalidSectionIndex { section, pc });
+                    };
+                    if instruction.opcode == CALLF && !callee_section.is_returning() {
+                        return Err(EofError::InvalidSectionIndex { section, pc });
+                    }
+                    returns |= instruction.opcode == JUMPF && callee_section.is_returning();
+                    callees.push(callee);
+                }
+                RETF => returns = true,
+                DATALOADN if instruction.u16_immediate() as usize + 32 > self.data.len() => {
+                    return Err(EofError::DataLoadOutOfBounds { section, pc });
+                }
+                EOFCREATE | RETURNCONTRACT
+                    if instruction.immediate[0] as usize >= self.containers.len() =>
+                {
+                    return Err(EofError::InvalidSectionIndex { section, pc });
+                }
+                _ => {}
+            }
+        }
+        if returns != code_section.is_returning() {
+            return Err(EofError::ReturningMismatch { section });
+        }
+
+        self.validate_stack(section, &instructions)?;
+        Ok(callees)
+    }
+
+    /// Validates how the code of the section uses the stack, in a single pass over its
+    /// instructions. The heights an instruction is reached with flow forward from the
+    /// ones before it, while backward jumps must land with the heights already found
+    fn validate_stack(&self, section: usize, instructions: &[Instruction]) -> Result<(), EofError> {
+        let code_section = &self.code_sections[section];
+        let inputs = code_section.inputs as usize;
+        let mut heights: Vec<Option<RangeInclusive<usize>>> = vec![None; code_section.code.len()];
+        heights[0] = Some(inputs..=inputs);
+        let mut max_height = inputs;
+
+        for (index, instruction) in instructions.iter().enumerate() {
+            let pc = instruction.pc;
+            let Some(height) = heights[pc].clone() else {
+                return Err(EofError::UnreachableInstruction { section, pc });
+            };
+            let (min, max) = (*height.start(), *height.end());
+            let (taken, pushed) = self.stack_effect(instruction);
+
+            match instruction.opcode {
+                CALLF | JUMPF => {
+                    // The callee's stack goes on top of the caller's
+                    let callee = &self.code_sections[instruction.u16_immediate() as usize];
+                    let growth = callee.max_stack_height as usize - callee.inputs as usize;
+                    if max + growth > MAX_STACK_SIZE {
+                        return Err(EofError::StackOverflow { section, pc });
+                    }
+                    if instruction.opcode == JUMPF && callee.is_returning() {
+                        // The callee returns to this section's caller in its place
+                        let outputs = code_section.outputs as usize;
+                        let expected =
+                            (outputs + callee.inputs as usize).checked_sub(callee.outputs as usize);
+                        if callee.outputs > code_section.outputs
+                            || expected != Some(min)
+                            || min != max
+                        {
+                            return Err(EofError::StackHeightMismatch { section, pc });
+                        }
+                    }
+                }
+                RETF => {
+                    let outputs = code_section.outputs as usize;
+                    if min != outputs || max != outputs {
+                        return Err(EofError::StackHeightMismatch { section, pc });
+                    }
+                }
+                _ => {}
+            }
+            if min < taken {
+                return Err(EofError::StackUnderflow { section, pc });
+            }
+            let next = (min - taken + pushed)..=(max - taken + pushed);
+            if *next.end() > MAX_STACK_SIZE {
+                return Err(EofError::StackOverflow { section, pc });
+            }
+            max_height = max_height.max(*next.end());
+
+            let mut successors = instruction.jump_targets();
+            if info(instruction.opcode).is_some_and(|info| info.falls_through) {
+                let next_pc = instructions[index + 1].pc as isize;
+                successors.push(next_pc);
+            }
+            for successor in successors {
+                let successor = successor as usize;
+                if successor > pc {
+                    heights[successor] = Some(match &heights[successor] {
+                        Some(heights) => {
+                            (*heights.start()).min(*next.start())
+                                ..=(*heights.end()).max(*next.end())
+                        }
+                        None => next.clone(),
+                    });
+                } else if heights[successor].as_ref() != Some(&next) {
+                    return Err(EofError::StackHeightMismatch { section, pc });
+                }
+            }
+        }
+
+        if max_height != code_section.max_stack_height as usize {
+            return Err(EofError::MaxStackHeightMismatch {
+                section,
+                declared: code_section.max_stack_height,
+                computed: max_height,
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the items the instruction takes from the stack, and the ones it leaves
+    fn stack_effect(&self, instruction: &Instruction) -> (usize, usize) {
+        let immediate = instruction.immediate.first().copied().unwrap_or_default() as usize;
+        match instruction.opcode {
+            CALLF | JUMPF => {
+                let callee = &self.code_sections[instruction.u16_immediate() as usize];
+                let outputs = match callee.is_returning() {
+                    true => callee.outputs as usize,
+                    false => 0,
+                };
+                (callee.inputs as usize, outputs)
+            }
+            DUPN => (immediate + 1, immediate + 2),
+            SWAPN => (immediate + 2, immediate + 2),
+            EXCHANGE => {
+                let items = (immediate >> 4) + (immediate & 0x0F) + 3;
+                (items, items)
+            }
+            opcode => {
+                let info = info(opcode).expect("instructions are defined");
+                (info.inputs as usize, info.outputs as usize)
+            }
+        }
+    }
+}
+
+/// Checks the type of the code section is valid
+fn validate_type(section: usize, code_section: &CodeSection) -> Result<(), EofError> {
+    let invalid = |reason| Err(EofError::InvalidType { section, reason });
+    if section == 0 && (code_section.inputs != 0 || code_section.is_returning()) {
+        return invalid("the first section must take no inputs and not return");
+    }
+    if code_section.inputs > MAX_IO {
+        return invalid("too many inputs");
+    }
+    if code_section.outputs > MAX_IO && code_section.is_returning() {
+        return invalid("too many outputs");
+    }
+    if code_section.max_stack_height > MAX_STACK_HEIGHT {
+        return invalid("the max stack height is too high");
+    }
+    if code_section.code.is_empty() {
+        return invalid("the code is empty");
+    }
+    Ok(())
+}
+
+/// An instruction of a code section
+#[derive(Debug)]
+struct Instruction<'a> {
+    pc: usize,
+    opcode: u8,
+    immediate: &'a [u8],
+}
+
+impl Instruction<'_> {
+    fn u16_immediate(&self) -> u16 {
+        u16::from_be_bytes([self.immediate[0], self.immediate[1]])
+    }
+
+    /// Returns the offsets the instruction may jump to, relative to the section
+    fn jump_targets(&self) -> Vec<isize> {
+        let end = (self.pc + 1 + self.immediate.len()) as isize;
+        let offsets = match self.opcode {
+            RJUMP | RJUMPI => self.immediate,
+            RJUMPV => &self.immediate[1..],
+            _ => &[],
+        };
+        offsets
+            .chunks(2)
+            .map(|offset| end + i16::from_be_bytes([offset[0], offset[1]]) as isize)
+            .collect()
+    }
+}
+
+/// Splits the code into its instructions, checking they're defined and whole
+fn decode(section: usize, code: &[u8]) -> Result<Vec<Instruction<'_>>, EofError> {
+    let mut instructions = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        let Some(info) = info(opcode) else {
+            return Err(EofError::UndefinedInstruction {
+                section,
+                pc,
+                opcode,
+            });
+        };
+        let immediate_size = match opcode {
+            // The number of offsets follows RJUMPV, minus one
+            RJUMPV => match code.get(pc + 1) {
+                Some(max_index) => 1 + (*max_index as usize + 1) * 2,
+                None => return Err(EofError::TruncatedImmediate { section, pc }),
+            },
+            _ => info.immediate_size,
+        };
+        let end = pc + 1 + immediate_size;
+        if end > code.len() {
+            return Err(EofError::TruncatedImmediate { section, pc });
+        }
+        instructions.push(Instruction {
+            pc,
+            opcode,
+            immediate: &code[pc + 1..end],
+        });
+        pc = end;
+    }
+    Ok(instructions)
+}
+
+/// How an instruction defined in EOF code uses the stack, when it doesn't depend on its
+/// immediate
+#[derive(Debug, Clone, Copy)]
+struct InstructionInfo {
+    inputs: u8,
+    outputs: u8,
+    immediate_size: usize,
+    /// Whether the next instruction may run after it
+    falls_through: bool,
+}
+
+/// Returns how the instruction uses the stack, or `None` if it's undefined in EOF code.
+/// The opcodes inspecting or jumping around the code, or the gas left, aren't defined
+fn info(opcode: u8) -> Option<InstructionInfo> {
+    let (inputs, outputs) = match opcode {
+        0x01..=0x07 | 0x0A | 0x0B | 0x10..=0x14 | 0x16..=0x18 | 0x1A..=0x1D | 0x20 => (2, 1),
+        0x08 | 0x09 => (3, 1),
+        0x15 | 0x19 | 0x31 | 0x35 | 0x40 | 0x49 | 0x51 | 0x54 | 0x5C => (1, 1),
+        0x30 | 0x32..=0x34 | 0x36 | 0x3A | 0x3D | 0x41..=0x48 | 0x4A | 0x59 | 0x5F => (0, 1),
+        0x37 | 0x3E | 0x5E => (3, 0),
+        0x50 => (1, 0),
+        0x52 | 0x53 | 0x55 | 0x5D => (2, 0),
+        // JUMPDEST is a no-op
+        0x5B => (0, 0),
+        0x60..=0x7F => {
+            return Some(InstructionInfo::new(0, 1).with_immediate(opcode as usize - 0x5F));
+        }
+        0x80..=0x8F => (opcode - 0x7F, opcode - 0x7E),
+        0x90..=0x9F => (opcode - 0x8E, opcode - 0x8E),
+        0xA0..=0xA4 => (opcode - 0x9E, 0),
+        DATALOAD => (1, 1),
+        DATALOADN => return Some(InstructionInfo::new(0, 1).with_immediate(2)),
+        DATASIZE => (0, 1),
+        DATACOPY => (3, 0),
+        RJUMP => return Some(InstructionInfo::new(0, 0).with_immediate(2).terminating()),
+        RJUMPI => return Some(InstructionInfo::new(1, 0).with_immediate(2)),
+        // The size of the immediate of RJUMPV is in its first byte
+        RJUMPV => (1, 0),
+        // The stack effect of these depends on their immediate
+        CALLF | DUPN | SWAPN | EXCHANGE => {
+            let immediate = if opcode == CALLF { 2 } else { 1 };
+            return Some(InstructionInfo::new(0, 0).with_immediate(immediate));
+        }
+        JUMPF => return Some(InstructionInfo::new(0, 0).with_immediate(2).terminating()),
+        RETF | 0x00 | 0xFE => return Some(InstructionInfo::new(0, 0).terminating()),
+        EOFCREATE => return Some(InstructionInfo::new(4, 1).with_immediate(1)),
+        RETURNCONTRACT => {
+            return Some(InstructionInfo::new(2, 0).with_immediate(1).terminating());
+        }
+        // RETURN and REVERT
+        0xF3 | 0xFD => return Some(InstructionInfo::new(2, 0).terminating()),
+        // RETURNDATALOAD, EXTCALL, EXTDELEGATECALL and EXTSTATICCALL
+        0xF7 => (1, 1),
+        0xF8 => (4, 1),
+        0xF9 | 0xFB => (3, 1),
+        _ => return None,
+    };
+    Some(InstructionInfo::new(inputs, outputs))
+}
+
+impl InstructionInfo {
+    fn new(inputs: u8, outputs: u8) -> Self {
+        Self {
+            inputs,
+            outputs,
+            immediate_size: 0,
+            falls_through: true,
+        }
+    }
+
+    fn with_immediate(self, immediate_size: usize) -> Self {
+        Self {
+            immediate_size,
+            ..self
+        }
+    }
+
+    fn terminating(self) -> Self {
+        Self {
+            falls_through: false,
+            ..self
+        }
+    }
+}
+
+/// Reads the header of a container
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, size: usize) -> Result<&'a [u8], EofError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + size)
+            .ok_or(EofError::InvalidHeader("the header is truncated"))?;
+        self.offset += size;
+        Ok(bytes)
+    }
+
+    fn peek(&self) -> Result<u8, EofError> {
+        self.bytes
+            .get(self.offset)
+            .copied()
+            .ok_or(EofError::InvalidHeader("the header is truncated"))
+    }
+
+    fn u8(&mut self) -> Result<u8, EofError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<usize, EofError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn u32(&mut self) -> Result<usize, EofError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    fn expect_kind(&mut self, kind: u8) -> Result<(), EofError> {
+        if self.u8()? != kind {
+            return Err(EofError::InvalidHeader(
+                "the sections are missing or out of order",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reads the number of sections of a kind, followed by their sizes, none of which
+    /// can be zero
+    fn sizes(
+        &mut self,
+        max_sections: usize,
+        read_size: fn(&mut Self) -> Result<usize, EofError>,
+    ) -> Result<Vec<usize>, EofError> {
+        let count = self.u16()?;
+        if count == 0 || count > max_sections {
+            return Err(EofError::InvalidHeader("invalid number of sections"));
+        }
+        let sizes = (0..count)
+            .map(|_| read_size(self))
+            .collect::<Result<Vec<_>, _>>()?;
+        if sizes.contains(&0) {
+            return Err(EofError::InvalidHeader("empty section"));
+        }
+        Ok(sizes)
+    }
+}
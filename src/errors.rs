@@ -1,13 +1,227 @@
+use std::{io, process::ExitStatus};
+
 use thiserror::Error;
 
+use crate::executor::HaltReason;
+
 #[derive(Debug, Error)]
 pub enum CodegenError {
     #[error("error linking: {0}")]
     LinkError(#[from] std::io::Error),
+    #[error("linker failed with {status}: {stderr}")]
+    LinkerFailed { status: ExitStatus, stderr: String },
+    #[error("the generated {0} failed MLIR verification")]
+    VerificationFailed(&'static str),
     #[error("llvm compile error: {0}")]
     LLVMCompileError(String),
     #[error("melior error: {0}")]
     MeliorError(#[from] melior::Error),
     #[error("not yet implemented: {0}")]
     NotImplemented(String),
+    #[error("invalid program: {0}")]
+    InvalidProgram(#[from] ProgramError),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("unknown opcode 0x{opcode:02X} at pc {pc}")]
+    UnknownOpcode { opcode: u8, pc: usize },
+}
+
+/// The reasons a program built from operations can't be compiled, or an operation built
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ProgramError {
+    #[error("PUSH immediates are 1 to 32 bytes long, not {size}")]
+    InvalidPushSize { size: usize },
+    #[error("the immediate of the PUSH at index {index} has {bits} bits, over 256")]
+    ImmediateTooLarge { index: usize, bits: u64 },
+    #[error("the immediate of the PUSH{size} at index {index} has {bits} bits, which don't fit")]
+    ImmediateDoesNotFit { index: usize, size: u8, bits: u64 },
+    #[error("the RJUMPV at index {index} has {size} offsets, not 1 to 256")]
+    InvalidJumpTableSize { index: usize, size: usize },
+    #[error("label `{label}` is defined again")]
+    DuplicateLabel { label: String },
+    #[error("undefined label `{label}`")]
+    UndefinedLabel { label: String },
+    #[error("code section {section} is called, but wasn't given")]
+    UndefinedCodeSection { section: u16 },
+    #[error("no code section was given")]
+    NoCodeSections,
+}
+
+/// The reasons an EOF container is invalid. The errors in code sections tell the section,
+/// and the offset of the instruction in it
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EofError {
+    #[error("the container doesn't start with the EOF magic 0xEF00")]
+    InvalidMagic,
+    #[error("unsupported EOF version {0}")]
+    UnsupportedVersion(u8),
+    #[error("invalid header: {0}")]
+    InvalidHeader(&'static str),
+    #[error("the container is {expected} bytes long, not {actual}")]
+    InvalidSize { expected: usize, actual: usize },
+    #[error("invalid type of code section {section}: {reason}")]
+    InvalidType {
+        section: usize,
+        reason: &'static str,
+    },
+    #[error("undefined instruction 0x{opcode:02X} at offset {pc} of code section {section}")]
+    UndefinedInstruction {
+        section: usize,
+        pc: usize,
+        opcode: u8,
+    },
+    /// A valid instruction which can't be compiled yet
+    #[error("unsupported instruction 0x{opcode:02X} at offset {pc} of code section {section}")]
+    UnsupportedInstruction {
+        section: usize,
+        pc: usize,
+        opcode: u8,
+    },
+    #[error("truncated immediate at offset {pc} of code section {section}")]
+    TruncatedImmediate { section: usize, pc: usize },
+    #[error("invalid jump destination at offset {pc} of code section {section}")]
+    InvalidJumpDestination { section: usize, pc: usize },
+    #[error("invalid section index at offset {pc} of code section {section}")]
+    InvalidSectionIndex { section: usize, pc: usize },
+    #[error("DATALOADN at offset {pc} of code section {section} reads past the data")]
+    DataLoadOutOfBounds { section: usize, pc: usize },
+    #[error("code section {section} doesn't end with a terminating instruction")]
+    MissingTerminator { section: usize },
+    #[error("unreachable instruction at offset {pc} of code section {section}")]
+    UnreachableInstruction { section: usize, pc: usize },
+    #[error("code section {section} is never called")]
+    UnreachableSection { section: usize },
+    #[error("stack underflow at offset {pc} of code section {section}")]
+    StackUnderflow { section: usize, pc: usize },
+    #[error("stack overflow at offset {pc} of code section {section}")]
+    StackOverflow { section: usize, pc: usize },
+    #[error("mismatched stack heights at offset {pc} of code section {section}")]
+    StackHeightMismatch { section: usize, pc: usize },
+    #[error("code section {section} declares a max stack height of {declared}, not {computed}")]
+    MaxStackHeightMismatch {
+        section: usize,
+        declared: u16,
+        computed: usize,
+    },
+    #[error("code section {section} doesn't return as its type says")]
+    ReturningMismatch { section: usize },
+}
+
+/// The reasons EVM assembly can't be assembled, with the line where they were found
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AssemblyError {
+    #[error("unknown mnemonic `{mnemonic}` at line {line}")]
+    UnknownMnemonic { mnemonic: String, line: usize },
+    #[error("`{mnemonic}` is missing its immediate at line {line}")]
+    MissingImmediate { mnemonic: String, line: usize },
+    #[error("invalid immediate `{immediate}` at line {line}")]
+    InvalidImmediate { immediate: String, line: usize },
+    #[error("immediate `{immediate}` doesn't fit in {size} bytes at line {line}")]
+    ImmediateTooLarge {
+        immediate: String,
+        size: usize,
+        line: usize,
+    },
+    #[error("label `{label}` is defined again at line {line}")]
+    DuplicateLabel { label: String, line: usize },
+    #[error("undefined label `{label}` at line {line}")]
+    UndefinedLabel { label: String, line: usize },
+}
+
+/// The reasons a transaction can be rejected before executing it.
+/// A rejected transaction doesn't change the state, nor charge any gas
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TransactionError {
+    #[error("intrinsic gas {intrinsic_gas} exceeds the gas limit {gas_limit}")]
+    IntrinsicGasTooLow { gas_limit: u64, intrinsic_gas: u64 },
+    #[error("initcode of {size} bytes exceeds the maximum size")]
+    InitcodeTooLarge { size: usize },
+    #[error("gas price is lower than the block's base fee")]
+    GasPriceLowerThanBasefee,
+    #[error("max priority fee per gas exceeds the max fee per gas")]
+    PriorityFeeGreaterThanMaxFee,
+    #[error("fee market transactions aren't supported before London")]
+    FeeMarketNotSupported,
+    #[error("sender can't afford the gas limit and value of the transaction")]
+    InsufficientFunds,
+    #[error("sender's nonce can't be incremented")]
+    NonceOverflow,
+    #[error("access lists aren't supported before Berlin")]
+    AccessListNotSupported,
+}
+
+/// The reasons a block can't be executed, which make it invalid
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BlockError {
+    #[error("transaction {index} was rejected: {error}")]
+    InvalidTransaction {
+        index: usize,
+        error: TransactionError,
+    },
+    #[error("transaction {index} goes over the block's gas limit")]
+    GasLimitReached { index: usize },
+}
+
+/// The reasons EVM bytecode can't be compiled
+#[derive(Debug, Error)]
+pub enum CompileError {
+    #[error("error parsing bytecode: {0}")]
+    Parse(#[from] ParseError),
+    #[error("error compiling program: {0}")]
+    Codegen(#[from] CodegenError),
+    #[error("error storing the compiled artifact: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Any error compiling or running a program. The errors of each step convert into it,
+/// so they can be handled in one place, by their kind
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("invalid program: {0}")]
+    InvalidProgram(#[from] ParseError),
+    #[error("invalid assembly: {0}")]
+    InvalidAssembly(#[from] AssemblyError),
+    #[error("invalid EOF container: {0}")]
+    InvalidEof(#[from] EofError),
+    /// The generated MLIR is invalid, which is a bug in the compiler
+    #[error("the generated {0} failed MLIR verification")]
+    Verification(&'static str),
+    #[error("linker failed with {status}: {stderr}")]
+    Link { status: ExitStatus, stderr: String },
+    /// Any other error generating code
+    #[error("error compiling program: {0}")]
+    Codegen(CodegenError),
+    #[error("transaction rejected: {0}")]
+    Transaction(#[from] TransactionError),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    /// The program halted with a REVERT
+    #[error("execution reverted, using {gas_used} gas")]
+    Revert { gas_used: u64, output: Vec<u8> },
+    /// The program halted with an exceptional error
+    #[error("execution halted: {0:?}")]
+    Halt(HaltReason),
+}
+
+impl From<CodegenError> for Error {
+    fn from(error: CodegenError) -> Self {
+        match error {
+            CodegenError::LinkError(error) => Error::Io(error),
+            CodegenError::LinkerFailed { status, stderr } => Error::Link { status, stderr },
+            CodegenError::VerificationFailed(what) => Error::Verification(what),
+            error => Error::Codegen(error),
+        }
+    }
+}
+
+impl From<CompileError> for Error {
+    fn from(error: CompileError) -> Self {
+        match error {
+            CompileError::Parse(error) => error.into(),
+            CompileError::Codegen(error) => error.into(),
+            CompileError::Io(error) => error.into(),
+        }
+    }
 }
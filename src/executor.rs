@@ -1,26 +1,254 @@
+//! # In-process execution of compiled programs
+//!
+//! An [`Executor`] holds a program JIT-compiled to native code by MLIR's execution engine,
+//! which can be called directly from the current process, without linking a binary.
+//! The same executor can run the program any number of times, each with its own
+//! [`SyscallContext`], reusing the [memory](crate::memory) of the contexts dropped.
+use std::{
+    num::NonZeroUsize,
+    panic,
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
 use melior::ExecutionEngine;
 
 use crate::{
-    constants::MAIN_ENTRYPOINT,
+    breakpoint::Breakpoints,
+    constants::{ExitStatusCode, MAIN_ENTRYPOINT},
+    context::{get_target_triple, Context},
+    errors::{CodegenError, Error},
+    memory::MemoryPool,
     module::MLIRModule,
-    syscall::{self, MainFunc, SyscallContext},
+    options::{CompileOptions, OverflowCheck, StackConfig},
+    program::Program,
+    spec::SpecId,
+    stack_guard,
+    state_diff::StateDiff,
+    syscall::{self, Log, MainFunc, SyscallContext},
 };
 
+/// The outcome of running a program with [`Executor::run`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionResult {
+    /// The program halted with a STOP or a RETURN
+    Success {
+        gas_used: u64,
+        /// The data given to RETURN, empty for a STOP
+        output: Vec<u8>,
+        /// The events emitted by the program, in emission order
+        logs: Vec<Log>,
+        /// The changes made to the state by the program
+        state_diff: StateDiff,
+    },
+    /// The program halted with a REVERT
+    Revert {
+        gas_used: u64,
+        /// The data given to REVERT
+        output: Vec<u8>,
+    },
+    /// The program halted with an exceptional error, consuming all its gas
+    Halt { reason: HaltReason },
+}
+
+impl ExecutionResult {
+    /// Returns whether the program halted with a STOP or a RETURN
+    pub fn is_success(&self) -> bool {
+        matches!(self, Self::Success { .. })
+    }
+
+    /// Returns the output of the program, which is empty if it halted exceptionally
+    pub fn output(&self) -> &[u8] {
+        match self {
+            Self::Success { output, .. } | Self::Revert { output, .. } => output,
+            Self::Halt { .. } => &[],
+        }
+    }
+
+    /// Returns the output of the program if it succeeded, or how it failed as an error
+    pub fn into_output(self) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Success { output, .. } => Ok(output),
+            Self::Revert { gas_used, output } => Err(Error::Revert { gas_used, output }),
+            Self::Halt { reason } => Err(Error::Halt(reason)),
+        }
+    }
+}
+
+/// The reason of an exceptional halt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HaltReason {
+    /// The program didn't have enough gas to continue
+    OutOfGas,
+    /// Any other exceptional halt, like a stack underflow or an invalid jump
+    Error,
+}
+
 pub struct Executor {
     engine: ExecutionEngine,
+    /// The memories of the contexts the program ran with, reused by the next ones
+    memory_pool: MemoryPool,
+    /// The stack the program was compiled for
+    stack: StackConfig,
 }
 
+// The engine owns the compiled code, and doesn't depend on the thread or the context
+// that compiled it
+unsafe impl Send for Executor {}
+
 impl Executor {
+    /// Creates an executor for an already compiled module, optimized at the level it was
+    /// compiled with
     pub fn new(module: &MLIRModule) -> Self {
-        let engine = ExecutionEngine::new(module.module(), 0, &[], false);
+        let opt_level = module.options().opt_level.level();
+        let engine = ExecutionEngine::new(module.module(), opt_level, &[], false);
         syscall::register_syscalls(&engine);
-        Self { engine }
+        Self {
+            engine,
+            memory_pool: MemoryPool::new(),
+            stack: module.options().stack,
+        }
     }
 
+    /// Compiles the program following the given hardfork, and creates an executor for it.
+    /// The program is JIT-compiled, so neither the filesystem nor a linker are used
+    pub fn compile(program: &Program, spec_id: SpecId) -> Result<Self, CodegenError> {
+        let context = Context::new();
+        let module = context.compile_jit(program, spec_id)?;
+        Ok(Self::new(&module))
+    }
+
+    /// Like [`Self::compile`], but for the code sections of an EOF container.
+    /// See [`Context::compile_code_sections`]
+    pub fn compile_code_sections(
+        sections: &[Program],
+        data: &[u8],
+        spec_id: SpecId,
+    ) -> Result<Self, CodegenError> {
+        let context = Context::new();
+        let module = context.compile_code_sections(sections, data, spec_id)?;
+        Ok(Self::new(&module))
+    }
+
+    /// Like [`Self::compile`], but following the given options.
+    /// The JIT only generates code for the host, so the target can't have another triple
+    pub fn compile_with_options(
+        program: &Program,
+        spec_id: SpecId,
+        options: &CompileOptions,
+    ) -> Result<Self, CodegenError> {
+        if let Some(triple) = &options.target.triple {
+            if *triple != get_target_triple() {
+                return Err(CodegenError::NotImplemented(format!(
+                    "JIT compilation for {triple}"
+                )));
+            }
+        }
+        let context = Context::new();
+        let module = context.compile_with_options(program, spec_id, options)?;
+        Ok(Self::new(&module))
+    }
+
+    /// Compiles the programs following the given hardfork, spread across as many threads
+    /// as the machine can run in parallel. Each program is compiled on its own, with its
+    /// own MLIR context, so one failing doesn't affect the others.
+    ///
+    /// Returns the results in the same order as the programs.
+    pub fn compile_many(programs: &[Program], spec_id: SpecId) -> Vec<Result<Self, CodegenError>> {
+        let threads = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(programs.len());
+        // Each thread takes the next program left, so the slow ones don't hold up the rest
+        let next = AtomicUsize::new(0);
+        let mut results: Vec<(usize, Result<Self, CodegenError>)> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut results = Vec::new();
+                        loop {
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(program) = programs.get(index) else {
+                                return results;
+                            };
+                            results.push((index, Self::compile(program, spec_id)));
+                        }
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|err| panic::resume_unwind(err))
+                })
+                .collect()
+        });
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    /// Like [`Self::compile`], but in instrumented mode: the program reports each opcode
+    /// before executing it, for tracing or inspecting the execution.
+    /// See [`SyscallContext::enable_tracing`] and [`SyscallContext::set_inspector`]
+    pub fn compile_instrumented(program: &Program, spec_id: SpecId) -> Result<Self, CodegenError> {
+        let context = Context::new();
+        let module = context.compile_jit_instrumented(program, spec_id)?;
+        Ok(Self::new(&module))
+    }
+
+    /// Like [`Self::compile`], but checking the given breakpoints before each opcode.
+    /// The ones hit are reported to the inspector set with
+    /// [`SyscallContext::set_inspector`], through
+    /// [`Inspector::breakpoint`](crate::inspector::Inspector::breakpoint)
+    pub fn compile_with_breakpoints(
+        program: &Program,
+        spec_id: SpecId,
+        breakpoints: &Breakpoints,
+    ) -> Result<Self, CodegenError> {
+        let context = Context::new();
+        let module = context.compile_jit_with_breakpoints(program, spec_id, breakpoints)?;
+        Ok(Self::new(&module))
+    }
+
+    /// Runs the program with the given context and gas, returning its raw exit code.
+    /// See [`Self::run`]
     pub fn execute(&self, context: &mut SyscallContext, initial_gas: u64) -> u8 {
         let main_fn: MainFunc = self.get_main_entrypoint();
 
-        main_fn(context, initial_gas)
+        context.use_memory_pool(&self.memory_pool);
+        let exit_code = match self.stack.overflow_check {
+            OverflowCheck::Branch => main_fn(context, initial_gas),
+            OverflowCheck::GuardPage => {
+                stack_guard::run_guarded(main_fn, context, initial_gas, self.stack.limit)
+            }
+        };
+        // The last opcode executed by an instrumented program is only finished here
+        context.finish_step(context.remaining_gas());
+        exit_code
+    }
+
+    /// Runs the program with the given context and gas, returning how it halted along
+    /// with its output. The state changes are kept in the context
+    pub fn run(&self, context: &mut SyscallContext, initial_gas: u64) -> ExecutionResult {
+        let exit_status = ExitStatusCode::from_u8(self.execute(context, initial_gas));
+        let gas_used = initial_gas - context.remaining_gas();
+        let output = context.return_values().to_vec();
+        match exit_status {
+            ExitStatusCode::Return | ExitStatusCode::Stop => ExecutionResult::Success {
+                gas_used,
+                output,
+                logs: context.logs().to_vec(),
+                state_diff: context.state_diff(),
+            },
+            ExitStatusCode::Revert => ExecutionResult::Revert { gas_used, output },
+            ExitStatusCode::OutOfGas => ExecutionResult::Halt {
+                reason: HaltReason::OutOfGas,
+            },
+            ExitStatusCode::Error | ExitStatusCode::Default => ExecutionResult::Halt {
+                reason: HaltReason::Error,
+            },
+        }
     }
 
     fn get_main_entrypoint(&self) -> MainFunc {
@@ -0,0 +1,118 @@
+//! # Inspecting the execution
+//!
+//! An [`Inspector`] is notified of what happens while a program runs: each opcode
+//! executed, each nested call or contract creation, and each event emitted.
+//! This is what tracers, debuggers and coverage tools are built on.
+//!
+//! The opcodes are only reported by programs compiled in instrumented mode
+//! (see [`Executor::compile_instrumented`](crate::executor::Executor::compile_instrumented)),
+//! which calls into the [`SyscallContext`] before executing each of them. Breakpoints are
+//! only reported by programs compiled with them. Calls are reported by the context itself,
+//! so they're seen regardless of how programs were compiled.
+use std::{cell::RefCell, fmt::Debug, rc::Rc};
+
+use crate::{
+    constants::CallType,
+    env::Address,
+    syscall::{Log, SyscallContext, U256},
+    trace::TraceStep,
+};
+
+/// Hooks called while a program runs, all of them doing nothing by default
+///
+/// Each hook gets the [`SyscallContext`] of the call being executed, giving access to
+/// its memory and state.
+pub trait Inspector: Debug {
+    /// Called before executing an opcode
+    fn step(&mut self, _step: &TraceStep, _context: &SyscallContext) {}
+
+    /// Called after executing an opcode, with the same step given to [`Inspector::step`]
+    /// and its gas cost filled in
+    fn step_end(&mut self, _step: &TraceStep, _context: &SyscallContext) {}
+
+    /// Called before executing an opcode with a breakpoint, in programs compiled with
+    /// breakpoints (see [`Breakpoints`](crate::breakpoint::Breakpoints)).
+    /// The gas cost of the step isn't known yet
+    fn breakpoint(&mut self, _step: &TraceStep, _context: &SyscallContext) {}
+
+    /// Called when entering a nested call or contract creation, before doing anything
+    fn call(&mut self, _inputs: &CallInputs, _context: &SyscallContext) {}
+
+    /// Called when a nested call or contract creation finishes
+    fn call_end(
+        &mut self,
+        _inputs: &CallInputs,
+        _outcome: &CallOutcome,
+        _context: &SyscallContext,
+    ) {
+    }
+
+    /// Called when an event is emitted, after the opcode emitting it was executed
+    fn log(&mut self, _log: &Log, _context: &SyscallContext) {}
+}
+
+/// An inspector shared with the caller, which can still reach it during the execution
+/// or after it
+impl<I: Inspector> Inspector for Rc<RefCell<I>> {
+    fn step(&mut self, step: &TraceStep, context: &SyscallContext) {
+        self.borrow_mut().step(step, context);
+    }
+
+    fn step_end(&mut self, step: &TraceStep, context: &SyscallContext) {
+        self.borrow_mut().step_end(step, context);
+    }
+
+    fn breakpoint(&mut self, step: &TraceStep, context: &SyscallContext) {
+        self.borrow_mut().breakpoint(step, context);
+    }
+
+    fn call(&mut self, inputs: &CallInputs, context: &SyscallContext) {
+        self.borrow_mut().call(inputs, context);
+    }
+
+    fn call_end(&mut self, inputs: &CallInputs, outcome: &CallOutcome, context: &SyscallContext) {
+        self.borrow_mut().call_end(inputs, outcome, context);
+    }
+
+    fn log(&mut self, log: &Log, context: &SyscallContext) {
+        self.borrow_mut().log(log, context);
+    }
+}
+
+/// The kind of a nested frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    /// A call made by one of the CALL* opcodes
+    Call(CallType),
+    /// A contract creation made by CREATE or CREATE2
+    Create,
+}
+
+/// What a nested call or contract creation was made with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallInputs {
+    pub kind: CallKind,
+    /// The account making the call
+    pub caller: Address,
+    /// The account whose code is executed, or the address of the contract created
+    pub address: Address,
+    /// The wei transferred, which is zero for DELEGATECALL and STATICCALL
+    pub value: U256,
+    /// The calldata, or the initcode when creating a contract
+    pub input: Vec<u8>,
+    /// The gas given to the frame
+    pub gas_limit: u64,
+    /// The depth of the new frame, counted like [`TraceStep::depth`], so it's 2 for
+    /// the calls made by the outermost one
+    pub depth: usize,
+}
+
+/// How a nested call or contract creation finished
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallOutcome {
+    pub success: bool,
+    /// The gas left unused, given back to the caller
+    pub gas_left: u64,
+    /// The output of the call, or of the initcode if it reverted
+    pub output: Vec<u8>,
+}
@@ -1,22 +1,22 @@
 use std::{
-    ffi::{CStr, CString},
+    ffi::{c_char, CStr, CString},
     mem::MaybeUninit,
     path::{Path, PathBuf},
-    ptr::{addr_of_mut, null_mut},
+    ptr::null_mut,
 };
 
 use errors::CodegenError;
 use llvm_sys::{
     core::{
-        LLVMContextCreate, LLVMContextDispose, LLVMDisposeMessage, LLVMDisposeModule,
-        LLVMPrintModuleToFile,
+        LLVMContextCreate, LLVMContextDispose, LLVMDisposeMemoryBuffer, LLVMDisposeMessage,
+        LLVMDisposeModule, LLVMGetBufferSize, LLVMGetBufferStart, LLVMPrintModuleToString,
     },
-    error::LLVMGetErrorMessage,
+    error::{LLVMDisposeErrorMessage, LLVMGetErrorMessage},
+    prelude::{LLVMContextRef, LLVMModuleRef},
     target_machine::{
-        LLVMCodeGenFileType, LLVMCodeGenOptLevel, LLVMCodeModel, LLVMCreateTargetMachine,
-        LLVMDisposeTargetMachine, LLVMGetDefaultTargetTriple, LLVMGetHostCPUFeatures,
-        LLVMGetHostCPUName, LLVMGetTargetFromTriple, LLVMRelocMode, LLVMTargetMachineEmitToFile,
-        LLVMTargetRef,
+        LLVMCodeGenFileType, LLVMCodeModel, LLVMCreateTargetMachine, LLVMDisposeTargetMachine,
+        LLVMGetHostCPUFeatures, LLVMGetHostCPUName, LLVMGetTargetFromTriple, LLVMRelocMode,
+        LLVMTargetMachineEmitToMemoryBuffer, LLVMTargetMachineRef, LLVMTargetRef,
     },
     transforms::pass_builder::{
         LLVMCreatePassBuilderOptions, LLVMDisposePassBuilderOptions, LLVMRunPasses,
@@ -26,16 +26,46 @@ use mlir_sys::mlirTranslateModuleToLLVMIR;
 use module::MLIRModule;
 use program::Program;
 
-use crate::context::Context;
+use crate::{
+    context::{get_target_triple, Context},
+    options::{OptLevel, Target},
+};
 
+pub mod analysis;
+pub mod assembly;
+pub mod block;
+pub mod breakpoint;
+pub mod builder;
+pub mod cache;
+pub mod cfg;
 pub mod codegen;
 pub mod constants;
 pub mod context;
+pub mod db;
+pub mod debugger;
+#[cfg(feature = "differential")]
+pub mod differential;
+pub mod env;
+pub mod eof;
 pub mod errors;
 pub mod executor;
+pub mod inspector;
+pub mod memory;
 pub mod module;
+pub mod optimizer;
+pub mod options;
+pub mod precompiles;
 pub mod program;
+pub mod receipt;
+#[cfg(feature = "revm")]
+pub mod revm_db;
+pub mod spec;
+pub mod stack_guard;
+pub mod state_diff;
+#[cfg(feature = "proptest")]
+pub mod strategies;
 pub mod syscall;
+pub mod trace;
 pub mod utils;
 
 pub fn compile(program: &Program, output_file: impl AsRef<Path>) -> Result<PathBuf, CodegenError> {
@@ -45,142 +75,209 @@ pub fn compile(program: &Program, output_file: impl AsRef<Path>) -> Result<PathB
 }
 
 /// Converts a module to an object.
-/// The object will be written to the specified target path.
+/// The object will be written to the specified target path, along with its LLVM IR
+/// and its assembly, with the `ll` and `asm` extensions.
 ///
 /// Returns the path to the object.
-// TODO: pass options to the function
 pub fn compile_to_object(
     module: &MLIRModule<'_>,
     output_file: impl AsRef<Path>,
 ) -> Result<PathBuf, CodegenError> {
     let target_file = output_file.as_ref().with_extension("o");
+    let llvm_module = LlvmModule::new(module)?;
+
+    std::fs::write(target_file.with_extension("ll"), llvm_module.ir())?;
+    std::fs::write(
+        &target_file,
+        llvm_module.emit(LLVMCodeGenFileType::LLVMObjectFile)?,
+    )?;
+    std::fs::write(
+        target_file.with_extension("asm"),
+        llvm_module.emit(LLVMCodeGenFileType::LLVMAssemblyFile)?,
+    )?;
+
+    Ok(target_file)
+}
 
-    // TODO: Rework so you can specify target and host features, etc.
-    // Right now it compiles for the native cpu feature set and arch
-    unsafe {
-        let llvm_context = LLVMContextCreate();
-
-        let op = module.melior_module.as_operation().to_raw();
-
-        let llvm_module = mlirTranslateModuleToLLVMIR(op, llvm_context as *mut _) as *mut _;
-
-        let mut null = null_mut();
-        let mut error_buffer = addr_of_mut!(null);
-
-        let target_triple = LLVMGetDefaultTargetTriple();
+/// Returns the LLVM IR of the module, as optimized before generating code from it.
+/// The module is compiled for the host, as with [`compile_to_object`]
+pub fn emit_llvm_ir(module: &MLIRModule<'_>) -> Result<String, CodegenError> {
+    Ok(LlvmModule::new(module)?.ir())
+}
 
-        let target_cpu = LLVMGetHostCPUName();
+/// Returns the assembly generated for the module, in the host's syntax
+pub fn emit_assembly(module: &MLIRModule<'_>) -> Result<String, CodegenError> {
+    let assembly = LlvmModule::new(module)?.emit(LLVMCodeGenFileType::LLVMAssemblyFile)?;
+    Ok(String::from_utf8_lossy(&assembly).into_owned())
+}
 
-        let target_cpu_features = LLVMGetHostCPUFeatures();
+/// Returns the object file generated for the module, which can be linked like the one
+/// [`compile_to_object`] writes, without writing it anywhere
+pub fn emit_object(module: &MLIRModule<'_>) -> Result<Vec<u8>, CodegenError> {
+    LlvmModule::new(module)?.emit(LLVMCodeGenFileType::LLVMObjectFile)
+}
 
-        let mut target: MaybeUninit<LLVMTargetRef> = MaybeUninit::uninit();
+/// A module translated to LLVM IR and optimized, with the machine to generate code for
+/// the host. Both are disposed when dropped
+struct LlvmModule {
+    context: LLVMContextRef,
+    module: LLVMModuleRef,
+    machine: LLVMTargetMachineRef,
+}
 
-        if LLVMGetTargetFromTriple(target_triple, target.as_mut_ptr(), error_buffer) != 0 {
-            let error = CStr::from_ptr(*error_buffer);
-            let err = error.to_string_lossy().to_string();
-            LLVMDisposeMessage(*error_buffer);
-            return Err(CodegenError::LLVMCompileError(err));
-        } else if !(*error_buffer).is_null() {
-            LLVMDisposeMessage(*error_buffer);
-            error_buffer = addr_of_mut!(null);
+impl LlvmModule {
+    /// Translates the module, and runs the LLVM passes over it, following the options
+    /// it was compiled with
+    fn new(module: &MLIRModule<'_>) -> Result<Self, CodegenError> {
+        unsafe {
+            let context = LLVMContextCreate();
+            let op = module.melior_module.as_operation().to_raw();
+            let mut llvm_module = Self {
+                context,
+                module: mlirTranslateModuleToLLVMIR(op, context as *mut _) as LLVMModuleRef,
+                machine: null_mut(),
+            };
+            if llvm_module.module.is_null() {
+                return Err(CodegenError::LLVMCompileError(
+                    "couldn't translate the module to LLVM IR".to_string(),
+                ));
+            }
+
+            let options = &module.options;
+            llvm_module.machine = create_target_machine(&options.target, options.opt_level)?;
+
+            let opts = LLVMCreatePassBuilderOptions();
+            let pipeline = options.passes.llvm_pipeline(options.opt_level);
+            let passes = CString::new(pipeline).map_err(|_| {
+                CodegenError::LLVMCompileError("invalid LLVM pass pipeline".to_string())
+            })?;
+            let error = LLVMRunPasses(
+                llvm_module.module,
+                passes.as_ptr(),
+                llvm_module.machine,
+                opts,
+            );
+            LLVMDisposePassBuilderOptions(opts);
+            if !error.is_null() {
+                let msg = LLVMGetErrorMessage(error);
+                let err = CStr::from_ptr(msg).to_string_lossy().into_owned();
+                LLVMDisposeErrorMessage(msg);
+                return Err(CodegenError::LLVMCompileError(err));
+            }
+
+            Ok(llvm_module)
         }
+    }
 
-        let target = target.assume_init();
+    /// Returns the module's LLVM IR, as text
+    fn ir(&self) -> String {
+        unsafe { take_string(LLVMPrintModuleToString(self.module)) }
+    }
 
-        let machine = LLVMCreateTargetMachine(
-            target,
-            target_triple.cast(),
-            target_cpu.cast(),
-            target_cpu_features.cast(),
-            LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
-            LLVMRelocMode::LLVMRelocPIC,
-            LLVMCodeModel::LLVMCodeModelDefault,
-        );
-
-        let opts = LLVMCreatePassBuilderOptions();
-        let opt = 0;
-        let passes = CString::new(format!("default<O{opt}>")).unwrap();
-        let error = LLVMRunPasses(llvm_module as *mut _, passes.as_ptr(), machine, opts);
-        if !error.is_null() {
-            let msg = LLVMGetErrorMessage(error);
-            let msg = CStr::from_ptr(msg);
-            return Err(CodegenError::LLVMCompileError(
-                msg.to_string_lossy().into_owned(),
-            ));
+    /// Generates code for the module, as an object (binary) or assembly (textual)
+    fn emit(&self, file_type: LLVMCodeGenFileType) -> Result<Vec<u8>, CodegenError> {
+        unsafe {
+            let mut error_buffer = null_mut();
+            let mut buffer = null_mut();
+            let failed = LLVMTargetMachineEmitToMemoryBuffer(
+                self.machine,
+                self.module,
+                file_type,
+                &mut error_buffer,
+                &mut buffer,
+            );
+            take_message(error_buffer, failed != 0)?;
+
+            let start = LLVMGetBufferStart(buffer).cast::<u8>();
+            let bytes = std::slice::from_raw_parts(start, LLVMGetBufferSize(buffer)).to_vec();
+            LLVMDisposeMemoryBuffer(buffer);
+            Ok(bytes)
         }
+    }
+}
 
-        LLVMDisposePassBuilderOptions(opts);
-
-        // Output the LLVM IR
-        let filename = CString::new(
-            target_file
-                .with_extension("ll")
-                .as_os_str()
-                .to_string_lossy()
-                .as_bytes(),
-        )
-        .unwrap();
-        if LLVMPrintModuleToFile(llvm_module, filename.as_ptr(), error_buffer) != 0 {
-            let error = CStr::from_ptr(*error_buffer);
-            let err = error.to_string_lossy().to_string();
-            LLVMDisposeMessage(*error_buffer);
-            return Err(CodegenError::LLVMCompileError(err));
-        } else if !(*error_buffer).is_null() {
-            LLVMDisposeMessage(*error_buffer);
-            error_buffer = addr_of_mut!(null);
+impl Drop for LlvmModule {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.machine.is_null() {
+                LLVMDisposeTargetMachine(self.machine);
+            }
+            if !self.module.is_null() {
+                LLVMDisposeModule(self.module);
+            }
+            LLVMContextDispose(self.context);
         }
+    }
+}
 
-        // Output the object file
-        let filename = CString::new(target_file.as_os_str().to_string_lossy().as_bytes()).unwrap();
-        let ok = LLVMTargetMachineEmitToFile(
-            machine,
-            llvm_module,
-            filename.as_ptr().cast_mut(),
-            LLVMCodeGenFileType::LLVMObjectFile, // object (binary) or assembly (textual)
-            error_buffer,
-        );
-
-        if ok != 0 {
-            let error = CStr::from_ptr(*error_buffer);
-            let err = error.to_string_lossy().to_string();
-            LLVMDisposeMessage(*error_buffer);
-            return Err(CodegenError::LLVMCompileError(err));
-        } else if !(*error_buffer).is_null() {
-            LLVMDisposeMessage(*error_buffer);
-        }
+/// Creates a machine generating code for the target, which must be disposed with
+/// `LLVMDisposeTargetMachine`
+pub(crate) fn create_target_machine(
+    target: &Target,
+    opt_level: OptLevel,
+) -> Result<LLVMTargetMachineRef, CodegenError> {
+    let host_triple = get_target_triple();
+    let triple = target.triple.clone().unwrap_or_else(|| host_triple.clone());
+    let is_host = triple == host_triple;
+    let cpu = match &target.cpu {
+        Some(cpu) => cpu.clone(),
+        None if is_host => unsafe { take_string(LLVMGetHostCPUName()) },
+        None => "generic".to_string(),
+    };
+    let features = match &target.features {
+        Some(features) => features.clone(),
+        None if is_host => unsafe { take_string(LLVMGetHostCPUFeatures()) },
+        None => String::new(),
+    };
+    let c_string = |value: String| {
+        CString::new(value)
+            .map_err(|error| CodegenError::LLVMCompileError(format!("invalid target: {error}")))
+    };
+    let (triple, cpu, features) = (c_string(triple)?, c_string(cpu)?, c_string(features)?);
 
-        // Output the assembly
-        let filename = CString::new(
-            target_file
-                .with_extension("asm")
-                .as_os_str()
-                .to_string_lossy()
-                .as_bytes(),
-        )
-        .unwrap();
-        let ok = LLVMTargetMachineEmitToFile(
-            machine,
-            llvm_module,
-            filename.as_ptr().cast_mut(),
-            LLVMCodeGenFileType::LLVMAssemblyFile,
-            error_buffer,
-        );
-
-        if ok != 0 {
-            let error = CStr::from_ptr(*error_buffer);
-            let err = error.to_string_lossy().to_string();
-            LLVMDisposeMessage(*error_buffer);
-            return Err(CodegenError::LLVMCompileError(err));
-        } else if !(*error_buffer).is_null() {
-            LLVMDisposeMessage(*error_buffer);
-        }
+    unsafe {
+        let mut error_buffer = null_mut();
+        let mut llvm_target: MaybeUninit<LLVMTargetRef> = MaybeUninit::uninit();
+        let failed =
+            LLVMGetTargetFromTriple(triple.as_ptr(), llvm_target.as_mut_ptr(), &mut error_buffer);
+        take_message(error_buffer, failed != 0)?;
+
+        Ok(LLVMCreateTargetMachine(
+            llvm_target.assume_init(),
+            triple.as_ptr(),
+            cpu.as_ptr(),
+            features.as_ptr(),
+            opt_level.codegen_level(),
+            LLVMRelocMode::LLVMRelocPIC,
+            LLVMCodeModel::LLVMCodeModelDefault,
+        ))
+    }
+}
 
-        LLVMDisposeTargetMachine(machine);
-        LLVMDisposeModule(llvm_module);
-        LLVMContextDispose(llvm_context);
+/// Copies a string allocated by LLVM, and disposes it
+///
+/// # Safety
+/// The string must have been allocated by LLVM, and not be used afterwards
+pub(crate) unsafe fn take_string(message: *mut c_char) -> String {
+    let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+    LLVMDisposeMessage(message);
+    text
+}
 
-        Ok(target_file)
+/// Disposes the message LLVM left, if any, returning it as an error if `failed`
+///
+/// # Safety
+/// The message must have been allocated by LLVM, or be null
+unsafe fn take_message(message: *mut c_char, failed: bool) -> Result<(), CodegenError> {
+    let text = (!message.is_null()).then(|| {
+        let text = CStr::from_ptr(message).to_string_lossy().into_owned();
+        LLVMDisposeMessage(message);
+        text
+    });
+    if failed {
+        return Err(CodegenError::LLVMCompileError(text.unwrap_or_default()));
     }
+    Ok(())
 }
 
 /// Links object file to produce an executable binary
@@ -188,7 +285,7 @@ pub fn compile_to_object(
 pub fn link_binary(
     objects: &[impl AsRef<Path>],
     output_filename: impl AsRef<Path>,
-) -> std::io::Result<()> {
+) -> Result<(), CodegenError> {
     let objects: Vec<_> = objects
         .iter()
         .map(|x| x.as_ref().display().to_string())
@@ -253,17 +350,11 @@ pub fn link_binary(
 
             args
         } else {
-            unimplemented!()
+            return Err(unsupported_platform());
         }
     };
 
-    let mut linker = std::process::Command::new("ld");
-    let proc = linker.args(args.iter()).spawn()?;
-    let output = proc.wait_with_output()?;
-
-    // TODO: propagate
-    assert!(output.status.success());
-    Ok(())
+    run_linker(&args)
 }
 
 pub fn compile_binary(
@@ -275,10 +366,14 @@ pub fn compile_binary(
     Ok(())
 }
 
+/// Links object files to produce a shared library.
+/// The platform's library extension is added to `output_filename` if it has none.
+///
+/// Returns the path to the library.
 pub fn link_shared_lib(
     objects: &[impl AsRef<Path>],
     output_filename: impl AsRef<Path>,
-) -> std::io::Result<()> {
+) -> Result<PathBuf, CodegenError> {
     let mut output_filename = output_filename.as_ref().to_path_buf();
     let objects: Vec<_> = objects
         .iter()
@@ -289,15 +384,19 @@ pub fn link_shared_lib(
         output_filename = output_filename.with_extension(get_platform_library_ext());
     }
 
-    let output_filename = output_filename.to_string_lossy().to_string();
+    let output_path = output_filename;
+    let output_filename = output_path.to_string_lossy().to_string();
 
     let args: Vec<_> = {
         if cfg!(target_os = "macos") {
+            // The syscalls are provided by the process loading the library
             let mut args = vec![
                 "-demangle",
                 "-no_deduplicate",
                 "-dynamic",
                 "-dylib",
+                "-undefined",
+                "dynamic_lookup",
                 "-L/usr/local/lib",
                 "-L/Library/Developer/CommandLineTools/SDKs/MacOSX.sdk/usr/lib",
             ];
@@ -318,16 +417,30 @@ pub fn link_shared_lib(
 
             args
         } else {
-            unimplemented!()
+            return Err(unsupported_platform());
         }
     };
 
-    let mut linker = std::process::Command::new("ld");
-    let proc = linker.args(args.iter()).spawn()?;
-    proc.wait_with_output()?;
+    run_linker(&args)?;
+    Ok(output_path)
+}
+
+/// Runs the system linker, failing with its diagnostics if it does
+fn run_linker(args: &[&str]) -> Result<(), CodegenError> {
+    let output = std::process::Command::new("ld").args(args).output()?;
+    if !output.status.success() {
+        return Err(CodegenError::LinkerFailed {
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
     Ok(())
 }
 
+fn unsupported_platform() -> CodegenError {
+    CodegenError::NotImplemented(format!("linking on {}", std::env::consts::OS))
+}
+
 pub fn get_platform_library_ext() -> &'static str {
     if cfg!(target_os = "macos") {
         "dylib"
@@ -338,11 +451,39 @@ pub fn get_platform_library_ext() -> &'static str {
     }
 }
 
-pub fn compile_shared_lib(
+/// Compiles the program into a shared library, to be loaded by other runtimes.
+/// The platform's library extension is added to `output_file` if it has none.
+///
+/// The library exports [`SHARED_ENTRYPOINT`](constants::SHARED_ENTRYPOINT), with the C
+/// signature of [`SharedEntrypoint`](syscall::SharedEntrypoint):
+///
+/// ```c
+/// uint8_t evm_mlir_execute(const void *env, const uint8_t *calldata,
+///                          uint64_t calldata_len, uint64_t *gas, uint8_t *output,
+///                          uint64_t output_capacity, uint64_t *output_len);
+/// ```
+///
+/// It runs the program in the [`Env`](env::Env) pointed to by `env`, with the given
+/// calldata and the gas pointed to by `gas`, and an empty storage. It returns its
+/// [`ExitStatusCode`](constants::ExitStatusCode), after writing the gas left to `gas`
+/// and copying as much of the output as fits in `output`. The whole size of the output
+/// is written to `output_len`.
+///
+/// The program's own entrypoint is exported too as
+/// [`MAIN_ENTRYPOINT`](constants::MAIN_ENTRYPOINT), with the signature of
+/// [`MainFunc`](syscall::MainFunc), to run it with a
+/// [`SyscallContext`](syscall::SyscallContext) set up by the caller.
+///
+/// The syscalls are exported by this crate with the names in [`syscall::symbols`], and
+/// are bound when the library is loaded. So the process loading it must export its
+/// symbols dynamically, linking with `-rdynamic` on Linux.
+///
+/// Returns the path to the library.
+pub fn compile_shared_library(
     program: &Program,
     output_file: impl AsRef<Path>,
-) -> Result<(), CodegenError> {
+) -> Result<PathBuf, CodegenError> {
     let object_file = compile(program, &output_file)?;
-    link_shared_lib(&[object_file], output_file)?;
-    Ok(())
+    let library_file = link_shared_lib(&[object_file], output_file)?;
+    Ok(library_file)
 }
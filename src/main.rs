@@ -1,27 +1,219 @@
-use std::path::PathBuf;
+use std::{io, path::PathBuf, process::ExitCode};
 
-use evm_mlir::{context::Context, executor::Executor, program::Program, syscall::SyscallContext};
+use evm_mlir::{
+    cfg::ControlFlowGraph,
+    compile_shared_library,
+    debugger::Debugger,
+    executor::{ExecutionResult, Executor},
+    program::{disassemble, Program},
+    spec::SpecId,
+    syscall::SyscallContext,
+    trace::write_trace,
+};
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let path = args.get(1).expect("No path provided").as_str();
-    let bytecode = std::fs::read(path).expect("Could not read file");
-    let program = Program::from_bytecode(&bytecode);
+const USAGE: &str = "\
+Usage: evm-mlir <COMMAND> <FILE> [OPTIONS]
 
-    // This is for intermediate files
-    let output_file = PathBuf::from("output");
+Commands:
+  compile  Compile the bytecode into a shared library, keeping the intermediate files
+  run      Run the bytecode, printing its result and output
+  trace    Run the bytecode, printing an EIP-3155 trace of each step
+  debug    Run the bytecode in the step debugger
+  disasm   Print the opcodes of the bytecode, with their offsets
+  cfg      Print the control-flow graph of the bytecode, as a Graphviz DOT graph
+  help     Print this message
 
-    let context = Context::new();
-    let module = context
-        .compile(&program, &output_file)
-        .expect("failed to compile program");
+FILE holds the bytecode, either as hex text or as raw bytes.
 
-    let executor = Executor::new(&module);
+Options:
+  -o, --output <PATH>  Where `compile` writes the library and intermediate files [default: output]
+  --calldata <HEX>     The calldata of `run`, `trace` and `debug` [default: empty]
+  --gas <GAS>          The gas available to `run`, `trace` and `debug` [default: 1000000]";
 
-    let mut context = SyscallContext::default();
-    let initial_gas = 1000;
+const DEFAULT_OUTPUT: &str = "output";
+const DEFAULT_GAS: u64 = 1_000_000;
 
-    let result = executor.execute(&mut context, initial_gas);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Command {
+    Compile,
+    Run,
+    Trace,
+    Debug,
+    Disasm,
+    Cfg,
+}
+
+/// The arguments following the command
+#[derive(Debug)]
+struct Options {
+    file: PathBuf,
+    output: Option<PathBuf>,
+    calldata: Option<Vec<u8>>,
+    gas: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match args.first().map(String::as_str) {
+        Some("compile") => Command::Compile,
+        Some("run") => Command::Run,
+        Some("trace") => Command::Trace,
+        Some("debug") => Command::Debug,
+        Some("disasm") => Command::Disasm,
+        Some("cfg") => Command::Cfg,
+        Some("help" | "-h" | "--help") => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        Some(other) => return usage_error(&format!("unknown command `{other}`")),
+        None => return usage_error("no command given"),
+    };
+    let options = match parse_options(command, &args[1..]) {
+        Ok(options) => options,
+        Err(message) => return usage_error(&message),
+    };
+    match run_command(command, options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn usage_error(message: &str) -> ExitCode {
+    eprintln!("error: {message}\n\n{USAGE}");
+    ExitCode::from(2)
+}
+
+fn parse_options(command: Command, args: &[String]) -> Result<Options, String> {
+    let mut file = None;
+    let mut output = None;
+    let mut calldata = None;
+    let mut gas = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("`{arg}` needs a value"));
+        match arg.as_str() {
+            "-o" | "--output" if command == Command::Compile => {
+                output = Some(PathBuf::from(value()?));
+            }
+            "--calldata" if matches!(command, Command::Run | Command::Trace | Command::Debug) => {
+                let hex = value()?;
+                calldata = Some(parse_hex(hex).ok_or_else(|| format!("invalid calldata `{hex}`"))?);
+            }
+            "--gas" if matches!(command, Command::Run | Command::Trace | Command::Debug) => {
+                let gas_value = value()?;
+                gas = Some(
+                    gas_value
+                        .parse()
+                        .map_err(|_| format!("invalid gas `{gas_value}`"))?,
+                );
+            }
+            option if option.starts_with('-') => {
+                return Err(format!("unexpected option `{option}`"));
+            }
+            path if file.is_none() => file = Some(PathBuf::from(path)),
+            extra => return Err(format!("unexpected argument `{extra}`")),
+        }
+    }
+
+    Ok(Options {
+        file: file.ok_or("no file given")?,
+        output,
+        calldata,
+        gas,
+    })
+}
+
+fn run_command(command: Command, options: Options) -> Result<(), String> {
+    let bytecode = read_bytecode(&options)?;
+    if command == Command::Disasm {
+        // The bytecode is listed as is, keeping the width of its pushes
+        print!("{}", disassemble(&bytecode));
+        return Ok(());
+    }
+
+    let program =
+        Program::from_bytecode(&bytecode).map_err(|error| format!("invalid bytecode: {error}"))?;
+    if command == Command::Cfg {
+        return ControlFlowGraph::new(&program)
+            .write_dot(io::stdout().lock())
+            .map_err(|error| format!("couldn't write the graph: {error}"));
+    }
+
+    let mut context = SyscallContext::with_calldata(options.calldata.unwrap_or_default());
+    context.set_code(bytecode);
+    let gas = options.gas.unwrap_or(DEFAULT_GAS);
+
+    let executor = match command {
+        Command::Compile => {
+            let output = options.output.unwrap_or_else(|| DEFAULT_OUTPUT.into());
+            let library = compile_shared_library(&program, output)
+                .map_err(|error| format!("compilation failed: {error}"))?;
+            println!("Compiled to {}", library.display());
+            return Ok(());
+        }
+        Command::Run => Executor::compile(&program, SpecId::default()),
+        Command::Trace => {
+            context.enable_tracing();
+            Executor::compile_instrumented(&program, SpecId::default())
+        }
+        Command::Debug => {
+            let debugger = Debugger::new(io::stdin().lock(), io::stdout());
+            context.set_inspector(Box::new(debugger));
+            Executor::compile_instrumented(&program, SpecId::default())
+        }
+        Command::Disasm | Command::Cfg => unreachable!("the program isn't compiled to be listed"),
+    }
+    .map_err(|error| format!("compilation failed: {error}"))?;
+
+    let result = executor.run(&mut context, gas);
+
+    if command == Command::Trace {
+        // The trace is kept alone on stdout, so it can be compared against other clients
+        write_trace(context.trace(), io::stdout().lock())
+            .map_err(|error| format!("couldn't write the trace: {error}"))?;
+        eprintln!("{}", describe_result(&result));
+    } else {
+        println!("{}", describe_result(&result));
+    }
+    Ok(())
+}
+
+/// Reads the bytecode in the file, which is decoded if it holds hex text.
+/// The hex may be split across lines
+fn read_bytecode(options: &Options) -> Result<Vec<u8>, String> {
+    let contents = std::fs::read(&options.file)
+        .map_err(|error| format!("couldn't read {}: {error}", options.file.display()))?;
+    let hex = std::str::from_utf8(&contents)
+        .ok()
+        .and_then(|text| parse_hex(&text.split_whitespace().collect::<String>()));
+    Ok(hex.unwrap_or(contents))
+}
+
+fn describe_result(result: &ExecutionResult) -> String {
+    let return_data: String = result
+        .output()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let outcome = match result {
+        ExecutionResult::Success { gas_used, .. } => format!("Success, using {gas_used} gas"),
+        ExecutionResult::Revert { gas_used, .. } => format!("Revert, using {gas_used} gas"),
+        ExecutionResult::Halt { reason } => format!("Halt ({reason:?})"),
+    };
+    format!("Execution result: {outcome}\nReturn data: 0x{return_data}")
+}
 
-    println!("Execution result: {result}");
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if hex.len() % 2 != 0 {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
 }
@@ -0,0 +1,133 @@
+//! # Runtime memory
+//!
+//! The memory of each call is a [`Memory`]. Rather than growing a buffer as the program
+//! expands its memory, copying it over each time it's reallocated, a memory taken from a
+//! [`MemoryPool`] starts with a reservation of [`MEMORY_RESERVATION`] zeroed bytes. Its
+//! pages are only committed by the operating system as the program touches them, so the
+//! reservation costs nothing until used, and expanding the memory within it doesn't
+//! allocate nor copy anything.
+//!
+//! Each [`Executor`] keeps a pool, giving a memory to each context it runs the program
+//! with. Once the context is dropped, its memory goes back to the pool, cleared, so the
+//! next run reuses the pages already committed.
+//!
+//! [`Executor`]: crate::executor::Executor
+use std::{
+    collections::TryReserveError,
+    fmt,
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+/// The bytes reserved for a memory taken from a pool. The memory can grow past them,
+/// like one not taken from a pool, but gas makes it too expensive in practice: 32 MiB
+/// of memory cost over 2 billion gas
+pub const MEMORY_RESERVATION: usize = 32 << 20;
+
+/// The memory of a call, dereferencing to the bytes the program expanded it to
+#[derive(Default)]
+pub struct Memory {
+    /// The bytes reserved, all zero past the size
+    bytes: Vec<u8>,
+    size: usize,
+    /// The pool the bytes go back to once the memory is dropped, if any
+    pool: Option<MemoryPool>,
+}
+
+impl Memory {
+    /// Creates an empty memory, reserving bytes as it's expanded
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether the memory was taken from a pool
+    pub fn is_pooled(&self) -> bool {
+        self.pool.is_some()
+    }
+
+    /// Expands the memory to `new_size` bytes, which are zero, unless it's already larger
+    pub fn expand(&mut self, new_size: usize) -> Result<(), TryReserveError> {
+        if new_size > self.bytes.len() {
+            self.bytes.try_reserve(new_size - self.bytes.len())?;
+            self.bytes.resize(new_size, 0);
+        }
+        self.size = self.size.max(new_size);
+        Ok(())
+    }
+}
+
+impl Deref for Memory {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.size]
+    }
+}
+
+impl DerefMut for Memory {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[..self.size]
+    }
+}
+
+impl fmt::Debug for Memory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.take() {
+            // Only the bytes used can be dirty
+            self.bytes[..self.size].fill(0);
+            pool.give_back(std::mem::take(&mut self.bytes));
+        }
+    }
+}
+
+/// The reservations of the memories dropped, handed out again to the next ones. Cloning
+/// the pool shares it
+#[derive(Debug, Clone, Default)]
+pub struct MemoryPool {
+    reservations: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl MemoryPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes an empty memory, reusing a reservation given back to the pool if there's any
+    pub fn take(&self) -> Memory {
+        let reserved = self.lock().pop();
+        // Large zeroed allocations are mapped straight from the operating system, which
+        // commits their pages lazily
+        let bytes = reserved.unwrap_or_else(|| vec![0; MEMORY_RESERVATION]);
+        Memory {
+            bytes,
+            size: 0,
+            pool: Some(self.clone()),
+        }
+    }
+
+    /// Returns how many reservations are waiting to be reused
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn give_back(&self, bytes: Vec<u8>) {
+        self.lock().push(bytes);
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+        // The reservations are left consistent even if a thread panicked holding them
+        self.reservations
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
@@ -2,14 +2,23 @@ use std::fmt::Debug;
 
 use melior::{ir::Module as MeliorModule, Context as MeliorContext};
 
+use crate::options::CompileOptions;
+
 pub struct MLIRModule<'m> {
     pub(crate) melior_module: MeliorModule<'m>,
+    /// The options the module was compiled with, which the code generated from it follows
+    pub(crate) options: CompileOptions,
 }
 
 impl<'m> MLIRModule<'m> {
     pub fn new(module: MeliorModule<'m>) -> Self {
+        Self::with_options(module, CompileOptions::default())
+    }
+
+    pub(crate) fn with_options(module: MeliorModule<'m>, options: CompileOptions) -> Self {
         Self {
             melior_module: module,
+            options,
         }
     }
 
@@ -17,6 +26,11 @@ impl<'m> MLIRModule<'m> {
         &self.melior_module
     }
 
+    /// Returns the options the module was compiled with
+    pub fn options(&self) -> &CompileOptions {
+        &self.options
+    }
+
     pub fn parse(context: &MeliorContext, source: &str) -> Option<Self> {
         MeliorModule::parse(context, source).map(Self::new)
     }
@@ -0,0 +1,256 @@
+//! # Peephole optimizer
+//!
+//! Rewrites short runs of operations of a [`Program`] into fewer ones before generating
+//! code for it, as Solidity leaves many of them behind:
+//!
+//! - A PUSH followed by a unary operation, or two PUSHes followed by a binary operation,
+//!   are folded into a PUSH of the result, like `PUSH1 2 PUSH1 3 ADD` into `PUSH1 5`
+//! - A PUSH followed by a POP is dropped
+//! - An `ISZERO ISZERO` whose result is only tested for being zero, by another ISZERO or
+//!   by a JUMPI right after pushing its target, is dropped
+//!
+//! The rewritten program behaves as the original one, down to the gas it uses: the gas
+//! of the operations folded away is charged along with the PUSH replacing them, and the
+//! gas of the ones dropped along with the operation following them, which the optimizer
+//! gives as [`OptimizedProgram::extra_gas`]. Since the operations rewritten can't halt
+//! but by running out of gas, charging it sooner doesn't change how the program halts.
+//! Runs which could overflow or underflow the stack, by the
+//! [stack-height analysis](crate::analysis), are left as they are.
+//!
+//! JUMPDEST and PC operations keep their offsets, so jumps land where they did. Programs
+//! with relative jumps, which land on offsets no operation keeps, aren't rewritten.
+use std::ops::RangeInclusive;
+
+use num_bigint::BigUint;
+
+use crate::{
+    analysis::StackAnalysis,
+    cfg::ControlFlowGraph,
+    constants::MAX_STACK_SIZE,
+    program::{Operation, Program},
+    spec::SpecId,
+};
+
+/// A program rewritten by [`optimize`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptimizedProgram {
+    pub program: Program,
+    /// The gas of the operations rewritten away, charged before each operation of the
+    /// program, in the order of its operations
+    pub extra_gas: Vec<i64>,
+}
+
+/// An operation being rewritten
+#[derive(Debug, Clone)]
+struct Item {
+    operation: Operation,
+    extra_gas: i64,
+    /// The heights the operation may run with, or `None` if it never runs
+    heights: Option<RangeInclusive<usize>>,
+}
+
+/// How a run of operations is rewritten
+enum Rewrite {
+    /// The first operations are replaced by one
+    Replace(usize, Operation),
+    /// The first operations are dropped
+    Drop(usize),
+}
+
+/// Rewrites the program until none of its operations can be folded or dropped. The
+/// operations introduced after `spec_id`, which halt the program, are left as they are
+pub fn optimize(program: &Program, spec_id: SpecId) -> OptimizedProgram {
+    optimize_with_stack_limit(program, spec_id, MAX_STACK_SIZE)
+}
+
+/// Like [`optimize`], but for a stack holding at most `stack_limit` items
+pub fn optimize_with_stack_limit(
+    program: &Program,
+    spec_id: SpecId,
+    stack_limit: usize,
+) -> OptimizedProgram {
+    // Relative jumps may land on any operation, and their offsets would change along
+    // with the operations rewritten, so programs with any are left as they are
+    let has_relative_jumps = program.operations().iter().any(|operation| {
+        matches!(
+            operation,
+            Operation::Rjump(_) | Operation::Rjumpi(_) | Operation::Rjumpv(_)
+        )
+    });
+    if has_relative_jumps {
+        return OptimizedProgram {
+            program: program.clone(),
+            extra_gas: vec![0; program.operations().len()],
+        };
+    }
+
+    let cfg = ControlFlowGraph::new(program);
+    let heights = StackAnalysis::with_stack_limit(&cfg, stack_limit).operation_heights(&cfg);
+    let mut items: Vec<Item> = program
+        .operations()
+        .iter()
+        .cloned()
+        .zip(heights)
+        .map(|(operation, heights)| Item {
+            operation,
+            extra_gas: 0,
+            heights,
+        })
+        .collect();
+
+    // Each rewrite leaves fewer operations, so a pass keeping all of them is the last
+    loop {
+        let rewritten = rewrite_pass(&items, spec_id, stack_limit);
+        let done = rewritten.len() == items.len();
+        items = rewritten;
+        if done {
+            break;
+        }
+    }
+
+    let (operations, extra_gas) = items
+        .into_iter()
+        .map(|item| (item.operation, item.extra_gas))
+        .unzip();
+    OptimizedProgram {
+        program: Program::from(operations),
+        extra_gas,
+    }
+}
+
+fn rewrite_pass(items: &[Item], spec_id: SpecId, stack_limit: usize) -> Vec<Item> {
+    let mut rewritten = Vec::with_capacity(items.len());
+    // The gas of the operations dropped, charged with the next one
+    let mut carried = 0;
+    let mut index = 0;
+    while index < items.len() {
+        let window = &items[index..];
+        match rewrite(window, spec_id, stack_limit) {
+            Some(Rewrite::Replace(length, operation)) => {
+                let gas =
+                    carried + total_gas(&window[..length], spec_id) - operation.static_gas(spec_id);
+                rewritten.push(Item {
+                    operation,
+                    extra_gas: gas,
+                    heights: window[0].heights.clone(),
+                });
+                carried = 0;
+                index += length;
+            }
+            Some(Rewrite::Drop(length)) => {
+                carried += total_gas(&window[..length], spec_id);
+                index += length;
+            }
+            None => {
+                let mut item = window[0].clone();
+                item.extra_gas += carried;
+                rewritten.push(item);
+                carried = 0;
+                index += 1;
+            }
+        }
+    }
+    debug_assert_eq!(carried, 0, "operations are only dropped before another one");
+    rewritten
+}
+
+/// Returns how the operations at the start of the window can be rewritten, if they can
+fn rewrite(window: &[Item], spec_id: SpecId, stack_limit: usize) -> Option<Rewrite> {
+    let operation = |index: usize| window.get(index).map(|item| &item.operation);
+    let pushed = |index: usize| operation(index).and_then(|op| constant(op, spec_id));
+    let heights = window[0].heights.as_ref();
+    // Whether the stack can hold `items` more items than the first operation runs with
+    let has_space_for =
+        |items: usize| heights.map_or(true, |heights| heights.end() + items <= stack_limit);
+    // Whether the first operation runs with at least `items` items on the stack
+    let has_at_least = |items: usize| heights.map_or(true, |heights| *heights.start() >= items);
+    // Gas is only carried to an operation which always runs after the dropped ones
+    let followed_in_block =
+        |index: usize| operation(index).is_some_and(|op| !matches!(op, Operation::Jumpdest { .. }));
+
+    if let Some(a) = pushed(0) {
+        if let Some(b) = pushed(1) {
+            let result = operation(2).and_then(|op| fold_binary(op, &a, &b));
+            if let Some(result) = result.filter(|_| has_space_for(2)) {
+                return Some(Rewrite::Replace(3, Operation::Push(result)));
+            }
+        }
+        if let Some(result) = operation(1).and_then(|op| fold_unary(op, &a)) {
+            return Some(Rewrite::Replace(2, Operation::Push(result)));
+        }
+        if operation(1) == Some(&Operation::Pop) && has_space_for(1) && followed_in_block(2) {
+            return Some(Rewrite::Drop(2));
+        }
+    }
+
+    if operation(0) == Some(&Operation::IsZero)
+        && operation(1) == Some(&Operation::IsZero)
+        && has_at_least(1)
+    {
+        let tested_for_zero = match (operation(2), operation(3)) {
+            (Some(Operation::IsZero), _) => true,
+            (
+                Some(Operation::Push0 | Operation::Push(_) | Operation::PushN(..)),
+                Some(Operation::Jumpi),
+            ) => true,
+            _ => false,
+        };
+        if tested_for_zero {
+            return Some(Rewrite::Drop(2));
+        }
+    }
+    None
+}
+
+/// Returns the value the operation pushes, if it's a constant word
+fn constant(operation: &Operation, spec_id: SpecId) -> Option<BigUint> {
+    match operation {
+        Operation::Push0 if spec_id.includes(operation.introduced_in()) => Some(BigUint::ZERO),
+        Operation::Push(value) | Operation::PushN(_, value) if value.bits() <= 256 => {
+            Some(value.clone())
+        }
+        _ => None,
+    }
+}
+
+/// Returns the result of the binary operation, with `a` pushed before `b`, which is on
+/// top of the stack
+fn fold_binary(operation: &Operation, a: &BigUint, b: &BigUint) -> Option<BigUint> {
+    let modulus = word_modulus();
+    let result = match operation {
+        Operation::Add => (a + b) % modulus,
+        Operation::Mul => (a * b) % modulus,
+        Operation::Sub => (b + &modulus - a) % modulus,
+        Operation::And => a & b,
+        Operation::Or => a | b,
+        Operation::Xor => a ^ b,
+        Operation::Eq => BigUint::from(u8::from(a == b)),
+        Operation::Lt => BigUint::from(u8::from(b < a)),
+        Operation::Gt => BigUint::from(u8::from(b > a)),
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Returns the result of the unary operation on `a`
+fn fold_unary(operation: &Operation, a: &BigUint) -> Option<BigUint> {
+    match operation {
+        Operation::IsZero => Some(BigUint::from(u8::from(*a == BigUint::ZERO))),
+        Operation::Not => Some(word_modulus() - 1_u8 - a),
+        _ => None,
+    }
+}
+
+/// Returns 2^256, which arithmetic on words wraps around
+fn word_modulus() -> BigUint {
+    BigUint::from(1_u8) << 256
+}
+
+/// Returns the gas charged for the items: the one of their operations, and the one
+/// carried by them
+fn total_gas(items: &[Item], spec_id: SpecId) -> i64 {
+    items
+        .iter()
+        .map(|item| item.operation.static_gas(spec_id) + item.extra_gas)
+        .sum()
+}
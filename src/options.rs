@@ -0,0 +1,382 @@
+//! # Compilation options
+//!
+//! [`CompileOptions`] tune how a program is compiled, through
+//! [`Context::compile_with_options`](crate::context::Context::compile_with_options) and
+//! [`Executor::compile_with_options`](crate::executor::Executor::compile_with_options).
+//! The compiled module keeps them, so the code generated from it with
+//! [`emit_object`](crate::emit_object) and the like follows them too.
+//!
+//! The [`OptLevel`] trades compilation time for faster code: JIT-compiling a contract
+//! to run it once is best done at [`OptLevel::O0`], while a library kept in a cache
+//! pays off at [`OptLevel::O3`]. [`Passes`] choose the passes run over the MLIR and the
+//! LLVM IR, the [`Target`] the machine code is generated for, and the [`WordRepr`] the
+//! arithmetic on words is lowered with.
+//!
+//! The [`StackConfig`] sets how many words the stack holds, and how a push overflowing
+//! it is caught: by a check before each push, or by the fault of writing to a guard page
+//! placed after the stack.
+//!
+//! With [`DebugInfo`], the generated code carries debug info locating the code of each
+//! operation in a listing of the program, so native profilers and debuggers attribute it
+//! to the EVM offset and opcode it came from.
+//!
+//! An [`IrDump`] gets the textual MLIR of the module at stages of the pipeline: as
+//! generated from the program, after each of the passes lowering it, and once lowered to
+//! the LLVM dialect. This shows where a miscompilation comes from without patching the
+//! compiler to print the module.
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use llvm_sys::target_machine::LLVMCodeGenOptLevel;
+use melior::{
+    ir::{Location, Module as MeliorModule},
+    Context as MeliorContext,
+};
+
+use crate::{
+    constants::MAX_STACK_SIZE,
+    program::{Operation, Program},
+};
+
+/// How to compile a program. The default compiles it as [`Executor::compile`] does.
+///
+/// [`Executor::compile`]: crate::executor::Executor::compile
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    pub opt_level: OptLevel,
+    pub passes: Passes,
+    pub target: Target,
+    pub word_repr: WordRepr,
+    pub stack: StackConfig,
+    /// Where to locate the generated code in debug info, if it's emitted
+    pub debug_info: Option<DebugInfo>,
+    /// Where to dump the MLIR of the module while compiling it, if anywhere
+    pub dump: Option<IrDump>,
+}
+
+/// How hard LLVM optimizes the code, as with `-O0` to `-O3`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum OptLevel {
+    /// No optimizations, for the fastest compilation
+    #[default]
+    O0,
+    O1,
+    O2,
+    /// Every optimization, for the fastest code
+    O3,
+}
+
+impl OptLevel {
+    /// Returns the level as a number, from 0 to 3
+    pub fn level(&self) -> usize {
+        *self as usize
+    }
+
+    pub(crate) fn codegen_level(&self) -> LLVMCodeGenOptLevel {
+        match self {
+            OptLevel::O0 => LLVMCodeGenOptLevel::LLVMCodeGenLevelNone,
+            OptLevel::O1 => LLVMCodeGenOptLevel::LLVMCodeGenLevelLess,
+            OptLevel::O2 => LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault,
+            OptLevel::O3 => LLVMCodeGenOptLevel::LLVMCodeGenLevelAggressive,
+        }
+    }
+}
+
+/// The passes run over the module.
+///
+/// The MLIR passes lowering the module to the LLVM dialect always run, since the code
+/// can't be generated otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Passes {
+    /// Whether to canonicalize the generated MLIR before lowering it, which simplifies
+    /// it at some cost in compilation time. On by default
+    pub canonicalize: bool,
+    /// Whether to check the stack height once at the entry of each basic block, or not at
+    /// all where the [stack-height analysis](crate::analysis) proves it valid, rather than
+    /// before each operation. On by default, except for instrumented programs, which
+    /// report each operation before checking it.
+    ///
+    /// A block that would underflow or overflow the stack then halts at its entry,
+    /// before running any operation. This only shows in the [`HaltReason`] when one of
+    /// those operations would have run out of gas first, since an exceptional halt
+    /// consumes all the gas either way.
+    ///
+    /// [`HaltReason`]: crate::executor::HaltReason
+    pub elide_stack_checks: bool,
+    /// Whether to charge the static gas of the operations of each basic block at once,
+    /// at its entry, rather than before each operation. The costs depending on the
+    /// operands, like expanding the memory, are still charged where they're computed, and
+    /// the operations depending on the gas left, like GAS and CALL, get the same gas as
+    /// without batching. On by default, except for instrumented programs, which report
+    /// the gas left before each operation.
+    ///
+    /// The operations a block runs without enough gas for all of them then halt before
+    /// the first one. Like with [`Self::elide_stack_checks`], this only shows in the
+    /// [`HaltReason`] when one of them would have halted exceptionally first.
+    ///
+    /// [`HaltReason`]: crate::executor::HaltReason
+    pub batch_gas: bool,
+    /// Whether to keep the words pushed by runs of operations only moving words around
+    /// the stack or computing on them, like PUSH, DUP, SWAP and ADD, as SSA values, and
+    /// only store the ones left on the stack at the end of each run. On by default,
+    /// except for instrumented programs, which report the stack before each operation
+    pub promote_stack: bool,
+    /// Whether to fold constants and drop redundant operations of the program before
+    /// generating code for it, with the [peephole optimizer](crate::optimizer). Off by
+    /// default, and for instrumented programs, which report each operation as written
+    pub peephole: bool,
+    /// The pipeline of passes run over the LLVM IR, in the syntax of `opt -passes`, like
+    /// `"instcombine,gvn"`. By default, the one of the optimization level,
+    /// `default<ON>`.
+    ///
+    /// It's only run when generating code ahead of time, since the JIT optimizes the
+    /// code by the optimization level alone
+    pub llvm: Option<String>,
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Self {
+            canonicalize: true,
+            elide_stack_checks: true,
+            batch_gas: true,
+            promote_stack: true,
+            peephole: false,
+            llvm: None,
+        }
+    }
+}
+
+impl Passes {
+    /// Returns the pipeline of LLVM passes to run at the optimization level
+    pub fn llvm_pipeline(&self, opt_level: OptLevel) -> String {
+        match &self.llvm {
+            Some(pipeline) => pipeline.clone(),
+            None => format!("default<O{}>", opt_level.level()),
+        }
+    }
+}
+
+/// How the arithmetic on 256-bit words is lowered. Either way, the words are stored on
+/// the stack as 256-bit integers, and the operations other than additions, subtractions,
+/// bitwise operations and unsigned comparisons are lowered on them.
+///
+/// Which one gives faster code depends on the target, since LLVM splits 256-bit integers
+/// into the registers it has in its own way. The `workloads` benchmark compares them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum WordRepr {
+    /// Each operation is a single operation on 256-bit integers
+    #[default]
+    I256,
+    /// The words are split into four 64-bit limbs, operated on one at a time
+    Limbs64,
+}
+
+/// The stack of the program. The default is the one of the EVM, holding 1024 words, and
+/// checked for space before each push
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StackConfig {
+    /// The most words the stack holds. Pushing past it halts the program exceptionally
+    pub limit: usize,
+    pub overflow_check: OverflowCheck,
+}
+
+impl Default for StackConfig {
+    fn default() -> Self {
+        Self {
+            limit: MAX_STACK_SIZE,
+            overflow_check: OverflowCheck::default(),
+        }
+    }
+}
+
+/// How a push overflowing the stack is caught
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum OverflowCheck {
+    /// Each operation growing the stack checks it has space first, or its basic block
+    /// does at its entry
+    #[default]
+    Branch,
+    /// The stack is mapped right before a guard page, and the fault of pushing onto it
+    /// is caught by a signal handler, so nothing is checked before pushing. The
+    /// underflows are still checked.
+    ///
+    /// Only the [`Executor`] catches the fault, so the code compiled ahead of time must
+    /// be run through [`run_guarded`] instead of calling its main function.
+    ///
+    /// [`Executor`]: crate::executor::Executor
+    /// [`run_guarded`]: crate::stack_guard::run_guarded
+    GuardPage,
+}
+
+/// The machine the code is generated for. Each part left out is the host's, or the
+/// most generic one when compiling for another triple.
+///
+/// The JIT only generates code for the host, so other triples are only supported when
+/// generating code ahead of time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct Target {
+    /// The target triple, like `x86_64-unknown-linux-gnu`
+    pub triple: Option<String>,
+    /// The CPU, like `znver3`
+    pub cpu: Option<String>,
+    /// The CPU features to enable or disable, like `+avx2,-sse4a`
+    pub features: Option<String>,
+}
+
+impl Target {
+    /// Returns the host, which is the default target
+    pub fn host() -> Self {
+        Self::default()
+    }
+}
+
+/// Debug info locating the code generated for each operation at its line in a listing of
+/// the program, which is written while compiling it. Each line holds the offset of an
+/// operation in hex, and the operation, like:
+///
+/// ```text
+/// 0000    PUSH1 0x04
+/// 0002    JUMP
+/// 0003 => JUMPDEST
+/// ```
+///
+/// so the source shown by a debugger, or annotated by a profiler, is the EVM code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DebugInfo {
+    /// Where the listing is written, and which the debug info refers to
+    pub listing: PathBuf,
+}
+
+impl DebugInfo {
+    pub fn new(listing: impl AsRef<Path>) -> Self {
+        Self {
+            listing: listing.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Writes the listing of the program, with an operation per line
+    pub(crate) fn write_listing(&self, program: &Program) -> io::Result<()> {
+        let mut listing = String::new();
+        for (pc, operation) in program.iter_with_pcs() {
+            let marker = match operation {
+                Operation::Jumpdest { .. } => "=>",
+                _ => "  ",
+            };
+            listing.push_str(&format!("{pc:04x} {marker} {operation}\n"));
+        }
+        fs::write(&self.listing, listing)
+    }
+
+    /// Returns the location of the operation with the given index in the program, at its
+    /// line in the listing
+    pub(crate) fn location<'c>(&self, context: &'c MeliorContext, index: usize) -> Location<'c> {
+        Location::new(context, &self.listing.to_string_lossy(), index + 1, 1)
+    }
+}
+
+/// A stage of the pipeline at which the module can be dumped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IrStage {
+    /// The module generated from the program, before running any pass
+    BeforeLowering,
+    /// The module after running a lowering pass, given by its name and its position in
+    /// the pipeline, starting at 1
+    AfterPass { index: usize, name: String },
+    /// The module lowered to the LLVM dialect, after every pass
+    Lowered,
+}
+
+impl IrStage {
+    /// Returns the name of the file the stage is dumped to, in a directory.
+    /// Sorting the names gives the stages in order
+    pub fn file_name(&self) -> String {
+        match self {
+            IrStage::BeforeLowering => "00-before-lowering.mlir".to_string(),
+            IrStage::AfterPass { index, name } => format!("{index:02}-after-{name}.mlir"),
+            IrStage::Lowered => "99-lowered.mlir".to_string(),
+        }
+    }
+}
+
+/// Which stages of the pipeline to dump the module at, and where
+#[derive(Debug, Clone)]
+pub struct IrDump {
+    /// Whether to dump the module as generated from the program
+    pub before_lowering: bool,
+    /// Whether to dump the module after each lowering pass, which then run one at a time
+    pub after_each_pass: bool,
+    /// Whether to dump the module lowered to the LLVM dialect
+    pub lowered: bool,
+    pub sink: IrSink,
+}
+
+/// Where the module is dumped to
+#[derive(Clone)]
+pub enum IrSink {
+    /// Writes each stage to its own file in the directory, named by
+    /// [`IrStage::file_name`]. The directory is created if missing
+    Directory(PathBuf),
+    /// Calls the function with each stage and the module at that stage, as text
+    Callback(Arc<dyn Fn(&IrStage, &str) + Send + Sync>),
+}
+
+impl fmt::Debug for IrSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrSink::Directory(dir) => f.debug_tuple("Directory").field(dir).finish(),
+            IrSink::Callback(_) => f.debug_tuple("Callback").finish_non_exhaustive(),
+        }
+    }
+}
+
+impl IrDump {
+    /// Dumps every stage into files in the directory
+    pub fn to_directory(dir: impl AsRef<Path>) -> Self {
+        Self::every_stage(IrSink::Directory(dir.as_ref().to_path_buf()))
+    }
+
+    /// Dumps every stage to the callback
+    pub fn to_callback(callback: impl Fn(&IrStage, &str) + Send + Sync + 'static) -> Self {
+        Self::every_stage(IrSink::Callback(Arc::new(callback)))
+    }
+
+    fn every_stage(sink: IrSink) -> Self {
+        Self {
+            before_lowering: true,
+            after_each_pass: true,
+            lowered: true,
+            sink,
+        }
+    }
+
+    /// Returns whether the module is dumped at the stage
+    pub fn includes(&self, stage: &IrStage) -> bool {
+        match stage {
+            IrStage::BeforeLowering => self.before_lowering,
+            IrStage::AfterPass { .. } => self.after_each_pass,
+            IrStage::Lowered => self.lowered,
+        }
+    }
+
+    /// Dumps the module if the stage is included
+    pub(crate) fn dump(&self, stage: &IrStage, module: &MeliorModule) -> io::Result<()> {
+        if !self.includes(stage) {
+            return Ok(());
+        }
+        let ir = module.as_operation().to_string();
+        match &self.sink {
+            IrSink::Directory(dir) => {
+                fs::create_dir_all(dir)?;
+                fs::write(dir.join(stage.file_name()), ir)
+            }
+            IrSink::Callback(callback) => {
+                callback(stage, &ir);
+                Ok(())
+            }
+        }
+    }
+}
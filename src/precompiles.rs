@@ -0,0 +1,518 @@
+//! # Precompiled contracts
+//!
+//! Some accounts at low addresses have no code, but calling them runs a function
+//! implemented natively, which would be too expensive to run as EVM code. These are
+//! the precompiled contracts, or precompiles. Each [`Precompile`] charges gas depending
+//! on its input, and fails using all the gas it's given if the input is invalid.
+//!
+//! The calls made by the generated code reach them through
+//! [`SyscallContext`](crate::syscall::SyscallContext), which runs the precompile at the
+//! address called, if any, instead of loading the account's code.
+use bn::{AffineG1, AffineG2, Fq, Fq2, Fr, Group, Gt, G1, G2};
+use c_kzg::{ethereum_kzg_settings, Bytes32, Bytes48, KzgProof};
+use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use num_bigint::BigUint;
+use ripemd::Ripemd160;
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+use crate::{constants::gas_cost, env::Address, spec::SpecId};
+
+/// A precompiled contract, living at a fixed address
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Precompile {
+    /// Recovers the address that signed a message hash with secp256k1, at 0x01
+    Ecrecover,
+    /// Hashes its input with SHA-256, at 0x02
+    Sha256,
+    /// Hashes its input with RIPEMD-160, at 0x03
+    Ripemd160,
+    /// Returns its input, at 0x04
+    Identity,
+    /// Computes a power of an arbitrarily large number modulo another (EIP-198), at 0x05
+    Modexp,
+    /// Adds two points of the BN254 curve, also known as alt_bn128 (EIP-196), at 0x06
+    EcAdd,
+    /// Multiplies a point of the BN254 curve by a scalar (EIP-196), at 0x07
+    EcMul,
+    /// Checks a product of pairings of points on the BN254 curve and its twist (EIP-197),
+    /// at 0x08
+    EcPairing,
+    /// Runs the compression function F of BLAKE2b, for any number of rounds (EIP-152),
+    /// at 0x09
+    Blake2f,
+    /// Verifies a KZG proof of the value at a point of the polynomial committed to by a
+    /// blob (EIP-4844), at 0x0A
+    PointEvaluation,
+}
+
+impl Precompile {
+    /// All the precompiles, in the order of their addresses
+    pub const ALL: [Self; 10] = [
+        Precompile::Ecrecover,
+        Precompile::Sha256,
+        Precompile::Ripemd160,
+        Precompile::Identity,
+        Precompile::Modexp,
+        Precompile::EcAdd,
+        Precompile::EcMul,
+        Precompile::EcPairing,
+        Precompile::Blake2f,
+        Precompile::PointEvaluation,
+    ];
+
+    /// Returns the precompile at `address`, if there's one in the hardfork
+    pub fn at(address: &Address, spec_id: SpecId) -> Option<Self> {
+        let (index, zeros) = address.split_last()?;
+        if zeros.iter().any(|byte| *byte != 0) {
+            return None;
+        }
+        let precompile = match index {
+            0x01 => Precompile::Ecrecover,
+            0x02 => Precompile::Sha256,
+            0x03 => Precompile::Ripemd160,
+            0x04 => Precompile::Identity,
+            0x05 => Precompile::Modexp,
+            0x06 => Precompile::EcAdd,
+            0x07 => Precompile::EcMul,
+            0x08 => Precompile::EcPairing,
+            0x09 => Precompile::Blake2f,
+            0x0A => Precompile::PointEvaluation,
+            _ => return None,
+        };
+        spec_id
+            .includes(precompile.introduced_in())
+            .then_some(precompile)
+    }
+
+    /// Returns the address of the precompile
+    pub fn address(self) -> Address {
+        let mut address = Address::default();
+        address[19] = match self {
+            Precompile::Ecrecover => 0x01,
+            Precompile::Sha256 => 0x02,
+            Precompile::Ripemd160 => 0x03,
+            Precompile::Identity => 0x04,
+            Precompile::Modexp => 0x05,
+            Precompile::EcAdd => 0x06,
+            Precompile::EcMul => 0x07,
+            Precompile::EcPairing => 0x08,
+            Precompile::Blake2f => 0x09,
+            Precompile::PointEvaluation => 0x0A,
+        };
+        address
+    }
+
+    /// Returns the hardfork the precompile was introduced in
+    pub fn introduced_in(self) -> SpecId {
+        match self {
+            Precompile::Ecrecover
+            | Precompile::Sha256
+            | Precompile::Ripemd160
+            | Precompile::Identity => SpecId::Frontier,
+            Precompile::Modexp | Precompile::EcAdd | Precompile::EcMul | Precompile::EcPairing => {
+                SpecId::Byzantium
+            }
+            Precompile::Blake2f => SpecId::Istanbul,
+            Precompile::PointEvaluation => SpecId::Cancun,
+        }
+    }
+
+    /// Returns the gas it costs to run the precompile on `input`
+    pub fn gas_cost(self, input: &[u8], spec_id: SpecId) -> u64 {
+        let words = input.len().div_ceil(32) as i64;
+        // The BN254 precompiles got cheaper in Istanbul (EIP-1108)
+        let istanbul = spec_id.includes(SpecId::Istanbul);
+        let cost = match self {
+            Precompile::Ecrecover => gas_cost::ECRECOVER,
+            Precompile::Sha256 => gas_cost::SHA256 + gas_cost::SHA256_WORD * words,
+            Precompile::Ripemd160 => gas_cost::RIPEMD160 + gas_cost::RIPEMD160_WORD * words,
+            Precompile::Identity => gas_cost::IDENTITY + gas_cost::IDENTITY_WORD * words,
+            Precompile::Modexp => return modexp_gas_cost(input, spec_id),
+            Precompile::EcAdd if istanbul => gas_cost::EC_ADD,
+            Precompile::EcAdd => gas_cost::EC_ADD_PRE_ISTANBUL,
+            Precompile::EcMul if istanbul => gas_cost::EC_MUL,
+            Precompile::EcMul => gas_cost::EC_MUL_PRE_ISTANBUL,
+            Precompile::EcPairing => {
+                let pairs = (input.len() / EC_PAIRING_PAIR_SIZE) as i64;
+                if istanbul {
+                    gas_cost::EC_PAIRING + gas_cost::EC_PAIRING_PAIR * pairs
+                } else {
+                    gas_cost::EC_PAIRING_PRE_ISTANBUL
+                        + gas_cost::EC_PAIRING_PAIR_PRE_ISTANBUL * pairs
+                }
+            }
+            // Costs nothing if the input is too short to hold the number of rounds, as it
+            // fails anyway
+            Precompile::Blake2f => {
+                let rounds = input.get(..4).map_or(0, |rounds| {
+                    u32::from_be_bytes(rounds.try_into().unwrap()) as i64
+                });
+                gas_cost::BLAKE2F_ROUND * rounds
+            }
+            Precompile::PointEvaluation => gas_cost::POINT_EVALUATION,
+        };
+        cost as u64
+    }
+
+    /// Runs the precompile on `input`, returning its output, or [`None`] if the input
+    /// is invalid
+    pub fn execute(self, input: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Precompile::Ecrecover => Some(ecrecover(input)),
+            Precompile::Sha256 => Some(Sha256::digest(input).to_vec()),
+            Precompile::Ripemd160 => {
+                // The hash is left-padded to a word
+                let mut output = vec![0; 12];
+                output.extend(Ripemd160::digest(input));
+                Some(output)
+            }
+            Precompile::Identity => Some(input.to_vec()),
+            Precompile::Modexp => modexp(input),
+            Precompile::EcAdd => ec_add(input),
+            Precompile::EcMul => ec_mul(input),
+            Precompile::EcPairing => ec_pairing(input),
+            Precompile::Blake2f => blake2f(input),
+            Precompile::PointEvaluation => point_evaluation(input),
+        }
+    }
+
+    /// Runs the precompile on `input` with at most `gas_limit` gas, like a call to its
+    /// address does. Returns whether it succeeded, along with the gas left unused and its
+    /// output. Running out of gas or failing on an invalid input uses all the gas
+    pub fn run(self, input: &[u8], gas_limit: u64, spec_id: SpecId) -> (bool, u64, Vec<u8>) {
+        let cost = self.gas_cost(input, spec_id);
+        if cost > gas_limit {
+            return (false, 0, Vec::new());
+        }
+        match self.execute(input) {
+            Some(output) => (true, gas_limit - cost, output),
+            None => (false, 0, Vec::new()),
+        }
+    }
+}
+
+/// The size of each pair of points in the input of [`Precompile::EcPairing`]: a point of G1
+/// followed by one of G2
+const EC_PAIRING_PAIR_SIZE: usize = 192;
+
+/// The size of the input of [`Precompile::Blake2f`]: the number of rounds, the state, the
+/// message block, the offset counter and the final block flag
+const BLAKE2F_INPUT_SIZE: usize = 4 + 8 * 8 + 16 * 8 + 2 * 8 + 1;
+
+/// The initialization vector of BLAKE2b
+const BLAKE2B_IV: [u64; 8] = [
+    0x6A09E667F3BCC908,
+    0xBB67AE8584CAA73B,
+    0x3C6EF372FE94F82B,
+    0xA54FF53A5F1D36F1,
+    0x510E527FADE682D1,
+    0x9B05688C2B3E6C1F,
+    0x1F83D9ABFB41BD6B,
+    0x5BE0CD19137E2179,
+];
+
+/// The order in which each round of BLAKE2b reads the words of the message block, which
+/// repeats every ten rounds
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// The version byte of the versioned hashes of KZG commitments, which replaces the first
+/// byte of their SHA-256 hash (EIP-4844)
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// The number of field elements in a blob, making the degree of the polynomial it commits to
+const FIELD_ELEMENTS_PER_BLOB: u64 = 4096;
+
+/// The modulus of the scalar field of BLS12-381, which blobs are made of elements of
+const BLS_MODULUS: [u8; 32] = [
+    0x73, 0xED, 0xA7, 0x53, 0x29, 0x9D, 0x7D, 0x48, 0x33, 0x39, 0xD8, 0x08, 0x09, 0xA1, 0xD8, 0x05,
+    0x53, 0xBD, 0xA4, 0x02, 0xFF, 0xFE, 0x5B, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x01,
+];
+
+/// Returns the `len` bytes of `input` starting at `offset`, which are zero past its end
+fn padded(input: &[u8], offset: usize, len: usize) -> Vec<u8> {
+    let mut padded = vec![0; len];
+    let start = offset.min(input.len());
+    let available = (input.len() - start).min(len);
+    padded[..available].copy_from_slice(&input[start..start + available]);
+    padded
+}
+
+/// Reads the word of `input` at `offset` as a length, or [`None`] if it doesn't fit in a
+/// `u64`
+fn read_length(input: &[u8], offset: usize) -> Option<u64> {
+    let word = padded(input, offset, 32);
+    let (high, low) = word.split_at(24);
+    high.iter()
+        .all(|byte| *byte == 0)
+        .then(|| u64::from_be_bytes(low.try_into().unwrap()))
+}
+
+/// Takes the hash of a message, followed by the `v`, `r` and `s` values of its signature,
+/// each as a 32-byte word. Returns the address that signed it, left-padded to a word, or
+/// nothing if the signature is invalid, which doesn't make the call fail
+fn ecrecover(input: &[u8]) -> Vec<u8> {
+    let input = padded(input, 0, 128);
+    let (hash, v, signature) = (&input[..32], &input[32..64], &input[64..]);
+
+    // `v` is 27 or 28, telling which of the two possible points `r` is the x of
+    if v[..31].iter().any(|byte| *byte != 0) || !matches!(v[31], 27 | 28) {
+        return Vec::new();
+    }
+    let Ok(mut signature) = Signature::from_slice(signature) else {
+        return Vec::new();
+    };
+    let mut recovery_id = v[31] - 27;
+    // The library only takes the signatures with the lower `s`, which are equivalent to
+    // the ones with the higher `s` for the other point
+    if let Some(normalized) = signature.normalize_s() {
+        signature = normalized;
+        recovery_id ^= 1;
+    }
+    let recovery_id = RecoveryId::from_byte(recovery_id).unwrap();
+    let Ok(key) = VerifyingKey::recover_from_prehash(hash, &signature, recovery_id) else {
+        return Vec::new();
+    };
+
+    // The address is the last 20 bytes of the hash of the uncompressed public key,
+    // without its prefix
+    let mut output = Keccak256::digest(&key.to_encoded_point(false).as_bytes()[1..]).to_vec();
+    output[..12].fill(0);
+    output
+}
+
+/// Returns the cost of [`modexp`], which grows with the square of the length of the
+/// base or the modulus, and with the bit length of the exponent. Lengths that don't fit in
+/// a `u64` cost more gas than there can be
+fn modexp_gas_cost(input: &[u8], spec_id: SpecId) -> u64 {
+    let [Some(base_len), Some(exp_len), Some(mod_len)] =
+        [0, 32, 64].map(|offset| read_length(input, offset))
+    else {
+        return u64::MAX;
+    };
+    let max_len = base_len.max(mod_len) as u128;
+
+    // Counts the bits of the exponent past its highest one, with the bytes past its first
+    // word counting as eight bits each
+    let exp_offset = usize::try_from(base_len.saturating_add(96)).unwrap_or(usize::MAX);
+    let exp_head = BigUint::from_bytes_be(&padded(input, exp_offset, exp_len.min(32) as usize));
+    let exp_bits = exp_head.bits().saturating_sub(1) as u128;
+    let iterations = (exp_bits + 8 * exp_len.saturating_sub(32) as u128).max(1);
+
+    let cost = if spec_id.includes(SpecId::Berlin) {
+        // The multiplications are counted in words of 8 bytes (EIP-2565)
+        let words = max_len.div_ceil(8);
+        let cost = (words * words).saturating_mul(iterations) / 3;
+        cost.max(gas_cost::MODEXP as u128)
+    } else {
+        let complexity = match max_len {
+            len if len <= 64 => len * len,
+            len if len <= 1024 => len * len / 4 + 96 * len - 3072,
+            len => len * len / 16 + 480 * len - 199680,
+        };
+        complexity.saturating_mul(iterations) / 20
+    };
+    u64::try_from(cost).unwrap_or(u64::MAX)
+}
+
+/// Takes the lengths of the base, the exponent and the modulus, each as a word, followed
+/// by the three numbers as big-endian bytes. Returns the base to the power of the exponent
+/// modulo the modulus, with the length of the modulus, or zero if the modulus is zero
+fn modexp(input: &[u8]) -> Option<Vec<u8>> {
+    let lengths = [0, 32, 64].map(|offset| read_length(input, offset));
+    let [Some(base_len), Some(exp_len), Some(mod_len)] =
+        lengths.map(|len| len.and_then(|len| usize::try_from(len).ok()))
+    else {
+        return None;
+    };
+    // Without a modulus there's nothing to compute, however long the other numbers are
+    if mod_len == 0 {
+        return Some(Vec::new());
+    }
+    let exp_offset = base_len.checked_add(96)?;
+    let mod_offset = exp_offset.checked_add(exp_len)?;
+
+    let base = BigUint::from_bytes_be(&padded(input, 96, base_len));
+    let exponent = BigUint::from_bytes_be(&padded(input, exp_offset, exp_len));
+    let modulus = BigUint::from_bytes_be(&padded(input, mod_offset, mod_len));
+    if modulus == BigUint::default() {
+        return Some(vec![0; mod_len]);
+    }
+
+    let result = base.modpow(&exponent, &modulus).to_bytes_be();
+    let mut output = vec![0; mod_len];
+    output[mod_len - result.len()..].copy_from_slice(&result);
+    Some(output)
+}
+
+/// Reads a point of BN254's G1 from its coordinates, each a word, where (0, 0) is the point
+/// at infinity. Returns [`None`] if a coordinate is past the field's modulus, or the point
+/// isn't on the curve
+fn read_g1(input: &[u8]) -> Option<G1> {
+    let x = Fq::from_slice(&input[..32]).ok()?;
+    let y = Fq::from_slice(&input[32..64]).ok()?;
+    if x == Fq::zero() && y == Fq::zero() {
+        return Some(G1::zero());
+    }
+    AffineG1::new(x, y).ok().map(G1::from)
+}
+
+/// Reads a point of G2, on BN254's twist, like [`read_g1`]. Its coordinates are in Fq2,
+/// each written with its imaginary part first
+fn read_g2(input: &[u8]) -> Option<G2> {
+    let read_fq2 = |offset: usize| -> Option<Fq2> {
+        let imaginary = Fq::from_slice(&input[offset..offset + 32]).ok()?;
+        let real = Fq::from_slice(&input[offset + 32..offset + 64]).ok()?;
+        Some(Fq2::new(real, imaginary))
+    };
+    let (x, y) = (read_fq2(0)?, read_fq2(64)?);
+    if x == Fq2::zero() && y == Fq2::zero() {
+        return Some(G2::zero());
+    }
+    AffineG2::new(x, y).ok().map(G2::from)
+}
+
+/// Writes a point of G1 as its coordinates, like [`read_g1`] reads it
+fn write_g1(point: G1) -> Vec<u8> {
+    let mut output = vec![0; 64];
+    if let Some(point) = AffineG1::from_jacobian(point) {
+        point.x().to_big_endian(&mut output[..32]).unwrap();
+        point.y().to_big_endian(&mut output[32..]).unwrap();
+    }
+    output
+}
+
+/// Takes two points of G1, and returns their sum
+fn ec_add(input: &[u8]) -> Option<Vec<u8>> {
+    let input = padded(input, 0, 128);
+    Some(write_g1(read_g1(&input[..64])? + read_g1(&input[64..])?))
+}
+
+/// Takes a point of G1 followed by a scalar as a word, and returns their product
+fn ec_mul(input: &[u8]) -> Option<Vec<u8>> {
+    let input = padded(input, 0, 96);
+    // The scalar is reduced modulo the order of the group, so it's never invalid
+    let scalar = Fr::from_slice(&input[64..]).ok()?;
+    Some(write_g1(read_g1(&input[..64])? * scalar))
+}
+
+/// Takes any number of pairs of a point of G1 and one of G2. Returns one as a word if the
+/// product of their pairings is one, which it is for no pairs, or zero otherwise
+fn ec_pairing(input: &[u8]) -> Option<Vec<u8>> {
+    if input.len() % EC_PAIRING_PAIR_SIZE != 0 {
+        return None;
+    }
+    let pairs = input
+        .chunks(EC_PAIRING_PAIR_SIZE)
+        .map(|pair| Some((read_g1(&pair[..64])?, read_g2(&pair[64..])?)))
+        .collect::<Option<Vec<_>>>()?;
+
+    let mut output = vec![0; 32];
+    output[31] = (pairs.is_empty() || bn::pairing_batch(&pairs) == Gt::one()) as u8;
+    Some(output)
+}
+
+/// Takes the number of rounds as a big-endian `u32`, followed by the state as 8 words and
+/// the message block as 16 words, the offset counter as 2 words, and a byte flagging the
+/// final block, with each word a little-endian `u64`. Returns the state after compressing
+/// the block, or [`None`] if the input isn't exactly that long or the flag isn't 0 or 1
+fn blake2f(input: &[u8]) -> Option<Vec<u8>> {
+    if input.len() != BLAKE2F_INPUT_SIZE || input[BLAKE2F_INPUT_SIZE - 1] > 1 {
+        return None;
+    }
+    let rounds = u32::from_be_bytes(input[..4].try_into().unwrap());
+    let mut words = input[4..BLAKE2F_INPUT_SIZE - 1]
+        .chunks(8)
+        .map(|word| u64::from_le_bytes(word.try_into().unwrap()));
+    let mut state: [u64; 8] = std::array::from_fn(|_| words.next().unwrap());
+    let message: [u64; 16] = std::array::from_fn(|_| words.next().unwrap());
+    let counter: [u64; 2] = std::array::from_fn(|_| words.next().unwrap());
+    let is_final = input[BLAKE2F_INPUT_SIZE - 1] == 1;
+
+    let mut v = [0; 16];
+    v[..8].copy_from_slice(&state);
+    v[8..].copy_from_slice(&BLAKE2B_IV);
+    v[12] ^= counter[0];
+    v[13] ^= counter[1];
+    if is_final {
+        v[14] = !v[14];
+    }
+    for round in 0..rounds as usize {
+        let s = &BLAKE2B_SIGMA[round % 10];
+        blake2b_mix(&mut v, [0, 4, 8, 12], message[s[0]], message[s[1]]);
+        blake2b_mix(&mut v, [1, 5, 9, 13], message[s[2]], message[s[3]]);
+        blake2b_mix(&mut v, [2, 6, 10, 14], message[s[4]], message[s[5]]);
+        blake2b_mix(&mut v, [3, 7, 11, 15], message[s[6]], message[s[7]]);
+        blake2b_mix(&mut v, [0, 5, 10, 15], message[s[8]], message[s[9]]);
+        blake2b_mix(&mut v, [1, 6, 11, 12], message[s[10]], message[s[11]]);
+        blake2b_mix(&mut v, [2, 7, 8, 13], message[s[12]], message[s[13]]);
+        blake2b_mix(&mut v, [3, 4, 9, 14], message[s[14]], message[s[15]]);
+    }
+    for (index, word) in state.iter_mut().enumerate() {
+        *word ^= v[index] ^ v[index + 8];
+    }
+
+    Some(state.iter().flat_map(|word| word.to_le_bytes()).collect())
+}
+
+/// The mixing function G of BLAKE2b, mixing two words of the message block into the four
+/// words of the working vector `v` at `indices`
+fn blake2b_mix(v: &mut [u64; 16], indices: [usize; 4], x: u64, y: u64) {
+    let [a, b, c, d] = indices;
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+    v[d] = (v[d] ^ v[a]).rotate_right(32);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(24);
+    v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+    v[d] = (v[d] ^ v[a]).rotate_right(16);
+    v[c] = v[c].wrapping_add(v[d]);
+    v[b] = (v[b] ^ v[c]).rotate_right(63);
+}
+
+/// Takes the versioned hash of a KZG commitment, a point `z` and the claimed value `y` of
+/// the committed polynomial at it, each as a word, followed by the commitment and the
+/// proof, of 48 bytes each. Returns the number of field elements in a blob followed by the
+/// modulus of the field, each as a word, or [`None`] if the commitment doesn't match the
+/// hash, or the proof is invalid
+fn point_evaluation(input: &[u8]) -> Option<Vec<u8>> {
+    if input.len() != 192 {
+        return None;
+    }
+    let (versioned_hash, z, y) = (&input[..32], &input[32..64], &input[64..96]);
+    let (commitment, proof) = (&input[96..144], &input[144..]);
+
+    let mut commitment_hash = Sha256::digest(commitment).to_vec();
+    commitment_hash[0] = VERSIONED_HASH_VERSION_KZG;
+    if commitment_hash != versioned_hash {
+        return None;
+    }
+    // The points and values past the modulus are invalid, as well as the commitments and
+    // proofs that aren't points of G1
+    let valid = KzgProof::verify_kzg_proof(
+        &Bytes48::from_bytes(commitment).ok()?,
+        &Bytes32::from_bytes(z).ok()?,
+        &Bytes32::from_bytes(y).ok()?,
+        &Bytes48::from_bytes(proof).ok()?,
+        ethereum_kzg_settings(),
+    )
+    .ok()?;
+    if !valid {
+        return None;
+    }
+
+    let mut output = vec![0; 64];
+    output[24..32].copy_from_slice(&FIELD_ELEMENTS_PER_BLOB.to_be_bytes());
+    output[32..].copy_from_slice(&BLS_MODULUS);
+    Some(output)
+}
@@ -1,6 +1,18 @@
+use std::{borrow::Cow, collections::HashMap, fmt};
+
 use num_bigint::BigUint;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assembly,
+    constants::gas_cost,
+    eof::{opcodes, SectionType},
+    errors::{AssemblyError, ParseError, ProgramError},
+    spec::SpecId,
+};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Opcode {
     STOP = 0x00,
     ADD = 0x01,
@@ -24,57 +36,57 @@ pub enum Opcode {
     AND = 0x16,
     OR = 0x17,
     XOR = 0x18,
-    // NOT = 0x19,
+    NOT = 0x19,
     BYTE = 0x1A,
     SHL = 0x1B,
     SHR = 0x1C,
     SAR = 0x1D,
     // unused 0x1E-0x1F
-    // KECCAK256 = 0x20,
+    KECCAK256 = 0x20,
     // unused 0x21-0x2F
-    // ADDRESS = 0x30,
-    // BALANCE = 0x31,
-    // ORIGIN = 0x32,
-    // CALLER = 0x33,
-    // CALLVALUE = 0x34,
-    // CALLDATALOAD = 0x35,
-    // CALLDATASIZE = 0x36,
-    // CALLDATACOPY = 0x37,
-    // CODESIZE = 0x38,
-    // CODECOPY = 0x39,
-    // GASPRICE = 0x3A,
-    // EXTCODESIZE = 0x3B,
-    // EXTCODECOPY = 0x3C,
-    // RETURNDATASIZE = 0x3D,
-    // RETURNDATACOPY = 0x3E,
-    // EXTCODEHASH = 0x3F,
-    // BLOCKHASH = 0x40,
-    // COINBASE = 0x41,
-    // TIMESTAMP = 0x42,
-    // NUMBER = 0x43,
-    // DIFFICULTY = 0x44,
-    // GASLIMIT = 0x45,
-    // CHAINID = 0x46,
-    // SELFBALANCE = 0x47,
-    // BASEFEE = 0x48,
-    // BLOBHASH = 0x49,
-    // BLOBBASEFEE = 0x4A,
+    ADDRESS = 0x30,
+    BALANCE = 0x31,
+    ORIGIN = 0x32,
+    CALLER = 0x33,
+    CALLVALUE = 0x34,
+    CALLDATALOAD = 0x35,
+    CALLDATASIZE = 0x36,
+    CALLDATACOPY = 0x37,
+    CODESIZE = 0x38,
+    CODECOPY = 0x39,
+    GASPRICE = 0x3A,
+    EXTCODESIZE = 0x3B,
+    EXTCODECOPY = 0x3C,
+    RETURNDATASIZE = 0x3D,
+    RETURNDATACOPY = 0x3E,
+    EXTCODEHASH = 0x3F,
+    BLOCKHASH = 0x40,
+    COINBASE = 0x41,
+    TIMESTAMP = 0x42,
+    NUMBER = 0x43,
+    PREVRANDAO = 0x44,
+    GASLIMIT = 0x45,
+    CHAINID = 0x46,
+    SELFBALANCE = 0x47,
+    BASEFEE = 0x48,
+    BLOBHASH = 0x49,
+    BLOBBASEFEE = 0x4A,
     // unused 0x4B-0x4F
     POP = 0x50,
-    // MLOAD = 0x51,
+    MLOAD = 0x51,
     MSTORE = 0x52,
     MSTORE8 = 0x53,
-    // SLOAD = 0x54,
-    // SSTORE = 0x55,
+    SLOAD = 0x54,
+    SSTORE = 0x55,
     JUMP = 0x56,
     JUMPI = 0x57,
     PC = 0x58,
-    // MSIZE = 0x59,
+    MSIZE = 0x59,
     GAS = 0x5A,
     JUMPDEST = 0x5B,
-    // TLOAD = 0x5C,
-    // TSTORE = 0x5D,
-    // MCOPY = 0x5E,
+    TLOAD = 0x5C,
+    TSTORE = 0x5D,
+    MCOPY = 0x5E,
     PUSH0 = 0x5F,
     PUSH1 = 0x60,
     PUSH2 = 0x61,
@@ -140,24 +152,24 @@ pub enum Opcode {
     SWAP14 = 0x9D,
     SWAP15 = 0x9E,
     SWAP16 = 0x9F,
-    // LOG0 = 0xA0,
-    // LOG1 = 0xA1,
-    // LOG2 = 0xA2,
-    // LOG3 = 0xA3,
-    // LOG4 = 0xA4,
+    LOG0 = 0xA0,
+    LOG1 = 0xA1,
+    LOG2 = 0xA2,
+    LOG3 = 0xA3,
+    LOG4 = 0xA4,
     // unused 0xA5-0xEF
-    // CREATE = 0xF0,
-    // CALL = 0xF1,
-    // CALLCODE = 0xF2,
+    CREATE = 0xF0,
+    CALL = 0xF1,
+    CALLCODE = 0xF2,
     RETURN = 0xF3,
-    // DELEGATECALL = 0xF4,
-    // CREATE2 = 0xF5,
+    DELEGATECALL = 0xF4,
+    CREATE2 = 0xF5,
     // unused 0xF6-0xF9
-    // STATICCALL = 0xFA,
+    STATICCALL = 0xFA,
     // unused 0xFB-0xFC
-    // REVERT = 0xFD,
-    // INVALID = 0xFE,
-    // SELFDESTRUCT = 0xFF,
+    REVERT = 0xFD,
+    INVALID = 0xFE,
+    SELFDESTRUCT = 0xFF,
     UNUSED,
 }
 
@@ -185,6 +197,7 @@ impl From<u8> for Opcode {
             x if x == Opcode::AND as u8 => Opcode::AND,
             x if x == Opcode::OR as u8 => Opcode::OR,
             x if x == Opcode::XOR as u8 => Opcode::XOR,
+            x if x == Opcode::NOT as u8 => Opcode::NOT,
             x if x == Opcode::BYTE as u8 => Opcode::BYTE,
             x if x == Opcode::SHR as u8 => Opcode::SHR,
             x if x == Opcode::SHL as u8 => Opcode::SHL,
@@ -260,15 +273,82 @@ impl From<u8> for Opcode {
             x if x == Opcode::SWAP14 as u8 => Opcode::SWAP14,
             x if x == Opcode::SWAP15 as u8 => Opcode::SWAP15,
             x if x == Opcode::SWAP16 as u8 => Opcode::SWAP16,
+            x if x == Opcode::LOG0 as u8 => Opcode::LOG0,
+            x if x == Opcode::LOG1 as u8 => Opcode::LOG1,
+            x if x == Opcode::LOG2 as u8 => Opcode::LOG2,
+            x if x == Opcode::LOG3 as u8 => Opcode::LOG3,
+            x if x == Opcode::LOG4 as u8 => Opcode::LOG4,
+            x if x == Opcode::ADDRESS as u8 => Opcode::ADDRESS,
+            x if x == Opcode::BALANCE as u8 => Opcode::BALANCE,
+            x if x == Opcode::ORIGIN as u8 => Opcode::ORIGIN,
+            x if x == Opcode::CALLER as u8 => Opcode::CALLER,
+            x if x == Opcode::CALLVALUE as u8 => Opcode::CALLVALUE,
+            x if x == Opcode::GASPRICE as u8 => Opcode::GASPRICE,
+            x if x == Opcode::EXTCODESIZE as u8 => Opcode::EXTCODESIZE,
+            x if x == Opcode::EXTCODECOPY as u8 => Opcode::EXTCODECOPY,
+            x if x == Opcode::EXTCODEHASH as u8 => Opcode::EXTCODEHASH,
+            x if x == Opcode::RETURNDATASIZE as u8 => Opcode::RETURNDATASIZE,
+            x if x == Opcode::RETURNDATACOPY as u8 => Opcode::RETURNDATACOPY,
+            x if x == Opcode::BLOCKHASH as u8 => Opcode::BLOCKHASH,
+            x if x == Opcode::COINBASE as u8 => Opcode::COINBASE,
+            x if x == Opcode::TIMESTAMP as u8 => Opcode::TIMESTAMP,
+            x if x == Opcode::NUMBER as u8 => Opcode::NUMBER,
+            x if x == Opcode::PREVRANDAO as u8 => Opcode::PREVRANDAO,
+            x if x == Opcode::GASLIMIT as u8 => Opcode::GASLIMIT,
+            x if x == Opcode::CHAINID as u8 => Opcode::CHAINID,
+            x if x == Opcode::SELFBALANCE as u8 => Opcode::SELFBALANCE,
+            x if x == Opcode::BASEFEE as u8 => Opcode::BASEFEE,
+            x if x == Opcode::BLOBHASH as u8 => Opcode::BLOBHASH,
+            x if x == Opcode::BLOBBASEFEE as u8 => Opcode::BLOBBASEFEE,
+            x if x == Opcode::CREATE as u8 => Opcode::CREATE,
+            x if x == Opcode::CALL as u8 => Opcode::CALL,
+            x if x == Opcode::CALLCODE as u8 => Opcode::CALLCODE,
             x if x == Opcode::RETURN as u8 => Opcode::RETURN,
+            x if x == Opcode::DELEGATECALL as u8 => Opcode::DELEGATECALL,
+            x if x == Opcode::CREATE2 as u8 => Opcode::CREATE2,
+            x if x == Opcode::STATICCALL as u8 => Opcode::STATICCALL,
+            x if x == Opcode::REVERT as u8 => Opcode::REVERT,
+            x if x == Opcode::INVALID as u8 => Opcode::INVALID,
+            x if x == Opcode::SELFDESTRUCT as u8 => Opcode::SELFDESTRUCT,
+            x if x == Opcode::SLOAD as u8 => Opcode::SLOAD,
+            x if x == Opcode::SSTORE as u8 => Opcode::SSTORE,
+            x if x == Opcode::TLOAD as u8 => Opcode::TLOAD,
+            x if x == Opcode::TSTORE as u8 => Opcode::TSTORE,
             x if x == Opcode::MSTORE as u8 => Opcode::MSTORE,
             x if x == Opcode::MSTORE8 as u8 => Opcode::MSTORE8,
+            x if x == Opcode::MLOAD as u8 => Opcode::MLOAD,
+            x if x == Opcode::MSIZE as u8 => Opcode::MSIZE,
+            x if x == Opcode::MCOPY as u8 => Opcode::MCOPY,
+            x if x == Opcode::KECCAK256 as u8 => Opcode::KECCAK256,
+            x if x == Opcode::CALLDATALOAD as u8 => Opcode::CALLDATALOAD,
+            x if x == Opcode::CALLDATASIZE as u8 => Opcode::CALLDATASIZE,
+            x if x == Opcode::CALLDATACOPY as u8 => Opcode::CALLDATACOPY,
+            x if x == Opcode::CODESIZE as u8 => Opcode::CODESIZE,
+            x if x == Opcode::CODECOPY as u8 => Opcode::CODECOPY,
             _ => Opcode::UNUSED,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// How an operation changes the stack, as the items it takes from the top and the
+/// items it leaves there in their place
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackEffect {
+    /// The items that must be on the stack before the operation
+    pub inputs: usize,
+    /// The items replacing the inputs after the operation
+    pub outputs: usize,
+}
+
+impl StackEffect {
+    /// Returns how much the stack grows, negative if it shrinks
+    pub fn difference(&self) -> isize {
+        self.outputs as isize - self.inputs as isize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Operation {
     Stop,
     Add,
@@ -291,6 +371,7 @@ pub enum Operation {
     And,
     Or,
     Xor,
+    Not,
     Byte,
     Shr,
     Shl,
@@ -298,298 +379,1257 @@ pub enum Operation {
     Pop,
     Jump,
     Jumpi,
-    PC { pc: usize },
+    PC {
+        pc: usize,
+    },
     Gas,
-    Jumpdest { pc: usize },
+    Jumpdest {
+        pc: usize,
+    },
     Push0,
-    Push(BigUint),
+    /// A PUSH of the value, with the smallest PUSHN holding it
+    Push(#[cfg_attr(feature = "serde", serde(with = "hex_value"))] BigUint),
+    /// A PUSHN with an immediate of N bytes, wider than the value needs, like `PUSH2 0x0004`.
+    /// It keeps the offsets of the operations after it as in the bytecode it came from
+    PushN(
+        u8,
+        #[cfg_attr(feature = "serde", serde(with = "hex_value"))] BigUint,
+    ),
     Dup(u32),
     Swap(u32),
     Return,
     Mstore,
     Mstore8,
+    Mload,
+    Msize,
+    Mcopy,
+    Keccak256,
+    CallDataLoad,
+    CallDataSize,
+    CallDataCopy,
+    Codesize,
+    Codecopy,
+    Revert,
+    Invalid,
+    /// A byte which isn't an opcode. It halts like INVALID when executed, and is kept so
+    /// the code encodes back to the same bytes
+    Undefined(u8),
+    Sload,
+    Sstore,
+    Tload,
+    Tstore,
+    Log(u8),
+    Address,
+    Balance,
+    Origin,
+    Caller,
+    Callvalue,
+    Gasprice,
+    Coinbase,
+    Timestamp,
+    Number,
+    Prevrandao,
+    Gaslimit,
+    Chainid,
+    Selfbalance,
+    Basefee,
+    Blockhash,
+    Extcodesize,
+    Extcodecopy,
+    Extcodehash,
+    Returndatasize,
+    Returndatacopy,
+    Blobhash,
+    Blobbasefee,
+    Call,
+    Callcode,
+    Delegatecall,
+    Staticcall,
+    Create,
+    Create2,
+    Selfdestruct,
+    /// An RJUMP, jumping by the offset from the end of its immediate (EIP-4200). The
+    /// relative jumps are only defined in EOF code, see [`crate::eof`]
+    Rjump(i16),
+    /// An RJUMPI, jumping by the offset if the top item isn't zero
+    Rjumpi(i16),
+    /// An RJUMPV, jumping by the offset at the index on top of the stack, or going on to
+    /// the next operation if there's none. It has 1 to 256 offsets
+    Rjumpv(Vec<i16>),
+    /// A CALLF, calling the EOF code section with the given index and type (EIP-4750).
+    /// The section takes its inputs from the stack, and leaves its outputs there
+    Callf(u16, SectionType),
+    /// A RETF, returning from the code section to the one which called it
+    Retf,
+    /// A JUMPF, going on into the code section with the given index and type (EIP-6206).
+    /// Unlike CALLF, it doesn't come back, so a RETF there returns from this section
+    Jumpf(u16, SectionType),
+    /// A DATALOAD, pushing the word of the EOF data section at the offset on top of the
+    /// stack (EIP-7480). The bytes past the end of the data read as zeros
+    Dataload,
+    /// A DATALOADN, pushing the word of the data section at the given offset
+    Dataloadn(u16),
+    /// A DATASIZE, pushing the size of the data section
+    Datasize,
+    /// A DATACOPY, copying from the data section to memory as CALLDATACOPY does from the
+    /// calldata
+    Datacopy,
+    /// A DUPN, pushing a copy of the item at depth immediate + 1 (EIP-663), reaching
+    /// deeper than DUP16 does
+    Dupn(u8),
+    /// A SWAPN, swapping the top item with the one at depth immediate + 2
+    Swapn(u8),
+    /// An EXCHANGE, swapping the items at depths n + 1 and n + m + 1, where n and m are
+    /// the high and low nibbles of the immediate plus one
+    Exchange(u8),
+    /// A JUMPDEST placed under a name, which [`Operation::PushLabel`] pushes the offset of.
+    /// Labels are resolved with [`Program::resolve_labels`], which compiling the program
+    /// does, and the other passes over a program expect them resolved
+    Label(String),
+    /// A PUSH of the offset of the label with the given name, with the smallest PUSHN
+    /// holding it
+    PushLabel(String),
+}
+
+impl Operation {
+    /// Returns a PUSH of the value, or an error if it doesn't fit in a word
+    pub fn push(value: impl Into<BigUint>) -> Result<Self, ProgramError> {
+        let value = value.into();
+        if value.bits() > 256 {
+            let size = value.bits().div_ceil(8) as usize;
+            return Err(ProgramError::InvalidPushSize { size });
+        }
+        Ok(Operation::Push(value))
+    }
+
+    /// Returns a PUSHN of the big-endian immediate, N being its length. The immediate
+    /// must be 1 to 32 bytes long
+    pub fn push_n(immediate: &[u8]) -> Result<Self, ProgramError> {
+        if !(1..=32).contains(&immediate.len()) {
+            let size = immediate.len();
+            return Err(ProgramError::InvalidPushSize { size });
+        }
+        Ok(Self::sized_push(immediate))
+    }
+
+    /// Returns a PUSH32 of the big-endian immediate, which always fits in a word
+    pub fn push32(immediate: [u8; 32]) -> Self {
+        Self::sized_push(&immediate)
+    }
+
+    /// Returns a PUSH keeping the width of the immediate, which is [`Operation::Push`]
+    /// unless it has leading zeros
+    fn sized_push(immediate: &[u8]) -> Self {
+        let value = BigUint::from_bytes_be(immediate);
+        if value.to_bytes_be().len() == immediate.len() {
+            Operation::Push(value)
+        } else {
+            Operation::PushN(immediate.len() as u8, value)
+        }
+    }
+
+    /// Returns the hardfork that introduced the operation.
+    /// In earlier hardforks its opcode is undefined
+    pub fn introduced_in(&self) -> SpecId {
+        match self {
+            Operation::Delegatecall => SpecId::Homestead,
+            Operation::Revert
+            | Operation::Returndatasize
+            | Operation::Returndatacopy
+            | Operation::Staticcall => SpecId::Byzantium,
+            Operation::Shl
+            | Operation::Shr
+            | Operation::Sar
+            | Operation::Create2
+            | Operation::Extcodehash => SpecId::Constantinople,
+            Operation::Chainid | Operation::Selfbalance => SpecId::Istanbul,
+            Operation::Basefee => SpecId::London,
+            Operation::Push0 => SpecId::Shanghai,
+            Operation::Tload
+            | Operation::Tstore
+            | Operation::Mcopy
+            | Operation::Blobhash
+            | Operation::Blobbasefee => SpecId::Cancun,
+            Operation::Rjump(_)
+            | Operation::Rjumpi(_)
+            | Operation::Rjumpv(_)
+            | Operation::Callf(..)
+            | Operation::Retf
+            | Operation::Jumpf(..)
+            | Operation::Dataload
+            | Operation::Dataloadn(_)
+            | Operation::Datasize
+            | Operation::Datacopy
+            | Operation::Dupn(_)
+            | Operation::Swapn(_)
+            | Operation::Exchange(_) => SpecId::Osaka,
+            _ => SpecId::Frontier,
+        }
+    }
+
+    /// Returns the items the operation needs on the stack and the ones it leaves.
+    ///
+    /// DUPN and SWAPN count all the items they reach as inputs, so a DUP2 takes 2 items
+    /// and leaves 3, and a SWAP2 takes 3 and leaves 3.
+    pub fn stack_effect(&self) -> StackEffect {
+        let (inputs, outputs) = match self {
+            Operation::Stop
+            | Operation::Jumpdest { .. }
+            | Operation::Label(_)
+            | Operation::Invalid
+            | Operation::Undefined(_)
+            | Operation::Rjump(_)
+            | Operation::Retf => (0, 0),
+            Operation::PC { .. }
+            | Operation::PushLabel(_)
+            | Operation::Gas
+            | Operation::Push0
+            | Operation::Push(_)
+            | Operation::PushN(..)
+            | Operation::Msize
+            | Operation::CallDataSize
+            | Operation::Codesize
+            | Operation::Address
+            | Operation::Origin
+            | Operation::Caller
+            | Operation::Callvalue
+            | Operation::Gasprice
+            | Operation::Coinbase
+            | Operation::Timestamp
+            | Operation::Number
+            | Operation::Prevrandao
+            | Operation::Gaslimit
+            | Operation::Chainid
+            | Operation::Selfbalance
+            | Operation::Basefee
+            | Operation::Returndatasize
+            | Operation::Blobbasefee
+            | Operation::Dataloadn(_)
+            | Operation::Datasize => (0, 1),
+            Operation::Pop
+            | Operation::Jump
+            | Operation::Selfdestruct
+            | Operation::Rjumpi(_)
+            | Operation::Rjumpv(_) => (1, 0),
+            Operation::IsZero
+            | Operation::Not
+            | Operation::Mload
+            | Operation::CallDataLoad
+            | Operation::Sload
+            | Operation::Tload
+            | Operation::Blockhash
+            | Operation::Balance
+            | Operation::Extcodesize
+            | Operation::Extcodehash
+            | Operation::Blobhash
+            | Operation::Dataload => (1, 1),
+            Operation::Jumpi
+            | Operation::Return
+            | Operation::Revert
+            | Operation::Mstore
+            | Operation::Mstore8
+            | Operation::Sstore
+            | Operation::Tstore => (2, 0),
+            Operation::Add
+            | Operation::Mul
+            | Operation::Sub
+            | Operation::Div
+            | Operation::Sdiv
+            | Operation::Mod
+            | Operation::SMod
+            | Operation::Exp
+            | Operation::SignExtend
+            | Operation::Lt
+            | Operation::Gt
+            | Operation::Slt
+            | Operation::Sgt
+            | Operation::Eq
+            | Operation::And
+            | Operation::Or
+            | Operation::Xor
+            | Operation::Byte
+            | Operation::Shr
+            | Operation::Shl
+            | Operation::Sar
+            | Operation::Keccak256 => (2, 1),
+            Operation::Mcopy
+            | Operation::CallDataCopy
+            | Operation::Codecopy
+            | Operation::Returndatacopy
+            | Operation::Datacopy => (3, 0),
+            Operation::Addmod | Operation::Mulmod | Operation::Create => (3, 1),
+            Operation::Extcodecopy => (4, 0),
+            Operation::Create2 => (4, 1),
+            Operation::Delegatecall | Operation::Staticcall => (6, 1),
+            Operation::Call | Operation::Callcode => (7, 1),
+            Operation::Dup(n) => (*n as usize, *n as usize + 1),
+            Operation::Swap(n) => (*n as usize + 1, *n as usize + 1),
+            Operation::Dupn(immediate) => (*immediate as usize + 1, *immediate as usize + 2),
+            Operation::Swapn(immediate) => (*immediate as usize + 2, *immediate as usize + 2),
+            Operation::Exchange(immediate) => {
+                let (_, deepest) = exchanged_depths(*immediate);
+                (deepest as usize, deepest as usize)
+            }
+            Operation::Log(n) => (*n as usize + 2, 0),
+            Operation::Callf(_, section_type) => {
+                (section_type.inputs as usize, section_type.outputs as usize)
+            }
+            Operation::Jumpf(_, section_type) => (section_type.inputs as usize, 0),
+        };
+        StackEffect { inputs, outputs }
+    }
+
+    /// Returns the gas charged for the operation whatever its operands, before the costs
+    /// depending on them, like expanding the memory.
+    ///
+    /// SSTORE has none, since all of its cost depends on the slot it stores to. The cost of
+    /// the operations accessing accounts and storage changed with some hardforks, so it's
+    /// the one of `spec_id`.
+    pub fn static_gas(&self, spec_id: SpecId) -> i64 {
+        match self {
+            Operation::Stop
+            | Operation::Return
+            | Operation::Revert
+            | Operation::Invalid
+            | Operation::Undefined(_)
+            | Operation::Sstore => 0,
+            Operation::Push0 => gas_cost::PUSH0,
+            Operation::Push(_) | Operation::PushN(..) | Operation::PushLabel(_) => gas_cost::PUSHN,
+            Operation::Add => gas_cost::ADD,
+            Operation::Mul => gas_cost::MUL,
+            Operation::Sub => gas_cost::SUB,
+            Operation::Div => gas_cost::DIV,
+            Operation::Sdiv => gas_cost::SDIV,
+            Operation::Mod => gas_cost::MOD,
+            Operation::SMod => gas_cost::SMOD,
+            Operation::Addmod => gas_cost::ADDMOD,
+            Operation::Mulmod => gas_cost::MULMOD,
+            Operation::Exp => gas_cost::EXP,
+            Operation::SignExtend => gas_cost::SIGNEXTEND,
+            Operation::Lt => gas_cost::LT,
+            Operation::Gt => gas_cost::GT,
+            Operation::Slt => gas_cost::SLT,
+            Operation::Sgt => gas_cost::SGT,
+            Operation::Eq => gas_cost::EQ,
+            Operation::IsZero => gas_cost::ISZERO,
+            Operation::And => gas_cost::AND,
+            Operation::Or => gas_cost::OR,
+            Operation::Xor => gas_cost::XOR,
+            Operation::Not => gas_cost::NOT,
+            Operation::Byte => gas_cost::BYTE,
+            Operation::Shr => gas_cost::SHR,
+            Operation::Shl => gas_cost::SHL,
+            Operation::Sar => gas_cost::SAR,
+            Operation::Pop => gas_cost::POP,
+            Operation::Jump => gas_cost::JUMP,
+            Operation::Jumpi => gas_cost::JUMPI,
+            Operation::PC { .. } => gas_cost::PC,
+            Operation::Gas => gas_cost::GAS,
+            Operation::Jumpdest { .. } | Operation::Label(_) => gas_cost::JUMPDEST,
+            Operation::Dup(_) => gas_cost::DUPN,
+            Operation::Swap(_) => gas_cost::SWAPN,
+            Operation::Mstore => gas_cost::MSTORE,
+            Operation::Mstore8 => gas_cost::MSTORE8,
+            Operation::Mload => gas_cost::MLOAD,
+            Operation::Msize => gas_cost::MSIZE,
+            Operation::Mcopy => gas_cost::MCOPY,
+            Operation::Keccak256 => gas_cost::KECCAK256,
+            Operation::CallDataLoad => gas_cost::CALLDATALOAD,
+            Operation::CallDataSize => gas_cost::CALLDATASIZE,
+            Operation::CallDataCopy => gas_cost::CALLDATACOPY,
+            Operation::Codesize => gas_cost::CODESIZE,
+            Operation::Codecopy => gas_cost::CODECOPY,
+            Operation::Sload => {
+                if spec_id.includes(SpecId::Berlin) {
+                    gas_cost::SLOAD
+                } else if spec_id.includes(SpecId::Istanbul) {
+                    gas_cost::SLOAD_PRE_BERLIN
+                } else if spec_id.includes(SpecId::TangerineWhistle) {
+                    gas_cost::SLOAD_PRE_ISTANBUL
+                } else {
+                    gas_cost::SLOAD_PRE_TANGERINE_WHISTLE
+                }
+            }
+            Operation::Tload => gas_cost::TLOAD,
+            Operation::Tstore => gas_cost::TSTORE,
+            Operation::Log(n) => gas_cost::LOG + gas_cost::LOG_TOPIC * *n as i64,
+            Operation::Address => gas_cost::ADDRESS,
+            Operation::Origin => gas_cost::ORIGIN,
+            Operation::Caller => gas_cost::CALLER,
+            Operation::Callvalue => gas_cost::CALLVALUE,
+            Operation::Gasprice => gas_cost::GASPRICE,
+            Operation::Coinbase => gas_cost::COINBASE,
+            Operation::Timestamp => gas_cost::TIMESTAMP,
+            Operation::Number => gas_cost::NUMBER,
+            Operation::Prevrandao => gas_cost::PREVRANDAO,
+            Operation::Gaslimit => gas_cost::GASLIMIT,
+            Operation::Chainid => gas_cost::CHAINID,
+            Operation::Selfbalance => gas_cost::SELFBALANCE,
+            Operation::Basefee => gas_cost::BASEFEE,
+            Operation::Blockhash => gas_cost::BLOCKHASH,
+            Operation::Balance => {
+                if spec_id.includes(SpecId::Berlin) {
+                    gas_cost::BALANCE
+                } else if spec_id.includes(SpecId::Istanbul) {
+                    gas_cost::BALANCE_PRE_BERLIN
+                } else if spec_id.includes(SpecId::TangerineWhistle) {
+                    gas_cost::BALANCE_PRE_ISTANBUL
+                } else {
+                    gas_cost::BALANCE_PRE_TANGERINE_WHISTLE
+                }
+            }
+            Operation::Extcodesize if spec_id.includes(SpecId::Berlin) => gas_cost::EXTCODESIZE,
+            Operation::Extcodecopy if spec_id.includes(SpecId::Berlin) => gas_cost::EXTCODECOPY,
+            Operation::Extcodesize | Operation::Extcodecopy => {
+                if spec_id.includes(SpecId::TangerineWhistle) {
+                    gas_cost::EXTCODE_PRE_BERLIN
+                } else {
+                    gas_cost::EXTCODE_PRE_TANGERINE_WHISTLE
+                }
+            }
+            Operation::Extcodehash => {
+                if spec_id.includes(SpecId::Berlin) {
+                    gas_cost::EXTCODEHASH
+                } else if spec_id.includes(SpecId::Istanbul) {
+                    gas_cost::EXTCODEHASH_PRE_BERLIN
+                } else {
+                    gas_cost::EXTCODEHASH_PRE_ISTANBUL
+                }
+            }
+            Operation::Returndatasize => gas_cost::RETURNDATASIZE,
+            Operation::Returndatacopy => gas_cost::RETURNDATACOPY,
+            Operation::Blobhash => gas_cost::BLOBHASH,
+            Operation::Blobbasefee => gas_cost::BLOBBASEFEE,
+            Operation::Call
+            | Operation::Callcode
+            | Operation::Delegatecall
+            | Operation::Staticcall => {
+                if spec_id.includes(SpecId::Berlin) {
+                    gas_cost::CALL
+                } else if spec_id.includes(SpecId::TangerineWhistle) {
+                    gas_cost::CALL_PRE_BERLIN
+                } else {
+                    gas_cost::CALL_PRE_TANGERINE_WHISTLE
+                }
+            }
+            Operation::Create | Operation::Create2 => gas_cost::CREATE,
+            Operation::Selfdestruct => {
+                if spec_id.includes(SpecId::TangerineWhistle) {
+                    gas_cost::SELFDESTRUCT
+                } else {
+                    0
+                }
+            }
+            Operation::Rjump(_) => gas_cost::RJUMP,
+            Operation::Rjumpi(_) => gas_cost::RJUMPI,
+            Operation::Rjumpv(_) => gas_cost::RJUMPV,
+            Operation::Callf(..) => gas_cost::CALLF,
+            Operation::Retf => gas_cost::RETF,
+            Operation::Jumpf(..) => gas_cost::JUMPF,
+            Operation::Dataload => gas_cost::DATALOAD,
+            Operation::Dataloadn(_) => gas_cost::DATALOADN,
+            Operation::Datasize => gas_cost::DATASIZE,
+            Operation::Datacopy => gas_cost::DATACOPY,
+            Operation::Dupn(_) => gas_cost::DUPN,
+            Operation::Swapn(_) => gas_cost::SWAPN,
+            Operation::Exchange(_) => gas_cost::EXCHANGE,
+        }
+    }
+
+    /// Returns the EVM byte encoding of the operation, including any immediate.
+    ///
+    /// [`Operation::Push`] is encoded with the smallest PUSHN able to hold the value, and
+    /// [`Operation::PushN`] with the width it holds. A label is encoded as a JUMPDEST.
+    ///
+    /// # Panics
+    ///
+    /// Panics on an [`Operation::PushLabel`], whose offset is only known once the labels
+    /// of the program are resolved.
+    pub fn to_bytecode(&self) -> Vec<u8> {
+        let opcode = match self {
+            Operation::Stop => Opcode::STOP,
+            Operation::Add => Opcode::ADD,
+            Operation::Mul => Opcode::MUL,
+            Operation::Sub => Opcode::SUB,
+            Operation::Sgt => Opcode::SGT,
+            Operation::Div => Opcode::DIV,
+            Operation::Sdiv => Opcode::SDIV,
+            Operation::Mod => Opcode::MOD,
+            Operation::SMod => Opcode::SMOD,
+            Operation::Addmod => Opcode::ADDMOD,
+            Operation::Mulmod => Opcode::MULMOD,
+            Operation::Exp => Opcode::EXP,
+            Operation::SignExtend => Opcode::SIGNEXTEND,
+            Operation::Lt => Opcode::LT,
+            Operation::Gt => Opcode::GT,
+            Operation::Slt => Opcode::SLT,
+            Operation::Eq => Opcode::EQ,
+            Operation::IsZero => Opcode::ISZERO,
+            Operation::And => Opcode::AND,
+            Operation::Or => Opcode::OR,
+            Operation::Xor => Opcode::XOR,
+            Operation::Not => Opcode::NOT,
+            Operation::Byte => Opcode::BYTE,
+            Operation::Shr => Opcode::SHR,
+            Operation::Shl => Opcode::SHL,
+            Operation::Sar => Opcode::SAR,
+            Operation::Pop => Opcode::POP,
+            Operation::Jump => Opcode::JUMP,
+            Operation::Jumpi => Opcode::JUMPI,
+            Operation::PC { .. } => Opcode::PC,
+            Operation::Gas => Opcode::GAS,
+            Operation::Jumpdest { .. } | Operation::Label(_) => Opcode::JUMPDEST,
+            Operation::Push0 => Opcode::PUSH0,
+            Operation::Push(x) => {
+                let value_bytes = x.to_bytes_be();
+                debug_assert!(value_bytes.len() <= 32);
+                let opcode = Opcode::PUSH0 as u8 + value_bytes.len() as u8;
+                return [vec![opcode], value_bytes].concat();
+            }
+            Operation::PushN(size, x) => {
+                let value_bytes = x.to_bytes_be();
+                debug_assert!(value_bytes.len() <= *size as usize && *size <= 32);
+                let padding = (*size as usize).saturating_sub(value_bytes.len());
+                let opcode = Opcode::PUSH0 as u8 + *size;
+                return [vec![opcode], vec![0; padding], value_bytes].concat();
+            }
+            Operation::Dup(n) => return vec![Opcode::DUP1 as u8 + (*n - 1) as u8],
+            Operation::Swap(n) => return vec![Opcode::SWAP1 as u8 + (*n - 1) as u8],
+            Operation::Log(n) => return vec![Opcode::LOG0 as u8 + *n],
+            Operation::Rjump(offset) => {
+                return [vec![opcodes::RJUMP], offset.to_be_bytes().to_vec()].concat()
+            }
+            Operation::Rjumpi(offset) => {
+                return [vec![opcodes::RJUMPI], offset.to_be_bytes().to_vec()].concat()
+            }
+            Operation::Rjumpv(offsets) => {
+                debug_assert!((1..=256).contains(&offsets.len()));
+                // The immediate starts with the highest index
+                let mut bytecode = vec![opcodes::RJUMPV, offsets.len().saturating_sub(1) as u8];
+                bytecode.extend(offsets.iter().flat_map(|offset| offset.to_be_bytes()));
+                return bytecode;
+            }
+            Operation::Callf(section, _) => {
+                return [vec![opcodes::CALLF], section.to_be_bytes().to_vec()].concat()
+            }
+            Operation::Retf => return vec![opcodes::RETF],
+            Operation::Jumpf(section, _) => {
+                return [vec![opcodes::JUMPF], section.to_be_bytes().to_vec()].concat()
+            }
+            Operation::Dataload => return vec![opcodes::DATALOAD],
+            Operation::Dataloadn(offset) => {
+                return [vec![opcodes::DATALOADN], offset.to_be_bytes().to_vec()].concat()
+            }
+            Operation::Datasize => return vec![opcodes::DATASIZE],
+            Operation::Datacopy => return vec![opcodes::DATACOPY],
+            Operation::Dupn(immediate) => return vec![opcodes::DUPN, *immediate],
+            Operation::Swapn(immediate) => return vec![opcodes::SWAPN, *immediate],
+            Operation::Exchange(immediate) => return vec![opcodes::EXCHANGE, *immediate],
+            Operation::Undefined(opcode) => return vec![*opcode],
+            Operation::Return => Opcode::RETURN,
+            Operation::Mstore => Opcode::MSTORE,
+            Operation::Mstore8 => Opcode::MSTORE8,
+            Operation::Mload => Opcode::MLOAD,
+            Operation::Msize => Opcode::MSIZE,
+            Operation::Mcopy => Opcode::MCOPY,
+            Operation::Keccak256 => Opcode::KECCAK256,
+            Operation::CallDataLoad => Opcode::CALLDATALOAD,
+            Operation::CallDataSize => Opcode::CALLDATASIZE,
+            Operation::CallDataCopy => Opcode::CALLDATACOPY,
+            Operation::Codesize => Opcode::CODESIZE,
+            Operation::Codecopy => Opcode::CODECOPY,
+            Operation::Revert => Opcode::REVERT,
+            Operation::Invalid => Opcode::INVALID,
+            Operation::Sload => Opcode::SLOAD,
+            Operation::Sstore => Opcode::SSTORE,
+            Operation::Tload => Opcode::TLOAD,
+            Operation::Tstore => Opcode::TSTORE,
+            Operation::Address => Opcode::ADDRESS,
+            Operation::Balance => Opcode::BALANCE,
+            Operation::Origin => Opcode::ORIGIN,
+            Operation::Caller => Opcode::CALLER,
+            Operation::Callvalue => Opcode::CALLVALUE,
+            Operation::Gasprice => Opcode::GASPRICE,
+            Operation::Coinbase => Opcode::COINBASE,
+            Operation::Timestamp => Opcode::TIMESTAMP,
+            Operation::Number => Opcode::NUMBER,
+            Operation::Prevrandao => Opcode::PREVRANDAO,
+            Operation::Gaslimit => Opcode::GASLIMIT,
+            Operation::Chainid => Opcode::CHAINID,
+            Operation::Selfbalance => Opcode::SELFBALANCE,
+            Operation::Basefee => Opcode::BASEFEE,
+            Operation::Blockhash => Opcode::BLOCKHASH,
+            Operation::Extcodesize => Opcode::EXTCODESIZE,
+            Operation::Extcodecopy => Opcode::EXTCODECOPY,
+            Operation::Extcodehash => Opcode::EXTCODEHASH,
+            Operation::Returndatasize => Opcode::RETURNDATASIZE,
+            Operation::Returndatacopy => Opcode::RETURNDATACOPY,
+            Operation::Blobhash => Opcode::BLOBHASH,
+            Operation::Blobbasefee => Opcode::BLOBBASEFEE,
+            Operation::Call => Opcode::CALL,
+            Operation::Callcode => Opcode::CALLCODE,
+            Operation::Delegatecall => Opcode::DELEGATECALL,
+            Operation::Staticcall => Opcode::STATICCALL,
+            Operation::Create => Opcode::CREATE,
+            Operation::Create2 => Opcode::CREATE2,
+            Operation::Selfdestruct => Opcode::SELFDESTRUCT,
+            Operation::PushLabel(label) => {
+                panic!("the label `{label}` must be resolved before encoding its PUSH")
+            }
+        };
+        vec![opcode as u8]
+    }
+
+    /// Returns the offsets a relative jump at `pc` may jump to, in the order of its
+    /// immediate, or none for the other operations. An offset before the start of the
+    /// code is `None`
+    pub(crate) fn relative_jump_targets(&self, pc: usize) -> Vec<Option<usize>> {
+        let offsets = match self {
+            Operation::Rjump(offset) | Operation::Rjumpi(offset) => std::slice::from_ref(offset),
+            Operation::Rjumpv(offsets) => offsets.as_slice(),
+            _ => return Vec::new(),
+        };
+        let end = pc + self.to_bytecode().len();
+        offsets
+            .iter()
+            .map(|offset| end.checked_add_signed(*offset as isize))
+            .collect()
+    }
 }
 
-#[derive(Debug, Clone)]
+/// Formats the operation as its mnemonic, followed by its immediate in hex if it has
+/// one, as [`Program::from_assembly`] reads it. Labels are formatted as in assembly too,
+/// and the instructions of EOF code, which assembly doesn't have, as the others
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operation::Label(label) => return write!(f, "{label}:"),
+            Operation::PushLabel(label) => return write!(f, "PUSH @{label}"),
+            Operation::Undefined(opcode) => return write!(f, "UNKNOWN {opcode:#04x}"),
+            _ => {}
+        }
+        let bytecode = self.to_bytecode();
+        match self {
+            Operation::Rjump(_) => write!(f, "RJUMP")?,
+            Operation::Rjumpi(_) => write!(f, "RJUMPI")?,
+            Operation::Rjumpv(_) => write!(f, "RJUMPV")?,
+            Operation::Callf(..) => write!(f, "CALLF")?,
+            Operation::Retf => write!(f, "RETF")?,
+            Operation::Jumpf(..) => write!(f, "JUMPF")?,
+            Operation::Dataload => write!(f, "DATALOAD")?,
+            Operation::Dataloadn(_) => write!(f, "DATALOADN")?,
+            Operation::Datasize => write!(f, "DATASIZE")?,
+            Operation::Datacopy => write!(f, "DATACOPY")?,
+            Operation::Dupn(_) => write!(f, "DUPN")?,
+            Operation::Swapn(_) => write!(f, "SWAPN")?,
+            Operation::Exchange(_) => write!(f, "EXCHANGE")?,
+            _ => write!(f, "{:?}", Opcode::from(bytecode[0]))?,
+        }
+        if bytecode.len() > 1 {
+            write!(f, " 0x")?;
+            for byte in &bytecode[1..] {
+                write!(f, "{byte:02x}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A program, as the list of its operations. With the `serde` feature, it's serialized
+/// as that list, with the values pushed in hex
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
 pub struct Program {
     pub(crate) operations: Vec<Operation>,
 }
 
 impl Program {
-    pub fn from_bytecode(bytecode: &[u8]) -> Self {
-        let mut operations = vec![];
+    /// Decodes raw EVM bytecode into a [`Program`].
+    ///
+    /// PUSH immediates are decoded into the pushed value, and each JUMPDEST and PC
+    /// operation records its byte offset in the original bytecode. Bytes which aren't
+    /// opcodes are decoded into [`Operation::Undefined`], since code can hold data which
+    /// is never executed, and only halt the program if they're reached.
+    ///
+    /// The metadata solc appends to the code of contracts is left out, when it follows an
+    /// operation which doesn't go on to the next one. It isn't code, so jumping into it is
+    /// taken as an invalid jump.
+    pub fn from_bytecode(bytecode: &[u8]) -> Result<Self, ParseError> {
+        let mut operations: Vec<Operation> = vec![];
         let mut pc = 0;
+        let metadata_start = solidity_metadata_start(bytecode);
 
         while pc < bytecode.len() {
-            let Some(opcode) = bytecode.get(pc).copied() else {
+            if Some(pc) == metadata_start && operations.last().is_some_and(never_falls_through) {
                 break;
-            };
-            let op = match Opcode::from(opcode) {
-                Opcode::STOP => Operation::Stop,
-                Opcode::ADD => Operation::Add,
-                Opcode::MUL => Operation::Mul,
-                Opcode::SUB => Operation::Sub,
-                Opcode::DIV => Operation::Div,
-                Opcode::SDIV => Operation::Sdiv,
-                Opcode::MOD => Operation::Mod,
-                Opcode::SMOD => Operation::SMod,
-                Opcode::ADDMOD => Operation::Addmod,
-                Opcode::MULMOD => Operation::Mulmod,
-                Opcode::EXP => Operation::Exp,
-                Opcode::SIGNEXTEND => Operation::SignExtend,
-                Opcode::LT => Operation::Lt,
-                Opcode::GT => Operation::Gt,
-                Opcode::SLT => Operation::Slt,
-                Opcode::SGT => Operation::Sgt,
-                Opcode::EQ => Operation::Eq,
-                Opcode::ISZERO => Operation::IsZero,
-                Opcode::AND => Operation::And,
-                Opcode::OR => Operation::Or,
-                Opcode::XOR => Operation::Xor,
-                Opcode::BYTE => Operation::Byte,
-                Opcode::SHR => Operation::Shr,
-                Opcode::SHL => Operation::Shl,
-                Opcode::SAR => Operation::Sar,
-                Opcode::POP => Operation::Pop,
-                Opcode::JUMP => Operation::Jump,
-                Opcode::JUMPI => Operation::Jumpi,
-                Opcode::PC => Operation::PC { pc },
-                Opcode::GAS => Operation::Gas,
-                Opcode::JUMPDEST => Operation::Jumpdest { pc },
-                Opcode::PUSH0 => Operation::Push0,
-                Opcode::PUSH1 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 1)].try_into().unwrap();
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH2 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 2)].try_into().unwrap();
-                    pc += 1;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH3 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 3)].try_into().unwrap();
-                    pc += 2;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH4 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 4)].try_into().unwrap();
-                    pc += 3;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH5 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 5)].try_into().unwrap();
-                    pc += 4;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH6 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 6)].try_into().unwrap();
-                    pc += 5;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH7 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 7)].try_into().unwrap();
-                    pc += 6;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH8 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 8)].try_into().unwrap();
-                    pc += 7;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH9 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 9)].try_into().unwrap();
-                    pc += 8;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH10 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 10)].try_into().unwrap();
-                    pc += 9;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH11 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 11)].try_into().unwrap();
-                    pc += 10;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH12 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 12)].try_into().unwrap();
-                    pc += 11;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH13 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 13)].try_into().unwrap();
-                    pc += 12;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH14 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 14)].try_into().unwrap();
-                    pc += 13;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH15 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 15)].try_into().unwrap();
-                    pc += 14;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH16 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 16)].try_into().unwrap();
-                    pc += 15;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH17 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 17)].try_into().unwrap();
-                    pc += 16;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH18 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 18)].try_into().unwrap();
-                    pc += 17;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH19 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 19)].try_into().unwrap();
-                    pc += 18;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH20 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 20)].try_into().unwrap();
-                    pc += 19;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH21 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 21)].try_into().unwrap();
-                    pc += 20;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH22 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 32)].try_into().unwrap();
-                    pc += 21;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH23 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 32)].try_into().unwrap();
-                    pc += 22;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH24 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 32)].try_into().unwrap();
-                    pc += 23;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH25 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 32)].try_into().unwrap();
-                    pc += 24;
-                    Operation::Push(BigUint::from_bytes_be(x))
-                }
-                Opcode::PUSH26 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 26)].try_into().unwrap();
-                    pc += 25;
-                    Operation::Push(BigUint::from_bytes_be(x))
+            }
+            let (op, next) = decode_operation(bytecode, pc);
+            operations.push(op);
+            pc = next;
+        }
+        Ok(Program { operations })
+    }
+
+    /// Parses mnemonic assembly into a [`Program`], resolving labels to the offsets of
+    /// their JUMPDESTs. See [`assembly`] for the syntax.
+    pub fn from_assembly(source: &str) -> Result<Self, AssemblyError> {
+        assembly::assemble(source)
+    }
+
+    /// Checks the program can be encoded and compiled, returning an error for its labels
+    /// if they can't be resolved, or for the first PUSH whose immediate doesn't fit in a
+    /// word, or in the width given to it, or for the first RJUMPV without 1 to 256
+    /// offsets. Programs decoded from bytecode or assembly are always valid, but
+    /// [`Operation::Push`] and [`Operation::PushN`] can hold any value
+    pub fn validate(&self) -> Result<(), ProgramError> {
+        if self.has_labels() {
+            self.label_offsets()?;
+        }
+        for (index, operation) in self.operations.iter().enumerate() {
+            match operation {
+                Operation::Push(value) if value.bits() > 256 => {
+                    let bits = value.bits();
+                    return Err(ProgramError::ImmediateTooLarge { index, bits });
                 }
-                Opcode::PUSH27 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 27)].try_into().unwrap();
-                    pc += 26;
-                    Operation::Push(BigUint::from_bytes_be(x))
+                Operation::PushN(size, value)
+                    if !(1..=32).contains(size) || value.bits() > *size as u64 * 8 =>
+                {
+                    return Err(ProgramError::ImmediateDoesNotFit {
+                        index,
+                        size: *size,
+                        bits: value.bits(),
+                    });
                 }
-                Opcode::PUSH28 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 28)].try_into().unwrap();
-                    pc += 27;
-                    Operation::Push(BigUint::from_bytes_be(x))
+                Operation::Rjumpv(offsets) if !(1..=256).contains(&offsets.len()) => {
+                    let size = offsets.len();
+                    return Err(ProgramError::InvalidJumpTableSize { index, size });
                 }
-                Opcode::PUSH29 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 29)].try_into().unwrap();
-                    pc += 28;
-                    Operation::Push(BigUint::from_bytes_be(x))
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the program with its labels resolved: each [`Operation::Label`] becomes a
+    /// JUMPDEST, and each [`Operation::PushLabel`] a PUSH of its offset. The offsets of
+    /// the program are computed from its encoding, as with [`Program::with_computed_pcs`].
+    ///
+    /// Returns an error if a label is defined twice, or pushed but never defined. A
+    /// program without labels is returned as is.
+    pub fn resolve_labels(self) -> Result<Self, ProgramError> {
+        if !self.has_labels() {
+            return Ok(self);
+        }
+        self.resolved().map(Cow::into_owned)
+    }
+
+    /// Returns the program with its labels resolved, borrowing it if it has none
+    pub(crate) fn resolved(&self) -> Result<Cow<'_, Self>, ProgramError> {
+        if !self.has_labels() {
+            return Ok(Cow::Borrowed(self));
+        }
+        let offsets = self.label_offsets()?;
+        let operations = self
+            .operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Label(_) => Operation::Jumpdest { pc: 0 },
+                Operation::PushLabel(label) => {
+                    Operation::Push(BigUint::from(offsets[label.as_str()]))
                 }
-                Opcode::PUSH30 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 30)].try_into().unwrap();
-                    pc += 29;
-                    Operation::Push(BigUint::from_bytes_be(x))
+                operation => operation.clone(),
+            })
+            .collect();
+        Ok(Cow::Owned(Program { operations }.with_computed_pcs()))
+    }
+
+    fn has_labels(&self) -> bool {
+        self.operations
+            .iter()
+            .any(|operation| matches!(operation, Operation::Label(_) | Operation::PushLabel(_)))
+    }
+
+    /// Returns the offset of each label, by name.
+    ///
+    /// Pushing the offset of a label takes more bytes the further it is, which moves the
+    /// labels after it. Offsets only grow, so this settles
+    fn label_offsets(&self) -> Result<HashMap<&str, usize>, ProgramError> {
+        let mut offsets = HashMap::new();
+        for operation in &self.operations {
+            if let Operation::Label(label) = operation {
+                if offsets.insert(label.as_str(), 0).is_some() {
+                    let label = label.clone();
+                    return Err(ProgramError::DuplicateLabel { label });
                 }
-                Opcode::PUSH31 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 31)].try_into().unwrap();
-                    pc += 30;
-                    Operation::Push(BigUint::from_bytes_be(x))
+            }
+        }
+        for operation in &self.operations {
+            if let Operation::PushLabel(label) = operation {
+                if !offsets.contains_key(label.as_str()) {
+                    let label = label.clone();
+                    return Err(ProgramError::UndefinedLabel { label });
                 }
-                Opcode::PUSH32 => {
-                    pc += 1;
-                    let x = bytecode[pc..(pc + 32)].try_into().unwrap();
-                    pc += 31;
-                    Operation::Push(BigUint::from_bytes_be(x))
+            }
+        }
+
+        loop {
+            let mut pc = 0;
+            let mut moved = false;
+            for operation in &self.operations {
+                pc += match operation {
+                    Operation::Label(label) => {
+                        let offset = offsets.get_mut(label.as_str()).expect("labels are defined");
+                        moved |= *offset != pc;
+                        *offset = pc;
+                        1
+                    }
+                    Operation::PushLabel(label) => {
+                        let offset = BigUint::from(offsets[label.as_str()]);
+                        Operation::Push(offset).to_bytecode().len()
+                    }
+                    operation => operation.to_bytecode().len(),
+                };
+            }
+            if !moved {
+                return Ok(offsets);
+            }
+        }
+    }
+
+    /// Returns the operations of the program, in order
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// Encodes the program back into EVM bytecode.
+    ///
+    /// For any program obtained through [`Program::from_bytecode`], this returns the
+    /// original bytecode, unless it ends with a truncated PUSH. The program must be
+    /// valid, see [`Program::validate`]. Its labels are resolved first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the labels of the program can't be resolved.
+    pub fn to_bytecode(&self) -> Vec<u8> {
+        let program = self
+            .resolved()
+            .unwrap_or_else(|error| panic!("can't encode the program: {error}"));
+        program
+            .operations
+            .iter()
+            .flat_map(Operation::to_bytecode)
+            .collect()
+    }
+
+    /// Returns the offset of each operation in the bytecode, as codegen lays them out.
+    /// The offsets recorded in JUMPDESTs and PCs are kept, in case they were given by hand
+    pub(crate) fn pcs(&self) -> Vec<usize> {
+        let mut pcs = Vec::with_capacity(self.operations.len());
+        let mut pc = 0;
+        for operation in &self.operations {
+            if let Operation::Jumpdest { pc: op_pc } | Operation::PC { pc: op_pc } = operation {
+                pc = *op_pc;
+            }
+            pcs.push(pc);
+            pc += operation.to_bytecode().len();
+        }
+        pcs
+    }
+
+    /// Returns the program with the offset of each JUMPDEST and PC computed from the
+    /// encoding of the operations before it, replacing the ones they held. Programs built
+    /// by hand can leave them as 0, and compute them before compiling
+    pub fn with_computed_pcs(mut self) -> Self {
+        let mut pc = 0;
+        for operation in &mut self.operations {
+            if let Operation::Jumpdest { pc: op_pc } | Operation::PC { pc: op_pc } = operation {
+                *op_pc = pc;
+            }
+            pc += operation.to_bytecode().len();
+        }
+        self
+    }
+
+    /// Returns an iterator over the operations, along with their offset in the bytecode
+    /// as codegen lays them out
+    pub fn iter_with_pcs(&self) -> impl Iterator<Item = (usize, &Operation)> {
+        self.pcs().into_iter().zip(&self.operations)
+    }
+
+    /// Returns a listing of the program, as [`disassemble`] gives for its bytecode
+    pub fn disassemble(&self) -> String {
+        disassemble(&self.to_bytecode())
+    }
+}
+
+/// Serializes the values pushed as hex strings, like `"0x2a"`
+#[cfg(feature = "serde")]
+mod hex_value {
+    use num_bigint::BigUint;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &BigUint, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{value:#x}"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BigUint, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.strip_prefix("0x")
+            .and_then(|digits| BigUint::parse_bytes(digits.as_bytes(), 16))
+            .ok_or_else(|| D::Error::custom(format!("invalid hex value `{hex}`")))
+    }
+}
+
+/// Decodes hex bytecode into a [`Program`], like `bytecode!("6005600701")`, to paste
+/// bytecode into code. The hex is decoded at compile time, failing to compile if it isn't
+/// valid. It can start with `0x`, and have whitespace between its digits.
+#[macro_export]
+macro_rules! bytecode {
+    ($hex:expr) => {{
+        const HEX: &str = $hex;
+        const BYTES: [u8; $crate::program::hex_len(HEX)] = $crate::program::decode_hex(HEX);
+        $crate::program::Program::from_bytecode(&BYTES).expect("invalid bytecode")
+    }};
+}
+
+/// Returns the bytes held by the hex, as [`decode_hex`] reads it.
+///
+/// # Panics
+///
+/// Panics if the hex has an odd number of digits.
+pub const fn hex_len(hex: &str) -> usize {
+    let hex = hex.as_bytes();
+    let mut index = hex_start(hex);
+    let mut digits = 0;
+    while index < hex.len() {
+        if !hex[index].is_ascii_whitespace() {
+            digits += 1;
+        }
+        index += 1;
+    }
+    if digits % 2 != 0 {
+        panic!("the hex has an odd number of digits");
+    }
+    digits / 2
+}
+
+/// Decodes the hex into `N` bytes. The hex can start with `0x`, and have whitespace
+/// between its digits. It can be decoded at compile time, into a constant.
+///
+/// # Panics
+///
+/// Panics if the hex has a character which isn't a hex digit nor whitespace, or if it
+/// doesn't hold `N` bytes.
+pub const fn decode_hex<const N: usize>(hex: &str) -> [u8; N] {
+    let hex = hex.as_bytes();
+    let mut bytes = [0; N];
+    let mut index = hex_start(hex);
+    let mut digits = 0;
+    while index < hex.len() {
+        let digit = hex[index];
+        index += 1;
+        if digit.is_ascii_whitespace() {
+            continue;
+        }
+        let value = match digit {
+            b'0'..=b'9' => digit - b'0',
+            b'a'..=b'f' => digit - b'a' + 10,
+            b'A'..=b'F' => digit - b'A' + 10,
+            _ => panic!("the hex has a character which isn't a hex digit"),
+        };
+        if digits / 2 >= N {
+            panic!("the hex holds more bytes than expected");
+        }
+        // The first digit of each byte is its high half
+        bytes[digits / 2] |= value << (4 * (1 - digits % 2));
+        digits += 1;
+    }
+    if digits != 2 * N {
+        panic!("the hex holds fewer bytes than expected");
+    }
+    bytes
+}
+
+/// Returns the index of the first digit of the hex, after its leading whitespace and its
+/// `0x` prefix, if any
+const fn hex_start(hex: &[u8]) -> usize {
+    let mut index = 0;
+    while index < hex.len() && hex[index].is_ascii_whitespace() {
+        index += 1;
+    }
+    if index + 1 < hex.len() && hex[index] == b'0' && (hex[index + 1] | 0x20) == b'x' {
+        index += 2;
+    }
+    index
+}
+
+/// Returns the offset of the metadata solc appends to the code of contracts, if the
+/// bytecode ends with it. The metadata is a CBOR map keyed by strings, holding the hash
+/// of the contract's metadata file under `ipfs`, `bzzr0` or `bzzr1`, and the version of
+/// solc under `solc`. Its length follows it, in two bytes
+fn solidity_metadata_start(bytecode: &[u8]) -> Option<usize> {
+    const KEYS: [&[u8]; 5] = [
+        b"\x64ipfs",
+        b"\x65bzzr0",
+        b"\x65bzzr1",
+        b"\x64solc",
+        b"\x6cexperimental",
+    ];
+    let [.., high, low] = bytecode else {
+        return None;
+    };
+    let length = u16::from_be_bytes([*high, *low]) as usize;
+    let start = bytecode.len().checked_sub(length + 2)?;
+    let (&header, entries) = bytecode[start..bytecode.len() - 2].split_first()?;
+    // A map of 1 to 5 entries, starting with one of the keys
+    let is_metadata =
+        (0xA1..=0xA5).contains(&header) && KEYS.iter().any(|key| entries.starts_with(key));
+    is_metadata.then_some(start)
+}
+
+/// Decodes the operation at `pc` of the legacy bytecode, returning it along with the
+/// offset of the next one
+pub(crate) fn decode_operation(bytecode: &[u8], pc: usize) -> (Operation, usize) {
+    let opcode = bytecode[pc];
+    let mut next = pc + 1;
+    let op = match Opcode::from(opcode) {
+        Opcode::STOP => Operation::Stop,
+        Opcode::ADD => Operation::Add,
+        Opcode::MUL => Operation::Mul,
+        Opcode::SUB => Operation::Sub,
+        Opcode::DIV => Operation::Div,
+        Opcode::SDIV => Operation::Sdiv,
+        Opcode::MOD => Operation::Mod,
+        Opcode::SMOD => Operation::SMod,
+        Opcode::ADDMOD => Operation::Addmod,
+        Opcode::MULMOD => Operation::Mulmod,
+        Opcode::EXP => Operation::Exp,
+        Opcode::SIGNEXTEND => Operation::SignExtend,
+        Opcode::LT => Operation::Lt,
+        Opcode::GT => Operation::Gt,
+        Opcode::SLT => Operation::Slt,
+        Opcode::SGT => Operation::Sgt,
+        Opcode::EQ => Operation::Eq,
+        Opcode::ISZERO => Operation::IsZero,
+        Opcode::AND => Operation::And,
+        Opcode::OR => Operation::Or,
+        Opcode::XOR => Operation::Xor,
+        Opcode::NOT => Operation::Not,
+        Opcode::BYTE => Operation::Byte,
+        Opcode::SHR => Operation::Shr,
+        Opcode::SHL => Operation::Shl,
+        Opcode::SAR => Operation::Sar,
+        Opcode::POP => Operation::Pop,
+        Opcode::JUMP => Operation::Jump,
+        Opcode::JUMPI => Operation::Jumpi,
+        Opcode::PC => Operation::PC { pc },
+        Opcode::GAS => Operation::Gas,
+        Opcode::JUMPDEST => Operation::Jumpdest { pc },
+        Opcode::PUSH0 => Operation::Push0,
+        Opcode::PUSH1
+        | Opcode::PUSH2
+        | Opcode::PUSH3
+        | Opcode::PUSH4
+        | Opcode::PUSH5
+        | Opcode::PUSH6
+        | Opcode::PUSH7
+        | Opcode::PUSH8
+        | Opcode::PUSH9
+        | Opcode::PUSH10
+        | Opcode::PUSH11
+        | Opcode::PUSH12
+        | Opcode::PUSH13
+        | Opcode::PUSH14
+        | Opcode::PUSH15
+        | Opcode::PUSH16
+        | Opcode::PUSH17
+        | Opcode::PUSH18
+        | Opcode::PUSH19
+        | Opcode::PUSH20
+        | Opcode::PUSH21
+        | Opcode::PUSH22
+        | Opcode::PUSH23
+        | Opcode::PUSH24
+        | Opcode::PUSH25
+        | Opcode::PUSH26
+        | Opcode::PUSH27
+        | Opcode::PUSH28
+        | Opcode::PUSH29
+        | Opcode::PUSH30
+        | Opcode::PUSH31
+        | Opcode::PUSH32 => {
+            // PUSHN reads the next N bytes as its immediate. If the bytecode ends
+            // before that, the missing bytes are treated as zeros.
+            let n = (opcode - Opcode::PUSH0 as u8) as usize;
+            let start = (pc + 1).min(bytecode.len());
+            let end = (pc + 1 + n).min(bytecode.len());
+            let mut value_bytes = bytecode[start..end].to_vec();
+            value_bytes.resize(n, 0);
+            next += n;
+            Operation::sized_push(&value_bytes)
+        }
+        Opcode::DUP1 => Operation::Dup(1),
+        Opcode::DUP2 => Operation::Dup(2),
+        Opcode::DUP3 => Operation::Dup(3),
+        Opcode::DUP4 => Operation::Dup(4),
+        Opcode::DUP5 => Operation::Dup(5),
+        Opcode::DUP6 => Operation::Dup(6),
+        Opcode::DUP7 => Operation::Dup(7),
+        Opcode::DUP8 => Operation::Dup(8),
+        Opcode::DUP9 => Operation::Dup(9),
+        Opcode::DUP10 => Operation::Dup(10),
+        Opcode::DUP11 => Operation::Dup(11),
+        Opcode::DUP12 => Operation::Dup(12),
+        Opcode::DUP13 => Operation::Dup(13),
+        Opcode::DUP14 => Operation::Dup(14),
+        Opcode::DUP15 => Operation::Dup(15),
+        Opcode::DUP16 => Operation::Dup(16),
+        Opcode::SWAP1 => Operation::Swap(1),
+        Opcode::SWAP2 => Operation::Swap(2),
+        Opcode::SWAP3 => Operation::Swap(3),
+        Opcode::SWAP4 => Operation::Swap(4),
+        Opcode::SWAP5 => Operation::Swap(5),
+        Opcode::SWAP6 => Operation::Swap(6),
+        Opcode::SWAP7 => Operation::Swap(7),
+        Opcode::SWAP8 => Operation::Swap(8),
+        Opcode::SWAP9 => Operation::Swap(9),
+        Opcode::SWAP10 => Operation::Swap(10),
+        Opcode::SWAP11 => Operation::Swap(11),
+        Opcode::SWAP12 => Operation::Swap(12),
+        Opcode::SWAP13 => Operation::Swap(13),
+        Opcode::SWAP14 => Operation::Swap(14),
+        Opcode::SWAP15 => Operation::Swap(15),
+        Opcode::SWAP16 => Operation::Swap(16),
+        Opcode::LOG0 => Operation::Log(0),
+        Opcode::LOG1 => Operation::Log(1),
+        Opcode::LOG2 => Operation::Log(2),
+        Opcode::LOG3 => Operation::Log(3),
+        Opcode::LOG4 => Operation::Log(4),
+        Opcode::ADDRESS => Operation::Address,
+        Opcode::BALANCE => Operation::Balance,
+        Opcode::ORIGIN => Operation::Origin,
+        Opcode::CALLER => Operation::Caller,
+        Opcode::CALLVALUE => Operation::Callvalue,
+        Opcode::GASPRICE => Operation::Gasprice,
+        Opcode::COINBASE => Operation::Coinbase,
+        Opcode::TIMESTAMP => Operation::Timestamp,
+        Opcode::NUMBER => Operation::Number,
+        Opcode::PREVRANDAO => Operation::Prevrandao,
+        Opcode::GASLIMIT => Operation::Gaslimit,
+        Opcode::CHAINID => Operation::Chainid,
+        Opcode::SELFBALANCE => Operation::Selfbalance,
+        Opcode::BASEFEE => Operation::Basefee,
+        Opcode::BLOCKHASH => Operation::Blockhash,
+        Opcode::EXTCODESIZE => Operation::Extcodesize,
+        Opcode::EXTCODECOPY => Operation::Extcodecopy,
+        Opcode::EXTCODEHASH => Operation::Extcodehash,
+        Opcode::RETURNDATASIZE => Operation::Returndatasize,
+        Opcode::RETURNDATACOPY => Operation::Returndatacopy,
+        Opcode::BLOBHASH => Operation::Blobhash,
+        Opcode::BLOBBASEFEE => Operation::Blobbasefee,
+        Opcode::CALL => Operation::Call,
+        Opcode::CALLCODE => Operation::Callcode,
+        Opcode::DELEGATECALL => Operation::Delegatecall,
+        Opcode::STATICCALL => Operation::Staticcall,
+        Opcode::CREATE => Operation::Create,
+        Opcode::CREATE2 => Operation::Create2,
+        Opcode::SELFDESTRUCT => Operation::Selfdestruct,
+        Opcode::RETURN => Operation::Return,
+        Opcode::MSTORE => Operation::Mstore,
+        Opcode::MSTORE8 => Operation::Mstore8,
+        Opcode::MLOAD => Operation::Mload,
+        Opcode::MSIZE => Operation::Msize,
+        Opcode::MCOPY => Operation::Mcopy,
+        Opcode::KECCAK256 => Operation::Keccak256,
+        Opcode::CALLDATALOAD => Operation::CallDataLoad,
+        Opcode::CALLDATASIZE => Operation::CallDataSize,
+        Opcode::CALLDATACOPY => Operation::CallDataCopy,
+        Opcode::CODESIZE => Operation::Codesize,
+        Opcode::CODECOPY => Operation::Codecopy,
+        Opcode::REVERT => Operation::Revert,
+        Opcode::INVALID => Operation::Invalid,
+        Opcode::SLOAD => Operation::Sload,
+        Opcode::SSTORE => Operation::Sstore,
+        Opcode::TLOAD => Operation::Tload,
+        Opcode::TSTORE => Operation::Tstore,
+        Opcode::UNUSED => Operation::Undefined(opcode),
+    };
+    (op, next)
+}
+
+/// Returns the depths of the items an EXCHANGE with the immediate swaps, the top item
+/// being at depth 1
+pub(crate) fn exchanged_depths(immediate: u8) -> (u32, u32) {
+    let n = (immediate >> 4) as u32 + 1;
+    let m = (immediate & 0x0F) as u32 + 1;
+    (n + 1, n + m + 1)
+}
+
+/// Returns whether the operation never goes on to the next one, since it jumps or halts
+fn never_falls_through(operation: &Operation) -> bool {
+    matches!(
+        operation,
+        Operation::Stop
+            | Operation::Jump
+            | Operation::Return
+            | Operation::Revert
+            | Operation::Invalid
+            | Operation::Undefined(_)
+            | Operation::Selfdestruct
+            | Operation::Rjump(_)
+            | Operation::Retf
+            | Operation::Jumpf(..)
+    )
+}
+
+/// Returns a listing of the bytecode, with an opcode per line after its offset in hex.
+///
+/// PUSH immediates follow their opcode in hex, as wide as in the bytecode, and are
+/// marked as truncated when the bytecode ends before them. JUMPDESTs are marked with
+/// `=>`, and bytes that aren't opcodes are listed as `UNKNOWN` with their value.
+///
+/// ```text
+/// 0000    PUSH1 0x04
+/// 0002    JUMP
+/// 0003    UNKNOWN 0x0c
+/// 0004 => JUMPDEST
+/// 0005    PUSH2 0x01 (truncated)
+/// ```
+pub fn disassemble(bytecode: &[u8]) -> String {
+    let mut listing = String::new();
+    let mut pc = 0;
+    while pc < bytecode.len() {
+        let byte = bytecode[pc];
+        let opcode = Opcode::from(byte);
+        let marker = if opcode == Opcode::JUMPDEST {
+            "=>"
+        } else {
+            "  "
+        };
+        listing.push_str(&format!("{pc:04x} {marker} "));
+        match opcode {
+            Opcode::UNUSED => listing.push_str(&format!("UNKNOWN {byte:#04x}")),
+            _ => listing.push_str(&format!("{opcode:?}")),
+        }
+        pc += 1;
+
+        if (Opcode::PUSH1 as u8..=Opcode::PUSH32 as u8).contains(&byte) {
+            let size = (byte - Opcode::PUSH0 as u8) as usize;
+            let immediate = &bytecode[pc..bytecode.len().min(pc + size)];
+            if !immediate.is_empty() {
+                listing.push_str(" 0x");
+                for byte in immediate {
+                    listing.push_str(&format!("{byte:02x}"));
                 }
-                Opcode::DUP1 => Operation::Dup(1),
-                Opcode::DUP2 => Operation::Dup(2),
-                Opcode::DUP3 => Operation::Dup(3),
-                Opcode::DUP4 => Operation::Dup(4),
-                Opcode::DUP5 => Operation::Dup(5),
-                Opcode::DUP6 => Operation::Dup(6),
-                Opcode::DUP7 => Operation::Dup(7),
-                Opcode::DUP8 => Operation::Dup(8),
-                Opcode::DUP9 => Operation::Dup(9),
-                Opcode::DUP10 => Operation::Dup(10),
-                Opcode::DUP11 => Operation::Dup(11),
-                Opcode::DUP12 => Operation::Dup(12),
-                Opcode::DUP13 => Operation::Dup(13),
-                Opcode::DUP14 => Operation::Dup(14),
-                Opcode::DUP15 => Operation::Dup(15),
-                Opcode::DUP16 => Operation::Dup(16),
-                Opcode::SWAP1 => Operation::Swap(1),
-                Opcode::SWAP2 => Operation::Swap(2),
-                Opcode::SWAP3 => Operation::Swap(3),
-                Opcode::SWAP4 => Operation::Swap(4),
-                Opcode::SWAP5 => Operation::Swap(5),
-                Opcode::SWAP6 => Operation::Swap(6),
-                Opcode::SWAP7 => Operation::Swap(7),
-                Opcode::SWAP8 => Operation::Swap(8),
-                Opcode::SWAP9 => Operation::Swap(9),
-                Opcode::SWAP10 => Operation::Swap(10),
-                Opcode::SWAP11 => Operation::Swap(11),
-                Opcode::SWAP12 => Operation::Swap(12),
-                Opcode::SWAP13 => Operation::Swap(13),
-                Opcode::SWAP14 => Operation::Swap(14),
-                Opcode::SWAP15 => Operation::Swap(15),
-                Opcode::SWAP16 => Operation::Swap(16),
-                Opcode::RETURN => Operation::Return,
-                Opcode::MSTORE => Operation::Mstore,
-                Opcode::MSTORE8 => Operation::Mstore8,
-                Opcode::UNUSED => panic!("Unknown opcode 0x{:02X}", opcode),
-            };
-            operations.push(op);
-            pc += 1;
+            }
+            if immediate.len() < size {
+                listing.push_str(" (truncated)");
+            }
+            pc += size;
         }
-        Program { operations }
+        listing.push('\n');
     }
+    listing
 }
 
 impl From<Vec<Operation>> for Program {
@@ -0,0 +1,112 @@
+//! # Transaction receipts
+//!
+//! A [`Receipt`] records how a transaction of a block went: whether it succeeded, the gas
+//! used by the block so far, and the events it emitted. Along with them goes a [`Bloom`]
+//! filter of the events' addresses and topics, which tells whether a log may be in the
+//! receipt without going through them.
+use std::fmt;
+
+use sha3::{Digest, Keccak256};
+
+use crate::syscall::{Log, TransactionResult};
+
+/// The summary of a transaction executed as part of a block
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Receipt {
+    /// Whether the transaction succeeded
+    pub status: bool,
+    /// The gas used by the block's transactions up to this one, included
+    pub cumulative_gas: u64,
+    /// The events emitted by the transaction, empty if it failed
+    pub logs: Vec<Log>,
+    /// The bloom filter of the events' addresses and topics
+    pub bloom: Bloom,
+}
+
+impl Receipt {
+    /// Makes the receipt of a transaction, given the gas used by the block's transactions
+    /// before it
+    pub fn new(result: &TransactionResult, previous_cumulative_gas: u64) -> Self {
+        Self {
+            status: result.success,
+            cumulative_gas: previous_cumulative_gas + result.gas_used,
+            logs: result.logs.clone(),
+            bloom: Bloom::from_logs(&result.logs),
+        }
+    }
+}
+
+/// The number of bytes of a bloom filter, which holds 2048 bits
+pub const BLOOM_SIZE: usize = 256;
+
+/// A 2048-bit bloom filter, as kept in receipts and block headers. Each entry sets three
+/// bits, given by the low 11 bits of the first three pairs of bytes of its Keccak-256
+/// hash, counting from the end of the filter.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bloom(pub [u8; BLOOM_SIZE]);
+
+impl Bloom {
+    /// Returns the filter of the addresses and topics of the given events
+    pub fn from_logs(logs: &[Log]) -> Self {
+        let mut bloom = Self::default();
+        for log in logs {
+            bloom.accrue_log(log);
+        }
+        bloom
+    }
+
+    /// Adds the event's address and each of its topics to the filter
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(&log.address);
+        for topic in &log.topics {
+            self.accrue(&topic.to_be_bytes());
+        }
+    }
+
+    /// Adds the entry to the filter
+    pub fn accrue(&mut self, input: &[u8]) {
+        for (byte, mask) in bloom_bits(input) {
+            self.0[byte] |= mask;
+        }
+    }
+
+    /// Adds every entry of the other filter, as the bloom of a block does with its receipts'
+    pub fn accrue_bloom(&mut self, other: &Bloom) {
+        for (byte, other_byte) in self.0.iter_mut().zip(other.0) {
+            *byte |= other_byte;
+        }
+    }
+
+    /// Returns whether the entry may have been added to the filter. It's never wrong when
+    /// it returns false, but it may be when it returns true
+    pub fn contains_input(&self, input: &[u8]) -> bool {
+        bloom_bits(input)
+            .into_iter()
+            .all(|(byte, mask)| self.0[byte] & mask != 0)
+    }
+}
+
+impl Default for Bloom {
+    fn default() -> Self {
+        Self([0; BLOOM_SIZE])
+    }
+}
+
+impl fmt::Debug for Bloom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Bloom(0x")?;
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// Returns the byte of the filter and the mask within it of each of the entry's three bits
+fn bloom_bits(input: &[u8]) -> [(usize, u8); 3] {
+    let hash = Keccak256::digest(input);
+    [0, 2, 4].map(|index| {
+        let bit = (u16::from_be_bytes([hash[index], hash[index + 1]]) & 0x7FF) as usize;
+        (BLOOM_SIZE - 1 - bit / 8, 1 << (bit % 8))
+    })
+}
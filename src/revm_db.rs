@@ -0,0 +1,160 @@
+//! # revm compatibility
+//!
+//! [`RevmDatabaseHost`] lets any [`revm::Database`] back the [`Host`] of a
+//! [`SyscallContext`](crate::syscall::SyscallContext), so existing revm users can keep
+//! their state layer.
+//!
+//! revm's databases are read-only, so the changes made by the program are kept in the
+//! host on top of the database, and can be retrieved afterwards for committing them.
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+};
+
+use revm::{
+    primitives::{Address as RevmAddress, U256 as RevmU256},
+    Database,
+};
+
+use crate::{
+    db::{AccountInfo, Host},
+    env::Address,
+    syscall::U256,
+};
+
+/// A [`Host`] reading the state from a [`revm::Database`], keeping the changes made to it
+///
+/// The host can't fail, so database errors are recorded, reading as if the account or
+/// value didn't exist. They can be checked after the execution with [`Self::take_error`].
+pub struct RevmDatabaseHost<DB: Database> {
+    db: RefCell<DB>,
+    error: RefCell<Option<DB::Error>>,
+    accounts: HashMap<Address, AccountInfo>,
+    storage: HashMap<(Address, U256), U256>,
+    cleared_storage: HashSet<Address>,
+}
+
+impl<DB: Database> RevmDatabaseHost<DB> {
+    pub fn new(db: DB) -> Self {
+        Self {
+            db: RefCell::new(db),
+            error: RefCell::new(None),
+            accounts: HashMap::new(),
+            storage: HashMap::new(),
+            cleared_storage: HashSet::new(),
+        }
+    }
+
+    /// Returns the first error given by the database, if any, clearing it
+    pub fn take_error(&mut self) -> Option<DB::Error> {
+        self.error.get_mut().take()
+    }
+
+    /// Returns the accounts changed by the program, with their new state
+    pub fn changed_accounts(&self) -> &HashMap<Address, AccountInfo> {
+        &self.accounts
+    }
+
+    /// Returns the storage slots written by the program, with their new values
+    pub fn changed_storage(&self) -> &HashMap<(Address, U256), U256> {
+        &self.storage
+    }
+
+    /// Returns the accounts whose storage was cleared by deleting them. The slots written
+    /// afterwards are in [`Self::changed_storage`], and the rest are zero
+    pub fn cleared_storage(&self) -> &HashSet<Address> {
+        &self.cleared_storage
+    }
+
+    /// Returns the database, discarding the changes made by the program
+    pub fn into_inner(self) -> DB {
+        self.db.into_inner()
+    }
+
+    /// Returns the result's value, or records its error and returns [`None`]
+    fn record_error<T>(&self, result: Result<T, DB::Error>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.error.borrow_mut().get_or_insert(error);
+                None
+            }
+        }
+    }
+}
+
+impl<DB: Database> Debug for RevmDatabaseHost<DB> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RevmDatabaseHost")
+            .field("accounts", &self.accounts)
+            .field("storage", &self.storage)
+            .field("cleared_storage", &self.cleared_storage)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<DB: Database> Host for RevmDatabaseHost<DB> {
+    fn sload(&self, address: &Address, key: &U256) -> U256 {
+        if let Some(value) = self.storage.get(&(*address, *key)) {
+            return *value;
+        }
+        if self.cleared_storage.contains(address) {
+            return U256::default();
+        }
+        let result = self
+            .db
+            .borrow_mut()
+            .storage(RevmAddress::from(*address), to_revm_u256(key));
+        self.record_error(result)
+            .map(|value| from_revm_u256(&value))
+            .unwrap_or_default()
+    }
+
+    fn sstore(&mut self, address: Address, key: U256, value: U256) {
+        self.storage.insert((address, key), value);
+    }
+
+    fn clear_storage(&mut self, address: &Address) {
+        self.storage
+            .retain(|(slot_address, _), _| slot_address != address);
+        self.cleared_storage.insert(*address);
+    }
+
+    fn account(&self, address: &Address) -> Option<AccountInfo> {
+        if let Some(account) = self.accounts.get(address) {
+            return Some(account.clone());
+        }
+        let mut db = self.db.borrow_mut();
+        let info = self.record_error(db.basic(RevmAddress::from(*address)))??;
+        // The code may be left out, to be fetched by its hash
+        let code = match info.code {
+            Some(code) => code,
+            None => self.record_error(db.code_by_hash(info.code_hash))?,
+        };
+        Some(AccountInfo {
+            balance: from_revm_u256(&info.balance),
+            nonce: info.nonce,
+            code: code.original_bytes().to_vec(),
+        })
+    }
+
+    fn set_account(&mut self, address: Address, account: AccountInfo) {
+        self.accounts.insert(address, account);
+    }
+
+    fn block_hash(&self, number: &U256) -> U256 {
+        let result = self.db.borrow_mut().block_hash(to_revm_u256(number));
+        self.record_error(result)
+            .map(|hash| U256::from_be_bytes(hash.0))
+            .unwrap_or_default()
+    }
+}
+
+pub(crate) fn to_revm_u256(value: &U256) -> RevmU256 {
+    RevmU256::from_be_bytes(value.to_be_bytes())
+}
+
+pub(crate) fn from_revm_u256(value: &RevmU256) -> U256 {
+    U256::from_be_bytes(value.to_be_bytes())
+}
@@ -0,0 +1,42 @@
+//! # Hardfork specifications
+//!
+//! Each hardfork changes the behavior of the EVM: it introduces new opcodes, and changes
+//! the gas costs or semantics of existing ones. A [`SpecId`] selects the hardfork to follow,
+//! both when compiling a program, where it decides which opcodes are defined, and when
+//! executing it, where it's read from the [`Env`](crate::env::Env).
+
+/// A hardfork of the Ethereum mainnet, ordered by activation
+///
+/// The hardforks that only delayed the difficulty bomb (e.g. Muir Glacier) are left out,
+/// as they don't change the execution.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum SpecId {
+    Frontier = 0,
+    Homestead,
+    /// Also known as EIP-150, which repriced the IO-heavy opcodes
+    TangerineWhistle,
+    SpuriousDragon,
+    Byzantium,
+    Constantinople,
+    Petersburg,
+    Istanbul,
+    Berlin,
+    London,
+    /// The transition to proof of stake, also known as Paris
+    Merge,
+    Shanghai,
+    #[default]
+    Cancun,
+    Prague,
+    /// Brings the EVM Object Format (EOF), see [`crate::eof`]
+    Osaka,
+}
+
+impl SpecId {
+    /// Returns whether the changes introduced by `fork` are active in this hardfork,
+    /// which is the case if it's `fork` itself or a later one
+    pub fn includes(self, fork: SpecId) -> bool {
+        self >= fork
+    }
+}
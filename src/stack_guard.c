@@ -0,0 +1,107 @@
+// Runs compiled programs whose stack is followed by a guard page, catching the fault of
+// a push past the end of the stack. See `stack_guard.rs`.
+#include <setjmp.h>
+#include <signal.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <pthread.h>
+#include <sys/mman.h>
+#include <unistd.h>
+
+typedef uint8_t (*evm_mlir_main_fn)(void *context, uint64_t initial_gas);
+
+struct guarded_run {
+    sigjmp_buf jump;
+    const char *guard_start;
+    const char *guard_end;
+};
+
+// The innermost run of the thread, which nested calls replace while they run
+static __thread struct guarded_run *current_run;
+
+static struct sigaction previous_segv;
+static struct sigaction previous_bus;
+static pthread_once_t handlers_installed = PTHREAD_ONCE_INIT;
+
+static void handle_fault(int signal, siginfo_t *info, void *ucontext) {
+    struct guarded_run *run = current_run;
+    const char *address = info->si_addr;
+    if (run != NULL && address >= run->guard_start && address < run->guard_end) {
+        siglongjmp(run->jump, 1);
+    }
+
+    // Any other fault goes to the handler installed before, like Rust's own
+    struct sigaction *previous = signal == SIGSEGV ? &previous_segv : &previous_bus;
+    if (previous->sa_flags & SA_SIGINFO) {
+        previous->sa_sigaction(signal, info, ucontext);
+    } else if (previous->sa_handler == SIG_DFL) {
+        // Returning runs the faulting instruction again, which now kills the process
+        sigaction(signal, previous, NULL);
+    } else if (previous->sa_handler != SIG_IGN) {
+        previous->sa_handler(signal);
+    }
+}
+
+static void install_handlers(void) {
+    struct sigaction action = {0};
+    action.sa_sigaction = handle_fault;
+    action.sa_flags = SA_SIGINFO | SA_ONSTACK;
+    sigemptyset(&action.sa_mask);
+    sigaction(SIGSEGV, &action, &previous_segv);
+    // Some systems report accesses to protected pages as bus errors
+    sigaction(SIGBUS, &action, &previous_bus);
+}
+
+// Maps `stack_size` bytes followed by a guard page, returning the start of the stack, or
+// NULL if they can't be mapped. The mapping is given back through `mapping` and
+// `mapping_size`, to unmap it with `evm_mlir_unmap_guarded_stack`
+void *evm_mlir_map_guarded_stack(size_t stack_size, void **mapping, size_t *mapping_size) {
+    size_t page_size = (size_t)sysconf(_SC_PAGESIZE);
+    size_t stack_pages_size = (stack_size + page_size - 1) / page_size * page_size;
+    size_t size = stack_pages_size + page_size;
+
+    char *start = mmap(NULL, size, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+    if (start == MAP_FAILED) {
+        return NULL;
+    }
+    if (mprotect(start + stack_pages_size, page_size, PROT_NONE) != 0) {
+        munmap(start, size);
+        return NULL;
+    }
+    *mapping = start;
+    *mapping_size = size;
+    // The stack ends right where the guard page starts
+    return start + stack_pages_size - stack_size;
+}
+
+void evm_mlir_unmap_guarded_stack(void *mapping, size_t mapping_size) {
+    munmap(mapping, mapping_size);
+}
+
+// Runs the main function, returning 1 if it faulted on the guard page at `guard_start`,
+// and 0 with its exit code in `exit_code` otherwise
+int evm_mlir_run_guarded(
+    evm_mlir_main_fn main_fn,
+    void *context,
+    uint64_t initial_gas,
+    const void *guard_start,
+    uint8_t *exit_code
+) {
+    pthread_once(&handlers_installed, install_handlers);
+
+    size_t page_size = (size_t)sysconf(_SC_PAGESIZE);
+    struct guarded_run run;
+    run.guard_start = guard_start;
+    run.guard_end = (const char *)guard_start + page_size;
+    struct guarded_run *outer_run = current_run;
+
+    // The signal mask is saved, as the handler jumps back with the fault's signal blocked
+    if (sigsetjmp(run.jump, 1) != 0) {
+        current_run = outer_run;
+        return 1;
+    }
+    current_run = &run;
+    *exit_code = main_fn(context, initial_gas);
+    current_run = outer_run;
+    return 0;
+}
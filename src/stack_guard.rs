@@ -0,0 +1,111 @@
+//! # Guard-page stack overflow detection
+//!
+//! Programs compiled with [`OverflowCheck::GuardPage`] don't check the stack has space
+//! before pushing onto it. Their stack is mapped right before a guard page, which can't
+//! be accessed, so a push past the end of the stack faults instead. [`run_guarded`]
+//! runs such a program, catching the fault and halting it with
+//! [`ExitStatusCode::Error`], as the check it replaces would.
+//!
+//! Only the faults on the guard page of the running program are caught, any other one
+//! goes to the handler installed before. The handler is installed for `SIGSEGV` and
+//! `SIGBUS` the first time a program runs, and chains to the previous ones.
+//!
+//! [`OverflowCheck::GuardPage`]: crate::options::OverflowCheck::GuardPage
+use std::{ffi::c_void, ptr::null_mut};
+
+use crate::{
+    constants::ExitStatusCode,
+    syscall::{MainFunc, SyscallContext, U256},
+};
+
+extern "C" {
+    fn evm_mlir_map_guarded_stack(
+        stack_size: usize,
+        mapping: *mut *mut c_void,
+        mapping_size: *mut usize,
+    ) -> *mut c_void;
+    fn evm_mlir_unmap_guarded_stack(mapping: *mut c_void, mapping_size: usize);
+    fn evm_mlir_run_guarded(
+        main_fn: MainFunc,
+        context: *mut SyscallContext,
+        initial_gas: u64,
+        guard_start: *const c_void,
+        exit_code: *mut u8,
+    ) -> i32;
+}
+
+/// A stack of words mapped right before a guard page. It's unmapped when dropped
+#[derive(Debug)]
+struct GuardedStack {
+    base: *mut U256,
+    limit: usize,
+    mapping: *mut c_void,
+    mapping_size: usize,
+}
+
+impl GuardedStack {
+    /// Maps a stack holding at most `limit` words, or returns `None` if it can't be mapped
+    fn new(limit: usize) -> Option<Self> {
+        let mut mapping = null_mut();
+        let mut mapping_size = 0;
+        let stack_size = limit * std::mem::size_of::<U256>();
+        let base =
+            unsafe { evm_mlir_map_guarded_stack(stack_size, &mut mapping, &mut mapping_size) };
+        if base.is_null() {
+            return None;
+        }
+        Some(Self {
+            base: base.cast(),
+            limit,
+            mapping,
+            mapping_size,
+        })
+    }
+
+    /// Returns the bottom of the stack
+    fn base(&self) -> *mut U256 {
+        self.base
+    }
+
+    /// Returns the start of the guard page, right past the last word of the stack
+    fn guard_start(&self) -> *const c_void {
+        self.base.wrapping_add(self.limit).cast()
+    }
+}
+
+impl Drop for GuardedStack {
+    fn drop(&mut self) {
+        unsafe { evm_mlir_unmap_guarded_stack(self.mapping, self.mapping_size) };
+    }
+}
+
+/// Runs the main function of a program compiled with a guard page with a new stack
+/// holding at most `stack_limit` words, returning its exit code. A push overflowing the
+/// stack halts the program with [`ExitStatusCode::Error`]
+pub fn run_guarded(
+    main_fn: MainFunc,
+    context: &mut SyscallContext,
+    initial_gas: u64,
+    stack_limit: usize,
+) -> u8 {
+    let Some(stack) = GuardedStack::new(stack_limit) else {
+        return ExitStatusCode::Error.to_u8();
+    };
+    context.set_stack(stack.base());
+    let mut exit_code = 0;
+    let faulted = unsafe {
+        evm_mlir_run_guarded(
+            main_fn,
+            context,
+            initial_gas,
+            stack.guard_start(),
+            &mut exit_code,
+        )
+    };
+    // The stack is unmapped on returning
+    context.set_stack(null_mut());
+    if faulted != 0 {
+        return ExitStatusCode::Error.to_u8();
+    }
+    exit_code
+}
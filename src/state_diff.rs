@@ -0,0 +1,98 @@
+//! # State diffs
+//!
+//! A [`StateDiff`] lists what an execution changed in the state: the balance, nonce and
+//! storage slots of each account, as their values before and after it, and which accounts
+//! were created or destroyed. Like the state revm returns, it only holds the accounts
+//! touched, but it leaves out what ended up as it was, so that both can be compared.
+use std::collections::BTreeMap;
+
+use crate::{db::AccountInfo, env::Address, syscall::U256};
+
+/// The changes made to the state by an execution, by address
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StateDiff {
+    /// The accounts changed, in the order of their addresses
+    pub accounts: BTreeMap<Address, AccountDiff>,
+}
+
+/// The changes made to an account
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AccountDiff {
+    /// The account's balance, if it changed
+    pub balance: Option<Change<U256>>,
+    /// The account's nonce, if it changed
+    pub nonce: Option<Change<u64>>,
+    /// The storage slots that changed, in the order of their keys
+    pub storage: BTreeMap<U256, Change<U256>>,
+    /// Whether the account was created by a contract creation
+    pub created: bool,
+    /// Whether the account was deleted, as it self-destructed
+    pub destroyed: bool,
+}
+
+/// A value before and after the execution
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Change<T> {
+    pub before: T,
+    pub after: T,
+}
+
+impl StateDiff {
+    /// Returns whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
+    }
+
+    /// Returns the changes made to the account at `address`, if any
+    pub fn account(&self, address: &Address) -> Option<&AccountDiff> {
+        self.accounts.get(address)
+    }
+
+    /// Records the balance and nonce of the account at `address` before and after the
+    /// execution. Nothing is recorded for the values that didn't change
+    pub(crate) fn record_account(
+        &mut self,
+        address: Address,
+        before: &AccountInfo,
+        after: &AccountInfo,
+    ) {
+        let balance = Change::of(before.balance, after.balance);
+        let nonce = Change::of(before.nonce, after.nonce);
+        if balance.is_some() || nonce.is_some() {
+            let account = self.accounts.entry(address).or_default();
+            account.balance = balance;
+            account.nonce = nonce;
+        }
+    }
+
+    /// Records the value at `key` in the storage of the account at `address` before and
+    /// after the execution, unless it didn't change
+    pub(crate) fn record_storage(
+        &mut self,
+        address: Address,
+        key: U256,
+        before: U256,
+        after: U256,
+    ) {
+        if let Some(change) = Change::of(before, after) {
+            let account = self.accounts.entry(address).or_default();
+            account.storage.insert(key, change);
+        }
+    }
+
+    /// Marks the account at `address` as created, or as destroyed
+    pub(crate) fn record_lifecycle(&mut self, address: Address, created: bool, destroyed: bool) {
+        if created || destroyed {
+            let account = self.accounts.entry(address).or_default();
+            account.created |= created;
+            account.destroyed |= destroyed;
+        }
+    }
+}
+
+impl<T: PartialEq> Change<T> {
+    /// Returns the change from `before` to `after`, or [`None`] if they're equal
+    pub fn of(before: T, after: T) -> Option<Self> {
+        (before != after).then_some(Self { before, after })
+    }
+}
@@ -0,0 +1,329 @@
+//! # Program generation
+//!
+//! [proptest](https://docs.rs/proptest) strategies generating structurally valid
+//! [`Program`]s, for property tests over the compiler.
+//!
+//! The generated programs never underflow nor overflow the stack, and only jump forward
+//! to JUMPDESTs they contain, so they always run to their end given enough gas.
+//! Memory is only reached at small offsets, and there are no calls, creations or
+//! opcodes halting on purpose (like REVERT or INVALID). The operations introduced in
+//! every hardfork up to Cancun may be used.
+//!
+//! Programs are generated as a sequence of fragments, and the stack is fixed up while
+//! laying them out: filler values are pushed before operations needing more inputs
+//! than there are, and items are popped before those growing a full stack.
+//! Shrinking a failing case removes fragments, which keeps the program valid.
+use num_bigint::BigUint;
+use proptest::{
+    arbitrary::Arbitrary,
+    collection::vec,
+    prelude::{any, prop_oneof, BoxedStrategy, Strategy},
+    sample::select,
+};
+
+use crate::{
+    constants::MAX_STACK_SIZE,
+    program::{Operation, Program},
+};
+
+/// The maximum offset, and size, of the memory regions accessed
+const MAX_MEMORY_ACCESS: usize = 1024;
+
+/// The maximum number of fragments at the top level of a program
+const MAX_FRAGMENTS: usize = 32;
+
+/// Returns a strategy generating operations that can't halt the program, whatever their
+/// inputs, as long as they are on the stack.
+///
+/// A PC is generated with a `pc` of 0, which is only right at the start of a program.
+pub fn operation() -> impl Strategy<Value = Operation> {
+    prop_oneof![
+        4 => select(simple_operations()),
+        // Immediates with leading zeros keep their width
+        1 => vec(any::<u8>(), 1..=32).prop_map(|bytes| Operation::push_n(&bytes).unwrap()),
+        1 => (1..=16_u32).prop_map(Operation::Dup),
+        1 => (1..=16_u32).prop_map(Operation::Swap),
+    ]
+}
+
+/// Returns a strategy generating valid programs, which may leave items on the stack
+/// and don't end with a STOP
+pub fn program() -> impl Strategy<Value = Program> {
+    vec(fragment(), 0..MAX_FRAGMENTS).prop_map(|fragments| {
+        let mut assembler = Assembler::default();
+        assembler.fragments(&fragments);
+        assembler.finish()
+    })
+}
+
+/// Returns a strategy generating valid programs that end with an empty stack and a STOP
+pub fn stack_neutral_program() -> impl Strategy<Value = Program> {
+    vec(fragment(), 0..MAX_FRAGMENTS).prop_map(|fragments| {
+        let mut assembler = Assembler::default();
+        assembler.fragments(&fragments);
+        while assembler.depth > 0 {
+            assembler.operation(Operation::Pop);
+        }
+        assembler.operation(Operation::Stop);
+        assembler.finish()
+    })
+}
+
+/// Generates programs with [`program`]
+impl Arbitrary for Program {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Program>;
+
+    fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+        program().boxed()
+    }
+}
+
+/// The operations without immediates or memory accesses generated by [`operation`]
+fn simple_operations() -> Vec<Operation> {
+    vec![
+        Operation::Add,
+        Operation::Mul,
+        Operation::Sub,
+        Operation::Div,
+        Operation::Sdiv,
+        Operation::Mod,
+        Operation::SMod,
+        Operation::Addmod,
+        Operation::Mulmod,
+        Operation::Exp,
+        Operation::SignExtend,
+        Operation::Lt,
+        Operation::Gt,
+        Operation::Slt,
+        Operation::Sgt,
+        Operation::Eq,
+        Operation::IsZero,
+        Operation::And,
+        Operation::Or,
+        Operation::Xor,
+        Operation::Not,
+        Operation::Byte,
+        Operation::Shr,
+        Operation::Shl,
+        Operation::Sar,
+        Operation::Pop,
+        Operation::PC { pc: 0 },
+        Operation::Gas,
+        Operation::Push0,
+        Operation::Msize,
+        Operation::CallDataLoad,
+        Operation::CallDataSize,
+        Operation::Codesize,
+        Operation::Sload,
+        Operation::Sstore,
+        Operation::Tload,
+        Operation::Tstore,
+        Operation::Address,
+        Operation::Balance,
+        Operation::Origin,
+        Operation::Caller,
+        Operation::Callvalue,
+        Operation::Gasprice,
+        Operation::Coinbase,
+        Operation::Timestamp,
+        Operation::Number,
+        Operation::Prevrandao,
+        Operation::Gaslimit,
+        Operation::Chainid,
+        Operation::Selfbalance,
+        Operation::Basefee,
+        Operation::Blockhash,
+        Operation::Returndatasize,
+        Operation::Blobhash,
+        Operation::Blobbasefee,
+    ]
+}
+
+/// A piece of a generated program, which the [`Assembler`] turns into operations
+#[derive(Debug, Clone)]
+enum Fragment {
+    /// An operation generated by [`operation`]
+    Operation(Operation),
+    /// An operation reaching memory, with the offsets and sizes it takes from the top
+    /// of the stack, in order
+    MemoryAccess(Operation, Vec<usize>),
+    /// A jump over the fragments, to a JUMPDEST right after them.
+    /// A conditional jump takes its condition from the stack
+    Jump {
+        conditional: bool,
+        skipped: Vec<Fragment>,
+    },
+}
+
+fn memory_access() -> impl Strategy<Value = Fragment> {
+    let place = || 0..MAX_MEMORY_ACCESS;
+    prop_oneof![
+        place().prop_map(|offset| Fragment::MemoryAccess(Operation::Mload, vec![offset])),
+        place().prop_map(|offset| Fragment::MemoryAccess(Operation::Mstore, vec![offset])),
+        place().prop_map(|offset| Fragment::MemoryAccess(Operation::Mstore8, vec![offset])),
+        (place(), place()).prop_map(|(offset, size)| {
+            Fragment::MemoryAccess(Operation::Keccak256, vec![offset, size])
+        }),
+        (place(), place(), place()).prop_map(|(dest, offset, size)| {
+            Fragment::MemoryAccess(Operation::Mcopy, vec![dest, offset, size])
+        }),
+        (place(), place(), place()).prop_map(|(dest, offset, size)| {
+            Fragment::MemoryAccess(Operation::CallDataCopy, vec![dest, offset, size])
+        }),
+        (place(), place(), place()).prop_map(|(dest, offset, size)| {
+            Fragment::MemoryAccess(Operation::Codecopy, vec![dest, offset, size])
+        }),
+        (0..=4_u8, place(), place()).prop_map(|(topics, offset, size)| {
+            Fragment::MemoryAccess(Operation::Log(topics), vec![offset, size])
+        }),
+    ]
+}
+
+fn fragment() -> impl Strategy<Value = Fragment> {
+    let leaf = prop_oneof![
+        4 => operation().prop_map(Fragment::Operation),
+        1 => memory_access(),
+    ];
+    leaf.prop_recursive(3, 64, 8, |inner| {
+        (any::<bool>(), vec(inner, 0..8)).prop_map(|(conditional, skipped)| Fragment::Jump {
+            conditional,
+            skipped,
+        })
+    })
+}
+
+/// An operation being assembled, before knowing where the JUMPDESTs land
+#[derive(Debug)]
+enum Item {
+    Operation(Operation),
+    /// Pushes the offset of the JUMPDEST with the given label
+    PushLabel(usize),
+    /// A JUMPDEST, with its label
+    Label(usize),
+}
+
+/// Turns fragments into operations, keeping track of the stack depth to fix it up
+#[derive(Debug, Default)]
+struct Assembler {
+    items: Vec<Item>,
+    /// The items on the stack after the last item
+    depth: usize,
+    /// The number of labels created
+    labels: usize,
+}
+
+impl Assembler {
+    fn fragments(&mut self, fragments: &[Fragment]) {
+        for fragment in fragments {
+            match fragment {
+                Fragment::Operation(operation) => self.operation(operation.clone()),
+                Fragment::MemoryAccess(operation, arguments) => {
+                    self.memory_access(operation.clone(), arguments)
+                }
+                Fragment::Jump {
+                    conditional,
+                    skipped,
+                } => self.jump(*conditional, skipped),
+            }
+        }
+    }
+
+    fn operation(&mut self, operation: Operation) {
+        let effect = operation.stack_effect();
+        self.make_room(effect.inputs, effect.outputs.saturating_sub(effect.inputs));
+        self.push_operation(operation);
+    }
+
+    fn memory_access(&mut self, operation: Operation, arguments: &[usize]) {
+        // The arguments are pushed over the rest of the inputs
+        let inputs = operation.stack_effect().inputs - arguments.len();
+        self.make_room(inputs, arguments.len());
+        for argument in arguments.iter().rev() {
+            self.push_operation(Operation::Push(BigUint::from(*argument)));
+        }
+        self.push_operation(operation);
+    }
+
+    fn jump(&mut self, conditional: bool, skipped: &[Fragment]) {
+        let label = self.labels;
+        self.labels += 1;
+
+        self.make_room(conditional as usize, 1);
+        self.items.push(Item::PushLabel(label));
+        if conditional {
+            self.items.push(Item::Operation(Operation::Jumpi));
+            self.depth -= 1;
+        } else {
+            self.items.push(Item::Operation(Operation::Jump));
+        }
+
+        // Both ways must reach the JUMPDEST with the same stack depth
+        let depth = self.depth;
+        self.fragments(skipped);
+        self.make_room(depth, 0);
+        while self.depth > depth {
+            self.push_operation(Operation::Pop);
+        }
+        self.items.push(Item::Label(label));
+    }
+
+    /// Pushes filler values until the stack holds `inputs` items, then pops items until
+    /// it can grow by `growth` items
+    fn make_room(&mut self, inputs: usize, growth: usize) {
+        while self.depth < inputs {
+            self.push_operation(Operation::Push(BigUint::from(self.depth)));
+        }
+        while self.depth + growth > MAX_STACK_SIZE {
+            self.push_operation(Operation::Pop);
+        }
+    }
+
+    /// Adds the operation as is, expecting the stack to hold its inputs
+    fn push_operation(&mut self, operation: Operation) {
+        let effect = operation.stack_effect();
+        self.depth = self.depth - effect.inputs + effect.outputs;
+        self.items.push(Item::Operation(operation));
+    }
+
+    /// Places the JUMPDESTs, giving the assembled program
+    fn finish(self) -> Program {
+        // Pushing the offset of a JUMPDEST takes more bytes the further it is, which
+        // moves the JUMPDESTs after it. Offsets only grow, so this settles
+        let mut offsets = vec![0; self.labels];
+        loop {
+            let mut pc = 0;
+            let mut moved = false;
+            for item in &self.items {
+                pc += match item {
+                    Item::Operation(operation) => operation.to_bytecode().len(),
+                    Item::PushLabel(label) => Operation::Push(BigUint::from(offsets[*label]))
+                        .to_bytecode()
+                        .len(),
+                    Item::Label(label) => {
+                        moved |= offsets[*label] != pc;
+                        offsets[*label] = pc;
+                        1
+                    }
+                };
+            }
+            if !moved {
+                break;
+            }
+        }
+
+        let mut pc = 0;
+        let mut operations = Vec::with_capacity(self.items.len());
+        for item in self.items {
+            let operation = match item {
+                Item::Operation(Operation::PC { .. }) => Operation::PC { pc },
+                Item::Operation(operation) => operation,
+                Item::PushLabel(label) => Operation::Push(BigUint::from(offsets[label])),
+                Item::Label(label) => Operation::Jumpdest { pc: offsets[label] },
+            };
+            pc += operation.to_bytecode().len();
+            operations.push(operation);
+        }
+        Program::from(operations)
+    }
+}
@@ -9,138 +9,3625 @@
 //! ### Adding a new syscall
 //!
 //! New syscalls should be implemented by adding a new method to the [`SyscallContext`]
-//! struct (see [`SyscallContext::write_result`] for an example). After that, the syscall
+//! struct (see [`SyscallContext::write_result`] for an example), exported with the name of
+//! its symbol in [`symbols`] so shared libraries can find it. After that, the syscall
 //! should be registered in the [`register_syscalls`] function, which will make it available
 //! to the generated code. Afterwards, the syscall should be declared in
 //! [`mlir::declare_syscalls`], which will make the syscall available inside the MLIR code.
 //! Finally, the function can be called from the MLIR code like a normal function (see
 //! [`mlir::write_result_syscall`] for an example).
-use std::ffi::c_void;
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    fmt,
+    rc::Rc,
+};
 
 use melior::ExecutionEngine;
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    cache::{compile_code, ContractCache},
+    constants::{
+        gas_cost, CallType, ExitStatusCode, MAX_CALL_DEPTH, MAX_CODE_SIZE, MAX_INITCODE_SIZE,
+    },
+    db::{
+        AccountInfo, AccountProvider, BlockHashProvider, Checkpoint, Host, InMemoryAccounts,
+        InMemoryBlockHashes, InMemoryStorage, SharedHost, Storage,
+    },
+    env::{Address, CallEnv, Env},
+    errors::TransactionError,
+    inspector::{CallInputs, CallKind, CallOutcome, Inspector},
+    memory::{Memory, MemoryPool},
+    precompiles::Precompile,
+    spec::SpecId,
+    state_diff::StateDiff,
+    trace::{TraceStep, Tracer},
+};
 
 /// Function type for the main entrypoint of the generated code
 pub type MainFunc = extern "C" fn(&mut SyscallContext, initial_gas: u64) -> u8;
 
+/// Function type for the entrypoint exported by shared libraries, see
+/// [`compile_shared_library`](crate::compile_shared_library)
+pub type SharedEntrypoint = unsafe extern "C" fn(
+    env: *const Env,
+    calldata: *const u8,
+    calldata_len: u64,
+    gas: *mut u64,
+    output: *mut u8,
+    output_capacity: u64,
+    output_len: *mut u64,
+) -> u8;
+
+/// A 256-bit unsigned integer, laid out in memory like an LLVM `i256`
+///
+/// This is used for passing 256-bit values between the generated code and syscalls.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[repr(C)]
+pub struct U256 {
+    pub lo: u128,
+    pub hi: u128,
+}
+
+impl U256 {
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let (hi, lo) = bytes.split_at(16);
+        Self {
+            hi: u128::from_be_bytes(hi.try_into().unwrap()),
+            lo: u128::from_be_bytes(lo.try_into().unwrap()),
+        }
+    }
+
+    pub fn to_be_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&self.hi.to_be_bytes());
+        bytes[16..].copy_from_slice(&self.lo.to_be_bytes());
+        bytes
+    }
+
+    /// Returns the address in the 20 least significant bytes, discarding the rest
+    pub fn to_address(&self) -> Address {
+        self.to_be_bytes()[12..].try_into().unwrap()
+    }
+
+    /// Returns `self + other`, or [`None`] if it overflows
+    pub fn checked_add(&self, other: &Self) -> Option<Self> {
+        let (lo, carry) = self.lo.overflowing_add(other.lo);
+        let hi = self.hi.checked_add(other.hi)?.checked_add(carry as u128)?;
+        Some(Self { lo, hi })
+    }
+
+    /// Returns `self - other`, or [`None`] if it underflows
+    pub fn checked_sub(&self, other: &Self) -> Option<Self> {
+        let (lo, borrow) = self.lo.overflowing_sub(other.lo);
+        let hi = self.hi.checked_sub(other.hi)?.checked_sub(borrow as u128)?;
+        Some(Self { lo, hi })
+    }
+
+    /// Returns `self * other`, or [`None`] if it overflows
+    pub fn checked_mul(&self, other: &Self) -> Option<Self> {
+        // The product only fits if one of the factors fits in the lower half
+        let (large, small) = match (self.hi, other.hi) {
+            (0, _) => (other, self.lo),
+            (_, 0) => (self, other.lo),
+            _ => return None,
+        };
+        let (lo, carry) = widening_mul(large.lo, small);
+        let hi = large.hi.checked_mul(small)?.checked_add(carry)?;
+        Some(Self { lo, hi })
+    }
+}
+
+/// Returns the full product of `a` and `b`, as its lower and upper halves
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a_lo, a_hi) = (a & MASK, a >> 64);
+    let (b_lo, b_hi) = (b & MASK, b >> 64);
+    let lo_lo = a_lo * b_lo;
+    let lo_hi = a_lo * b_hi;
+    let hi_lo = a_hi * b_lo;
+    let hi_hi = a_hi * b_hi;
+    let middle = (lo_lo >> 64) + (lo_hi & MASK) + (hi_lo & MASK);
+    let lo = (lo_lo & MASK) | (middle << 64);
+    let hi = hi_hi + (lo_hi >> 64) + (hi_lo >> 64) + (middle >> 64);
+    (lo, hi)
+}
+
+/// Formats the value without leading zeros, like `0x2a` with the `#` flag
+impl fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.hi == 0 {
+            return fmt::LowerHex::fmt(&self.lo, f);
+        }
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        write!(f, "{:x}{:032x}", self.hi, self.lo)
+    }
+}
+
+/// Compares the values as numbers, the upper halves first
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.hi, self.lo).cmp(&(other.hi, other.lo))
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl From<u128> for U256 {
+    fn from(value: u128) -> Self {
+        Self { lo: value, hi: 0 }
+    }
+}
+
+impl From<Address> for U256 {
+    fn from(address: Address) -> Self {
+        // Addresses are stored in the least significant bytes
+        let mut bytes = [0; 32];
+        bytes[12..].copy_from_slice(&address);
+        Self::from_be_bytes(bytes)
+    }
+}
+
+/// An event emitted by one of the LOG opcodes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Log {
+    /// The address of the contract that emitted the event
+    pub address: Address,
+    /// The memory segment given to the LOG opcode
+    pub data: Vec<u8>,
+    /// The topics given to the LOG opcode, at most four
+    pub topics: Vec<U256>,
+}
+
+/// The outcome of a transaction executed with [`SyscallContext::transact`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionResult {
+    /// Whether the transaction's call or contract creation succeeded
+    pub success: bool,
+    /// The gas paid by the sender, including the intrinsic gas and net of the refund
+    pub gas_used: u64,
+    /// The gas refunded to the sender, capped at a fifth of the gas used (EIP-3529)
+    pub gas_refunded: u64,
+    /// The output of the call, or of the initcode when creating a contract
+    pub output: Vec<u8>,
+    /// The address of the created contract, if the transaction created one
+    pub created_address: Option<Address>,
+    /// The events emitted by the transaction, in order, which are discarded if it failed
+    pub logs: Vec<Log>,
+    /// The changes made to the state, including the fees paid and the sender's nonce
+    pub state_diff: StateDiff,
+}
+
+/// The outcome of a deployment made with [`SyscallContext::deploy`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeploymentResult {
+    /// The address of the new contract, or [`None`] if the deployment failed
+    pub address: Option<Address>,
+    /// The gas used by the initcode and for depositing the code
+    pub gas_used: u64,
+    /// The output of the initcode if it reverted, which is empty otherwise
+    pub output: Vec<u8>,
+}
+
+/// A change made by a [`SyscallContext`] to the state of the transaction, holding what's
+/// needed for undoing it. The changes to the accounts and storage are only undone when the
+/// host can't roll them back by itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum JournalEntry {
+    /// The account at `address` was set, replacing `previous`
+    AccountChanged {
+        address: Address,
+        previous: Option<AccountInfo>,
+    },
+    /// The slot at `key` in the storage of the account at `address` was written,
+    /// replacing `previous`
+    StorageChanged {
+        address: Address,
+        key: U256,
+        previous: U256,
+    },
+    /// The slot at `key` in the transient storage of the account at `address` was written,
+    /// replacing `previous`
+    TransientStorageChanged {
+        address: Address,
+        key: U256,
+        previous: U256,
+    },
+}
+
+/// The state of the transaction when a call frame started, which is restored if the frame
+/// fails. See [`SyscallContext::snapshot`]
+#[derive(Debug)]
+struct FrameSnapshot {
+    /// The checkpoint of the host, if it keeps a journal
+    checkpoint: Option<Checkpoint>,
+    /// The number of changes in the context's own journal
+    journal_len: usize,
+    original_storage: HashMap<(Address, U256), U256>,
+    refund_counter: i64,
+    created_accounts: HashSet<Address>,
+    destroyed_accounts: HashSet<Address>,
+    warm_addresses: HashSet<Address>,
+    warm_slots: HashSet<(Address, U256)>,
+}
+
 /// The context passed to syscalls
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct SyscallContext {
     /// The memory segment of the EVM.
     /// For extending it, see [`Self::extend_memory`]
-    memory: Vec<u8>,
+    memory: Memory,
     /// The offset and size in [`Self::memory`] corresponding to the EVM return data.
     /// It's [`None`] in case there's no return data
     result: Option<(usize, usize)>,
+    /// The exit status given by the program when writing its result.
+    /// It's [`None`] if the program halted with an exceptional error
+    exit_status: Option<ExitStatusCode>,
+    /// The gas left when the program halted.
+    /// It's zero if the program halted with an exceptional error
+    remaining_gas: u64,
+    /// The environment the program is executed in.
+    env: Env,
+    /// The input data of the call.
+    calldata: Vec<u8>,
+    /// The code being executed, read by CODESIZE and CODECOPY.
+    code: Vec<u8>,
+    /// The output data of the last subcall made by the program.
+    return_data: Vec<u8>,
+    /// The persistent storage of the accounts.
+    storage: Box<dyn Storage>,
+    /// The values of the storage slots at the start of the transaction,
+    /// recorded the first time each slot is written.
+    /// SSTORE's gas cost and refund depend on them (EIP-2200).
+    original_storage: HashMap<(Address, U256), U256>,
+    /// The gas to be refunded at the end of the transaction, before capping it.
+    /// It can go negative when a refund is undone.
+    refund_counter: i64,
+    /// The transient storage of the accounts (EIP-1153).
+    /// It starts empty, and is discarded along with the context at the end of the transaction.
+    transient_storage: InMemoryStorage,
+    /// The events emitted by the program, in order.
+    logs: Vec<Log>,
+    /// The provider of the hashes of previous blocks.
+    block_hashes: Box<dyn BlockHashProvider>,
+    /// The provider of the state of the accounts.
+    accounts: Box<dyn AccountProvider>,
+    /// The host supplying all the state, if any.
+    /// It's used for rolling back the changes made by failed calls.
+    host: Option<Rc<RefCell<dyn Host>>>,
+    /// The changes made to the accounts, storage and transient storage in the current
+    /// transaction, in order. They're undone when a call fails.
+    journal: Vec<JournalEntry>,
+    /// The accounts created in the current transaction.
+    /// Only these are deleted when they self-destruct (EIP-6780).
+    created_accounts: HashSet<Address>,
+    /// The state of the accounts before the program changed them, recorded the first time
+    /// each account is set. [`Self::state_diff`] compares them with their current state.
+    original_accounts: HashMap<Address, Option<AccountInfo>>,
+    /// The accounts self-destructed in the current transaction, which are deleted at its end
+    /// along with their storage. See [`Self::transact`].
+    destroyed_accounts: HashSet<Address>,
+    /// The accounts accessed in the current transaction (EIP-2929), starting with the ones
+    /// accessed from its start, see [`Self::is_warm_address`].
+    warm_addresses: HashSet<Address>,
+    /// The storage slots accessed in the current transaction (EIP-2929), starting with the
+    /// ones in its access list.
+    warm_slots: HashSet<(Address, U256)>,
+    /// The number of calls the current one is nested in. It's zero for the outermost call.
+    depth: usize,
+    /// Whether the call is forbidden from modifying the state,
+    /// which is the case inside a STATICCALL and any call nested in it.
+    is_static: bool,
+    /// The recorder of the steps executed in all the calls of the transaction.
+    /// It's [`None`] unless tracing was enabled.
+    tracer: Option<Tracer>,
+    /// The inspector notified of the execution of all the calls of the transaction.
+    inspector: Option<Box<dyn Inspector>>,
+    /// The last step executed in this call, along with the number of events emitted
+    /// before it. It's finished once the next one starts, or the call halts.
+    current_step: Option<(TraceStep, usize)>,
+    /// The cache of the code compiled for the calls of the transaction, if any.
+    contract_cache: Option<Rc<RefCell<ContractCache>>>,
+    /// The stack mapped for the call, for the programs compiled with a guard page.
+    /// It's null unless they're running, see [`Self::get_stack`]
+    stack: *mut U256,
+}
+
+impl Default for SyscallContext {
+    fn default() -> Self {
+        Self::new(
+            Env::default(),
+            Vec::new(),
+            Box::<InMemoryStorage>::default(),
+        )
+    }
+}
+
+impl SyscallContext {
+    /// Creates a syscall context with the given environment, the given calldata as
+    /// the program's input, and the given backend for the persistent storage
+    pub fn new(env: Env, calldata: Vec<u8>, storage: Box<dyn Storage>) -> Self {
+        Self {
+            memory: Memory::new(),
+            result: None,
+            exit_status: None,
+            remaining_gas: 0,
+            env,
+            calldata,
+            code: Vec::new(),
+            return_data: Vec::new(),
+            storage,
+            original_storage: HashMap::new(),
+            refund_counter: 0,
+            transient_storage: InMemoryStorage::new(),
+            logs: Vec::new(),
+            block_hashes: Box::<InMemoryBlockHashes>::default(),
+            accounts: Box::<InMemoryAccounts>::default(),
+            host: None,
+            journal: Vec::new(),
+            created_accounts: HashSet::new(),
+            original_accounts: HashMap::new(),
+            destroyed_accounts: HashSet::new(),
+            warm_addresses: HashSet::new(),
+            warm_slots: HashSet::new(),
+            depth: 0,
+            is_static: false,
+            tracer: None,
+            inspector: None,
+            current_step: None,
+            contract_cache: None,
+            stack: std::ptr::null_mut(),
+        }
+    }
+
+    /// Creates a syscall context with the given environment and calldata as the program's
+    /// input, accessing all the state through `host`.
+    ///
+    /// The host is shared, so it can be inspected after the execution.
+    pub fn with_host<H: Host + 'static>(env: Env, calldata: Vec<u8>, host: Rc<RefCell<H>>) -> Self {
+        let mut context = Self::new(env, calldata, Box::new(SharedHost(host.clone())));
+        context.set_block_hashes(Box::new(SharedHost(host.clone())));
+        context.set_accounts(Box::new(SharedHost(host.clone())));
+        context.host = Some(host);
+        context
+    }
+
+    /// Creates a syscall context with the given calldata as the program's input
+    pub fn with_calldata(calldata: Vec<u8>) -> Self {
+        Self::new(Env::default(), calldata, Box::<InMemoryStorage>::default())
+    }
+
+    /// Creates a syscall context with the given backend for the persistent storage
+    pub fn with_storage(storage: Box<dyn Storage>) -> Self {
+        Self::new(Env::default(), Vec::new(), storage)
+    }
+
+    /// Creates a syscall context with the given execution environment
+    pub fn with_env(env: Env) -> Self {
+        Self::new(env, Vec::new(), Box::<InMemoryStorage>::default())
+    }
+
+    /// Sets the code being executed, to be read with CODESIZE and CODECOPY.
+    /// It's empty by default, and set for each frame run by the context itself.
+    pub fn set_code(&mut self, code: Vec<u8>) {
+        self.code = code;
+    }
+
+    /// Sets the output data of the last subcall, to be read with the RETURNDATA* opcodes.
+    /// It's empty until a subcall is made.
+    pub fn set_return_data(&mut self, return_data: Vec<u8>) {
+        self.return_data = return_data;
+    }
+
+    /// Sets the provider for the hashes of previous blocks.
+    /// By default, the hashes of all blocks are zero.
+    pub fn set_block_hashes(&mut self, block_hashes: Box<dyn BlockHashProvider>) {
+        self.block_hashes = block_hashes;
+    }
+
+    /// Sets the provider for the state of the accounts.
+    /// By default, no account exists.
+    pub fn set_accounts(&mut self, accounts: Box<dyn AccountProvider>) {
+        self.accounts = accounts;
+    }
+
+    /// Starts recording a [`TraceStep`] for each opcode executed, including the ones of
+    /// nested calls. Only programs compiled in instrumented mode report the opcodes they
+    /// execute, see [`Executor::compile_instrumented`].
+    ///
+    /// [`Executor::compile_instrumented`]: crate::executor::Executor::compile_instrumented
+    pub fn enable_tracing(&mut self) {
+        self.tracer.get_or_insert_with(Tracer::new);
+    }
+
+    /// Sets the inspector notified of the execution, including the one of nested calls.
+    /// Only programs compiled in instrumented mode report the opcodes they execute,
+    /// see [`Executor::compile_instrumented`].
+    ///
+    /// [`Executor::compile_instrumented`]: crate::executor::Executor::compile_instrumented
+    pub fn set_inspector(&mut self, inspector: Box<dyn Inspector>) {
+        self.inspector = Some(inspector);
+    }
+
+    /// Sets the cache of compiled code used for running the transaction and the calls
+    /// it makes. By default, the code of every call is compiled anew.
+    ///
+    /// The cache is shared, so it can outlive the context and be used for other ones.
+    pub fn set_contract_cache(&mut self, cache: Rc<RefCell<ContractCache>>) {
+        self.contract_cache = Some(cache);
+    }
+
+    /// Returns whether the programs run with this context should report the opcodes
+    /// they execute, for tracing or inspecting them
+    pub(crate) fn is_instrumented(&self) -> bool {
+        self.tracer.is_some() || self.inspector.is_some()
+    }
+
+    /// Calls `hook` with the tracer and the inspector, if any, along with the context
+    fn inspect(&mut self, hook: impl Fn(&mut dyn Inspector, &Self)) {
+        // They're taken out while they're called, since they get the whole context
+        if let Some(mut tracer) = self.tracer.take() {
+            hook(&mut tracer, self);
+            self.tracer = Some(tracer);
+        }
+        if let Some(mut inspector) = self.inspector.take() {
+            hook(inspector.as_mut(), self);
+            self.inspector = Some(inspector);
+        }
+    }
+
+    /// Swaps the state shared by all the calls of a transaction with `other`.
+    /// This lends the state to the context of a subcall, and takes it back afterwards.
+    fn swap_state(&mut self, other: &mut Self) {
+        std::mem::swap(&mut self.storage, &mut other.storage);
+        std::mem::swap(&mut self.original_storage, &mut other.original_storage);
+        std::mem::swap(&mut self.refund_counter, &mut other.refund_counter);
+        std::mem::swap(&mut self.transient_storage, &mut other.transient_storage);
+        std::mem::swap(&mut self.block_hashes, &mut other.block_hashes);
+        std::mem::swap(&mut self.accounts, &mut other.accounts);
+        std::mem::swap(&mut self.host, &mut other.host);
+        std::mem::swap(&mut self.journal, &mut other.journal);
+        std::mem::swap(&mut self.created_accounts, &mut other.created_accounts);
+        std::mem::swap(&mut self.original_accounts, &mut other.original_accounts);
+        std::mem::swap(&mut self.destroyed_accounts, &mut other.destroyed_accounts);
+        std::mem::swap(&mut self.warm_addresses, &mut other.warm_addresses);
+        std::mem::swap(&mut self.warm_slots, &mut other.warm_slots);
+        std::mem::swap(&mut self.tracer, &mut other.tracer);
+        std::mem::swap(&mut self.inspector, &mut other.inspector);
+        std::mem::swap(&mut self.contract_cache, &mut other.contract_cache);
+    }
+
+    /// Marks the current state of the host, so that the changes made from now on can be
+    /// rolled back with [`Self::revert`]. Returns [`None`] if there's no journaled host.
+    fn checkpoint(&self) -> Option<Checkpoint> {
+        self.host.as_ref()?.borrow_mut().checkpoint()
+    }
+
+    /// Rolls back the changes made to the host since `checkpoint` was taken.
+    /// Returns whether they were rolled back, which isn't possible without a checkpoint,
+    /// in which case the caller has to undo what it can by itself.
+    fn revert(&self, checkpoint: Option<Checkpoint>) -> bool {
+        match (&self.host, checkpoint) {
+            (Some(host), Some(checkpoint)) => {
+                host.borrow_mut().revert(checkpoint);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Takes a snapshot of the state of the transaction at the start of a call frame, so
+    /// that everything the frame changes can be rolled back with [`Self::revert_to`]
+    fn snapshot(&self) -> FrameSnapshot {
+        FrameSnapshot {
+            checkpoint: self.checkpoint(),
+            journal_len: self.journal.len(),
+            original_storage: self.original_storage.clone(),
+            refund_counter: self.refund_counter,
+            created_accounts: self.created_accounts.clone(),
+            destroyed_accounts: self.destroyed_accounts.clone(),
+            warm_addresses: self.warm_addresses.clone(),
+            warm_slots: self.warm_slots.clone(),
+        }
+    }
+
+    /// Rolls back the state of the transaction to `snapshot`, undoing the changes made to
+    /// the accounts and storage if the host can't do it, and the ones made to the transient
+    /// storage
+    fn revert_to(&mut self, snapshot: FrameSnapshot) {
+        let entries = self.journal.split_off(snapshot.journal_len);
+        let reverted = self.revert(snapshot.checkpoint);
+        // The changes are undone from the latest one
+        for entry in entries.into_iter().rev() {
+            match entry {
+                // Accounts can't be removed, but an empty one is as good as missing
+                JournalEntry::AccountChanged { address, previous } if !reverted => self
+                    .accounts
+                    .set_account(address, previous.unwrap_or_default()),
+                JournalEntry::StorageChanged {
+                    address,
+                    key,
+                    previous,
+                } if !reverted => self.storage.write(address, key, previous),
+                // The host doesn't hold the transient storage
+                JournalEntry::TransientStorageChanged {
+                    address,
+                    key,
+                    previous,
+                } => self.transient_storage.write(address, key, previous),
+                _ => {}
+            }
+        }
+        self.original_storage = snapshot.original_storage;
+        self.refund_counter = snapshot.refund_counter;
+        self.created_accounts = snapshot.created_accounts;
+        self.destroyed_accounts = snapshot.destroyed_accounts;
+        self.warm_addresses = snapshot.warm_addresses;
+        self.warm_slots = snapshot.warm_slots;
+    }
+
+    /// Marks the account at `address` as accessed in the transaction.
+    /// Returns whether it was cold, which no account is before Berlin (EIP-2929)
+    fn access_address(&mut self, address: Address) -> bool {
+        self.env.spec_id.includes(SpecId::Berlin) && self.warm_addresses.insert(address)
+    }
+
+    /// Marks the slot at `key` in the storage of the account at `address` as accessed in the
+    /// transaction. Returns whether it was cold, which no slot is before Berlin (EIP-2929)
+    fn access_slot(&mut self, address: Address, key: U256) -> bool {
+        self.env.spec_id.includes(SpecId::Berlin) && self.warm_slots.insert((address, key))
+    }
+
+    /// Returns a copy of the memory segment `[offset, offset + size)`
+    fn memory_segment(&self, offset: u32, size: u32) -> Vec<u8> {
+        let offset = offset as usize;
+        let size = size as usize;
+        // An empty segment may point anywhere, even past the end of the memory
+        if size == 0 {
+            Vec::new()
+        } else {
+            self.memory[offset..offset + size].to_vec()
+        }
+    }
+
+    /// Sets the account at `address`, journaling the change and recording the account's
+    /// state before the first one
+    fn set_account(&mut self, address: Address, account: AccountInfo) {
+        let previous = self.accounts.account(&address);
+        self.original_accounts
+            .entry(address)
+            .or_insert_with(|| previous.clone());
+        self.journal
+            .push(JournalEntry::AccountChanged { address, previous });
+        self.accounts.set_account(address, account);
+    }
+
+    /// Moves `value` wei from the account at `from` to the account at `to`,
+    /// creating the latter if it doesn't exist.
+    /// Returns whether the transfer succeeded, which fails if `from` can't afford it.
+    fn transfer(&mut self, from: Address, to: Address, value: U256) -> bool {
+        let mut sender = self.accounts.account(&from).unwrap_or_default();
+        let Some(sender_balance) = sender.balance.checked_sub(&value) else {
+            return false;
+        };
+        // Transferring nothing, or to the sender itself, doesn't change any balance
+        if value == U256::default() || from == to {
+            return true;
+        }
+        let mut recipient = self.accounts.account(&to).unwrap_or_default();
+        let Some(recipient_balance) = recipient.balance.checked_add(&value) else {
+            return false;
+        };
+        sender.balance = sender_balance;
+        recipient.balance = recipient_balance;
+        self.set_account(from, sender);
+        self.set_account(to, recipient);
+        true
+    }
+
+    /// Executes the code of the account at `code_address` in a new call frame.
+    /// The context of the frame, and whether `value` wei are transferred, depend on `call_type`.
+    /// Returns whether the call succeeded, along with its output.
+    fn execute_call(
+        &mut self,
+        call_type: CallType,
+        gas_limit: u64,
+        code_address: Address,
+        value: U256,
+        calldata: Vec<u8>,
+    ) -> (bool, u64, Vec<u8>) {
+        if self.inspector.is_none() {
+            return self.execute_call_frame(call_type, gas_limit, code_address, value, calldata);
+        }
+        let inputs = CallInputs {
+            kind: CallKind::Call(call_type),
+            caller: self.env.call.address,
+            address: code_address,
+            value,
+            input: calldata.clone(),
+            gas_limit,
+            depth: self.depth + 2,
+        };
+        self.inspect_frame(inputs, |context| {
+            context.execute_call_frame(call_type, gas_limit, code_address, value, calldata)
+        })
+    }
+
+    /// Deploys a contract at `address`, running `init_code` in a new call frame with at most
+    /// `gas_limit` gas, and transferring `value` wei to the new contract.
+    /// The output of the initcode becomes the contract's code.
+    /// Returns whether the creation succeeded, along with the gas left unused and the output
+    /// of the initcode in case it reverted.
+    fn execute_create(
+        &mut self,
+        init_code: Vec<u8>,
+        address: Address,
+        value: U256,
+        gas_limit: u64,
+    ) -> (bool, u64, Vec<u8>) {
+        if self.inspector.is_none() {
+            return self.execute_create_frame(init_code, address, value, gas_limit);
+        }
+        let inputs = CallInputs {
+            kind: CallKind::Create,
+            caller: self.env.call.address,
+            address,
+            value,
+            input: init_code.clone(),
+            gas_limit,
+            depth: self.depth + 2,
+        };
+        self.inspect_frame(inputs, |context| {
+            context.execute_create_frame(init_code, address, value, gas_limit)
+        })
+    }
+
+    /// Runs `frame`, reporting it to the inspector as a nested frame made with `inputs`
+    fn inspect_frame(
+        &mut self,
+        inputs: CallInputs,
+        frame: impl FnOnce(&mut Self) -> (bool, u64, Vec<u8>),
+    ) -> (bool, u64, Vec<u8>) {
+        self.inspect(|inspector, context| inspector.call(&inputs, context));
+        let (success, gas_left, output) = frame(self);
+        let outcome = CallOutcome {
+            success,
+            gas_left,
+            output,
+        };
+        self.inspect(|inspector, context| inspector.call_end(&inputs, &outcome, context));
+        (outcome.success, outcome.gas_left, outcome.output)
+    }
+
+    /// The frame of [`Self::execute_call`]
+    fn execute_call_frame(
+        &mut self,
+        call_type: CallType,
+        gas_limit: u64,
+        code_address: Address,
+        value: U256,
+        calldata: Vec<u8>,
+    ) -> (bool, u64, Vec<u8>) {
+        let caller = self.env.call.address;
+        let (call_env, transferred_value) = match call_type {
+            CallType::Call | CallType::StaticCall => {
+                let call_env = CallEnv {
+                    address: code_address,
+                    caller,
+                    value,
+                };
+                (call_env, value)
+            }
+            // The value is "transferred" to the caller itself, which only checks its balance
+            CallType::CallCode => {
+                let call_env = CallEnv {
+                    address: caller,
+                    caller,
+                    value,
+                };
+                (call_env, value)
+            }
+            // The sender and value of the current call are kept, and nothing is transferred
+            CallType::DelegateCall => (self.env.call.clone(), U256::default()),
+        };
+        let callee = call_env.address;
+
+        let snapshot = self.snapshot();
+        if self.depth >= MAX_CALL_DEPTH || !self.transfer(caller, callee, transferred_value) {
+            return (false, gas_limit, Vec::new());
+        }
+
+        if let Some(precompile) = Precompile::at(&code_address, self.env.spec_id) {
+            let (success, gas_left, output) =
+                precompile.run(&calldata, gas_limit, self.env.spec_id);
+            if !success {
+                self.revert_to(snapshot);
+            }
+            return (success, gas_left, output);
+        }
+        let code = self
+            .accounts
+            .account(&code_address)
+            .map(|account| account.code)
+            .unwrap_or_default();
+        // Calling an account without code succeeds without executing anything
+        if code.is_empty() {
+            return (true, gas_limit, Vec::new());
+        }
+
+        let mut env = self.env.clone();
+        env.call = call_env;
+        let mut callee_ctx = Self::new(env, calldata, Box::<InMemoryStorage>::default());
+        callee_ctx.depth = self.depth + 1;
+        callee_ctx.is_static = self.is_static || call_type == CallType::StaticCall;
+
+        self.swap_state(&mut callee_ctx);
+        let exit_status = run_code(&code, &mut callee_ctx, gas_limit);
+        self.swap_state(&mut callee_ctx);
+
+        match exit_status {
+            Some(ExitStatusCode::Return | ExitStatusCode::Stop) => {
+                self.logs.append(&mut callee_ctx.logs);
+                let output = callee_ctx.return_values().to_vec();
+                (true, callee_ctx.remaining_gas, output)
+            }
+            exit_status => {
+                self.revert_to(snapshot);
+                // Only a REVERT keeps its output and the gas it left unused
+                if exit_status == Some(ExitStatusCode::Revert) {
+                    let output = callee_ctx.return_values().to_vec();
+                    (false, callee_ctx.remaining_gas, output)
+                } else {
+                    (false, 0, Vec::new())
+                }
+            }
+        }
+    }
+
+    /// The frame of [`Self::execute_create`]
+    fn execute_create_frame(
+        &mut self,
+        init_code: Vec<u8>,
+        address: Address,
+        value: U256,
+        gas_limit: u64,
+    ) -> (bool, u64, Vec<u8>) {
+        let creator = self.env.call.address;
+        let mut creator_account = self.accounts.account(&creator).unwrap_or_default();
+        let can_afford = creator_account.balance.checked_sub(&value).is_some();
+
+        // Failing before running the initcode leaves the gas unused
+        if self.depth >= MAX_CALL_DEPTH || !can_afford || creator_account.nonce == u64::MAX {
+            return (false, gas_limit, Vec::new());
+        }
+        creator_account.nonce += 1;
+        self.set_account(creator, creator_account);
+        // The new contract's address stays warm even if the creation fails (EIP-2929)
+        self.access_address(address);
+
+        // The creator's nonce stays incremented even if the creation fails
+        let snapshot = self.snapshot();
+        // Creating a contract where there's already one fails, consuming all the gas
+        let account = self.accounts.account(&address).unwrap_or_default();
+        if !account.code.is_empty() || account.nonce != 0 {
+            return (false, 0, Vec::new());
+        }
+        // Contracts start with a nonce of one since Spurious Dragon (EIP-161)
+        let nonce = if self.env.spec_id.includes(SpecId::SpuriousDragon) {
+            1
+        } else {
+            0
+        };
+        let account = AccountInfo { nonce, ..account };
+        self.set_account(address, account);
+        self.created_accounts.insert(address);
+        self.transfer(creator, address, value);
+
+        let mut env = self.env.clone();
+        env.call = CallEnv {
+            address,
+            caller: creator,
+            value,
+        };
+        let mut init_ctx = Self::new(env, Vec::new(), Box::<InMemoryStorage>::default());
+        init_ctx.depth = self.depth + 1;
+
+        self.swap_state(&mut init_ctx);
+        let exit_status = run_code(&init_code, &mut init_ctx, gas_limit);
+        self.swap_state(&mut init_ctx);
+
+        let remaining_gas = init_ctx.remaining_gas;
+        let output = init_ctx.return_values().to_vec();
+        let initialized = matches!(
+            exit_status,
+            Some(ExitStatusCode::Return | ExitStatusCode::Stop)
+        );
+        let deposit_cost =
+            code_deposit_cost(&output, self.env.spec_id).filter(|cost| *cost <= remaining_gas);
+
+        if let (true, Some(deposit_cost)) = (initialized, deposit_cost) {
+            let mut account = self.accounts.account(&address).unwrap_or_default();
+            account.code = output;
+            self.set_account(address, account);
+            self.logs.append(&mut init_ctx.logs);
+            return (true, remaining_gas - deposit_cost, Vec::new());
+        }
+
+        self.revert_to(snapshot);
+        if exit_status == Some(ExitStatusCode::Revert) {
+            (false, remaining_gas, output)
+        } else {
+            (false, 0, Vec::new())
+        }
+    }
+}
+
+/// Transaction execution
+impl SyscallContext {
+    /// Executes the transaction described by the environment's [`TxEnv`](crate::env::TxEnv)
+    /// on the state held by the context, which acts as the outermost call frame.
+    ///
+    /// The sender pays for the whole gas limit upfront, at the effective gas price, and the
+    /// intrinsic gas is charged before running any code. At the end, the sender is paid back
+    /// for the gas left unused and the refund, while the block's coinbase gets the priority
+    /// fee for the gas used, and the base fee is burnt (EIP-1559). A transaction that can't
+    /// pay for itself, at its max fee, is rejected without changing anything.
+    pub fn transact(&mut self) -> Result<TransactionResult, TransactionError> {
+        let tx = self.env.tx.clone();
+        let origin = tx.origin;
+
+        let spec_id = self.env.spec_id;
+        if !tx.access_list.is_empty() && !spec_id.includes(SpecId::Berlin) {
+            return Err(TransactionError::AccessListNotSupported);
+        }
+        if tx.to.is_none()
+            && spec_id.includes(SpecId::Shanghai)
+            && tx.data.len() > MAX_INITCODE_SIZE
+        {
+            return Err(TransactionError::InitcodeTooLarge {
+                size: tx.data.len(),
+            });
+        }
+        let intrinsic_gas = tx.intrinsic_gas(spec_id);
+        if intrinsic_gas > tx.gas_limit {
+            return Err(TransactionError::IntrinsicGasTooLow {
+                gas_limit: tx.gas_limit,
+                intrinsic_gas,
+            });
+        }
+        if let Some(max_fee) = tx.max_fee_per_gas {
+            if !spec_id.includes(SpecId::London) {
+                return Err(TransactionError::FeeMarketNotSupported);
+            }
+            if tx.max_priority_fee_per_gas > max_fee {
+                return Err(TransactionError::PriorityFeeGreaterThanMaxFee);
+            }
+        }
+        let basefee = self.env.block.basefee;
+        let max_gas_price = tx.max_fee_per_gas.unwrap_or(tx.gas_price);
+        if max_gas_price < basefee {
+            return Err(TransactionError::GasPriceLowerThanBasefee);
+        }
+        let gas_price = tx.effective_gas_price(&basefee);
+        // The effective gas price is between the base fee and the max gas price
+        let priority_fee = gas_price.checked_sub(&basefee).unwrap_or_default();
+
+        let mut sender = self.accounts.account(&origin).unwrap_or_default();
+        let gas_limit = U256::from(tx.gas_limit as u128);
+        let max_cost = max_gas_price
+            .checked_mul(&gas_limit)
+            .and_then(|fee| fee.checked_add(&tx.value));
+        if max_cost
+            .and_then(|cost| sender.balance.checked_sub(&cost))
+            .is_none()
+        {
+            return Err(TransactionError::InsufficientFunds);
+        }
+        if sender.nonce == u64::MAX {
+            return Err(TransactionError::NonceOverflow);
+        }
+        let nonce = sender.nonce;
+        // Bounded by the max cost, which the sender can afford
+        let fee = gas_price.checked_mul(&gas_limit).unwrap_or_default();
+        sender.balance = sender.balance.checked_sub(&fee).unwrap_or_default();
+        sender.nonce += 1;
+        self.set_account(origin, sender);
+
+        let target = tx.to.unwrap_or_else(|| create_address(&origin, nonce));
+        self.warm_up(target);
+        // Only the changes of this transaction can be rolled back
+        self.journal.clear();
+        // Only the events of this transaction go in its result
+        self.logs.clear();
+
+        let gas_limit = tx.gas_limit - intrinsic_gas;
+        let (success, remaining_gas, output, created_address) = match tx.to {
+            Some(to) => {
+                let (success, remaining_gas, output) =
+                    self.transact_call(to, tx.value, tx.data, gas_limit);
+                (success, remaining_gas, output, None)
+            }
+            None => {
+                let (success, remaining_gas) =
+                    self.transact_create(target, tx.value, tx.data, gas_limit);
+                let output = self.return_values().to_vec();
+                (success, remaining_gas, output, success.then_some(target))
+            }
+        };
+
+        // The logs and refund are discarded along with the rest of the changes
+        // of a failed transaction
+        if !success {
+            self.logs.clear();
+        }
+        let gas_used = tx.gas_limit - remaining_gas;
+        let gas_refunded = if success {
+            self.gas_refund(gas_used)
+        } else {
+            0
+        };
+        let gas_used = gas_used - gas_refunded;
+
+        let unused_fee = gas_price.checked_mul(&U256::from((tx.gas_limit - gas_used) as u128));
+        let coinbase_fee = priority_fee.checked_mul(&U256::from(gas_used as u128));
+        // Both are bounded by the fee the sender paid upfront
+        self.add_balance(origin, unused_fee.unwrap_or_default());
+        self.add_balance(self.env.block.coinbase, coinbase_fee.unwrap_or_default());
+        self.delete_destroyed_accounts();
+
+        Ok(TransactionResult {
+            success,
+            gas_used,
+            gas_refunded,
+            output,
+            created_address,
+            logs: self.logs.clone(),
+            state_diff: self.state_diff(),
+        })
+    }
+
+    /// Deletes the accounts self-destructed in the transaction, along with their code and
+    /// storage. A failed transaction leaves none of them (EIP-6780)
+    fn delete_destroyed_accounts(&mut self) {
+        for address in self.destroyed_accounts.clone() {
+            self.set_account(address, AccountInfo::default());
+            self.storage.clear(&address);
+        }
+    }
+
+    /// Marks the accounts and storage slots accessed from the start of the transaction as
+    /// warm, since Berlin: its sender and `target`, the precompiles, the block's coinbase
+    /// since Shanghai (EIP-3651), and the ones in its access list (EIP-2930)
+    fn warm_up(&mut self, target: Address) {
+        self.warm_addresses.clear();
+        self.warm_slots.clear();
+        let spec_id = self.env.spec_id;
+        if !spec_id.includes(SpecId::Berlin) {
+            return;
+        }
+        self.warm_addresses.extend([self.env.tx.origin, target]);
+        self.warm_addresses.extend(
+            Precompile::ALL
+                .iter()
+                .filter(|precompile| spec_id.includes(precompile.introduced_in()))
+                .map(|precompile| precompile.address()),
+        );
+        if spec_id.includes(SpecId::Shanghai) {
+            self.warm_addresses.insert(self.env.block.coinbase);
+        }
+        for (address, keys) in &self.env.tx.access_list {
+            self.warm_addresses.insert(*address);
+            self.warm_slots
+                .extend(keys.iter().map(|key| (*address, *key)));
+        }
+    }
+
+    /// Runs the code of the account at `to` as the transaction's call, transferring it
+    /// `value` wei, or the precompile at that address. Returns whether the call succeeded,
+    /// along with the gas left unused and the output
+    fn transact_call(
+        &mut self,
+        to: Address,
+        value: U256,
+        data: Vec<u8>,
+        gas_limit: u64,
+    ) -> (bool, u64, Vec<u8>) {
+        let origin = self.env.tx.origin;
+        self.env.call = CallEnv {
+            address: to,
+            caller: origin,
+            value,
+        };
+        self.calldata = data;
+        let snapshot = self.snapshot();
+        // The sender was checked to afford the value
+        self.transfer(origin, to, value);
+
+        if let Some(precompile) = Precompile::at(&to, self.env.spec_id) {
+            let (success, gas_left, output) =
+                precompile.run(&self.calldata, gas_limit, self.env.spec_id);
+            if !success {
+                self.revert_to(snapshot);
+            }
+            return (success, gas_left, output);
+        }
+        let code = self
+            .accounts
+            .account(&to)
+            .map(|account| account.code)
+            .unwrap_or_default();
+        // Calling an account without code succeeds without executing anything
+        if code.is_empty() {
+            return (true, gas_limit, Vec::new());
+        }
+
+        let exit_status = run_code(&code, self, gas_limit);
+        let output = self.return_values().to_vec();
+        match exit_status {
+            Some(ExitStatusCode::Return | ExitStatusCode::Stop) => {
+                (true, self.remaining_gas, output)
+            }
+            exit_status => {
+                self.revert_to(snapshot);
+                if exit_status == Some(ExitStatusCode::Revert) {
+                    (false, self.remaining_gas, output)
+                } else {
+                    (false, 0, output)
+                }
+            }
+        }
+    }
+
+    /// Deploys a contract from the account of the current call, as CREATE does: the
+    /// initcode is compiled and run with at most `gas_limit` gas, `value` wei go to the
+    /// new contract, and the code the initcode returns is stored as the contract's, paying
+    /// for each of its bytes. The address follows from the deployer's nonce.
+    ///
+    /// Unlike [`Self::transact`], there's no intrinsic gas, and the deployer doesn't pay
+    /// for the gas used. The deployment fails like a CREATE would, using all the gas
+    /// unless the initcode reverts.
+    pub fn deploy(&mut self, init_code: Vec<u8>, value: U256, gas_limit: u64) -> DeploymentResult {
+        let deployer = self.env.call.address;
+        // The initcode can't be bigger than the limit (EIP-3860)
+        if self.env.spec_id.includes(SpecId::Shanghai) && init_code.len() > MAX_INITCODE_SIZE {
+            return DeploymentResult {
+                gas_used: gas_limit,
+                ..Default::default()
+            };
+        }
+        let nonce = self
+            .accounts
+            .account(&deployer)
+            .map(|account| account.nonce)
+            .unwrap_or_default();
+        let address = create_address(&deployer, nonce);
+
+        let (success, gas_left, output) = self.execute_create(init_code, address, value, gas_limit);
+
+        DeploymentResult {
+            address: success.then_some(address),
+            gas_used: gas_limit - gas_left,
+            output,
+        }
+    }
+
+    /// Deploys a contract at `address` as the transaction's creation, running `init_code`
+    /// and transferring `value` wei to the new contract.
+    /// Returns whether the creation succeeded, along with the gas left unused
+    fn transact_create(
+        &mut self,
+        address: Address,
+        value: U256,
+        init_code: Vec<u8>,
+        gas_limit: u64,
+    ) -> (bool, u64) {
+        let origin = self.env.tx.origin;
+        let snapshot = self.snapshot();
+
+        // Creating a contract where there's already one fails, consuming all the gas
+        let account = self.accounts.account(&address).unwrap_or_default();
+        if !account.code.is_empty() || account.nonce != 0 {
+            return (false, 0);
+        }
+        // Contracts start with a nonce of one since Spurious Dragon (EIP-161)
+        let nonce = if self.env.spec_id.includes(SpecId::SpuriousDragon) {
+            1
+        } else {
+            0
+        };
+        let account = AccountInfo { nonce, ..account };
+        self.set_account(address, account);
+        self.created_accounts.insert(address);
+        self.transfer(origin, address, value);
+
+        self.env.call = CallEnv {
+            address,
+            caller: origin,
+            value,
+        };
+        self.calldata = Vec::new();
+        let exit_status = run_code(&init_code, self, gas_limit);
+
+        let remaining_gas = self.remaining_gas;
+        let output = self.return_values().to_vec();
+        let initialized = matches!(
+            exit_status,
+            Some(ExitStatusCode::Return | ExitStatusCode::Stop)
+        );
+        let deposit_cost =
+            code_deposit_cost(&output, self.env.spec_id).filter(|cost| *cost <= remaining_gas);
+
+        if let (true, Some(deposit_cost)) = (initialized, deposit_cost) {
+            let mut account = self.accounts.account(&address).unwrap_or_default();
+            account.code = output;
+            self.set_account(address, account);
+            return (true, remaining_gas - deposit_cost);
+        }
+
+        self.revert_to(snapshot);
+        if exit_status == Some(ExitStatusCode::Revert) {
+            (false, remaining_gas)
+        } else {
+            (false, 0)
+        }
+    }
+
+    /// Adds `value` wei to the balance of the account at `address`, creating it if it
+    /// doesn't exist
+    fn add_balance(&mut self, address: Address, value: U256) {
+        if value == U256::default() {
+            return;
+        }
+        let mut account = self.accounts.account(&address).unwrap_or_default();
+        account.balance = account
+            .balance
+            .checked_add(&value)
+            .expect("balances are bounded by the total supply");
+        self.set_account(address, account);
+    }
 }
 
 /// Accessors for disponibilizing the execution results
 impl SyscallContext {
     pub fn return_values(&self) -> &[u8] {
-        // TODO: maybe initialize as (0, 0) instead of None
-        let (offset, size) = self.result.unwrap_or((0, 0));
-        &self.memory[offset..offset + size]
+        match self.result {
+            // An empty result may point anywhere, even past the end of the memory
+            Some((offset, size)) if size > 0 => &self.memory[offset..offset + size],
+            _ => &[],
+        }
+    }
+
+    /// Returns whether the account at `address` was accessed in the current transaction,
+    /// which makes accessing it again cheaper since Berlin (EIP-2929). The ones accessed
+    /// from the start of the transaction are warm once it starts, see [`Self::transact`]
+    pub fn is_warm_address(&self, address: &Address) -> bool {
+        self.warm_addresses.contains(address)
+    }
+
+    /// Returns whether the storage slot at `key` of the account at `address` was accessed
+    /// in the current transaction, like [`Self::is_warm_address`]
+    pub fn is_warm_slot(&self, address: &Address, key: &U256) -> bool {
+        self.warm_slots.contains(&(*address, *key))
+    }
+
+    /// Returns the gas left when the program halted, which is zero after an exceptional halt
+    pub fn remaining_gas(&self) -> u64 {
+        self.remaining_gas
+    }
+
+    /// Returns whether the program halted with a REVERT
+    pub fn is_revert(&self) -> bool {
+        self.exit_status == Some(ExitStatusCode::Revert)
+    }
+
+    /// Returns the persistent storage, as left by the program
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    /// Returns the gas refunded at the end of the transaction, given the gas it used.
+    /// The refund is capped at a fifth of the gas used (EIP-3529), or half of it before London
+    pub fn gas_refund(&self, gas_used: u64) -> u64 {
+        let refund = self.refund_counter.max(0) as u64;
+        let max_refund_quotient = if self.env.spec_id.includes(SpecId::London) {
+            gas_cost::MAX_REFUND_QUOTIENT
+        } else {
+            gas_cost::MAX_REFUND_QUOTIENT_PRE_LONDON
+        };
+        refund.min(gas_used / max_refund_quotient as u64)
+    }
+
+    /// Returns the transient storage, as left by the program
+    pub fn transient_storage(&self) -> &dyn Storage {
+        &self.transient_storage
+    }
+
+    /// Returns the events emitted by the program, in emission order
+    pub fn logs(&self) -> &[Log] {
+        &self.logs
+    }
+
+    /// Returns the state of the accounts, as left by the program
+    pub fn accounts(&self) -> &dyn AccountProvider {
+        self.accounts.as_ref()
+    }
+
+    /// Returns the changes made to the state by the program, or by the whole transaction
+    /// when run with [`Self::transact`]. The ones rolled back by a failed call aren't
+    /// included
+    pub fn state_diff(&self) -> StateDiff {
+        let mut diff = StateDiff::default();
+        for (address, before) in &self.original_accounts {
+            let before = before.clone().unwrap_or_default();
+            let after = self.accounts.account(address).unwrap_or_default();
+            diff.record_account(*address, &before, &after);
+            // A failed creation leaves the account empty, while a failed call keeps the
+            // account it destroyed
+            let created = self.created_accounts.contains(address) && !after.is_empty();
+            let destroyed =
+                self.destroyed_accounts.contains(address) && !before.is_empty() && after.is_empty();
+            diff.record_lifecycle(*address, created, destroyed);
+        }
+        for ((address, key), before) in &self.original_storage {
+            let after = self.storage.read(address, key);
+            diff.record_storage(*address, *key, *before, after);
+        }
+        diff
+    }
+
+    /// Returns the steps executed, which is empty unless tracing was enabled.
+    /// See [`Self::enable_tracing`]
+    pub fn trace(&self) -> &[TraceStep] {
+        self.tracer.as_ref().map(Tracer::steps).unwrap_or_default()
+    }
+
+    /// Returns the memory of the call, which is only meaningful while it runs
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Sets the stack mapped for the call, returned to the program by [`Self::get_stack`]
+    pub(crate) fn set_stack(&mut self, stack: *mut U256) {
+        self.stack = stack;
+    }
+
+    /// Takes the memory of the call from the pool, if it wasn't used yet. It goes back to
+    /// the pool along with the context
+    pub(crate) fn use_memory_pool(&mut self, pool: &MemoryPool) {
+        if !self.memory.is_pooled() && self.memory.is_empty() {
+            self.memory = pool.take();
+        }
+    }
+
+    /// Returns the environment the program is executed in
+    pub fn env(&self) -> &Env {
+        &self.env
+    }
+}
+
+/// Syscall implementations
+///
+/// Note that each function is marked as `extern "C"`, which is necessary for the
+/// function to be callable from the generated code.
+impl SyscallContext {
+    #[export_name = "emv_mlir__write_result"]
+    pub extern "C" fn write_result(
+        &mut self,
+        offset: u32,
+        bytes_len: u32,
+        remaining_gas: u64,
+        execution_result: u8,
+    ) {
+        self.result = Some((offset as usize, bytes_len as usize));
+        self.remaining_gas = remaining_gas;
+        self.exit_status = Some(ExitStatusCode::from_u8(execution_result));
+    }
+
+    /// Reports the step about to be executed to the tracer and inspector, if any,
+    /// finishing the previous one.
+    /// The stack is given by its bottom and top, which points past its last element.
+    ///
+    /// # Safety
+    ///
+    /// `stack_base` and `stack_top` must delimit a valid array of [`U256`].
+    #[export_name = "emv_mlir__trace_step"]
+    pub unsafe extern "C" fn trace_step(
+        &mut self,
+        pc: u64,
+        opcode: u8,
+        gas: u64,
+        stack_base: *const U256,
+        stack_top: *const U256,
+    ) {
+        if !self.is_instrumented() {
+            return;
+        }
+        self.finish_step(gas);
+        let step = self.read_step(pc, opcode, gas, stack_base, stack_top);
+        self.inspect(|inspector, context| inspector.step(&step, context));
+        self.current_step = Some((step, self.logs.len()));
+    }
+
+    /// Reports a breakpoint hit right before executing the opcode at `pc` to the
+    /// inspector, if any.
+    /// The stack is given by its bottom and top, which points past its last element.
+    ///
+    /// # Safety
+    ///
+    /// `stack_base` and `stack_top` must delimit a valid array of [`U256`].
+    #[export_name = "emv_mlir__breakpoint"]
+    pub unsafe extern "C" fn breakpoint(
+        &mut self,
+        pc: u64,
+        opcode: u8,
+        gas: u64,
+        stack_base: *const U256,
+        stack_top: *const U256,
+    ) {
+        if !self.is_instrumented() {
+            return;
+        }
+        let step = self.read_step(pc, opcode, gas, stack_base, stack_top);
+        self.inspect(|inspector, context| inspector.breakpoint(&step, context));
+    }
+
+    /// Returns the state before executing the opcode at `pc`.
+    ///
+    /// # Safety
+    ///
+    /// `stack_base` and `stack_top` must delimit a valid array of [`U256`].
+    unsafe fn read_step(
+        &self,
+        pc: u64,
+        opcode: u8,
+        gas: u64,
+        stack_base: *const U256,
+        stack_top: *const U256,
+    ) -> TraceStep {
+        let size = stack_top.offset_from(stack_base) as usize;
+        let stack = std::slice::from_raw_parts(stack_base, size).to_vec();
+        TraceStep {
+            pc,
+            op: opcode,
+            gas,
+            // Until the next step starts, the step is assumed to consume all the gas
+            gas_cost: gas,
+            mem_size: self.memory.len() as u64,
+            stack,
+            depth: self.depth + 1,
+            refund: self.refund_counter,
+        }
+    }
+
+    /// Finishes the last step executed in this call, if any, given the gas left after
+    /// executing it. The events it emitted are reported to the inspector.
+    pub(crate) fn finish_step(&mut self, remaining_gas: u64) {
+        let Some((mut step, logs_before)) = self.current_step.take() else {
+            return;
+        };
+        step.gas_cost = step.gas.saturating_sub(remaining_gas);
+        self.inspect(|inspector, context| {
+            inspector.step_end(&step, context);
+            for log in &context.logs[logs_before..] {
+                inspector.log(log, context);
+            }
+        });
+    }
+
+    #[export_name = "emv_mlir__extend_memory"]
+    pub extern "C" fn extend_memory(&mut self, new_size: u32) -> *mut u8 {
+        match self.memory.expand(new_size as usize) {
+            Ok(()) => self.memory.as_mut_ptr(),
+            // TODO: use tracing here
+            Err(err) => {
+                eprintln!("Failed to reserve memory: {err}");
+                std::ptr::null_mut()
+            }
+        }
+    }
+
+    /// Returns the bottom of the stack of a program compiled with a guard page after it,
+    /// mapped by [`run_guarded`](crate::stack_guard::run_guarded)
+    #[export_name = "emv_mlir__get_stack"]
+    pub extern "C" fn get_stack(&mut self) -> *mut U256 {
+        self.stack
+    }
+
+    #[export_name = "emv_mlir__keccak256_hasher"]
+    pub extern "C" fn keccak256_hasher(&mut self, offset: u32, size: u32, hash_ptr: &mut U256) {
+        let offset = offset as usize;
+        let size = size as usize;
+        let data = &self.memory[offset..offset + size];
+        let hash = Keccak256::digest(data);
+        *hash_ptr = U256::from_be_bytes(hash.into());
+    }
+
+    #[export_name = "emv_mlir__get_address"]
+    pub extern "C" fn get_address(&mut self, value_ptr: &mut U256) {
+        *value_ptr = U256::from(self.env.call.address);
+    }
+
+    #[export_name = "emv_mlir__get_origin"]
+    pub extern "C" fn get_origin(&mut self, value_ptr: &mut U256) {
+        *value_ptr = U256::from(self.env.tx.origin);
+    }
+
+    #[export_name = "emv_mlir__get_caller"]
+    pub extern "C" fn get_caller(&mut self, value_ptr: &mut U256) {
+        *value_ptr = U256::from(self.env.call.caller);
+    }
+
+    #[export_name = "emv_mlir__get_callvalue"]
+    pub extern "C" fn get_callvalue(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.call.value;
+    }
+
+    #[export_name = "emv_mlir__get_gasprice"]
+    pub extern "C" fn get_gasprice(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.tx.effective_gas_price(&self.env.block.basefee);
+    }
+
+    #[export_name = "emv_mlir__get_coinbase"]
+    pub extern "C" fn get_coinbase(&mut self, value_ptr: &mut U256) {
+        *value_ptr = U256::from(self.env.block.coinbase);
+    }
+
+    #[export_name = "emv_mlir__get_timestamp"]
+    pub extern "C" fn get_timestamp(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.block.timestamp;
+    }
+
+    #[export_name = "emv_mlir__get_number"]
+    pub extern "C" fn get_number(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.block.number;
+    }
+
+    #[export_name = "emv_mlir__get_prevrandao"]
+    pub extern "C" fn get_prevrandao(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.block.prevrandao;
+    }
+
+    #[export_name = "emv_mlir__get_gaslimit"]
+    pub extern "C" fn get_gaslimit(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.block.gas_limit;
+    }
+
+    #[export_name = "emv_mlir__get_chainid"]
+    pub extern "C" fn get_chainid(&mut self, value_ptr: &mut U256) {
+        *value_ptr = U256::from(self.env.chain_id as u128);
+    }
+
+    #[export_name = "emv_mlir__get_basefee"]
+    pub extern "C" fn get_basefee(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.block.basefee;
+    }
+
+    #[export_name = "emv_mlir__get_blobbasefee"]
+    pub extern "C" fn get_blobbasefee(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self.env.block.blob_basefee;
+    }
+
+    #[export_name = "emv_mlir__get_self_balance"]
+    pub extern "C" fn get_self_balance(&mut self, value_ptr: &mut U256) {
+        *value_ptr = self
+            .accounts
+            .account(&self.env.call.address)
+            .map(|account| account.balance)
+            .unwrap_or_default();
+    }
+
+    #[export_name = "emv_mlir__get_blob_hash"]
+    pub extern "C" fn get_blob_hash(&mut self, index: &U256, hash_ptr: &mut U256) {
+        // Indexes past the last blob return zero
+        let blob_hashes = &self.env.tx.blob_hashes;
+        *hash_ptr = if index.hi == 0 && index.lo < blob_hashes.len() as u128 {
+            blob_hashes[index.lo as usize]
+        } else {
+            U256::default()
+        };
+    }
+
+    #[export_name = "emv_mlir__get_block_hash"]
+    pub extern "C" fn get_block_hash(&mut self, number: &U256, hash_ptr: &mut U256) {
+        *hash_ptr = self.block_hashes.block_hash(number);
+    }
+
+    #[export_name = "emv_mlir__calldata_load"]
+    pub extern "C" fn calldata_load(&mut self, offset: u32, value_ptr: &mut U256) {
+        // Bytes past the end of the calldata are read as zeros
+        let offset = (offset as usize).min(self.calldata.len());
+        let end = (offset + 32).min(self.calldata.len());
+        let mut bytes = [0; 32];
+        bytes[..end - offset].copy_from_slice(&self.calldata[offset..end]);
+        *value_ptr = U256::from_be_bytes(bytes);
+    }
+
+    #[export_name = "emv_mlir__get_calldata_size"]
+    pub extern "C" fn get_calldata_size(&mut self) -> u32 {
+        self.calldata.len() as u32
+    }
+
+    #[export_name = "emv_mlir__copy_calldata_to_memory"]
+    pub extern "C" fn copy_calldata_to_memory(
+        &mut self,
+        calldata_offset: u32,
+        size: u32,
+        dest_offset: u32,
+    ) {
+        let size = size as usize;
+        let dest_offset = dest_offset as usize;
+        let dest = &mut self.memory[dest_offset..dest_offset + size];
+        copy_zero_padded(&self.calldata, calldata_offset as usize, dest);
+    }
+
+    #[export_name = "emv_mlir__get_code_size"]
+    pub extern "C" fn get_code_size(&mut self) -> u32 {
+        self.code.len() as u32
+    }
+
+    #[export_name = "emv_mlir__copy_code_to_memory"]
+    pub extern "C" fn copy_code_to_memory(
+        &mut self,
+        code_offset: u32,
+        size: u32,
+        dest_offset: u32,
+    ) {
+        let size = size as usize;
+        let dest_offset = dest_offset as usize;
+        let dest = &mut self.memory[dest_offset..dest_offset + size];
+        copy_zero_padded(&self.code, code_offset as usize, dest);
+    }
+
+    #[export_name = "emv_mlir__get_return_data_size"]
+    pub extern "C" fn get_return_data_size(&mut self) -> u32 {
+        self.return_data.len() as u32
+    }
+
+    #[export_name = "emv_mlir__copy_return_data_to_memory"]
+    pub extern "C" fn copy_return_data_to_memory(
+        &mut self,
+        return_data_offset: u32,
+        size: u32,
+        dest_offset: u32,
+    ) {
+        // The generated code checks the range is inside the return data
+        let return_data_offset = return_data_offset as usize;
+        let size = size as usize;
+        let dest_offset = dest_offset as usize;
+        self.memory[dest_offset..dest_offset + size]
+            .copy_from_slice(&self.return_data[return_data_offset..return_data_offset + size]);
+    }
+
+    #[export_name = "emv_mlir__get_extcode_size"]
+    pub extern "C" fn get_extcode_size(&mut self, address: &U256) -> u32 {
+        self.accounts
+            .account(&address.to_address())
+            .map(|account| account.code.len() as u32)
+            .unwrap_or(0)
+    }
+
+    #[export_name = "emv_mlir__get_balance"]
+    pub extern "C" fn get_balance(&mut self, address: &U256, balance_ptr: &mut U256) {
+        *balance_ptr = self
+            .accounts
+            .account(&address.to_address())
+            .map(|account| account.balance)
+            .unwrap_or_default();
+    }
+
+    #[export_name = "emv_mlir__get_extcode_hash"]
+    pub extern "C" fn get_extcode_hash(&mut self, address: &U256, hash_ptr: &mut U256) {
+        // Non-existent and empty accounts have a zero hash (EIP-1052)
+        *hash_ptr = match self.accounts.account(&address.to_address()) {
+            Some(account) if !account.is_empty() => account.code_hash(),
+            _ => U256::default(),
+        };
+    }
+
+    #[export_name = "emv_mlir__copy_extcode_to_memory"]
+    pub extern "C" fn copy_extcode_to_memory(
+        &mut self,
+        address: &U256,
+        code_offset: u32,
+        size: u32,
+        dest_offset: u32,
+    ) {
+        let code = self
+            .accounts
+            .account(&address.to_address())
+            .map(|account| account.code)
+            .unwrap_or_default();
+        let size = size as usize;
+        let dest_offset = dest_offset as usize;
+        let dest = &mut self.memory[dest_offset..dest_offset + size];
+        copy_zero_padded(&code, code_offset as usize, dest);
+    }
+
+    #[export_name = "emv_mlir__storage_read"]
+    pub extern "C" fn storage_read(&mut self, key: &U256, value_ptr: &mut U256) {
+        *value_ptr = self.storage.read(&self.env.call.address, key);
+    }
+
+    /// Writes `value` at `key` in the storage of the executing account,
+    /// adding the refund it earns to the refund counter.
+    ///
+    /// Returns the gas cost, and only does the above if it's at most `remaining_gas`.
+    #[export_name = "emv_mlir__storage_write"]
+    pub extern "C" fn storage_write(
+        &mut self,
+        key: &U256,
+        value: &U256,
+        remaining_gas: u64,
+    ) -> u64 {
+        let address = self.env.call.address;
+        let current = self.storage.read(&address, key);
+        // Until the slot is first written, its current value is the original one
+        let original = *self
+            .original_storage
+            .entry((address, *key))
+            .or_insert(current);
+
+        let (mut gas_cost, refund) =
+            sstore_gas_cost_and_refund(&original, &current, value, self.env.spec_id);
+        // The first access to the slot also pays for loading it (EIP-2929)
+        if self.access_slot(address, *key) {
+            gas_cost += gas_cost::COLD_SLOAD as u64;
+        }
+        if gas_cost > remaining_gas {
+            return gas_cost;
+        }
+
+        self.journal.push(JournalEntry::StorageChanged {
+            address,
+            key: *key,
+            previous: current,
+        });
+        self.storage.write(address, *key, *value);
+        self.refund_counter += refund;
+        gas_cost
+    }
+
+    /// Marks the slot at `key` in the storage of the executing account as accessed in the
+    /// transaction. Returns the gas cost of accessing it on top of a warm access, which
+    /// is only charged the first time (EIP-2929)
+    #[export_name = "emv_mlir__access_storage_slot"]
+    pub extern "C" fn access_storage_slot(&mut self, key: &U256) -> u64 {
+        if self.access_slot(self.env.call.address, *key) {
+            (gas_cost::COLD_SLOAD - gas_cost::SLOAD) as u64
+        } else {
+            0
+        }
+    }
+
+    /// Marks the account at `address` as accessed in the transaction. Returns the gas cost
+    /// of accessing it on top of a warm access, which is only charged the first time (EIP-2929)
+    #[export_name = "emv_mlir__access_account"]
+    pub extern "C" fn access_account(&mut self, address: &U256) -> u64 {
+        if self.access_address(address.to_address()) {
+            (gas_cost::COLD_ACCOUNT_ACCESS - gas_cost::WARM_ACCOUNT_ACCESS) as u64
+        } else {
+            0
+        }
+    }
+
+    /// Returns the extra gas cost of a CALL sending `value` to the account at `address`,
+    /// which is only charged when the call creates the account.
+    /// Since Spurious Dragon, that's when it sends value to an empty account (EIP-161)
+    #[export_name = "emv_mlir__call_new_account_cost"]
+    pub extern "C" fn call_new_account_cost(&mut self, address: &U256, value: &U256) -> u64 {
+        let account = self.accounts.account(&address.to_address());
+        let creates_account = if self.env.spec_id.includes(SpecId::SpuriousDragon) {
+            *value != U256::default() && account.map_or(true, |account| account.is_empty())
+        } else {
+            account.is_none()
+        };
+        if creates_account {
+            gas_cost::CALL_NEW_ACCOUNT as u64
+        } else {
+            0
+        }
+    }
+
+    #[export_name = "emv_mlir__transient_storage_read"]
+    pub extern "C" fn transient_storage_read(&mut self, key: &U256, value_ptr: &mut U256) {
+        *value_ptr = self.transient_storage.read(&self.env.call.address, key);
+    }
+
+    #[export_name = "emv_mlir__transient_storage_write"]
+    pub extern "C" fn transient_storage_write(&mut self, key: &U256, value: &U256) {
+        let address = self.env.call.address;
+        let previous = self.transient_storage.read(&address, key);
+        self.journal.push(JournalEntry::TransientStorageChanged {
+            address,
+            key: *key,
+            previous,
+        });
+        self.transient_storage.write(address, *key, *value);
+    }
+
+    /// Emits a new event with the memory segment `[offset, offset + size)` as data.
+    /// Its topics are added afterwards with [`Self::append_log_topic`].
+    #[export_name = "emv_mlir__append_log"]
+    pub extern "C" fn append_log(&mut self, offset: u32, size: u32) {
+        let data = self.memory_segment(offset, size);
+        self.logs.push(Log {
+            address: self.env.call.address,
+            data,
+            topics: Vec::new(),
+        });
+    }
+
+    /// Adds a topic to the last event emitted with [`Self::append_log`]
+    #[export_name = "emv_mlir__append_log_topic"]
+    pub extern "C" fn append_log_topic(&mut self, topic: &U256) {
+        let log = self
+            .logs
+            .last_mut()
+            .expect("topics are added after emitting the event");
+        log.topics.push(*topic);
+    }
+
+    #[export_name = "emv_mlir__is_static"]
+    pub extern "C" fn is_static(&mut self) -> u8 {
+        self.is_static as u8
+    }
+
+    /// Runs the code of the account at `address` with the memory segment
+    /// `[args_offset, args_offset + args_size)` as input and at most `gas_limit` gas.
+    /// The `call_type` decides the context the code runs in, and whether `value` wei
+    /// are transferred (see [`CallType`]).
+    /// The output is kept as return data, and as much of it as fits is copied to the memory
+    /// segment `[ret_offset, ret_offset + ret_size)`.
+    /// The gas left unused by the callee is written to `remaining_gas_ptr`.
+    ///
+    /// Returns 1 if the call succeeded, or 0 otherwise.
+    #[allow(clippy::too_many_arguments)]
+    #[export_name = "emv_mlir__call"]
+    pub extern "C" fn call(
+        &mut self,
+        call_type: u8,
+        gas_limit: u64,
+        address: &U256,
+        value: &U256,
+        args_offset: u32,
+        args_size: u32,
+        ret_offset: u32,
+        ret_size: u32,
+        remaining_gas_ptr: &mut u64,
+    ) -> u8 {
+        let calldata = self.memory_segment(args_offset, args_size);
+
+        let call_type = CallType::from_u8(call_type);
+        let (success, remaining_gas, output) =
+            self.execute_call(call_type, gas_limit, address.to_address(), *value, calldata);
+        *remaining_gas_ptr = remaining_gas;
+
+        let ret_offset = ret_offset as usize;
+        let copied = (ret_size as usize).min(output.len());
+        if copied > 0 {
+            self.memory[ret_offset..ret_offset + copied].copy_from_slice(&output[..copied]);
+        }
+        self.return_data = output;
+        success as u8
+    }
+
+    /// Creates a contract with the memory segment `[offset, offset + size)` as initcode,
+    /// at an address derived from the creator's address and nonce.
+    /// The initcode runs with at most `gas_limit` gas, and `value` wei are transferred
+    /// to the new contract.
+    /// Writes the address of the new contract to `address_ptr`, or zero if the creation failed.
+    ///
+    /// Returns the gas left unused.
+    #[export_name = "emv_mlir__create"]
+    pub extern "C" fn create(
+        &mut self,
+        offset: u32,
+        size: u32,
+        value: &U256,
+        gas_limit: u64,
+        address_ptr: &mut U256,
+    ) -> u64 {
+        let init_code = self.memory_segment(offset, size);
+        let creator = self.env.call.address;
+        let nonce = self
+            .accounts
+            .account(&creator)
+            .map(|account| account.nonce)
+            .unwrap_or(0);
+        let address = create_address(&creator, nonce);
+
+        let (success, remaining_gas, output) =
+            self.execute_create(init_code, address, *value, gas_limit);
+
+        *address_ptr = if success {
+            U256::from(address)
+        } else {
+            U256::default()
+        };
+        self.return_data = output;
+        remaining_gas
+    }
+
+    /// Like [`Self::create`], with the new contract's address derived from the creator's
+    /// address, the salt pointed to by `salt` and the hash of the initcode
+    #[export_name = "emv_mlir__create2"]
+    pub extern "C" fn create2(
+        &mut self,
+        offset: u32,
+        size: u32,
+        value: &U256,
+        salt: &U256,
+        gas_limit: u64,
+        address_ptr: &mut U256,
+    ) -> u64 {
+        let init_code = self.memory_segment(offset, size);
+        let address = create2_address(&self.env.call.address, salt, &init_code);
+
+        let (success, remaining_gas, output) =
+            self.execute_create(init_code, address, *value, gas_limit);
+
+        *address_ptr = if success {
+            U256::from(address)
+        } else {
+            U256::default()
+        };
+        self.return_data = output;
+        remaining_gas
+    }
+
+    /// Sends all the balance of the executing account to the account at `beneficiary`.
+    /// If the executing account was created in the current transaction, it's also deleted,
+    /// burning its balance in case it's the beneficiary (EIP-6780). Before Cancun, the
+    /// account is always deleted.
+    ///
+    /// Returns the dynamic gas cost, and only does the above if it's at most `remaining_gas`.
+    #[export_name = "emv_mlir__selfdestruct"]
+    pub extern "C" fn selfdestruct(&mut self, beneficiary: &U256, remaining_gas: u64) -> u64 {
+        let address = self.env.call.address;
+        let beneficiary = beneficiary.to_address();
+        let balance = self
+            .accounts
+            .account(&address)
+            .map(|account| account.balance)
+            .unwrap_or_default();
+
+        // Sending value to an empty account has an extra cost since Spurious Dragon
+        // (EIP-161), and creating the account did since Tangerine Whistle (EIP-150)
+        let beneficiary_account = self.accounts.account(&beneficiary);
+        let creates_account = if self.env.spec_id.includes(SpecId::SpuriousDragon) {
+            balance != U256::default()
+                && beneficiary_account.map_or(true, |account| account.is_empty())
+        } else {
+            self.env.spec_id.includes(SpecId::TangerineWhistle) && beneficiary_account.is_none()
+        };
+        let mut gas_cost = if creates_account {
+            gas_cost::SELFDESTRUCT_NEW_ACCOUNT as u64
+        } else {
+            0
+        };
+        // The first access to the beneficiary costs more (EIP-2929)
+        if self.access_address(beneficiary) {
+            gas_cost += gas_cost::COLD_ACCOUNT_ACCESS as u64;
+        }
+        if gas_cost > remaining_gas {
+            return gas_cost;
+        }
+
+        self.transfer(address, beneficiary, balance);
+        // Before Cancun, every account that self-destructs is deleted at the end of the
+        // transaction. Until then, its balance is burnt if it's its own beneficiary
+        if self.created_accounts.contains(&address) || !self.env.spec_id.includes(SpecId::Cancun) {
+            let mut account = self.accounts.account(&address).unwrap_or_default();
+            account.balance = U256::default();
+            self.set_account(address, account);
+            let newly_destroyed = self.destroyed_accounts.insert(address);
+            // Destroying an account was refunded before London (EIP-3529)
+            if newly_destroyed && !self.env.spec_id.includes(SpecId::London) {
+                self.refund_counter += gas_cost::SELFDESTRUCT_REFUND_PRE_LONDON;
+            }
+        }
+        gas_cost
+    }
+}
+
+/// Syscalls of the entrypoint of shared libraries, which runs the program with a new
+/// context, see [`SharedEntrypoint`]
+impl SyscallContext {
+    /// Creates a context running in `env` with the given calldata and an empty storage,
+    /// to be released with [`Self::finish_context`]
+    ///
+    /// # Safety
+    ///
+    /// `env` must point to a valid [`Env`], and `calldata` to `calldata_len` bytes.
+    #[export_name = "emv_mlir__new_context"]
+    pub unsafe extern "C" fn new_context(
+        env: *const Env,
+        calldata: *const u8,
+        calldata_len: u64,
+    ) -> *mut SyscallContext {
+        let calldata = match calldata_len {
+            0 => Vec::new(),
+            len => std::slice::from_raw_parts(calldata, len as usize).to_vec(),
+        };
+        let context = Self::new((*env).clone(), calldata, Box::<InMemoryStorage>::default());
+        Box::into_raw(Box::new(context))
+    }
+
+    /// Releases a context created with [`Self::new_context`], after writing the gas left
+    /// to `gas` and copying as much of the output as fits in `output`. The whole size of
+    /// the output is written to `output_len`
+    ///
+    /// # Safety
+    ///
+    /// `context` must come from [`Self::new_context`], `output` must point to
+    /// `output_capacity` writable bytes, and `gas` and `output_len` must be writable.
+    #[export_name = "emv_mlir__finish_context"]
+    pub unsafe extern "C" fn finish_context(
+        context: *mut SyscallContext,
+        gas: *mut u64,
+        output: *mut u8,
+        output_capacity: u64,
+        output_len: *mut u64,
+    ) {
+        let context = Box::from_raw(context);
+        let return_values = context.return_values();
+        let copied = return_values.len().min(output_capacity as usize);
+        if copied > 0 {
+            std::ptr::copy_nonoverlapping(return_values.as_ptr(), output, copied);
+        }
+        *gas = context.remaining_gas();
+        *output_len = return_values.len() as u64;
+    }
+}
+
+/// Copies `src[offset..]` into `dest`, filling with zeros the bytes past the end of `src`
+fn copy_zero_padded(src: &[u8], offset: usize, dest: &mut [u8]) {
+    let offset = offset.min(src.len());
+    let copied = dest.len().min(src.len() - offset);
+    dest[..copied].copy_from_slice(&src[offset..offset + copied]);
+    dest[copied..].fill(0);
+}
+
+/// Returns the gas cost and refund of an SSTORE changing a slot from `current` to `new`,
+/// given its `original` value at the start of the transaction (EIP-2200, EIP-3529),
+/// without the cost of a cold slot. The refund is negative when it undoes a previous one.
+///
+/// Before Istanbul, the cost only depends on the current and new values. Constantinople's
+/// net gas metering (EIP-1283) is left out, as Petersburg removed it in the same block
+fn sstore_gas_cost_and_refund(
+    original: &U256,
+    current: &U256,
+    new: &U256,
+    spec_id: SpecId,
+) -> (u64, i64) {
+    let zero = U256::default();
+    let clear_refund = if spec_id.includes(SpecId::London) {
+        gas_cost::SSTORE_CLEAR_REFUND
+    } else {
+        gas_cost::SSTORE_CLEAR_REFUND_PRE_LONDON
+    };
+    if !spec_id.includes(SpecId::Istanbul) {
+        let gas_cost = if *current == zero && *new != zero {
+            gas_cost::SSTORE_SET
+        } else {
+            gas_cost::SSTORE_RESET_PRE_BERLIN
+        };
+        let refund = if *current != zero && *new == zero {
+            clear_refund
+        } else {
+            0
+        };
+        return (gas_cost as u64, refund);
+    }
+
+    // Before Berlin, these also paid for loading the slot (EIP-2929)
+    let (sstore_cost, reset_cost) = if spec_id.includes(SpecId::Berlin) {
+        (gas_cost::SSTORE, gas_cost::SSTORE_RESET)
+    } else {
+        (
+            gas_cost::SSTORE_PRE_BERLIN,
+            gas_cost::SSTORE_RESET_PRE_BERLIN,
+        )
+    };
+    if new == current {
+        return (sstore_cost as u64, 0);
+    }
+
+    // The slot wasn't modified in the transaction
+    if original == current {
+        let (gas_cost, refund) = if *original == zero {
+            (gas_cost::SSTORE_SET, 0)
+        } else if *new == zero {
+            (reset_cost, clear_refund)
+        } else {
+            (reset_cost, 0)
+        };
+        return (gas_cost as u64, refund);
+    }
+
+    // The slot was already modified, so its cost was paid by a previous SSTORE
+    let mut refund = 0;
+    if *original != zero {
+        if *current == zero {
+            // Undo the refund for clearing the slot
+            refund -= clear_refund;
+        } else if *new == zero {
+            refund += clear_refund;
+        }
+    }
+    // Restoring the original value refunds the cost of the first SSTORE,
+    // save for the cost of this one
+    if new == original {
+        let first_cost = if *original == zero {
+            gas_cost::SSTORE_SET
+        } else {
+            reset_cost
+        };
+        refund += first_cost - sstore_cost;
+    }
+    (sstore_cost as u64, refund)
+}
+
+/// Returns the cost of depositing `code` as a contract's code, charged per byte,
+/// or [`None`] if it can't be deposited: it's larger than [`MAX_CODE_SIZE`] (EIP-170),
+/// or starts with 0xEF (EIP-3541). Failing to deposit it is an exceptional halt
+fn code_deposit_cost(code: &[u8], spec_id: SpecId) -> Option<u64> {
+    let too_large = spec_id.includes(SpecId::SpuriousDragon) && code.len() > MAX_CODE_SIZE;
+    let reserved = spec_id.includes(SpecId::London) && code.first() == Some(&0xEF);
+    if too_large || reserved {
+        return None;
+    }
+    Some(code.len() as u64 * gas_cost::CODE_DEPOSIT_BYTE as u64)
+}
+
+/// Returns the address of the contract created with CREATE by `creator` when its nonce
+/// was `nonce`, which is the last 20 bytes of the hash of `rlp([creator, nonce])`
+fn create_address(creator: &Address, nonce: u64) -> Address {
+    // The nonce is encoded as its big-endian bytes without leading zeros,
+    // which are prefixed with their length unless it's a single byte below 0x80
+    let nonce_bytes = nonce.to_be_bytes();
+    let nonce_bytes = &nonce_bytes[nonce.leading_zeros() as usize / 8..];
+    let mut encoded_nonce = Vec::new();
+    if !matches!(nonce_bytes, [byte] if *byte < 0x80) {
+        encoded_nonce.push(0x80 + nonce_bytes.len() as u8);
+    }
+    encoded_nonce.extend_from_slice(nonce_bytes);
+
+    // Both items are short, so the list's prefix is its length plus 0xC0
+    let list_length = 1 + creator.len() + encoded_nonce.len();
+    let mut encoded = vec![0xC0 + list_length as u8, 0x80 + creator.len() as u8];
+    encoded.extend_from_slice(creator);
+    encoded.extend_from_slice(&encoded_nonce);
+
+    Keccak256::digest(&encoded)[12..].try_into().unwrap()
+}
+
+/// Returns the address of the contract created with CREATE2 by `creator`, which is
+/// the last 20 bytes of the hash of `0xFF ++ creator ++ salt ++ keccak256(init_code)` (EIP-1014)
+fn create2_address(creator: &Address, salt: &U256, init_code: &[u8]) -> Address {
+    let mut hasher = Keccak256::new();
+    hasher.update([0xFF]);
+    hasher.update(creator);
+    hasher.update(salt.to_be_bytes());
+    hasher.update(Keccak256::digest(init_code));
+    hasher.finalize()[12..].try_into().unwrap()
+}
+
+/// The native stack a nested frame needs left to start on the current stack segment,
+/// enough for its EVM stack and the syscalls it makes before the next frame
+const NATIVE_STACK_RED_ZONE: usize = 256 * 1024;
+/// The size of the native stack segments the nested frames are run on, once it runs low
+const NATIVE_STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+/// Compiles and runs `code` with the given context and gas, which it reads as its own code.
+/// Returns the exit status, or [`None`] if the code couldn't be compiled.
+fn run_code(code: &[u8], context: &mut SyscallContext, initial_gas: u64) -> Option<ExitStatusCode> {
+    context.code = code.to_vec();
+    let spec_id = context.env.spec_id;
+    // The nested calls are instrumented along with the outermost one
+    let instrumented = context.is_instrumented();
+    // The cache is released before running the code, since the calls it makes use it too
+    let executor = match &context.contract_cache {
+        Some(cache) => cache
+            .borrow_mut()
+            .get_or_compile(code, spec_id, instrumented)
+            .ok()?,
+        None => Rc::new(compile_code(code, spec_id, instrumented).ok()?),
+    };
+    // Each frame allocates its EVM stack on the native one, which a chain of nested calls as
+    // deep as MAX_CALL_DEPTH would overflow, so it's extended when it runs low
+    let exit_code = stacker::maybe_grow(NATIVE_STACK_RED_ZONE, NATIVE_STACK_SEGMENT, || {
+        executor.execute(context, initial_gas)
+    });
+    Some(ExitStatusCode::from_u8(exit_code))
+}
+
+pub mod symbols {
+    pub const WRITE_RESULT: &str = "emv_mlir__write_result";
+    pub const TRACE_STEP: &str = "emv_mlir__trace_step";
+    pub const BREAKPOINT: &str = "emv_mlir__breakpoint";
+    pub const EXTEND_MEMORY: &str = "emv_mlir__extend_memory";
+    pub const GET_STACK: &str = "emv_mlir__get_stack";
+    pub const KECCAK256_HASHER: &str = "emv_mlir__keccak256_hasher";
+    pub const GET_ADDRESS: &str = "emv_mlir__get_address";
+    pub const GET_ORIGIN: &str = "emv_mlir__get_origin";
+    pub const GET_CALLER: &str = "emv_mlir__get_caller";
+    pub const GET_CALLVALUE: &str = "emv_mlir__get_callvalue";
+    pub const GET_GASPRICE: &str = "emv_mlir__get_gasprice";
+    pub const GET_COINBASE: &str = "emv_mlir__get_coinbase";
+    pub const GET_TIMESTAMP: &str = "emv_mlir__get_timestamp";
+    pub const GET_NUMBER: &str = "emv_mlir__get_number";
+    pub const GET_PREVRANDAO: &str = "emv_mlir__get_prevrandao";
+    pub const GET_GASLIMIT: &str = "emv_mlir__get_gaslimit";
+    pub const GET_CHAINID: &str = "emv_mlir__get_chainid";
+    pub const GET_BASEFEE: &str = "emv_mlir__get_basefee";
+    pub const GET_BLOBBASEFEE: &str = "emv_mlir__get_blobbasefee";
+    pub const GET_SELF_BALANCE: &str = "emv_mlir__get_self_balance";
+    pub const GET_BLOB_HASH: &str = "emv_mlir__get_blob_hash";
+    pub const GET_BLOCK_HASH: &str = "emv_mlir__get_block_hash";
+    pub const CALLDATA_LOAD: &str = "emv_mlir__calldata_load";
+    pub const GET_CALLDATA_SIZE: &str = "emv_mlir__get_calldata_size";
+    pub const COPY_CALLDATA_TO_MEMORY: &str = "emv_mlir__copy_calldata_to_memory";
+    pub const GET_CODE_SIZE: &str = "emv_mlir__get_code_size";
+    pub const COPY_CODE_TO_MEMORY: &str = "emv_mlir__copy_code_to_memory";
+    pub const GET_RETURN_DATA_SIZE: &str = "emv_mlir__get_return_data_size";
+    pub const COPY_RETURN_DATA_TO_MEMORY: &str = "emv_mlir__copy_return_data_to_memory";
+    pub const GET_EXTCODE_SIZE: &str = "emv_mlir__get_extcode_size";
+    pub const GET_EXTCODE_HASH: &str = "emv_mlir__get_extcode_hash";
+    pub const GET_BALANCE: &str = "emv_mlir__get_balance";
+    pub const COPY_EXTCODE_TO_MEMORY: &str = "emv_mlir__copy_extcode_to_memory";
+    pub const STORAGE_READ: &str = "emv_mlir__storage_read";
+    pub const STORAGE_WRITE: &str = "emv_mlir__storage_write";
+    pub const ACCESS_STORAGE_SLOT: &str = "emv_mlir__access_storage_slot";
+    pub const ACCESS_ACCOUNT: &str = "emv_mlir__access_account";
+    pub const CALL_NEW_ACCOUNT_COST: &str = "emv_mlir__call_new_account_cost";
+    pub const TRANSIENT_STORAGE_READ: &str = "emv_mlir__transient_storage_read";
+    pub const TRANSIENT_STORAGE_WRITE: &str = "emv_mlir__transient_storage_write";
+    pub const APPEND_LOG: &str = "emv_mlir__append_log";
+    pub const APPEND_LOG_TOPIC: &str = "emv_mlir__append_log_topic";
+    pub const IS_STATIC: &str = "emv_mlir__is_static";
+    pub const CALL: &str = "emv_mlir__call";
+    pub const CREATE: &str = "emv_mlir__create";
+    pub const CREATE2: &str = "emv_mlir__create2";
+    pub const SELFDESTRUCT: &str = "emv_mlir__selfdestruct";
+    pub const NEW_CONTEXT: &str = "emv_mlir__new_context";
+    pub const FINISH_CONTEXT: &str = "emv_mlir__finish_context";
+}
+
+/// Registers all the syscalls as symbols in the execution engine
+///
+/// This allows the generated code to call the syscalls by name.
+pub fn register_syscalls(engine: &ExecutionEngine) {
+    unsafe {
+        engine.register_symbol(
+            symbols::WRITE_RESULT,
+            SyscallContext::write_result as *const fn(*mut c_void, u32, u32, u64, u8) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::TRACE_STEP,
+            SyscallContext::trace_step
+                as *const fn(*mut c_void, u64, u8, u64, *const U256, *const U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::BREAKPOINT,
+            SyscallContext::breakpoint
+                as *const fn(*mut c_void, u64, u8, u64, *const U256, *const U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::EXTEND_MEMORY,
+            SyscallContext::extend_memory as *const fn(*mut c_void, u32) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_STACK,
+            SyscallContext::get_stack as *const fn(*mut c_void) -> *mut U256 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::KECCAK256_HASHER,
+            SyscallContext::keccak256_hasher as *const fn(*mut c_void, u32, u32, *mut U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_ADDRESS,
+            SyscallContext::get_address as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_ORIGIN,
+            SyscallContext::get_origin as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_CALLER,
+            SyscallContext::get_caller as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_CALLVALUE,
+            SyscallContext::get_callvalue as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_GASPRICE,
+            SyscallContext::get_gasprice as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_COINBASE,
+            SyscallContext::get_coinbase as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_TIMESTAMP,
+            SyscallContext::get_timestamp as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_NUMBER,
+            SyscallContext::get_number as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_PREVRANDAO,
+            SyscallContext::get_prevrandao as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_GASLIMIT,
+            SyscallContext::get_gaslimit as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_CHAINID,
+            SyscallContext::get_chainid as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_BASEFEE,
+            SyscallContext::get_basefee as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_BLOBBASEFEE,
+            SyscallContext::get_blobbasefee as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_SELF_BALANCE,
+            SyscallContext::get_self_balance as *const fn(*mut c_void, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_BLOB_HASH,
+            SyscallContext::get_blob_hash as *const fn(*mut c_void, *const U256, *mut U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_BLOCK_HASH,
+            SyscallContext::get_block_hash as *const fn(*mut c_void, *const U256, *mut U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::CALLDATA_LOAD,
+            SyscallContext::calldata_load as *const fn(*mut c_void, u32, *mut U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_CALLDATA_SIZE,
+            SyscallContext::get_calldata_size as *const fn(*mut c_void) -> u32 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::COPY_CALLDATA_TO_MEMORY,
+            SyscallContext::copy_calldata_to_memory as *const fn(*mut c_void, u32, u32, u32)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_CODE_SIZE,
+            SyscallContext::get_code_size as *const fn(*mut c_void) -> u32 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::COPY_CODE_TO_MEMORY,
+            SyscallContext::copy_code_to_memory as *const fn(*mut c_void, u32, u32, u32) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_RETURN_DATA_SIZE,
+            SyscallContext::get_return_data_size as *const fn(*mut c_void) -> u32 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::COPY_RETURN_DATA_TO_MEMORY,
+            SyscallContext::copy_return_data_to_memory as *const fn(*mut c_void, u32, u32, u32)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_EXTCODE_SIZE,
+            SyscallContext::get_extcode_size as *const fn(*mut c_void, *const U256) -> u32
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_EXTCODE_HASH,
+            SyscallContext::get_extcode_hash as *const fn(*mut c_void, *const U256, *mut U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::GET_BALANCE,
+            SyscallContext::get_balance as *const fn(*mut c_void, *const U256, *mut U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::COPY_EXTCODE_TO_MEMORY,
+            SyscallContext::copy_extcode_to_memory
+                as *const fn(*mut c_void, *const U256, u32, u32, u32) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::STORAGE_READ,
+            SyscallContext::storage_read as *const fn(*mut c_void, *const U256, *mut U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::STORAGE_WRITE,
+            SyscallContext::storage_write
+                as *const fn(*mut c_void, *const U256, *const U256, u64) -> u64
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::ACCESS_STORAGE_SLOT,
+            SyscallContext::access_storage_slot as *const fn(*mut c_void, *const U256) -> u64
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::ACCESS_ACCOUNT,
+            SyscallContext::access_account as *const fn(*mut c_void, *const U256) -> u64 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::CALL_NEW_ACCOUNT_COST,
+            SyscallContext::call_new_account_cost
+                as *const fn(*mut c_void, *const U256, *const U256) -> u64 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::TRANSIENT_STORAGE_READ,
+            SyscallContext::transient_storage_read as *const fn(*mut c_void, *const U256, *mut U256)
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::TRANSIENT_STORAGE_WRITE,
+            SyscallContext::transient_storage_write
+                as *const fn(*mut c_void, *const U256, *const U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::APPEND_LOG,
+            SyscallContext::append_log as *const fn(*mut c_void, u32, u32) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::APPEND_LOG_TOPIC,
+            SyscallContext::append_log_topic as *const fn(*mut c_void, *const U256) as *mut (),
+        );
+        engine.register_symbol(
+            symbols::IS_STATIC,
+            SyscallContext::is_static as *const fn(*mut c_void) -> u8 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::CALL,
+            SyscallContext::call
+                as *const fn(
+                    *mut c_void,
+                    u8,
+                    u64,
+                    *const U256,
+                    *const U256,
+                    u32,
+                    u32,
+                    u32,
+                    u32,
+                    *mut u64,
+                ) -> u8 as *mut (),
+        );
+        engine.register_symbol(
+            symbols::CREATE,
+            SyscallContext::create
+                as *const fn(*mut c_void, u32, u32, *const U256, u64, *mut U256) -> u64
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::CREATE2,
+            SyscallContext::create2
+                as *const fn(*mut c_void, u32, u32, *const U256, *const U256, u64, *mut U256) -> u64
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::SELFDESTRUCT,
+            SyscallContext::selfdestruct as *const fn(*mut c_void, *const U256, u64) -> u64
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::NEW_CONTEXT,
+            SyscallContext::new_context as *const fn(*const Env, *const u8, u64) -> *mut c_void
+                as *mut (),
+        );
+        engine.register_symbol(
+            symbols::FINISH_CONTEXT,
+            SyscallContext::finish_context
+                as *const fn(*mut c_void, *mut u64, *mut u8, u64, *mut u64) as *mut (),
+        );
+    };
+}
+
+/// MLIR util for declaring syscalls
+pub(crate) mod mlir {
+    use melior::{
+        dialect::{func, llvm::r#type::pointer},
+        ir::{
+            attribute::{FlatSymbolRefAttribute, StringAttribute, TypeAttribute},
+            r#type::{FunctionType, IntegerType},
+            Block, Identifier, Location, Module as MeliorModule, Region, Value,
+        },
+        Context as MeliorContext,
+    };
+
+    use crate::errors::CodegenError;
+
+    use super::symbols;
+
+    pub(crate) fn declare_syscalls(context: &MeliorContext, module: &MeliorModule) {
+        let location = Location::unknown(context);
+
+        // Type declarations
+        let ptr_type = pointer(context, 0);
+        let uint8 = IntegerType::new(context, 8).into();
+        let uint32 = IntegerType::new(context, 32).into();
+        let uint64 = IntegerType::new(context, 64).into();
+
+        let attributes = &[(
+            Identifier::new(context, "sym_visibility"),
+            StringAttribute::new(context, "private").into(),
+        )];
+
+        // Syscall declarations
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::WRITE_RESULT),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, uint32, uint32, uint64, uint8], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::TRACE_STEP),
+            TypeAttribute::new(
+                FunctionType::new(
+                    context,
+                    &[ptr_type, uint64, uint8, uint64, ptr_type, ptr_type],
+                    &[],
+                )
+                .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::BREAKPOINT),
+            TypeAttribute::new(
+                FunctionType::new(
+                    context,
+                    &[ptr_type, uint64, uint8, uint64, ptr_type, ptr_type],
+                    &[],
+                )
+                .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::EXTEND_MEMORY),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, uint32], &[ptr_type]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_STACK),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[ptr_type]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::KECCAK256_HASHER),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, uint32, uint32, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        for symbol in [
+            symbols::GET_ADDRESS,
+            symbols::GET_ORIGIN,
+            symbols::GET_CALLER,
+            symbols::GET_CALLVALUE,
+            symbols::GET_GASPRICE,
+            symbols::GET_COINBASE,
+            symbols::GET_TIMESTAMP,
+            symbols::GET_NUMBER,
+            symbols::GET_PREVRANDAO,
+            symbols::GET_GASLIMIT,
+            symbols::GET_CHAINID,
+            symbols::GET_BASEFEE,
+            symbols::GET_BLOBBASEFEE,
+            symbols::GET_SELF_BALANCE,
+        ] {
+            module.body().append_operation(func::func(
+                context,
+                StringAttribute::new(context, symbol),
+                TypeAttribute::new(FunctionType::new(context, &[ptr_type, ptr_type], &[]).into()),
+                Region::new(),
+                attributes,
+                location,
+            ));
+        }
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_BLOB_HASH),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_BLOCK_HASH),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::CALLDATA_LOAD),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, uint32, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_CALLDATA_SIZE),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint32]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::COPY_CALLDATA_TO_MEMORY),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, uint32, uint32, uint32], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_CODE_SIZE),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint32]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::COPY_CODE_TO_MEMORY),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, uint32, uint32, uint32], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_RETURN_DATA_SIZE),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint32]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::COPY_RETURN_DATA_TO_MEMORY),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, uint32, uint32, uint32], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_EXTCODE_SIZE),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, ptr_type], &[uint32]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_EXTCODE_HASH),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::GET_BALANCE),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::COPY_EXTCODE_TO_MEMORY),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, uint32, uint32, uint32], &[])
+                    .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::STORAGE_READ),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::STORAGE_WRITE),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type, uint64], &[uint64])
+                    .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::ACCESS_STORAGE_SLOT),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, ptr_type], &[uint64]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::ACCESS_ACCOUNT),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, ptr_type], &[uint64]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::CALL_NEW_ACCOUNT_COST),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[uint64]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::TRANSIENT_STORAGE_READ),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::TRANSIENT_STORAGE_WRITE),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, ptr_type], &[]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::APPEND_LOG),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, uint32, uint32], &[]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::APPEND_LOG_TOPIC),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type, ptr_type], &[]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::IS_STATIC),
+            TypeAttribute::new(FunctionType::new(context, &[ptr_type], &[uint8]).into()),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::CALL),
+            TypeAttribute::new(
+                FunctionType::new(
+                    context,
+                    &[
+                        ptr_type, uint8, uint64, ptr_type, ptr_type, uint32, uint32, uint32,
+                        uint32, ptr_type,
+                    ],
+                    &[uint8],
+                )
+                .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::CREATE),
+            TypeAttribute::new(
+                FunctionType::new(
+                    context,
+                    &[ptr_type, uint32, uint32, ptr_type, uint64, ptr_type],
+                    &[uint64],
+                )
+                .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::CREATE2),
+            TypeAttribute::new(
+                FunctionType::new(
+                    context,
+                    &[
+                        ptr_type, uint32, uint32, ptr_type, ptr_type, uint64, ptr_type,
+                    ],
+                    &[uint64],
+                )
+                .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::SELFDESTRUCT),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, uint64], &[uint64]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::NEW_CONTEXT),
+            TypeAttribute::new(
+                FunctionType::new(context, &[ptr_type, ptr_type, uint64], &[ptr_type]).into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+
+        module.body().append_operation(func::func(
+            context,
+            StringAttribute::new(context, symbols::FINISH_CONTEXT),
+            TypeAttribute::new(
+                FunctionType::new(
+                    context,
+                    &[ptr_type, ptr_type, ptr_type, uint64, ptr_type],
+                    &[],
+                )
+                .into(),
+            ),
+            Region::new(),
+            attributes,
+            location,
+        ));
+    }
+
+    /// Stores the return values, the remaining gas and the exit status in the syscall context
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn write_result_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        offset: Value,
+        size: Value,
+        remaining_gas: Value,
+        execution_result: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::WRITE_RESULT),
+            &[syscall_ctx, offset, size, remaining_gas, execution_result],
+            &[],
+            location,
+        ));
+    }
+
+    /// Reports the opcode at `pc` to the syscall context, before executing it.
+    /// The stack is given by its bottom and top pointers.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn trace_step_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        pc: Value,
+        opcode: Value,
+        gas: Value,
+        stack_base: Value,
+        stack_top: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::TRACE_STEP),
+            &[syscall_ctx, pc, opcode, gas, stack_base, stack_top],
+            &[],
+            location,
+        ));
+    }
+
+    /// Reports a breakpoint hit at `pc` to the syscall context, before executing its opcode.
+    /// The stack is given by its bottom and top pointers.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn breakpoint_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        pc: Value,
+        opcode: Value,
+        gas: Value,
+        stack_base: Value,
+        stack_top: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::BREAKPOINT),
+            &[syscall_ctx, pc, opcode, gas, stack_base, stack_top],
+            &[],
+            location,
+        ));
+    }
+
+    /// Extends the memory segment of the syscall context.
+    /// Returns a pointer to the start of the memory segment.
+    pub(crate) fn extend_memory_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        new_size: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let ptr_type = pointer(mlir_ctx, 0);
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::EXTEND_MEMORY),
+                &[syscall_ctx, new_size],
+                &[ptr_type],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Returns the bottom of the stack mapped for a program compiled with a guard page.
+    pub(crate) fn get_stack_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let ptr_type = pointer(mlir_ctx, 0);
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_STACK),
+                &[syscall_ctx],
+                &[ptr_type],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Computes the Keccak-256 hash of the memory segment `[offset, offset + size)`.
+    /// The hash is written to the 256-bit value pointed to by `hash_ptr`.
+    pub(crate) fn keccak256_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        offset: Value,
+        size: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::KECCAK256_HASHER),
+            &[syscall_ctx, offset, size, hash_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Calls a syscall that writes a value of the execution environment
+    /// to the 256-bit value pointed to by `value_ptr`.
+    fn env_value_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        symbol: &str,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbol),
+            &[syscall_ctx, value_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Writes the address of the executing contract to `value_ptr`.
+    pub(crate) fn get_address_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_ADDRESS;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the sender of the transaction to `value_ptr`.
+    pub(crate) fn get_origin_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_ORIGIN;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the sender of the call to `value_ptr`.
+    pub(crate) fn get_caller_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_CALLER;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the value transferred with the call to `value_ptr`.
+    pub(crate) fn get_callvalue_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_CALLVALUE;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the transaction's effective gas price to `value_ptr`.
+    pub(crate) fn get_gasprice_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_GASPRICE;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the address of the block's beneficiary to `value_ptr`.
+    pub(crate) fn get_coinbase_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_COINBASE;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the block's timestamp to `value_ptr`.
+    pub(crate) fn get_timestamp_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_TIMESTAMP;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the block's number to `value_ptr`.
+    pub(crate) fn get_number_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_NUMBER;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the randomness beacon output of the previous block to `value_ptr`.
+    pub(crate) fn get_prevrandao_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_PREVRANDAO;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the block's gas limit to `value_ptr`.
+    pub(crate) fn get_gaslimit_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_GASLIMIT;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the chain ID to `value_ptr`.
+    pub(crate) fn get_chainid_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_CHAINID;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the block's base fee to `value_ptr`.
+    pub(crate) fn get_basefee_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_BASEFEE;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the block's base fee per blob gas to `value_ptr`.
+    pub(crate) fn get_blobbasefee_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_BLOBBASEFEE;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the balance of the executing account to `value_ptr`.
+    pub(crate) fn get_self_balance_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        let symbol = symbols::GET_SELF_BALANCE;
+        env_value_syscall(mlir_ctx, syscall_ctx, block, symbol, value_ptr, location);
+    }
+
+    /// Writes the versioned hash of the transaction's blob whose index is pointed to
+    /// by `index_ptr` to `hash_ptr`.
+    pub(crate) fn get_blob_hash_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        index_ptr: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_BLOB_HASH),
+            &[syscall_ctx, index_ptr, hash_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Writes the hash of the block whose number is pointed to by `number_ptr`
+    /// to `hash_ptr`.
+    pub(crate) fn get_block_hash_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        number_ptr: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_BLOCK_HASH),
+            &[syscall_ctx, number_ptr, hash_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Reads a 32-byte word from the calldata, starting at `offset`.
+    /// The word is written to the 256-bit value pointed to by `value_ptr`.
+    pub(crate) fn calldata_load_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        offset: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::CALLDATA_LOAD),
+            &[syscall_ctx, offset, value_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Returns the size of the calldata, in bytes.
+    pub(crate) fn get_calldata_size_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint32 = IntegerType::new(mlir_ctx, 32).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_CALLDATA_SIZE),
+                &[syscall_ctx],
+                &[uint32],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Copies `size` bytes of the calldata, starting at `calldata_offset`,
+    /// into the memory at `dest_offset`.
+    pub(crate) fn copy_calldata_to_memory_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        calldata_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::COPY_CALLDATA_TO_MEMORY),
+            &[syscall_ctx, calldata_offset, size, dest_offset],
+            &[],
+            location,
+        ));
+    }
+
+    /// Returns the size of the code being executed, in bytes.
+    pub(crate) fn get_code_size_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint32 = IntegerType::new(mlir_ctx, 32).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_CODE_SIZE),
+                &[syscall_ctx],
+                &[uint32],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Copies `size` bytes of the code being executed, starting at `code_offset`,
+    /// into the memory at `dest_offset`.
+    pub(crate) fn copy_code_to_memory_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        code_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::COPY_CODE_TO_MEMORY),
+            &[syscall_ctx, code_offset, size, dest_offset],
+            &[],
+            location,
+        ));
+    }
+
+    /// Returns the size of the output data of the last subcall, in bytes.
+    pub(crate) fn get_return_data_size_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint32 = IntegerType::new(mlir_ctx, 32).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_RETURN_DATA_SIZE),
+                &[syscall_ctx],
+                &[uint32],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Copies `size` bytes of the output data of the last subcall, starting at
+    /// `return_data_offset`, into the memory at `dest_offset`.
+    pub(crate) fn copy_return_data_to_memory_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        return_data_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::COPY_RETURN_DATA_TO_MEMORY),
+            &[syscall_ctx, return_data_offset, size, dest_offset],
+            &[],
+            location,
+        ));
+    }
+
+    /// Returns the size of the code of the account whose address is pointed to by `address_ptr`.
+    pub(crate) fn get_extcode_size_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint32 = IntegerType::new(mlir_ctx, 32).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_EXTCODE_SIZE),
+                &[syscall_ctx, address_ptr],
+                &[uint32],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Writes the code hash of the account whose address is pointed to by `address_ptr`
+    /// to `hash_ptr`.
+    pub(crate) fn get_extcode_hash_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        address_ptr: Value,
+        hash_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_EXTCODE_HASH),
+            &[syscall_ctx, address_ptr, hash_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Writes the balance of the account whose address is pointed to by `address_ptr`
+    /// to `balance_ptr`.
+    pub(crate) fn get_balance_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        address_ptr: Value,
+        balance_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::GET_BALANCE),
+            &[syscall_ctx, address_ptr, balance_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Copies `size` bytes of the code of the account whose address is pointed to by
+    /// `address_ptr`, starting at `code_offset`, into the memory at `dest_offset`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn copy_extcode_to_memory_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        address_ptr: Value,
+        code_offset: Value,
+        size: Value,
+        dest_offset: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::COPY_EXTCODE_TO_MEMORY),
+            &[syscall_ctx, address_ptr, code_offset, size, dest_offset],
+            &[],
+            location,
+        ));
     }
-}
 
-/// Syscall implementations
-///
-/// Note that each function is marked as `extern "C"`, which is necessary for the
-/// function to be callable from the generated code.
-impl SyscallContext {
-    pub extern "C" fn write_result(&mut self, offset: u32, bytes_len: u32) {
-        self.result = Some((offset as usize, bytes_len as usize));
+    /// Reads the storage slot pointed to by `key_ptr`.
+    /// The value is written to the 256-bit value pointed to by `value_ptr`.
+    pub(crate) fn storage_read_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        key_ptr: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::STORAGE_READ),
+            &[syscall_ctx, key_ptr, value_ptr],
+            &[],
+            location,
+        ));
     }
 
-    pub extern "C" fn extend_memory(&mut self, new_size: u32) -> *mut u8 {
-        let new_size = new_size as usize;
-        if new_size <= self.memory.len() {
-            return self.memory.as_mut_ptr();
-        }
-        match self.memory.try_reserve(new_size - self.memory.len()) {
-            Ok(()) => {
-                self.memory.resize(new_size, 0);
-                self.memory.as_mut_ptr()
-            }
-            // TODO: use tracing here
-            Err(err) => {
-                eprintln!("Failed to reserve memory: {err}");
-                std::ptr::null_mut()
-            }
-        }
+    /// Writes the value pointed to by `value_ptr` to the storage slot pointed to by `key_ptr`,
+    /// if its gas cost is at most `remaining_gas`. Returns the gas cost.
+    pub(crate) fn storage_write_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        key_ptr: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        remaining_gas: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::STORAGE_WRITE),
+                &[syscall_ctx, key_ptr, value_ptr, remaining_gas],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
     }
-}
-
-pub mod symbols {
-    pub const WRITE_RESULT: &str = "emv_mlir__write_result";
-    pub const EXTEND_MEMORY: &str = "emv_mlir__extend_memory";
-}
-
-/// Registers all the syscalls as symbols in the execution engine
-///
-/// This allows the generated code to call the syscalls by name.
-pub fn register_syscalls(engine: &ExecutionEngine) {
-    unsafe {
-        engine.register_symbol(
-            symbols::WRITE_RESULT,
-            SyscallContext::write_result as *const fn(*mut c_void, u32, u32) as *mut (),
-        );
-        engine.register_symbol(
-            symbols::EXTEND_MEMORY,
-            SyscallContext::extend_memory as *const fn(*mut c_void, u32) as *mut (),
-        );
-    };
-}
-
-/// MLIR util for declaring syscalls
-pub(crate) mod mlir {
-    use melior::{
-        dialect::{func, llvm::r#type::pointer},
-        ir::{
-            attribute::{FlatSymbolRefAttribute, StringAttribute, TypeAttribute},
-            r#type::{FunctionType, IntegerType},
-            Block, Identifier, Location, Module as MeliorModule, Region, Value,
-        },
-        Context as MeliorContext,
-    };
-
-    use crate::errors::CodegenError;
-
-    use super::symbols;
 
-    pub(crate) fn declare_syscalls(context: &MeliorContext, module: &MeliorModule) {
-        let location = Location::unknown(context);
+    /// Marks the storage slot pointed to by `key_ptr` as accessed.
+    /// Returns the gas cost of accessing it, on top of the cost of a warm access.
+    pub(crate) fn access_storage_slot_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        key_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::ACCESS_STORAGE_SLOT),
+                &[syscall_ctx, key_ptr],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
 
-        // Type declarations
-        let ptr_type = pointer(context, 0);
-        let uint32 = IntegerType::new(context, 32).into();
+    /// Marks the account at the address pointed to by `address_ptr` as accessed.
+    /// Returns the gas cost of accessing it, on top of the cost of a warm access.
+    pub(crate) fn access_account_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::ACCESS_ACCOUNT),
+                &[syscall_ctx, address_ptr],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
 
-        let attributes = &[(
-            Identifier::new(context, "sym_visibility"),
-            StringAttribute::new(context, "private").into(),
-        )];
+    /// Returns the extra gas cost of a CALL sending the value pointed to by `value_ptr`
+    /// to the account at the address pointed to by `address_ptr`.
+    pub(crate) fn call_new_account_cost_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        address_ptr: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::CALL_NEW_ACCOUNT_COST),
+                &[syscall_ctx, address_ptr, value_ptr],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
 
-        // Syscall declarations
-        module.body().append_operation(func::func(
-            context,
-            StringAttribute::new(context, symbols::WRITE_RESULT),
-            TypeAttribute::new(FunctionType::new(context, &[ptr_type, uint32, uint32], &[]).into()),
-            Region::new(),
-            attributes,
+    /// Reads the transient storage slot pointed to by `key_ptr`.
+    /// The value is written to the 256-bit value pointed to by `value_ptr`.
+    pub(crate) fn transient_storage_read_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        key_ptr: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::TRANSIENT_STORAGE_READ),
+            &[syscall_ctx, key_ptr, value_ptr],
+            &[],
             location,
         ));
+    }
 
-        module.body().append_operation(func::func(
-            context,
-            StringAttribute::new(context, symbols::EXTEND_MEMORY),
-            TypeAttribute::new(FunctionType::new(context, &[ptr_type, uint32], &[ptr_type]).into()),
-            Region::new(),
-            attributes,
+    /// Writes the value pointed to by `value_ptr` to the transient storage slot
+    /// pointed to by `key_ptr`.
+    pub(crate) fn transient_storage_write_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        key_ptr: Value,
+        value_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::TRANSIENT_STORAGE_WRITE),
+            &[syscall_ctx, key_ptr, value_ptr],
+            &[],
             location,
         ));
     }
 
-    /// Stores the return values in the syscall context
-    pub(crate) fn write_result_syscall<'c>(
+    /// Emits a new event with the memory segment `[offset, offset + size)` as data.
+    pub(crate) fn append_log_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
         block: &Block,
@@ -150,32 +3637,232 @@ pub(crate) mod mlir {
     ) {
         block.append_operation(func::call(
             mlir_ctx,
-            FlatSymbolRefAttribute::new(mlir_ctx, symbols::WRITE_RESULT),
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::APPEND_LOG),
             &[syscall_ctx, offset, size],
             &[],
             location,
         ));
     }
 
-    /// Extends the memory segment of the syscall context.
-    /// Returns a pointer to the start of the memory segment.
-    pub(crate) fn extend_memory_syscall<'c>(
+    /// Adds the 256-bit value pointed to by `topic_ptr` as a topic of the last emitted event.
+    pub(crate) fn append_log_topic_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &Block,
+        topic_ptr: Value,
+        location: Location,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::APPEND_LOG_TOPIC),
+            &[syscall_ctx, topic_ptr],
+            &[],
+            location,
+        ));
+    }
+
+    /// Returns 1 if the state can't be modified, as inside a static call, or 0 otherwise.
+    pub(crate) fn is_static_syscall<'c>(
         mlir_ctx: &'c MeliorContext,
         syscall_ctx: Value<'c, 'c>,
         block: &'c Block,
-        new_size: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint8 = IntegerType::new(mlir_ctx, 8).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::IS_STATIC),
+                &[syscall_ctx],
+                &[uint8],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Runs the code of the account at the address pointed to by `address_ptr`,
+    /// with at most `gas_limit` gas. The `call_type` decides the context the code runs in,
+    /// and whether the value pointed to by `value_ptr` is transferred.
+    /// The gas left unused by the callee is written to `remaining_gas_ptr`.
+    /// Returns 1 if the call succeeded, or 0 otherwise.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn call_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        call_type: Value<'c, 'c>,
+        gas_limit: Value<'c, 'c>,
+        address_ptr: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        args_offset: Value<'c, 'c>,
+        args_size: Value<'c, 'c>,
+        ret_offset: Value<'c, 'c>,
+        ret_size: Value<'c, 'c>,
+        remaining_gas_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint8 = IntegerType::new(mlir_ctx, 8).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::CALL),
+                &[
+                    syscall_ctx,
+                    call_type,
+                    gas_limit,
+                    address_ptr,
+                    value_ptr,
+                    args_offset,
+                    args_size,
+                    ret_offset,
+                    ret_size,
+                    remaining_gas_ptr,
+                ],
+                &[uint8],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Creates a contract with the memory segment `[offset, offset + size)` as initcode,
+    /// giving it the value pointed to by `value_ptr` and at most `gas_limit` gas.
+    /// The address of the new contract, or zero on failure, is written to `address_ptr`.
+    /// Returns the gas left unused.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        offset: Value<'c, 'c>,
+        size: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        gas_limit: Value<'c, 'c>,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::CREATE),
+                &[syscall_ctx, offset, size, value_ptr, gas_limit, address_ptr],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Like [`create_syscall`], deriving the address from the salt pointed to by `salt_ptr`.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn create2_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        offset: Value<'c, 'c>,
+        size: Value<'c, 'c>,
+        value_ptr: Value<'c, 'c>,
+        salt_ptr: Value<'c, 'c>,
+        gas_limit: Value<'c, 'c>,
+        address_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::CREATE2),
+                &[
+                    syscall_ctx,
+                    offset,
+                    size,
+                    value_ptr,
+                    salt_ptr,
+                    gas_limit,
+                    address_ptr,
+                ],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Sends all the balance of the executing account to the account whose address is
+    /// pointed to by `beneficiary_ptr`, if its dynamic gas cost is at most `remaining_gas`.
+    /// Returns the dynamic gas cost.
+    pub(crate) fn selfdestruct_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        beneficiary_ptr: Value<'c, 'c>,
+        remaining_gas: Value<'c, 'c>,
+        location: Location<'c>,
+    ) -> Result<Value<'c, 'c>, CodegenError> {
+        let uint64 = IntegerType::new(mlir_ctx, 64).into();
+        let value = block
+            .append_operation(func::call(
+                mlir_ctx,
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::SELFDESTRUCT),
+                &[syscall_ctx, beneficiary_ptr, remaining_gas],
+                &[uint64],
+                location,
+            ))
+            .result(0)?;
+        Ok(value.into())
+    }
+
+    /// Creates a context running in the environment pointed to by `env`, with the
+    /// `calldata_len` bytes pointed to by `calldata` as input. Returns the context.
+    pub(crate) fn new_context_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        block: &'c Block,
+        env: Value<'c, 'c>,
+        calldata: Value<'c, 'c>,
+        calldata_len: Value<'c, 'c>,
         location: Location<'c>,
     ) -> Result<Value<'c, 'c>, CodegenError> {
         let ptr_type = pointer(mlir_ctx, 0);
         let value = block
             .append_operation(func::call(
                 mlir_ctx,
-                FlatSymbolRefAttribute::new(mlir_ctx, symbols::EXTEND_MEMORY),
-                &[syscall_ctx, new_size],
+                FlatSymbolRefAttribute::new(mlir_ctx, symbols::NEW_CONTEXT),
+                &[env, calldata, calldata_len],
                 &[ptr_type],
                 location,
             ))
             .result(0)?;
         Ok(value.into())
     }
+
+    /// Releases a context created with [`new_context_syscall`], writing the gas left to
+    /// `gas_ptr` and the output to the buffer of `output_capacity` bytes at `output_ptr`.
+    /// The size of the output is written to `output_len_ptr`
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn finish_context_syscall<'c>(
+        mlir_ctx: &'c MeliorContext,
+        syscall_ctx: Value<'c, 'c>,
+        block: &'c Block,
+        gas_ptr: Value<'c, 'c>,
+        output_ptr: Value<'c, 'c>,
+        output_capacity: Value<'c, 'c>,
+        output_len_ptr: Value<'c, 'c>,
+        location: Location<'c>,
+    ) {
+        block.append_operation(func::call(
+            mlir_ctx,
+            FlatSymbolRefAttribute::new(mlir_ctx, symbols::FINISH_CONTEXT),
+            &[
+                syscall_ctx,
+                gas_ptr,
+                output_ptr,
+                output_capacity,
+                output_len_ptr,
+            ],
+            &[],
+            location,
+        ));
+    }
 }
@@ -0,0 +1,113 @@
+//! # Execution traces
+//!
+//! A program compiled in instrumented mode reports each opcode to its
+//! [`SyscallContext`] right before executing it. When tracing is enabled, a [`Tracer`]
+//! keeps each report as a [`TraceStep`], which can be written as a line of JSON
+//! in the format defined by [EIP-3155](https://eips.ethereum.org/EIPS/eip-3155).
+//!
+//! This is the same output given by `evm t8n --trace`, so traces can be compared
+//! line by line with other clients.
+use std::io;
+
+use crate::{
+    inspector::Inspector,
+    program::Opcode,
+    syscall::{SyscallContext, U256},
+};
+
+/// The state of the EVM right before executing an opcode
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceStep {
+    /// The offset of the opcode in the bytecode
+    pub pc: u64,
+    /// The opcode executed
+    pub op: u8,
+    /// The gas left before executing the opcode
+    pub gas: u64,
+    /// The gas consumed by the opcode, including the gas used by the calls it makes.
+    /// An opcode halting exceptionally consumes all the gas left
+    pub gas_cost: u64,
+    /// The size of the memory, in bytes
+    pub mem_size: u64,
+    /// The stack, from bottom to top
+    pub stack: Vec<U256>,
+    /// The depth of the call executing the opcode, which is 1 for the outermost call
+    pub depth: usize,
+    /// The gas to be refunded at the end of the transaction, before capping it
+    pub refund: i64,
+}
+
+impl TraceStep {
+    /// Returns the step as a line of JSON, following EIP-3155.
+    /// The line doesn't include a trailing newline
+    pub fn to_json(&self) -> String {
+        let stack: Vec<String> = self
+            .stack
+            .iter()
+            .map(|value| format!("\"{value:#x}\""))
+            .collect();
+        format!(
+            "{{\"pc\":{},\"op\":{},\"gas\":\"{:#x}\",\"gasCost\":\"{:#x}\",\"memSize\":{},\
+             \"stack\":[{}],\"depth\":{},\"refund\":{},\"opName\":\"{}\"}}",
+            self.pc,
+            self.op,
+            self.gas,
+            self.gas_cost,
+            self.mem_size,
+            stack.join(","),
+            self.depth,
+            self.refund,
+            self.op_name(),
+        )
+    }
+
+    /// Returns the mnemonic of the opcode executed
+    pub fn op_name(&self) -> String {
+        match Opcode::from(self.op) {
+            Opcode::UNUSED => format!("opcode {:#x} not defined", self.op),
+            opcode => format!("{opcode:?}"),
+        }
+    }
+}
+
+/// An [`Inspector`] recording every step executed, in order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tracer {
+    steps: Vec<TraceStep>,
+    /// The indices of the steps being executed, one for each nested call.
+    /// A step is finished before the next one in its call starts, so the last one
+    /// is always the innermost.
+    unfinished: Vec<usize>,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the steps recorded so far
+    pub fn steps(&self) -> &[TraceStep] {
+        &self.steps
+    }
+}
+
+impl Inspector for Tracer {
+    fn step(&mut self, step: &TraceStep, _context: &SyscallContext) {
+        self.unfinished.push(self.steps.len());
+        self.steps.push(step.clone());
+    }
+
+    fn step_end(&mut self, step: &TraceStep, _context: &SyscallContext) {
+        if let Some(index) = self.unfinished.pop() {
+            self.steps[index].gas_cost = step.gas_cost;
+        }
+    }
+}
+
+/// Writes the steps as lines of JSON, following EIP-3155
+pub fn write_trace(steps: &[TraceStep], mut writer: impl io::Write) -> io::Result<()> {
+    for step in steps {
+        writeln!(writer, "{}", step.to_json())?;
+    }
+    Ok(())
+}
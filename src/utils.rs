@@ -1,11 +1,11 @@
 use melior::{
     dialect::{
         arith, func,
-        llvm::{self, r#type::pointer, LoadStoreOptions},
+        llvm::{self, r#type::pointer, AllocaOptions, LoadStoreOptions},
         ods,
     },
     ir::{
-        attribute::{DenseI32ArrayAttribute, IntegerAttribute},
+        attribute::{DenseI32ArrayAttribute, IntegerAttribute, TypeAttribute},
         operation::OperationResult,
         r#type::IntegerType,
         Block, Location, Value,
@@ -16,8 +16,8 @@ use melior::{
 use crate::{
     codegen::context::OperationCtx,
     constants::{
-        GAS_COUNTER_GLOBAL, MAX_STACK_SIZE, MEMORY_PTR_GLOBAL, MEMORY_SIZE_GLOBAL,
-        REVERT_EXIT_CODE, STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL,
+        gas_cost, ExitStatusCode, GAS_COUNTER_GLOBAL, MAX_MEMORY_SIZE, MAX_STACK_SIZE,
+        MEMORY_PTR_GLOBAL, MEMORY_SIZE_GLOBAL, STACK_BASEPTR_GLOBAL, STACK_PTR_GLOBAL,
     },
     errors::CodegenError,
 };
@@ -27,6 +27,29 @@ pub fn consume_gas<'ctx>(
     context: &'ctx MeliorContext,
     block: &'ctx Block,
     amount: i64,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint64 = IntegerType::new(context, 64).into();
+
+    let gas_value = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64, amount).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    consume_gas_as_value(context, block, gas_value)
+}
+
+/// Consumes an amount of gas only known at runtime, given as an `i64` value.
+/// Returns true if there is enough Gas. Otherwise the gas counter is left negative,
+/// which the revert block reports as [`ExitStatusCode::OutOfGas`]
+pub fn consume_gas_as_value<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+    gas_value: Value<'ctx, 'ctx>,
 ) -> Result<Value<'ctx, 'ctx>, CodegenError> {
     let location = Location::unknown(context);
     let ptr_type = pointer(context, 0);
@@ -54,15 +77,6 @@ pub fn consume_gas<'ctx>(
         .result(0)?
         .into();
 
-    let gas_value = block
-        .append_operation(arith::constant(
-            context,
-            IntegerAttribute::new(uint64, amount).into(),
-            location,
-        ))
-        .result(0)?
-        .into();
-
     // Check that gas_counter >= gas_value
     let flag = block
         .append_operation(arith::cmpi(
@@ -91,12 +105,52 @@ pub fn consume_gas<'ctx>(
     Ok(flag.into())
 }
 
-pub fn get_remaining_gas<'ctx>(
+/// Sets the gas counter to zero, consuming all the remaining gas
+pub fn consume_all_gas<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+    let uint64 = IntegerType::new(context, 64).into();
+
+    let gas_counter_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            GAS_COUNTER_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64, 0).into(),
+            location,
+        ))
+        .result(0)?;
+
+    let res = block.append_operation(llvm::store(
+        context,
+        zero.into(),
+        gas_counter_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+    assert!(res.verify());
+
+    Ok(())
+}
+
+/// Returns the remaining gas, as the `i64` value held by the gas counter
+pub fn get_gas_counter<'ctx>(
     context: &'ctx MeliorContext,
     block: &'ctx Block,
 ) -> Result<Value<'ctx, 'ctx>, CodegenError> {
     let location = Location::unknown(context);
     let ptr_type = pointer(context, 0);
+    let uint64 = IntegerType::new(context, 64);
 
     // Get address of gas counter global
     let gas_counter_ptr = block
@@ -109,11 +163,13 @@ pub fn get_remaining_gas<'ctx>(
         .result(0)?;
 
     // Load gas counter
+    // NOTE: the gas counter is 64 bits wide, so loading it as a wider type
+    // would read past the end of the global
     let gas_counter = block
         .append_operation(llvm::load(
             context,
             gas_counter_ptr.into(),
-            IntegerType::new(context, 256).into(),
+            uint64.into(),
             location,
             LoadStoreOptions::default(),
         ))
@@ -123,6 +179,92 @@ pub fn get_remaining_gas<'ctx>(
     Ok(gas_counter)
 }
 
+/// Returns the remaining gas, extended to 256 bits
+pub fn get_remaining_gas<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint256 = IntegerType::new(context, 256);
+
+    let gas_counter = get_gas_counter(context, block)?;
+
+    let gas_counter = block
+        .append_operation(arith::extui(gas_counter, uint256.into(), location))
+        .result(0)?
+        .into();
+
+    Ok(gas_counter)
+}
+
+/// Returns all but one 64th of `gas`, given as an `i64` value.
+/// This is the most gas a subcall can be given (EIP-150)
+pub fn all_but_one_64th<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+    gas: Value<'ctx, 'ctx>,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint64 = IntegerType::new(context, 64);
+
+    let divisor = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), 64).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let one_64th = block
+        .append_operation(arith::divui(gas, divisor, location))
+        .result(0)?
+        .into();
+
+    let value = block
+        .append_operation(arith::subi(gas, one_64th, location))
+        .result(0)?
+        .into();
+
+    Ok(value)
+}
+
+/// Adds back to the gas counter an amount of gas only known at runtime, given as an
+/// `i64` value. This is used for taking back the gas a subcall left unused
+pub fn return_unused_gas<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+    gas_value: Value<'ctx, 'ctx>,
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+
+    let gas_counter = get_gas_counter(context, block)?;
+
+    let new_gas_counter = block
+        .append_operation(arith::addi(gas_counter, gas_value, location))
+        .result(0)?;
+
+    let gas_counter_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            GAS_COUNTER_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+
+    block.append_operation(llvm::store(
+        context,
+        new_gas_counter.into(),
+        gas_counter_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+
+    Ok(())
+}
+
 pub fn stack_pop<'ctx>(
     context: &'ctx MeliorContext,
     block: &'ctx Block,
@@ -367,13 +509,15 @@ pub fn swap_stack_elements<'ctx>(
     Ok(())
 }
 
-/// Generates code for checking if the stack has enough space for `element_count` more elements.
+/// Generates code for checking if the stack has enough space for `element_count` more elements,
+/// holding at most `stack_limit` elements.
 pub fn check_stack_has_space_for<'ctx>(
     context: &'ctx MeliorContext,
     block: &'ctx Block,
     element_count: u32,
+    stack_limit: usize,
 ) -> Result<Value<'ctx, 'ctx>, CodegenError> {
-    debug_assert!(element_count < MAX_STACK_SIZE as u32);
+    debug_assert!((element_count as usize) < stack_limit);
     let location = Location::unknown(context);
     let ptr_type = pointer(context, 0);
     let uint256 = IntegerType::new(context, 256);
@@ -420,19 +564,19 @@ pub fn check_stack_has_space_for<'ctx>(
         ))
         .result(0)?;
 
-    // Compare `subtracted_stack_ptr = stack_ptr + element_count - MAX_STACK_SIZE`
+    // Compare `subtracted_stack_ptr = stack_ptr + element_count - stack_limit`
     let subtracted_stack_ptr = block
         .append_operation(llvm::get_element_ptr(
             context,
             stack_ptr.into(),
-            DenseI32ArrayAttribute::new(context, &[element_count as i32 - MAX_STACK_SIZE as i32]),
+            DenseI32ArrayAttribute::new(context, &[element_count as i32 - stack_limit as i32]),
             uint256.into(),
             ptr_type,
             location,
         ))
         .result(0)?;
 
-    // Compare `stack_ptr + element_count - MAX_STACK_SIZE <= stack_baseptr`
+    // Compare `stack_ptr + element_count - stack_limit <= stack_baseptr`
     let flag = block
         .append_operation(
             ods::llvm::icmp(
@@ -462,7 +606,6 @@ pub fn check_stack_has_at_least<'ctx>(
     block: &'ctx Block,
     element_count: u32,
 ) -> Result<Value<'ctx, 'ctx>, CodegenError> {
-    debug_assert!(element_count < MAX_STACK_SIZE as u32);
     let location = Location::unknown(context);
     let ptr_type = pointer(context, 0);
     let uint256 = IntegerType::new(context, 256);
@@ -566,16 +709,82 @@ pub fn check_is_greater_than<'ctx>(
 }
 
 pub fn generate_revert_block(context: &MeliorContext) -> Result<Block, CodegenError> {
-    // TODO: return result via write_result syscall
     let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
     let uint8 = IntegerType::new(context, 8);
+    let uint64 = IntegerType::new(context, 64);
 
     let revert_block = Block::new(&[]);
 
-    let constant_value = IntegerAttribute::new(uint8.into(), REVERT_EXIT_CODE as _).into();
+    // This block handles exceptional halts, which don't return any data.
+    // Gas is charged before each operation, and a failed charge leaves the
+    // gas counter negative, which tells running out of gas apart from the
+    // other exceptional halts (e.g. stack underflow)
+    let gas_counter_ptr = revert_block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            GAS_COUNTER_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+
+    let gas_counter = revert_block
+        .append_operation(llvm::load(
+            context,
+            gas_counter_ptr.into(),
+            uint64.into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
+
+    let zero = revert_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let out_of_gas = revert_block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Slt,
+            gas_counter,
+            zero,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let out_of_gas_code = revert_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), ExitStatusCode::OutOfGas.to_u8() as _).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let error_code = revert_block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), ExitStatusCode::Error.to_u8() as _).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
 
     let exit_code = revert_block
-        .append_operation(arith::constant(context, constant_value, location))
+        .append_operation(arith::select(
+            out_of_gas,
+            out_of_gas_code,
+            error_code,
+            location,
+        ))
         .result(0)?
         .into();
 
@@ -622,67 +831,719 @@ pub fn check_if_zero<'ctx>(
     Ok(flag.into())
 }
 
-/// Wrapper for calling the [`extend_memory`](crate::syscall::SyscallContext::extend_memory) syscall.
-pub(crate) fn extend_memory<'c>(
-    op_ctx: &'c OperationCtx,
-    block: &'c Block,
-    new_size: Value<'c, 'c>,
-) -> Result<Value<'c, 'c>, CodegenError> {
-    let context = op_ctx.mlir_context;
+/// Generates code for computing a denominator for signed division that avoids the
+/// `MIN_I256 / -1` overflow, which is undefined behavior in LLVM.
+/// Returns 1 when `num == MIN_I256` and `den == -1`, and `den` otherwise.
+pub fn signed_division_safe_denominator<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+    num: Value<'ctx, 'ctx>,
+    den: Value<'ctx, 'ctx>,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
     let location = Location::unknown(context);
 
-    let ptr_type = pointer(context, 0);
+    let one = constant_value_from_i64(context, block, 1)?;
+    let max_shift = constant_value_from_i64(context, block, 255)?;
 
-    let memory_ptr = op_ctx.extend_memory_syscall(block, new_size, location)?;
+    // MIN_I256 = 1 << 255
+    let min_value = block
+        .append_operation(arith::shli(one, max_shift, location))
+        .result(0)?
+        .into();
 
-    let memory_size_ptr = block
-        .append_operation(llvm_mlir::addressof(
+    // -1 = MIN_I256 >> 255 (arithmetic shift)
+    let minus_one = block
+        .append_operation(arith::shrsi(min_value, max_shift, location))
+        .result(0)?
+        .into();
+
+    let num_is_min = block
+        .append_operation(arith::cmpi(
             context,
-            MEMORY_SIZE_GLOBAL,
-            ptr_type,
+            arith::CmpiPredicate::Eq,
+            num,
+            min_value,
             location,
         ))
-        .result(0)?;
+        .result(0)?
+        .into();
 
-    let res = block.append_operation(llvm::store(
-        context,
-        new_size,
-        memory_size_ptr.into(),
-        location,
-        LoadStoreOptions::default(),
-    ));
-    assert!(res.verify());
+    let den_is_minus_one = block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Eq,
+            den,
+            minus_one,
+            location,
+        ))
+        .result(0)?
+        .into();
 
-    let memory_ptr_ptr = block
+    let overflows = block
+        .append_operation(arith::andi(num_is_min, den_is_minus_one, location))
+        .result(0)?
+        .into();
+
+    let safe_den = block
+        .append_operation(arith::select(overflows, one, den, location))
+        .result(0)?
+        .into();
+
+    Ok(safe_den)
+}
+
+/// Loads the current size of the memory, in bytes, as an i32
+pub fn get_memory_size<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+
+    let memory_size_ptr = block
         .append_operation(llvm_mlir::addressof(
             context,
-            MEMORY_PTR_GLOBAL,
+            MEMORY_SIZE_GLOBAL,
             ptr_type,
             location,
         ))
         .result(0)?;
 
-    let res = block.append_operation(llvm::store(
-        context,
-        memory_ptr,
-        memory_ptr_ptr.into(),
-        location,
-        LoadStoreOptions::default(),
-    ));
-    assert!(res.verify());
+    let memory_size = block
+        .append_operation(llvm::load(
+            context,
+            memory_size_ptr.into(),
+            IntegerType::new(context, 32).into(),
+            location,
+            LoadStoreOptions::default(),
+        ))
+        .result(0)?
+        .into();
 
-    Ok(memory_ptr)
+    Ok(memory_size)
 }
 
-pub fn integer_constant_from_i64(context: &MeliorContext, value: i64) -> IntegerAttribute {
-    let uint256 = IntegerType::new(context, 256);
-    IntegerAttribute::new(uint256.into(), value)
+/// A segment of the memory given to an opcode, with its bounds checked before truncating
+/// them to 32 bits. See [`memory_segment`]
+#[derive(Clone, Copy)]
+pub(crate) struct MemorySegment<'c> {
+    /// The segment's offset, as an `i32` value. It's zero for an empty segment
+    pub offset: Value<'c, 'c>,
+    /// The segment's size, as an `i32` value
+    pub size: Value<'c, 'c>,
+    /// The size the memory needs for fitting the segment, as an `i32` value: the segment's
+    /// end, or zero for an empty segment, which doesn't touch the memory
+    pub required_size: Value<'c, 'c>,
+    /// Whether the segment ends within [`MAX_MEMORY_SIZE`]. The values above are only
+    /// meaningful if it does
+    pub fits: Value<'c, 'c>,
 }
 
-pub fn integer_constant_from_i8(context: &MeliorContext, value: i8) -> IntegerAttribute {
-    let int8 = IntegerType::new(context, 8);
-    IntegerAttribute::new(int8.into(), value.into())
-}
+/// Checks the memory segment `[offset, offset + size)` given to an opcode as 256-bit values,
+/// and truncates its bounds to 32 bits.
+///
+/// An empty segment fits wherever its offset is. Any other one has to end within
+/// [`MAX_MEMORY_SIZE`], since no program could pay for a bigger memory:
+/// [`consume_memory_expansion_gas`] runs out of gas for the segments that don't fit
+pub(crate) fn memory_segment<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+    offset: Value<'c, 'c>,
+    size: Value<'c, 'c>,
+) -> Result<MemorySegment<'c>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+
+    // The high bits are checked before truncating, so that no offset nor size wraps around
+    let u32_max = constant_value_from_i64(context, block, u32::MAX as i64)?;
+    let offset_fits = block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            offset,
+            u32_max,
+            location,
+        ))
+        .result(0)?
+        .into();
+    let size_fits = block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            size,
+            u32_max,
+            location,
+        ))
+        .result(0)?
+        .into();
+    let zero = constant_value_from_i64(context, block, 0)?;
+    let is_empty = block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Eq,
+            size,
+            zero,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let offset = block
+        .append_operation(arith::trunci(offset, uint64.into(), location))
+        .result(0)?
+        .into();
+    let size = block
+        .append_operation(arith::trunci(size, uint64.into(), location))
+        .result(0)?
+        .into();
+    // Both fit in 32 bits, so their sum can't overflow 64 bits
+    let end = block
+        .append_operation(arith::addi(offset, size, location))
+        .result(0)?
+        .into();
+    let max_size = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), MAX_MEMORY_SIZE).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let end_fits = block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Ule,
+            end,
+            max_size,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let bounds_fit = block
+        .append_operation(arith::andi(offset_fits, size_fits, location))
+        .result(0)?
+        .into();
+    let fits = block
+        .append_operation(arith::andi(bounds_fit, end_fits, location))
+        .result(0)?
+        .into();
+    let fits = block
+        .append_operation(arith::ori(is_empty, fits, location))
+        .result(0)?
+        .into();
+
+    // The offset of an empty segment may be anything, so it's left out
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let offset = block
+        .append_operation(arith::select(is_empty, zero, offset, location))
+        .result(0)?
+        .into();
+    let end = block
+        .append_operation(arith::select(is_empty, zero, end, location))
+        .result(0)?
+        .into();
+
+    let [offset, size, required_size] = [offset, size, end].map(|value| {
+        block
+            .append_operation(arith::trunci(value, uint32.into(), location))
+            .result(0)
+            .map(Value::from)
+    });
+
+    Ok(MemorySegment {
+        offset: offset?,
+        size: size?,
+        required_size: required_size?,
+        fits,
+    })
+}
+
+/// Consumes the gas needed for expanding the memory to fit all the `segments`. Returns true
+/// if there is enough Gas, along with the size the memory has to be expanded to, as an
+/// `i32` value
+///
+/// The cost of the memory grows quadratically with its size in words
+/// (`MEMORY_WORD * words + words² / MEMORY_QUADRATIC_DIVISOR`), and expanding it charges
+/// the difference between the costs of its new and current sizes.
+/// If the memory is already big enough, this costs nothing. If any segment doesn't fit,
+/// all the gas is consumed, so the program halts out of gas.
+pub(crate) fn consume_memory_expansion_gas<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+    segments: &[MemorySegment<'c>],
+) -> Result<(Value<'c, 'c>, Value<'c, 'c>), CodegenError> {
+    let location = Location::unknown(context);
+    let uint64 = IntegerType::new(context, 64);
+
+    let (first, rest) = segments
+        .split_first()
+        .expect("the memory is expanded for some segment");
+    let mut required_size = first.required_size;
+    let mut fits = first.fits;
+    for segment in rest {
+        required_size = block
+            .append_operation(arith::maxui(required_size, segment.required_size, location))
+            .result(0)?
+            .into();
+        fits = block
+            .append_operation(arith::andi(fits, segment.fits, location))
+            .result(0)?
+            .into();
+    }
+
+    // NOTE: the sizes are extended to 64 bits, so the squared word count can't overflow
+    let required_size_64 = block
+        .append_operation(arith::extui(required_size, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let current_size = get_memory_size(context, block)?;
+    let current_size = block
+        .append_operation(arith::extui(current_size, uint64.into(), location))
+        .result(0)?
+        .into();
+
+    let word_size = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // required_words = ceil(required_size / 32)
+    let required_words = block
+        .append_operation(arith::ceildivui(required_size_64, word_size, location))
+        .result(0)?
+        .into();
+
+    // The memory size is always a multiple of 32 bytes
+    let current_words = block
+        .append_operation(arith::divui(current_size, word_size, location))
+        .result(0)?
+        .into();
+
+    // The memory never shrinks
+    let new_words = block
+        .append_operation(arith::maxui(current_words, required_words, location))
+        .result(0)?
+        .into();
+
+    let new_cost = memory_cost(context, block, new_words)?;
+    let current_cost = memory_cost(context, block, current_words)?;
+
+    let expansion_cost = block
+        .append_operation(arith::subi(new_cost, current_cost, location))
+        .result(0)?
+        .into();
+
+    // A segment that doesn't fit costs more than any gas left
+    let unaffordable_cost = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), i64::MAX).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+    let expansion_cost = block
+        .append_operation(arith::select(
+            fits,
+            expansion_cost,
+            unaffordable_cost,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let gas_flag = consume_gas_as_value(context, block, expansion_cost)?;
+    let flag = block
+        .append_operation(arith::andi(gas_flag, fits, location))
+        .result(0)?
+        .into();
+
+    Ok((flag, required_size))
+}
+
+/// Returns the total cost of a memory of `words` 32-byte words, given as an `i64` value
+fn memory_cost<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+    words: Value<'c, 'c>,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint64 = IntegerType::new(context, 64);
+
+    let word_cost = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::MEMORY_WORD).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let quadratic_divisor = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint64.into(), gas_cost::MEMORY_QUADRATIC_DIVISOR).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // linear_cost = words * MEMORY_WORD
+    let linear_cost = block
+        .append_operation(arith::muli(words, word_cost, location))
+        .result(0)?
+        .into();
+
+    // quadratic_cost = words² / MEMORY_QUADRATIC_DIVISOR
+    let words_squared = block
+        .append_operation(arith::muli(words, words, location))
+        .result(0)?
+        .into();
+
+    let quadratic_cost = block
+        .append_operation(arith::divui(words_squared, quadratic_divisor, location))
+        .result(0)?
+        .into();
+
+    let cost = block
+        .append_operation(arith::addi(linear_cost, quadratic_cost, location))
+        .result(0)?
+        .into();
+
+    Ok(cost)
+}
+
+/// Wrapper for calling the [`extend_memory`](crate::syscall::SyscallContext::extend_memory) syscall.
+///
+/// The memory is expanded in 32-byte words, so the requested size is rounded up
+/// to the next multiple of 32. The memory never shrinks: if it's already big enough,
+/// its size is left untouched.
+pub(crate) fn extend_memory<'c>(
+    op_ctx: &'c OperationCtx,
+    block: &'c Block,
+    required_size: Value<'c, 'c>,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = Location::unknown(context);
+
+    let ptr_type = pointer(context, 0);
+    let uint32 = IntegerType::new(context, 32);
+
+    let word_size = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 32).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    // required_words = ceil(required_size / 32)
+    let required_words = block
+        .append_operation(arith::ceildivui(required_size, word_size, location))
+        .result(0)?
+        .into();
+
+    // rounded_size = required_words * 32
+    let rounded_size = block
+        .append_operation(arith::muli(required_words, word_size, location))
+        .result(0)?
+        .into();
+
+    let current_size = get_memory_size(context, block)?;
+
+    // new_size = max(current_size, rounded_size)
+    let new_size = block
+        .append_operation(arith::maxui(current_size, rounded_size, location))
+        .result(0)?
+        .into();
+
+    let memory_ptr = op_ctx.extend_memory_syscall(block, new_size, location)?;
+
+    let memory_size_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            MEMORY_SIZE_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+
+    let res = block.append_operation(llvm::store(
+        context,
+        new_size,
+        memory_size_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+    assert!(res.verify());
+
+    let memory_ptr_ptr = block
+        .append_operation(llvm_mlir::addressof(
+            context,
+            MEMORY_PTR_GLOBAL,
+            ptr_type,
+            location,
+        ))
+        .result(0)?;
+
+    let res = block.append_operation(llvm::store(
+        context,
+        memory_ptr,
+        memory_ptr_ptr.into(),
+        location,
+        LoadStoreOptions::default(),
+    ));
+    assert!(res.verify());
+
+    Ok(memory_ptr)
+}
+
+/// The globals holding the state of the running program, along with the types they hold
+fn program_state_globals(context: &MeliorContext) -> [(&'static str, melior::ir::Type<'_>); 5] {
+    let ptr_type = pointer(context, 0);
+    [
+        (GAS_COUNTER_GLOBAL, IntegerType::new(context, 64).into()),
+        (STACK_BASEPTR_GLOBAL, ptr_type),
+        (STACK_PTR_GLOBAL, ptr_type),
+        (MEMORY_PTR_GLOBAL, ptr_type),
+        (MEMORY_SIZE_GLOBAL, IntegerType::new(context, 32).into()),
+    ]
+}
+
+/// Loads the globals holding the state of the running program: its gas counter, and where
+/// its stack and memory are. A call or a creation running the same compiled code again,
+/// like a contract calling itself through a contract cache, overwrites them, so they're
+/// saved before its syscall and stored back after it with [`restore_program_state`]
+pub(crate) fn save_program_state<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+) -> Result<Vec<Value<'c, 'c>>, CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+
+    let mut state = Vec::new();
+    for (global, global_type) in program_state_globals(context) {
+        let global_ptr = block
+            .append_operation(llvm_mlir::addressof(context, global, ptr_type, location))
+            .result(0)?;
+        let value = block
+            .append_operation(llvm::load(
+                context,
+                global_ptr.into(),
+                global_type,
+                location,
+                LoadStoreOptions::default(),
+            ))
+            .result(0)?
+            .into();
+        state.push(value);
+    }
+
+    Ok(state)
+}
+
+/// Stores the state loaded by [`save_program_state`] back into its globals
+pub(crate) fn restore_program_state<'c>(
+    context: &'c MeliorContext,
+    block: &'c Block,
+    state: &[Value<'c, 'c>],
+) -> Result<(), CodegenError> {
+    let location = Location::unknown(context);
+    let ptr_type = pointer(context, 0);
+
+    for ((global, _), value) in program_state_globals(context).into_iter().zip(state) {
+        let global_ptr = block
+            .append_operation(llvm_mlir::addressof(context, global, ptr_type, location))
+            .result(0)?;
+        let res = block.append_operation(llvm::store(
+            context,
+            *value,
+            global_ptr.into(),
+            location,
+            LoadStoreOptions::default(),
+        ));
+        assert!(res.verify());
+    }
+
+    Ok(())
+}
+
+/// Returns true if the state can be modified, which isn't the case inside a static call
+pub(crate) fn check_not_static<'c>(
+    op_ctx: &'c OperationCtx,
+    block: &'c Block,
+) -> Result<Value<'c, 'c>, CodegenError> {
+    let context = op_ctx.mlir_context;
+    let location = Location::unknown(context);
+    let uint8 = IntegerType::new(context, 8);
+
+    let is_static = op_ctx.is_static_syscall(block, location)?;
+
+    let zero = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint8.into(), 0).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let flag = block
+        .append_operation(arith::cmpi(
+            context,
+            arith::CmpiPredicate::Eq,
+            is_static,
+            zero,
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    Ok(flag)
+}
+
+/// Truncates a 256-bit value to 32 bits, saturating at `u32::MAX`
+///
+/// This is useful for offsets into buffers that are read with zero-padding,
+/// like the calldata: any offset past `u32::MAX` reads the same as `u32::MAX`.
+pub fn saturating_trunc_to_u32<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+    value: Value<'ctx, 'ctx>,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint32 = IntegerType::new(context, 32);
+
+    let u32_max = constant_value_from_i64(context, block, u32::MAX as i64)?;
+
+    let value = block
+        .append_operation(arith::minui(value, u32_max, location))
+        .result(0)?
+        .into();
+
+    let value = block
+        .append_operation(arith::trunci(value, uint32.into(), location))
+        .result(0)?
+        .into();
+
+    Ok(value)
+}
+
+/// Allocates space for a single 256-bit value in the native stack.
+/// Returns a pointer to it, to be passed to syscalls that write a 256-bit value.
+pub fn allocate_u256<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint32 = IntegerType::new(context, 32);
+    let uint256 = IntegerType::new(context, 256);
+    let ptr_type = pointer(context, 0);
+
+    let one = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 1).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let ptr = block
+        .append_operation(llvm::alloca(
+            context,
+            one,
+            ptr_type,
+            location,
+            AllocaOptions::new().elem_type(Some(TypeAttribute::new(uint256.into()))),
+        ))
+        .result(0)?
+        .into();
+
+    Ok(ptr)
+}
+
+/// Allocates space for a single 64-bit value in the native stack.
+/// Returns a pointer to it, to be passed to syscalls that write a 64-bit value.
+pub fn allocate_u64<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+    let uint32 = IntegerType::new(context, 32);
+    let uint64 = IntegerType::new(context, 64);
+    let ptr_type = pointer(context, 0);
+
+    let one = block
+        .append_operation(arith::constant(
+            context,
+            IntegerAttribute::new(uint32.into(), 1).into(),
+            location,
+        ))
+        .result(0)?
+        .into();
+
+    let ptr = block
+        .append_operation(llvm::alloca(
+            context,
+            one,
+            ptr_type,
+            location,
+            AllocaOptions::new().elem_type(Some(TypeAttribute::new(uint64.into()))),
+        ))
+        .result(0)?
+        .into();
+
+    Ok(ptr)
+}
+
+/// Allocates space for a 256-bit value in the native stack, and stores `value` in it.
+/// Returns a pointer to it, to be passed to syscalls that read a 256-bit value.
+pub fn allocate_and_store_u256<'ctx>(
+    context: &'ctx MeliorContext,
+    block: &'ctx Block,
+    value: Value<'ctx, 'ctx>,
+) -> Result<Value<'ctx, 'ctx>, CodegenError> {
+    let location = Location::unknown(context);
+
+    let ptr = allocate_u256(context, block)?;
+
+    let res = block.append_operation(llvm::store(
+        context,
+        value,
+        ptr,
+        location,
+        LoadStoreOptions::default(),
+    ));
+    assert!(res.verify());
+
+    Ok(ptr)
+}
+
+pub fn integer_constant_from_i64(context: &MeliorContext, value: i64) -> IntegerAttribute {
+    let uint256 = IntegerType::new(context, 256);
+    IntegerAttribute::new(uint256.into(), value)
+}
+
+pub fn integer_constant_from_i8(context: &MeliorContext, value: i8) -> IntegerAttribute {
+    let int8 = IntegerType::new(context, 8);
+    IntegerAttribute::new(int8.into(), value.into())
+}
 
 pub mod llvm_mlir {
     use melior::{
@@ -690,7 +1551,7 @@ pub mod llvm_mlir {
         ir::{
             attribute::{FlatSymbolRefAttribute, StringAttribute, TypeAttribute},
             operation::OperationBuilder,
-            Identifier, Location, Region,
+            Attribute, Identifier, Location, Region,
         },
         Context as MeliorContext,
     };
@@ -722,6 +1583,36 @@ pub mod llvm_mlir {
             .expect("valid operation")
     }
 
+    /// Like [`global`], but with an initial value instead of being left uninitialized
+    pub fn initialized_global<'c>(
+        context: &'c MeliorContext,
+        name: &str,
+        global_type: melior::ir::Type<'c>,
+        value: Attribute<'c>,
+        location: Location<'c>,
+    ) -> melior::ir::Operation<'c> {
+        // TODO: use ODS
+        OperationBuilder::new("llvm.mlir.global", location)
+            .add_regions([Region::new()])
+            .add_attributes(&[
+                (
+                    Identifier::new(context, "sym_name"),
+                    StringAttribute::new(context, name).into(),
+                ),
+                (
+                    Identifier::new(context, "global_type"),
+                    TypeAttribute::new(global_type).into(),
+                ),
+                (Identifier::new(context, "value"), value),
+                (
+                    Identifier::new(context, "linkage"),
+                    llvm::attributes::linkage(context, Linkage::Internal),
+                ),
+            ])
+            .build()
+            .expect("valid operation")
+    }
+
     pub fn addressof<'c>(
         context: &'c MeliorContext,
         name: &str,
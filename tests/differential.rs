@@ -0,0 +1,296 @@
+#![cfg(feature = "differential")]
+
+use evm_mlir::{
+    db::AccountInfo,
+    differential::{DifferentialTest, DivergenceKind},
+    precompiles::Precompile,
+    program::{Operation, Program},
+    syscall::U256,
+};
+use num_bigint::BigUint;
+
+const GAS_LIMIT: u64 = 1_000_000;
+
+fn call_test(program: Vec<Operation>, calldata: Vec<u8>) -> DifferentialTest {
+    let code = Program::from(program).to_bytecode();
+    DifferentialTest::call(code, calldata, GAS_LIMIT)
+}
+
+fn assert_agrees(test: &DifferentialTest) {
+    if let Err(divergence) = test.run() {
+        let mut report = Vec::new();
+        divergence.write_report(&mut report).unwrap();
+        panic!("{}", String::from_utf8_lossy(&report));
+    }
+}
+
+#[test]
+fn returning_calldata_agrees() {
+    let program = vec![
+        Operation::Push0,
+        Operation::CallDataLoad,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Add,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let test = call_test(program, vec![0xAB; 32]);
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn storage_changes_agree() {
+    let program = vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+        // Clearing a slot set before the transaction gives a refund
+        Operation::Push0,
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Sstore,
+    ];
+    let mut test = call_test(program, Vec::new());
+    test.storage = vec![(DifferentialTest::CONTRACT, U256::from(2), U256::from(7))];
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn revert_agrees() {
+    let program = vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Revert,
+    ];
+    let test = call_test(program, Vec::new());
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn exceptional_halt_agrees() {
+    let program = vec![Operation::Push0, Operation::Jump];
+    let test = call_test(program, Vec::new());
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn logs_agree() {
+    let program = vec![
+        Operation::Push(BigUint::from(0xFF_u8)),
+        Operation::Push0,
+        Operation::Mstore8,
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Log(1),
+    ];
+    let test = call_test(program, Vec::new());
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn paid_transfer_agrees() {
+    let mut test = call_test(vec![Operation::Stop], Vec::new());
+    test.env.block.basefee = U256::from(7);
+    test.env.block.coinbase = [0x30; 20];
+    test.env.tx.gas_price = U256::from(10);
+    test.env.tx.value = U256::from(1000);
+    let sender = AccountInfo {
+        balance: U256::from(1e9 as u128 * 10),
+        ..Default::default()
+    };
+    test.accounts.push((DifferentialTest::SENDER, sender));
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn contract_creation_agrees() {
+    // Deploys a single STOP
+    let init_code = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Mstore8,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let mut test = call_test(Vec::new(), Vec::new());
+    test.env.tx.to = None;
+    test.env.tx.data = Program::from(init_code).to_bytecode();
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn cold_and_warm_slot_accesses_agree() {
+    let program = vec![
+        // The first access to a slot is cold, and the next ones are warm
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Sstore,
+        // The slots in the access list are warm from the start
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Sload,
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Push(BigUint::from(6_u8)),
+        Operation::Sstore,
+    ];
+    let mut test = call_test(program, Vec::new());
+    test.env.tx.access_list = vec![(
+        DifferentialTest::CONTRACT,
+        vec![U256::from(5), U256::from(6)],
+    )];
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn cold_and_warm_account_accesses_agree() {
+    let program = vec![
+        // The first access to an account is cold, and the next ones are warm
+        Operation::Push(BigUint::from(0xAA_u8)),
+        Operation::Extcodesize,
+        Operation::Push(BigUint::from(0xAA_u8)),
+        Operation::Extcodehash,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push(BigUint::from(0xBB_u8)),
+        Operation::Extcodecopy,
+        Operation::Push(BigUint::from(0xDD_u8)),
+        Operation::Balance,
+        Operation::Push(BigUint::from(0xDD_u8)),
+        Operation::Balance,
+        // The sender already paid for the gas, which its balance reflects
+        Operation::Push(BigUint::from_bytes_be(&DifferentialTest::SENDER)),
+        Operation::Balance,
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Selfbalance,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+        // The addresses in the access list are warm from the start
+        Operation::Push(BigUint::from(0xCC_u8)),
+        Operation::Extcodesize,
+    ];
+    let mut test = call_test(program, Vec::new());
+    let mut warm_address = [0; 20];
+    warm_address[19] = 0xCC;
+    test.env.tx.access_list = vec![(warm_address, Vec::new())];
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn cold_and_warm_calls_agree() {
+    let call = |address: u8| {
+        vec![
+            Operation::Push0,
+            Operation::Push0,
+            Operation::Push0,
+            Operation::Push0,
+            Operation::Push0,
+            Operation::Push(BigUint::from(address)),
+            Operation::Push(BigUint::from(1000_u16)),
+            Operation::Call,
+            Operation::Pop,
+        ]
+    };
+    // Calls a cold account twice, a warm one from the access list and a precompile
+    let program = [call(0xAA), call(0xAA), call(0xCC), call(0x04)].concat();
+    let mut test = call_test(program, Vec::new());
+    let mut warm_address = [0; 20];
+    warm_address[19] = 0xCC;
+    test.env.tx.access_list = vec![(warm_address, Vec::new())];
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn call_creating_account_agrees() {
+    // Sends value to an empty account, which creates it
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(0xAA_u8)),
+        Operation::Push0,
+        Operation::Call,
+    ];
+    let mut test = call_test(program, Vec::new());
+    test.accounts[0].1.balance = U256::from(10);
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn selfdestruct_to_cold_beneficiary_agrees() {
+    let program = vec![
+        Operation::Push(BigUint::from(0xAA_u8)),
+        Operation::Selfdestruct,
+    ];
+    let mut test = call_test(program, Vec::new());
+    test.accounts[0].1.balance = U256::from(10);
+
+    assert_agrees(&test);
+}
+
+#[test]
+fn transactions_to_precompiles_agree() {
+    // ECADD fails on points which aren't on the curve
+    let cases = [
+        (Precompile::Sha256, b"abc".to_vec()),
+        (Precompile::EcAdd, vec![1; 128]),
+    ];
+    for (precompile, data) in cases {
+        let mut test = call_test(Vec::new(), data);
+        test.env.tx.to = Some(precompile.address());
+
+        assert_agrees(&test);
+    }
+}
+
+#[test]
+fn rejected_transactions_agree() {
+    let mut test = call_test(vec![Operation::Stop], Vec::new());
+    test.env.tx.gas_price = U256::from(1);
+
+    // The sender can't pay for the gas
+    assert_agrees(&test);
+}
+
+#[test]
+fn divergence_is_reported_with_the_trace() {
+    // revm rejects transactions over the block's gas limit, but the compiled code doesn't
+    let mut test = call_test(vec![Operation::Push0, Operation::Stop], Vec::new());
+    test.env.block.gas_limit = U256::from(GAS_LIMIT as u128 - 1);
+
+    let divergence = test.run().unwrap_err();
+
+    assert_eq!(divergence.kind, DivergenceKind::Validity);
+    assert_eq!(divergence.trace.len(), 2);
+    let mut report = Vec::new();
+    divergence.write_report(&mut report).unwrap();
+    let report = String::from_utf8(report).unwrap();
+    let lines: Vec<&str> = report.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("executions diverged on Validity"));
+    assert_eq!(lines[1], divergence.trace[0].to_json());
+}
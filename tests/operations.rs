@@ -1,55 +1,204 @@
 use evm_mlir::{
-    constants::{gas_cost, REVERT_EXIT_CODE},
+    block::execute_block,
+    breakpoint::Breakpoints,
+    builder::ProgramBuilder,
+    cache::{ArtifactCache, ContractCache},
+    constants::{
+        gas_cost, CallType, ExitStatusCode, MAX_CALL_DEPTH, MAX_CODE_SIZE, MAX_INITCODE_SIZE,
+        SHARED_ENTRYPOINT, SHARED_LIBRARY_ABI_VERSION,
+    },
     context::Context,
-    executor::Executor,
+    db::{
+        AccountInfo, AccountProvider, BlockHashProvider, Host, InMemoryAccounts,
+        InMemoryBlockHashes, InMemoryDB, InMemoryStorage, Storage,
+    },
+    debugger::Debugger,
+    env::{Address, BlockEnv, CallEnv, Env, TxEnv},
+    eof::{CodeSection, Container, SectionType, NON_RETURNING},
+    errors::{
+        BlockError, CodegenError, CompileError, Error, ParseError, ProgramError, TransactionError,
+    },
+    executor::{ExecutionResult, Executor, HaltReason},
+    inspector::{CallInputs, CallKind, CallOutcome, Inspector},
+    options::{
+        CompileOptions, DebugInfo, IrDump, IrStage, OptLevel, OverflowCheck, Passes, StackConfig,
+        Target, WordRepr,
+    },
+    precompiles::Precompile,
     program::{Operation, Program},
-    syscall::SyscallContext,
+    receipt::{Bloom, Receipt},
+    spec::SpecId,
+    state_diff::{AccountDiff, Change, StateDiff},
+    syscall::{Log, SharedEntrypoint, SyscallContext, U256},
+    trace::{write_trace, TraceStep},
 };
 use num_bigint::{BigInt, BigUint};
 use rstest::rstest;
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    ffi::{c_char, c_int, c_void, CString},
+    fs,
+    os::unix::ffi::OsStrExt,
+    path::Path,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
 use tempfile::NamedTempFile;
 
-fn run_program_assert_result_with_gas(
-    operations: Vec<Operation>,
-    expected_result: u8,
+fn run_program(
+    program: impl Into<Program>,
+    syscall_context: &mut SyscallContext,
     initial_gas: u64,
-) {
-    let program = Program::from(operations);
+) -> u8 {
+    run_program_with_spec(program, syscall_context, initial_gas, SpecId::default())
+}
+
+/// Compiles the program for the given hardfork, and runs it.
+/// The hardfork should match the one in the context's environment
+fn run_program_with_spec(
+    program: impl Into<Program>,
+    syscall_context: &mut SyscallContext,
+    initial_gas: u64,
+    spec_id: SpecId,
+) -> u8 {
+    let program = program.into();
     let output_file = NamedTempFile::new()
         .expect("failed to generate tempfile")
         .into_temp_path();
 
     let context = Context::new();
     let module = context
-        .compile(&program, &output_file)
+        .compile_with_spec(&program, &output_file, spec_id)
         .expect("failed to compile program");
 
     let executor = Executor::new(&module);
 
-    let mut context = SyscallContext::default();
+    executor.execute(syscall_context, initial_gas)
+}
+
+/// Gas consumed by the operations appended in [`run_program_assert_stack_top`],
+/// including expanding the empty memory to one word.
+///
+/// Programs using the memory only need to pay for expanding it past that first word.
+const RETURN_STACK_TOP_GAS: u64 = (gas_cost::PUSH0
+    + gas_cost::MSTORE
+    + gas_cost::MEMORY_WORD
+    + gas_cost::PUSHN
+    + gas_cost::PUSH0) as _;
+
+/// Gas cost of expanding the memory from `from_words` to `to_words` 32-byte words.
+/// It costs nothing if the memory is already big enough
+fn memory_expansion_cost(from_words: i64, to_words: i64) -> i64 {
+    let memory_cost = |words: i64| {
+        gas_cost::MEMORY_WORD * words + words * words / gas_cost::MEMORY_QUADRATIC_DIVISOR
+    };
+    memory_cost(to_words.max(from_words)) - memory_cost(from_words)
+}
+
+/// Runs the program, returning the stack top as a 32-byte output,
+/// and checks its least significant byte.
+///
+/// The gas needed for returning the stack top is added on top of `initial_gas`.
+fn run_program_assert_stack_top(
+    mut operations: Vec<Operation>,
+    mut context: SyscallContext,
+    expected_result: u8,
+    initial_gas: u64,
+) {
+    operations.extend([
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
 
-    let result = executor.execute(&mut context, initial_gas);
+    let result = run_program(operations, &mut context, initial_gas + RETURN_STACK_TOP_GAS);
 
-    assert_eq!(result, expected_result);
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(context.return_values().last(), Some(&expected_result));
+}
+
+fn run_program_assert_result_with_gas(
+    operations: Vec<Operation>,
+    expected_result: u8,
+    initial_gas: u64,
+) {
+    let context = SyscallContext::default();
+    run_program_assert_stack_top(operations, context, expected_result, initial_gas);
+}
+
+fn run_program_assert_result_with_calldata(
+    operations: Vec<Operation>,
+    calldata: Vec<u8>,
+    expected_result: u8,
+) {
+    let context = SyscallContext::with_calldata(calldata);
+    run_program_assert_stack_top(operations, context, expected_result, 1e7 as _);
 }
 
 fn run_program_assert_result(operations: Vec<Operation>, expected_result: u8) {
     run_program_assert_result_with_gas(operations, expected_result, 1e7 as _);
 }
 
-fn run_program_assert_reverts_with_gas(program: Vec<Operation>, initial_gas: u64) {
-    // TODO: design a way to check for stack overflow
-    run_program_assert_result_with_gas(program, REVERT_EXIT_CODE, initial_gas);
+fn run_program_assert_halt_with_gas(program: Vec<Operation>, initial_gas: u64) {
+    let mut context = SyscallContext::default();
+
+    let result = run_program(program, &mut context, initial_gas);
+
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+    assert!(context.return_values().is_empty());
+}
+
+fn run_program_assert_out_of_gas(program: Vec<Operation>, initial_gas: u64) {
+    let mut context = SyscallContext::default();
+
+    let result = run_program(program, &mut context, initial_gas);
+
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+    assert!(context.return_values().is_empty());
 }
 
 fn run_program_assert_gas_exact(program: Vec<Operation>, expected_result: u8, exact_gas: u64) {
     run_program_assert_result_with_gas(program.clone(), expected_result, exact_gas);
-    run_program_assert_reverts_with_gas(program, exact_gas - 1);
+    run_program_assert_out_of_gas(program, exact_gas - 1);
+}
+
+fn run_program_assert_gas_exact_with_spec(
+    program: Vec<Operation>,
+    exact_gas: u64,
+    spec_id: SpecId,
+) {
+    let new_context = || {
+        SyscallContext::with_env(Env {
+            spec_id,
+            ..Default::default()
+        })
+    };
+
+    let result = run_program_with_spec(program.clone(), &mut new_context(), exact_gas, spec_id);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    let result = run_program_with_spec(program, &mut new_context(), exact_gas - 1, spec_id);
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+}
+
+fn run_program_assert_halt(program: Vec<Operation>) {
+    run_program_assert_halt_with_gas(program, 1e7 as _);
 }
 
-fn run_program_assert_revert(program: Vec<Operation>) {
-    // TODO: design a way to check for stack overflow
-    run_program_assert_result(program, REVERT_EXIT_CODE);
+fn run_program_assert_output(
+    program: impl Into<Program>,
+    expected_status: ExitStatusCode,
+    expected_output: &[u8],
+) {
+    let mut context = SyscallContext::default();
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, expected_status.to_u8());
+    assert_eq!(context.return_values(), expected_output);
 }
 
 pub fn biguint_256_from_bigint(value: BigInt) -> BigUint {
@@ -98,8 +247,10 @@ fn push_fill_stack() {
     let stack_top = BigUint::from(88_u8);
 
     // Push 1024 times
-    let program = vec![Operation::Push(stack_top.clone()); 1024];
-    run_program_assert_result(program, stack_top.try_into().unwrap());
+    // The stack is full, so the program can't return its top;
+    // it should still run to completion without overflowing
+    let program = vec![Operation::Push(stack_top); 1024];
+    run_program_assert_output(program, ExitStatusCode::Stop, &[]);
 }
 
 #[test]
@@ -115,7 +266,23 @@ fn push_reverts_without_gas() {
 fn push_stack_overflow() {
     // Push 1025 times
     let program = vec![Operation::Push(BigUint::from(88_u8)); 1025];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn push_with_leading_zeros() {
+    // [00] PUSH2 0x0007
+    // [03] PUSH32 0x00..00 0x0a
+    // [24] PC
+    // [25] ADD
+    // [26] ADD
+    let mut bytecode = vec![0x61, 0x00, 0x07, 0x7F];
+    bytecode.extend([0x00; 31]);
+    bytecode.extend([0x0A, 0x58, 0x01, 0x01]);
+    let program = Program::from_bytecode(&bytecode).unwrap();
+    let initial_gas = (gas_cost::PUSHN * 2 + gas_cost::PC + gas_cost::ADD * 2) as _;
+
+    run_program_assert_gas_exact(program.operations().to_vec(), 7 + 10 + 0x24, initial_gas);
 }
 
 #[test]
@@ -172,7 +339,7 @@ fn dup_nth(#[case] nth: u8) {
 fn dup_with_stack_underflow() {
     let program = vec![Operation::Dup(1)];
 
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -210,7 +377,7 @@ fn shl_shift_grater_than_255() {
 fn shl_with_stack_underflow() {
     let program = vec![Operation::Shl];
 
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -286,7 +453,7 @@ fn swap_stack_underflow() {
         Operation::Swap(2),
     ];
 
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -371,7 +538,7 @@ fn sub_out_of_gas() {
 
 #[test]
 fn add_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Add]);
+    run_program_assert_halt(vec![Operation::Add]);
 }
 
 #[test]
@@ -455,7 +622,7 @@ fn div_with_zero_numerator() {
 
 #[test]
 fn div_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Div]);
+    run_program_assert_halt(vec![Operation::Div]);
 }
 
 #[test]
@@ -612,7 +779,7 @@ fn mul_wraps_result() {
 
 #[test]
 fn mul_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Mul]);
+    run_program_assert_halt(vec![Operation::Mul]);
 }
 
 #[test]
@@ -653,7 +820,7 @@ fn shift_bigger_than_256() {
 
 #[test]
 fn shr_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Shr]);
+    run_program_assert_halt(vec![Operation::Shr]);
 }
 
 #[test]
@@ -671,7 +838,7 @@ fn push_push_xor() {
 fn xor_with_stack_underflow() {
     let program = vec![Operation::Xor];
 
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -687,6 +854,50 @@ fn xor_out_of_gas() {
     run_program_assert_gas_exact(program, expected_result, initial_gas as _);
 }
 
+#[test]
+fn not_flips_every_bit() {
+    let value = BigUint::from(0x0f_u8);
+    let program = vec![Operation::Push(value), Operation::Not];
+
+    run_program_assert_result(program, 0xf0);
+}
+
+#[test]
+fn not_of_zero_is_max_value() {
+    // NOT 0 has all bits set, so shifting it right by 248 leaves 0xff
+    let program = vec![
+        Operation::Push0,
+        Operation::Not,
+        Operation::Push(BigUint::from(248_u8)),
+        Operation::Shr,
+    ];
+
+    run_program_assert_result(program, 0xff);
+}
+
+#[test]
+fn not_twice_is_identity() {
+    let value = BigUint::from_bytes_be(&[0xa5; 32]);
+    let program = vec![Operation::Push(value), Operation::Not, Operation::Not];
+
+    run_program_assert_result(program, 0xa5);
+}
+
+#[test]
+fn not_with_stack_underflow() {
+    let program = vec![Operation::Not];
+
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn not_out_of_gas() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Not];
+    let initial_gas = gas_cost::PUSHN + gas_cost::NOT;
+
+    run_program_assert_gas_exact(program, 0xfe, initial_gas as _);
+}
+
 #[test]
 fn push_push_pop() {
     // Push two values to the stack and then pop once
@@ -706,7 +917,7 @@ fn push_push_pop() {
 fn pop_with_stack_underflow() {
     // Pop with an empty stack
     let program = vec![Operation::Pop];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -724,7 +935,7 @@ fn push_push_sar() {
 #[test]
 fn sar_with_stack_underflow() {
     let program = vec![Operation::Sar];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -745,7 +956,7 @@ fn push_push_byte() {
 #[test]
 fn byte_with_stack_underflow() {
     let program = vec![Operation::Byte];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -871,7 +1082,7 @@ fn test_eq_false() {
 
 #[test]
 fn test_eq_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Eq]);
+    run_program_assert_halt(vec![Operation::Eq]);
 }
 
 #[test]
@@ -890,7 +1101,7 @@ fn test_or() {
 #[test]
 fn test_or_with_stack_underflow() {
     let program = vec![Operation::Or];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -932,7 +1143,7 @@ fn test_iszero_false() {
 #[test]
 fn test_iszero_stack_underflow() {
     let program = vec![Operation::IsZero];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -960,6 +1171,83 @@ fn jump() {
     run_program_assert_result(program, a);
 }
 
+#[test]
+fn jump_to_computed_pcs() {
+    // The offsets of the JUMPDEST and the PC are left for the program to compute
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(8_u8)),
+        Operation::Jump,
+        Operation::PushN(4, BigUint::from(10_u8)), // this should not be executed
+        Operation::Jumpdest { pc: 0 },
+        Operation::PC { pc: 0 },
+    ])
+    .with_computed_pcs();
+
+    run_program_assert_result(program.operations().to_vec(), 9);
+}
+
+#[test]
+fn jump_to_labels() {
+    // Counts down from 3 to 0, adding 2 to the result on each step
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Label("loop".to_string()),
+        Operation::Swap(1),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+        Operation::Swap(1),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Dup(1),
+        Operation::PushLabel("loop".to_string()),
+        Operation::Jumpi,
+        Operation::Pop,
+    ];
+
+    run_program_assert_result(program, 6);
+}
+
+#[test]
+fn undefined_labels_are_rejected() {
+    let program = Program::from(vec![
+        Operation::PushLabel("nowhere".to_string()),
+        Operation::Jump,
+    ]);
+    let context = Context::new();
+
+    let result = context.compile_jit(&program, SpecId::default());
+
+    let expected = ProgramError::UndefinedLabel {
+        label: "nowhere".to_string(),
+    };
+    assert!(matches!(result, Err(CodegenError::InvalidProgram(error)) if error == expected));
+}
+
+#[test]
+fn program_builder_runs() {
+    // Returns the sum of 1 to 4, added in a loop
+    let program = ProgramBuilder::new()
+        .push0()
+        .push(4_u8)
+        .label("loop")
+        .dup(1)
+        .swap(2)
+        .add()
+        .swap(1)
+        .push(1_u8)
+        .swap(1)
+        .sub()
+        .dup(1)
+        .jumpi_to("loop")
+        .pop()
+        .build()
+        .unwrap();
+
+    run_program_assert_result(program.operations().to_vec(), 10);
+}
+
 #[test]
 fn jumpi_with_false_condition() {
     // this test is equivalent to the following bytecode program
@@ -996,7 +1284,7 @@ fn jumpi_reverts_if_pc_is_wrong() {
         Operation::Jumpi,
         Operation::Jumpdest { pc: 83 },
     ];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -1009,7 +1297,21 @@ fn jump_reverts_if_pc_is_wrong() {
         Operation::Jump,
         Operation::Jumpdest { pc: 83 },
     ];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn jump_reverts_if_pc_only_matches_in_its_low_bits() {
+    // The jumptable narrows the pc it switches on, once it's known to be small enough
+    let pc = (BigUint::from(1_u8) << 32) + 4_u8;
+    let program = vec![
+        Operation::Push(pc),
+        Operation::Push0,
+        Operation::Add,
+        Operation::Jump,
+        Operation::Jumpdest { pc: 4 },
+    ];
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -1073,7 +1375,7 @@ fn test_and_with_zero() {
 
 #[test]
 fn and_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::And]);
+    run_program_assert_halt(vec![Operation::And]);
 }
 
 #[test]
@@ -1112,7 +1414,7 @@ fn mod_with_zero_numerator() {
 
 #[test]
 fn mod_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Mod]);
+    run_program_assert_halt(vec![Operation::Mod]);
 }
 
 #[test]
@@ -1185,7 +1487,7 @@ fn smod_with_zero_denominator() {
 
 #[test]
 fn smod_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::SMod]);
+    run_program_assert_halt(vec![Operation::SMod]);
 }
 
 #[test]
@@ -1220,7 +1522,7 @@ fn addmod_with_non_zero_result() {
 
 #[test]
 fn addmod_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Addmod]);
+    run_program_assert_halt(vec![Operation::Addmod]);
 }
 
 #[test]
@@ -1276,7 +1578,7 @@ fn addmod_reverts_when_program_runs_out_of_gas() {
 fn test_gt_less_than() {
     let a = BigUint::from(9_u8);
     let b = BigUint::from(8_u8);
-    let program = vec![Operation::Push(a), Operation::Push(b), Operation::Gt];
+    let program = vec![Operation::Push(b), Operation::Push(a), Operation::Gt];
     run_program_assert_result(program, 1);
 }
 
@@ -1284,7 +1586,7 @@ fn test_gt_less_than() {
 fn test_gt_greater_than() {
     let a = BigUint::from(8_u8);
     let b = BigUint::from(9_u8);
-    let program = vec![Operation::Push(a), Operation::Push(b), Operation::Gt];
+    let program = vec![Operation::Push(b), Operation::Push(a), Operation::Gt];
     run_program_assert_result(program, 0);
 }
 
@@ -1292,13 +1594,13 @@ fn test_gt_greater_than() {
 fn test_gt_equal() {
     let a = BigUint::from(10_u8);
     let b = BigUint::from(10_u8);
-    let program = vec![Operation::Push(a), Operation::Push(b), Operation::Gt];
+    let program = vec![Operation::Push(b), Operation::Push(a), Operation::Gt];
     run_program_assert_result(program, 0);
 }
 
 #[test]
 fn gt_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Gt]);
+    run_program_assert_halt(vec![Operation::Gt]);
 }
 
 #[test]
@@ -1320,7 +1622,7 @@ fn mulmod_with_non_zero_result() {
 
 #[test]
 fn mulmod_with_stack_underflow() {
-    run_program_assert_revert(vec![Operation::Mulmod]);
+    run_program_assert_halt(vec![Operation::Mulmod]);
 }
 
 #[test]
@@ -1443,7 +1745,7 @@ fn test_sgt_equal() {
 #[test]
 fn test_sgt_stack_underflow() {
     let program = vec![Operation::Sgt];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -1479,7 +1781,7 @@ fn test_lt_equal() {
 #[test]
 fn test_lt_stack_underflow() {
     let program = vec![Operation::Lt];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -1500,15 +1802,21 @@ fn test_gas_with_add_should_revert() {
 fn stop() {
     // the push operation should not be executed
     let program = vec![Operation::Stop, Operation::Push(BigUint::from(10_u8))];
-    run_program_assert_result(program, 0);
+    run_program_assert_output(program, ExitStatusCode::Stop, &[]);
+}
+
+#[test]
+fn reaching_the_end_of_the_program_stops() {
+    let program = vec![Operation::Push(BigUint::from(10_u8))];
+    run_program_assert_output(program, ExitStatusCode::Stop, &[]);
 }
 
 #[test]
 fn push_push_exp() {
     let (a, b) = (BigUint::from(2_u8), BigUint::from(3_u8));
     let program = vec![
-        Operation::Push(a.clone()),
         Operation::Push(b.clone()),
+        Operation::Push(a.clone()),
         Operation::Exp,
     ];
 
@@ -1520,8 +1828,8 @@ fn exp_with_overflow_should_wrap() {
     let a = BigUint::from(3_u8);
     let b = BigUint::from(256_u16);
     let program = vec![
-        Operation::Push(a.clone()),
         Operation::Push(b.clone()),
+        Operation::Push(a.clone()),
         Operation::Exp,
     ];
     run_program_assert_result(program, 1);
@@ -1530,7 +1838,7 @@ fn exp_with_overflow_should_wrap() {
 #[test]
 fn exp_with_stack_underflow() {
     let program = vec![Operation::Exp];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -1607,7 +1915,7 @@ fn signextend_one_byte_positive_value() {
 #[test]
 fn signextend_with_stack_underflow() {
     let program = vec![Operation::SignExtend];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -1628,7 +1936,7 @@ fn signextend_gas_should_revert() {
 fn gas_get_starting_value() {
     const INITIAL_GAS: i64 = 30;
 
-    let expected_result = (INITIAL_GAS - gas_cost::GAS) as _;
+    let expected_result = ((INITIAL_GAS - gas_cost::GAS) as u64 + RETURN_STACK_TOP_GAS) as _;
 
     let program = vec![
         Operation::Gas, // <No collapse>
@@ -1642,7 +1950,7 @@ fn gas_value_after_operations() {
     const INITIAL_GAS: i64 = 50;
 
     let gas_consumption = gas_cost::PUSHN * 3 + gas_cost::ADD * 2 + gas_cost::GAS;
-    let expected_result = (INITIAL_GAS - gas_consumption) as _;
+    let expected_result = ((INITIAL_GAS - gas_consumption) as u64 + RETURN_STACK_TOP_GAS) as _;
 
     let program = vec![
         Operation::Push(BigUint::ZERO), // <No collapse>
@@ -1659,7 +1967,8 @@ fn gas_value_after_operations() {
 #[test]
 fn gas_without_enough_gas_revert() {
     let gas_consumption = gas_cost::PUSHN * 3 + gas_cost::ADD * 2 + gas_cost::GAS;
-    let expected_result = 0;
+    // the only gas left is the one needed for returning the result
+    let expected_result = RETURN_STACK_TOP_GAS as _;
 
     let program = vec![
         Operation::Push(BigUint::ZERO), // <No collapse>
@@ -1673,6 +1982,18 @@ fn gas_without_enough_gas_revert() {
     run_program_assert_gas_exact(program, expected_result, gas_consumption as _);
 }
 
+#[test]
+fn out_of_gas_halts_with_out_of_gas_status() {
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Add];
+    let initial_gas = (gas_cost::PUSH0 * 2) as _;
+    run_program_assert_out_of_gas(program, initial_gas);
+}
+
+#[test]
+fn stack_underflow_with_enough_gas_is_not_out_of_gas() {
+    run_program_assert_halt_with_gas(vec![Operation::Add], 1e7 as _);
+}
+
 #[test]
 fn byte_gas_cost() {
     let value: [u8; 32] = [0xff; 32];
@@ -1704,12 +2025,13 @@ fn and_reverts_when_program_run_out_of_gas() {
 #[test]
 fn exp_reverts_when_program_runs_out_of_gas() {
     let program = vec![
-        Operation::Push(BigUint::from(3_u8)),
         Operation::Push(BigUint::from(256_u16)),
+        Operation::Push(BigUint::from(3_u8)),
         Operation::Exp,
     ];
 
-    let initial_gas = gas_cost::PUSHN * 2 + gas_cost::EXP;
+    // the exponent takes 2 bytes
+    let initial_gas = gas_cost::PUSHN * 2 + gas_cost::EXP + gas_cost::EXP_BYTE * 2;
     let expected_result = 1;
     run_program_assert_gas_exact(program, expected_result, initial_gas as _);
 }
@@ -1744,8 +2066,8 @@ fn sgt_reverts_when_program_runs_out_of_gas() {
 fn gt_reverts_when_program_runs_out_of_gas() {
     let (a, b) = (BigUint::from(0_u8), BigUint::from(1_u8));
     let program = vec![
-        Operation::Push(a.clone()),
         Operation::Push(b.clone()),
+        Operation::Push(a.clone()),
         Operation::Gt,
     ];
     let needed_gas = gas_cost::PUSHN * 2 + gas_cost::GT;
@@ -1889,7 +2211,7 @@ fn slt_gas_should_revert() {
 #[test]
 fn slt_stack_underflow() {
     let program = vec![Operation::Slt];
-    run_program_assert_revert(program);
+    run_program_assert_halt(program);
 }
 
 #[test]
@@ -1912,3 +2234,9188 @@ fn jump_with_gas_cost() {
     let needed_gas = gas_cost::PUSHN * 2 + gas_cost::JUMPDEST + gas_cost::JUMP;
     run_program_assert_gas_exact(program, expected_result, needed_gas as _);
 }
+
+#[test]
+fn jumpi_with_stack_underflow() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Jumpi];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn jumpi_with_gas_cost() {
+    // this test is equivalent to the following bytecode program
+    //
+    // [00] PUSH1 0
+    // [02] PUSH1 1  // push condition
+    // [04] PUSH1 7  // push pc
+    // [06] JUMPI
+    // [07] JUMPDEST
+    let jumpdest: u8 = 7;
+    let program = vec![
+        Operation::Push(BigUint::from(0_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(jumpdest)),
+        Operation::Jumpi,
+        Operation::Jumpdest {
+            pc: jumpdest as usize,
+        },
+    ];
+    let expected_result = 0;
+    let needed_gas = gas_cost::PUSHN * 3 + gas_cost::JUMPI + gas_cost::JUMPDEST;
+    run_program_assert_gas_exact(program, expected_result, needed_gas as _);
+}
+
+#[test]
+fn sub_with_stack_underflow() {
+    run_program_assert_halt(vec![Operation::Push(BigUint::from(1_u8)), Operation::Sub]);
+}
+
+#[test]
+fn div_with_256_bit_operands() {
+    let num = (BigUint::from(1_u8) << 256) - 1_u8;
+    let den = BigUint::from(1_u8) << 248;
+
+    let program = vec![Operation::Push(den), Operation::Push(num), Operation::Div];
+
+    run_program_assert_result(program, 255);
+}
+
+#[test]
+fn mod_with_256_bit_operands() {
+    let num = (BigUint::from(1_u8) << 256) - 1_u8;
+    let den = (BigUint::from(1_u8) << 255) + 7_u8;
+
+    let program = vec![
+        Operation::Push(den.clone()),
+        Operation::Push(num.clone()),
+        Operation::Mod,
+    ];
+    let expected_result = (num % den % 256_u32).try_into().unwrap();
+
+    run_program_assert_result(program, expected_result);
+}
+
+#[test]
+fn sdiv_min_value_by_minus_one() {
+    // MIN_I256 / -1 overflows and should result in MIN_I256
+    let min_value = BigUint::from(1_u8) << 255;
+    let minus_one = biguint_256_from_bigint(BigInt::from(-1_i8));
+
+    let program = vec![
+        Operation::Push(minus_one),
+        Operation::Push(min_value),
+        Operation::Sdiv,
+        // get the most significant byte of the result
+        Operation::Push(BigUint::from(248_u8)),
+        Operation::Shr,
+    ];
+
+    run_program_assert_result(program, 0x80);
+}
+
+#[test]
+fn smod_min_value_by_minus_one() {
+    // MIN_I256 % -1 overflows and should result in 0
+    let min_value = BigUint::from(1_u8) << 255;
+    let minus_one = biguint_256_from_bigint(BigInt::from(-1_i8));
+
+    let program = vec![
+        Operation::Push(minus_one),
+        Operation::Push(min_value),
+        Operation::SMod,
+        Operation::IsZero,
+    ];
+
+    run_program_assert_result(program, 1);
+}
+
+#[test]
+fn signextend_byte_size_out_of_bounds() {
+    // SIGNEXTEND with a byte size of 31 or more leaves the value untouched
+    let value = BigUint::from(0xFF_u8);
+
+    let program = vec![
+        Operation::Push(value),
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::SignExtend,
+    ];
+
+    run_program_assert_result(program, 0xFF);
+}
+
+#[rstest]
+#[case(BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xfe; 32]))]
+#[case(BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xee; 32]), BigUint::from_bytes_be(&[0xff; 31]))]
+#[case(BigUint::from(1_u8) << 255, BigUint::from(1_u8) << 255, (BigUint::from(1_u8) << 255) + 3_u8)]
+fn addmod_with_wide_operands(#[case] a: BigUint, #[case] b: BigUint, #[case] den: BigUint) {
+    let program = vec![
+        Operation::Push(den.clone()),
+        Operation::Push(b.clone()),
+        Operation::Push(a.clone()),
+        Operation::Addmod,
+    ];
+    let expected_result = ((a + b) % den % 256_u32).try_into().unwrap();
+    run_program_assert_result(program, expected_result);
+}
+
+#[rstest]
+#[case(BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xfe; 32]))]
+#[case(BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xee; 32]), BigUint::from_bytes_be(&[0xff; 31]))]
+#[case(BigUint::from(1_u8) << 255, BigUint::from(1_u8) << 255, (BigUint::from(1_u8) << 255) + 3_u8)]
+fn mulmod_with_wide_operands(#[case] a: BigUint, #[case] b: BigUint, #[case] den: BigUint) {
+    let program = vec![
+        Operation::Push(den.clone()),
+        Operation::Push(b.clone()),
+        Operation::Push(a.clone()),
+        Operation::Mulmod,
+    ];
+    let expected_result = ((a * b) % den % 256_u32).try_into().unwrap();
+    run_program_assert_result(program, expected_result);
+}
+
+#[test]
+fn exp_with_zero_exponent_has_no_dynamic_cost() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Exp,
+    ];
+
+    let initial_gas = gas_cost::PUSH0 + gas_cost::PUSHN + gas_cost::EXP;
+    run_program_assert_gas_exact(program, 1, initial_gas as _);
+}
+
+#[test]
+fn exp_dynamic_gas_with_32_byte_exponent() {
+    let exponent = BigUint::from_bytes_be(&[0xff; 32]);
+    let program = vec![
+        Operation::Push(exponent),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Exp,
+    ];
+
+    let initial_gas = gas_cost::PUSHN * 2 + gas_cost::EXP + gas_cost::EXP_BYTE * 32;
+    run_program_assert_gas_exact(program, 1, initial_gas as _);
+}
+
+#[rstest]
+#[case::frontier(SpecId::Frontier, 10)]
+#[case::spurious_dragon(SpecId::SpuriousDragon, 50)]
+fn exp_gas_of_each_hardfork(#[case] spec_id: SpecId, #[case] exponent_byte_gas: u64) {
+    // The exponent takes two bytes
+    let program = vec![
+        Operation::Push(BigUint::from(0x0100_u16)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Exp,
+    ];
+    let needed_gas = (gas_cost::PUSHN * 2 + gas_cost::EXP) as u64 + exponent_byte_gas * 2;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, spec_id);
+}
+
+#[test]
+fn gt_with_stack_top_greater() {
+    // GT compares the top of the stack against the second element
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Gt,
+    ];
+    run_program_assert_result(program, 1);
+}
+
+#[rstest]
+#[case(Operation::Lt, BigUint::from(1_u8), BigUint::from_bytes_be(&[0xff; 32]))]
+#[case(Operation::Gt, BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xfe; 32]))]
+#[case(Operation::Eq, BigUint::from_bytes_be(&[0xff; 32]), BigUint::from_bytes_be(&[0xff; 32]))]
+fn comparison_result_fills_the_whole_word(
+    #[case] op: Operation,
+    #[case] a: BigUint,
+    #[case] b: BigUint,
+) {
+    // The result is stored where `b` was, so none of its upper bits
+    // should remain after the comparison
+    let program = vec![
+        Operation::Push(b),
+        Operation::Push(a),
+        op,
+        // shift the result so that only its upper bits remain
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Shr,
+    ];
+    run_program_assert_result(program, 0);
+}
+
+#[rstest]
+#[case(Operation::Slt, BigInt::from(-2_i8), BigInt::from(-1_i8))]
+#[case(Operation::Sgt, BigInt::from(-1_i8), BigInt::from(-2_i8))]
+#[case(Operation::Sgt, BigInt::from(-1_i8), BigInt::from(-1_i8))]
+fn signed_comparison_result_fills_the_whole_word(
+    #[case] op: Operation,
+    #[case] a: BigInt,
+    #[case] b: BigInt,
+) {
+    // `b` is negative, so all of its upper bits are set before the
+    // result is stored in its place
+    let program = vec![
+        Operation::Push(biguint_256_from_bigint(b)),
+        Operation::Push(biguint_256_from_bigint(a)),
+        op,
+        // shift the result so that only its upper bits remain
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Shr,
+    ];
+    run_program_assert_result(program, 0);
+}
+
+#[test]
+fn slt_min_value_is_less_than_max_value() {
+    let mut min = BigUint::from(0_u8);
+    min.set_bit(255, true);
+    let max = (BigUint::from(1_u8) << 255_u32) - 1_u8;
+
+    let program = vec![Operation::Push(max), Operation::Push(min), Operation::Slt];
+    run_program_assert_result(program, 1);
+}
+
+#[test]
+fn sgt_max_value_is_greater_than_min_value() {
+    let mut min = BigUint::from(0_u8);
+    min.set_bit(255, true);
+    let max = (BigUint::from(1_u8) << 255_u32) - 1_u8;
+
+    let program = vec![Operation::Push(min), Operation::Push(max), Operation::Sgt];
+    run_program_assert_result(program, 1);
+}
+
+#[test]
+fn mstore_then_mload() {
+    let value = BigUint::from(42_u8);
+    let program = vec![
+        Operation::Push(value),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    run_program_assert_result(program, 42);
+}
+
+#[test]
+fn mload_from_unwritten_memory_is_zero() {
+    let program = vec![Operation::Push(BigUint::from(64_u8)), Operation::Mload];
+    run_program_assert_result(program, 0);
+}
+
+#[test]
+fn mload_with_unaligned_offset() {
+    // The stored word ends with 0xff at byte 31, so reading from offset 1
+    // leaves it as the second least significant byte
+    let program = vec![
+        Operation::Push(BigUint::from(0xff_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Mload,
+        Operation::Push(BigUint::from(8_u8)),
+        Operation::Shr,
+    ];
+    run_program_assert_result(program, 0xff);
+}
+
+#[test]
+fn mstore8_then_mload_reads_big_endian() {
+    // A byte stored at offset 0 is the most significant byte of the word
+    let program = vec![
+        Operation::Push(BigUint::from(0xab_u8)),
+        Operation::Push0,
+        Operation::Mstore8,
+        Operation::Push0,
+        Operation::Mload,
+        Operation::Push(BigUint::from(248_u8)),
+        Operation::Shr,
+    ];
+    run_program_assert_result(program, 0xab);
+}
+
+#[test]
+fn mstore8_stores_only_the_least_significant_byte() {
+    let program = vec![
+        Operation::Push(BigUint::from(0x1234_u16)),
+        Operation::Push(BigUint::from(31_u8)),
+        Operation::Mstore8,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    run_program_assert_result(program, 0x34);
+}
+
+#[test]
+fn mload_with_stack_underflow() {
+    let program = vec![Operation::Mload];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn mload_with_gas_cost() {
+    let program = vec![Operation::Push0, Operation::Mload];
+    let needed_gas = gas_cost::PUSH0 + gas_cost::MLOAD;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn mstore_memory_expansion_gas_exact() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Mstore,
+        Operation::Push0,
+    ];
+    let needed_gas =
+        gas_cost::PUSHN * 2 + gas_cost::MSTORE + gas_cost::PUSH0 + memory_expansion_cost(1, 3);
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn mstore_in_expanded_memory_has_no_expansion_cost() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push0,
+    ];
+    let needed_gas = gas_cost::PUSH0 * 5 + gas_cost::MSTORE * 2;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn memory_expansion_cost_is_quadratic() {
+    // Reads the word ending at 1000 words of memory
+    let program = vec![
+        Operation::Push(BigUint::from(999_u32 * 32)),
+        Operation::Mload,
+    ];
+    // 1000 words cost 3 * 1000 + 1000² / 512
+    assert_eq!(memory_expansion_cost(0, 1000), 4953);
+    let needed_gas = gas_cost::PUSHN + gas_cost::MLOAD + memory_expansion_cost(1, 1000);
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn out_of_gas_on_memory_expansion() {
+    // Extending the memory to 1 MiB costs much more than the available gas
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(1_u32 << 20)),
+        Operation::Mstore,
+    ];
+    run_program_assert_out_of_gas(program, 100_000);
+}
+
+#[rstest]
+#[case(BigUint::from(1_u64 << 32))]
+#[case(BigUint::from(1_u8) << 255)]
+// Wraps around to a small required size if added in 32 bits
+#[case(BigUint::from(0xFFFF_FFF0_u32))]
+fn out_of_gas_on_memory_offset_too_big(#[case] offset: BigUint) {
+    let mload = vec![Operation::Push(offset.clone()), Operation::Mload];
+    run_program_assert_out_of_gas(mload, 10_000_000);
+
+    let mstore = vec![
+        Operation::Push0,
+        Operation::Push(offset.clone()),
+        Operation::Mstore,
+    ];
+    run_program_assert_out_of_gas(mstore, 10_000_000);
+
+    let mstore8 = vec![
+        Operation::Push0,
+        Operation::Push(offset),
+        Operation::Mstore8,
+    ];
+    run_program_assert_out_of_gas(mstore8, 10_000_000);
+}
+
+#[rstest]
+#[case(Operation::Return)]
+#[case(Operation::Revert)]
+fn halt_with_output_out_of_gas_on_size_too_big(#[case] op: Operation) {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u64 << 32)),
+        Operation::Push0,
+        op.clone(),
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+
+    // offset + size overflows 32 bits
+    let program = vec![
+        Operation::Push(BigUint::from(0x20_u8)),
+        Operation::Push(BigUint::from(0xFFFF_FFF0_u32)),
+        op,
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+}
+
+#[test]
+fn return_empty_output_with_huge_offset() {
+    // An empty output doesn't touch the memory, wherever it is
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8) << 255),
+        Operation::Return,
+    ];
+    run_program_assert_output(program, ExitStatusCode::Return, &[]);
+}
+
+#[test]
+fn msize_of_empty_memory() {
+    let program = vec![Operation::Msize];
+    run_program_assert_result(program, 0);
+}
+
+#[rstest]
+#[case(Operation::Mstore8, 0, 32)]
+#[case(Operation::Mstore8, 31, 32)]
+#[case(Operation::Mstore8, 32, 64)]
+#[case(Operation::Mstore, 0, 32)]
+#[case(Operation::Mstore, 1, 64)]
+#[case(Operation::Mstore, 64, 96)]
+fn msize_is_word_aligned(
+    #[case] store_op: Operation,
+    #[case] offset: u8,
+    #[case] expected_size: u8,
+) {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(offset)),
+        store_op,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, expected_size);
+}
+
+#[test]
+fn msize_after_mload() {
+    let program = vec![
+        Operation::Push(BigUint::from(33_u8)),
+        Operation::Mload,
+        Operation::Pop,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 96);
+}
+
+#[test]
+fn memory_does_not_shrink() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Mstore,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 96);
+}
+
+#[test]
+fn msize_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::Msize);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn msize_with_gas_cost() {
+    let program = vec![Operation::Msize];
+    run_program_assert_gas_exact(program, 0, gas_cost::MSIZE as _);
+}
+
+#[test]
+fn mcopy_copies_a_word() {
+    let program = vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        // copy 32 bytes from offset 0 to offset 32
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Mcopy,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Mload,
+    ];
+    run_program_assert_result(program, 42);
+}
+
+#[test]
+fn mcopy_with_overlapping_ranges() {
+    // memory[0..32] = 0x0102...20, then copy memory[0..32] to memory[1..33]
+    let value = BigUint::from_bytes_be(&(1..=32).collect::<Vec<u8>>());
+    let program = vec![
+        Operation::Push(value),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Mcopy,
+        // with memmove semantics, memory[32] holds the original memory[31]
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Mload,
+    ];
+    run_program_assert_result(program, 32);
+}
+
+#[test]
+fn mcopy_expands_memory_for_source() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Push0,
+        Operation::Mcopy,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 96);
+}
+
+#[test]
+fn mcopy_expands_memory_for_destination() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Mcopy,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 96);
+}
+
+#[test]
+fn mcopy_with_zero_size_does_not_expand_memory() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Push(BigUint::from(128_u8)),
+        Operation::Mcopy,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 0);
+}
+
+#[rstest]
+// size, offset, dest_offset
+#[case(1_u64 << 32, 0, 0)]
+#[case(32, 1_u64 << 32, 0)]
+#[case(32, 0, 1_u64 << 32)]
+#[case(0x20, 0xFFFF_FFF0, 0)]
+#[case(0x20, 0, 0xFFFF_FFF0)]
+fn mcopy_out_of_gas_on_segment_too_big(
+    #[case] size: u64,
+    #[case] offset: u64,
+    #[case] dest_offset: u64,
+) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push(BigUint::from(offset)),
+        Operation::Push(BigUint::from(dest_offset)),
+        Operation::Mcopy,
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+}
+
+#[test]
+fn mcopy_with_zero_size_and_huge_offsets() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8) << 255),
+        Operation::Push(BigUint::from(1_u8) << 255),
+        Operation::Mcopy,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 0);
+}
+
+#[test]
+fn mcopy_with_stack_underflow() {
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Mcopy];
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(0, 0)]
+#[case(1, 1)]
+#[case(32, 1)]
+#[case(33, 2)]
+fn mcopy_with_gas_cost(#[case] size: u8, #[case] words: i64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Mcopy,
+        Operation::Push0,
+    ];
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::PUSH0 * 3
+        + gas_cost::MCOPY
+        + gas_cost::COPY_WORD * words
+        + memory_expansion_cost(1, words);
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+fn biguint_from_hex(hex: &str) -> BigUint {
+    BigUint::parse_bytes(hex.as_bytes(), 16).unwrap()
+}
+
+#[test]
+fn keccak256_of_empty_input() {
+    let expected_hash =
+        biguint_from_hex("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+    let program = vec![
+        Operation::Push(expected_hash),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Keccak256,
+        Operation::Eq,
+    ];
+    run_program_assert_result(program, 1);
+}
+
+#[test]
+fn keccak256_of_zero_word() {
+    let expected_hash =
+        biguint_from_hex("290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563");
+    let program = vec![
+        Operation::Push(expected_hash),
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Keccak256,
+        Operation::Eq,
+    ];
+    run_program_assert_result(program, 1);
+}
+
+#[test]
+fn keccak256_of_stored_word() {
+    let expected_hash =
+        biguint_from_hex("b10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf6");
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(expected_hash),
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Keccak256,
+        Operation::Eq,
+    ];
+    run_program_assert_result(program, 1);
+}
+
+#[test]
+fn keccak256_expands_memory() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Keccak256,
+        Operation::Pop,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 96);
+}
+
+#[test]
+fn keccak256_with_zero_size_does_not_expand_memory() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Keccak256,
+        Operation::Pop,
+        Operation::Msize,
+    ];
+    run_program_assert_result(program, 0);
+}
+
+#[rstest]
+#[case(1_u64 << 32, 0)]
+#[case(32, 1_u64 << 32)]
+#[case(0x20, 0xFFFF_FFF0)]
+fn keccak256_out_of_gas_on_segment_too_big(#[case] size: u64, #[case] offset: u64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push(BigUint::from(offset)),
+        Operation::Keccak256,
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+}
+
+#[test]
+fn keccak256_with_stack_underflow() {
+    let program = vec![Operation::Push0, Operation::Keccak256];
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(0, 0)]
+#[case(1, 1)]
+#[case(32, 1)]
+#[case(64, 2)]
+#[case(65, 3)]
+fn keccak256_with_gas_cost(#[case] size: u8, #[case] words: i64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Keccak256,
+        Operation::Pop,
+        Operation::Push0,
+    ];
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::PUSH0 * 2
+        + gas_cost::KECCAK256
+        + gas_cost::KECCAK256_WORD * words
+        + gas_cost::POP
+        + memory_expansion_cost(1, words);
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn calldataload_reads_a_word() {
+    let mut calldata = vec![0; 32];
+    calldata[31] = 42;
+    let program = vec![Operation::Push0, Operation::CallDataLoad];
+    run_program_assert_result_with_calldata(program, calldata, 42);
+}
+
+#[test]
+fn calldataload_with_unaligned_offset() {
+    let calldata = (0..64).collect::<Vec<u8>>();
+    let program = vec![
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::CallDataLoad,
+    ];
+    // the least significant byte is calldata[5 + 31]
+    run_program_assert_result_with_calldata(program, calldata, 36);
+}
+
+#[test]
+fn calldataload_pads_with_zeros() {
+    // Only the first byte of the word is in the calldata
+    let calldata = vec![0xab, 0xcd];
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::CallDataLoad,
+        Operation::Push(BigUint::from(248_u8)),
+        Operation::Shr,
+    ];
+    run_program_assert_result_with_calldata(program.clone(), calldata.clone(), 0xcd);
+
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::CallDataLoad,
+    ];
+    run_program_assert_result_with_calldata(program, calldata, 0);
+}
+
+#[rstest]
+#[case(BigUint::from(32_u8))]
+#[case(BigUint::from(u32::MAX))]
+#[case(BigUint::from_bytes_be(&[0xff; 32]))]
+fn calldataload_out_of_bounds_is_zero(#[case] offset: BigUint) {
+    let calldata = vec![0xff; 32];
+    let program = vec![Operation::Push(offset), Operation::CallDataLoad];
+    run_program_assert_result_with_calldata(program, calldata, 0);
+}
+
+#[test]
+fn calldataload_with_stack_underflow() {
+    let program = vec![Operation::CallDataLoad];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn calldataload_with_gas_cost() {
+    let program = vec![Operation::Push0, Operation::CallDataLoad];
+    let needed_gas = gas_cost::PUSH0 + gas_cost::CALLDATALOAD;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[rstest]
+#[case(vec![], 0)]
+#[case(vec![1, 2, 3], 3)]
+#[case(vec![0; 200], 200)]
+fn calldatasize(#[case] calldata: Vec<u8>, #[case] expected_size: u8) {
+    let program = vec![Operation::CallDataSize];
+    run_program_assert_result_with_calldata(program, calldata, expected_size);
+}
+
+#[test]
+fn calldatasize_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::CallDataSize);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn calldatasize_with_gas_cost() {
+    let program = vec![Operation::CallDataSize];
+    run_program_assert_gas_exact(program, 0, gas_cost::CALLDATASIZE as _);
+}
+
+#[test]
+fn calldatacopy_copies_to_memory() {
+    let calldata = (1..=32).collect::<Vec<u8>>();
+    let program = vec![
+        // copy 32 bytes of calldata, starting at 0, into memory at 32
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::CallDataCopy,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Mload,
+    ];
+    run_program_assert_result_with_calldata(program, calldata, 32);
+}
+
+#[test]
+fn calldatacopy_pads_with_zeros() {
+    let calldata = vec![0xff; 4];
+    let program = vec![
+        // write a non-zero word to check it gets overwritten
+        Operation::Push(BigUint::from(0xaa_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        // copy calldata[2..34] into memory at 0
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push0,
+        Operation::CallDataCopy,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    run_program_assert_result_with_calldata(program.clone(), calldata.clone(), 0);
+
+    let mut program = program;
+    program.extend([Operation::Push(BigUint::from(240_u8)), Operation::Shr]);
+    run_program_assert_result_with_calldata(program, calldata, 0xff);
+}
+
+#[test]
+fn calldatacopy_expands_memory() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::CallDataCopy,
+        Operation::Msize,
+    ];
+    run_program_assert_result_with_calldata(program, vec![], 96);
+}
+
+#[test]
+fn calldatacopy_with_zero_size_does_not_expand_memory() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::CallDataCopy,
+        Operation::Msize,
+    ];
+    run_program_assert_result_with_calldata(program, vec![1, 2, 3], 0);
+}
+
+#[rstest]
+#[case(1_u64 << 32, 0)]
+#[case(32, 1_u64 << 32)]
+#[case(0x20, 0xFFFF_FFF0)]
+fn calldatacopy_out_of_gas_on_segment_too_big(#[case] size: u64, #[case] dest_offset: u64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(dest_offset)),
+        Operation::CallDataCopy,
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+}
+
+#[test]
+fn calldatacopy_with_stack_underflow() {
+    let program = vec![Operation::Push0, Operation::Push0, Operation::CallDataCopy];
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(0, 0)]
+#[case(1, 1)]
+#[case(33, 2)]
+fn calldatacopy_with_gas_cost(#[case] size: u8, #[case] words: i64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::CallDataCopy,
+        Operation::Push0,
+    ];
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::PUSH0 * 3
+        + gas_cost::CALLDATACOPY
+        + gas_cost::COPY_WORD * words
+        + memory_expansion_cost(1, words);
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+/// Runs the program with `code` as the code being executed, returning the stack top as
+/// in [`run_program_assert_stack_top`]
+fn run_program_assert_result_with_code(operations: Vec<Operation>, code: Vec<u8>, expected: u8) {
+    let mut context = SyscallContext::default();
+    context.set_code(code);
+    run_program_assert_stack_top(operations, context, expected, 1e7 as _);
+}
+
+#[rstest]
+#[case(vec![], 0)]
+#[case(vec![0x5F, 0x00], 2)]
+#[case(vec![0; 200], 200)]
+fn codesize(#[case] code: Vec<u8>, #[case] expected_size: u8) {
+    let program = vec![Operation::Codesize];
+    run_program_assert_result_with_code(program, code, expected_size);
+}
+
+#[test]
+fn codesize_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::Codesize);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn codesize_with_gas_cost() {
+    let program = vec![Operation::Codesize];
+    run_program_assert_gas_exact(program, 0, gas_cost::CODESIZE as _);
+}
+
+#[test]
+fn codecopy_copies_to_memory() {
+    let code = (1..=32).collect::<Vec<u8>>();
+    let program = vec![
+        // copy 32 bytes of code, starting at 0, into memory at 32
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Codecopy,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Mload,
+    ];
+    run_program_assert_result_with_code(program, code, 32);
+}
+
+#[test]
+fn codecopy_pads_with_zeros() {
+    let code = vec![0xff; 4];
+    let program = vec![
+        // write a non-zero word to check it gets overwritten
+        Operation::Push(BigUint::from(0xaa_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        // copy code[2..34] into memory at 0
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push0,
+        Operation::Codecopy,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    run_program_assert_result_with_code(program.clone(), code.clone(), 0);
+
+    let mut program = program;
+    program.extend([Operation::Push(BigUint::from(240_u8)), Operation::Shr]);
+    run_program_assert_result_with_code(program, code, 0xff);
+}
+
+#[test]
+fn codecopy_expands_memory() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Codecopy,
+        Operation::Msize,
+    ];
+    run_program_assert_result_with_code(program, vec![], 96);
+}
+
+#[rstest]
+#[case(1_u64 << 32, 0)]
+#[case(32, 1_u64 << 32)]
+fn codecopy_out_of_gas_on_segment_too_big(#[case] size: u64, #[case] dest_offset: u64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(dest_offset)),
+        Operation::Codecopy,
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+}
+
+#[test]
+fn codecopy_with_stack_underflow() {
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Codecopy];
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(0, 0)]
+#[case(1, 1)]
+#[case(33, 2)]
+fn codecopy_with_gas_cost(#[case] size: u8, #[case] words: i64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Codecopy,
+        Operation::Push0,
+    ];
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::PUSH0 * 3
+        + gas_cost::CODECOPY
+        + gas_cost::COPY_WORD * words
+        + memory_expansion_cost(1, words);
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[rstest]
+#[case(Operation::Return, ExitStatusCode::Return)]
+#[case(Operation::Revert, ExitStatusCode::Revert)]
+fn halt_with_output_memory_expansion_gas_exact(
+    #[case] op: Operation,
+    #[case] expected_status: ExitStatusCode,
+) {
+    let program = vec![Operation::Push(BigUint::from(33_u8)), Operation::Push0, op];
+    let needed_gas = gas_cost::PUSHN + gas_cost::PUSH0 + memory_expansion_cost(0, 2);
+
+    let mut context = SyscallContext::default();
+    let result = run_program(program.clone(), &mut context, needed_gas as _);
+    assert_eq!(result, expected_status.to_u8());
+    assert_eq!(context.return_values(), &[0; 33]);
+
+    let mut context = SyscallContext::default();
+    let result = run_program(program, &mut context, needed_gas as u64 - 1);
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+}
+
+#[rstest]
+#[case(Operation::Return, ExitStatusCode::Return)]
+#[case(Operation::Revert, ExitStatusCode::Revert)]
+fn halt_with_full_word_output(#[case] op: Operation, #[case] expected_status: ExitStatusCode) {
+    let value = BigUint::from_bytes_be(&(1..=32).collect::<Vec<u8>>());
+    let program = vec![
+        Operation::Push(value),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        op,
+    ];
+    let expected_output = (1..=32).collect::<Vec<u8>>();
+    run_program_assert_output(program, expected_status, &expected_output);
+}
+
+#[rstest]
+#[case(Operation::Return, ExitStatusCode::Return)]
+#[case(Operation::Revert, ExitStatusCode::Revert)]
+fn halt_with_partial_output(#[case] op: Operation, #[case] expected_status: ExitStatusCode) {
+    let value = BigUint::from_bytes_be(&(1..=32).collect::<Vec<u8>>());
+    let program = vec![
+        Operation::Push(value),
+        Operation::Push0,
+        Operation::Mstore,
+        // output memory[30..34]
+        Operation::Push(BigUint::from(4_u8)),
+        Operation::Push(BigUint::from(30_u8)),
+        op,
+    ];
+    run_program_assert_output(program, expected_status, &[31, 32, 0, 0]);
+}
+
+#[rstest]
+#[case(Operation::Return, ExitStatusCode::Return)]
+#[case(Operation::Revert, ExitStatusCode::Revert)]
+fn halt_with_empty_output(#[case] op: Operation, #[case] expected_status: ExitStatusCode) {
+    // the offset of an empty output is irrelevant
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(1000_u16)),
+        op,
+    ];
+    run_program_assert_output(program, expected_status, &[]);
+}
+
+#[rstest]
+#[case(Operation::Return)]
+#[case(Operation::Revert)]
+fn halt_with_output_stops_execution(#[case] op: Operation) {
+    // the operations after the halt shouldn't run
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        op,
+        Operation::Push(BigUint::from(10_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let mut context = SyscallContext::default();
+    run_program(program, &mut context, 1e7 as _);
+    assert!(context.return_values().is_empty());
+}
+
+#[test]
+fn revert_is_reported_by_the_syscall_context() {
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Revert];
+    let mut context = SyscallContext::default();
+    run_program(program, &mut context, 1e7 as _);
+    assert!(context.is_revert());
+
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Return];
+    let mut context = SyscallContext::default();
+    run_program(program, &mut context, 1e7 as _);
+    assert!(!context.is_revert());
+}
+
+#[rstest]
+#[case(Operation::Return)]
+#[case(Operation::Revert)]
+fn halt_with_output_with_stack_underflow(#[case] op: Operation) {
+    let program = vec![Operation::Push0, op];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn return_expands_memory() {
+    // return memory[64..96], which was never written
+    let program = vec![
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Return,
+    ];
+    run_program_assert_output(program, ExitStatusCode::Return, &[0; 32]);
+}
+
+#[test]
+fn invalid_halts_with_error() {
+    let program = vec![Operation::Invalid];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn invalid_stops_execution() {
+    // the return after the invalid shouldn't run
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Invalid,
+        Operation::Return,
+    ];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn invalid_is_not_a_revert() {
+    let program = vec![Operation::Invalid];
+    let mut context = SyscallContext::default();
+    let result = run_program(program, &mut context, 1e7 as _);
+    assert_ne!(result, ExitStatusCode::Revert.to_u8());
+    assert!(!context.is_revert());
+}
+
+#[test]
+fn undefined_opcode_halts_with_error() {
+    let program = Program::from_bytecode(&[0x5F, 0x0C]).unwrap();
+    assert_eq!(program.operations()[1], Operation::Undefined(0x0C));
+
+    let mut context = SyscallContext::default();
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+    assert!(context.return_values().is_empty());
+}
+
+#[test]
+fn undefined_opcode_not_executed_is_skipped() {
+    // [00] PUSH1 4
+    // [02] JUMP
+    // [03] 0x0C
+    // [04] JUMPDEST
+    // [05] STOP
+    let program = Program::from_bytecode(&[0x60, 0x04, 0x56, 0x0C, 0x5B, 0x00]).unwrap();
+    run_program_assert_output(program, ExitStatusCode::Stop, &[]);
+}
+
+#[test]
+fn stop_has_no_gas_cost() {
+    let program = vec![Operation::Push0, Operation::Stop];
+    let mut context = SyscallContext::default();
+    let result = run_program(program, &mut context, gas_cost::PUSH0 as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+}
+
+#[test]
+fn stop_discards_the_stack() {
+    // STOP returns no data, even with values in the stack
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Stop,
+    ];
+    run_program_assert_output(program, ExitStatusCode::Stop, &[]);
+}
+
+#[test]
+fn stop_skips_jumped_over_code() {
+    // [00] PUSH1 4
+    // [02] JUMP
+    // [03] INVALID
+    // [04] JUMPDEST
+    // [05] STOP
+    let program = vec![
+        Operation::Push(BigUint::from(4_u8)),
+        Operation::Jump,
+        Operation::Invalid,
+        Operation::Jumpdest { pc: 4 },
+        Operation::Stop,
+    ];
+    run_program_assert_output(program, ExitStatusCode::Stop, &[]);
+}
+
+#[test]
+fn gas_with_more_than_one_byte() {
+    const INITIAL_GAS: u64 = 1000;
+
+    // the second least significant byte of the remaining gas
+    let remaining_gas = INITIAL_GAS + RETURN_STACK_TOP_GAS - gas_cost::GAS as u64;
+    let expected_result = (remaining_gas >> 8) as u8;
+
+    let program = vec![
+        Operation::Gas,
+        Operation::Push(BigUint::from(8_u8)),
+        Operation::Shr,
+    ];
+    run_program_assert_result_with_gas(program, expected_result, INITIAL_GAS);
+}
+
+#[test]
+fn gas_upper_bits_are_zero() {
+    let program = vec![
+        Operation::Gas,
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Shr,
+    ];
+    run_program_assert_result_with_gas(program, 0, 1 << 62);
+}
+
+#[test]
+fn gas_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::Gas);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn pc_from_bytecode_counts_push_immediates() {
+    // [00] PUSH32 0xFF..FF
+    // [21] POP
+    // [22] PC
+    // [23] PUSH0
+    // [24] MSTORE
+    // [25] PUSH1 32
+    // [27] PUSH0
+    // [28] RETURN
+    let mut bytecode = vec![0x7F];
+    bytecode.extend([0xFF; 32]);
+    bytecode.extend([0x50, 0x58, 0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3]);
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let mut expected_output = [0; 32];
+    expected_output[31] = 0x22;
+    run_program_assert_output(program, ExitStatusCode::Return, &expected_output);
+}
+
+#[test]
+fn dead_code_and_solidity_metadata_are_skipped() {
+    let program = Program::from_assembly(
+        "
+            PUSH1 7
+            PUSH @end
+            JUMP
+            PUSH1 1
+        dead:
+            ADD
+        end:
+            PUSH0
+            MSTORE
+            PUSH1 32
+            PUSH0
+            RETURN
+            INVALID
+        ",
+    )
+    .unwrap();
+    // The metadata of solc, with a made-up hash
+    let mut bytecode = program.to_bytecode();
+    bytecode.extend([0xA2, 0x64]);
+    bytecode.extend(b"ipfs");
+    bytecode.extend([0x58, 0x22, 0x12, 0x20]);
+    bytecode.extend([0xEF; 32]);
+    bytecode.push(0x64);
+    bytecode.extend(b"solc");
+    bytecode.extend([0x43, 0x00, 0x08, 0x14, 0x00, 0x33]);
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let mut expected_output = [0; 32];
+    expected_output[31] = 7;
+    run_program_assert_output(program, ExitStatusCode::Return, &expected_output);
+}
+
+#[test]
+fn pc_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::PC { pc: 1024 });
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn sload_of_unwritten_slot_is_zero() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Sload];
+    run_program_assert_result(program, 0);
+}
+
+#[test]
+fn sstore_then_sload() {
+    let key = BigUint::from(1_u8);
+    let value = 42_u8;
+    let program = vec![
+        Operation::Push(BigUint::from(value)),
+        Operation::Push(key.clone()),
+        Operation::Sstore,
+        Operation::Push(key),
+        Operation::Sload,
+    ];
+    run_program_assert_result(program, value);
+}
+
+/// The address of the executing contract in the default environment
+const DEFAULT_ADDRESS: Address = [0; 20];
+
+#[test]
+fn sload_reads_from_initial_storage() {
+    let storage = InMemoryStorage::from_iter([(DEFAULT_ADDRESS, U256::from(1), U256::from(42))]);
+    let context = SyscallContext::with_storage(Box::new(storage));
+
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Sload];
+    run_program_assert_stack_top(program, context, 42, 1e7 as _);
+}
+
+#[test]
+fn sload_reads_from_executing_contract_storage() {
+    let address = [0x11; 20];
+    let storage = InMemoryStorage::from_iter([
+        (address, U256::from(1), U256::from(42)),
+        (DEFAULT_ADDRESS, U256::from(1), U256::from(7)),
+    ]);
+    let env = Env {
+        call: CallEnv {
+            address,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let context = SyscallContext::new(env, Vec::new(), Box::new(storage));
+
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Sload];
+    run_program_assert_stack_top(program, context, 42, 1e7 as _);
+}
+
+#[test]
+fn sstore_writes_to_storage() {
+    let key = U256 { lo: 1, hi: 1 };
+    let program = vec![
+        Operation::Push(BigUint::from(0xABCD_u16)),
+        Operation::Push((BigUint::from(1_u8) << 128_u32) + 1_u8),
+        Operation::Sstore,
+    ];
+    let mut context = SyscallContext::default();
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        context.storage().read(&DEFAULT_ADDRESS, &key),
+        U256::from(0xABCD)
+    );
+    assert_eq!(
+        context.storage().read(&DEFAULT_ADDRESS, &U256::from(1)),
+        U256::default()
+    );
+}
+
+#[test]
+fn sstore_overwrites_previous_value() {
+    let storage = InMemoryStorage::from_iter([(DEFAULT_ADDRESS, U256::from(1), U256::from(42))]);
+    let mut context = SyscallContext::with_storage(Box::new(storage));
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+    ];
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        context.storage().read(&DEFAULT_ADDRESS, &U256::from(1)),
+        U256::default()
+    );
+}
+
+#[test]
+fn sload_with_stack_underflow() {
+    let program = vec![Operation::Sload];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn sstore_with_stack_underflow() {
+    let program = vec![Operation::Push0, Operation::Sstore];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn sload_gas_exact() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Sload];
+    // The slot hasn't been accessed yet
+    let needed_gas = gas_cost::PUSHN + gas_cost::COLD_SLOAD;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn sload_of_warm_slot_gas_exact() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+    ];
+    let needed_gas = gas_cost::PUSHN * 2 + gas_cost::COLD_SLOAD + gas_cost::SLOAD;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn sload_before_berlin_has_no_cold_cost() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Sload];
+    let needed_gas = (gas_cost::PUSHN + gas_cost::SLOAD_PRE_BERLIN) as u64;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, SpecId::Istanbul);
+}
+
+#[rstest]
+#[case::frontier(SpecId::Frontier, 50)]
+#[case::tangerine_whistle(SpecId::TangerineWhistle, 200)]
+#[case::istanbul(SpecId::Istanbul, 800)]
+#[case::berlin(SpecId::Berlin, 2100)]
+fn sload_gas_of_each_hardfork(#[case] spec_id: SpecId, #[case] sload_gas: u64) {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Sload];
+    let needed_gas = gas_cost::PUSHN as u64 + sload_gas;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, spec_id);
+}
+
+#[test]
+fn sstore_gas_exact() {
+    let program = vec![
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Sload,
+    ];
+    // Only the first access to the slot is cold
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::PUSH0 * 2
+        + gas_cost::SSTORE_SET
+        + gas_cost::COLD_SLOAD
+        + gas_cost::SLOAD;
+    run_program_assert_gas_exact(program, 3, needed_gas as _);
+}
+
+/// Each case stores `values` in order, in a slot whose original value is `original`,
+/// and checks the total gas cost of the SSTOREs and the refund they earn
+#[rstest]
+#[case::unchanged(0, &[0], gas_cost::SSTORE, 0)]
+#[case::set(0, &[1], gas_cost::SSTORE_SET, 0)]
+#[case::reset(1, &[2], gas_cost::SSTORE_RESET, 0)]
+#[case::clear(1, &[0], gas_cost::SSTORE_RESET, gas_cost::SSTORE_CLEAR_REFUND)]
+#[case::unchanged_nonzero(1, &[1], gas_cost::SSTORE, 0)]
+#[case::set_twice(0, &[1, 2], gas_cost::SSTORE_SET + gas_cost::SSTORE, 0)]
+#[case::set_and_restore(
+    0,
+    &[1, 0],
+    gas_cost::SSTORE_SET + gas_cost::SSTORE,
+    gas_cost::SSTORE_SET - gas_cost::SSTORE
+)]
+#[case::reset_and_restore(
+    1,
+    &[2, 1],
+    gas_cost::SSTORE_RESET + gas_cost::SSTORE,
+    gas_cost::SSTORE_RESET - gas_cost::SSTORE
+)]
+#[case::clear_and_restore(
+    1,
+    &[0, 1],
+    gas_cost::SSTORE_RESET + gas_cost::SSTORE,
+    gas_cost::SSTORE_RESET - gas_cost::SSTORE
+)]
+#[case::clear_and_reset(1, &[0, 2], gas_cost::SSTORE_RESET + gas_cost::SSTORE, 0)]
+#[case::reset_and_clear(
+    1,
+    &[2, 0],
+    gas_cost::SSTORE_RESET + gas_cost::SSTORE,
+    gas_cost::SSTORE_CLEAR_REFUND
+)]
+fn sstore_gas_cost_and_refund(
+    #[case] original: u8,
+    #[case] values: &[u8],
+    #[case] expected_gas: i64,
+    #[case] expected_refund: i64,
+) {
+    const INITIAL_GAS: u64 = 1e7 as _;
+    let storage = InMemoryStorage::from_iter([(
+        DEFAULT_ADDRESS,
+        U256::default(),
+        U256::from(original as u128),
+    )]);
+    let mut context = SyscallContext::with_storage(Box::new(storage));
+
+    let mut program = Vec::new();
+    for value in values {
+        program.extend([
+            Operation::Push(BigUint::from(*value)),
+            Operation::Push0,
+            Operation::Sstore,
+        ]);
+    }
+    // Returns the gas left after the SSTOREs
+    program.extend([
+        Operation::Gas,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let pushes_gas = (gas_cost::PUSHN + gas_cost::PUSH0) * values.len() as i64;
+    // The first SSTORE also pays for accessing the cold slot
+    let sstores_gas = expected_gas + gas_cost::COLD_SLOAD;
+    let remaining_gas = INITIAL_GAS - (pushes_gas + sstores_gas + gas_cost::GAS) as u64;
+
+    let result = run_program(program, &mut context, INITIAL_GAS);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(
+        context.return_values(),
+        U256::from(remaining_gas as u128).to_be_bytes()
+    );
+    assert_eq!(context.gas_refund(INITIAL_GAS), expected_refund as u64);
+}
+
+/// Like [`sstore_gas_cost_and_refund`], in the given fork. The slot starts cold,
+/// which only costs more since Berlin
+#[rstest]
+#[case::frontier_set(SpecId::Frontier, 0, &[1], 20000, 0)]
+#[case::frontier_unchanged(SpecId::Frontier, 0, &[0], 5000, 0)]
+#[case::frontier_reset(SpecId::Frontier, 1, &[2], 5000, 0)]
+#[case::frontier_clear(SpecId::Frontier, 1, &[0], 5000, 15000)]
+#[case::frontier_set_twice(SpecId::Frontier, 0, &[1, 2], 20000 + 5000, 0)]
+#[case::petersburg_set_and_restore(SpecId::Petersburg, 0, &[1, 0], 20000 + 5000, 15000)]
+#[case::istanbul_unchanged(SpecId::Istanbul, 1, &[1], 800, 0)]
+#[case::istanbul_reset(SpecId::Istanbul, 1, &[2], 5000, 0)]
+#[case::istanbul_clear(SpecId::Istanbul, 1, &[0], 5000, 15000)]
+#[case::istanbul_set_twice(SpecId::Istanbul, 0, &[1, 2], 20000 + 800, 0)]
+#[case::istanbul_set_and_restore(SpecId::Istanbul, 0, &[1, 0], 20000 + 800, 20000 - 800)]
+#[case::berlin_unchanged(SpecId::Berlin, 1, &[1], 2100 + 100, 0)]
+#[case::berlin_reset(SpecId::Berlin, 1, &[2], 2100 + 2900, 0)]
+#[case::berlin_clear(SpecId::Berlin, 1, &[0], 2100 + 2900, 15000)]
+#[case::berlin_set_and_restore(SpecId::Berlin, 0, &[1, 0], 2100 + 20000 + 100, 20000 - 100)]
+#[case::london_clear(SpecId::London, 1, &[0], 2100 + 2900, 4800)]
+fn sstore_gas_cost_and_refund_by_fork(
+    #[case] spec_id: SpecId,
+    #[case] original: u8,
+    #[case] values: &[u8],
+    #[case] expected_gas: i64,
+    #[case] expected_refund: i64,
+) {
+    const INITIAL_GAS: u64 = 1e7 as _;
+    let storage = InMemoryStorage::from_iter([(
+        DEFAULT_ADDRESS,
+        U256::default(),
+        U256::from(original as u128),
+    )]);
+    let env = Env {
+        spec_id,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::new(env, Vec::new(), Box::new(storage));
+
+    // PUSH0 wasn't introduced in most of these forks
+    let zero = || Operation::Push(BigUint::ZERO);
+    let mut program = Vec::new();
+    for value in values {
+        program.extend([
+            Operation::Push(BigUint::from(*value)),
+            zero(),
+            Operation::Sstore,
+        ]);
+    }
+    // Returns the gas left after the SSTOREs
+    program.extend([
+        Operation::Gas,
+        zero(),
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        zero(),
+        Operation::Return,
+    ]);
+    let pushes_gas = gas_cost::PUSHN * 2 * values.len() as i64;
+    let remaining_gas = INITIAL_GAS - (pushes_gas + expected_gas + gas_cost::GAS) as u64;
+
+    let result = run_program_with_spec(program, &mut context, INITIAL_GAS, spec_id);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(
+        context.return_values(),
+        U256::from(remaining_gas as u128).to_be_bytes()
+    );
+    assert_eq!(context.gas_refund(INITIAL_GAS), expected_refund as u64);
+}
+
+#[test]
+fn sstore_before_istanbul_has_no_sentry() {
+    let zero = || Operation::Push(BigUint::ZERO);
+    let program = vec![zero(), zero(), Operation::Sstore];
+    let needed_gas = (gas_cost::PUSHN * 2 + gas_cost::SSTORE_RESET_PRE_BERLIN) as u64;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, SpecId::Petersburg);
+}
+
+#[test]
+fn sstore_refund_is_capped() {
+    let storage = InMemoryStorage::from_iter([(DEFAULT_ADDRESS, U256::default(), U256::from(1))]);
+    let mut context = SyscallContext::with_storage(Box::new(storage));
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Sstore];
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    // At most a fifth of the gas used is refunded (EIP-3529)
+    assert_eq!(context.gas_refund(10000), 2000);
+    assert_eq!(
+        context.gas_refund(1e7 as _),
+        gas_cost::SSTORE_CLEAR_REFUND as u64
+    );
+}
+
+#[test]
+fn sstore_runs_out_of_gas_at_sentry() {
+    // Storing zero in an empty slot costs less than the sentry,
+    // but there must be more gas left than the sentry to store it (EIP-2200)
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Sstore];
+    let needed_gas = gas_cost::PUSH0 * 2 + gas_cost::SSTORE_SENTRY + 1;
+
+    let mut context = SyscallContext::default();
+    let result = run_program(program.clone(), &mut context, needed_gas as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    run_program_assert_out_of_gas(program, needed_gas as u64 - 1);
+}
+
+#[test]
+fn sstore_without_enough_gas_does_not_write() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Sstore,
+    ];
+    let needed_gas =
+        gas_cost::PUSHN + gas_cost::PUSH0 + gas_cost::SSTORE_SET + gas_cost::COLD_SLOAD;
+
+    let mut context = SyscallContext::default();
+    let result = run_program(program, &mut context, needed_gas as u64 - 1);
+
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+    assert_eq!(
+        context.storage().read(&DEFAULT_ADDRESS, &U256::default()),
+        U256::default()
+    );
+    assert_eq!(context.gas_refund(1e7 as _), 0);
+}
+
+#[test]
+fn tload_of_unwritten_slot_is_zero() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Tload];
+    run_program_assert_result(program, 0);
+}
+
+#[test]
+fn tstore_then_tload() {
+    let key = BigUint::from(1_u8);
+    let value = 42_u8;
+    let program = vec![
+        Operation::Push(BigUint::from(value)),
+        Operation::Push(key.clone()),
+        Operation::Tstore,
+        Operation::Push(key),
+        Operation::Tload,
+    ];
+    run_program_assert_result(program, value);
+}
+
+#[test]
+fn tstore_does_not_write_persistent_storage() {
+    let program = vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Tstore,
+    ];
+    let mut context = SyscallContext::default();
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        context
+            .transient_storage()
+            .read(&DEFAULT_ADDRESS, &U256::from(1)),
+        U256::from(42)
+    );
+    assert_eq!(
+        context.storage().read(&DEFAULT_ADDRESS, &U256::from(1)),
+        U256::default()
+    );
+}
+
+#[test]
+fn tload_does_not_read_persistent_storage() {
+    let storage = InMemoryStorage::from_iter([(DEFAULT_ADDRESS, U256::from(1), U256::from(42))]);
+    let context = SyscallContext::with_storage(Box::new(storage));
+
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Tload];
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn transient_storage_starts_empty_on_each_execution() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Tload,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Tstore,
+    ];
+    let mut context = SyscallContext::default();
+    let result = run_program(program.clone(), &mut context, 1e7 as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    // a fresh context holds the next transaction's transient storage
+    let mut context = SyscallContext::default();
+    let result = run_program(program, &mut context, 1e7 as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        context
+            .transient_storage()
+            .read(&DEFAULT_ADDRESS, &U256::from(1)),
+        U256::default()
+    );
+}
+
+#[test]
+fn tload_with_stack_underflow() {
+    let program = vec![Operation::Tload];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn tstore_with_stack_underflow() {
+    let program = vec![Operation::Push0, Operation::Tstore];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn tload_gas_exact() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Tload];
+    let needed_gas = gas_cost::PUSHN + gas_cost::TLOAD;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn tstore_gas_exact() {
+    let program = vec![
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push0,
+        Operation::Tstore,
+        Operation::Push0,
+        Operation::Tload,
+    ];
+    let needed_gas = gas_cost::PUSHN + gas_cost::PUSH0 * 2 + gas_cost::TSTORE + gas_cost::TLOAD;
+    run_program_assert_gas_exact(program, 3, needed_gas as _);
+}
+
+fn run_program_get_logs(program: Vec<Operation>) -> Vec<Log> {
+    let mut context = SyscallContext::default();
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    context.logs().to_vec()
+}
+
+#[test]
+fn log0_with_empty_data() {
+    let program = vec![Operation::Push0, Operation::Push0, Operation::Log(0)];
+    let logs = run_program_get_logs(program);
+    assert_eq!(logs, vec![Log::default()]);
+}
+
+#[test]
+fn log0_with_data_from_memory() {
+    let program = vec![
+        Operation::Push(BigUint::from(0xABCD_u16)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push(BigUint::from(30_u8)),
+        Operation::Log(0),
+    ];
+    let logs = run_program_get_logs(program);
+    let expected = Log {
+        data: vec![0xAB, 0xCD],
+        topics: vec![],
+    };
+    assert_eq!(logs, vec![expected]);
+}
+
+#[test]
+fn log_data_past_memory_end_is_zero() {
+    let program = vec![
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push(BigUint::from(64_u8)),
+        Operation::Log(0),
+        Operation::Msize,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let mut context = SyscallContext::default();
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(context.logs()[0].data, vec![0; 3]);
+    // the memory was expanded to fit the data
+    assert_eq!(context.return_values().last(), Some(&96));
+}
+
+#[rstest]
+#[case(1)]
+#[case(2)]
+#[case(3)]
+#[case(4)]
+fn log_topics_are_popped_in_order(#[case] topics: u8) {
+    let mut program: Vec<_> = (1..=topics)
+        .rev()
+        .map(|topic| Operation::Push(BigUint::from(topic)))
+        .collect();
+    program.extend([Operation::Push0, Operation::Push0, Operation::Log(topics)]);
+
+    let logs = run_program_get_logs(program);
+
+    let expected = Log {
+        data: vec![],
+        topics: (1..=topics)
+            .map(|topic| U256::from(topic as u128))
+            .collect(),
+    };
+    assert_eq!(logs, vec![expected]);
+}
+
+#[test]
+fn log_topic_keeps_the_whole_word() {
+    let topic = BigUint::from_bytes_be(&[0xFF; 32]);
+    let program = vec![
+        Operation::Push(topic),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+    ];
+    let logs = run_program_get_logs(program);
+    assert_eq!(logs[0].topics, vec![U256::from_be_bytes([0xFF; 32])]);
+}
+
+#[test]
+fn logs_are_kept_in_emission_order() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+    ];
+    let logs = run_program_get_logs(program);
+    let topics: Vec<_> = logs.iter().map(|log| log.topics.clone()).collect();
+    assert_eq!(topics, vec![vec![U256::from(1)], vec![U256::from(2)]]);
+}
+
+#[test]
+fn log_leaves_the_rest_of_the_stack() {
+    let program = vec![
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+    ];
+    run_program_assert_result(program, 7);
+}
+
+#[rstest]
+#[case(0)]
+#[case(1)]
+#[case(2)]
+#[case(3)]
+#[case(4)]
+fn log_with_stack_underflow(#[case] topics: u8) {
+    // one element short of the offset, size and topics
+    let mut program = vec![Operation::Push0; topics as usize + 1];
+    program.push(Operation::Log(topics));
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+// A size of 2^32 would be free to log if truncated to 32 bits
+#[case(1_u64 << 32, 0)]
+#[case(32, 1_u64 << 32)]
+#[case(0x20, 0xFFFF_FFF0)]
+fn log_out_of_gas_on_segment_too_big(#[case] size: u64, #[case] offset: u64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push(BigUint::from(offset)),
+        Operation::Log(0),
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+}
+
+#[rstest]
+#[case(0)]
+#[case(2)]
+#[case(4)]
+fn log_gas_exact(#[case] topics: u8) {
+    let mut program = vec![Operation::Push0; topics as usize];
+    program.extend([
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Push0,
+        Operation::Log(topics),
+        Operation::Push(BigUint::from(9_u8)),
+    ]);
+    let needed_gas = gas_cost::PUSH0 * (topics as i64 + 1)
+        + gas_cost::PUSHN * 2
+        + gas_cost::LOG
+        + gas_cost::LOG_TOPIC * topics as i64
+        + gas_cost::LOG_DATA_BYTE * 5;
+    run_program_assert_gas_exact(program, 9, needed_gas as _);
+}
+
+/// Runs the program, returning the stack top as a 32-byte output,
+/// and checks the whole word.
+fn run_program_assert_word(
+    mut operations: Vec<Operation>,
+    mut context: SyscallContext,
+    expected_word: [u8; 32],
+) {
+    operations.extend([
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+
+    let result = run_program(operations, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(context.return_values(), expected_word);
+}
+
+fn run_program_assert_word_with_env(operations: Vec<Operation>, env: Env, expected_word: [u8; 32]) {
+    let context = SyscallContext::with_env(env);
+    run_program_assert_word(operations, context, expected_word);
+}
+
+fn address_as_word(address: Address) -> [u8; 32] {
+    let mut word = [0; 32];
+    word[12..].copy_from_slice(&address);
+    word
+}
+
+fn test_env() -> Env {
+    Env {
+        chain_id: 0x0102_0304_0506_0708,
+        block: BlockEnv {
+            number: U256::from(0x66),
+            coinbase: [0x77; 20],
+            timestamp: U256::from(0x88),
+            gas_limit: U256::from(0x99),
+            basefee: U256::from(0xAA),
+            prevrandao: U256 {
+                hi: u128::MAX,
+                lo: 0xBB,
+            },
+            blob_basefee: U256::from(0xCC),
+        },
+        tx: TxEnv {
+            origin: [0x11; 20],
+            blob_hashes: vec![
+                U256 {
+                    hi: 0x01 << 120,
+                    lo: 0xD0,
+                },
+                U256 {
+                    hi: 0x01 << 120,
+                    lo: 0xD1,
+                },
+            ],
+            ..Default::default()
+        },
+        call: CallEnv {
+            address: [0x22; 20],
+            caller: [0x33; 20],
+            value: U256 {
+                hi: 0x44 << 120,
+                lo: 0x55,
+            },
+        },
+        ..Default::default()
+    }
+}
+
+#[test]
+fn address_returns_executing_contract() {
+    let env = test_env();
+    let expected = address_as_word(env.call.address);
+    run_program_assert_word_with_env(vec![Operation::Address], env, expected);
+}
+
+#[test]
+fn origin_returns_transaction_sender() {
+    let env = test_env();
+    let expected = address_as_word(env.tx.origin);
+    run_program_assert_word_with_env(vec![Operation::Origin], env, expected);
+}
+
+#[test]
+fn caller_returns_call_sender() {
+    let env = test_env();
+    let expected = address_as_word(env.call.caller);
+    run_program_assert_word_with_env(vec![Operation::Caller], env, expected);
+}
+
+#[test]
+fn callvalue_returns_the_whole_word() {
+    let env = test_env();
+    let mut expected = [0; 32];
+    expected[0] = 0x44;
+    expected[31] = 0x55;
+    run_program_assert_word_with_env(vec![Operation::Callvalue], env, expected);
+}
+
+#[test]
+fn gasprice_returns_legacy_gas_price() {
+    let mut env = test_env();
+    env.tx.gas_price = U256::from(0xEE);
+    let expected = u256_as_word(U256::from(0xEE));
+    run_program_assert_word_with_env(vec![Operation::Gasprice], env, expected);
+}
+
+#[rstest]
+#[case(0x100, 5, 0xAA + 5)]
+#[case(0xAC, 5, 0xAC)]
+fn gasprice_returns_effective_gas_price(
+    #[case] max_fee: u128,
+    #[case] max_priority_fee: u128,
+    #[case] expected: u128,
+) {
+    // The base fee is 0xAA
+    let mut env = test_env();
+    env.tx.gas_price = U256::from(0xEE);
+    env.tx.max_fee_per_gas = Some(U256::from(max_fee));
+    env.tx.max_priority_fee_per_gas = U256::from(max_priority_fee);
+    let expected = u256_as_word(U256::from(expected));
+    run_program_assert_word_with_env(vec![Operation::Gasprice], env, expected);
+}
+
+#[rstest]
+#[case(Operation::Address)]
+#[case(Operation::Origin)]
+#[case(Operation::Caller)]
+#[case(Operation::Callvalue)]
+#[case(Operation::Gasprice)]
+fn caller_context_defaults_to_zero(#[case] operation: Operation) {
+    run_program_assert_result(vec![operation], 0);
+}
+
+#[rstest]
+#[case(Operation::Address)]
+#[case(Operation::Origin)]
+#[case(Operation::Caller)]
+#[case(Operation::Callvalue)]
+#[case(Operation::Gasprice)]
+fn caller_context_with_stack_overflow(#[case] operation: Operation) {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(operation);
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(Operation::Address, gas_cost::ADDRESS)]
+#[case(Operation::Origin, gas_cost::ORIGIN)]
+#[case(Operation::Caller, gas_cost::CALLER)]
+#[case(Operation::Callvalue, gas_cost::CALLVALUE)]
+#[case(Operation::Gasprice, gas_cost::GASPRICE)]
+fn caller_context_gas_exact(#[case] operation: Operation, #[case] gas: i64) {
+    run_program_assert_gas_exact(vec![operation], 0, gas as _);
+}
+
+fn u256_as_word(value: U256) -> [u8; 32] {
+    value.to_be_bytes()
+}
+
+#[test]
+fn coinbase_returns_block_beneficiary() {
+    let env = test_env();
+    let expected = address_as_word(env.block.coinbase);
+    run_program_assert_word_with_env(vec![Operation::Coinbase], env, expected);
+}
+
+#[test]
+fn timestamp_returns_block_timestamp() {
+    let env = test_env();
+    let expected = u256_as_word(env.block.timestamp);
+    run_program_assert_word_with_env(vec![Operation::Timestamp], env, expected);
+}
+
+#[test]
+fn number_returns_block_number() {
+    let env = test_env();
+    let expected = u256_as_word(env.block.number);
+    run_program_assert_word_with_env(vec![Operation::Number], env, expected);
+}
+
+#[test]
+fn prevrandao_returns_the_whole_word() {
+    let env = test_env();
+    let expected = u256_as_word(env.block.prevrandao);
+    run_program_assert_word_with_env(vec![Operation::Prevrandao], env, expected);
+}
+
+#[test]
+fn gaslimit_returns_block_gas_limit() {
+    let env = test_env();
+    let expected = u256_as_word(env.block.gas_limit);
+    run_program_assert_word_with_env(vec![Operation::Gaslimit], env, expected);
+}
+
+#[test]
+fn chainid_returns_chain_id() {
+    let env = test_env();
+    let mut expected = [0; 32];
+    expected[24..].copy_from_slice(&env.chain_id.to_be_bytes());
+    run_program_assert_word_with_env(vec![Operation::Chainid], env, expected);
+}
+
+#[test]
+fn basefee_returns_block_base_fee() {
+    let env = test_env();
+    let expected = u256_as_word(env.block.basefee);
+    run_program_assert_word_with_env(vec![Operation::Basefee], env, expected);
+}
+
+#[rstest]
+#[case(Operation::Coinbase)]
+#[case(Operation::Timestamp)]
+#[case(Operation::Number)]
+#[case(Operation::Prevrandao)]
+#[case(Operation::Gaslimit)]
+#[case(Operation::Chainid)]
+#[case(Operation::Basefee)]
+fn block_env_defaults_to_zero(#[case] operation: Operation) {
+    run_program_assert_result(vec![operation], 0);
+}
+
+#[rstest]
+#[case(Operation::Coinbase)]
+#[case(Operation::Timestamp)]
+#[case(Operation::Number)]
+#[case(Operation::Prevrandao)]
+#[case(Operation::Gaslimit)]
+#[case(Operation::Chainid)]
+#[case(Operation::Basefee)]
+fn block_env_with_stack_overflow(#[case] operation: Operation) {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(operation);
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(Operation::Coinbase, gas_cost::COINBASE)]
+#[case(Operation::Timestamp, gas_cost::TIMESTAMP)]
+#[case(Operation::Number, gas_cost::NUMBER)]
+#[case(Operation::Prevrandao, gas_cost::PREVRANDAO)]
+#[case(Operation::Gaslimit, gas_cost::GASLIMIT)]
+#[case(Operation::Chainid, gas_cost::CHAINID)]
+#[case(Operation::Basefee, gas_cost::BASEFEE)]
+fn block_env_gas_exact(#[case] operation: Operation, #[case] gas: i64) {
+    run_program_assert_gas_exact(vec![operation], 0, gas as _);
+}
+
+const BLOCKHASH_CURRENT_BLOCK: u128 = 1000;
+
+/// A context at block [`BLOCKHASH_CURRENT_BLOCK`], where each block's hash is its number
+/// with the upper bits set.
+fn blockhash_context() -> SyscallContext {
+    let env = Env {
+        block: BlockEnv {
+            number: U256::from(BLOCKHASH_CURRENT_BLOCK),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let hashes = (0..=BLOCKHASH_CURRENT_BLOCK + 1).map(|number| {
+        let hash = U256 {
+            hi: u128::MAX,
+            lo: number,
+        };
+        (U256::from(number), hash)
+    });
+    let mut context = SyscallContext::with_env(env);
+    context.set_block_hashes(Box::new(InMemoryBlockHashes::from_iter(hashes)));
+    context
+}
+
+#[rstest]
+#[case(BLOCKHASH_CURRENT_BLOCK - 1)]
+#[case(BLOCKHASH_CURRENT_BLOCK - 100)]
+#[case(BLOCKHASH_CURRENT_BLOCK - 256)]
+fn blockhash_of_recent_block(#[case] number: u128) {
+    let program = vec![Operation::Push(BigUint::from(number)), Operation::Blockhash];
+    let expected = U256 {
+        hi: u128::MAX,
+        lo: number,
+    };
+    run_program_assert_word(program, blockhash_context(), expected.to_be_bytes());
+}
+
+#[rstest]
+#[case(BLOCKHASH_CURRENT_BLOCK - 257)]
+#[case(0)]
+#[case(BLOCKHASH_CURRENT_BLOCK)]
+#[case(BLOCKHASH_CURRENT_BLOCK + 1)]
+fn blockhash_out_of_range_is_zero(#[case] number: u128) {
+    let program = vec![Operation::Push(BigUint::from(number)), Operation::Blockhash];
+    run_program_assert_word(program, blockhash_context(), [0; 32]);
+}
+
+#[test]
+fn blockhash_of_number_wider_than_128_bits_is_zero() {
+    let number = BigUint::from(BLOCKHASH_CURRENT_BLOCK - 1) + (BigUint::from(1_u8) << 128_u32);
+    let program = vec![Operation::Push(number), Operation::Blockhash];
+    run_program_assert_word(program, blockhash_context(), [0; 32]);
+}
+
+#[test]
+fn blockhash_near_genesis() {
+    // at the first blocks, there are less than 256 previous blocks
+    let env = Env {
+        block: BlockEnv {
+            number: U256::from(3),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+    context.set_block_hashes(Box::new(InMemoryBlockHashes::from_iter([(
+        U256::from(0),
+        U256::from(0xAB),
+    )])));
+
+    let program = vec![Operation::Push0, Operation::Blockhash];
+    run_program_assert_word(program, context, U256::from(0xAB).to_be_bytes());
+}
+
+#[test]
+fn blockhash_without_provider_is_zero() {
+    let program = vec![Operation::Push0, Operation::Blockhash];
+    let env = Env {
+        block: BlockEnv {
+            number: U256::from(1),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    run_program_assert_word_with_env(program, env, [0; 32]);
+}
+
+#[test]
+fn blockhash_with_stack_underflow() {
+    let program = vec![Operation::Blockhash];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn blockhash_gas_exact() {
+    let program = vec![Operation::Push0, Operation::Blockhash];
+    let needed_gas = gas_cost::PUSH0 + gas_cost::BLOCKHASH;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+const CONTRACT_ADDRESS: Address = [0xAA; 20];
+const EMPTY_ACCOUNT_ADDRESS: Address = [0xBB; 20];
+const EOA_ADDRESS: Address = [0xCC; 20];
+const MISSING_ADDRESS: Address = [0xDD; 20];
+const CONTRACT_CODE: [u8; 5] = [0x60, 0x01, 0x60, 0x02, 0x01];
+
+/// A context with a contract, an empty account and an externally owned account
+fn extcode_context() -> SyscallContext {
+    let accounts = InMemoryAccounts::from_iter([
+        (
+            CONTRACT_ADDRESS,
+            AccountInfo {
+                nonce: 1,
+                code: CONTRACT_CODE.to_vec(),
+                ..Default::default()
+            },
+        ),
+        (EMPTY_ACCOUNT_ADDRESS, AccountInfo::default()),
+        (
+            EOA_ADDRESS,
+            AccountInfo {
+                balance: U256::from(1),
+                ..Default::default()
+            },
+        ),
+    ]);
+    let mut context = SyscallContext::default();
+    context.set_accounts(Box::new(accounts));
+    context
+}
+
+fn push_address(address: Address) -> Operation {
+    Operation::Push(BigUint::from_bytes_be(&address))
+}
+
+#[rstest]
+#[case(CONTRACT_ADDRESS, CONTRACT_CODE.len() as u8)]
+#[case(EMPTY_ACCOUNT_ADDRESS, 0)]
+#[case(EOA_ADDRESS, 0)]
+#[case(MISSING_ADDRESS, 0)]
+fn extcodesize(#[case] address: Address, #[case] expected_size: u8) {
+    let program = vec![push_address(address), Operation::Extcodesize];
+    run_program_assert_stack_top(program, extcode_context(), expected_size, 1e7 as _);
+}
+
+#[test]
+fn extcodesize_ignores_upper_address_bits() {
+    let address = BigUint::from_bytes_be(&CONTRACT_ADDRESS) + (BigUint::from(1_u8) << 200_u32);
+    let program = vec![Operation::Push(address), Operation::Extcodesize];
+    let expected_size = CONTRACT_CODE.len() as u8;
+    run_program_assert_stack_top(program, extcode_context(), expected_size, 1e7 as _);
+}
+
+#[test]
+fn extcodehash_of_contract() {
+    let expected_hash = AccountInfo {
+        code: CONTRACT_CODE.to_vec(),
+        ..Default::default()
+    }
+    .code_hash();
+    let program = vec![push_address(CONTRACT_ADDRESS), Operation::Extcodehash];
+    run_program_assert_word(program, extcode_context(), expected_hash.to_be_bytes());
+}
+
+#[test]
+fn extcodehash_of_account_without_code_is_empty_hash() {
+    let expected_hash =
+        biguint_from_hex("c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470");
+    let mut expected_word = [0; 32];
+    expected_word.copy_from_slice(&expected_hash.to_bytes_be());
+    let program = vec![push_address(EOA_ADDRESS), Operation::Extcodehash];
+    run_program_assert_word(program, extcode_context(), expected_word);
+}
+
+#[rstest]
+#[case(EMPTY_ACCOUNT_ADDRESS)]
+#[case(MISSING_ADDRESS)]
+fn extcodehash_of_empty_or_missing_account_is_zero(#[case] address: Address) {
+    let program = vec![push_address(address), Operation::Extcodehash];
+    run_program_assert_word(program, extcode_context(), [0; 32]);
+}
+
+#[test]
+fn extcodecopy_copies_code_into_memory() {
+    let program = vec![
+        Operation::Push(BigUint::from(CONTRACT_CODE.len())),
+        Operation::Push0,
+        Operation::Push(BigUint::from(27_u8)),
+        push_address(CONTRACT_ADDRESS),
+        Operation::Extcodecopy,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    let mut expected_word = [0; 32];
+    expected_word[27..].copy_from_slice(&CONTRACT_CODE);
+    run_program_assert_word(program, extcode_context(), expected_word);
+}
+
+#[test]
+fn extcodecopy_pads_with_zeros_past_the_end_of_code() {
+    // fill the word first, to check the padding is written
+    let program = vec![
+        Operation::Push(BigUint::from_bytes_be(&[0xFF; 32])),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push0,
+        push_address(CONTRACT_ADDRESS),
+        Operation::Extcodecopy,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    let mut expected_word = [0; 32];
+    expected_word[..2].copy_from_slice(&CONTRACT_CODE[3..]);
+    run_program_assert_word(program, extcode_context(), expected_word);
+}
+
+#[test]
+fn extcodecopy_of_missing_account_writes_zeros() {
+    let program = vec![
+        Operation::Push(BigUint::from_bytes_be(&[0xFF; 32])),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        push_address(MISSING_ADDRESS),
+        Operation::Extcodecopy,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    run_program_assert_word(program, extcode_context(), [0; 32]);
+}
+
+#[test]
+fn extcodecopy_with_huge_code_offset_writes_zeros() {
+    let program = vec![
+        Operation::Push(BigUint::from_bytes_be(&[0xFF; 32])),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push(BigUint::from_bytes_be(&[0xFF; 32])),
+        Operation::Push0,
+        push_address(CONTRACT_ADDRESS),
+        Operation::Extcodecopy,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    run_program_assert_word(program, extcode_context(), [0; 32]);
+}
+
+#[test]
+fn extcodecopy_with_zero_size_does_not_expand_memory() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push(BigUint::from(1000_u16)),
+        push_address(CONTRACT_ADDRESS),
+        Operation::Extcodecopy,
+        Operation::Msize,
+    ];
+    run_program_assert_stack_top(program, extcode_context(), 0, 1e7 as _);
+}
+
+#[rstest]
+#[case(1_u64 << 32, 0)]
+#[case(32, 1_u64 << 32)]
+#[case(0x20, 0xFFFF_FFF0)]
+fn extcodecopy_out_of_gas_on_segment_too_big(#[case] size: u64, #[case] dest_offset: u64) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(dest_offset)),
+        Operation::Push0,
+        Operation::Extcodecopy,
+    ];
+    run_program_assert_out_of_gas(program, 10_000_000);
+}
+
+#[rstest]
+#[case(Operation::Extcodesize, 1)]
+#[case(Operation::Extcodehash, 1)]
+#[case(Operation::Extcodecopy, 4)]
+fn extcode_with_stack_underflow(#[case] operation: Operation, #[case] arguments: usize) {
+    let mut program = vec![Operation::Push0; arguments - 1];
+    program.push(operation);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn extcodesize_gas_exact() {
+    // The account hasn't been accessed yet
+    let program = vec![Operation::Push0, Operation::Extcodesize];
+    let needed_gas = gas_cost::PUSH0 + gas_cost::COLD_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn extcodehash_gas_exact() {
+    let program = vec![Operation::Push0, Operation::Extcodehash];
+    let needed_gas = gas_cost::PUSH0 + gas_cost::COLD_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[rstest]
+#[case(Operation::Extcodesize)]
+#[case(Operation::Extcodehash)]
+fn extcode_of_warm_account_gas_exact(#[case] operation: Operation) {
+    let program = vec![
+        Operation::Push0,
+        operation.clone(),
+        Operation::Push0,
+        operation,
+    ];
+    let needed_gas =
+        gas_cost::PUSH0 * 2 + gas_cost::COLD_ACCOUNT_ACCESS + gas_cost::WARM_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn extcodesize_before_berlin_has_no_cold_cost() {
+    let zero = || Operation::Push(BigUint::ZERO);
+    let program = vec![zero(), Operation::Extcodesize];
+    let needed_gas = (gas_cost::PUSHN + gas_cost::EXTCODE_PRE_BERLIN) as u64;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, SpecId::Istanbul);
+}
+
+#[rstest]
+#[case::frontier(Operation::Extcodesize, 1, SpecId::Frontier, 20)]
+#[case::tangerine_whistle(Operation::Extcodesize, 1, SpecId::TangerineWhistle, 700)]
+#[case::berlin(Operation::Extcodesize, 1, SpecId::Berlin, 2600)]
+#[case::frontier_copy(Operation::Extcodecopy, 4, SpecId::Frontier, 20)]
+#[case::tangerine_whistle_copy(Operation::Extcodecopy, 4, SpecId::TangerineWhistle, 700)]
+#[case::berlin_copy(Operation::Extcodecopy, 4, SpecId::Berlin, 2600)]
+#[case::constantinople_hash(Operation::Extcodehash, 1, SpecId::Constantinople, 400)]
+#[case::istanbul_hash(Operation::Extcodehash, 1, SpecId::Istanbul, 700)]
+#[case::berlin_hash(Operation::Extcodehash, 1, SpecId::Berlin, 2600)]
+fn extcode_gas_of_each_hardfork(
+    #[case] operation: Operation,
+    #[case] arguments: usize,
+    #[case] spec_id: SpecId,
+    #[case] operation_gas: u64,
+) {
+    // EXTCODECOPY copies nothing, so it has no copy cost
+    let mut program = vec![Operation::Push(BigUint::ZERO); arguments];
+    program.push(operation);
+    let needed_gas = (gas_cost::PUSHN * arguments as i64) as u64 + operation_gas;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, spec_id);
+}
+
+#[test]
+fn extcodecopy_gas_exact() {
+    // copies two words, and leaves a zero on the stack to be returned
+    let program = vec![
+        Operation::Push(BigUint::from(33_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Extcodecopy,
+        Operation::Push0,
+    ];
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::PUSH0 * 4
+        + gas_cost::COLD_ACCOUNT_ACCESS
+        + gas_cost::COPY_WORD * 2
+        + memory_expansion_cost(1, 2);
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+const EOA_BALANCE: u128 = 1_000_000;
+
+/// Like [`extcode_context`], but the externally owned account has [`EOA_BALANCE`], and
+/// the program runs as the account at `address`
+fn balance_context(address: Address) -> SyscallContext {
+    let accounts = InMemoryAccounts::from_iter([
+        (
+            CONTRACT_ADDRESS,
+            AccountInfo {
+                nonce: 1,
+                code: CONTRACT_CODE.to_vec(),
+                ..Default::default()
+            },
+        ),
+        (
+            EOA_ADDRESS,
+            AccountInfo {
+                balance: U256::from(EOA_BALANCE),
+                ..Default::default()
+            },
+        ),
+    ]);
+    let mut env = Env::default();
+    env.call.address = address;
+    let mut context = SyscallContext::with_env(env);
+    context.set_accounts(Box::new(accounts));
+    context
+}
+
+#[rstest]
+#[case(EOA_ADDRESS, EOA_BALANCE)]
+#[case(CONTRACT_ADDRESS, 0)]
+#[case(MISSING_ADDRESS, 0)]
+fn balance(#[case] address: Address, #[case] expected_balance: u128) {
+    let program = vec![push_address(address), Operation::Balance];
+    let expected_word = U256::from(expected_balance).to_be_bytes();
+    run_program_assert_word(program, balance_context(CONTRACT_ADDRESS), expected_word);
+}
+
+#[test]
+fn balance_ignores_upper_address_bits() {
+    let address = BigUint::from_bytes_be(&EOA_ADDRESS) + (BigUint::from(1_u8) << 200_u32);
+    let program = vec![Operation::Push(address), Operation::Balance];
+    let expected_word = U256::from(EOA_BALANCE).to_be_bytes();
+    run_program_assert_word(program, balance_context(CONTRACT_ADDRESS), expected_word);
+}
+
+#[test]
+fn balance_with_stack_underflow() {
+    run_program_assert_halt(vec![Operation::Balance]);
+}
+
+#[test]
+fn balance_gas_exact() {
+    // The account hasn't been accessed yet
+    let program = vec![Operation::Push0, Operation::Balance];
+    let needed_gas = gas_cost::PUSH0 + gas_cost::COLD_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn balance_of_warm_account_gas_exact() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Balance,
+        Operation::Push0,
+        Operation::Balance,
+    ];
+    let needed_gas =
+        gas_cost::PUSH0 * 2 + gas_cost::COLD_ACCOUNT_ACCESS + gas_cost::WARM_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[rstest]
+#[case::frontier(SpecId::Frontier, 20)]
+#[case::tangerine_whistle(SpecId::TangerineWhistle, 400)]
+#[case::istanbul(SpecId::Istanbul, 700)]
+#[case::berlin(SpecId::Berlin, 2600)]
+fn balance_gas_of_each_hardfork(#[case] spec_id: SpecId, #[case] operation_gas: u64) {
+    let program = vec![Operation::Push(BigUint::ZERO), Operation::Balance];
+    let needed_gas = gas_cost::PUSHN as u64 + operation_gas;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, spec_id);
+}
+
+#[rstest]
+#[case(EOA_ADDRESS, EOA_BALANCE)]
+#[case(MISSING_ADDRESS, 0)]
+fn selfbalance(#[case] address: Address, #[case] expected_balance: u128) {
+    let program = vec![Operation::Selfbalance];
+    let expected_word = U256::from(expected_balance).to_be_bytes();
+    run_program_assert_word(program, balance_context(address), expected_word);
+}
+
+#[test]
+fn selfbalance_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::Selfbalance);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn selfbalance_gas_exact() {
+    // The executing account is always warm
+    let program = vec![Operation::Selfbalance];
+    run_program_assert_gas_exact(program, 0, gas_cost::SELFBALANCE as _);
+}
+
+fn returndata_context(return_data: &[u8]) -> SyscallContext {
+    let mut context = SyscallContext::default();
+    context.set_return_data(return_data.to_vec());
+    context
+}
+
+#[test]
+fn returndatasize_without_subcalls_is_zero() {
+    run_program_assert_result(vec![Operation::Returndatasize], 0);
+}
+
+#[test]
+fn returndatasize_returns_size_of_last_return_data() {
+    let program = vec![Operation::Returndatasize];
+    run_program_assert_stack_top(program, returndata_context(&[1; 40]), 40, 1e7 as _);
+}
+
+#[test]
+fn returndatacopy_copies_return_data_into_memory() {
+    let return_data: Vec<u8> = (1..=40).collect();
+    let program = vec![
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push(BigUint::from(8_u8)),
+        Operation::Push0,
+        Operation::Returndatacopy,
+        Operation::Push0,
+        Operation::Mload,
+    ];
+    let mut expected_word = [0; 32];
+    expected_word.copy_from_slice(&return_data[8..]);
+    run_program_assert_word(program, returndata_context(&return_data), expected_word);
+}
+
+#[test]
+fn returndatacopy_with_zero_size_at_the_end_is_valid() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(40_u8)),
+        Operation::Push(BigUint::from(1000_u16)),
+        Operation::Returndatacopy,
+        Operation::Msize,
+    ];
+    run_program_assert_stack_top(program, returndata_context(&[1; 40]), 0, 1e7 as _);
+}
+
+#[rstest]
+#[case(33, 8)]
+#[case(1, 40)]
+#[case(0, 41)]
+fn returndatacopy_out_of_bounds_halts(#[case] size: u8, #[case] offset: u8) {
+    let program = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push(BigUint::from(offset)),
+        Operation::Push0,
+        Operation::Returndatacopy,
+    ];
+    let mut context = returndata_context(&[1; 40]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+}
+
+#[test]
+fn returndatacopy_with_overflowing_range_halts() {
+    // offset + size wraps around to a value inside the return data
+    let program = vec![
+        Operation::Push(BigUint::from_bytes_be(&[0xFF; 32])),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push0,
+        Operation::Returndatacopy,
+    ];
+    let mut context = returndata_context(&[1; 40]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+}
+
+#[rstest]
+#[case(1_u64 << 32)]
+#[case(0xFFFF_FFF0)]
+fn returndatacopy_out_of_gas_on_destination_too_big(#[case] dest_offset: u64) {
+    let program = vec![
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push(BigUint::from(dest_offset)),
+        Operation::Returndatacopy,
+    ];
+    let mut context = returndata_context(&[1; 40]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+}
+
+#[test]
+fn returndatacopy_without_subcalls_halts() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Returndatacopy,
+    ];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn returndatasize_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::Returndatasize);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn returndatacopy_with_stack_underflow() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Returndatacopy,
+    ];
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn returndatasize_gas_exact() {
+    let program = vec![Operation::Returndatasize];
+    run_program_assert_gas_exact(program, 0, gas_cost::RETURNDATASIZE as _);
+}
+
+#[test]
+fn returndatacopy_gas_exact() {
+    // copies nothing, and leaves a zero on the stack to be returned
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Returndatacopy,
+        Operation::Push0,
+    ];
+    let needed_gas = gas_cost::PUSH0 * 4 + gas_cost::RETURNDATACOPY;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[rstest]
+#[case(0)]
+#[case(1)]
+fn blobhash_returns_versioned_hash(#[case] index: usize) {
+    let env = test_env();
+    let expected = u256_as_word(env.tx.blob_hashes[index]);
+    let program = vec![Operation::Push(BigUint::from(index)), Operation::Blobhash];
+    run_program_assert_word_with_env(program, env, expected);
+}
+
+#[test]
+fn blobhash_past_the_last_blob_is_zero() {
+    let program = vec![Operation::Push(BigUint::from(2_u8)), Operation::Blobhash];
+    run_program_assert_word_with_env(program, test_env(), [0; 32]);
+}
+
+#[test]
+fn blobhash_with_index_wider_than_128_bits_is_zero() {
+    let index = BigUint::from(1_u8) << 128_u32;
+    let program = vec![Operation::Push(index), Operation::Blobhash];
+    run_program_assert_word_with_env(program, test_env(), [0; 32]);
+}
+
+#[test]
+fn blobhash_without_blobs_is_zero() {
+    let program = vec![Operation::Push0, Operation::Blobhash];
+    run_program_assert_result(program, 0);
+}
+
+#[test]
+fn blobbasefee_returns_blob_base_fee() {
+    let env = test_env();
+    let expected = u256_as_word(env.block.blob_basefee);
+    run_program_assert_word_with_env(vec![Operation::Blobbasefee], env, expected);
+}
+
+#[test]
+fn blobhash_with_stack_underflow() {
+    run_program_assert_halt(vec![Operation::Blobhash]);
+}
+
+#[test]
+fn blobbasefee_with_stack_overflow() {
+    let mut program = vec![Operation::Push0; 1024];
+    program.push(Operation::Blobbasefee);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn blobhash_gas_exact() {
+    let program = vec![Operation::Push0, Operation::Blobhash];
+    let needed_gas = gas_cost::PUSH0 + gas_cost::BLOBHASH;
+    run_program_assert_gas_exact(program, 0, needed_gas as _);
+}
+
+#[test]
+fn blobbasefee_gas_exact() {
+    let program = vec![Operation::Blobbasefee];
+    run_program_assert_gas_exact(program, 0, gas_cost::BLOBBASEFEE as _);
+}
+
+const CALLER_ADDRESS: Address = [0x10; 20];
+const CALLEE_ADDRESS: Address = [0x20; 20];
+const CALLER_BALANCE: u128 = 1000;
+
+/// A context executing at [`CALLER_ADDRESS`], with some balance,
+/// and an account at [`CALLEE_ADDRESS`] with the given code
+fn call_context(callee_code: Vec<Operation>) -> SyscallContext {
+    let call_env = CallEnv {
+        address: CALLER_ADDRESS,
+        ..Default::default()
+    };
+    call_context_with(call_env, vec![(CALLEE_ADDRESS, callee_code)])
+}
+
+/// A context executing the given call, with some balance at [`CALLER_ADDRESS`],
+/// and accounts with the given code
+fn call_context_with(
+    call_env: CallEnv,
+    contracts: Vec<(Address, Vec<Operation>)>,
+) -> SyscallContext {
+    let mut accounts = InMemoryAccounts::from_iter([(
+        CALLER_ADDRESS,
+        AccountInfo {
+            balance: U256::from(CALLER_BALANCE),
+            ..Default::default()
+        },
+    )]);
+    for (address, code) in contracts {
+        let account = AccountInfo {
+            code: Program::from(code).to_bytecode(),
+            ..Default::default()
+        };
+        accounts.insert(address, account);
+    }
+    let env = Env {
+        call: call_env,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+    context.set_accounts(Box::new(accounts));
+    context
+}
+
+/// Pushes the arguments of a CALL, and performs it.
+/// The arguments and output segments are given as `(offset, size)`
+fn call_operations(
+    gas: u64,
+    address: Address,
+    value: u64,
+    args: (u8, u8),
+    ret: (u8, u8),
+) -> Vec<Operation> {
+    vec![
+        Operation::Push(BigUint::from(ret.1)),
+        Operation::Push(BigUint::from(ret.0)),
+        Operation::Push(BigUint::from(args.1)),
+        Operation::Push(BigUint::from(args.0)),
+        Operation::Push(BigUint::from(value)),
+        push_address(address),
+        Operation::Push(BigUint::from(gas)),
+        Operation::Call,
+    ]
+}
+
+/// Like [`call_operations`], performing a CALLCODE instead
+fn callcode_operations(
+    gas: u64,
+    address: Address,
+    value: u64,
+    args: (u8, u8),
+    ret: (u8, u8),
+) -> Vec<Operation> {
+    let mut operations = call_operations(gas, address, value, args, ret);
+    *operations.last_mut().unwrap() = Operation::Callcode;
+    operations
+}
+
+/// Like [`call_operations`], performing `call` without pushing a value,
+/// as DELEGATECALL and STATICCALL don't take one
+fn valueless_call_operations(
+    call: Operation,
+    gas: u64,
+    address: Address,
+    args: (u8, u8),
+    ret: (u8, u8),
+) -> Vec<Operation> {
+    let mut operations = call_operations(gas, address, 0, args, ret);
+    // The value is the fifth argument pushed
+    operations.remove(4);
+    *operations.last_mut().unwrap() = call;
+    operations
+}
+
+/// Callee code returning the 32-byte word computed by `operation`
+fn callee_returning(operation: Operation) -> Vec<Operation> {
+    vec![
+        operation,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]
+}
+
+/// Callee code storing 42 at the slot 1
+fn callee_storing() -> Vec<Operation> {
+    vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+    ]
+}
+
+/// Callee code halting with `halt` and the word `0xAB` as output
+fn callee_output_word(halt: Operation) -> Vec<Operation> {
+    vec![
+        Operation::Push(BigUint::from(0xAB_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        halt,
+    ]
+}
+
+#[test]
+fn call_to_account_without_code_succeeds() {
+    let program = call_operations(1000, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    run_program_assert_stack_top(program, call_context(vec![]), 1, 1e7 as _);
+}
+
+#[test]
+fn call_to_callee_that_stops_succeeds() {
+    let program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let context = call_context(vec![Operation::Stop]);
+    run_program_assert_stack_top(program, context, 1, 1e7 as _);
+}
+
+#[test]
+fn call_copies_output_to_memory() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let context = call_context(callee_output_word(Operation::Return));
+    run_program_assert_stack_top(program, context, 0xAB, 1e7 as _);
+}
+
+#[test]
+fn call_only_copies_output_fitting_in_segment() {
+    // The last byte of the output doesn't fit, so the memory is left zeroed
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 31));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let context = call_context(callee_output_word(Operation::Return));
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn call_keeps_output_as_return_data() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend([Operation::Pop, Operation::Returndatasize]);
+    let context = call_context(callee_output_word(Operation::Return));
+    run_program_assert_stack_top(program, context, 32, 1e7 as _);
+}
+
+#[test]
+fn call_to_callee_that_reverts_fails() {
+    let program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let context = call_context(callee_output_word(Operation::Revert));
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn call_to_callee_out_of_gas_fails() {
+    let program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let callee_code = vec![
+        Operation::Jumpdest { pc: 0 },
+        Operation::Push0,
+        Operation::Jump,
+    ];
+    let context = call_context(callee_code);
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn call_to_callee_that_reverts_keeps_output() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let context = call_context(callee_output_word(Operation::Revert));
+    run_program_assert_stack_top(program, context, 0xAB, 1e7 as _);
+}
+
+#[test]
+fn call_to_callee_that_halts_has_no_return_data() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend([Operation::Pop, Operation::Returndatasize]);
+    let context = call_context(vec![Operation::Invalid]);
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn call_fails_when_callee_runs_out_of_gas() {
+    let program = call_operations(0, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let context = call_context(vec![Operation::Push0]);
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn call_passes_memory_segment_as_calldata() {
+    let mut program = vec![
+        Operation::Push(BigUint::from(0x42_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+    ];
+    program.extend(call_operations(1000, CALLEE_ADDRESS, 0, (0, 32), (32, 32)));
+    program.extend([
+        Operation::Pop,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Mload,
+    ]);
+    // The callee returns its calldata
+    let callee_code = vec![
+        Operation::CallDataSize,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::CallDataCopy,
+        Operation::CallDataSize,
+        Operation::Push0,
+        Operation::Return,
+    ];
+    run_program_assert_stack_top(program, call_context(callee_code), 0x42, 1e7 as _);
+}
+
+#[test]
+fn callee_sees_caller_address() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let callee_code = vec![
+        Operation::Caller,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let expected = address_as_word(CALLER_ADDRESS);
+    run_program_assert_word(program, call_context(callee_code), expected);
+}
+
+#[test]
+fn callee_sees_call_value() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 7, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let callee_code = vec![
+        Operation::Callvalue,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    run_program_assert_stack_top(program, call_context(callee_code), 7, 1e7 as _);
+}
+
+#[test]
+fn call_transfers_value() {
+    let program = call_operations(1000, EOA_ADDRESS, 100, (0, 0), (0, 0));
+    let mut context = call_context(vec![]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    let callee = context.accounts().account(&EOA_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE - 100));
+    assert_eq!(callee.balance, U256::from(100));
+}
+
+#[test]
+fn call_with_insufficient_balance_fails() {
+    let program = call_operations(1000, EOA_ADDRESS, 1001, (0, 0), (0, 0));
+    let mut context = call_context(vec![]);
+
+    let result = run_program(program.clone(), &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE));
+    assert_eq!(context.accounts().account(&EOA_ADDRESS), None);
+
+    run_program_assert_stack_top(program, call_context(vec![]), 0, 1e7 as _);
+}
+
+#[test]
+fn call_to_callee_that_reverts_returns_value() {
+    let program = call_operations(1000, CALLEE_ADDRESS, 100, (0, 0), (0, 0));
+    let mut context = call_context(vec![Operation::Push0, Operation::Push0, Operation::Revert]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE));
+}
+
+#[test]
+fn callee_writes_its_own_storage() {
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let callee_code = vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+    ];
+    let mut context = call_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let key = U256::from(1);
+    assert_eq!(
+        context.storage().read(&CALLEE_ADDRESS, &key),
+        U256::from(42)
+    );
+    assert_eq!(
+        context.storage().read(&CALLER_ADDRESS, &key),
+        U256::default()
+    );
+}
+
+/// Code storing one at slot zero, then reverting
+fn sstore_then_revert_code() -> Vec<Operation> {
+    vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ]
+}
+
+#[test]
+fn call_to_callee_that_reverts_rolls_back_its_storage() {
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let mut context = call_context(sstore_then_revert_code());
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let key = U256::default();
+    assert_eq!(
+        context.storage().read(&CALLEE_ADDRESS, &key),
+        U256::default()
+    );
+}
+
+#[test]
+fn call_to_callee_that_reverts_rolls_back_its_refund() {
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    // Clearing the slot it just set earns a refund
+    let callee_code = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ];
+    let mut context = call_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(context.gas_refund(1e7 as _), 0);
+}
+
+#[test]
+fn call_to_callee_that_reverts_rolls_back_its_calls() {
+    // The callee forwards half of the value it gets to an account, then reverts
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 100, (0, 0), (0, 0));
+    let mut callee_code = call_operations(1000, EOA_ADDRESS, 50, (0, 0), (0, 0));
+    callee_code.extend([Operation::Push0, Operation::Push0, Operation::Revert]);
+    let mut context = call_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE));
+    let eoa = context.accounts().account(&EOA_ADDRESS).unwrap_or_default();
+    assert_eq!(eoa.balance, U256::default());
+}
+
+#[rstest]
+#[case(Operation::Stop, 7)]
+#[case(Operation::Revert, 0)]
+fn callee_tstore_is_rolled_back_when_it_reverts(#[case] halt: Operation, #[case] expected: u8) {
+    // The callee runs as the caller with CALLCODE, so it writes the caller's transient storage
+    let mut program = callcode_operations(1e5 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Tload]);
+    let callee_code = vec![
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push0,
+        Operation::Tstore,
+        Operation::Push0,
+        Operation::Push0,
+        halt,
+    ];
+    run_program_assert_stack_top(program, call_context(callee_code), expected, 1e7 as _);
+}
+
+#[test]
+fn call_collects_callee_logs_on_success() {
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let callee_code = vec![Operation::Push0, Operation::Push0, Operation::Log(0)];
+    let mut context = call_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        context.logs(),
+        [Log {
+            address: CALLEE_ADDRESS,
+            ..Default::default()
+        }]
+    );
+}
+
+#[test]
+fn call_discards_callee_logs_on_failure() {
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let callee_code = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(0),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ];
+    let mut context = call_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert!(context.logs().is_empty());
+}
+
+#[rstest]
+// args_offset, args_size, ret_offset, ret_size
+#[case(0, 1_u64 << 32, 0, 0)]
+#[case(1_u64 << 32, 32, 0, 0)]
+#[case(0, 0, 0, 1_u64 << 32)]
+#[case(0, 0, 1_u64 << 32, 32)]
+#[case(0xFFFF_FFF0, 0x20, 0, 0)]
+#[case(0, 0, 0xFFFF_FFF0, 0x20)]
+fn call_out_of_gas_on_segment_too_big(
+    #[case] args_offset: u64,
+    #[case] args_size: u64,
+    #[case] ret_offset: u64,
+    #[case] ret_size: u64,
+) {
+    let program = vec![
+        Operation::Push(BigUint::from(ret_size)),
+        Operation::Push(BigUint::from(ret_offset)),
+        Operation::Push(BigUint::from(args_size)),
+        Operation::Push(BigUint::from(args_offset)),
+        Operation::Push0,
+        push_address(EOA_ADDRESS),
+        Operation::Push(BigUint::from(1000_u16)),
+        Operation::Call,
+    ];
+    let mut context = call_context(vec![]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+}
+
+#[test]
+fn call_with_stack_underflow() {
+    let mut program = call_operations(1000, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    program.remove(0);
+    run_program_assert_halt(program);
+}
+
+#[test]
+fn call_gas_exact() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        push_address(EOA_ADDRESS),
+        Operation::Push0,
+        Operation::Call,
+    ];
+    let needed_gas = gas_cost::PUSH0 * 6 + gas_cost::PUSHN + gas_cost::COLD_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 1, needed_gas as _);
+}
+
+/// The word holding the gas left by [`call_operations`] with empty segments and no value
+/// to a cold callee, followed by a POP and a GAS, given the gas used by the callee
+fn gas_left_after_call(callee_gas: i64) -> [u8; 32] {
+    let caller_gas =
+        gas_cost::PUSHN * 7 + gas_cost::COLD_ACCOUNT_ACCESS + gas_cost::POP + gas_cost::GAS;
+    let gas_left = 1e7 as i64 - caller_gas - callee_gas;
+    U256::from(gas_left as u128).to_be_bytes()
+}
+
+#[test]
+fn call_returns_unused_gas() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend([Operation::Pop, Operation::Gas]);
+    let callee_code = vec![Operation::Push0, Operation::Pop];
+    let callee_gas = gas_cost::PUSH0 + gas_cost::POP;
+    run_program_assert_word(
+        program,
+        call_context(callee_code),
+        gas_left_after_call(callee_gas),
+    );
+}
+
+#[test]
+fn call_to_callee_that_reverts_returns_unused_gas() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend([Operation::Pop, Operation::Gas]);
+    let callee_code = vec![Operation::Push0, Operation::Push0, Operation::Revert];
+    let callee_gas = gas_cost::PUSH0 * 2;
+    run_program_assert_word(
+        program,
+        call_context(callee_code),
+        gas_left_after_call(callee_gas),
+    );
+}
+
+#[test]
+fn call_to_callee_that_halts_consumes_forwarded_gas() {
+    let mut program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend([Operation::Pop, Operation::Gas]);
+    let callee_code = vec![Operation::Invalid];
+    run_program_assert_word(
+        program,
+        call_context(callee_code),
+        gas_left_after_call(1000),
+    );
+}
+
+#[test]
+fn call_to_account_without_code_returns_all_gas() {
+    let mut program = call_operations(1000, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend([Operation::Pop, Operation::Gas]);
+    run_program_assert_word(program, call_context(vec![]), gas_left_after_call(0));
+}
+
+#[test]
+fn call_forwards_all_but_one_64th_of_remaining_gas() {
+    // The callee returns the gas it got, minus the cost of reading it
+    let mut program = call_operations(u64::MAX, CALLEE_ADDRESS, 0, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let context = call_context(callee_returning(Operation::Gas));
+
+    let remaining_gas = 1e7 as i64
+        - gas_cost::PUSHN * 7
+        - gas_cost::COLD_ACCOUNT_ACCESS
+        - memory_expansion_cost(0, 1);
+    let forwarded_gas = remaining_gas - remaining_gas / 64;
+    let expected_gas = forwarded_gas - gas_cost::GAS;
+    run_program_assert_word(
+        program,
+        context,
+        U256::from(expected_gas as u128).to_be_bytes(),
+    );
+}
+
+#[test]
+fn call_to_warm_callee_gas_exact() {
+    let mut program = call_operations(0, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    program.extend(call_operations(0, EOA_ADDRESS, 0, (0, 0), (0, 0)));
+    // Only the first call accesses the callee while it's cold
+    let needed_gas =
+        gas_cost::PUSHN * 14 + gas_cost::COLD_ACCOUNT_ACCESS + gas_cost::WARM_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 1, needed_gas as _);
+}
+
+#[test]
+fn call_before_berlin_has_no_cold_cost() {
+    let program = call_operations(0, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    let needed_gas = (gas_cost::PUSHN * 7 + gas_cost::CALL_PRE_BERLIN) as u64;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, SpecId::Istanbul);
+}
+
+#[rstest]
+#[case::frontier(SpecId::Frontier, 40 + 25000)]
+#[case::tangerine_whistle(SpecId::TangerineWhistle, 700 + 25000)]
+#[case::spurious_dragon(SpecId::SpuriousDragon, 700)]
+#[case::berlin(SpecId::Berlin, 2600)]
+fn call_gas_of_each_hardfork(#[case] spec_id: SpecId, #[case] call_gas: u64) {
+    // The callee doesn't exist, and creating it is charged before Spurious Dragon
+    let program = call_operations(0, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    let needed_gas = (gas_cost::PUSHN * 7) as u64 + call_gas;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, spec_id);
+}
+
+#[rstest]
+#[case::homestead(SpecId::Homestead, ExitStatusCode::OutOfGas)]
+#[case::tangerine_whistle(SpecId::TangerineWhistle, ExitStatusCode::Stop)]
+fn call_requesting_more_gas_than_left(#[case] spec_id: SpecId, #[case] expected: ExitStatusCode) {
+    // Before Tangerine Whistle, the callee is given all the gas requested, which must be left
+    let program = call_operations(1e6 as _, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    let mut context = SyscallContext::with_env(Env {
+        spec_id,
+        ..Default::default()
+    });
+
+    let result = run_program_with_spec(program, &mut context, 1e5 as _, spec_id);
+
+    assert_eq!(result, expected.to_u8());
+}
+
+#[test]
+fn call_with_value_gas_exact() {
+    // The default context can't afford the value, but the transfer is charged anyway,
+    // along with creating the callee's account
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        push_address(EOA_ADDRESS),
+        Operation::Push0,
+        Operation::Call,
+    ];
+    let needed_gas = gas_cost::PUSH0 * 5
+        + gas_cost::PUSHN * 2
+        + gas_cost::COLD_ACCOUNT_ACCESS
+        + gas_cost::CALL_VALUE_TRANSFER
+        + gas_cost::CALL_NEW_ACCOUNT;
+
+    // The unused stipend is given back after the call, so it must run out of gas before it
+    let mut context = SyscallContext::default();
+    let result = run_program(program.clone(), &mut context, needed_gas as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    run_program_assert_out_of_gas(program, needed_gas as u64 - 1);
+}
+
+#[test]
+fn call_with_value_to_existing_account_gas_exact() {
+    let program = call_operations(0, CALLEE_ADDRESS, 1, (0, 0), (0, 0));
+    let needed_gas =
+        gas_cost::PUSHN * 7 + gas_cost::COLD_ACCOUNT_ACCESS + gas_cost::CALL_VALUE_TRANSFER;
+
+    let mut context = call_context(vec![Operation::Stop]);
+    let result = run_program(program.clone(), &mut context, needed_gas as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    let mut context = call_context(vec![Operation::Stop]);
+    let result = run_program(program, &mut context, needed_gas as u64 - 1);
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+}
+
+#[test]
+fn call_before_spurious_dragon_charges_new_account_without_value() {
+    let program = call_operations(0, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    let needed_gas = (gas_cost::PUSHN * 7
+        + gas_cost::CALL_PRE_TANGERINE_WHISTLE
+        + gas_cost::CALL_NEW_ACCOUNT) as u64;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, SpecId::Homestead);
+}
+
+#[test]
+fn callcode_writes_caller_storage() {
+    let program = callcode_operations(1e5 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let mut context = call_context(callee_storing());
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let key = U256::from(1);
+    assert_eq!(
+        context.storage().read(&CALLER_ADDRESS, &key),
+        U256::from(42)
+    );
+    assert_eq!(
+        context.storage().read(&CALLEE_ADDRESS, &key),
+        U256::default()
+    );
+}
+
+#[rstest]
+#[case(Operation::Address)]
+#[case(Operation::Caller)]
+fn callcode_runs_as_the_caller(#[case] operation: Operation) {
+    let mut program = callcode_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let context = call_context(callee_returning(operation));
+    run_program_assert_word(program, context, address_as_word(CALLER_ADDRESS));
+}
+
+#[test]
+fn callcode_with_value_keeps_balance() {
+    let program = callcode_operations(1000, CALLEE_ADDRESS, 100, (0, 0), (0, 0));
+    let mut context = call_context(vec![Operation::Stop]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    let callee = context.accounts().account(&CALLEE_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE));
+    assert_eq!(callee.balance, U256::default());
+}
+
+#[test]
+fn callcode_with_insufficient_balance_fails() {
+    let program = callcode_operations(1000, CALLEE_ADDRESS, 1001, (0, 0), (0, 0));
+    let context = call_context(vec![Operation::Stop]);
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn delegatecall_writes_caller_storage() {
+    let program = valueless_call_operations(
+        Operation::Delegatecall,
+        1e5 as _,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 0),
+    );
+    let mut context = call_context(callee_storing());
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let key = U256::from(1);
+    assert_eq!(
+        context.storage().read(&CALLER_ADDRESS, &key),
+        U256::from(42)
+    );
+    assert_eq!(
+        context.storage().read(&CALLEE_ADDRESS, &key),
+        U256::default()
+    );
+}
+
+#[rstest]
+#[case(Operation::Address, address_as_word(CALLER_ADDRESS))]
+#[case(Operation::Caller, address_as_word([0x30; 20]))]
+#[case(Operation::Callvalue, u256_as_word(U256::from(5)))]
+fn delegatecall_keeps_call_context(#[case] operation: Operation, #[case] expected: [u8; 32]) {
+    let mut program = valueless_call_operations(
+        Operation::Delegatecall,
+        1000,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 32),
+    );
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let call_env = CallEnv {
+        address: CALLER_ADDRESS,
+        caller: [0x30; 20],
+        value: U256::from(5),
+    };
+    let context = call_context_with(
+        call_env,
+        vec![(CALLEE_ADDRESS, callee_returning(operation))],
+    );
+    run_program_assert_word(program, context, expected);
+}
+
+#[test]
+fn delegatecall_does_not_transfer_value() {
+    let program = valueless_call_operations(
+        Operation::Delegatecall,
+        1000,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 0),
+    );
+    let call_env = CallEnv {
+        address: CALLER_ADDRESS,
+        value: U256::from(5),
+        ..Default::default()
+    };
+    let mut context = call_context_with(call_env, vec![(CALLEE_ADDRESS, vec![Operation::Stop])]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    let callee = context.accounts().account(&CALLEE_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE));
+    assert_eq!(callee.balance, U256::default());
+}
+
+#[test]
+fn staticcall_copies_output_to_memory() {
+    let mut program =
+        valueless_call_operations(Operation::Staticcall, 1000, CALLEE_ADDRESS, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let context = call_context(callee_output_word(Operation::Return));
+    run_program_assert_stack_top(program, context, 0xAB, 1e7 as _);
+}
+
+#[test]
+fn staticcall_runs_in_callee_context() {
+    let mut program =
+        valueless_call_operations(Operation::Staticcall, 1000, CALLEE_ADDRESS, (0, 0), (0, 32));
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    let context = call_context(callee_returning(Operation::Address));
+    run_program_assert_word(program, context, address_as_word(CALLEE_ADDRESS));
+}
+
+#[rstest]
+#[case(callee_storing())]
+#[case(vec![Operation::Push0, Operation::Push0, Operation::Tstore])]
+#[case(vec![Operation::Push0, Operation::Push0, Operation::Log(0)])]
+#[case([vec![Operation::Push0; 3], vec![Operation::Log(1)]].concat())]
+#[case([vec![Operation::Push0; 6], vec![Operation::Log(4)]].concat())]
+#[case(call_operations(0, EOA_ADDRESS, 1, (0, 0), (0, 0)))]
+fn staticcall_forbids_state_modification(#[case] callee_code: Vec<Operation>) {
+    let program = valueless_call_operations(
+        Operation::Staticcall,
+        1e5 as _,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 0),
+    );
+    let mut context = call_context(callee_code.clone());
+
+    let result = run_program(program.clone(), &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert!(context.logs().is_empty());
+    assert_eq!(
+        context.storage().read(&CALLEE_ADDRESS, &U256::from(1)),
+        U256::default()
+    );
+
+    run_program_assert_stack_top(program, call_context(callee_code), 0, 1e7 as _);
+}
+
+#[test]
+fn staticcall_allows_call_without_value() {
+    let program = valueless_call_operations(
+        Operation::Staticcall,
+        1e5 as _,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 0),
+    );
+    let callee_code = call_operations(0, EOA_ADDRESS, 0, (0, 0), (0, 0));
+    run_program_assert_stack_top(program, call_context(callee_code), 1, 1e7 as _);
+}
+
+const NESTED_CALLEE_ADDRESS: Address = [0x21; 20];
+
+#[rstest]
+#[case(call_operations(1e4 as _, NESTED_CALLEE_ADDRESS, 0, (0, 0), (0, 0)))]
+#[case(callcode_operations(1e4 as _, NESTED_CALLEE_ADDRESS, 0, (0, 0), (0, 0)))]
+#[case(valueless_call_operations(
+    Operation::Delegatecall,
+    1e4 as _,
+    NESTED_CALLEE_ADDRESS,
+    (0, 0),
+    (0, 0)
+))]
+fn staticcall_forbids_state_modification_in_nested_calls(#[case] nested_call: Vec<Operation>) {
+    let mut program = valueless_call_operations(
+        Operation::Staticcall,
+        1e5 as _,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 32),
+    );
+    program.extend([Operation::Pop, Operation::Push0, Operation::Mload]);
+    // The callee returns whether its own call, which inherits the static context, succeeded
+    let mut callee_code = nested_call;
+    callee_code.extend([
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let call_env = CallEnv {
+        address: CALLER_ADDRESS,
+        ..Default::default()
+    };
+    let contracts = vec![
+        (CALLEE_ADDRESS, callee_code),
+        (NESTED_CALLEE_ADDRESS, callee_storing()),
+    ];
+    let context = call_context_with(call_env, contracts);
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[rstest]
+#[case(Operation::Callcode)]
+#[case(Operation::Delegatecall)]
+#[case(Operation::Staticcall)]
+fn call_variants_with_stack_underflow(#[case] call: Operation) {
+    let mut program = valueless_call_operations(call.clone(), 1000, EOA_ADDRESS, (0, 0), (0, 0));
+    if call != Operation::Callcode {
+        program.remove(0);
+    }
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(Operation::Delegatecall)]
+#[case(Operation::Staticcall)]
+fn valueless_call_gas_exact(#[case] call: Operation) {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        push_address(EOA_ADDRESS),
+        Operation::Push0,
+        call,
+    ];
+    let needed_gas = gas_cost::PUSH0 * 5 + gas_cost::PUSHN + gas_cost::COLD_ACCOUNT_ACCESS;
+    run_program_assert_gas_exact(program, 1, needed_gas as _);
+}
+
+#[test]
+fn callcode_with_value_gas_exact() {
+    // The default context can't afford the value, but the transfer is charged anyway
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        push_address(EOA_ADDRESS),
+        Operation::Push0,
+        Operation::Callcode,
+    ];
+    let needed_gas = gas_cost::PUSH0 * 5
+        + gas_cost::PUSHN * 2
+        + gas_cost::COLD_ACCOUNT_ACCESS
+        + gas_cost::CALL_VALUE_TRANSFER;
+
+    // The unused stipend is given back after the call, so it must run out of gas before it
+    let mut context = SyscallContext::default();
+    let result = run_program(program.clone(), &mut context, needed_gas as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    run_program_assert_out_of_gas(program, needed_gas as u64 - 1);
+}
+
+fn bytes_from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).unwrap())
+        .collect()
+}
+
+/// Runs a program calling the precompile with `gas` gas, passing the calldata as input.
+/// Returns whether the call succeeded, along with its output
+fn run_precompile_call(precompile: Precompile, gas: u64, input: Vec<u8>) -> (bool, Vec<u8>) {
+    let program = vec![
+        Operation::CallDataSize,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::CallDataCopy,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::CallDataSize,
+        Operation::Push0,
+        Operation::Push0,
+        push_address(precompile.address()),
+        Operation::Push(BigUint::from(gas)),
+        Operation::Call,
+        // Returns the output of the call, followed by whether it succeeded
+        Operation::Returndatasize,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Returndatacopy,
+        Operation::Returndatasize,
+        Operation::Mstore,
+        Operation::Returndatasize,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Add,
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let mut context = SyscallContext::with_calldata(input);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    let output = context.return_values();
+    let (output, success) = output.split_at(output.len() - 32);
+    (success[31] == 1, output.to_vec())
+}
+
+/// A message hash, followed by the `v`, `r` and `s` of its signature
+const ECRECOVER_INPUT: &str = concat!(
+    "18c547e4f7b0f325ad1e56f57e26c745b09a3e503d86e00e5255ff7f715d3d1c",
+    "000000000000000000000000000000000000000000000000000000000000001c",
+    "73b1693892219d736caba55bdb67216e485557ea6b6af75f37096c9aa6a5a75f",
+    "eeb940b1d03b21e36b0e47e79769f095fe2ab855bd91e3a38756b7d75a9c4549",
+);
+/// The address that signed [`ECRECOVER_INPUT`], as a word
+const ECRECOVER_OUTPUT: &str = "000000000000000000000000a94f5374fce5edbc8e2a8697c15331677e6ebf0b";
+
+#[test]
+fn ecrecover_recovers_the_signer() {
+    let input = bytes_from_hex(ECRECOVER_INPUT);
+
+    let result = run_precompile_call(Precompile::Ecrecover, 3000, input);
+
+    assert_eq!(result, (true, bytes_from_hex(ECRECOVER_OUTPUT)));
+}
+
+#[rstest]
+#[case::v_not_27_or_28(32, U256::from(29))]
+#[case::v_wider_than_a_byte(32, U256 { hi: 1, lo: 28 })]
+#[case::r_zero(64, U256::default())]
+#[case::s_past_the_curve_order(96, U256 { hi: u128::MAX, lo: u128::MAX })]
+fn ecrecover_with_invalid_signature_returns_nothing(#[case] offset: usize, #[case] word: U256) {
+    let mut input = bytes_from_hex(ECRECOVER_INPUT);
+    input[offset..offset + 32].copy_from_slice(&word.to_be_bytes());
+
+    let result = run_precompile_call(Precompile::Ecrecover, 3000, input);
+
+    assert_eq!(result, (true, Vec::new()));
+}
+
+#[test]
+fn ecrecover_pads_short_input_with_zeros() {
+    let result = run_precompile_call(Precompile::Ecrecover, 3000, vec![1; 40]);
+    assert_eq!(result, (true, Vec::new()));
+}
+
+#[test]
+fn precompile_call_without_enough_gas_fails() {
+    let input = bytes_from_hex(ECRECOVER_INPUT);
+    let result = run_precompile_call(Precompile::Ecrecover, 2999, input);
+    assert_eq!(result, (false, Vec::new()));
+}
+
+#[test]
+fn precompile_returns_the_gas_left() {
+    let input = bytes_from_hex(ECRECOVER_INPUT);
+
+    let result = Precompile::Ecrecover.run(&input, 5000, SpecId::default());
+
+    let expected_gas_left = 5000 - gas_cost::ECRECOVER as u64;
+    assert_eq!(
+        result,
+        (true, expected_gas_left, bytes_from_hex(ECRECOVER_OUTPUT))
+    );
+}
+
+#[rstest]
+#[case::sha256(
+    Precompile::Sha256,
+    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+)]
+#[case::ripemd160(
+    Precompile::Ripemd160,
+    "0000000000000000000000008eb208f7e05d987a9b044a8e98c6b087f15a0bfc"
+)]
+fn hash_precompile_hashes_its_input(#[case] precompile: Precompile, #[case] expected: &str) {
+    let result = run_precompile_call(precompile, 1000, b"abc".to_vec());
+    assert_eq!(result, (true, bytes_from_hex(expected)));
+}
+
+#[test]
+fn identity_returns_its_input() {
+    let input = vec![1, 2, 3, 4, 5];
+    let result = run_precompile_call(Precompile::Identity, 1000, input.clone());
+    assert_eq!(result, (true, input));
+}
+
+/// The input of modexp: the lengths of the numbers, followed by the numbers
+fn modexp_input(base: &[u8], exponent: &[u8], modulus: &[u8]) -> Vec<u8> {
+    let mut input = Vec::new();
+    for number in [base, exponent, modulus] {
+        input.extend(U256::from(number.len() as u128).to_be_bytes());
+    }
+    input.extend([base, exponent, modulus].concat());
+    input
+}
+
+/// The prime of the secp256k1 field
+const SECP256K1_PRIME: &str = "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f";
+/// [`SECP256K1_PRIME`] minus one, which any number coprime with it to the power of is one
+const SECP256K1_PRIME_MINUS_ONE: &str =
+    "fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e";
+
+#[rstest]
+#[case::small(vec![2], vec![10], vec![0x03, 0xE8], vec![0x00, 0x18])]
+#[case::fermat(
+    vec![3],
+    bytes_from_hex(SECP256K1_PRIME_MINUS_ONE),
+    bytes_from_hex(SECP256K1_PRIME),
+    U256::from(1).to_be_bytes().to_vec()
+)]
+#[case::zero_exponent(vec![7], vec![], vec![5], vec![1])]
+#[case::zero_modulus(vec![7], vec![2], vec![0, 0], vec![0, 0])]
+#[case::empty_modulus(vec![7], vec![2], vec![], vec![])]
+fn modexp_computes_the_power_modulo(
+    #[case] base: Vec<u8>,
+    #[case] exponent: Vec<u8>,
+    #[case] modulus: Vec<u8>,
+    #[case] expected: Vec<u8>,
+) {
+    let input = modexp_input(&base, &exponent, &modulus);
+    let result = run_precompile_call(Precompile::Modexp, 20000, input);
+    assert_eq!(result, (true, expected));
+}
+
+#[test]
+fn modexp_pads_the_numbers_past_the_input_with_zeros() {
+    // The modulus is 0x0100, missing its last byte
+    let mut input = modexp_input(&[2], &[7], &[1, 0]);
+    input.pop();
+
+    let result = Precompile::Modexp.execute(&input);
+
+    assert_eq!(result, Some(vec![0x00, 0x80]));
+}
+
+#[rstest]
+#[case(SpecId::Byzantium, 1024 * 255 / 20)]
+#[case(SpecId::Berlin, 4 * 4 * 255 / 3)]
+fn modexp_gas_cost_depends_on_the_hardfork(#[case] spec_id: SpecId, #[case] expected_gas: u64) {
+    let input = modexp_input(
+        &[3],
+        &bytes_from_hex(SECP256K1_PRIME_MINUS_ONE),
+        &bytes_from_hex(SECP256K1_PRIME),
+    );
+    assert_eq!(Precompile::Modexp.gas_cost(&input, spec_id), expected_gas);
+}
+
+#[rstest]
+#[case::at_least_200(vec![2], vec![10], 200)]
+#[case::long_exponent(vec![2; 64], vec![1; 40], 8 * 8 * (8 * 8 + 248) / 3)]
+fn modexp_gas_cost_since_berlin(
+    #[case] base: Vec<u8>,
+    #[case] exponent: Vec<u8>,
+    #[case] expected_gas: u64,
+) {
+    let input = modexp_input(&base, &exponent, &[1]);
+    assert_eq!(
+        Precompile::Modexp.gas_cost(&input, SpecId::Berlin),
+        expected_gas
+    );
+}
+
+#[test]
+fn modexp_with_lengths_past_u64_costs_all_the_gas() {
+    let mut input = vec![0xFF; 32];
+    input.extend_from_slice(&modexp_input(&[], &[], &[1])[32..]);
+
+    assert_eq!(
+        Precompile::Modexp.gas_cost(&input, SpecId::Berlin),
+        u64::MAX
+    );
+    let result = run_precompile_call(Precompile::Modexp, 1e6 as _, input);
+    assert_eq!(result, (false, Vec::new()));
+}
+
+/// The generator of BN254's G1, as its coordinates
+const BN254_G1: &str = concat!(
+    "0000000000000000000000000000000000000000000000000000000000000001",
+    "0000000000000000000000000000000000000000000000000000000000000002",
+);
+/// [`BN254_G1`] doubled
+const BN254_G1_DOUBLED: &str = concat!(
+    "030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd3",
+    "15ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4",
+);
+/// [`BN254_G1`] tripled
+const BN254_G1_TRIPLED: &str = concat!(
+    "0769bf9ac56bea3ff40232bcb1b6bd159315d84715b8e679f2d355961915abf0",
+    "2ab799bee0489429554fdb7c8d086475319e63b40b9c5b57cdf1ff3dd9fe2261",
+);
+/// The negation of [`BN254_G1`]
+const BN254_G1_NEGATED: &str = concat!(
+    "0000000000000000000000000000000000000000000000000000000000000001",
+    "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd45",
+);
+/// The generator of G2, on BN254's twist, as its coordinates with their imaginary part first
+const BN254_G2: &str = concat!(
+    "198e9393920d483a7260bfb731fb5d25f1aa493335a9e71297e485b7aef312c2",
+    "1800deef121f1e76426a00665e5c4479674322d4f75edadd46debd5cd992f6ed",
+    "090689d0585ff075ec9e99ad690c3395bc4b313370b38ef355acdadcd122975b",
+    "12c85ea5db8c6deb4aab71808dcb408fe3d1e7690c43d37b4ce6cc0166fa7daa",
+);
+
+#[rstest]
+#[case::doubling(&[BN254_G1, BN254_G1], BN254_G1_DOUBLED)]
+#[case::different_points(&[BN254_G1_DOUBLED, BN254_G1], BN254_G1_TRIPLED)]
+#[case::opposite_points(&[BN254_G1, BN254_G1_NEGATED], &"0".repeat(128))]
+#[case::point_at_infinity(&[BN254_G1], BN254_G1)]
+fn ec_add_adds_the_points(#[case] points: &[&str], #[case] expected: &str) {
+    let input = bytes_from_hex(&points.concat());
+    let result = run_precompile_call(Precompile::EcAdd, 1000, input);
+    assert_eq!(result, (true, bytes_from_hex(expected)));
+}
+
+#[rstest]
+#[case::by_two(2, BN254_G1_DOUBLED)]
+#[case::by_three(3, BN254_G1_TRIPLED)]
+#[case::by_zero(0, &"0".repeat(128))]
+fn ec_mul_multiplies_the_point(#[case] scalar: u128, #[case] expected: &str) {
+    let mut input = bytes_from_hex(BN254_G1);
+    input.extend(U256::from(scalar).to_be_bytes());
+
+    let result = run_precompile_call(Precompile::EcMul, 10000, input);
+
+    assert_eq!(result, (true, bytes_from_hex(expected)));
+}
+
+#[rstest]
+#[case::not_on_the_curve(Precompile::EcAdd, concat!(
+    "0000000000000000000000000000000000000000000000000000000000000001",
+    "0000000000000000000000000000000000000000000000000000000000000003",
+))]
+#[case::coordinate_past_the_modulus(Precompile::EcMul, concat!(
+    "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd48",
+    "0000000000000000000000000000000000000000000000000000000000000002",
+))]
+#[case::pairing_input_not_of_whole_pairs(Precompile::EcPairing, BN254_G1)]
+fn bn254_precompile_with_invalid_point_fails(#[case] precompile: Precompile, #[case] input: &str) {
+    let result = run_precompile_call(precompile, 200000, bytes_from_hex(input));
+    assert_eq!(result, (false, Vec::new()));
+}
+
+#[rstest]
+#[case::no_pairs(&[], 1)]
+#[case::canceling_pairs(&[BN254_G1, BN254_G2, BN254_G1_NEGATED, BN254_G2], 1)]
+#[case::single_pair(&[BN254_G1, BN254_G2], 0)]
+fn ec_pairing_checks_the_product_of_the_pairings(#[case] points: &[&str], #[case] expected: u128) {
+    let input = bytes_from_hex(&points.concat());
+    let result = run_precompile_call(Precompile::EcPairing, 200000, input);
+    assert_eq!(result, (true, U256::from(expected).to_be_bytes().to_vec()));
+}
+
+#[rstest]
+#[case(Precompile::EcAdd, 0, SpecId::Byzantium, 500)]
+#[case(Precompile::EcAdd, 0, SpecId::Istanbul, 150)]
+#[case(Precompile::EcMul, 0, SpecId::Byzantium, 40000)]
+#[case(Precompile::EcMul, 0, SpecId::Istanbul, 6000)]
+#[case(Precompile::EcPairing, 2, SpecId::Byzantium, 100000 + 2 * 80000)]
+#[case(Precompile::EcPairing, 2, SpecId::Istanbul, 45000 + 2 * 34000)]
+fn bn254_precompile_gas_cost_depends_on_the_hardfork(
+    #[case] precompile: Precompile,
+    #[case] pairs: usize,
+    #[case] spec_id: SpecId,
+    #[case] expected_gas: u64,
+) {
+    let input = vec![0; pairs * 192];
+    assert_eq!(precompile.gas_cost(&input, spec_id), expected_gas);
+}
+
+/// The input of blake2f compressing the only block of "abc" in 12 rounds, which are the
+/// rounds of BLAKE2b (EIP-152)
+const BLAKE2F_INPUT: &str = concat!(
+    "0000000c48c9bdf267e6096a3ba7ca8485ae67bb2bf894fe72f36e3cf1361d5f",
+    "3af54fa5d182e6ad7f520e511f6c3e2b8c68059b6bbd41fbabd9831f79217e13",
+    "19cde05b61626300000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "0000000000000000000000000000000000000000000000000000000000000000",
+    "000000000300000000000000000000000000000001",
+);
+/// The output of blake2f for [`BLAKE2F_INPUT`], which is the BLAKE2b hash of "abc"
+const BLAKE2F_OUTPUT: &str = concat!(
+    "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d1",
+    "7d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923",
+);
+/// The output of blake2f for [`BLAKE2F_INPUT`] without flagging the block as the last one
+const BLAKE2F_OUTPUT_NOT_FINAL: &str = concat!(
+    "75ab69d3190a562c51aef8d88f1c2775876944407270c42c9844252c26d28752",
+    "98743e7f6d5ea2f2d3e8d226039cd31b4e426ac4f2d3d666a610c2116fde4735",
+);
+
+#[rstest]
+#[case::final_block(1, BLAKE2F_OUTPUT)]
+#[case::not_final_block(0, BLAKE2F_OUTPUT_NOT_FINAL)]
+fn blake2f_compresses_the_block(#[case] flag: u8, #[case] expected: &str) {
+    let mut input = bytes_from_hex(BLAKE2F_INPUT);
+    *input.last_mut().unwrap() = flag;
+
+    let result = run_precompile_call(Precompile::Blake2f, 1000, input);
+
+    assert_eq!(result, (true, bytes_from_hex(expected)));
+}
+
+#[test]
+fn blake2f_costs_a_gas_per_round() {
+    let input = bytes_from_hex(BLAKE2F_INPUT);
+
+    let result = Precompile::Blake2f.run(&input, 100, SpecId::Istanbul);
+
+    assert_eq!(result, (true, 100 - 12, bytes_from_hex(BLAKE2F_OUTPUT)));
+}
+
+#[rstest]
+#[case::too_short(BLAKE2F_INPUT.len() / 2 - 1, 1)]
+#[case::too_long(BLAKE2F_INPUT.len() / 2 + 1, 1)]
+#[case::invalid_flag(BLAKE2F_INPUT.len() / 2, 2)]
+fn blake2f_with_invalid_input_fails(#[case] size: usize, #[case] flag: u8) {
+    let mut input = bytes_from_hex(BLAKE2F_INPUT);
+    input.resize(size, 0);
+    *input.last_mut().unwrap() = flag;
+
+    let result = run_precompile_call(Precompile::Blake2f, 1000, input);
+
+    assert_eq!(result, (false, Vec::new()));
+}
+
+/// The input of the point evaluation precompile for the polynomial of constant value 2,
+/// which is committed to by the generator of BLS12-381's G1 doubled. Its value anywhere
+/// is proven by the point at infinity
+const POINT_EVALUATION_INPUT: &str = concat!(
+    // The versioned hash of the commitment
+    "01cf45213dd7b4716864d378f3c6d861467987e4d94b7f79a1f814a697e38637",
+    // The point, and the value at it
+    "0000000000000000000000000000000000000000000000000000000000000001",
+    "0000000000000000000000000000000000000000000000000000000000000002",
+    // The commitment, and the proof
+    "a572cbea904d67468808c8eb50a9450c9721db309128012543902d0ac358a62a",
+    "e28f75bb8f1c7c42c39a8c5529bf0f4e",
+    "c000000000000000000000000000000000000000000000000000000000000000",
+    "00000000000000000000000000000000",
+);
+
+#[test]
+fn point_evaluation_verifies_the_proof() {
+    let input = bytes_from_hex(POINT_EVALUATION_INPUT);
+
+    let result = run_precompile_call(Precompile::PointEvaluation, 60000, input);
+
+    let expected = concat!(
+        "0000000000000000000000000000000000000000000000000000000000001000",
+        "73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001",
+    );
+    assert_eq!(result, (true, bytes_from_hex(expected)));
+}
+
+#[rstest]
+#[case::wrong_versioned_hash(0, 0x02)]
+#[case::wrong_value(95, 0x03)]
+#[case::wrong_proof(191, 0x01)]
+fn point_evaluation_with_invalid_input_fails(#[case] index: usize, #[case] byte: u8) {
+    let mut input = bytes_from_hex(POINT_EVALUATION_INPUT);
+    input[index] = byte;
+
+    let result = run_precompile_call(Precompile::PointEvaluation, 60000, input);
+
+    assert_eq!(result, (false, Vec::new()));
+}
+
+#[test]
+fn point_evaluation_with_input_too_short_fails() {
+    let mut input = bytes_from_hex(POINT_EVALUATION_INPUT);
+    input.pop();
+
+    let result = run_precompile_call(Precompile::PointEvaluation, 60000, input);
+
+    assert_eq!(result, (false, Vec::new()));
+}
+
+#[rstest]
+#[case(Precompile::Sha256, 0, 60)]
+#[case(Precompile::Sha256, 33, 60 + 2 * 12)]
+#[case(Precompile::Ripemd160, 32, 600 + 120)]
+#[case(Precompile::Ecrecover, 200, 3000)]
+#[case(Precompile::Identity, 64, 15 + 2 * 3)]
+#[case(Precompile::Blake2f, 2, 0)]
+#[case(Precompile::PointEvaluation, 192, 50000)]
+fn precompile_gas_cost(
+    #[case] precompile: Precompile,
+    #[case] input_size: usize,
+    #[case] expected_gas: u64,
+) {
+    let input = vec![0; input_size];
+    assert_eq!(precompile.gas_cost(&input, SpecId::default()), expected_gas);
+}
+
+#[rstest]
+#[case(Precompile::Modexp, SpecId::SpuriousDragon)]
+#[case(Precompile::EcAdd, SpecId::SpuriousDragon)]
+#[case(Precompile::Blake2f, SpecId::Petersburg)]
+#[case(Precompile::PointEvaluation, SpecId::Shanghai)]
+fn precompile_is_missing_before_its_hardfork(
+    #[case] precompile: Precompile,
+    #[case] spec_id: SpecId,
+) {
+    assert_eq!(Precompile::at(&precompile.address(), spec_id), None);
+}
+
+#[rstest]
+#[case([0; 20], None)]
+#[case(Precompile::Ecrecover.address(), Some(Precompile::Ecrecover))]
+#[case(Precompile::Sha256.address(), Some(Precompile::Sha256))]
+#[case(Precompile::Ripemd160.address(), Some(Precompile::Ripemd160))]
+#[case(Precompile::Modexp.address(), Some(Precompile::Modexp))]
+#[case(Precompile::EcPairing.address(), Some(Precompile::EcPairing))]
+#[case(CALLEE_ADDRESS, None)]
+fn precompile_is_found_at_its_address(
+    #[case] address: Address,
+    #[case] expected: Option<Precompile>,
+) {
+    assert_eq!(Precompile::at(&address, SpecId::default()), expected);
+}
+
+/// The creator address used in the examples of contract addresses
+const CREATOR_ADDRESS: Address = [
+    0x6A, 0xC7, 0xEA, 0x33, 0xF8, 0x83, 0x1E, 0xA9, 0xDC, 0xC5, 0x33, 0x93, 0xAA, 0xA8, 0x8B, 0x25,
+    0xA7, 0x85, 0xDB, 0xF0,
+];
+/// The address of the contract created by [`CREATOR_ADDRESS`] with nonce zero
+const CREATED_ADDRESS: Address = [
+    0xCD, 0x23, 0x4A, 0x47, 0x1B, 0x72, 0xBA, 0x2F, 0x1C, 0xCF, 0x0A, 0x70, 0xFC, 0xAB, 0xA6, 0x48,
+    0xA5, 0xEE, 0xCD, 0x8D,
+];
+/// The address of the contract created by [`CREATOR_ADDRESS`] with nonce one
+const SECOND_CREATED_ADDRESS: Address = [
+    0x34, 0x3C, 0x43, 0xA3, 0x7D, 0x37, 0xDF, 0xF0, 0x8A, 0xE8, 0xC4, 0xA1, 0x15, 0x44, 0xC7, 0x18,
+    0xAB, 0xB4, 0xFC, 0xF8,
+];
+
+/// A context executing at [`CREATOR_ADDRESS`], whose account has some balance and `nonce`
+fn create_context(nonce: u64) -> SyscallContext {
+    create_context_with_spec(nonce, SpecId::default())
+}
+
+/// Like [`create_context`], for the given hardfork
+fn create_context_with_spec(nonce: u64, spec_id: SpecId) -> SyscallContext {
+    let accounts = InMemoryAccounts::from_iter([(
+        CREATOR_ADDRESS,
+        AccountInfo {
+            balance: U256::from(CALLER_BALANCE),
+            nonce,
+            ..Default::default()
+        },
+    )]);
+    let env = Env {
+        spec_id,
+        call: CallEnv {
+            address: CREATOR_ADDRESS,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+    context.set_accounts(Box::new(accounts));
+    context
+}
+
+/// Stores `init_code` at the start of the memory, and creates a contract with it,
+/// using CREATE2 if a salt is given. The initcode must fit in a word
+fn create_operations(init_code: Vec<Operation>, value: u64, salt: Option<u64>) -> Vec<Operation> {
+    let init_code = Program::from(init_code).to_bytecode();
+    let mut word = [0; 32];
+    word[..init_code.len()].copy_from_slice(&init_code);
+
+    let mut operations = vec![
+        Operation::Push(BigUint::from_bytes_be(&word)),
+        Operation::Push0,
+        Operation::Mstore,
+    ];
+    if let Some(salt) = salt {
+        operations.push(Operation::Push(BigUint::from(salt)));
+    }
+    operations.extend([
+        Operation::Push(BigUint::from(init_code.len())),
+        Operation::Push0,
+        Operation::Push(BigUint::from(value)),
+        if salt.is_some() {
+            Operation::Create2
+        } else {
+            Operation::Create
+        },
+    ]);
+    operations
+}
+
+/// Initcode returning `code` as the code of the new contract.
+/// The code must be at most 24 bytes long, for the initcode to fit in a word
+fn initcode_returning(code: &[u8]) -> Vec<Operation> {
+    vec![
+        Operation::Push(BigUint::from_bytes_be(code)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(code.len())),
+        Operation::Push(BigUint::from(32 - code.len())),
+        Operation::Return,
+    ]
+}
+
+#[rstest]
+#[case(0, CREATED_ADDRESS)]
+#[case(1, SECOND_CREATED_ADDRESS)]
+fn create_pushes_address_from_creator_nonce(#[case] nonce: u64, #[case] expected: Address) {
+    let program = create_operations(vec![Operation::Stop], 0, None);
+    run_program_assert_word(program, create_context(nonce), address_as_word(expected));
+}
+
+#[test]
+fn create_deploys_returned_code() {
+    let code = [0x60, 0x2A];
+    let program = create_operations(initcode_returning(&code), 0, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert_eq!(created.code, code);
+    assert_eq!(created.nonce, 1);
+}
+
+#[rstest]
+#[case::homestead(SpecId::Homestead, 0)]
+#[case::spurious_dragon(SpecId::SpuriousDragon, 1)]
+fn created_contract_nonce_of_each_hardfork(#[case] spec_id: SpecId, #[case] expected_nonce: u64) {
+    // Creates a contract with empty initcode, without PUSH0, which comes with Shanghai
+    let zero = || Operation::Push(BigUint::ZERO);
+    let program = vec![zero(), zero(), zero(), Operation::Create];
+    let mut context = create_context_with_spec(0, spec_id);
+
+    let result = run_program_with_spec(program, &mut context, 1e7 as _, spec_id);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    // Contracts start with a nonce of one since Spurious Dragon (EIP-161)
+    let created = context
+        .accounts()
+        .account(&CREATED_ADDRESS)
+        .unwrap_or_default();
+    assert_eq!(created.nonce, expected_nonce);
+}
+
+#[test]
+fn create_increments_creator_nonce() {
+    let program = create_operations(vec![Operation::Stop], 0, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let creator = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(creator.nonce, 1);
+}
+
+#[test]
+fn create_twice_uses_next_nonce() {
+    let mut program = create_operations(vec![Operation::Stop], 0, None);
+    program.push(Operation::Pop);
+    program.extend(create_operations(vec![Operation::Stop], 0, None));
+    let expected = address_as_word(SECOND_CREATED_ADDRESS);
+    run_program_assert_word(program, create_context(0), expected);
+}
+
+#[test]
+fn create_transfers_value() {
+    let program = create_operations(vec![Operation::Stop], 100, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let creator = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert_eq!(creator.balance, U256::from(CALLER_BALANCE - 100));
+    assert_eq!(created.balance, U256::from(100));
+}
+
+#[test]
+fn initcode_sees_creation_context() {
+    // The initcode returns its address, sender and value as the first bytes of the code
+    let init_code = vec![
+        Operation::Address,
+        Operation::Caller,
+        Operation::Callvalue,
+        Operation::Push(BigUint::from(52_u8)),
+        Operation::Mstore,
+        Operation::Push(BigUint::from(20_u8)),
+        Operation::Mstore,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(72_u8)),
+        Operation::Push(BigUint::from(12_u8)),
+        Operation::Return,
+    ];
+    let program = create_operations(init_code, 7, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let code = context.accounts().account(&CREATED_ADDRESS).unwrap().code;
+    assert_eq!(code[..20], CREATED_ADDRESS);
+    assert_eq!(code[20..40], CREATOR_ADDRESS);
+    assert_eq!(code[40..], u256_as_word(U256::from(7)));
+}
+
+#[test]
+fn created_contract_can_be_called() {
+    let code = Program::from(callee_returning(Operation::Push(BigUint::from(0x2A_u8))));
+    let mut program = create_operations(initcode_returning(&code.to_bytecode()), 0, None);
+    // The address of the new contract is left in the stack for the CALL
+    program.extend([
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Dup(6),
+        Operation::Push(BigUint::from(1000_u16)),
+        Operation::Call,
+        Operation::Pop,
+        Operation::Push0,
+        Operation::Mload,
+    ]);
+    run_program_assert_stack_top(program, create_context(0), 0x2A, 1e7 as _);
+}
+
+#[test]
+fn create_with_empty_initcode_creates_empty_contract() {
+    let program = create_operations(vec![], 0, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert!(created.code.is_empty());
+    assert_eq!(created.nonce, 1);
+}
+
+#[test]
+fn create_with_insufficient_balance_pushes_zero() {
+    let program = create_operations(vec![Operation::Stop], CALLER_BALANCE as u64 + 1, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program.clone(), &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    // The creation fails before the nonce is incremented
+    let creator = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(creator.nonce, 0);
+    assert_eq!(creator.balance, U256::from(CALLER_BALANCE));
+    run_program_assert_stack_top(program, create_context(0), 0, 1e7 as _);
+}
+
+#[test]
+fn create_with_reverting_initcode_pushes_zero() {
+    let program = create_operations(callee_output_word(Operation::Revert), 100, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program.clone(), &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    // The nonce is incremented anyway, and the value goes back to the creator
+    let creator = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(creator.nonce, 1);
+    assert_eq!(creator.balance, U256::from(CALLER_BALANCE));
+    let created = context
+        .accounts()
+        .account(&CREATED_ADDRESS)
+        .unwrap_or_default();
+    assert!(created.is_empty());
+    run_program_assert_stack_top(program, create_context(0), 0, 1e7 as _);
+}
+
+#[test]
+fn create_with_reverting_initcode_rolls_back_its_storage() {
+    let program = create_operations(sstore_then_revert_code(), 0, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let key = U256::default();
+    assert_eq!(
+        context.storage().read(&CREATED_ADDRESS, &key),
+        U256::default()
+    );
+}
+
+#[test]
+fn create_with_reverting_initcode_keeps_output_as_return_data() {
+    let mut program = create_operations(callee_output_word(Operation::Revert), 0, None);
+    program.extend([Operation::Pop, Operation::Returndatasize]);
+    run_program_assert_stack_top(program, create_context(0), 32, 1e7 as _);
+}
+
+#[test]
+fn create_with_halting_initcode_pushes_zero() {
+    let program = create_operations(vec![Operation::Invalid], 0, None);
+    run_program_assert_stack_top(program, create_context(0), 0, 1e7 as _);
+}
+
+#[test]
+fn create_with_code_starting_with_ef_pushes_zero() {
+    // Code starting with 0xEF is rejected (EIP-3541)
+    let program = create_operations(initcode_returning(&[0xEF]), 0, None);
+    run_program_assert_stack_top(program, create_context(0), 0, 1e7 as _);
+}
+
+#[test]
+fn create_without_gas_for_code_deposit_pushes_zero() {
+    let code = [0x60, 0x2A];
+    let program = create_operations(initcode_returning(&code), 0, None);
+    // The gas left after running the initcode must pay for each byte of the code
+    let initcode_gas =
+        gas_cost::PUSHN * 3 + gas_cost::PUSH0 + gas_cost::MSTORE + memory_expansion_cost(0, 1);
+    let program_gas = gas_cost::PUSHN * 3
+        + gas_cost::PUSH0 * 2
+        + gas_cost::MSTORE
+        + memory_expansion_cost(0, 1)
+        + gas_cost::CREATE
+        + gas_cost::INITCODE_WORD;
+    let deposit_gas = gas_cost::CODE_DEPOSIT_BYTE * code.len() as i64;
+    // The initcode only gets all but one 64th of the gas left (EIP-150)
+    let forwarded_gas = initcode_gas + deposit_gas;
+    let gas_left = (forwarded_gas..)
+        .find(|gas| gas - gas / 64 >= forwarded_gas)
+        .unwrap();
+    let needed_gas = program_gas + gas_left;
+
+    let mut context = create_context(0);
+    let result = run_program(program.clone(), &mut context, needed_gas as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        context.accounts().account(&CREATED_ADDRESS).unwrap().code,
+        code
+    );
+
+    let mut context = create_context(0);
+    let result = run_program(program, &mut context, needed_gas as u64 - 1);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let created = context
+        .accounts()
+        .account(&CREATED_ADDRESS)
+        .unwrap_or_default();
+    assert!(created.code.is_empty());
+}
+
+#[test]
+fn create_at_existing_contract_pushes_zero() {
+    let program = create_operations(vec![Operation::Stop], 0, None);
+    let mut context = create_context(0);
+    let mut accounts = InMemoryAccounts::from_iter([(
+        CREATOR_ADDRESS,
+        AccountInfo {
+            balance: U256::from(CALLER_BALANCE),
+            ..Default::default()
+        },
+    )]);
+    accounts.insert(
+        CREATED_ADDRESS,
+        AccountInfo {
+            nonce: 1,
+            code: vec![0x00],
+            ..Default::default()
+        },
+    );
+    context.set_accounts(Box::new(accounts));
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[test]
+fn create_collects_initcode_logs() {
+    let init_code = vec![Operation::Push0, Operation::Push0, Operation::Log(0)];
+    let program = create_operations(init_code, 0, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        context.logs(),
+        [Log {
+            address: CREATED_ADDRESS,
+            ..Default::default()
+        }]
+    );
+}
+
+#[test]
+fn create2_pushes_address_from_salt_and_initcode() {
+    // Example from EIP-1014
+    let creator = [
+        0xDE, 0xAD, 0xBE, 0xEF, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    ];
+    let expected = [
+        0xB9, 0x28, 0xF6, 0x9B, 0xB1, 0xD9, 0x1C, 0xD6, 0x52, 0x74, 0xE3, 0xC7, 0x9D, 0x89, 0x86,
+        0x36, 0x29, 0x84, 0xFD, 0xA3,
+    ];
+    let program = create_operations(vec![Operation::Stop], 0, Some(0));
+    let env = Env {
+        call: CallEnv {
+            address: creator,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    run_program_assert_word(
+        program,
+        SyscallContext::with_env(env),
+        address_as_word(expected),
+    );
+}
+
+#[test]
+fn create2_deploys_returned_code() {
+    let code = [0x60, 0x2A];
+    let mut program = create_operations(initcode_returning(&code), 0, Some(1));
+    program.push(Operation::Extcodesize);
+    run_program_assert_stack_top(program, create_context(0), code.len() as u8, 1e7 as _);
+}
+
+#[test]
+fn create2_twice_with_same_salt_pushes_zero() {
+    let mut program = create_operations(vec![Operation::Stop], 0, Some(1));
+    program.push(Operation::Pop);
+    program.extend(create_operations(vec![Operation::Stop], 0, Some(1)));
+    run_program_assert_stack_top(program, create_context(0), 0, 1e7 as _);
+}
+
+#[rstest]
+#[case(None)]
+#[case(Some(1))]
+fn create_inside_static_call_halts(#[case] salt: Option<u64>) {
+    let program = valueless_call_operations(
+        Operation::Staticcall,
+        1e5 as _,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 0),
+    );
+    let context = call_context(create_operations(vec![Operation::Stop], 0, salt));
+    run_program_assert_stack_top(program, context, 0, 1e7 as _);
+}
+
+#[rstest]
+#[case(None)]
+#[case(Some(1))]
+fn create_with_stack_underflow(#[case] salt: Option<u64>) {
+    let mut program = create_operations(vec![Operation::Stop], 0, salt);
+    // Remove the push of the value
+    program.remove(program.len() - 2);
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(Operation::Create)]
+#[case(Operation::Create2)]
+fn create_with_initcode_too_large_halts(#[case] create: Operation) {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(MAX_INITCODE_SIZE + 1)),
+        Operation::Push0,
+        Operation::Push0,
+        create,
+    ];
+    run_program_assert_halt(program);
+}
+
+#[rstest]
+#[case(Operation::Create, 1_u64 << 32)]
+#[case(Operation::Create2, 1_u64 << 32)]
+#[case(Operation::Create, 0xFFFF_FFF0)]
+#[case(Operation::Create2, 0xFFFF_FFF0)]
+fn create_out_of_gas_on_initcode_offset_too_big(#[case] create: Operation, #[case] offset: u64) {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push(BigUint::from(offset)),
+        Operation::Push0,
+        create,
+    ];
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+}
+
+#[test]
+fn create_with_initcode_past_the_limit_before_shanghai_succeeds() {
+    // The initcode is made of zeros, which stop right away
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(MAX_INITCODE_SIZE + 1)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Create,
+    ];
+    let mut context = create_context(0);
+
+    let result = run_program_with_spec(program, &mut context, 1e7 as _, SpecId::Merge);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert_eq!(created.nonce, 1);
+}
+
+#[rstest]
+#[case(MAX_CODE_SIZE, true)]
+#[case(MAX_CODE_SIZE + 1, false)]
+fn create_deploys_code_up_to_the_size_limit(#[case] size: usize, #[case] deployed: bool) {
+    // The initcode returns `size` zeros, which can't be more than the limit (EIP-170)
+    let init_code = vec![
+        Operation::Push(BigUint::from(size)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let program = create_operations(init_code, 0, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let created = context
+        .accounts()
+        .account(&CREATED_ADDRESS)
+        .unwrap_or_default();
+    assert_eq!(created.code.len(), if deployed { size } else { 0 });
+}
+
+#[test]
+fn create_gas_exact() {
+    // The initcode gets all the remaining gas, and gives back what it leaves unused
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Create,
+    ];
+    let needed_gas =
+        gas_cost::PUSHN + gas_cost::PUSH0 * 2 + gas_cost::CREATE + gas_cost::INITCODE_WORD;
+    // The default context creates the contract at 0xbd770416a3345f91e4b34576cb804a576fa48eb1
+    run_program_assert_gas_exact(program, 0xB1, needed_gas as _);
+}
+
+#[test]
+fn create2_gas_exact() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Create2,
+    ];
+    let needed_gas = gas_cost::PUSHN
+        + gas_cost::PUSH0 * 3
+        + gas_cost::CREATE
+        + gas_cost::INITCODE_WORD
+        + gas_cost::KECCAK256_WORD;
+    // The default context creates the contract at 0x4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38
+    run_program_assert_gas_exact(program, 0x38, needed_gas as _);
+}
+
+#[test]
+fn selfdestruct_sends_balance_to_beneficiary() {
+    let program = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let mut context = call_context(vec![]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    let beneficiary = context.accounts().account(&EOA_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::default());
+    assert_eq!(beneficiary.balance, U256::from(CALLER_BALANCE));
+}
+
+#[test]
+fn selfdestruct_halts_execution() {
+    let program = vec![
+        push_address(EOA_ADDRESS),
+        Operation::Selfdestruct,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ];
+    let mut context = call_context(vec![]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert!(context.return_values().is_empty());
+}
+
+#[test]
+fn selfdestruct_keeps_account_not_created_in_transaction() {
+    let callee_code = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let bytecode = Program::from(callee_code.clone()).to_bytecode();
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 100, (0, 0), (0, 0));
+    let mut context = call_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    // Only the balance is sent, and the code is kept
+    let callee = context.accounts().account(&CALLEE_ADDRESS).unwrap();
+    let beneficiary = context.accounts().account(&EOA_ADDRESS).unwrap();
+    assert_eq!(callee.code, bytecode);
+    assert_eq!(callee.balance, U256::default());
+    assert_eq!(beneficiary.balance, U256::from(100));
+}
+
+#[test]
+fn selfdestruct_to_itself_keeps_balance() {
+    let program = vec![Operation::Address, Operation::Selfdestruct];
+    let mut context = call_context(vec![]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE));
+}
+
+#[test]
+fn selfdestruct_deletes_account_created_in_transaction() {
+    let init_code = vec![
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+        push_address(EOA_ADDRESS),
+        Operation::Selfdestruct,
+    ];
+    let tx = TxEnv {
+        to: None,
+        data: Program::from(init_code).to_bytecode(),
+        ..call_tx(100_000, 0, 100)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    let created = context
+        .accounts()
+        .account(&CREATED_ADDRESS)
+        .unwrap_or_default();
+    let beneficiary = context.accounts().account(&EOA_ADDRESS).unwrap();
+    assert!(created.is_empty());
+    assert_eq!(
+        context.storage().read(&CREATED_ADDRESS, &U256::from(1)),
+        U256::default()
+    );
+    assert_eq!(beneficiary.balance, U256::from(100));
+}
+
+#[test]
+fn selfdestructed_account_is_deleted_at_end_of_transaction() {
+    // The account keeps its nonce and storage until then
+    let init_code = vec![
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+        push_address(EOA_ADDRESS),
+        Operation::Selfdestruct,
+    ];
+    let program = create_operations(init_code, 100, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert_eq!(created.nonce, 1);
+    assert_eq!(created.balance, U256::default());
+    assert_eq!(
+        context.storage().read(&CREATED_ADDRESS, &U256::from(1)),
+        U256::from(7)
+    );
+}
+
+#[test]
+fn selfdestruct_to_itself_burns_balance_of_account_created_in_transaction() {
+    let init_code = vec![Operation::Address, Operation::Selfdestruct];
+    let program = create_operations(init_code, 100, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let creator = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    let created = context
+        .accounts()
+        .account(&CREATED_ADDRESS)
+        .unwrap_or_default();
+    assert_eq!(creator.balance, U256::from(CALLER_BALANCE - 100));
+    assert_eq!(created.balance, U256::default());
+}
+
+#[test]
+fn selfdestruct_inside_static_call_halts() {
+    let program = valueless_call_operations(
+        Operation::Staticcall,
+        1e5 as _,
+        CALLEE_ADDRESS,
+        (0, 0),
+        (0, 0),
+    );
+    let callee_code = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    run_program_assert_stack_top(program, call_context(callee_code), 0, 1e7 as _);
+}
+
+#[test]
+fn selfdestruct_with_stack_underflow() {
+    run_program_assert_halt(vec![Operation::Selfdestruct]);
+}
+
+#[test]
+fn selfdestruct_gas_exact() {
+    // The default context has no balance to send
+    let program = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let needed_gas =
+        (gas_cost::PUSHN + gas_cost::SELFDESTRUCT + gas_cost::COLD_ACCOUNT_ACCESS) as u64;
+
+    let mut context = SyscallContext::default();
+    let result = run_program(program.clone(), &mut context, needed_gas);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    run_program_assert_out_of_gas(program, needed_gas - 1);
+}
+
+#[test]
+fn selfdestruct_to_warm_beneficiary_gas_exact() {
+    // EXTCODESIZE warms up the beneficiary
+    let program = vec![
+        push_address(EOA_ADDRESS),
+        Operation::Extcodesize,
+        Operation::Pop,
+        push_address(EOA_ADDRESS),
+        Operation::Selfdestruct,
+    ];
+    let needed_gas = gas_cost::PUSHN * 2
+        + gas_cost::COLD_ACCOUNT_ACCESS
+        + gas_cost::POP
+        + gas_cost::SELFDESTRUCT;
+
+    let mut context = SyscallContext::default();
+    let result = run_program(program.clone(), &mut context, needed_gas as _);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+
+    run_program_assert_out_of_gas(program, needed_gas as u64 - 1);
+}
+
+#[test]
+fn selfdestruct_before_berlin_has_no_cold_cost() {
+    let program = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let needed_gas = (gas_cost::PUSHN + gas_cost::SELFDESTRUCT) as u64;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, SpecId::Istanbul);
+}
+
+#[rstest]
+#[case::frontier(SpecId::Frontier, 0)]
+#[case::tangerine_whistle(SpecId::TangerineWhistle, 5000 + 25000)]
+#[case::spurious_dragon(SpecId::SpuriousDragon, 5000)]
+#[case::berlin(SpecId::Berlin, 5000 + 2600)]
+fn selfdestruct_gas_of_each_hardfork(#[case] spec_id: SpecId, #[case] selfdestruct_gas: u64) {
+    // The beneficiary doesn't exist, and creating it is charged from Tangerine Whistle to
+    // Spurious Dragon, after which it's only charged when the contract has some balance
+    let program = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let needed_gas = gas_cost::PUSHN as u64 + selfdestruct_gas;
+    run_program_assert_gas_exact_with_spec(program, needed_gas, spec_id);
+}
+
+#[rstest]
+#[case::istanbul(SpecId::Istanbul, 24000)]
+#[case::london(SpecId::London, 0)]
+fn selfdestruct_refund_of_each_hardfork(#[case] spec_id: SpecId, #[case] expected_refund: u64) {
+    let program = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let mut context = SyscallContext::with_env(Env {
+        spec_id,
+        ..Default::default()
+    });
+
+    let result = run_program_with_spec(program, &mut context, 1e7 as _, spec_id);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(context.gas_refund(1e7 as _), expected_refund);
+}
+
+#[test]
+fn selfdestruct_to_new_account_gas_exact() {
+    let program = vec![push_address(MISSING_ADDRESS), Operation::Selfdestruct];
+    let needed_gas = (gas_cost::PUSHN
+        + gas_cost::SELFDESTRUCT
+        + gas_cost::COLD_ACCOUNT_ACCESS
+        + gas_cost::SELFDESTRUCT_NEW_ACCOUNT) as u64;
+
+    let mut context = call_context(vec![]);
+    let result = run_program(program.clone(), &mut context, needed_gas);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let beneficiary = context.accounts().account(&MISSING_ADDRESS).unwrap();
+    assert_eq!(beneficiary.balance, U256::from(CALLER_BALANCE));
+
+    // Without enough gas, the balance isn't sent
+    let mut context = call_context(vec![]);
+    let result = run_program(program, &mut context, needed_gas - 1);
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+    let caller = context.accounts().account(&CALLER_ADDRESS).unwrap();
+    assert_eq!(caller.balance, U256::from(CALLER_BALANCE));
+}
+
+const SENDER_BALANCE: u128 = 1_000_000_000;
+
+/// A context for the given transaction, sent by [`CREATOR_ADDRESS`], which has some balance,
+/// to an account at [`CALLEE_ADDRESS`] with the given code
+fn transaction_context(tx: TxEnv, callee_code: Vec<Operation>) -> SyscallContext {
+    let env = Env {
+        tx,
+        ..Default::default()
+    };
+    transaction_context_with(env, callee_code, InMemoryStorage::new())
+}
+
+/// Like [`transaction_context`], but with the given environment and storage
+fn transaction_context_with(
+    env: Env,
+    callee_code: Vec<Operation>,
+    storage: InMemoryStorage,
+) -> SyscallContext {
+    let accounts = InMemoryAccounts::from_iter([
+        (
+            CREATOR_ADDRESS,
+            AccountInfo {
+                balance: U256::from(SENDER_BALANCE),
+                ..Default::default()
+            },
+        ),
+        (
+            CALLEE_ADDRESS,
+            AccountInfo {
+                code: Program::from(callee_code).to_bytecode(),
+                ..Default::default()
+            },
+        ),
+    ]);
+    let mut context = SyscallContext::new(env, Vec::new(), Box::new(storage));
+    context.set_accounts(Box::new(accounts));
+    context
+}
+
+fn call_tx(gas_limit: u64, gas_price: u128, value: u128) -> TxEnv {
+    TxEnv {
+        origin: CREATOR_ADDRESS,
+        to: Some(CALLEE_ADDRESS),
+        value: U256::from(value),
+        gas_limit,
+        gas_price: U256::from(gas_price),
+        ..Default::default()
+    }
+}
+
+fn balance_of(context: &SyscallContext, address: &Address) -> U256 {
+    context
+        .accounts()
+        .account(address)
+        .map(|account| account.balance)
+        .unwrap_or_default()
+}
+
+#[rstest]
+#[case(Some(CALLEE_ADDRESS), vec![], vec![], 21000)]
+#[case(Some(CALLEE_ADDRESS), vec![0, 1, 0, 2], vec![], 21000 + 2 * 4 + 2 * 16)]
+#[case(Some(CALLEE_ADDRESS), vec![], vec![(CALLEE_ADDRESS, vec![])], 21000 + 2400)]
+#[case(
+    Some(CALLEE_ADDRESS),
+    vec![],
+    vec![(CALLEE_ADDRESS, vec![U256::from(1), U256::from(2)]), (EOA_ADDRESS, vec![])],
+    21000 + 2 * 2400 + 2 * 1900
+)]
+#[case(None, vec![], vec![], 21000 + 32000)]
+#[case(None, vec![1; 33], vec![], 21000 + 32000 + 33 * 16 + 2 * 2)]
+fn intrinsic_gas(
+    #[case] to: Option<Address>,
+    #[case] data: Vec<u8>,
+    #[case] access_list: Vec<(Address, Vec<U256>)>,
+    #[case] expected_gas: u64,
+) {
+    let tx = TxEnv {
+        to,
+        data,
+        access_list,
+        ..Default::default()
+    };
+    assert_eq!(tx.intrinsic_gas(SpecId::Cancun), expected_gas);
+}
+
+#[test]
+fn intrinsic_gas_before_shanghai_does_not_charge_initcode_words() {
+    let tx = TxEnv {
+        to: None,
+        data: vec![1; 33],
+        ..Default::default()
+    };
+    assert_eq!(tx.intrinsic_gas(SpecId::Merge), 21000 + 32000 + 33 * 16);
+}
+
+#[rstest]
+#[case::frontier(SpecId::Frontier, 21000 + 2 * 4 + 2 * 68, 21000 + 2 * 4 + 2 * 68)]
+#[case::homestead(SpecId::Homestead, 21000 + 2 * 4 + 2 * 68, 21000 + 32000 + 2 * 4 + 2 * 68)]
+#[case::petersburg(
+    SpecId::Petersburg,
+    21000 + 2 * 4 + 2 * 68,
+    21000 + 32000 + 2 * 4 + 2 * 68
+)]
+#[case::istanbul(SpecId::Istanbul, 21000 + 2 * 4 + 2 * 16, 21000 + 32000 + 2 * 4 + 2 * 16)]
+#[case::shanghai(
+    SpecId::Shanghai,
+    21000 + 2 * 4 + 2 * 16,
+    21000 + 32000 + 2 * 4 + 2 * 16 + 2
+)]
+fn intrinsic_gas_of_each_hardfork(
+    #[case] spec_id: SpecId,
+    #[case] call_gas: u64,
+    #[case] create_gas: u64,
+) {
+    let call = TxEnv {
+        to: Some(CALLEE_ADDRESS),
+        data: vec![0, 1, 0, 2],
+        ..Default::default()
+    };
+    let create = TxEnv {
+        to: None,
+        ..call.clone()
+    };
+    assert_eq!(call.intrinsic_gas(spec_id), call_gas);
+    assert_eq!(create.intrinsic_gas(spec_id), create_gas);
+}
+
+#[test]
+fn transaction_to_account_without_code_charges_intrinsic_gas() {
+    let tx = TxEnv {
+        to: Some(EOA_ADDRESS),
+        data: vec![0, 1],
+        ..call_tx(30000, 2, 5)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    let intrinsic_gas = 21000 + 4 + 16;
+    assert!(result.success);
+    assert_eq!(result.gas_used, intrinsic_gas);
+    let sender = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    let expected_balance = SENDER_BALANCE - intrinsic_gas as u128 * 2 - 5;
+    assert_eq!(sender.balance, U256::from(expected_balance));
+    assert_eq!(sender.nonce, 1);
+    assert_eq!(balance_of(&context, &EOA_ADDRESS), U256::from(5));
+}
+
+#[test]
+fn transaction_executes_callee_code() {
+    let callee_code = vec![
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let code_gas =
+        gas_cost::PUSHN * 2 + gas_cost::PUSH0 * 2 + gas_cost::MSTORE + memory_expansion_cost(0, 1);
+    let mut context = transaction_context(call_tx(100_000, 1, 0), callee_code);
+
+    let result = context.transact().unwrap();
+
+    let mut expected_output = [0; 32];
+    expected_output[31] = 7;
+    assert!(result.success);
+    assert_eq!(result.output, expected_output);
+    assert_eq!(result.gas_used, 21000 + code_gas as u64);
+    let expected_balance = SENDER_BALANCE - result.gas_used as u128;
+    assert_eq!(
+        balance_of(&context, &CREATOR_ADDRESS),
+        U256::from(expected_balance)
+    );
+}
+
+#[test]
+fn transaction_passes_data_as_calldata() {
+    let callee_code = vec![
+        Operation::Push0,
+        Operation::CallDataLoad,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let tx = TxEnv {
+        data: vec![0xAB; 32],
+        ..call_tx(100_000, 1, 0)
+    };
+    let mut context = transaction_context(tx, callee_code);
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    assert_eq!(result.output, [0xAB; 32]);
+}
+
+#[test]
+fn reverted_transaction_charges_gas_and_returns_value() {
+    let callee_code = vec![Operation::Push0, Operation::Push0, Operation::Revert];
+    let mut context = transaction_context(call_tx(100_000, 1, 10), callee_code);
+
+    let result = context.transact().unwrap();
+
+    let gas_used = 21000 + 2 * gas_cost::PUSH0 as u64;
+    assert!(!result.success);
+    assert_eq!(result.gas_used, gas_used);
+    let expected_balance = SENDER_BALANCE - gas_used as u128;
+    assert_eq!(
+        balance_of(&context, &CREATOR_ADDRESS),
+        U256::from(expected_balance)
+    );
+    assert_eq!(balance_of(&context, &CALLEE_ADDRESS), U256::default());
+    // The nonce is incremented even if the transaction fails
+    let sender = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(sender.nonce, 1);
+}
+
+#[test]
+fn transaction_halting_consumes_all_gas() {
+    let mut context = transaction_context(call_tx(100_000, 1, 0), vec![Operation::Invalid]);
+
+    let result = context.transact().unwrap();
+
+    assert!(!result.success);
+    assert_eq!(result.gas_used, 100_000);
+    let expected_balance = SENDER_BALANCE - 100_000;
+    assert_eq!(
+        balance_of(&context, &CREATOR_ADDRESS),
+        U256::from(expected_balance)
+    );
+}
+
+#[test]
+fn transaction_refunds_cleared_storage() {
+    let storage = InMemoryStorage::from_iter([(CALLEE_ADDRESS, U256::default(), U256::from(1))]);
+    let callee_code = vec![Operation::Push0, Operation::Push0, Operation::Sstore];
+    let env = Env {
+        tx: call_tx(100_000, 1, 0),
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, callee_code, storage);
+
+    let result = context.transact().unwrap();
+
+    // The refund is capped at a fifth of the gas used
+    let gas_used =
+        (21000 + gas_cost::PUSH0 * 2 + gas_cost::SSTORE_RESET + gas_cost::COLD_SLOAD) as u64;
+    let gas_refunded = gas_used / 5;
+    assert!(result.success);
+    assert_eq!(result.gas_refunded, gas_refunded);
+    assert_eq!(result.gas_used, gas_used - gas_refunded);
+    let expected_balance = SENDER_BALANCE - result.gas_used as u128;
+    assert_eq!(
+        balance_of(&context, &CREATOR_ADDRESS),
+        U256::from(expected_balance)
+    );
+}
+
+#[test]
+fn transaction_pays_priority_fee_to_coinbase() {
+    let env = Env {
+        block: BlockEnv {
+            coinbase: EOA_ADDRESS,
+            basefee: U256::from(1),
+            ..Default::default()
+        },
+        tx: call_tx(30000, 3, 0),
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, vec![], InMemoryStorage::new());
+
+    let result = context.transact().unwrap();
+
+    assert_eq!(result.gas_used, 21000);
+    let expected_balance = SENDER_BALANCE - 21000 * 3;
+    assert_eq!(
+        balance_of(&context, &CREATOR_ADDRESS),
+        U256::from(expected_balance)
+    );
+    // The base fee is burned
+    assert_eq!(balance_of(&context, &EOA_ADDRESS), U256::from(21000 * 2));
+}
+
+/// A fee market transaction (EIP-1559) from the creator to the callee, in a block whose
+/// base fee is 10 and whose coinbase is [`EOA_ADDRESS`]
+fn fee_market_env(spec_id: SpecId, max_fee: u128, max_priority_fee: u128) -> Env {
+    Env {
+        spec_id,
+        block: BlockEnv {
+            coinbase: EOA_ADDRESS,
+            basefee: U256::from(10),
+            ..Default::default()
+        },
+        tx: TxEnv {
+            max_fee_per_gas: Some(U256::from(max_fee)),
+            max_priority_fee_per_gas: U256::from(max_priority_fee),
+            ..call_tx(30000, 0, 0)
+        },
+        ..Default::default()
+    }
+}
+
+#[rstest]
+#[case(20, 3, 13)]
+#[case(12, 3, 12)]
+#[case(10, 0, 10)]
+fn fee_market_transaction_pays_effective_gas_price(
+    #[case] max_fee: u128,
+    #[case] max_priority_fee: u128,
+    #[case] gas_price: u128,
+) {
+    let env = fee_market_env(SpecId::default(), max_fee, max_priority_fee);
+    let mut context = transaction_context_with(env, vec![], InMemoryStorage::new());
+
+    let result = context.transact().unwrap();
+
+    assert_eq!(result.gas_used, 21000);
+    let expected_balance = SENDER_BALANCE - 21000 * gas_price;
+    assert_eq!(
+        balance_of(&context, &CREATOR_ADDRESS),
+        U256::from(expected_balance)
+    );
+    // The base fee is burned
+    let priority_fee = gas_price - 10;
+    assert_eq!(
+        balance_of(&context, &EOA_ADDRESS),
+        U256::from(21000 * priority_fee)
+    );
+}
+
+#[test]
+fn fee_market_transaction_exposes_effective_gas_price() {
+    let callee_code = vec![
+        Operation::Gasprice,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let env = fee_market_env(SpecId::default(), 20, 3);
+    let mut context = transaction_context_with(env, callee_code, InMemoryStorage::new());
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    assert_eq!(result.output, u256_as_word(U256::from(13)));
+}
+
+#[test]
+fn fee_market_transaction_must_afford_its_max_fee() {
+    let env = Env {
+        tx: TxEnv {
+            max_fee_per_gas: Some(U256::from(SENDER_BALANCE / 30000 + 1)),
+            ..call_tx(30000, 0, 0)
+        },
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, vec![], InMemoryStorage::new());
+
+    let result = context.transact();
+
+    assert_eq!(result, Err(TransactionError::InsufficientFunds));
+}
+
+#[rstest]
+#[case(
+    fee_market_env(SpecId::default(), 9, 0),
+    TransactionError::GasPriceLowerThanBasefee
+)]
+#[case(
+    fee_market_env(SpecId::default(), 20, 21),
+    TransactionError::PriorityFeeGreaterThanMaxFee
+)]
+#[case(
+    fee_market_env(SpecId::Berlin, 20, 3),
+    TransactionError::FeeMarketNotSupported
+)]
+fn invalid_fee_market_transaction_is_rejected(#[case] env: Env, #[case] error: TransactionError) {
+    let mut context = transaction_context_with(env, vec![], InMemoryStorage::new());
+
+    let result = context.transact();
+
+    assert_eq!(result, Err(error));
+    let sender = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(sender.nonce, 0);
+}
+
+#[test]
+fn transaction_creates_contract() {
+    let code = [0x60, 0x01, 0x60, 0x02, 0x01];
+    let init_code = Program::from(initcode_returning(&code)).to_bytecode();
+    let tx = TxEnv {
+        to: None,
+        data: init_code.clone(),
+        ..call_tx(100_000, 1, 10)
+    };
+    let intrinsic_gas = tx.intrinsic_gas(SpecId::default());
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    let init_gas = gas_cost::PUSHN * 3
+        + gas_cost::PUSH0
+        + gas_cost::MSTORE
+        + memory_expansion_cost(0, 1)
+        + gas_cost::CODE_DEPOSIT_BYTE * code.len() as i64;
+    assert!(result.success);
+    assert_eq!(result.created_address, Some(CREATED_ADDRESS));
+    assert_eq!(result.gas_used, intrinsic_gas + init_gas as u64);
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert_eq!(created.code, code);
+    assert_eq!(created.nonce, 1);
+    assert_eq!(created.balance, U256::from(10));
+}
+
+/// The initcode solc 0.8.24 emits, with the optimizer on and `--metadata-hash none`, for
+/// `contract C { function f() external pure returns (uint256) { return 42; } }`.
+/// It copies [`SOLIDITY_RUNTIME_CODE`] from the end of its own code, and returns it
+const SOLIDITY_INITCODE: [u8; 26] = [
+    0x60, 0x80, 0x60, 0x40, 0x52, 0x34, 0x80, 0x15, 0x60, 0x0E, 0x57, 0x5F, 0x80, 0xFD, 0x5B, 0x50,
+    0x60, 0x4B, 0x80, 0x60, 0x1A, 0x5F, 0x39, 0x5F, 0xF3, 0xFE,
+];
+/// The code of the contract deployed by [`SOLIDITY_INITCODE`], followed by its metadata
+const SOLIDITY_RUNTIME_CODE: [u8; 75] = [
+    0x60, 0x80, 0x60, 0x40, 0x52, 0x34, 0x80, 0x15, 0x60, 0x0E, 0x57, 0x5F, 0x80, 0xFD, 0x5B, 0x50,
+    0x60, 0x04, 0x36, 0x10, 0x60, 0x26, 0x57, 0x5F, 0x35, 0x60, 0xE0, 0x1C, 0x80, 0x63, 0x26, 0x12,
+    0x1F, 0xF0, 0x14, 0x60, 0x2A, 0x57, 0x5B, 0x5F, 0x80, 0xFD, 0x5B, 0x60, 0x2A, 0x60, 0x40, 0x51,
+    0x90, 0x81, 0x52, 0x60, 0x20, 0x01, 0x60, 0x40, 0x51, 0x80, 0x91, 0x03, 0x90, 0xF3, 0xFE, 0xA1,
+    0x64, 0x73, 0x6F, 0x6C, 0x63, 0x43, 0x00, 0x08, 0x18, 0x00, 0x0A,
+];
+/// The selector of `f()`
+const SOLIDITY_SELECTOR: [u8; 4] = [0x26, 0x12, 0x1F, 0xF0];
+
+#[test]
+fn transaction_deploys_and_calls_solidity_contract() {
+    let tx = TxEnv {
+        to: None,
+        data: [SOLIDITY_INITCODE.as_slice(), &SOLIDITY_RUNTIME_CODE].concat(),
+        ..call_tx(200_000, 1, 0)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    assert_eq!(result.created_address, Some(CREATED_ADDRESS));
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert_eq!(created.code, SOLIDITY_RUNTIME_CODE);
+
+    let env = Env {
+        tx: TxEnv {
+            to: Some(CREATED_ADDRESS),
+            data: SOLIDITY_SELECTOR.to_vec(),
+            ..call_tx(100_000, 1, 0)
+        },
+        ..Default::default()
+    };
+    let accounts = InMemoryAccounts::from_iter([
+        (
+            CREATOR_ADDRESS,
+            AccountInfo {
+                balance: U256::from(SENDER_BALANCE),
+                ..Default::default()
+            },
+        ),
+        (CREATED_ADDRESS, created),
+    ]);
+    let mut context = SyscallContext::new(env, Vec::new(), Box::<InMemoryStorage>::default());
+    context.set_accounts(Box::new(accounts));
+
+    let result = context.transact().unwrap();
+
+    let mut expected_output = [0; 32];
+    expected_output[31] = 42;
+    assert!(result.success);
+    assert_eq!(result.output, expected_output);
+}
+
+#[test]
+fn transaction_to_precompile_runs_it() {
+    let data = b"abc".to_vec();
+    let tx = TxEnv {
+        to: Some(Precompile::Sha256.address()),
+        data,
+        ..call_tx(100_000, 1, 10)
+    };
+    let intrinsic_gas = tx.intrinsic_gas(SpecId::default());
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    let expected_hash =
+        biguint_from_hex("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    assert!(result.success);
+    assert_eq!(result.output, expected_hash.to_bytes_be());
+    let precompile_gas = gas_cost::SHA256 + gas_cost::SHA256_WORD;
+    assert_eq!(result.gas_used, intrinsic_gas + precompile_gas as u64);
+    assert_eq!(
+        balance_of(&context, &Precompile::Sha256.address()),
+        U256::from(10)
+    );
+}
+
+#[test]
+fn failed_transaction_to_precompile_uses_all_the_gas() {
+    // The points aren't on the curve
+    let tx = TxEnv {
+        to: Some(Precompile::EcAdd.address()),
+        data: vec![1; 128],
+        ..call_tx(100_000, 1, 10)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    assert!(!result.success);
+    assert!(result.output.is_empty());
+    assert_eq!(result.gas_used, 100_000);
+    assert_eq!(
+        balance_of(&context, &Precompile::EcAdd.address()),
+        U256::default()
+    );
+}
+
+#[test]
+fn transaction_creating_contract_with_reverting_initcode_fails() {
+    let init_code = Program::from(vec![Operation::Push0, Operation::Push0, Operation::Revert]);
+    let tx = TxEnv {
+        to: None,
+        data: init_code.to_bytecode(),
+        ..call_tx(100_000, 1, 10)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    assert!(!result.success);
+    assert_eq!(result.created_address, None);
+    assert_eq!(balance_of(&context, &CREATED_ADDRESS), U256::default());
+}
+
+#[test]
+fn transaction_below_intrinsic_gas_is_rejected() {
+    let mut context = transaction_context(call_tx(20999, 1, 0), vec![]);
+
+    let result = context.transact();
+
+    let expected_error = TransactionError::IntrinsicGasTooLow {
+        gas_limit: 20999,
+        intrinsic_gas: 21000,
+    };
+    assert_eq!(result, Err(expected_error));
+    let sender = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(sender.balance, U256::from(SENDER_BALANCE));
+    assert_eq!(sender.nonce, 0);
+}
+
+#[rstest]
+#[case(SENDER_BALANCE / 21000 + 1, 0)]
+#[case(1, SENDER_BALANCE - 21000 + 1)]
+fn transaction_sender_cant_afford_is_rejected(#[case] gas_price: u128, #[case] value: u128) {
+    let mut context = transaction_context(call_tx(21000, gas_price, value), vec![]);
+
+    let result = context.transact();
+
+    assert_eq!(result, Err(TransactionError::InsufficientFunds));
+    assert_eq!(
+        balance_of(&context, &CREATOR_ADDRESS),
+        U256::from(SENDER_BALANCE)
+    );
+}
+
+#[test]
+fn transaction_with_gas_price_below_basefee_is_rejected() {
+    let env = Env {
+        block: BlockEnv {
+            basefee: U256::from(2),
+            ..Default::default()
+        },
+        tx: call_tx(30000, 1, 0),
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, vec![], InMemoryStorage::new());
+
+    let result = context.transact();
+
+    assert_eq!(result, Err(TransactionError::GasPriceLowerThanBasefee));
+}
+
+#[test]
+fn transaction_warms_up_its_access_list() {
+    let coinbase = [0x30; 20];
+    let env = Env {
+        block: BlockEnv {
+            coinbase,
+            ..Default::default()
+        },
+        tx: TxEnv {
+            access_list: vec![
+                (EOA_ADDRESS, vec![U256::from(1), U256::from(2)]),
+                (MISSING_ADDRESS, vec![]),
+            ],
+            ..call_tx(100_000, 0, 0)
+        },
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, vec![], InMemoryStorage::new());
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    for address in [
+        CREATOR_ADDRESS,
+        CALLEE_ADDRESS,
+        coinbase,
+        EOA_ADDRESS,
+        MISSING_ADDRESS,
+    ] {
+        assert!(context.is_warm_address(&address));
+    }
+    for precompile in Precompile::ALL {
+        assert!(context.is_warm_address(&precompile.address()));
+    }
+    assert!(!context.is_warm_address(&CONTRACT_ADDRESS));
+    assert!(context.is_warm_slot(&EOA_ADDRESS, &U256::from(1)));
+    assert!(context.is_warm_slot(&EOA_ADDRESS, &U256::from(2)));
+    assert!(!context.is_warm_slot(&EOA_ADDRESS, &U256::from(3)));
+    assert!(!context.is_warm_slot(&MISSING_ADDRESS, &U256::from(1)));
+}
+
+#[rstest]
+#[case::cold(vec![])]
+#[case::warm(vec![(CALLEE_ADDRESS, vec![U256::from(1)]), (EOA_ADDRESS, vec![])])]
+fn transaction_access_list_makes_accesses_cheaper(#[case] access_list: Vec<(Address, Vec<U256>)>) {
+    let callee_code = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+        push_address(EOA_ADDRESS),
+        Operation::Extcodesize,
+    ];
+    let accesses_gas = if access_list.is_empty() {
+        gas_cost::COLD_SLOAD + gas_cost::COLD_ACCOUNT_ACCESS
+    } else {
+        gas_cost::TX_ACCESS_LIST_STORAGE_KEY
+            + gas_cost::TX_ACCESS_LIST_ADDRESS * 2
+            + gas_cost::SLOAD
+            + gas_cost::WARM_ACCOUNT_ACCESS
+    };
+    let env = Env {
+        tx: TxEnv {
+            access_list,
+            ..call_tx(100_000, 0, 0)
+        },
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, callee_code, InMemoryStorage::new());
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    let gas_used = gas_cost::TX + gas_cost::PUSHN * 2 + accesses_gas;
+    assert_eq!(result.gas_used, gas_used as u64);
+}
+
+#[test]
+fn call_warms_up_the_callee() {
+    let program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let mut context = call_context(vec![Operation::Stop]);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert!(context.is_warm_address(&CALLEE_ADDRESS));
+}
+
+#[test]
+fn create_warms_up_the_created_address() {
+    let program = create_operations(vec![Operation::Stop], 0, None);
+    let mut context = create_context(0);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert!(context.is_warm_address(&CREATED_ADDRESS));
+}
+
+#[test]
+fn transaction_with_access_list_before_berlin_is_rejected() {
+    let env = Env {
+        spec_id: SpecId::Istanbul,
+        tx: TxEnv {
+            access_list: vec![(EOA_ADDRESS, vec![U256::from(1)])],
+            ..call_tx(100_000, 0, 0)
+        },
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, vec![], InMemoryStorage::new());
+
+    let result = context.transact();
+
+    assert_eq!(result, Err(TransactionError::AccessListNotSupported));
+}
+
+fn log1_code(topic: u8) -> Vec<Operation> {
+    vec![
+        Operation::Push(BigUint::from(topic)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+    ]
+}
+
+#[test]
+fn transaction_receipt_has_logs_and_bloom() {
+    let mut context = transaction_context(call_tx(100_000, 1, 0), log1_code(7));
+
+    let result = context.transact().unwrap();
+    let receipt = Receipt::new(&result, 21000);
+
+    let log = Log {
+        address: CALLEE_ADDRESS,
+        data: vec![],
+        topics: vec![U256::from(7)],
+    };
+    assert!(receipt.status);
+    assert_eq!(receipt.cumulative_gas, 21000 + result.gas_used);
+    assert_eq!(receipt.logs, [log]);
+    // The bits set by the callee's address and by the topic
+    let mut expected_bloom = Bloom::default();
+    for (byte, bits) in [
+        (50, 0x10),
+        (66, 0x20),
+        (87, 0x80),
+        (217, 0x80),
+        (218, 0x01),
+        (228, 0x02),
+    ] {
+        expected_bloom.0[byte] = bits;
+    }
+    assert_eq!(receipt.bloom, expected_bloom);
+    assert!(receipt.bloom.contains_input(&CALLEE_ADDRESS));
+    assert!(receipt.bloom.contains_input(&U256::from(7).to_be_bytes()));
+    assert!(!receipt.bloom.contains_input(&CALLER_ADDRESS));
+}
+
+#[test]
+fn failed_transaction_receipt_has_no_logs() {
+    let mut callee_code = log1_code(7);
+    callee_code.extend([Operation::Push0, Operation::Push0, Operation::Revert]);
+    let mut context = transaction_context(call_tx(100_000, 1, 0), callee_code);
+
+    let result = context.transact().unwrap();
+    let receipt = Receipt::new(&result, 0);
+
+    assert!(!receipt.status);
+    assert_eq!(receipt.cumulative_gas, result.gas_used);
+    assert!(receipt.logs.is_empty());
+    assert_eq!(receipt.bloom, Bloom::default());
+}
+
+#[test]
+fn transaction_result_only_has_its_own_logs() {
+    let mut context = transaction_context(call_tx(100_000, 1, 0), log1_code(7));
+
+    let first = context.transact().unwrap();
+    let second = context.transact().unwrap();
+
+    assert_eq!(first.logs.len(), 1);
+    assert_eq!(second.logs, first.logs);
+}
+
+#[test]
+fn bloom_accrues_other_blooms() {
+    let logs = [CALLEE_ADDRESS, CALLER_ADDRESS].map(|address| Log {
+        address,
+        ..Default::default()
+    });
+
+    let mut bloom = Bloom::from_logs(&logs[..1]);
+    bloom.accrue_bloom(&Bloom::from_logs(&logs[1..]));
+
+    assert!(bloom.contains_input(&CALLEE_ADDRESS));
+    assert!(bloom.contains_input(&CALLER_ADDRESS));
+    assert_eq!(bloom, Bloom::from_logs(&logs));
+}
+
+#[test]
+fn transaction_state_diff_has_balances_nonces_and_storage() {
+    let storage = InMemoryStorage::from_iter([(CALLEE_ADDRESS, U256::default(), U256::from(1))]);
+    let callee_code = vec![
+        // Slot 0 goes from 1 to 2
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push0,
+        Operation::Sstore,
+        // Slot 1 ends up as it was
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+    ];
+    let env = Env {
+        tx: call_tx(100_000, 1, 10),
+        ..Default::default()
+    };
+    let mut context = transaction_context_with(env, callee_code, storage);
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    let gas_used = U256::from(result.gas_used as u128);
+    let sender_balance = SENDER_BALANCE - result.gas_used as u128 - 10;
+    let sender = AccountDiff {
+        balance: Change::of(U256::from(SENDER_BALANCE), U256::from(sender_balance)),
+        nonce: Change::of(0, 1),
+        ..Default::default()
+    };
+    let callee = AccountDiff {
+        balance: Change::of(U256::default(), U256::from(10)),
+        storage: [(
+            U256::default(),
+            Change::of(U256::from(1), U256::from(2)).unwrap(),
+        )]
+        .into(),
+        ..Default::default()
+    };
+    // The coinbase gets the whole gas price, without a base fee
+    let coinbase = AccountDiff {
+        balance: Change::of(U256::default(), gas_used),
+        ..Default::default()
+    };
+    let expected = StateDiff {
+        accounts: [
+            (CREATOR_ADDRESS, sender),
+            (CALLEE_ADDRESS, callee),
+            (Address::default(), coinbase),
+        ]
+        .into(),
+    };
+    assert_eq!(result.state_diff, expected);
+    assert_eq!(context.state_diff(), expected);
+}
+
+#[test]
+fn transaction_state_diff_marks_created_accounts() {
+    let init_code = Program::from(initcode_returning(&[0x00])).to_bytecode();
+    let tx = TxEnv {
+        to: None,
+        data: init_code,
+        ..call_tx(100_000, 0, 10)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    let created = result.state_diff.account(&CREATED_ADDRESS).unwrap();
+    assert!(created.created);
+    assert!(!created.destroyed);
+    assert_eq!(created.nonce, Change::of(0, 1));
+    assert_eq!(created.balance, Change::of(U256::default(), U256::from(10)));
+    let sender = result.state_diff.account(&CREATOR_ADDRESS).unwrap();
+    assert!(!sender.created);
+}
+
+#[test]
+fn transaction_state_diff_marks_destroyed_accounts() {
+    let env = Env {
+        spec_id: SpecId::Shanghai,
+        tx: call_tx(100_000, 0, 10),
+        ..Default::default()
+    };
+    let callee_code = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let mut context = transaction_context_with(env, callee_code, InMemoryStorage::new());
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    let callee = result.state_diff.account(&CALLEE_ADDRESS).unwrap();
+    assert!(callee.destroyed);
+    assert!(!callee.created);
+    // The value sent to it went on to the beneficiary
+    assert_eq!(callee.balance, None);
+    let beneficiary = result.state_diff.account(&EOA_ADDRESS).unwrap();
+    assert_eq!(
+        beneficiary.balance,
+        Change::of(U256::default(), U256::from(10))
+    );
+}
+
+#[test]
+fn execution_result_has_state_diff() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Stop,
+    ]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+    let mut context = SyscallContext::default();
+
+    let result = executor.run(&mut context, 1e7 as _);
+
+    let ExecutionResult::Success { state_diff, .. } = result else {
+        panic!("expected a success, got {result:?}");
+    };
+    let address = context.env().call.address;
+    let slot = Change::of(U256::default(), U256::from(7)).unwrap();
+    let expected = StateDiff {
+        accounts: [(
+            address,
+            AccountDiff {
+                storage: [(U256::default(), slot)].into(),
+                ..Default::default()
+            },
+        )]
+        .into(),
+    };
+    assert_eq!(state_diff, expected);
+}
+
+/// Increments the value at slot 0, emitting an event with the new value as its topic
+fn counter_code() -> Vec<Operation> {
+    vec![
+        Operation::Push0,
+        Operation::Sload,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Add,
+        Operation::Dup(1),
+        Operation::Push0,
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+    ]
+}
+
+/// A block with the given gas limit and a base fee of one, whose coinbase is
+/// [`EOA_ADDRESS`], along with a state where [`CREATOR_ADDRESS`] has some balance and
+/// [`CALLEE_ADDRESS`] has the given code
+fn block_env_and_db(gas_limit: u64, callee_code: Vec<Operation>) -> (Env, InMemoryDB) {
+    let env = Env {
+        block: BlockEnv {
+            coinbase: EOA_ADDRESS,
+            basefee: U256::from(1),
+            gas_limit: U256::from(gas_limit as u128),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut db = InMemoryDB::new();
+    let sender = AccountInfo {
+        balance: U256::from(SENDER_BALANCE),
+        ..Default::default()
+    };
+    db.insert_account(CREATOR_ADDRESS, sender);
+    let callee = AccountInfo {
+        code: Program::from(callee_code).to_bytecode(),
+        ..Default::default()
+    };
+    db.insert_account(CALLEE_ADDRESS, callee);
+    (env, db)
+}
+
+#[test]
+fn block_executes_transactions_in_order() {
+    let (env, mut db) = block_env_and_db(1_000_000, counter_code());
+    let transactions = [call_tx(100_000, 2, 0), call_tx(100_000, 2, 0)];
+
+    let block = execute_block(&env, &transactions, &mut db).unwrap();
+
+    let gas_used: Vec<u64> = block.results.iter().map(|result| result.gas_used).collect();
+    assert!(block.receipts.iter().all(|receipt| receipt.status));
+    assert_eq!(block.receipts[0].cumulative_gas, gas_used[0]);
+    assert_eq!(block.receipts[1].cumulative_gas, gas_used[0] + gas_used[1]);
+    assert_eq!(block.gas_used, gas_used[0] + gas_used[1]);
+    // The second transaction sees the value stored by the first
+    assert_eq!(block.receipts[0].logs[0].topics, [U256::from(1)]);
+    assert_eq!(block.receipts[1].logs[0].topics, [U256::from(2)]);
+    assert!(block.bloom.contains_input(&CALLEE_ADDRESS));
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::default()), U256::from(2));
+    assert_eq!(db.account(&CREATOR_ADDRESS).unwrap().nonce, 2);
+    // The coinbase gets the gas price over the base fee
+    let coinbase_fees = U256::from(block.gas_used as u128);
+    assert_eq!(block.coinbase_fees, coinbase_fees);
+    assert_eq!(db.balance(&EOA_ADDRESS), coinbase_fees);
+}
+
+#[test]
+fn block_includes_failed_transactions() {
+    let mut callee_code = counter_code();
+    callee_code.extend([Operation::Push0, Operation::Push0, Operation::Revert]);
+    let (env, mut db) = block_env_and_db(1_000_000, callee_code);
+    let transactions = [call_tx(100_000, 1, 0), call_tx(100_000, 1, 0)];
+
+    let block = execute_block(&env, &transactions, &mut db).unwrap();
+
+    assert_eq!(block.receipts.len(), 2);
+    assert!(block.receipts.iter().all(|receipt| !receipt.status));
+    assert!(block.receipts.iter().all(|receipt| receipt.logs.is_empty()));
+    assert_eq!(block.bloom, Bloom::default());
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::default()), U256::default());
+    // Both paid for their gas
+    let expected_balance = SENDER_BALANCE - block.gas_used as u128;
+    assert_eq!(db.balance(&CREATOR_ADDRESS), U256::from(expected_balance));
+}
+
+#[test]
+fn block_over_its_gas_limit_is_invalid() {
+    let (env, mut db) = block_env_and_db(150_000, counter_code());
+    // The second one's gas limit is more than the gas the first one left
+    let transactions = [call_tx(100_000, 1, 0), call_tx(140_000, 1, 0)];
+
+    let result = execute_block(&env, &transactions, &mut db);
+
+    assert_eq!(result, Err(BlockError::GasLimitReached { index: 1 }));
+    // The first transaction is rolled back
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::default()), U256::default());
+    assert_eq!(db.account(&CREATOR_ADDRESS).unwrap().nonce, 0);
+}
+
+#[test]
+fn block_with_rejected_transaction_is_invalid() {
+    let (env, mut db) = block_env_and_db(1_000_000, counter_code());
+    let unfunded_tx = TxEnv {
+        origin: EOA_ADDRESS,
+        ..call_tx(100_000, 1, 0)
+    };
+    let transactions = [call_tx(100_000, 1, 0), unfunded_tx];
+
+    let result = execute_block(&env, &transactions, &mut db);
+
+    let expected_error = BlockError::InvalidTransaction {
+        index: 1,
+        error: TransactionError::InsufficientFunds,
+    };
+    assert_eq!(result, Err(expected_error));
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::default()), U256::default());
+    assert_eq!(db.balance(&CREATOR_ADDRESS), U256::from(SENDER_BALANCE));
+}
+
+#[test]
+fn deploy_stores_the_returned_code() {
+    let code = [0x60, 0x01, 0x60, 0x02, 0x01];
+    let init_code = Program::from(initcode_returning(&code)).to_bytecode();
+    let mut context = create_context(0);
+
+    let result = context.deploy(init_code, U256::from(10), 100_000);
+
+    let init_gas = gas_cost::PUSHN * 3
+        + gas_cost::PUSH0
+        + gas_cost::MSTORE
+        + memory_expansion_cost(0, 1)
+        + gas_cost::CODE_DEPOSIT_BYTE * code.len() as i64;
+    assert_eq!(result.address, Some(CREATED_ADDRESS));
+    assert_eq!(result.gas_used, init_gas as u64);
+    let created = context.accounts().account(&CREATED_ADDRESS).unwrap();
+    assert_eq!(created.code, code);
+    assert_eq!(created.nonce, 1);
+    assert_eq!(created.balance, U256::from(10));
+    let deployer = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(deployer.nonce, 1);
+    assert_eq!(deployer.balance, U256::from(CALLER_BALANCE - 10));
+}
+
+#[test]
+fn deploy_with_reverting_initcode_returns_its_output() {
+    let init_code = Program::from(callee_output_word(Operation::Revert)).to_bytecode();
+    let mut context = create_context(0);
+
+    let result = context.deploy(init_code, U256::from(10), 100_000);
+
+    assert_eq!(result.address, None);
+    assert_eq!(result.output.last(), Some(&0xAB));
+    assert_eq!(balance_of(&context, &CREATED_ADDRESS), U256::default());
+    let deployer = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(deployer.balance, U256::from(CALLER_BALANCE));
+}
+
+#[test]
+fn deploy_with_initcode_too_large_uses_all_the_gas() {
+    let mut context = create_context(0);
+
+    let result = context.deploy(vec![0; MAX_INITCODE_SIZE + 1], U256::default(), 100_000);
+
+    assert_eq!(result.address, None);
+    assert_eq!(result.gas_used, 100_000);
+    let deployer = context.accounts().account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(deployer.nonce, 0);
+}
+
+#[test]
+fn transaction_creating_contract_with_code_too_large_fails() {
+    let init_code = Program::from(vec![
+        Operation::Push(BigUint::from(MAX_CODE_SIZE + 1)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let tx = TxEnv {
+        to: None,
+        data: init_code.to_bytecode(),
+        ..call_tx(10_000_000, 1, 0)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact().unwrap();
+
+    // Failing to deposit the code is an exceptional halt, which consumes all the gas
+    assert!(!result.success);
+    assert_eq!(result.created_address, None);
+    assert_eq!(result.gas_used, 10_000_000);
+    let created = context
+        .accounts()
+        .account(&CREATED_ADDRESS)
+        .unwrap_or_default();
+    assert!(created.code.is_empty());
+}
+
+#[test]
+fn transaction_with_initcode_too_large_is_rejected() {
+    let tx = TxEnv {
+        to: None,
+        data: vec![0; MAX_INITCODE_SIZE + 1],
+        ..call_tx(10_000_000, 1, 0)
+    };
+    let mut context = transaction_context(tx, vec![]);
+
+    let result = context.transact();
+
+    let expected_error = TransactionError::InitcodeTooLarge {
+        size: MAX_INITCODE_SIZE + 1,
+    };
+    assert_eq!(result, Err(expected_error));
+}
+
+#[rstest]
+#[case(Operation::Returndatasize, SpecId::Byzantium)]
+#[case(Operation::Chainid, SpecId::Istanbul)]
+#[case(Operation::Selfbalance, SpecId::Istanbul)]
+#[case(Operation::Basefee, SpecId::London)]
+#[case(Operation::Push0, SpecId::Shanghai)]
+#[case(Operation::Blobbasefee, SpecId::Cancun)]
+fn opcode_is_available_since_its_hardfork(#[case] operation: Operation, #[case] spec_id: SpecId) {
+    assert_eq!(operation.introduced_in(), spec_id);
+
+    let env = Env {
+        spec_id,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+    let result = run_program_with_spec(vec![operation], &mut context, 1e7 as _, spec_id);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+}
+
+#[rstest]
+#[case(Operation::Returndatasize, SpecId::SpuriousDragon)]
+#[case(Operation::Chainid, SpecId::Petersburg)]
+#[case(Operation::Selfbalance, SpecId::Petersburg)]
+#[case(Operation::Basefee, SpecId::Berlin)]
+#[case(Operation::Push0, SpecId::Merge)]
+#[case(Operation::Blobbasefee, SpecId::Shanghai)]
+#[case(Operation::Rjump(0), SpecId::Prague)]
+#[case(Operation::Dupn(0), SpecId::Prague)]
+fn opcode_before_its_hardfork_is_undefined(#[case] operation: Operation, #[case] spec_id: SpecId) {
+    let env = Env {
+        spec_id,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+    let result = run_program_with_spec(vec![operation], &mut context, 1e7 as _, spec_id);
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+}
+
+/// Runs the program compiled for Osaka, which brings the EOF relative jumps, and checks
+/// the least significant byte of the stack top
+fn run_osaka_program_assert_result(mut operations: Vec<Operation>, expected_result: u8) {
+    operations.extend([
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let env = Env {
+        spec_id: SpecId::Osaka,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+
+    let result = run_program_with_spec(operations, &mut context, 1e7 as _, SpecId::Osaka);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(context.return_values().last(), Some(&expected_result));
+}
+
+#[test]
+fn rjump_skips_to_its_target() {
+    // [00] RJUMP 1
+    // [03] INVALID
+    // [04] PUSH1 42
+    let program = vec![
+        Operation::Rjump(1),
+        Operation::Invalid,
+        Operation::Push(BigUint::from(42_u8)),
+    ];
+    run_osaka_program_assert_result(program, 42);
+}
+
+#[test]
+fn rjump_out_of_the_program_reverts() {
+    let env = Env {
+        spec_id: SpecId::Osaka,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+    let program = vec![Operation::Rjump(5), Operation::Stop];
+
+    let result = run_program_with_spec(program, &mut context, 1e7 as _, SpecId::Osaka);
+
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+}
+
+#[test]
+fn rjumpi_loops_while_not_zero() {
+    // Adds 2 to the first item as many times as the second one counts down from 5
+    // [00] PUSH0
+    // [01] PUSH1 5
+    // [03] SWAP1
+    // [04] PUSH1 2
+    // [06] ADD
+    // [07] SWAP1
+    // [08] PUSH1 1
+    // [10] SWAP1
+    // [11] SUB
+    // [12] DUP1
+    // [13] RJUMPI -13
+    // [16] POP
+    let program = vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Swap(1),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+        Operation::Swap(1),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Dup(1),
+        Operation::Rjumpi(-13),
+        Operation::Pop,
+    ];
+    run_osaka_program_assert_result(program, 10);
+}
+
+#[rstest]
+#[case(BigUint::from(0_u8), 20)]
+#[case(BigUint::from(1_u8), 30)]
+#[case(BigUint::from(2_u8), 10)]
+#[case(BigUint::from(1_u64 << 32), 10)]
+fn rjumpv_jumps_to_the_indexed_target(#[case] index: BigUint, #[case] expected_result: u8) {
+    // Indices past the last offset fall through to the next operation
+    // [00] PUSHN index
+    // [..] RJUMPV 5, 10
+    // [+0] PUSH1 10
+    // [+2] RJUMP 7
+    // [+5] PUSH1 20
+    // [+7] RJUMP 2
+    // [+10] PUSH1 30
+    let program = vec![
+        Operation::Push(index),
+        Operation::Rjumpv(vec![5, 10]),
+        Operation::Push(BigUint::from(10_u8)),
+        Operation::Rjump(7),
+        Operation::Push(BigUint::from(20_u8)),
+        Operation::Rjump(2),
+        Operation::Push(BigUint::from(30_u8)),
+    ];
+    run_osaka_program_assert_result(program, expected_result);
+}
+
+#[test]
+fn rjumpi_with_stack_underflow() {
+    let env = Env {
+        spec_id: SpecId::Osaka,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+
+    let result = run_program_with_spec(
+        vec![Operation::Rjumpi(0)],
+        &mut context,
+        1e7 as _,
+        SpecId::Osaka,
+    );
+
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+}
+
+#[test]
+fn eof_code_section_runs() {
+    // Counts down from 3 with RJUMPI, then returns 42
+    let code = vec![
+        0x60, 0x03, 0x60, 0x01, 0x90, 0x03, 0x80, 0xE1, 0xFF, 0xF8, 0x50, 0x60, 0x2A, 0x5F, 0x52,
+        0x60, 0x20, 0x5F, 0xF3,
+    ];
+    let code_section = CodeSection {
+        inputs: 0,
+        outputs: NON_RETURNING,
+        max_stack_height: 2,
+        code,
+    };
+    let bytecode = Container::new(vec![code_section], vec![], vec![]).to_bytecode();
+    let container = Container::from_bytecode(&bytecode).unwrap();
+    let program = container.program(0).unwrap();
+    let env = Env {
+        spec_id: SpecId::Osaka,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_env(env);
+
+    let result = run_program_with_spec(program, &mut context, 1e7 as _, SpecId::Osaka);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(context.return_values().last(), Some(&42));
+}
+
+/// Validates a container of the code sections and data, and runs them compiled for Osaka
+fn run_code_sections(
+    code_sections: Vec<CodeSection>,
+    data: Vec<u8>,
+    context: &mut SyscallContext,
+) -> u8 {
+    let bytecode = Container::new(code_sections, vec![], data).to_bytecode();
+    let container = Container::from_bytecode(&bytecode).unwrap();
+    let programs = container.programs().unwrap();
+    let executor =
+        Executor::compile_code_sections(&programs, container.data(), SpecId::Osaka).unwrap();
+    executor.execute(context, 1e7 as _)
+}
+
+fn osaka_context() -> SyscallContext {
+    SyscallContext::with_env(Env {
+        spec_id: SpecId::Osaka,
+        ..Default::default()
+    })
+}
+
+#[test]
+fn callf_returns_the_outputs_of_the_section() {
+    let code_sections = vec![
+        CodeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 2,
+            // PUSH1 20, CALLF 1, then returns the stack top
+            code: vec![
+                0x60, 0x14, 0xE3, 0x00, 0x01, 0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3,
+            ],
+        },
+        CodeSection {
+            inputs: 1,
+            outputs: 1,
+            max_stack_height: 2,
+            // DUP1, ADD, RETF
+            code: vec![0x80, 0x01, 0xE4],
+        },
+    ];
+    let mut context = osaka_context();
+
+    let result = run_code_sections(code_sections, vec![], &mut context);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(context.return_values().last(), Some(&40));
+}
+
+#[test]
+fn jumpf_goes_on_into_the_section() {
+    let code_sections = vec![
+        CodeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 1,
+            // PUSH1 7, JUMPF 1
+            code: vec![0x60, 0x07, 0xE5, 0x00, 0x01],
+        },
+        CodeSection {
+            inputs: 1,
+            outputs: NON_RETURNING,
+            max_stack_height: 2,
+            // Returns the stack top
+            code: vec![0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3],
+        },
+    ];
+    let mut context = osaka_context();
+
+    let result = run_code_sections(code_sections, vec![], &mut context);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    assert_eq!(context.return_values().last(), Some(&7));
+}
+
+#[rstest]
+#[case(0, ExitStatusCode::Stop)]
+#[case(1, ExitStatusCode::Revert)]
+fn halting_in_a_called_section_halts_the_program(
+    #[case] condition: u8,
+    #[case] expected_status: ExitStatusCode,
+) {
+    let code_sections = vec![
+        CodeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 1,
+            // PUSH1 condition, CALLF 1, STOP
+            code: vec![0x60, condition, 0xE3, 0x00, 0x01, 0x00],
+        },
+        CodeSection {
+            inputs: 1,
+            outputs: 0,
+            max_stack_height: 2,
+            // RJUMPI 1, RETF, then reverts
+            code: vec![0xE1, 0x00, 0x01, 0xE4, 0x5F, 0x5F, 0xFD],
+        },
+    ];
+    let mut context = osaka_context();
+
+    let result = run_code_sections(code_sections, vec![], &mut context);
+
+    assert_eq!(result, expected_status.to_u8());
+}
+
+#[test]
+fn callf_past_the_return_stack_limit_halts() {
+    let code_sections = vec![
+        CodeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 0,
+            // CALLF 1, STOP
+            code: vec![0xE3, 0x00, 0x01, 0x00],
+        },
+        CodeSection {
+            inputs: 0,
+            outputs: 0,
+            max_stack_height: 0,
+            // Calls itself until the return stack is full
+            code: vec![0xE3, 0x00, 0x01, 0xE4],
+        },
+    ];
+    let mut context = osaka_context();
+
+    let result = run_code_sections(code_sections, vec![], &mut context);
+
+    assert_eq!(result, ExitStatusCode::Error.to_u8());
+}
+
+#[test]
+fn callf_to_a_section_not_given_is_rejected() {
+    let section_type = SectionType {
+        inputs: 0,
+        outputs: 0,
+        max_stack_height: 0,
+    };
+    let program = Program::from(vec![Operation::Callf(1, section_type), Operation::Stop]);
+
+    let result = Context::new().compile_jit(&program, SpecId::Osaka);
+
+    assert!(matches!(
+        result,
+        Err(CodegenError::InvalidProgram(
+            ProgramError::UndefinedCodeSection { section: 1 }
+        ))
+    ));
+}
+
+#[test]
+fn compiling_no_code_sections_is_rejected() {
+    let result = Executor::compile_code_sections(&[], &[], SpecId::Osaka);
+
+    assert!(matches!(
+        result,
+        Err(CodegenError::InvalidProgram(ProgramError::NoCodeSections))
+    ));
+}
+
+/// Runs the code section with the data, returning its output
+fn run_code_section(code: Vec<u8>, max_stack_height: u16, data: Vec<u8>) -> Vec<u8> {
+    let code_section = CodeSection {
+        inputs: 0,
+        outputs: NON_RETURNING,
+        max_stack_height,
+        code,
+    };
+    let mut context = osaka_context();
+
+    let result = run_code_sections(vec![code_section], data, &mut context);
+
+    assert_eq!(result, ExitStatusCode::Return.to_u8());
+    context.return_values().to_vec()
+}
+
+#[rstest]
+#[case(0, (1..=32).collect())]
+#[case(20, (21..=40).chain([0; 12]).collect())]
+#[case(40, vec![0; 32])]
+#[case(255, vec![0; 32])]
+fn dataload_reads_the_data_zero_padded(#[case] offset: u8, #[case] expected: Vec<u8>) {
+    // PUSH1 offset, DATALOAD, then returns the word
+    let code = vec![0x60, offset, 0xD0, 0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3];
+
+    let output = run_code_section(code, 2, (1..=40).collect());
+
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn dataloadn_reads_the_word_at_its_offset() {
+    // DATALOADN 8, then returns the word
+    let code = vec![0xD1, 0x00, 0x08, 0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3];
+
+    let output = run_code_section(code, 2, (1..=40).collect());
+
+    assert_eq!(output, (9..=40).collect::<Vec<u8>>());
+}
+
+#[test]
+fn datasize_pushes_the_size_of_the_data() {
+    // DATASIZE, then returns it
+    let code = vec![0xD2, 0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3];
+
+    let output = run_code_section(code, 2, (1..=40).collect());
+
+    assert_eq!(output.last(), Some(&40));
+}
+
+#[test]
+fn datacopy_copies_the_data_zero_padded() {
+    // Fills the first word of memory with ones, copies 16 bytes of the data from offset
+    // 30 over it, and returns the word
+    let code = vec![
+        0x5F, 0x19, 0x5F, 0x52, 0x60, 0x10, 0x60, 0x1E, 0x5F, 0xD3, 0x60, 0x20, 0x5F, 0xF3,
+    ];
+
+    let output = run_code_section(code, 3, (1..=40).collect());
+
+    let expected: Vec<u8> = (31..=40).chain([0; 6]).chain([0xFF; 16]).collect();
+    assert_eq!(output, expected);
+}
+
+#[rstest]
+// PUSH5 2^32
+#[case(vec![0x64, 0x01, 0x00, 0x00, 0x00, 0x00])]
+// PUSH4 0xFFFFFFF0, which wraps around when adding the size in 32 bits
+#[case(vec![0x63, 0xFF, 0xFF, 0xFF, 0xF0])]
+fn datacopy_out_of_gas_on_destination_too_big(#[case] push_dest_offset: Vec<u8>) {
+    // Copies a word of the data to the destination, then stops
+    let mut code = vec![0x60, 0x20, 0x5F];
+    code.extend(push_dest_offset);
+    code.extend([0xD3, 0x00]);
+    let code_section = CodeSection {
+        inputs: 0,
+        outputs: NON_RETURNING,
+        max_stack_height: 3,
+        code,
+    };
+    let mut context = osaka_context();
+
+    let result = run_code_sections(vec![code_section], (1..=40).collect(), &mut context);
+
+    assert_eq!(result, ExitStatusCode::OutOfGas.to_u8());
+}
+
+/// Pushes the values in order, for a code section
+fn push_all(values: impl IntoIterator<Item = u8>) -> Vec<u8> {
+    values.into_iter().flat_map(|value| [0x60, value]).collect()
+}
+
+/// Returns the top of the stack, for a code section
+const RETURN_TOP: [u8; 6] = [0x5F, 0x52, 0x60, 0x20, 0x5F, 0xF3];
+
+#[test]
+fn dupn_copies_items_deeper_than_dup16() {
+    // DUPN 19 copies the 20th item, the first one pushed
+    let mut code = push_all(1..=20);
+    code.extend([0xE6, 0x13]);
+    code.extend(RETURN_TOP);
+
+    let output = run_code_section(code, 22, vec![]);
+
+    assert_eq!(output.last(), Some(&1));
+}
+
+#[test]
+fn swapn_swaps_items_deeper_than_swap16() {
+    // SWAPN 18 swaps the top with the 20th item, then the 20th item is returned
+    let mut code = push_all(1..=20);
+    code.extend([0xE7, 0x12, 0xE6, 0x13]);
+    code.extend(RETURN_TOP);
+
+    let output = run_code_section(code, 22, vec![]);
+
+    assert_eq!(output.last(), Some(&20));
+}
+
+#[test]
+fn exchange_swaps_the_items_of_its_immediate() {
+    // EXCHANGE 0x12 swaps the 3rd and 6th items, then the 3rd one is returned
+    let mut code = push_all([10, 20, 30, 40, 50, 60]);
+    code.extend([0xE8, 0x12, 0x50, 0x50]);
+    code.extend(RETURN_TOP);
+
+    let output = run_code_section(code, 6, vec![]);
+
+    assert_eq!(output.last(), Some(&10));
+}
+
+#[test]
+fn spec_ids_are_ordered_by_activation() {
+    assert!(SpecId::Cancun.includes(SpecId::Shanghai));
+    assert!(SpecId::Cancun.includes(SpecId::Cancun));
+    assert!(!SpecId::Shanghai.includes(SpecId::Cancun));
+    assert!(SpecId::Frontier < SpecId::Prague);
+    assert_eq!(SpecId::default(), SpecId::Cancun);
+}
+
+#[test]
+fn sstore_refund_before_london() {
+    let storage = InMemoryStorage::from_iter([(DEFAULT_ADDRESS, U256::default(), U256::from(1))]);
+    let env = Env {
+        spec_id: SpecId::Berlin,
+        ..Default::default()
+    };
+    let mut context = SyscallContext::new(env, Vec::new(), Box::new(storage));
+    // PUSH0 wasn't introduced yet
+    let zero = || Operation::Push(BigUint::ZERO);
+    let program = vec![zero(), zero(), Operation::Sstore];
+
+    let result = run_program_with_spec(program, &mut context, 1e7 as _, SpecId::Berlin);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    // Clearing a slot refunded more, and at most half of the gas used was refunded
+    assert_eq!(context.gas_refund(10000), 5000);
+    assert_eq!(
+        context.gas_refund(1e7 as _),
+        gas_cost::SSTORE_CLEAR_REFUND_PRE_LONDON as u64
+    );
+}
+
+#[test]
+fn selfdestruct_before_cancun_deletes_account() {
+    let storage = InMemoryStorage::from_iter([(CALLEE_ADDRESS, U256::from(1), U256::from(7))]);
+    let env = Env {
+        spec_id: SpecId::Shanghai,
+        tx: call_tx(100_000, 0, 10),
+        ..Default::default()
+    };
+    let callee_code = vec![push_address(EOA_ADDRESS), Operation::Selfdestruct];
+    let mut context = transaction_context_with(env, callee_code, storage);
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    let deleted = context
+        .accounts()
+        .account(&CALLEE_ADDRESS)
+        .unwrap_or_default();
+    let beneficiary = context.accounts().account(&EOA_ADDRESS).unwrap();
+    assert_eq!(deleted, AccountInfo::default());
+    assert_eq!(
+        context.storage().read(&CALLEE_ADDRESS, &U256::from(1)),
+        U256::default()
+    );
+    assert_eq!(beneficiary.balance, U256::from(10));
+}
+
+#[test]
+fn create_before_shanghai_does_not_charge_initcode_words() {
+    // Creates a contract with a single-byte initcode from the empty memory, which is a STOP,
+    // and returns the gas left. PUSH0 isn't used, as it was introduced in Shanghai
+    let zero = || Operation::Push(BigUint::ZERO);
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        zero(),
+        zero(),
+        Operation::Create,
+        Operation::Pop,
+        Operation::Gas,
+        zero(),
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        zero(),
+        Operation::Return,
+    ];
+    let gas_left = |spec_id| {
+        let env = Env {
+            spec_id,
+            call: CallEnv {
+                address: CREATOR_ADDRESS,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut context = SyscallContext::with_env(env);
+        let result = run_program_with_spec(program.clone(), &mut context, 1e7 as _, spec_id);
+        assert_eq!(result, ExitStatusCode::Return.to_u8());
+        BigUint::from_bytes_be(context.return_values())
+    };
+
+    let gas_difference = gas_left(SpecId::Merge) - gas_left(SpecId::Shanghai);
+    assert_eq!(
+        gas_difference,
+        BigUint::from(gas_cost::INITCODE_WORD as u64)
+    );
+}
+
+#[test]
+fn executor_compiles_and_runs_program_in_process() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+    let mut context = SyscallContext::default();
+
+    let result = executor.run(&mut context, 1e7 as _);
+
+    let mut expected_output = [0; 32];
+    expected_output[31] = 42;
+    assert!(result.is_success());
+    assert_eq!(result.output(), expected_output);
+}
+
+#[test]
+fn executor_runs_program_many_times() {
+    let program = Program::from(vec![
+        Operation::Push0,
+        Operation::CallDataLoad,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+
+    for byte in 1..=3 {
+        let mut context = SyscallContext::with_calldata(vec![byte; 32]);
+        let result = executor.run(&mut context, 1e7 as _);
+        assert!(result.is_success());
+        assert_eq!(result.output(), [byte; 32]);
+    }
+}
+
+#[test]
+fn executor_reports_how_program_halted() {
+    let program = Program::from(vec![Operation::Push0, Operation::Push0, Operation::Revert]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+
+    let result = executor.run(&mut SyscallContext::default(), 1e7 as _);
+    let expected_result = ExecutionResult::Revert {
+        gas_used: gas_cost::PUSH0 as u64 * 2,
+        output: vec![],
+    };
+    assert_eq!(result, expected_result);
+
+    let result = executor.run(&mut SyscallContext::default(), 1);
+    let expected_result = ExecutionResult::Halt {
+        reason: HaltReason::OutOfGas,
+    };
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn executor_compiles_for_given_hardfork() {
+    let program = Program::from(vec![Operation::Push0]);
+    let executor = Executor::compile(&program, SpecId::Merge).unwrap();
+
+    let result = executor.run(&mut SyscallContext::default(), 1e7 as _);
+
+    let expected_result = ExecutionResult::Halt {
+        reason: HaltReason::Error,
+    };
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn jit_compiled_module_runs_in_executor() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push(BigUint::from(4_u8)),
+        Operation::Add,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let context = Context::new();
+    let module = context.compile_jit(&program, SpecId::default()).unwrap();
+    let executor = Executor::new(&module);
+    let mut syscall_context = SyscallContext::default();
+
+    let result = executor.run(&mut syscall_context, 1e7 as _);
+
+    let mut expected_output = [0; 32];
+    expected_output[31] = 7;
+    assert!(result.is_success());
+    assert_eq!(result.output(), expected_output);
+}
+
+#[test]
+fn execution_result_of_return_has_gas_used_and_output() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+
+    let result = executor.run(&mut SyscallContext::default(), 1e7 as _);
+
+    // A returned value of 2 isn't mistaken for a REVERT
+    let mut output = vec![0; 32];
+    output[31] = 2;
+    let gas_used =
+        gas_cost::PUSHN * 2 + gas_cost::PUSH0 * 2 + gas_cost::MSTORE + memory_expansion_cost(0, 1);
+    let expected_result = ExecutionResult::Success {
+        gas_used: gas_used as u64,
+        output,
+        logs: vec![],
+        state_diff: StateDiff::default(),
+    };
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn execution_result_of_stop_has_logs() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(0xAB_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+        Operation::Stop,
+    ]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+
+    let result = executor.run(&mut SyscallContext::default(), 1e7 as _);
+
+    let ExecutionResult::Success { output, logs, .. } = result else {
+        panic!("expected a success, got {result:?}");
+    };
+    let expected_log = Log {
+        data: vec![],
+        topics: vec![U256::from(0xAB)],
+    };
+    assert!(output.is_empty());
+    assert_eq!(logs, [expected_log]);
+}
+
+#[test]
+fn execution_result_of_exceptional_halt() {
+    let program = Program::from(vec![Operation::Add]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+
+    let result = executor.run(&mut SyscallContext::default(), 1e7 as _);
+
+    let expected_result = ExecutionResult::Halt {
+        reason: HaltReason::Error,
+    };
+    assert_eq!(result, expected_result);
+    assert!(result.output().is_empty());
+}
+
+/// A [`Host`] keeping all the state in memory
+#[derive(Debug, Default)]
+struct TestHost {
+    storage: InMemoryStorage,
+    accounts: InMemoryAccounts,
+    block_hashes: InMemoryBlockHashes,
+}
+
+impl Host for TestHost {
+    fn sload(&self, address: &Address, key: &U256) -> U256 {
+        self.storage.read(address, key)
+    }
+
+    fn sstore(&mut self, address: Address, key: U256, value: U256) {
+        self.storage.write(address, key, value);
+    }
+
+    fn clear_storage(&mut self, address: &Address) {
+        self.storage.clear(address);
+    }
+
+    fn account(&self, address: &Address) -> Option<AccountInfo> {
+        self.accounts.account(address)
+    }
+
+    fn set_account(&mut self, address: Address, account: AccountInfo) {
+        self.accounts.set_account(address, account);
+    }
+
+    fn block_hash(&self, number: &U256) -> U256 {
+        self.block_hashes.block_hash(number)
+    }
+}
+
+#[test]
+fn host_storage_is_read_and_written() {
+    let host = Rc::new(RefCell::new(TestHost {
+        storage: InMemoryStorage::from_iter([(DEFAULT_ADDRESS, U256::from(1), U256::from(42))]),
+        ..Default::default()
+    }));
+    let mut context = SyscallContext::with_host(Env::default(), Vec::new(), host.clone());
+    // Copies slot 1 into slot 2
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Sstore,
+    ];
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let value = host.borrow().sload(&DEFAULT_ADDRESS, &U256::from(2));
+    assert_eq!(value, U256::from(42));
+}
+
+#[test]
+fn host_provides_accounts() {
+    let host = Rc::new(RefCell::new(TestHost::default()));
+    host.borrow_mut().set_account(
+        CONTRACT_ADDRESS,
+        AccountInfo {
+            code: CONTRACT_CODE.to_vec(),
+            ..Default::default()
+        },
+    );
+    let context = SyscallContext::with_host(Env::default(), Vec::new(), host);
+    let program = vec![push_address(CONTRACT_ADDRESS), Operation::Extcodesize];
+    run_program_assert_stack_top(program, context, CONTRACT_CODE.len() as u8, 1e7 as _);
+}
+
+#[test]
+fn host_provides_block_hashes() {
+    let hash = U256::from(0xABCD);
+    let host = Rc::new(RefCell::new(TestHost {
+        block_hashes: InMemoryBlockHashes::from_iter([(U256::from(9), hash)]),
+        ..Default::default()
+    }));
+    let env = Env {
+        block: BlockEnv {
+            number: U256::from(10),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let context = SyscallContext::with_host(env, Vec::new(), host);
+    let program = vec![Operation::Push(BigUint::from(9_u8)), Operation::Blockhash];
+    run_program_assert_word(program, context, hash.to_be_bytes());
+}
+
+#[test]
+fn host_accounts_are_updated_by_calls() {
+    let host = Rc::new(RefCell::new(TestHost::default()));
+    host.borrow_mut().set_account(
+        CALLER_ADDRESS,
+        AccountInfo {
+            balance: U256::from(CALLER_BALANCE),
+            ..Default::default()
+        },
+    );
+    let env = Env {
+        call: CallEnv {
+            address: CALLER_ADDRESS,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let mut context = SyscallContext::with_host(env, Vec::new(), host.clone());
+    let program = call_operations(1000, EOA_ADDRESS, 100, (0, 0), (0, 0));
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert_eq!(
+        host.borrow().balance(&CALLER_ADDRESS),
+        U256::from(CALLER_BALANCE - 100)
+    );
+    assert_eq!(host.borrow().balance(&EOA_ADDRESS), U256::from(100));
+}
+
+#[test]
+fn in_memory_db_reads_inserted_state() {
+    let mut db = InMemoryDB::new();
+    let account = AccountInfo {
+        balance: U256::from(100),
+        nonce: 2,
+        code: CONTRACT_CODE.to_vec(),
+    };
+    db.insert_account(CONTRACT_ADDRESS, account.clone());
+    db.insert_storage(CONTRACT_ADDRESS, U256::from(1), U256::from(42));
+    db.insert_block_hash(U256::from(9), U256::from(0xABCD));
+
+    assert_eq!(db.account(&CONTRACT_ADDRESS), Some(account.clone()));
+    assert_eq!(db.account(&EOA_ADDRESS), None);
+    assert_eq!(db.sload(&CONTRACT_ADDRESS, &U256::from(1)), U256::from(42));
+    assert_eq!(db.block_hash(&U256::from(9)), U256::from(0xABCD));
+    assert_eq!(
+        db.code_by_hash(&account.code_hash()),
+        Some(&CONTRACT_CODE[..])
+    );
+}
+
+#[test]
+fn in_memory_db_reverts_to_checkpoint() {
+    let mut db = InMemoryDB::new();
+    db.insert_storage(CONTRACT_ADDRESS, U256::from(1), U256::from(42));
+    let checkpoint = db.checkpoint().unwrap();
+
+    db.sstore(CONTRACT_ADDRESS, U256::from(1), U256::from(7));
+    db.sstore(CONTRACT_ADDRESS, U256::from(2), U256::from(7));
+    db.set_account(EOA_ADDRESS, AccountInfo::default());
+    db.revert(checkpoint);
+
+    assert_eq!(db.sload(&CONTRACT_ADDRESS, &U256::from(1)), U256::from(42));
+    assert_eq!(db.sload(&CONTRACT_ADDRESS, &U256::from(2)), U256::default());
+    assert_eq!(db.account(&EOA_ADDRESS), None);
+}
+
+#[test]
+fn in_memory_db_reverts_nested_checkpoints() {
+    let mut db = InMemoryDB::new();
+    let outer = db.checkpoint().unwrap();
+    db.sstore(CONTRACT_ADDRESS, U256::from(1), U256::from(1));
+    let inner = db.checkpoint().unwrap();
+    db.sstore(CONTRACT_ADDRESS, U256::from(1), U256::from(2));
+
+    db.revert(inner);
+    assert_eq!(db.sload(&CONTRACT_ADDRESS, &U256::from(1)), U256::from(1));
+
+    db.revert(outer);
+    assert_eq!(db.sload(&CONTRACT_ADDRESS, &U256::from(1)), U256::default());
+}
+
+#[test]
+fn in_memory_db_keeps_committed_changes() {
+    let mut db = InMemoryDB::new();
+    let checkpoint = db.checkpoint().unwrap();
+    let account = AccountInfo {
+        balance: U256::from(100),
+        ..Default::default()
+    };
+    db.set_account(EOA_ADDRESS, account.clone());
+
+    db.commit();
+    db.revert(checkpoint);
+
+    assert_eq!(db.account(&EOA_ADDRESS), Some(account));
+}
+
+#[test]
+fn in_memory_db_lists_changes_since_last_commit() {
+    let mut db = InMemoryDB::new();
+    db.insert_account(CONTRACT_ADDRESS, AccountInfo::default());
+    db.set_account(EOA_ADDRESS, AccountInfo::default());
+    db.sstore(CONTRACT_ADDRESS, U256::from(1), U256::from(1));
+    db.commit();
+
+    db.sstore(CONTRACT_ADDRESS, U256::from(2), U256::from(2));
+    db.sstore(CONTRACT_ADDRESS, U256::from(2), U256::from(3));
+    let checkpoint = db.checkpoint().unwrap();
+    db.set_account(CONTRACT_ADDRESS, AccountInfo::default());
+    db.sstore(CONTRACT_ADDRESS, U256::from(3), U256::from(3));
+    db.revert(checkpoint);
+
+    assert!(db.changed_accounts().is_empty());
+    assert_eq!(
+        db.changed_storage(),
+        HashSet::from([(CONTRACT_ADDRESS, U256::from(2))])
+    );
+}
+
+/// A context executing at [`CALLER_ADDRESS`], with some balance, holding its state in an
+/// [`InMemoryDB`] with an account at [`CALLEE_ADDRESS`] with the given code
+fn in_memory_db_context(callee_code: Vec<Operation>) -> (SyscallContext, Rc<RefCell<InMemoryDB>>) {
+    let mut db = InMemoryDB::new();
+    let caller = AccountInfo {
+        balance: U256::from(CALLER_BALANCE),
+        ..Default::default()
+    };
+    db.insert_account(CALLER_ADDRESS, caller);
+    let callee = AccountInfo {
+        code: Program::from(callee_code).to_bytecode(),
+        ..Default::default()
+    };
+    db.insert_account(CALLEE_ADDRESS, callee);
+    let env = Env {
+        call: CallEnv {
+            address: CALLER_ADDRESS,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let db = Rc::new(RefCell::new(db));
+    let context = SyscallContext::with_host(env, Vec::new(), db.clone());
+    (context, db)
+}
+
+#[test]
+fn call_to_callee_that_succeeds_keeps_its_changes() {
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 100, (0, 0), (0, 0));
+    let (mut context, db) = in_memory_db_context(callee_storing());
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let db = db.borrow();
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::from(1)), U256::from(42));
+    assert_eq!(db.balance(&CALLEE_ADDRESS), U256::from(100));
+}
+
+#[test]
+fn call_to_callee_that_reverts_rolls_back_its_changes() {
+    let program = call_operations(1e5 as _, CALLEE_ADDRESS, 100, (0, 0), (0, 0));
+    let mut callee_code = callee_storing();
+    callee_code.extend([Operation::Push0, Operation::Push0, Operation::Revert]);
+    let (mut context, db) = in_memory_db_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let db = db.borrow();
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::from(1)), U256::default());
+    assert_eq!(db.balance(&CALLER_ADDRESS), U256::from(CALLER_BALANCE));
+    assert_eq!(db.balance(&CALLEE_ADDRESS), U256::default());
+}
+
+#[test]
+fn nested_call_that_reverts_only_rolls_back_its_own_changes() {
+    // The callee stores 42 at slot 1, and then calls itself with no value, which
+    // stores at slot 2 and reverts when the slot 1 is already set
+    let callee_code = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+        Operation::Push(BigUint::from(47_u8)),
+        Operation::Jumpi,
+        // First call: store 42 at slot 1, and call itself
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+    ]
+    .into_iter()
+    .chain(call_operations(5e4 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0)))
+    .chain([
+        Operation::Stop,
+        // Nested call: store 7 at slot 2, and revert
+        Operation::Jumpdest { pc: 47 },
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ])
+    .collect::<Vec<_>>();
+    let jumpdest = Program::from(callee_code[..callee_code.len() - 7].to_vec())
+        .to_bytecode()
+        .len();
+    assert_eq!(jumpdest, 47, "the jump destination moved");
+    let program = call_operations(1e6 as _, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let (mut context, db) = in_memory_db_context(callee_code);
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let db = db.borrow();
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::from(1)), U256::from(42));
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::from(2)), U256::default());
+}
+
+#[test]
+fn create_with_initcode_that_reverts_rolls_back_its_changes() {
+    let init_code = vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sstore,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Revert,
+    ];
+    let program = create_operations(init_code, 100, None);
+    let mut db = InMemoryDB::new();
+    let creator = AccountInfo {
+        balance: U256::from(CALLER_BALANCE),
+        ..Default::default()
+    };
+    db.insert_account(CREATOR_ADDRESS, creator);
+    let env = Env {
+        call: CallEnv {
+            address: CREATOR_ADDRESS,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let db = Rc::new(RefCell::new(db));
+    let mut context = SyscallContext::with_host(env, Vec::new(), db.clone());
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let db = db.borrow();
+    assert_eq!(db.account(&CREATED_ADDRESS), None);
+    assert_eq!(db.sload(&CREATED_ADDRESS, &U256::from(1)), U256::default());
+    // The creator's nonce is still incremented
+    let creator = db.account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(creator.nonce, 1);
+    assert_eq!(creator.balance, U256::from(CALLER_BALANCE));
+}
+
+#[test]
+fn reverted_transaction_rolls_back_the_call_but_charges_the_sender() {
+    let mut callee_code = callee_storing();
+    callee_code.extend([Operation::Push0, Operation::Push0, Operation::Revert]);
+    let mut db = InMemoryDB::new();
+    let sender = AccountInfo {
+        balance: U256::from(SENDER_BALANCE),
+        ..Default::default()
+    };
+    db.insert_account(CREATOR_ADDRESS, sender);
+    let callee = AccountInfo {
+        code: Program::from(callee_code).to_bytecode(),
+        ..Default::default()
+    };
+    db.insert_account(CALLEE_ADDRESS, callee);
+    let env = Env {
+        tx: call_tx(1e5 as _, 1, 100),
+        ..Default::default()
+    };
+    let db = Rc::new(RefCell::new(db));
+    let mut context = SyscallContext::with_host(env, Vec::new(), db.clone());
+
+    let result = context.transact().unwrap();
+
+    assert!(!result.success);
+    let db = db.borrow();
+    assert_eq!(db.sload(&CALLEE_ADDRESS, &U256::from(1)), U256::default());
+    assert_eq!(db.balance(&CALLEE_ADDRESS), U256::default());
+    let sender = db.account(&CREATOR_ADDRESS).unwrap();
+    assert_eq!(sender.nonce, 1);
+    assert_eq!(
+        sender.balance,
+        U256::from(SENDER_BALANCE - result.gas_used as u128)
+    );
+}
+
+/// Runs the program compiled in instrumented mode, returning the steps it executed
+fn run_program_traced(
+    program: Vec<Operation>,
+    context: &mut SyscallContext,
+    initial_gas: u64,
+) -> Vec<TraceStep> {
+    let executor = Executor::compile_instrumented(&Program::from(program), SpecId::default())
+        .expect("failed to compile program");
+    context.enable_tracing();
+    executor.execute(context, initial_gas);
+    context.trace().to_vec()
+}
+
+#[test]
+fn trace_reports_each_opcode_before_executing_it() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+    ];
+    let mut context = SyscallContext::default();
+
+    let trace = run_program_traced(program, &mut context, 100);
+
+    let expected = vec![
+        TraceStep {
+            pc: 0,
+            op: 0x60,
+            gas: 100,
+            gas_cost: gas_cost::PUSHN as _,
+            depth: 1,
+            ..Default::default()
+        },
+        TraceStep {
+            pc: 2,
+            op: 0x60,
+            gas: 97,
+            gas_cost: gas_cost::PUSHN as _,
+            stack: vec![U256::from(1)],
+            depth: 1,
+            ..Default::default()
+        },
+        TraceStep {
+            pc: 4,
+            op: 0x01,
+            gas: 94,
+            gas_cost: gas_cost::ADD as _,
+            stack: vec![U256::from(1), U256::from(2)],
+            depth: 1,
+            ..Default::default()
+        },
+    ];
+    assert_eq!(trace, expected);
+}
+
+#[test]
+fn trace_reports_memory_size() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Stop,
+    ];
+    let mut context = SyscallContext::default();
+
+    let trace = run_program_traced(program, &mut context, 1000);
+
+    let mem_sizes: Vec<_> = trace.iter().map(|step| step.mem_size).collect();
+    assert_eq!(mem_sizes, vec![0, 0, 0, 32]);
+}
+
+#[test]
+fn trace_reports_jump_destinations() {
+    let program = vec![
+        Operation::Push(BigUint::from(4_u8)),
+        Operation::Jump,
+        Operation::Invalid,
+        Operation::Jumpdest { pc: 4 },
+    ];
+    let mut context = SyscallContext::default();
+
+    let trace = run_program_traced(program, &mut context, 1000);
+
+    let pcs: Vec<_> = trace.iter().map(|step| step.pc).collect();
+    assert_eq!(pcs, vec![0, 2, 4]);
+}
+
+#[test]
+fn trace_step_halting_exceptionally_consumes_all_gas() {
+    let program = vec![Operation::Push0, Operation::Invalid];
+    let mut context = SyscallContext::default();
+
+    let trace = run_program_traced(program, &mut context, 1000);
+
+    let last_step = trace.last().unwrap();
+    assert_eq!(last_step.op, 0xFE);
+    assert_eq!(last_step.gas_cost, last_step.gas);
+}
+
+#[test]
+fn trace_includes_nested_calls() {
+    let program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let mut context = call_context(vec![Operation::Push0, Operation::Stop]);
+
+    let trace = run_program_traced(program, &mut context, 1e7 as _);
+
+    // The callee's steps come right after the CALL
+    let steps: Vec<_> = trace
+        .iter()
+        .map(|step| (step.pc, step.op, step.depth))
+        .collect();
+    assert_eq!(steps.len(), 10);
+    assert_eq!(steps[7].1, 0xF1);
+    assert_eq!(steps[8..], [(0, 0x5F, 2), (1, 0x00, 2)]);
+    assert!(steps[..8].iter().all(|(_, _, depth)| *depth == 1));
+}
+
+#[test]
+fn trace_is_empty_for_programs_not_instrumented() {
+    let program = Program::from(vec![Operation::Push0, Operation::Stop]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+    let mut context = SyscallContext::default();
+    context.enable_tracing();
+
+    executor.execute(&mut context, 1000);
+
+    assert!(context.trace().is_empty());
+}
+
+#[test]
+fn trace_step_to_json() {
+    let step = TraceStep {
+        pc: 2,
+        op: 0x60,
+        gas: 97,
+        gas_cost: 3,
+        mem_size: 32,
+        stack: vec![U256::from(0x2A), U256 { lo: 1, hi: 1 }],
+        depth: 1,
+        refund: 0,
+    };
+    let expected = concat!(
+        r#"{"pc":2,"op":96,"gas":"0x61","gasCost":"0x3","memSize":32,"#,
+        r#""stack":["0x2a","0x100000000000000000000000000000001"],"depth":1,"refund":0,"#,
+        r#""opName":"PUSH1"}"#
+    );
+    assert_eq!(step.to_json(), expected);
+}
+
+#[test]
+fn trace_is_written_as_json_lines() {
+    let steps = vec![
+        TraceStep {
+            op: 0x5F,
+            ..Default::default()
+        },
+        TraceStep {
+            pc: 1,
+            op: 0x00,
+            ..Default::default()
+        },
+    ];
+    let mut output = Vec::new();
+
+    write_trace(&steps, &mut output).unwrap();
+
+    let lines: Vec<_> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    assert_eq!(lines, vec![steps[0].to_json(), steps[1].to_json()]);
+    assert!(lines[0].ends_with(r#""opName":"PUSH0"}"#));
+}
+
+/// What a [`RecordingInspector`] was notified of
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum InspectorEvent {
+    Step { pc: u64, depth: usize },
+    StepEnd { pc: u64, gas_cost: u64 },
+    Breakpoint(TraceStep),
+    Call(CallInputs),
+    CallEnd(CallOutcome),
+    Log(Log),
+}
+
+/// An [`Inspector`] recording everything it's notified of, in order
+#[derive(Debug, Default)]
+struct RecordingInspector {
+    events: Vec<InspectorEvent>,
+    /// The memory size seen through the context on each step
+    memory_sizes: Vec<usize>,
+}
+
+impl Inspector for RecordingInspector {
+    fn step(&mut self, step: &TraceStep, context: &SyscallContext) {
+        self.events.push(InspectorEvent::Step {
+            pc: step.pc,
+            depth: step.depth,
+        });
+        self.memory_sizes.push(context.memory().len());
+    }
+
+    fn step_end(&mut self, step: &TraceStep, _context: &SyscallContext) {
+        self.events.push(InspectorEvent::StepEnd {
+            pc: step.pc,
+            gas_cost: step.gas_cost,
+        });
+    }
+
+    fn breakpoint(&mut self, step: &TraceStep, _context: &SyscallContext) {
+        self.events.push(InspectorEvent::Breakpoint(step.clone()));
+    }
+
+    fn call(&mut self, inputs: &CallInputs, _context: &SyscallContext) {
+        self.events.push(InspectorEvent::Call(inputs.clone()));
+    }
+
+    fn call_end(&mut self, _inputs: &CallInputs, outcome: &CallOutcome, _context: &SyscallContext) {
+        self.events.push(InspectorEvent::CallEnd(outcome.clone()));
+    }
+
+    fn log(&mut self, log: &Log, _context: &SyscallContext) {
+        self.events.push(InspectorEvent::Log(log.clone()));
+    }
+}
+
+/// Runs the program compiled in instrumented mode with a [`RecordingInspector`],
+/// returning it afterwards
+fn run_program_inspected(
+    program: Vec<Operation>,
+    context: &mut SyscallContext,
+    initial_gas: u64,
+) -> RecordingInspector {
+    let inspector = Rc::new(RefCell::new(RecordingInspector::default()));
+    context.set_inspector(Box::new(inspector.clone()));
+    let executor = Executor::compile_instrumented(&Program::from(program), SpecId::default())
+        .expect("failed to compile program");
+    executor.execute(context, initial_gas);
+    // The context keeps the other reference
+    inspector.take()
+}
+
+#[test]
+fn inspector_is_notified_before_and_after_each_opcode() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+    ];
+    let mut context = SyscallContext::default();
+
+    let inspector = run_program_inspected(program, &mut context, 100);
+
+    let expected = vec![
+        InspectorEvent::Step { pc: 0, depth: 1 },
+        InspectorEvent::StepEnd {
+            pc: 0,
+            gas_cost: gas_cost::PUSHN as _,
+        },
+        InspectorEvent::Step { pc: 2, depth: 1 },
+        InspectorEvent::StepEnd {
+            pc: 2,
+            gas_cost: gas_cost::PUSHN as _,
+        },
+        InspectorEvent::Step { pc: 4, depth: 1 },
+        InspectorEvent::StepEnd {
+            pc: 4,
+            gas_cost: gas_cost::ADD as _,
+        },
+    ];
+    assert_eq!(inspector.events, expected);
+}
+
+#[test]
+fn inspector_reads_memory_through_context() {
+    let program = vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Stop,
+    ];
+    let mut context = SyscallContext::default();
+
+    let inspector = run_program_inspected(program, &mut context, 1000);
+
+    assert_eq!(inspector.memory_sizes, vec![0, 0, 0, 32]);
+}
+
+#[test]
+fn inspector_is_notified_of_logs_after_the_log_opcode() {
+    let program = vec![
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Log(1),
+    ];
+    let mut context = SyscallContext::default();
+
+    let inspector = run_program_inspected(program, &mut context, 1e5 as _);
+
+    let log = Log {
+        data: Vec::new(),
+        topics: vec![U256::from(7)],
+    };
+    let last_events = &inspector.events[inspector.events.len() - 2..];
+    assert!(matches!(
+        last_events[0],
+        InspectorEvent::StepEnd { pc: 4, .. }
+    ));
+    assert_eq!(last_events[1], InspectorEvent::Log(log));
+}
+
+#[test]
+fn inspector_is_notified_of_nested_calls() {
+    let program = call_operations(1000, CALLEE_ADDRESS, 0, (0, 0), (0, 0));
+    let mut context = call_context(vec![Operation::Push0, Operation::Stop]);
+
+    let inspector = run_program_inspected(program, &mut context, 1e7 as _);
+
+    let call_index = inspector
+        .events
+        .iter()
+        .position(|event| matches!(event, InspectorEvent::Call(_)))
+        .unwrap();
+    let inputs = CallInputs {
+        kind: CallKind::Call(CallType::Call),
+        caller: CALLER_ADDRESS,
+        address: CALLEE_ADDRESS,
+        value: U256::default(),
+        input: Vec::new(),
+        gas_limit: 1000,
+        depth: 2,
+    };
+    let gas_used = gas_cost::PUSH0 as u64;
+    let outcome = CallOutcome {
+        success: true,
+        gas_left: 1000 - gas_used,
+        output: Vec::new(),
+    };
+    // The callee's steps are nested between the call's events
+    let expected = vec![
+        InspectorEvent::Call(inputs),
+        InspectorEvent::Step { pc: 0, depth: 2 },
+        InspectorEvent::StepEnd {
+            pc: 0,
+            gas_cost: gas_used,
+        },
+        InspectorEvent::Step { pc: 1, depth: 2 },
+        InspectorEvent::StepEnd { pc: 1, gas_cost: 0 },
+        InspectorEvent::CallEnd(outcome),
+    ];
+    assert_eq!(inspector.events[call_index..call_index + 6], expected);
+}
+
+#[test]
+fn inspector_is_notified_of_failed_creations() {
+    let init_code = vec![Operation::Push0, Operation::Push0, Operation::Revert];
+    let program = create_operations(init_code.clone(), 0, None);
+    let mut context = create_context(0);
+    let inspector = Rc::new(RefCell::new(RecordingInspector::default()));
+    context.set_inspector(Box::new(inspector.clone()));
+
+    let result = run_program(program, &mut context, 1e7 as _);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let events = &inspector.borrow().events;
+    let InspectorEvent::Call(inputs) = &events[0] else {
+        panic!("expected a call, got {:?}", events[0]);
+    };
+    assert_eq!(inputs.kind, CallKind::Create);
+    assert_eq!(inputs.address, CREATED_ADDRESS);
+    assert_eq!(inputs.input, Program::from(init_code).to_bytecode());
+    let InspectorEvent::CallEnd(outcome) = events.last().unwrap() else {
+        panic!("expected the end of a call, got {:?}", events.last());
+    };
+    assert!(!outcome.success);
+}
+
+/// Runs the program compiled in instrumented mode with a [`Debugger`] reading the
+/// given commands, returning what it wrote
+fn run_program_debugged(
+    program: Vec<Operation>,
+    context: &mut SyscallContext,
+    commands: &'static str,
+    initial_gas: u64,
+) -> String {
+    let debugger = Rc::new(RefCell::new(Debugger::new(commands.as_bytes(), Vec::new())));
+    context.set_inspector(Box::new(debugger.clone()));
+    let executor = Executor::compile_instrumented(&Program::from(program), SpecId::default())
+        .expect("failed to compile program");
+    executor.execute(context, initial_gas);
+    let output = debugger.borrow().output().clone();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn debugger_stops_before_each_opcode() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+    ];
+    let mut context = SyscallContext::default();
+
+    let output = run_program_debugged(program, &mut context, "s\n\nstep\n", 100);
+
+    let expected = "\
+pc 0: PUSH1 (gas: 100, depth: 1)
+(evm-mlir) pc 2: PUSH1 (gas: 97, depth: 1)
+(evm-mlir) pc 4: ADD (gas: 94, depth: 1)
+(evm-mlir) ";
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn debugger_stops_prompting_after_continue() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+    ];
+    let mut context = SyscallContext::default();
+
+    let output = run_program_debugged(program, &mut context, "continue\nstep\n", 100);
+
+    assert_eq!(output, "pc 0: PUSH1 (gas: 100, depth: 1)\n(evm-mlir) ");
+}
+
+#[test]
+fn debugger_runs_to_the_end_when_out_of_commands() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+    ];
+    let mut context = SyscallContext::default();
+
+    let output = run_program_debugged(program, &mut context, "", 100);
+
+    assert_eq!(output, "pc 0: PUSH1 (gas: 100, depth: 1)\n(evm-mlir) ");
+    let gas_used = gas_cost::PUSHN * 2 + gas_cost::ADD;
+    assert_eq!(context.remaining_gas(), 100 - gas_used as u64);
+}
+
+#[test]
+fn debugger_prints_stack_from_the_top() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(0xAB_u8)),
+        Operation::Add,
+    ];
+    let mut context = SyscallContext::default();
+
+    let output = run_program_debugged(program, &mut context, "stack\ns\ns\nstack\nc\n", 100);
+
+    let expected = "\
+pc 0: PUSH1 (gas: 100, depth: 1)
+(evm-mlir) empty stack
+(evm-mlir) pc 2: PUSH1 (gas: 97, depth: 1)
+(evm-mlir) pc 4: ADD (gas: 94, depth: 1)
+(evm-mlir) 0: 0xab
+1: 0x1
+(evm-mlir) ";
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn debugger_prints_memory_in_words() {
+    let program = vec![
+        Operation::Push(BigUint::from(0xFF_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Mstore8,
+        Operation::Stop,
+    ];
+    let mut context = SyscallContext::default();
+
+    let output = run_program_debugged(program, &mut context, "mem\ns\ns\ns\nmemory\nc\n", 100);
+
+    let word = format!("00ff{}", "0".repeat(60));
+    // Expanding the memory to a word costs 3 gas, on top of MSTORE8's
+    let expected = format!(
+        "\
+pc 0: PUSH1 (gas: 100, depth: 1)
+(evm-mlir) empty memory
+(evm-mlir) pc 2: PUSH1 (gas: 97, depth: 1)
+(evm-mlir) pc 4: MSTORE8 (gas: 94, depth: 1)
+(evm-mlir) pc 5: STOP (gas: 88, depth: 1)
+(evm-mlir) 0x0000: {word}
+(evm-mlir) "
+    );
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn debugger_reads_storage_of_executing_account() {
+    let key = U256 { lo: 2, hi: 1 };
+    let storage = InMemoryStorage::from_iter([
+        (DEFAULT_ADDRESS, U256::from(1), U256::from(42)),
+        (DEFAULT_ADDRESS, key, U256::from(0xBEEF)),
+    ]);
+    let mut context = SyscallContext::with_storage(Box::new(storage));
+    let commands =
+        "storage 1\nstorage 0x100000000000000000000000000000002\nstorage 7\nstorage\nc\n";
+
+    let output = run_program_debugged(vec![Operation::Stop], &mut context, commands, 100);
+
+    let expected = "\
+pc 0: STOP (gas: 100, depth: 1)
+(evm-mlir) 0x2a
+(evm-mlir) 0xbeef
+(evm-mlir) 0x0
+(evm-mlir) usage: storage KEY
+(evm-mlir) ";
+    assert_eq!(output, expected);
+}
+
+#[test]
+fn debugger_rejects_unknown_commands() {
+    let mut context = SyscallContext::default();
+
+    let output = run_program_debugged(vec![Operation::Stop], &mut context, "jump\nc\n", 100);
+
+    let expected = "\
+pc 0: STOP (gas: 100, depth: 1)
+(evm-mlir) unknown command `jump`, see `help`
+(evm-mlir) ";
+    assert_eq!(output, expected);
+}
+
+/// Runs the program compiled with the given breakpoints, with a [`RecordingInspector`].
+/// Returns the exit code along with the inspector
+fn run_program_with_breakpoints(
+    program: Vec<Operation>,
+    context: &mut SyscallContext,
+    breakpoints: &Breakpoints,
+    initial_gas: u64,
+) -> (u8, RecordingInspector) {
+    let inspector = Rc::new(RefCell::new(RecordingInspector::default()));
+    context.set_inspector(Box::new(inspector.clone()));
+    let executor =
+        Executor::compile_with_breakpoints(&Program::from(program), SpecId::default(), breakpoints)
+            .expect("failed to compile program");
+    let result = executor.execute(context, initial_gas);
+    // The context keeps the other reference
+    (result, inspector.take())
+}
+
+#[test]
+fn breakpoints_can_be_inserted_and_removed() {
+    let mut breakpoints = Breakpoints::from_iter([4, 0]);
+
+    assert!(breakpoints.insert(2));
+    assert!(!breakpoints.insert(4));
+    assert!(breakpoints.remove(0));
+    assert!(!breakpoints.remove(0));
+
+    assert!(breakpoints.contains(2));
+    assert!(!breakpoints.contains(0));
+    assert_eq!(breakpoints.iter().collect::<Vec<_>>(), vec![2, 4]);
+}
+
+#[test]
+fn breakpoint_is_hit_before_its_opcode() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+    ];
+    let mut context = SyscallContext::default();
+    let breakpoints = Breakpoints::from_iter([4]);
+
+    let (result, inspector) =
+        run_program_with_breakpoints(program, &mut context, &breakpoints, 100);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let gas = 100 - 2 * gas_cost::PUSHN as u64;
+    let step = TraceStep {
+        pc: 4,
+        op: 0x01,
+        gas,
+        gas_cost: gas,
+        mem_size: 0,
+        stack: vec![U256::from(1), U256::from(2)],
+        depth: 1,
+        refund: 0,
+    };
+    // No other opcode is reported
+    assert_eq!(inspector.events, vec![InspectorEvent::Breakpoint(step)]);
+}
+
+#[test]
+fn breakpoint_is_hit_each_time_its_opcode_runs() {
+    // Counts down from 3, jumping back to the JUMPDEST until reaching zero
+    let program = vec![
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Jumpdest { pc: 2 },
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Dup(1),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Jumpi,
+        Operation::Stop,
+    ];
+    let mut context = SyscallContext::default();
+    let breakpoints = Breakpoints::from_iter([2, 11]);
+
+    let (result, inspector) =
+        run_program_with_breakpoints(program, &mut context, &breakpoints, 1000);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    let hits: Vec<(u64, Vec<U256>)> = inspector
+        .events
+        .into_iter()
+        .map(|event| match event {
+            InspectorEvent::Breakpoint(step) => (step.pc, step.stack),
+            event => panic!("expected a breakpoint, got {event:?}"),
+        })
+        .collect();
+    let expected = vec![
+        (2, vec![U256::from(3)]),
+        (2, vec![U256::from(2)]),
+        (2, vec![U256::from(1)]),
+        (11, vec![U256::from(0)]),
+    ];
+    assert_eq!(hits, expected);
+}
+
+#[test]
+fn breakpoints_outside_the_code_are_never_hit() {
+    let program = vec![Operation::Push(BigUint::from(1_u8)), Operation::Push0];
+    let mut context = SyscallContext::default();
+    // The first one points to the argument of the PUSH1, and the others past the end
+    let breakpoints = Breakpoints::from_iter([1, 3, 1000]);
+
+    let (result, inspector) =
+        run_program_with_breakpoints(program, &mut context, &breakpoints, 100);
+
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    assert!(inspector.events.is_empty());
+}
+
+#[test]
+fn breakpoints_without_inspector_dont_change_the_execution() {
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Add,
+    ];
+    let breakpoints = Breakpoints::from_iter([0, 2, 4]);
+    let executor = Executor::compile_with_breakpoints(
+        &Program::from(program),
+        SpecId::default(),
+        &breakpoints,
+    )
+    .expect("failed to compile program");
+    let mut context = SyscallContext::default();
+
+    let result = executor.run(&mut context, 100);
+
+    let gas_used = (2 * gas_cost::PUSHN + gas_cost::ADD) as u64;
+    assert_eq!(
+        result,
+        ExecutionResult::Success {
+            gas_used,
+            output: Vec::new(),
+            logs: Vec::new(),
+            state_diff: StateDiff::default(),
+        }
+    );
+}
+
+#[test]
+fn contract_cache_compiles_each_code_once() {
+    let code = Program::from(vec![Operation::Push0, Operation::Stop]).to_bytecode();
+    let mut cache = ContractCache::new();
+
+    let first = cache
+        .get_or_compile(&code, SpecId::default(), false)
+        .unwrap();
+    let second = cache
+        .get_or_compile(&code, SpecId::default(), false)
+        .unwrap();
+
+    assert!(Rc::ptr_eq(&first, &second));
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.misses(), 1);
+    assert_eq!(cache.hits(), 1);
+    let result = second.execute(&mut SyscallContext::default(), 100);
+    assert_eq!(result, ExitStatusCode::Stop.to_u8());
+}
+
+#[test]
+fn contract_cache_keeps_hardforks_and_instrumentation_apart() {
+    let code = Program::from(vec![Operation::Push0, Operation::Stop]).to_bytecode();
+    let other_code = Program::from(vec![Operation::Stop]).to_bytecode();
+    let mut cache = ContractCache::new();
+
+    cache.get_or_compile(&code, SpecId::Cancun, false).unwrap();
+    cache
+        .get_or_compile(&code, SpecId::Shanghai, false)
+        .unwrap();
+    cache.get_or_compile(&code, SpecId::Cancun, true).unwrap();
+    cache
+        .get_or_compile(&other_code, SpecId::Cancun, false)
+        .unwrap();
+
+    assert_eq!(cache.len(), 4);
+    assert_eq!(cache.hits(), 0);
+
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn contract_cache_keeps_code_with_undefined_opcodes() {
+    let mut cache = ContractCache::new();
+
+    let executor = cache
+        .get_or_compile(&[0x0C], SpecId::default(), false)
+        .unwrap();
+
+    assert_eq!(cache.len(), 1);
+    assert_eq!(cache.misses(), 1);
+    let result = executor.run(&mut SyscallContext::default(), 1000);
+    let expected_result = ExecutionResult::Halt {
+        reason: HaltReason::Error,
+    };
+    assert_eq!(result, expected_result);
+}
+
+#[test]
+fn transactions_share_the_contract_cache() {
+    let callee_code = vec![Operation::Push0, Operation::Push0, Operation::Return];
+    let cache = Rc::new(RefCell::new(ContractCache::new()));
+
+    for _ in 0..3 {
+        let mut context = transaction_context(call_tx(100_000, 1, 0), callee_code.clone());
+        context.set_contract_cache(cache.clone());
+        let result = context.transact().unwrap();
+        assert!(result.success);
+    }
+
+    assert_eq!(cache.borrow().len(), 1);
+    assert_eq!(cache.borrow().misses(), 1);
+    assert_eq!(cache.borrow().hits(), 2);
+}
+
+#[test]
+fn nested_calls_use_the_contract_cache() {
+    // Calls itself once, with one byte of calldata
+    let callee_code = vec![
+        // [00]
+        Operation::CallDataSize,
+        Operation::Push(BigUint::from(15_u8)),
+        Operation::Jumpi,
+        // [04]
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Address,
+        Operation::Gas,
+        Operation::Call,
+        // [13]
+        Operation::Pop,
+        Operation::Stop,
+        // [15]
+        Operation::Jumpdest { pc: 15 },
+        Operation::Stop,
+    ];
+    let cache = Rc::new(RefCell::new(ContractCache::new()));
+    let mut context = transaction_context(call_tx(100_000, 1, 0), callee_code);
+    context.set_contract_cache(cache.clone());
+
+    let result = context.transact().unwrap();
+
+    assert!(result.success);
+    assert_eq!(cache.borrow().misses(), 1);
+    assert_eq!(cache.borrow().hits(), 1);
+}
+
+#[test]
+fn recursive_calls_stop_at_the_max_call_depth() {
+    // Calls itself, and returns one more than the callee returned
+    let callee_code = vec![
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Address,
+        Operation::Gas,
+        Operation::Call,
+        Operation::Pop,
+        Operation::Push0,
+        Operation::Mload,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Add,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ];
+    let cache = Rc::new(RefCell::new(ContractCache::new()));
+    let mut context = transaction_context(call_tx(1e12 as _, 0, 0), callee_code);
+    context.set_contract_cache(cache.clone());
+
+    let result = context.transact().unwrap();
+
+    // The call made at the max depth fails, so it's the last frame to run
+    assert!(result.success);
+    let frames = MAX_CALL_DEPTH as u128 + 1;
+    assert_eq!(result.output, u256_as_word(U256::from(frames)));
+    assert_eq!(cache.borrow().misses(), 1);
+}
+
+fn stop_code() -> Vec<u8> {
+    Program::from(vec![Operation::Push0, Operation::Stop]).to_bytecode()
+}
+
+#[test]
+fn artifact_cache_compiles_each_library_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ArtifactCache::open(dir.path().join("artifacts")).unwrap();
+    let code = stop_code();
+    assert_eq!(cache.get(&code, SpecId::Cancun), None);
+
+    let library = cache.get_or_compile(&code, SpecId::Cancun).unwrap();
+
+    assert!(library.is_file());
+    assert!(library.with_extension("meta").is_file());
+    assert_eq!(cache.get(&code, SpecId::Cancun), Some(library.clone()));
+    assert_eq!(cache.get(&code, SpecId::Shanghai), None);
+    let modified = fs::metadata(&library).unwrap().modified().unwrap();
+    assert_eq!(
+        cache.get_or_compile(&code, SpecId::Cancun).unwrap(),
+        library
+    );
+    assert_eq!(
+        fs::metadata(&library).unwrap().modified().unwrap(),
+        modified
+    );
+    assert_eq!(cache.prune().unwrap(), 0);
+}
+
+#[test]
+fn artifact_cache_replaces_corrupted_libraries() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ArtifactCache::open(dir.path()).unwrap();
+    let code = stop_code();
+    let library = cache.get_or_compile(&code, SpecId::Cancun).unwrap();
+
+    fs::write(&library, b"not a library").unwrap();
+
+    assert_eq!(cache.get(&code, SpecId::Cancun), None);
+    assert_eq!(
+        cache.get_or_compile(&code, SpecId::Cancun).unwrap(),
+        library
+    );
+    assert_eq!(cache.get(&code, SpecId::Cancun), Some(library));
+}
+
+#[test]
+fn artifact_cache_prunes_stale_libraries() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ArtifactCache::open(dir.path()).unwrap();
+    let stale_code = stop_code();
+    let stale_library = cache.get_or_compile(&stale_code, SpecId::Cancun).unwrap();
+    let code = Program::from(vec![Operation::Stop]).to_bytecode();
+    let library = cache.get_or_compile(&code, SpecId::Cancun).unwrap();
+    let extension = library.extension().unwrap().to_str().unwrap();
+    let orphan_library = dir.path().join(format!("orphan.{extension}"));
+    fs::write(&orphan_library, b"").unwrap();
+
+    // Built by an older compiler
+    let stale_metadata = stale_library.with_extension("meta");
+    let metadata = fs::read_to_string(&stale_metadata).unwrap().replacen(
+        concat!("version ", env!("CARGO_PKG_VERSION")),
+        "version 0.0.0",
+        1,
+    );
+    fs::write(&stale_metadata, metadata).unwrap();
+
+    assert_eq!(cache.get(&stale_code, SpecId::Cancun), None);
+    assert_eq!(cache.prune().unwrap(), 2);
+    assert!(!stale_library.exists());
+    assert!(!stale_metadata.exists());
+    assert!(!orphan_library.exists());
+    assert!(library.exists());
+}
+
+#[test]
+fn artifact_cache_compiles_code_with_undefined_opcodes() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ArtifactCache::open(dir.path()).unwrap();
+
+    let library = cache.get_or_compile(&[0x0C], SpecId::default()).unwrap();
+
+    assert!(library.exists());
+    assert_eq!(cache.get(&[0x0C], SpecId::default()), Some(library));
+}
+
+#[test]
+fn artifact_cache_libraries_run_through_their_entrypoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = ArtifactCache::open(dir.path()).unwrap();
+    let code = calldata_plus_chain_id_program().to_bytecode();
+    let calldata = u256_as_word(U256::from(7_u128));
+
+    for (spec_id, exit_code, output_len, output) in [
+        (
+            SpecId::Shanghai,
+            ExitStatusCode::Return,
+            32,
+            u256_as_word(U256::from(12_u128)),
+        ),
+        // PUSH0 is undefined before Shanghai
+        (SpecId::London, ExitStatusCode::Error, 0, [0; 32]),
+    ] {
+        let library = cache.get_or_compile(&code, spec_id).unwrap();
+        let env = Env {
+            spec_id,
+            chain_id: 5,
+            ..Default::default()
+        };
+        let entrypoint = load_shared_entrypoint(&library);
+
+        let result = run_shared_entrypoint(entrypoint, &env, &calldata, 1000, 32);
+
+        assert_eq!(result.0, exit_code.to_u8());
+        assert_eq!(result.2, output_len);
+        assert_eq!(result.3, output);
+    }
+
+    // Built for another interface with the syscalls
+    let library = cache.get(&code, SpecId::Shanghai).unwrap();
+    let metadata_path = library.with_extension("meta");
+    let metadata = fs::read_to_string(&metadata_path).unwrap().replacen(
+        &format!("abi {SHARED_LIBRARY_ABI_VERSION}"),
+        "abi 0",
+        1,
+    );
+    fs::write(&metadata_path, metadata).unwrap();
+    assert_eq!(cache.get(&code, SpecId::Shanghai), None);
+}
+
+#[test]
+fn pushes_wider_than_a_word_are_rejected() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(1_u8) << 256),
+        Operation::Stop,
+    ]);
+    let context = Context::new();
+
+    let result = context.compile_jit(&program, SpecId::default());
+
+    assert!(matches!(
+        result,
+        Err(CodegenError::InvalidProgram(
+            ProgramError::ImmediateTooLarge {
+                index: 0,
+                bits: 257
+            }
+        ))
+    ));
+}
+
+/// A program returning `value` as a 32-byte word
+fn return_value_program(value: u8) -> Program {
+    Program::from(vec![
+        Operation::Push(BigUint::from(value)),
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ])
+}
+
+#[test]
+fn compile_many_keeps_the_order_of_the_programs() {
+    let programs: Vec<Program> = (1..=16).map(return_value_program).collect();
+
+    let executors = Executor::compile_many(&programs, SpecId::default());
+
+    assert_eq!(executors.len(), programs.len());
+    for (value, executor) in (1..=16).zip(executors) {
+        let mut context = SyscallContext::default();
+        let result = executor.unwrap().run(&mut context, 1000);
+        assert!(result.is_success());
+        assert_eq!(result.output()[31], value);
+    }
+}
+
+#[test]
+fn compile_many_without_programs() {
+    assert!(Executor::compile_many(&[], SpecId::default()).is_empty());
+}
+
+#[test]
+fn contract_cache_warms_up_in_parallel() {
+    let codes = vec![
+        return_value_program(1).to_bytecode(),
+        return_value_program(2).to_bytecode(),
+        return_value_program(1).to_bytecode(),
+        // Halts when executed
+        vec![0x0C],
+    ];
+    let mut cache = ContractCache::new();
+
+    let errors = cache.warm(&codes, SpecId::default());
+
+    // Any code compiles, even the bytes which aren't opcodes
+    assert!(errors.is_empty());
+    assert_eq!(cache.len(), 3);
+    assert_eq!(cache.hits() + cache.misses(), 0);
+    let executor = cache
+        .get_or_compile(&codes[1], SpecId::default(), false)
+        .unwrap();
+    assert_eq!(cache.hits(), 1);
+    let result = executor.run(&mut SyscallContext::default(), 1000);
+    assert_eq!(result.output()[31], 2);
+    let executor = cache
+        .get_or_compile(&codes[3], SpecId::default(), false)
+        .unwrap();
+    assert_eq!(cache.hits(), 2);
+    assert!(!executor
+        .run(&mut SyscallContext::default(), 1000)
+        .is_success());
+}
+
+#[test]
+fn compile_options_dump_every_stage_to_a_callback() {
+    let dumps = Arc::new(Mutex::new(Vec::new()));
+    let sink = dumps.clone();
+    let options = CompileOptions {
+        dump: Some(IrDump::to_callback(move |stage, ir| {
+            sink.lock().unwrap().push((stage.clone(), ir.to_string()))
+        })),
+        ..Default::default()
+    };
+    let program = return_value_program(7);
+
+    let executor = Executor::compile_with_options(&program, SpecId::default(), &options).unwrap();
+
+    let dumps = dumps.lock().unwrap();
+    let stages: Vec<&IrStage> = dumps.iter().map(|(stage, _)| stage).collect();
+    assert_eq!(stages.len(), 12);
+    assert_eq!(stages[0], &IrStage::BeforeLowering);
+    for (index, stage) in stages[1..11].iter().enumerate() {
+        assert!(matches!(stage, IrStage::AfterPass { index: i, .. } if *i == index + 1));
+    }
+    assert_eq!(
+        stages[1],
+        &IrStage::AfterPass {
+            index: 1,
+            name: "canonicalize".to_string()
+        }
+    );
+    assert_eq!(stages[11], &IrStage::Lowered);
+    assert!(dumps[0].1.contains("func.func"));
+    assert!(dumps[11].1.contains("llvm.func"));
+    assert!(!dumps[11].1.contains("func.func"));
+
+    // Dumping doesn't change the compiled code
+    let result = executor.run(&mut SyscallContext::default(), 1000);
+    assert_eq!(result.output(), U256::from(7).to_be_bytes());
+}
+
+#[test]
+fn compile_options_dump_chosen_stages_to_a_directory() {
+    let dir = tempfile::tempdir().unwrap();
+    let options = CompileOptions {
+        dump: Some(IrDump {
+            after_each_pass: false,
+            ..IrDump::to_directory(dir.path().join("ir"))
+        }),
+        ..Default::default()
+    };
+
+    Executor::compile_with_options(&return_value_program(7), SpecId::default(), &options).unwrap();
+
+    let mut files: Vec<String> = fs::read_dir(dir.path().join("ir"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(files, ["00-before-lowering.mlir", "99-lowered.mlir"]);
+    let lowered = fs::read_to_string(dir.path().join("ir/99-lowered.mlir")).unwrap();
+    assert!(lowered.contains("llvm.func"));
+}
+
+#[test]
+fn ir_stages_are_named_in_order() {
+    let stages = [
+        IrStage::BeforeLowering,
+        IrStage::AfterPass {
+            index: 1,
+            name: "canonicalize".to_string(),
+        },
+        IrStage::AfterPass {
+            index: 10,
+            name: "reconcile-unrealized-casts".to_string(),
+        },
+        IrStage::Lowered,
+    ];
+    let names: Vec<String> = stages.iter().map(IrStage::file_name).collect();
+
+    assert_eq!(
+        names,
+        [
+            "00-before-lowering.mlir",
+            "01-after-canonicalize.mlir",
+            "10-after-reconcile-unrealized-casts.mlir",
+            "99-lowered.mlir"
+        ]
+    );
+    let mut sorted = names.clone();
+    sorted.sort();
+    assert_eq!(sorted, names);
+}
+
+#[rstest]
+#[case(OptLevel::O0)]
+#[case(OptLevel::O1)]
+#[case(OptLevel::O2)]
+#[case(OptLevel::O3)]
+fn compile_options_opt_level_keeps_the_result(#[case] opt_level: OptLevel) {
+    let options = CompileOptions {
+        opt_level,
+        ..Default::default()
+    };
+    let program = return_value_program(7);
+
+    let executor = Executor::compile_with_options(&program, SpecId::default(), &options).unwrap();
+    let result = executor.run(&mut SyscallContext::default(), 1000);
+    assert_eq!(result.output(), U256::from(7).to_be_bytes());
+
+    let module = Context::new()
+        .compile_with_options(&program, SpecId::default(), &options)
+        .unwrap();
+    assert_eq!(module.options().opt_level, opt_level);
+    assert!(evm_mlir::emit_llvm_ir(&module).unwrap().contains("@main"));
+}
+
+#[test]
+fn opt_levels_are_numbered_and_pick_the_llvm_pipeline() {
+    let levels = [OptLevel::O0, OptLevel::O1, OptLevel::O2, OptLevel::O3];
+    let numbers: Vec<usize> = levels.iter().map(OptLevel::level).collect();
+    assert_eq!(numbers, [0, 1, 2, 3]);
+    assert_eq!(OptLevel::default(), OptLevel::O0);
+
+    let passes = Passes::default();
+    assert!(passes.canonicalize);
+    assert_eq!(passes.llvm_pipeline(OptLevel::O3), "default<O3>");
+    let passes = Passes {
+        llvm: Some("instcombine".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(passes.llvm_pipeline(OptLevel::O3), "instcombine");
+}
+
+#[test]
+fn compile_options_can_skip_canonicalizing() {
+    let dumps = Arc::new(Mutex::new(Vec::new()));
+    let sink = dumps.clone();
+    let options = CompileOptions {
+        passes: Passes {
+            canonicalize: false,
+            ..Default::default()
+        },
+        dump: Some(IrDump::to_callback(move |stage, _| {
+            sink.lock().unwrap().push(stage.clone())
+        })),
+        ..Default::default()
+    };
+
+    let executor =
+        Executor::compile_with_options(&return_value_program(7), SpecId::default(), &options)
+            .unwrap();
+
+    let stages = dumps.lock().unwrap();
+    assert_eq!(stages.len(), 11);
+    assert_eq!(
+        stages[1],
+        IrStage::AfterPass {
+            index: 1,
+            name: "convert-scf-to-cf".to_string()
+        }
+    );
+    let result = executor.run(&mut SyscallContext::default(), 1000);
+    assert_eq!(result.output(), U256::from(7).to_be_bytes());
+}
+
+#[test]
+fn compile_options_llvm_passes_are_run() {
+    let options = CompileOptions {
+        passes: Passes {
+            llvm: Some("instcombine,simplifycfg".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let module = Context::new()
+        .compile_with_options(&return_value_program(7), SpecId::default(), &options)
+        .unwrap();
+    assert!(evm_mlir::emit_object(&module).is_ok());
+
+    let options = CompileOptions {
+        passes: Passes {
+            llvm: Some("not-a-pass".to_string()),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let module = Context::new()
+        .compile_with_options(&return_value_program(7), SpecId::default(), &options)
+        .unwrap();
+    assert!(evm_mlir::emit_object(&module).is_err());
+}
+
+#[test]
+fn compile_options_target_another_triple() {
+    let options = CompileOptions {
+        target: Target {
+            triple: Some("aarch64-unknown-linux-gnu".to_string()),
+            ..Target::host()
+        },
+        ..Default::default()
+    };
+    let program = return_value_program(7);
+    let module = Context::new()
+        .compile_with_options(&program, SpecId::default(), &options)
+        .unwrap();
+
+    let ir = evm_mlir::emit_llvm_ir(&module).unwrap();
+    assert!(ir.contains("aarch64-unknown-linux-gnu"));
+    let object = evm_mlir::emit_object(&module).unwrap();
+    assert_eq!(&object[..4], b"\x7FELF");
+    // e_machine, right after the identification and the type, is EM_AARCH64
+    assert_eq!(&object[18..20], 183_u16.to_le_bytes());
+
+    // The JIT can only run code for the host
+    let result = Executor::compile_with_options(&program, SpecId::default(), &options);
+    assert!(matches!(result, Err(CodegenError::NotImplemented(_))));
+}
+
+#[test]
+fn compile_options_debug_info_locates_operations_in_the_listing() {
+    let dir = tempfile::tempdir().unwrap();
+    let listing = dir.path().join("program.evm");
+    let options = CompileOptions {
+        debug_info: Some(DebugInfo::new(&listing)),
+        ..Default::default()
+    };
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Jumpdest { pc: 3 },
+        Operation::Add,
+        Operation::Stop,
+    ]);
+
+    let module = Context::new()
+        .compile_with_options(&program, SpecId::default(), &options)
+        .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&listing).unwrap(),
+        "0000    PUSH1 0x01\n0002    PUSH0\n0003 => JUMPDEST\n0004    ADD\n0005    STOP\n"
+    );
+    let ir = evm_mlir::emit_llvm_ir(&module).unwrap();
+    assert!(ir.contains("!DISubprogram(name: \"main\""));
+    assert!(ir.contains("program.evm"));
+    for line in 1..=5 {
+        assert!(ir.contains(&format!("!DILocation(line: {line},")));
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let object = evm_mlir::emit_object(&module).unwrap();
+        let debug_line = b".debug_line";
+        assert!(object
+            .windows(debug_line.len())
+            .any(|name| name == debug_line));
+    }
+
+    // Debug info doesn't change the compiled code
+    let executor = Executor::compile_with_options(&program, SpecId::default(), &options).unwrap();
+    let result = executor.run(&mut SyscallContext::default(), 1000);
+    assert!(result.is_success());
+}
+
+#[test]
+fn emit_llvm_ir_assembly_and_object() {
+    let context = Context::new();
+    let module = context
+        .compile_jit(&return_value_program(7), SpecId::default())
+        .unwrap();
+
+    let ir = evm_mlir::emit_llvm_ir(&module).unwrap();
+    assert!(ir.contains("define"));
+    assert!(ir.contains("@main"));
+
+    let assembly = evm_mlir::emit_assembly(&module).unwrap();
+    assert!(assembly.contains("main"));
+
+    let object = evm_mlir::emit_object(&module).unwrap();
+    assert!(!object.is_empty());
+    #[cfg(target_os = "linux")]
+    assert_eq!(&object[..4], b"\x7FELF");
+    #[cfg(target_os = "macos")]
+    assert_eq!(&object[..4], [0xCF, 0xFA, 0xED, 0xFE]);
+}
+
+#[test]
+fn compile_to_object_writes_the_ir_and_the_assembly() {
+    let dir = tempfile::tempdir().unwrap();
+    let context = Context::new();
+    let module = context
+        .compile(&return_value_program(7), dir.path().join("program"))
+        .unwrap();
+
+    let object_file = evm_mlir::compile_to_object(&module, dir.path().join("program")).unwrap();
+
+    assert_eq!(object_file, dir.path().join("program.o"));
+    assert_eq!(
+        fs::read(&object_file).unwrap(),
+        evm_mlir::emit_object(&module).unwrap()
+    );
+    let ir = fs::read_to_string(dir.path().join("program.ll")).unwrap();
+    assert_eq!(ir, evm_mlir::emit_llvm_ir(&module).unwrap());
+    assert!(dir.path().join("program.asm").exists());
+}
+
+#[test]
+fn execution_results_convert_into_errors() {
+    let executor = Executor::compile(&return_value_program(7), SpecId::default()).unwrap();
+    let output = executor
+        .run(&mut SyscallContext::default(), 1000)
+        .into_output()
+        .unwrap();
+    assert_eq!(output, U256::from(7).to_be_bytes());
+
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Revert,
+    ]);
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+    let error = executor
+        .run(&mut SyscallContext::default(), 1000)
+        .into_output()
+        .unwrap_err();
+    assert!(matches!(error, Error::Revert { output, .. } if output == [0]));
+
+    let error = executor
+        .run(&mut SyscallContext::default(), 1)
+        .into_output()
+        .unwrap_err();
+    assert!(matches!(error, Error::Halt(HaltReason::OutOfGas)));
+}
+
+#[test]
+fn errors_of_each_step_convert_by_kind() {
+    let error = Error::from(CompileError::Parse(ParseError::UnknownOpcode {
+        opcode: 0x0c,
+        pc: 0,
+    }));
+    assert!(matches!(error, Error::InvalidProgram(_)));
+
+    let error = Error::from(CodegenError::VerificationFailed("module"));
+    assert!(matches!(error, Error::Verification("module")));
+
+    let error = Error::from(CompileError::Codegen(CodegenError::NotImplemented(
+        "JIT compilation".to_string(),
+    )));
+    assert!(matches!(
+        error,
+        Error::Codegen(CodegenError::NotImplemented(_))
+    ));
+
+    let error = Error::from(Program::from_assembly("PUSH1").unwrap_err());
+    assert!(matches!(error, Error::InvalidAssembly(_)));
+}
+
+#[test]
+fn linker_failures_are_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let missing_object = dir.path().join("missing.o");
+
+    let error = evm_mlir::link_shared_lib(&[missing_object], dir.path().join("lib")).unwrap_err();
+
+    match Error::from(error) {
+        Error::Link { status, stderr } => {
+            assert!(!status.success());
+            assert!(stderr.contains("missing.o"));
+        }
+        error => panic!("expected a linker failure, got {error}"),
+    }
+}
+
+extern "C" {
+    fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+}
+
+const RTLD_NOW: c_int = 2;
+
+/// Loads the shared library at `path`, binding all its symbols, and returns its
+/// entrypoint. The library stays loaded until the tests finish
+fn load_shared_entrypoint(path: &Path) -> SharedEntrypoint {
+    let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    let symbol = CString::new(SHARED_ENTRYPOINT).unwrap();
+    unsafe {
+        let handle = dlopen(path.as_ptr(), RTLD_NOW);
+        assert!(!handle.is_null(), "failed to load the library");
+        let entrypoint = dlsym(handle, symbol.as_ptr());
+        assert!(!entrypoint.is_null(), "the library has no entrypoint");
+        std::mem::transmute(entrypoint)
+    }
+}
+
+/// Runs the entrypoint of a shared library with an output buffer of `output_capacity`
+/// bytes. Returns the exit code, the gas left, the size of the output and the buffer
+fn run_shared_entrypoint(
+    entrypoint: SharedEntrypoint,
+    env: &Env,
+    calldata: &[u8],
+    gas: u64,
+    output_capacity: usize,
+) -> (u8, u64, u64, Vec<u8>) {
+    let mut gas = gas;
+    let mut output = vec![0; output_capacity];
+    let mut output_len = 0;
+    let exit_code = unsafe {
+        entrypoint(
+            env,
+            calldata.as_ptr(),
+            calldata.len() as u64,
+            &mut gas,
+            output.as_mut_ptr(),
+            output.len() as u64,
+            &mut output_len,
+        )
+    };
+    (exit_code, gas, output_len, output)
+}
+
+/// Returns the word at the start of the calldata plus the chain id
+fn calldata_plus_chain_id_program() -> Program {
+    Program::from(vec![
+        Operation::Push0,
+        Operation::CallDataLoad,
+        Operation::Chainid,
+        Operation::Add,
+        Operation::Push0,
+        Operation::Mstore,
+        Operation::Push(BigUint::from(32_u8)),
+        Operation::Push0,
+        Operation::Return,
+    ])
+}
+
+#[test]
+fn shared_libraries_run_through_their_entrypoint() {
+    let dir = tempfile::tempdir().unwrap();
+    let program = calldata_plus_chain_id_program();
+    let library = evm_mlir::compile_shared_library(&program, dir.path().join("program")).unwrap();
+    let env = Env {
+        chain_id: 5,
+        ..Default::default()
+    };
+    let calldata = u256_as_word(U256::from(7_u128));
+    let entrypoint = load_shared_entrypoint(&library);
+
+    let (exit_code, gas_left, output_len, output) =
+        run_shared_entrypoint(entrypoint, &env, &calldata, 1000, 32);
+
+    let mut context = SyscallContext::new(
+        env.clone(),
+        calldata.to_vec(),
+        Box::<InMemoryStorage>::default(),
+    );
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+    let ExecutionResult::Success { gas_used, .. } = executor.run(&mut context, 1000) else {
+        panic!("the program failed");
+    };
+    assert_eq!(exit_code, ExitStatusCode::Return.to_u8());
+    assert_eq!(gas_left, 1000 - gas_used);
+    assert_eq!(output_len, 32);
+    assert_eq!(output, u256_as_word(U256::from(12_u128)));
+
+    // The output is truncated to the buffer, and the program halts without enough gas
+    let (exit_code, _, output_len, output) =
+        run_shared_entrypoint(entrypoint, &env, &calldata, 1000, 16);
+    assert_eq!(exit_code, ExitStatusCode::Return.to_u8());
+    assert_eq!(output_len, 32);
+    assert_eq!(output, u256_as_word(U256::from(12_u128))[..16]);
+    let (exit_code, ..) = run_shared_entrypoint(entrypoint, &env, &calldata, gas_used - 1, 32);
+    assert_eq!(exit_code, ExitStatusCode::OutOfGas.to_u8());
+}
+
+#[rstest]
+#[case::countdown(
+    "PUSH1 3 loop: PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI PUSH0 MSTORE PUSH1 32 PUSH0 RETURN"
+)]
+#[case::underflow("PUSH1 1 ADD")]
+#[case::underflow_after_jump("PUSH @next JUMP next: ADD")]
+#[case::underflow_after_dynamic_jump("PUSH1 5 CALLDATASIZE ADD JUMP next: POP POP")]
+#[case::overflow("grow: PUSH0 PUSH @grow JUMP")]
+fn elided_stack_checks_keep_the_results(#[case] assembly: &str) {
+    let program = Program::from_assembly(assembly).unwrap();
+    let checked = CompileOptions {
+        passes: Passes {
+            elide_stack_checks: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let run = |options: &CompileOptions| {
+        let executor =
+            Executor::compile_with_options(&program, SpecId::default(), options).unwrap();
+        executor.run(&mut SyscallContext::default(), 1e7 as _)
+    };
+    assert_eq!(run(&CompileOptions::default()), run(&checked));
+}
+
+#[rstest]
+#[case::countdown(
+    "PUSH1 3 loop: PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI PUSH0 MSTORE PUSH1 32 PUSH0 RETURN"
+)]
+#[case::gas_left("PUSH1 1 PUSH1 2 ADD POP GAS PUSH0 MSTORE PUSH1 32 PUSH0 RETURN")]
+#[case::memory_expansion("PUSH1 1 PUSH2 0x1000 MSTORE GAS PUSH0 MSTORE PUSH1 32 PUSH0 RETURN")]
+#[case::sstore_sentry("PUSH1 1 PUSH0 PUSH0 PUSH0 SSTORE SSTORE STOP")]
+#[case::dynamic_jump(
+    "PUSH @end CALLDATASIZE ADD JUMP INVALID end: GAS PUSH0 MSTORE PUSH1 32 PUSH0 RETURN"
+)]
+fn batched_gas_keeps_the_results(#[case] assembly: &str) {
+    let program = Program::from_assembly(assembly).unwrap();
+    let unbatched = CompileOptions {
+        passes: Passes {
+            batch_gas: false,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let run = |options: &CompileOptions, gas: u64| {
+        let executor =
+            Executor::compile_with_options(&program, SpecId::default(), options).unwrap();
+        executor.run(&mut SyscallContext::default(), gas)
+    };
+    // Running out of gas midway halts the same way too
+    for gas in [1e7 as u64, 25_000, 2_310, 10] {
+        assert_eq!(run(&CompileOptions::default(), gas), run(&unbatched, gas));
+    }
+}
+
+#[rstest]
+#[case::add_carrying(
+    "PUSH32 0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff PUSH1 1 ADD"
+)]
+#[case::add_across_limbs("PUSH8 0xffffffffffffffff PUSH8 0xffffffffffffffff ADD")]
+#[case::sub_borrowing("PUSH1 1 PUSH0 SUB")]
+#[case::sub_across_limbs("PUSH9 0x010000000000000000 PUSH1 1 SWAP1 SUB")]
+#[case::bitwise("PUSH0 NOT PUSH9 0x0100ff00000000ff00 DUP2 DUP2 AND DUP3 DUP3 OR XOR XOR NOT")]
+#[case::lt_high_limb("PUSH9 0x010000000000000000 PUSH8 0xffffffffffffffff LT")]
+#[case::gt_high_limb("PUSH9 0x010000000000000000 PUSH8 0xffffffffffffffff GT")]
+#[case::lt_low_limb("PUSH9 0x010000000000000001 PUSH9 0x010000000000000000 LT")]
+#[case::eq_low_limb("PUSH9 0x010000000000000001 PUSH9 0x010000000000000000 EQ")]
+#[case::eq("PUSH0 NOT PUSH0 NOT EQ")]
+fn word_representations_give_the_same_results(#[case] assembly: &str) {
+    let assembly = format!("{assembly} PUSH0 MSTORE PUSH1 32 PUSH0 RETURN");
+    let program = Program::from_assembly(&assembly).unwrap();
+    let limbs = CompileOptions {
+        word_repr: WordRepr::Limbs64,
+        ..Default::default()
+    };
+
+    let run = |options: &CompileOptions| {
+        let executor =
+            Executor::compile_with_options(&program, SpecId::default(), options).unwrap();
+        executor.run(&mut SyscallContext::default(), 1e6 as _)
+    };
+    let result = run(&limbs);
+    assert!(result.is_success());
+    assert_eq!(result, run(&CompileOptions::default()));
+}
+
+#[rstest]
+fn stack_limit_halts_pushes_past_it(
+    #[values(OverflowCheck::Branch, OverflowCheck::GuardPage)] overflow_check: OverflowCheck,
+) {
+    let options = CompileOptions {
+        stack: StackConfig {
+            limit: 16,
+            overflow_check,
+        },
+        ..Default::default()
+    };
+    let run = |pushes: usize| {
+        let program = Program::from(vec![Operation::Push0; pushes]);
+        let executor =
+            Executor::compile_with_options(&program, SpecId::default(), &options).unwrap();
+        executor.run(&mut SyscallContext::default(), 1e6 as _)
+    };
+
+    assert!(run(16).is_success());
+    let halt = ExecutionResult::Halt {
+        reason: HaltReason::Error,
+    };
+    assert_eq!(run(17), halt);
+}
+
+#[rstest]
+#[case::loop_("grow: PUSH0 PUSH @grow JUMP")]
+#[case::dup_loop("PUSH1 1 grow: DUP1 PUSH @grow JUMP")]
+#[case::dynamic_jump("PUSH @grow grow: DUP1 DUP1 JUMP")]
+#[case::underflow("PUSH0 ADD")]
+#[case::within_the_limit(
+    "PUSH1 3 loop: PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI PUSH0 MSTORE PUSH1 32 PUSH0 RETURN"
+)]
+fn guard_page_keeps_the_results(#[case] assembly: &str) {
+    let program = Program::from_assembly(assembly).unwrap();
+    let guarded = CompileOptions {
+        stack: StackConfig {
+            overflow_check: OverflowCheck::GuardPage,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let executor = Executor::compile_with_options(&program, SpecId::default(), &guarded).unwrap();
+    let expected = Executor::compile(&program, SpecId::default())
+        .unwrap()
+        .run(&mut SyscallContext::default(), 1e7 as _);
+    // The fault is caught again on each run
+    for _ in 0..3 {
+        let result = executor.run(&mut SyscallContext::default(), 1e7 as _);
+        assert_eq!(result, expected);
+    }
+}
+
+#[test]
+fn memory_is_reused_cleared_across_runs() {
+    // Adds 1 to the word at the end of the memory expanded by the program
+    let program = Program::from_assembly(
+        "PUSH2 0x1000 MLOAD PUSH1 1 ADD DUP1 PUSH2 0x1000 MSTORE \
+         PUSH0 MSTORE PUSH1 32 PUSH0 RETURN",
+    )
+    .unwrap();
+    let executor = Executor::compile(&program, SpecId::default()).unwrap();
+    let run = || {
+        let mut context = SyscallContext::default();
+        let result = executor.run(&mut context, 1e6 as _);
+        assert_eq!(context.memory().len(), 0x1020);
+        result.into_output().unwrap()
+    };
+
+    let mut expected = [0; 32];
+    expected[31] = 1;
+    // Each run gets the memory of the last one, zeroed again
+    for _ in 0..3 {
+        assert_eq!(run(), expected);
+    }
+}
+
+#[rstest]
+#[case::stack_words("CALLER CALLDATASIZE PUSH1 7 DUP3 ADD SWAP2 DUP2 MUL SWAP1 POP SLT")]
+#[case::swap_below("CALLDATASIZE CALLER PUSH1 1 POP SWAP1 SUB ISZERO")]
+#[case::held_words("PUSH1 2 PUSH1 3 DUP2 DUP2 SWAP3 LT SWAP2 SGT EQ NOT PUSH0 XOR")]
+#[case::underflow("CALLDATASIZE PUSH1 1 DUP3 ADD")]
+#[case::loop_("PUSH1 3 loop: PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI")]
+fn promoted_stack_keeps_the_results(#[case] assembly: &str) {
+    let assembly = format!("{assembly} PUSH0 MSTORE PUSH1 32 PUSH0 RETURN");
+    let program = Program::from_assembly(&assembly).unwrap();
+    let options = |promote_stack: bool, batch_gas: bool| CompileOptions {
+        passes: Passes {
+            promote_stack,
+            batch_gas,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let run = |options: &CompileOptions, gas: u64| {
+        let executor =
+            Executor::compile_with_options(&program, SpecId::default(), options).unwrap();
+        executor.run(&mut SyscallContext::default(), gas)
+    };
+    // Running out of gas midway halts the same way too, charging the gas one operation
+    // at a time or not
+    for batch_gas in [true, false] {
+        for gas in [1e7 as u64, 30, 10] {
+            assert_eq!(
+                run(&options(true, batch_gas), gas),
+                run(&options(false, batch_gas), gas)
+            );
+        }
+    }
+}
+
+#[rstest]
+#[case::folds("PUSH1 2 PUSH1 3 ADD PUSH1 4 MUL ISZERO PUSH0 MSTORE PUSH1 32 PUSH0 RETURN")]
+#[case::wrapping_sub("PUSH1 3 PUSH1 2 SUB NOT PUSH0 MSTORE PUSH1 32 PUSH0 RETURN")]
+#[case::popped_push("PUSH1 1 POP CALLER PUSH0 MSTORE PUSH1 32 PUSH0 RETURN")]
+#[case::double_iszero("CALLDATASIZE ISZERO ISZERO PUSH @end JUMPI INVALID end: STOP")]
+#[case::folded_jump_target("PUSH @end PUSH0 ADD JUMP INVALID end: GAS STOP")]
+#[case::underflow("ISZERO ISZERO ISZERO")]
+fn peephole_optimizer_keeps_the_results(#[case] assembly: &str) {
+    let program = Program::from_assembly(assembly).unwrap();
+    let optimized = CompileOptions {
+        passes: Passes {
+            peephole: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    let run = |options: &CompileOptions, gas: u64| {
+        let executor =
+            Executor::compile_with_options(&program, SpecId::default(), options).unwrap();
+        executor.run(&mut SyscallContext::default(), gas)
+    };
+    // Running out of gas midway halts the same way too
+    for gas in [1e7 as u64, 10] {
+        assert_eq!(run(&optimized, gas), run(&CompileOptions::default(), gas));
+    }
+}
+
+#[rstest]
+#[case::pushed_jump("PUSH @end JUMP INVALID end: STOP", false)]
+#[case::pushed_jumpi("PUSH1 1 PUSH @end JUMPI INVALID end: STOP", false)]
+#[case::computed_jump("PUSH @end CALLDATASIZE ADD JUMP INVALID end: STOP", true)]
+fn only_computed_jumps_go_through_the_jumptable(
+    #[case] assembly: &str,
+    #[case] uses_jumptable: bool,
+) {
+    let program = Program::from_assembly(assembly).unwrap();
+    let ir = Arc::new(Mutex::new(String::new()));
+    let sink = ir.clone();
+    let options = CompileOptions {
+        dump: Some(IrDump {
+            after_each_pass: false,
+            lowered: false,
+            ..IrDump::to_callback(move |_, module| *sink.lock().unwrap() = module.to_string())
+        }),
+        ..Default::default()
+    };
+
+    let executor = Executor::compile_with_options(&program, SpecId::default(), &options).unwrap();
+
+    // Branches to the jumptable pass it the offset to jump to
+    assert_eq!(ir.lock().unwrap().contains(" : i256)"), uses_jumptable);
+    let result = executor.run(&mut SyscallContext::default(), 1e6 as _);
+    assert!(matches!(result, ExecutionResult::Success { .. }));
+}
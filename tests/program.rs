@@ -0,0 +1,1922 @@
+use evm_mlir::{
+    analysis::{StackAnalysis, StackCheck},
+    builder::ProgramBuilder,
+    bytecode,
+    cfg::{BasicBlock, ControlFlowGraph, Exit, JumpTarget},
+    eof::{is_eof, CodeSection, Container, SectionType, NON_RETURNING},
+    errors::{AssemblyError, EofError, ProgramError},
+    optimizer::optimize,
+    program::{decode_hex, disassemble, hex_len, Operation, Program, StackEffect},
+    spec::SpecId,
+};
+use num_bigint::BigUint;
+
+#[test]
+fn from_bytecode_push_and_add() {
+    // [00] PUSH1 5
+    // [02] PUSH1 7
+    // [04] ADD
+    let bytecode = [0x60, 0x05, 0x60, 0x07, 0x01];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Add,
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_bytecode_all_push_widths() {
+    for n in 1..=32_u8 {
+        let mut bytecode = vec![0x5F + n];
+        bytecode.extend((1..=n).collect::<Vec<u8>>());
+        let program = Program::from_bytecode(&bytecode).unwrap();
+
+        let value = BigUint::from_bytes_be(&bytecode[1..]);
+        assert_eq!(program, Program::from(vec![Operation::Push(value)]));
+    }
+}
+
+#[test]
+fn from_bytecode_truncated_push_is_zero_padded() {
+    // PUSH2 with only one immediate byte
+    let bytecode = [0x61, 0x01];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![Operation::Push(BigUint::from(0x0100_u16))]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_bytecode_assigns_pcs() {
+    // [00] PUSH1 4
+    // [02] JUMP
+    // [03] PC
+    // [04] JUMPDEST
+    let bytecode = [0x60, 0x04, 0x56, 0x58, 0x5B];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(4_u8)),
+        Operation::Jump,
+        Operation::PC { pc: 3 },
+        Operation::Jumpdest { pc: 4 },
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn checked_pushes() {
+    let word_max = (BigUint::from(1_u8) << 256) - 1_u8;
+
+    assert_eq!(
+        Operation::push(word_max.clone()),
+        Ok(Operation::Push(word_max.clone()))
+    );
+    assert_eq!(
+        Operation::push(BigUint::from(1_u8) << 256),
+        Err(ProgramError::InvalidPushSize { size: 33 })
+    );
+    assert_eq!(
+        Operation::push_n(&[0x01, 0x02]),
+        Ok(Operation::Push(BigUint::from(0x0102_u16)))
+    );
+    assert_eq!(
+        Operation::push_n(&[]),
+        Err(ProgramError::InvalidPushSize { size: 0 })
+    );
+    assert_eq!(
+        Operation::push_n(&[0xFF; 33]),
+        Err(ProgramError::InvalidPushSize { size: 33 })
+    );
+    assert_eq!(Operation::push32([0xFF; 32]), Operation::Push(word_max));
+    assert_eq!(
+        Operation::push_n(&[0x00, 0x02]),
+        Ok(Operation::PushN(2, BigUint::from(2_u8)))
+    );
+}
+
+#[test]
+fn validate_rejects_pushes_wider_than_a_word() {
+    let valid = Program::from(vec![
+        Operation::Push0,
+        Operation::push32([0xFF; 32]),
+        Operation::Add,
+    ]);
+    let invalid = Program::from(vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(1_u8) << 300),
+        Operation::Add,
+    ]);
+
+    assert_eq!(valid.validate(), Ok(()));
+    assert_eq!(
+        invalid.validate(),
+        Err(ProgramError::ImmediateTooLarge {
+            index: 1,
+            bits: 301
+        })
+    );
+}
+
+#[test]
+fn from_bytecode_keeps_the_width_of_pushes() {
+    // [00] PUSH2 0x0006
+    // [03] PUSH32 0x00..00
+    // [24] JUMP
+    // [25] JUMPDEST
+    let mut bytecode = vec![0x61, 0x00, 0x06, 0x7F];
+    bytecode.extend([0x00; 32]);
+    bytecode.extend([0x56, 0x5B]);
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::PushN(2, BigUint::from(6_u8)),
+        Operation::PushN(32, BigUint::ZERO),
+        Operation::Jump,
+        Operation::Jumpdest { pc: 0x25 },
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+    assert_eq!(program.disassemble(), disassemble(&bytecode));
+    assert_eq!(program.operations()[0].to_string(), "PUSH2 0x0006");
+}
+
+#[test]
+fn validate_rejects_pushes_wider_than_their_width() {
+    let program = Program::from(vec![Operation::PushN(1, BigUint::from(0x0100_u16))]);
+
+    assert_eq!(
+        program.validate(),
+        Err(ProgramError::ImmediateDoesNotFit {
+            index: 0,
+            size: 1,
+            bits: 9
+        })
+    );
+    let program = Program::from(vec![Operation::PushN(33, BigUint::ZERO)]);
+    assert!(program.validate().is_err());
+}
+
+#[test]
+fn validate_rejects_rjumpv_without_offsets() {
+    let program = Program::from(vec![Operation::Push0, Operation::Rjumpv(vec![])]);
+    assert_eq!(
+        program.validate(),
+        Err(ProgramError::InvalidJumpTableSize { index: 1, size: 0 })
+    );
+
+    let program = Program::from(vec![Operation::Push0, Operation::Rjumpv(vec![0; 257])]);
+    assert!(program.validate().is_err());
+    let program = Program::from(vec![Operation::Push0, Operation::Rjumpv(vec![0; 256])]);
+    assert_eq!(program.validate(), Ok(()));
+}
+
+#[test]
+fn with_computed_pcs_replaces_the_offsets() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(0x0102_u16)),
+        Operation::Jumpdest { pc: 0 },
+        Operation::PushN(3, BigUint::from(1_u8)),
+        Operation::PC { pc: 42 },
+        Operation::Jumpdest { pc: 0 },
+    ])
+    .with_computed_pcs();
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(0x0102_u16)),
+        Operation::Jumpdest { pc: 3 },
+        Operation::PushN(3, BigUint::from(1_u8)),
+        Operation::PC { pc: 8 },
+        Operation::Jumpdest { pc: 9 },
+    ]);
+    assert_eq!(program, expected);
+
+    let pcs: Vec<_> = program.iter_with_pcs().map(|(pc, _)| pc).collect();
+    assert_eq!(pcs, vec![0, 3, 4, 8, 9]);
+    assert!(program
+        .iter_with_pcs()
+        .zip(program.operations())
+        .all(|((_, operation), expected)| operation == expected));
+}
+
+#[test]
+fn iter_with_pcs_keeps_the_recorded_offsets() {
+    // A JUMPDEST recorded further than its encoding moves the operations after it
+    let program = Program::from(vec![
+        Operation::Push0,
+        Operation::Jumpdest { pc: 5 },
+        Operation::Add,
+    ]);
+
+    let pcs: Vec<_> = program.iter_with_pcs().map(|(pc, _)| pc).collect();
+    assert_eq!(pcs, vec![0, 5, 6]);
+}
+
+#[test]
+fn resolve_labels_pushes_their_offsets() {
+    let program = Program::from(vec![
+        Operation::PushLabel("end".to_string()),
+        Operation::Jump,
+        Operation::Label("start".to_string()),
+        Operation::PC { pc: 0 },
+        Operation::Label("end".to_string()),
+        Operation::PushLabel("start".to_string()),
+    ]);
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Jump,
+        Operation::Jumpdest { pc: 3 },
+        Operation::PC { pc: 4 },
+        Operation::Jumpdest { pc: 5 },
+        Operation::Push(BigUint::from(3_u8)),
+    ]);
+    assert_eq!(program.to_bytecode(), expected.to_bytecode());
+    assert_eq!(program.validate(), Ok(()));
+    assert_eq!(program.resolve_labels(), Ok(expected));
+}
+
+#[test]
+fn resolve_labels_far_away_take_wider_pushes() {
+    // 300 JUMPDESTs put the label past the reach of a PUSH1
+    let mut operations = vec![Operation::PushLabel("end".to_string()), Operation::Jump];
+    operations.extend(vec![Operation::Jumpdest { pc: 0 }; 300]);
+    operations.push(Operation::Label("end".to_string()));
+
+    let program = Program::from(operations).resolve_labels().unwrap();
+
+    // PUSH2 takes 3 bytes and JUMP 1, before the 300 JUMPDESTs
+    let operations = program.operations();
+    assert_eq!(operations[0], Operation::Push(BigUint::from(304_u16)));
+    assert_eq!(operations[302], Operation::Jumpdest { pc: 304 });
+}
+
+#[test]
+fn resolve_labels_errors() {
+    let duplicate = Program::from(vec![
+        Operation::Label("a".to_string()),
+        Operation::Label("a".to_string()),
+    ]);
+    let undefined = Program::from(vec![
+        Operation::Label("a".to_string()),
+        Operation::PushLabel("b".to_string()),
+    ]);
+
+    let label = "a".to_string();
+    assert_eq!(
+        duplicate.validate(),
+        Err(ProgramError::DuplicateLabel { label })
+    );
+    let label = "b".to_string();
+    assert_eq!(
+        undefined.resolve_labels(),
+        Err(ProgramError::UndefinedLabel { label })
+    );
+}
+
+#[test]
+fn program_builder_computes_offsets_and_labels() {
+    let program = ProgramBuilder::new()
+        .push(3_u8)
+        .label("loop")
+        .push(1_u8)
+        .swap(1)
+        .sub()
+        .dup(1)
+        .jumpi_to("loop")
+        .pc()
+        .jumpdest()
+        .build()
+        .unwrap();
+
+    let expected =
+        Program::from_assembly("PUSH1 3 loop: PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI PC JUMPDEST")
+            .unwrap();
+    assert_eq!(program, expected);
+    assert_eq!(program.operations()[8], Operation::PC { pc: 11 });
+}
+
+#[test]
+fn program_builder_rejects_undefined_labels() {
+    let result = ProgramBuilder::new().jump_to("nowhere").build();
+
+    let label = "nowhere".to_string();
+    assert_eq!(result, Err(ProgramError::UndefinedLabel { label }));
+}
+
+#[test]
+fn bytecode_macro_decodes_hex() {
+    let expected = Program::from_bytecode(&[0x60, 0x05, 0x60, 0x07, 0x01]).unwrap();
+
+    assert_eq!(bytecode!("6005600701"), expected);
+    assert_eq!(bytecode!("0x6005600701"), expected);
+    assert_eq!(bytecode!(" 0x60 05\n 60 07\t01 "), expected);
+    assert_eq!(bytecode!("0X6005600701"), expected);
+    assert_eq!(bytecode!(""), Program::from(vec![]));
+}
+
+#[test]
+fn hex_is_decoded_into_constants() {
+    const HEX: &str = "0xdeadBEEF";
+    const BYTES: [u8; hex_len(HEX)] = decode_hex(HEX);
+
+    assert_eq!(BYTES, [0xDE, 0xAD, 0xBE, 0xEF]);
+}
+
+#[test]
+fn from_bytecode_undefined_opcode() {
+    let bytecode = [0x5F, 0x0C];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![Operation::Push0, Operation::Undefined(0x0C)]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+    assert_eq!(Operation::Undefined(0x0C).to_string(), "UNKNOWN 0x0c");
+}
+
+/// The metadata solc appends to contracts, with a made-up hash full of JUMPDESTs
+fn solidity_metadata() -> Vec<u8> {
+    let mut metadata = vec![0xA2, 0x64];
+    metadata.extend(b"ipfs");
+    metadata.extend([0x58, 0x22, 0x12, 0x20]);
+    metadata.extend([0x5B; 32]);
+    metadata.push(0x64);
+    metadata.extend(b"solc");
+    metadata.extend([0x43, 0x00, 0x08, 0x14]);
+    // The length of the metadata
+    metadata.extend([0x00, 0x33]);
+    metadata
+}
+
+#[test]
+fn from_bytecode_skips_solidity_metadata() {
+    // [00] PUSH0
+    // [01] PUSH0
+    // [02] RETURN
+    // [03] INVALID
+    let mut bytecode = vec![0x5F, 0x5F, 0xF3, 0xFE];
+    bytecode.extend(solidity_metadata());
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Return,
+        Operation::Invalid,
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_bytecode_decodes_metadata_run_into() {
+    // [00] PUSH0
+    // [01] LOG2
+    // [02] PUSH5 "ipfs" 0x58
+    // [08] 0x22
+    let mut bytecode = vec![0x5F];
+    bytecode.extend(solidity_metadata());
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    assert_eq!(program.operations()[3], Operation::Undefined(0x22));
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_memory_opcodes() {
+    // [00] MLOAD
+    // [01] MSTORE
+    // [02] MSTORE8
+    // [03] MSIZE
+    // [04] MCOPY
+    let bytecode = [0x51, 0x52, 0x53, 0x59, 0x5E];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Mload,
+        Operation::Mstore,
+        Operation::Mstore8,
+        Operation::Msize,
+        Operation::Mcopy,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_halting_opcodes() {
+    // [00] STOP
+    // [01] RETURN
+    // [02] REVERT
+    // [03] INVALID
+    let bytecode = [0x00, 0xF3, 0xFD, 0xFE];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Stop,
+        Operation::Return,
+        Operation::Revert,
+        Operation::Invalid,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_storage_opcodes() {
+    // [00] SLOAD
+    // [01] SSTORE
+    // [02] TLOAD
+    // [03] TSTORE
+    let bytecode = [0x54, 0x55, 0x5C, 0x5D];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Sload,
+        Operation::Sstore,
+        Operation::Tload,
+        Operation::Tstore,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_log_opcodes() {
+    // [00] LOG0
+    // [01] LOG1
+    // [02] LOG2
+    // [03] LOG3
+    // [04] LOG4
+    let bytecode = [0xA0, 0xA1, 0xA2, 0xA3, 0xA4];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Log(0),
+        Operation::Log(1),
+        Operation::Log(2),
+        Operation::Log(3),
+        Operation::Log(4),
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_caller_context_opcodes() {
+    // [00] ADDRESS
+    // [01] ORIGIN
+    // [02] CALLER
+    // [03] CALLVALUE
+    // [04] GASPRICE
+    let bytecode = [0x30, 0x32, 0x33, 0x34, 0x3A];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Address,
+        Operation::Origin,
+        Operation::Caller,
+        Operation::Callvalue,
+        Operation::Gasprice,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_balance_opcodes() {
+    // [00] BALANCE
+    // [01] SELFBALANCE
+    let bytecode = [0x31, 0x47];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![Operation::Balance, Operation::Selfbalance]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_code_opcodes() {
+    // [00] CODESIZE
+    // [01] CODECOPY
+    let bytecode = [0x38, 0x39];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![Operation::Codesize, Operation::Codecopy]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+    assert_eq!(Operation::Codecopy.to_string(), "CODECOPY");
+}
+
+#[test]
+fn from_bytecode_external_data_opcodes() {
+    // [00] EXTCODESIZE
+    // [01] EXTCODECOPY
+    // [02] RETURNDATASIZE
+    // [03] RETURNDATACOPY
+    // [04] EXTCODEHASH
+    let bytecode = [0x3B, 0x3C, 0x3D, 0x3E, 0x3F];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Extcodesize,
+        Operation::Extcodecopy,
+        Operation::Returndatasize,
+        Operation::Returndatacopy,
+        Operation::Extcodehash,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_call() {
+    // [00] PUSH0
+    // [01] PUSH0
+    // [02] PUSH0
+    // [03] PUSH0
+    // [04] PUSH0
+    // [05] PUSH1 0x10
+    // [07] GAS
+    // [08] CALL
+    let bytecode = [0x5F, 0x5F, 0x5F, 0x5F, 0x5F, 0x60, 0x10, 0x5A, 0xF1];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let mut expected = vec![Operation::Push0; 5];
+    expected.extend([
+        Operation::Push(BigUint::from(0x10_u8)),
+        Operation::Gas,
+        Operation::Call,
+    ]);
+    assert_eq!(program, Program::from(expected));
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_call_variants() {
+    // [00] CALL
+    // [01] CALLCODE
+    // [02] DELEGATECALL
+    // [03] STATICCALL
+    let bytecode = [0xF1, 0xF2, 0xF4, 0xFA];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Call,
+        Operation::Callcode,
+        Operation::Delegatecall,
+        Operation::Staticcall,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_create() {
+    // [00] PUSH0
+    // [01] PUSH0
+    // [02] PUSH0
+    // [03] CREATE
+    // [04] PUSH1 0x01
+    // [06] PUSH0
+    // [07] PUSH0
+    // [08] PUSH0
+    // [09] CREATE2
+    let bytecode = [0x5F, 0x5F, 0x5F, 0xF0, 0x60, 0x01, 0x5F, 0x5F, 0x5F, 0xF5];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Create,
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Create2,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_selfdestruct() {
+    // [00] PUSH0
+    // [01] SELFDESTRUCT
+    let bytecode = [0x5F, 0xFF];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![Operation::Push0, Operation::Selfdestruct]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn from_bytecode_block_env_opcodes() {
+    // [00] BLOCKHASH
+    // [01] COINBASE
+    // [02] TIMESTAMP
+    // [03] NUMBER
+    // [04] PREVRANDAO
+    // [05] GASLIMIT
+    // [06] CHAINID
+    // [07] BASEFEE
+    // [08] BLOBHASH
+    // [09] BLOBBASEFEE
+    let bytecode = [0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x48, 0x49, 0x4A];
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Blockhash,
+        Operation::Coinbase,
+        Operation::Timestamp,
+        Operation::Number,
+        Operation::Prevrandao,
+        Operation::Gaslimit,
+        Operation::Chainid,
+        Operation::Basefee,
+        Operation::Blobhash,
+        Operation::Blobbasefee,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), bytecode);
+}
+
+#[test]
+fn to_bytecode_push_and_add() {
+    let program = Program::from(vec![
+        Operation::Push0,
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Push(BigUint::from(0x0102_u16)),
+        Operation::Add,
+        Operation::Add,
+    ]);
+
+    let expected = vec![0x5F, 0x60, 0x05, 0x61, 0x01, 0x02, 0x01, 0x01];
+    assert_eq!(program.to_bytecode(), expected);
+}
+
+#[test]
+fn to_bytecode_dup_and_swap() {
+    let program = Program::from(vec![
+        Operation::Dup(1),
+        Operation::Dup(16),
+        Operation::Swap(1),
+        Operation::Swap(16),
+    ]);
+
+    assert_eq!(program.to_bytecode(), vec![0x80, 0x8F, 0x90, 0x9F]);
+}
+
+#[test]
+fn bytecode_round_trip() {
+    // [00] PUSH1 1
+    // [02] PUSH1 8
+    // [04] JUMPI
+    // [05] PUSH0
+    // [06] PUSH1 0
+    // [08] JUMPDEST
+    // [09] PC
+    // [0A] PUSH32 0xFF..FF
+    // [2B] DUP2
+    // [2C] SWAP1
+    // [2D] MSTORE
+    // [2E] STOP
+    let mut bytecode = vec![
+        0x60, 0x01, 0x60, 0x08, 0x57, 0x5F, 0x60, 0x00, 0x5B, 0x58, 0x7F,
+    ];
+    bytecode.extend([0xFF; 32]);
+    bytecode.extend([0x81, 0x90, 0x52, 0x00]);
+
+    let program = Program::from_bytecode(&bytecode).unwrap();
+    assert_eq!(program.to_bytecode(), bytecode);
+
+    let reparsed = Program::from_bytecode(&program.to_bytecode()).unwrap();
+    assert_eq!(reparsed, program);
+}
+
+#[test]
+fn stack_effects() {
+    let cases = [
+        (Operation::Stop, 0, 0),
+        (Operation::Push0, 0, 1),
+        (Operation::Pop, 1, 0),
+        (Operation::Add, 2, 1),
+        (Operation::Addmod, 3, 1),
+        (Operation::Jumpi, 2, 0),
+        (Operation::Mcopy, 3, 0),
+        (Operation::Codecopy, 3, 0),
+        (Operation::Dup(1), 1, 2),
+        (Operation::Dup(16), 16, 17),
+        (Operation::Swap(1), 2, 2),
+        (Operation::Swap(16), 17, 17),
+        (Operation::Log(0), 2, 0),
+        (Operation::Log(4), 6, 0),
+        (Operation::Extcodecopy, 4, 0),
+        (Operation::Create2, 4, 1),
+        (Operation::Staticcall, 6, 1),
+        (Operation::Call, 7, 1),
+    ];
+    for (operation, inputs, outputs) in cases {
+        let expected = StackEffect { inputs, outputs };
+        assert_eq!(operation.stack_effect(), expected, "{operation:?}");
+    }
+}
+
+#[test]
+fn stack_effect_difference() {
+    assert_eq!(Operation::Push0.stack_effect().difference(), 1);
+    assert_eq!(Operation::Swap(3).stack_effect().difference(), 0);
+    assert_eq!(Operation::Call.stack_effect().difference(), -6);
+}
+
+#[test]
+fn static_gas() {
+    let spec_id = SpecId::default();
+    assert_eq!(Operation::Push0.static_gas(spec_id), 2);
+    assert_eq!(Operation::Push(BigUint::from(1_u8)).static_gas(spec_id), 3);
+    assert_eq!(Operation::Jumpdest { pc: 0 }.static_gas(spec_id), 1);
+    assert_eq!(Operation::Log(2).static_gas(spec_id), 1125);
+    assert_eq!(Operation::Create2.static_gas(spec_id), 32000);
+    // The cost of these depends on their operands alone
+    assert_eq!(Operation::Return.static_gas(spec_id), 0);
+    assert_eq!(Operation::Sstore.static_gas(spec_id), 0);
+}
+
+#[test]
+fn static_gas_of_each_hardfork() {
+    assert_eq!(Operation::Sload.static_gas(SpecId::Frontier), 50);
+    assert_eq!(Operation::Sload.static_gas(SpecId::TangerineWhistle), 200);
+    assert_eq!(Operation::Sload.static_gas(SpecId::Istanbul), 800);
+    assert_eq!(Operation::Sload.static_gas(SpecId::Berlin), 100);
+    assert_eq!(Operation::Extcodecopy.static_gas(SpecId::Frontier), 20);
+    assert_eq!(
+        Operation::Extcodecopy.static_gas(SpecId::TangerineWhistle),
+        700
+    );
+    assert_eq!(
+        Operation::Extcodehash.static_gas(SpecId::Constantinople),
+        400
+    );
+    assert_eq!(Operation::Extcodehash.static_gas(SpecId::Istanbul), 700);
+    assert_eq!(Operation::Balance.static_gas(SpecId::Frontier), 20);
+    assert_eq!(Operation::Balance.static_gas(SpecId::TangerineWhistle), 400);
+    assert_eq!(Operation::Balance.static_gas(SpecId::Istanbul), 700);
+    assert_eq!(Operation::Balance.static_gas(SpecId::Berlin), 100);
+    assert_eq!(Operation::Delegatecall.static_gas(SpecId::Homestead), 40);
+    assert_eq!(Operation::Staticcall.static_gas(SpecId::Byzantium), 700);
+    assert_eq!(Operation::Call.static_gas(SpecId::Berlin), 100);
+    assert_eq!(Operation::Selfdestruct.static_gas(SpecId::Homestead), 0);
+    assert_eq!(
+        Operation::Selfdestruct.static_gas(SpecId::TangerineWhistle),
+        5000
+    );
+}
+
+#[test]
+fn operations_are_kept_in_order() {
+    let operations = vec![Operation::Push0, Operation::IsZero, Operation::Stop];
+    let program = Program::from(operations.clone());
+
+    assert_eq!(program.operations(), operations);
+}
+
+#[test]
+fn from_assembly_push_and_add() {
+    let program = Program::from_assembly("PUSH1 0x05\nPUSH1 7\nADD").unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(5_u8)),
+        Operation::Push(BigUint::from(7_u8)),
+        Operation::Add,
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_assembly_ignores_case_comments_and_blank_lines() {
+    let source = "
+        // Nothing but a push
+        push1 0x2A   // the answer
+
+        Push0 pop
+    ";
+    let program = Program::from_assembly(source).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::Push0,
+        Operation::Pop,
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_assembly_resolves_labels() {
+    let source = "
+            PUSH1 3
+        loop:
+            PUSH1 1
+            SWAP1
+            SUB
+            DUP1
+            PUSH @loop
+            JUMPI
+            PUSH @end
+            JUMP
+        end:
+            STOP
+    ";
+    let program = Program::from_assembly(source).unwrap();
+
+    let expected = Program::from(vec![
+        // [00]
+        Operation::Push(BigUint::from(3_u8)),
+        // [02]
+        Operation::Jumpdest { pc: 2 },
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Dup(1),
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Jumpi,
+        // [11]
+        Operation::Push(BigUint::from(14_u8)),
+        Operation::Jump,
+        // [14]
+        Operation::Jumpdest { pc: 14 },
+        Operation::Stop,
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_assembly_sets_pcs() {
+    let program = Program::from_assembly("PUSH2 0x0102 PC JUMPDEST").unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(0x0102_u16)),
+        Operation::PC { pc: 3 },
+        Operation::Jumpdest { pc: 4 },
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_assembly_labels_far_away_take_wider_pushes() {
+    // 300 bytes of JUMPDESTs put the label past the reach of a PUSH1
+    let source = format!("PUSH @end JUMP\n{}\nend: STOP", "JUMPDEST ".repeat(300));
+    let program = Program::from_assembly(&source).unwrap();
+
+    // PUSH2 takes 3 bytes and JUMP 1, before the 300 JUMPDESTs
+    let operations = program.operations();
+    assert_eq!(operations[0], Operation::Push(BigUint::from(304_u16)));
+    assert_eq!(operations[302], Operation::Jumpdest { pc: 304 });
+    assert_eq!(program.to_bytecode()[304], 0x5B);
+
+    let error = Program::from_assembly(&source.replace("PUSH @end", "PUSH1 @end")).unwrap_err();
+    assert_eq!(
+        error,
+        AssemblyError::ImmediateTooLarge {
+            immediate: "@end".to_string(),
+            size: 1,
+            line: 1
+        }
+    );
+}
+
+#[test]
+fn from_assembly_keeps_the_width_of_pushes() {
+    let program = Program::from_assembly("PUSH2 0x04 PUSH3 @end JUMP end: STOP").unwrap();
+
+    let expected = Program::from(vec![
+        Operation::PushN(2, BigUint::from(4_u8)),
+        Operation::PushN(3, BigUint::from(8_u8)),
+        Operation::Jump,
+        Operation::Jumpdest { pc: 8 },
+        Operation::Stop,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(
+        program.to_bytecode(),
+        [0x61, 0x00, 0x04, 0x62, 0x00, 0x00, 0x08, 0x56, 0x5B, 0x00]
+    );
+}
+
+#[test]
+fn from_assembly_accepts_old_mnemonics() {
+    let program = Program::from_assembly("SHA3 DIFFICULTY KECCAK256 PREVRANDAO").unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Keccak256,
+        Operation::Prevrandao,
+        Operation::Keccak256,
+        Operation::Prevrandao,
+    ]);
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn from_assembly_round_trips_through_bytecode() {
+    let source = "PUSH32 0xFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF
+        DUP16 SWAP3 LOG4 CALL CREATE2 INVALID";
+    let program = Program::from_assembly(source).unwrap();
+
+    assert_eq!(Program::from_bytecode(&program.to_bytecode()), Ok(program));
+}
+
+#[test]
+fn from_assembly_errors() {
+    let cases = [
+        (
+            "PUSH1 1\nFOO",
+            AssemblyError::UnknownMnemonic {
+                mnemonic: "FOO".to_string(),
+                line: 2,
+            },
+        ),
+        (
+            "PUSH33 1",
+            AssemblyError::UnknownMnemonic {
+                mnemonic: "PUSH33".to_string(),
+                line: 1,
+            },
+        ),
+        (
+            "PUSH2",
+            AssemblyError::MissingImmediate {
+                mnemonic: "PUSH2".to_string(),
+                line: 1,
+            },
+        ),
+        (
+            "PUSH1 0xZZ",
+            AssemblyError::InvalidImmediate {
+                immediate: "0xZZ".to_string(),
+                line: 1,
+            },
+        ),
+        (
+            "PUSH1 256",
+            AssemblyError::ImmediateTooLarge {
+                immediate: "256".to_string(),
+                size: 1,
+                line: 1,
+            },
+        ),
+        (
+            "a:\na:",
+            AssemblyError::DuplicateLabel {
+                label: "a".to_string(),
+                line: 2,
+            },
+        ),
+        (
+            "PUSH @nowhere JUMP",
+            AssemblyError::UndefinedLabel {
+                label: "nowhere".to_string(),
+                line: 1,
+            },
+        ),
+    ];
+    for (source, expected) in cases {
+        assert_eq!(Program::from_assembly(source), Err(expected), "{source}");
+    }
+}
+
+#[test]
+fn disassemble_program() {
+    let program = Program::from_assembly(
+        "
+            PUSH1 0x2A
+            PUSH @end
+            JUMP
+        end:
+            PUSH32 0x0102030405060708090A0B0C0D0E0F101112131415161718191A1B1C1D1E1F20
+            DUP2
+            STOP
+        ",
+    )
+    .unwrap();
+
+    let expected = "\
+0000    PUSH1 0x2a
+0002    PUSH1 0x05
+0004    JUMP
+0005 => JUMPDEST
+0006    PUSH32 0x0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20
+0027    DUP2
+0028    STOP
+";
+    assert_eq!(program.disassemble(), expected);
+}
+
+#[test]
+fn disassemble_empty_program() {
+    assert_eq!(Program::from(vec![]).disassemble(), "");
+}
+
+#[test]
+fn disassemble_keeps_the_width_of_pushes() {
+    // [00] PUSH2 0x0004
+    // [03] JUMP
+    // [04] JUMPDEST
+    let bytecode = [0x61, 0x00, 0x04, 0x56, 0x5B];
+
+    let expected = "\
+0000    PUSH2 0x0004
+0003    JUMP
+0004 => JUMPDEST
+";
+    assert_eq!(disassemble(&bytecode), expected);
+}
+
+#[test]
+fn disassemble_unknown_opcodes_and_truncated_pushes() {
+    let bytecode = [0x0C, 0xFE, 0x62, 0x01, 0x02];
+    let expected = "\
+0000    UNKNOWN 0x0c
+0001    INVALID
+0002    PUSH3 0x0102 (truncated)
+";
+    assert_eq!(disassemble(&bytecode), expected);
+
+    assert_eq!(disassemble(&[0x7F]), "0000    PUSH32 (truncated)\n");
+}
+
+#[test]
+fn control_flow_graph_splits_blocks() {
+    let program = Program::from_assembly(
+        "
+            PUSH1 3
+        loop:
+            PUSH1 1
+            SWAP1
+            SUB
+            DUP1
+            PUSH @loop
+            JUMPI
+            STOP
+            PUSH0
+        end:
+            CALLDATASIZE
+        ",
+    )
+    .unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+
+    let expected = [
+        BasicBlock {
+            operations: 0..1,
+            exit: Exit::Fallthrough(1),
+        },
+        BasicBlock {
+            operations: 1..8,
+            exit: Exit::Branch {
+                target: JumpTarget::Block(1),
+                fallthrough: Some(2),
+            },
+        },
+        BasicBlock {
+            operations: 8..9,
+            exit: Exit::Halt,
+        },
+        // Unreachable, but kept
+        BasicBlock {
+            operations: 9..10,
+            exit: Exit::Fallthrough(4),
+        },
+        BasicBlock {
+            operations: 10..12,
+            exit: Exit::Halt,
+        },
+    ];
+    assert_eq!(cfg.blocks(), expected);
+    let pcs: Vec<usize> = (0..expected.len())
+        .map(|block| cfg.block_pc(block))
+        .collect();
+    assert_eq!(pcs, [0, 2, 11, 12, 13]);
+}
+
+#[test]
+fn control_flow_graph_jump_targets() {
+    let program = Program::from_assembly(
+        "
+            PUSH @end JUMP
+            PUSH1 0x42 JUMP
+            CALLDATASIZE JUMP
+            PUSH0 JUMPI
+        end:
+            STOP
+        ",
+    )
+    .unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+
+    let exits: Vec<Exit> = cfg
+        .blocks()
+        .iter()
+        .map(|block| block.exit.clone())
+        .collect();
+    let expected = [
+        Exit::Jump(JumpTarget::Block(4)),
+        Exit::Jump(JumpTarget::Invalid),
+        Exit::Jump(JumpTarget::Dynamic),
+        // There's no JUMPDEST at 0
+        Exit::Branch {
+            target: JumpTarget::Invalid,
+            fallthrough: Some(4),
+        },
+        Exit::Halt,
+    ];
+    assert_eq!(exits, expected);
+}
+
+#[test]
+fn control_flow_graph_keeps_recorded_offsets() {
+    // [00] PUSH2 0x0004
+    // [03] JUMP
+    // [04] JUMPDEST
+    let program = Program::from_bytecode(&[0x61, 0x00, 0x04, 0x56, 0x5B]).unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+
+    assert_eq!(cfg.blocks()[0].exit, Exit::Jump(JumpTarget::Block(1)));
+    assert_eq!(cfg.block_pc(1), 4);
+}
+
+#[test]
+fn control_flow_graph_splits_at_relative_jump_targets() {
+    // [00] PUSH0
+    // [01] RJUMPI 1
+    // [04] PUSH0
+    // [05] STOP
+    let program = Program::from(vec![
+        Operation::Push0,
+        Operation::Rjumpi(1),
+        Operation::Push0,
+        Operation::Stop,
+    ]);
+    let cfg = ControlFlowGraph::new(&program);
+
+    let ranges: Vec<_> = cfg
+        .blocks()
+        .iter()
+        .map(|block| block.operations.clone())
+        .collect();
+    assert_eq!(ranges, vec![0..2, 2..3, 3..4]);
+    assert_eq!(
+        cfg.blocks()[0].exit,
+        Exit::Branch {
+            target: JumpTarget::Block(2),
+            fallthrough: Some(1)
+        }
+    );
+    assert_eq!(cfg.blocks()[1].exit, Exit::Fallthrough(2));
+    assert_eq!(cfg.block_pc(2), 5);
+
+    // [00] PUSH0
+    // [01] RJUMPV 0, -100
+    // [07] STOP
+    let program = Program::from(vec![
+        Operation::Push0,
+        Operation::Rjumpv(vec![0, -100]),
+        Operation::Stop,
+    ]);
+    let cfg = ControlFlowGraph::new(&program);
+
+    assert_eq!(
+        cfg.blocks()[0].exit,
+        Exit::Switch {
+            targets: vec![JumpTarget::Block(1), JumpTarget::Invalid],
+            fallthrough: Some(1)
+        }
+    );
+    assert_eq!(cfg.reachable_blocks(), vec![true, true]);
+}
+
+#[test]
+fn control_flow_graph_finds_dead_code() {
+    let program = Program::from_assembly("PUSH @end JUMP STOP dead: ADD end: STOP").unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+
+    // The block of `dead` is only reachable by jumping to it, which nothing does
+    assert_eq!(cfg.reachable_blocks(), vec![true, false, false, true]);
+
+    // Through the jumptable, any JUMPDEST is reachable
+    let program = Program::from_assembly("CALLDATASIZE JUMP STOP dead: STOP").unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+
+    assert_eq!(cfg.reachable_blocks(), vec![true, false, true]);
+}
+
+#[test]
+fn control_flow_graph_of_empty_program() {
+    let program = Program::from(vec![]);
+    let cfg = ControlFlowGraph::new(&program);
+
+    assert!(cfg.blocks().is_empty());
+    let mut dot = Vec::new();
+    cfg.write_dot(&mut dot).unwrap();
+    assert_eq!(
+        String::from_utf8(dot).unwrap(),
+        "digraph cfg {\n    node [shape=box, fontname=monospace];\n}\n"
+    );
+}
+
+#[test]
+fn control_flow_graph_to_dot() {
+    let program = Program::from_assembly(
+        "
+            CALLDATASIZE
+            PUSH @end
+            JUMPI
+            CALLDATASIZE
+            JUMP
+        end:
+            PUSH1 0x2A
+            PUSH1 0x07
+            JUMP
+        ",
+    )
+    .unwrap();
+    let mut dot = Vec::new();
+    ControlFlowGraph::new(&program).write_dot(&mut dot).unwrap();
+
+    let expected = r#"digraph cfg {
+    node [shape=box, fontname=monospace];
+    block_0 [label="0000: CALLDATASIZE\l0001: PUSH1 0x06\l0003: JUMPI\l"];
+    block_1 [label="0004: CALLDATASIZE\l0005: JUMP\l"];
+    block_2 [label="0006: JUMPDEST\l0007: PUSH1 0x2a\l0009: PUSH1 0x07\l000b: JUMP\l"];
+    block_0 -> block_2 [label="jump"];
+    block_0 -> block_1 [label="fallthrough"];
+    block_1 -> jumptable [label="jump", style=dashed];
+    block_2 -> invalid_jump [label="jump"];
+    jumptable [shape=diamond];
+    jumptable -> block_2 [style=dashed];
+    invalid_jump [shape=octagon, label="invalid jump"];
+}
+"#;
+    assert_eq!(String::from_utf8(dot).unwrap(), expected);
+}
+
+#[test]
+fn stack_analysis_of_straight_line_code() {
+    let program = Program::from_assembly("PUSH1 1 PUSH1 2 ADD POP STOP").unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+    let analysis = StackAnalysis::new(&cfg);
+
+    let [block] = analysis.blocks() else {
+        panic!("expected a single block");
+    };
+    assert_eq!(block.required, 0);
+    assert_eq!(block.growth, 2);
+    assert_eq!(block.difference, 0);
+    assert_eq!(block.entry, Some(0..=0));
+    assert_eq!(block.check, StackCheck::None);
+}
+
+#[test]
+fn stack_analysis_follows_pushed_jumps() {
+    let program = Program::from_assembly(
+        "
+            PUSH1 1
+            PUSH @next
+            JUMP
+        next:
+            PUSH1 2
+            ADD
+            STOP
+            ADD
+        ",
+    )
+    .unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+    let analysis = StackAnalysis::new(&cfg);
+
+    let blocks = analysis.blocks();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!((blocks[0].growth, blocks[0].difference), (2, 1));
+    assert_eq!(blocks[0].check, StackCheck::None);
+    // The item left by the first block is enough for the ADD
+    assert_eq!(blocks[1].required, 1);
+    assert_eq!(blocks[1].entry, Some(1..=1));
+    assert_eq!(blocks[1].check, StackCheck::None);
+    // Unreachable blocks are still checked
+    assert_eq!(blocks[2].required, 2);
+    assert_eq!(blocks[2].entry, None);
+    assert_eq!(blocks[2].check, StackCheck::Entry);
+}
+
+#[test]
+fn stack_analysis_of_dynamic_jumps() {
+    let program = Program::from_assembly(
+        "
+            CALLDATASIZE
+            JUMP
+        target:
+            POP
+            STOP
+        ",
+    )
+    .unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+    let analysis = StackAnalysis::new(&cfg);
+
+    let blocks = analysis.blocks();
+    assert_eq!(blocks[0].check, StackCheck::None);
+    // The JUMPDEST may be reached through the jumptable with any height
+    assert_eq!(blocks[1].entry, Some(0..=1024));
+    assert_eq!(blocks[1].required, 1);
+    assert_eq!(blocks[1].check, StackCheck::Entry);
+}
+
+#[test]
+fn stack_analysis_of_loops() {
+    let program = Program::from_assembly(
+        "
+            PUSH1 3
+        countdown:
+            PUSH1 1
+            SWAP1
+            SUB
+            DUP1
+            PUSH @countdown
+            JUMPI
+        grow:
+            PUSH0
+            PUSH @grow
+            JUMP
+        ",
+    )
+    .unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+    let analysis = StackAnalysis::new(&cfg);
+
+    let blocks = analysis.blocks();
+    assert_eq!(blocks.len(), 3);
+    // The countdown leaves the stack as it found it, so its height settles
+    assert_eq!((blocks[1].required, blocks[1].difference), (1, 0));
+    assert_eq!(blocks[1].entry, Some(1..=1));
+    assert_eq!(blocks[1].check, StackCheck::None);
+    // Growing the stack on each iteration widens it up to the limit
+    assert_eq!(blocks[2].entry, Some(1..=1024));
+    assert_eq!(blocks[2].check, StackCheck::Entry);
+}
+
+#[test]
+fn stack_analysis_of_blocks_filling_the_stack() {
+    let program = Program::from(vec![Operation::Push0; 1024]);
+    let cfg = ControlFlowGraph::new(&program);
+    let analysis = StackAnalysis::new(&cfg);
+
+    assert_eq!(analysis.blocks()[0].growth, 1024);
+    assert_eq!(analysis.blocks()[0].check, StackCheck::EachOperation);
+}
+
+#[test]
+fn stack_analysis_with_a_stack_limit() {
+    let program = Program::from_assembly(
+        "
+            PUSH0
+            PUSH0
+            PUSH @grow
+            JUMP
+        grow:
+            PUSH0
+            PUSH @grow
+            JUMP
+        ",
+    )
+    .unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+    let analysis = StackAnalysis::with_stack_limit(&cfg, 3);
+
+    let blocks = analysis.blocks();
+    // Three items fit, so the first block isn't checked
+    assert_eq!(blocks[0].growth, 3);
+    assert_eq!(blocks[0].check, StackCheck::None);
+    // The loop overflows the stack on its first iteration, so it's never entered again
+    assert_eq!(blocks[1].entry, Some(2..=2));
+    assert_eq!(blocks[1].check, StackCheck::Entry);
+    let heights = analysis.operation_heights(&cfg);
+    assert_eq!(heights[6], Some(3..=3));
+    assert_eq!(heights[7], None);
+}
+
+#[test]
+fn operations_display_as_mnemonics() {
+    assert_eq!(Operation::Add.to_string(), "ADD");
+    assert_eq!(Operation::Dup(3).to_string(), "DUP3");
+    assert_eq!(Operation::Jumpdest { pc: 7 }.to_string(), "JUMPDEST");
+    assert_eq!(Operation::Label("loop".to_string()).to_string(), "loop:");
+    assert_eq!(
+        Operation::PushLabel("loop".to_string()).to_string(),
+        "PUSH @loop"
+    );
+    assert_eq!(
+        Operation::Push(BigUint::from(0x0102_u16)).to_string(),
+        "PUSH2 0x0102"
+    );
+    assert_eq!(Operation::Rjump(-3).to_string(), "RJUMP 0xfffd");
+    assert_eq!(
+        Operation::Rjumpv(vec![1, -2]).to_string(),
+        "RJUMPV 0x010001fffe"
+    );
+}
+
+#[test]
+fn stack_analysis_gives_the_heights_of_operations() {
+    let program = Program::from_assembly("PUSH1 1 ADD PUSH @next JUMP next: POP").unwrap();
+    let cfg = ControlFlowGraph::new(&program);
+    let analysis = StackAnalysis::new(&cfg);
+
+    let heights = analysis.operation_heights(&cfg);
+    // The ADD underflows, so the jump never runs, and the block it leads to is unreachable
+    let any = Some(0..=1024);
+    assert_eq!(
+        heights,
+        vec![Some(0..=0), Some(1..=1), None, None, any.clone(), any]
+    );
+}
+
+#[test]
+fn optimizer_folds_constants() {
+    let program =
+        Program::from_assembly("PUSH1 2 PUSH1 3 ADD PUSH1 4 MUL ISZERO PUSH1 1 NOT STOP").unwrap();
+
+    let optimized = optimize(&program, SpecId::default());
+
+    let max = (BigUint::from(1_u8) << 256) - 1_u8;
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::ZERO),
+        Operation::Push(max - 1_u8),
+        Operation::Stop,
+    ]);
+    assert_eq!(optimized.program, expected);
+    // The folded PUSHes charge the gas of the operations they replace
+    assert_eq!(optimized.extra_gas, vec![17, 3, 0]);
+}
+
+#[test]
+fn optimizer_drops_popped_pushes() {
+    let program = Program::from_assembly("PUSH1 1 POP PUSH0 PUSH0 POP POP CALLER STOP").unwrap();
+
+    let optimized = optimize(&program, SpecId::default());
+    let expected = Program::from(vec![Operation::Caller, Operation::Stop]);
+    assert_eq!(optimized.program, expected);
+    assert_eq!(optimized.extra_gas, vec![13, 0]);
+
+    // PUSH0 is undefined before Shanghai, so it's kept to halt the program
+    let optimized = optimize(&program, SpecId::London);
+    let expected = Program::from(vec![
+        Operation::Push0,
+        Operation::Push0,
+        Operation::Pop,
+        Operation::Pop,
+        Operation::Caller,
+        Operation::Stop,
+    ]);
+    assert_eq!(optimized.program, expected);
+    assert_eq!(optimized.extra_gas, vec![5, 0, 0, 0, 0, 0]);
+}
+
+#[test]
+fn optimizer_drops_double_iszero_before_tests_for_zero() {
+    let program = Program::from_assembly(
+        "CALLDATASIZE ISZERO ISZERO PUSH @end JUMPI CALLDATASIZE ISZERO ISZERO end:",
+    )
+    .unwrap();
+
+    let optimized = optimize(&program, SpecId::default());
+
+    let operations = optimized.program.operations();
+    assert_eq!(
+        operations,
+        [
+            Operation::CallDataSize,
+            Operation::Push(BigUint::from(9_u8)),
+            Operation::Jumpi,
+            Operation::CallDataSize,
+            Operation::IsZero,
+            Operation::IsZero,
+            Operation::Jumpdest { pc: 9 },
+        ]
+    );
+    assert_eq!(optimized.extra_gas, vec![0, 6, 0, 0, 0, 0, 0]);
+
+    // Without an item on the stack, the ISZERO halts the program
+    let program = Program::from_assembly("ISZERO ISZERO ISZERO").unwrap();
+    let optimized = optimize(&program, SpecId::default());
+    assert_eq!(optimized.program, program);
+}
+
+#[test]
+fn optimizer_keeps_runs_overflowing_the_stack() {
+    let fill = |items| vec![Operation::Push(BigUint::from(1_u8)); items];
+    let fold = [
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Add,
+    ];
+
+    let program = Program::from([fill(1022), fold.to_vec()].concat());
+    let optimized = optimize(&program, SpecId::default());
+    assert_eq!(optimized.program.operations().len(), 1023);
+
+    // The second PUSH overflows the stack
+    let program = Program::from([fill(1023), fold.to_vec()].concat());
+    let optimized = optimize(&program, SpecId::default());
+    assert_eq!(optimized.program, program);
+}
+
+/// Returns a container whose only code section is `code`
+fn eof_container(code: Vec<u8>, max_stack_height: u16) -> Vec<u8> {
+    let code_section = CodeSection {
+        inputs: 0,
+        outputs: NON_RETURNING,
+        max_stack_height,
+        code,
+    };
+    Container::new(vec![code_section], vec![], vec![]).to_bytecode()
+}
+
+#[test]
+fn eof_container_round_trips() {
+    let code_sections = vec![
+        CodeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 1,
+            // PUSH1 1, CALLF 1, POP, STOP
+            code: vec![0x60, 0x01, 0xE3, 0x00, 0x01, 0x50, 0x00],
+        },
+        CodeSection {
+            inputs: 1,
+            outputs: 1,
+            max_stack_height: 2,
+            // DUP1, ADD, RETF
+            code: vec![0x80, 0x01, 0xE4],
+        },
+    ];
+    let container = Container::new(code_sections, vec![vec![0xAA; 4]], vec![1, 2, 3]);
+
+    let bytecode = container.to_bytecode();
+
+    #[rustfmt::skip]
+    let header = [
+        0xEF, 0x00, 0x01,
+        0x01, 0x00, 0x08,
+        0x02, 0x00, 0x02, 0x00, 0x07, 0x00, 0x03,
+        0x03, 0x00, 0x01, 0x00, 0x00, 0x00, 0x04,
+        0xFF, 0x00, 0x03,
+        0x00,
+    ];
+    assert!(is_eof(&bytecode));
+    assert_eq!(bytecode[..header.len()], header);
+    assert_eq!(bytecode.len(), header.len() + 8 + 10 + 4 + 3);
+    assert_eq!(Container::from_bytecode(&bytecode), Ok(container));
+}
+
+#[test]
+fn eof_relative_jumps_are_validated() {
+    // Counts down from 3, jumping back with RJUMPI
+    let code = vec![
+        0x60, 0x03, 0x60, 0x01, 0x90, 0x03, 0x80, 0xE1, 0xFF, 0xF8, 0x50, 0x00,
+    ];
+    assert!(Container::from_bytecode(&eof_container(code, 2)).is_ok());
+
+    // RJUMPV to either of two STOPs
+    let code = vec![0x5F, 0xE2, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00];
+    assert!(Container::from_bytecode(&eof_container(code, 1)).is_ok());
+
+    // The STOP is reached with either 0 or 1 items on the stack
+    let code = vec![0x5F, 0xE1, 0x00, 0x01, 0x5F, 0x00];
+    assert!(Container::from_bytecode(&eof_container(code, 1)).is_ok());
+}
+
+#[test]
+fn eof_invalid_containers() {
+    let valid = eof_container(vec![0x00], 0);
+    let mut trailing = valid.clone();
+    trailing.push(0x00);
+    let mut version_2 = valid.clone();
+    version_2[2] = 0x02;
+    let returning_first_section = Container::new(
+        vec![CodeSection {
+            inputs: 0,
+            outputs: 0,
+            max_stack_height: 0,
+            code: vec![0xE4],
+        }],
+        vec![],
+        vec![],
+    );
+    let unreachable_section = Container::new(
+        vec![
+            CodeSection {
+                inputs: 0,
+                outputs: NON_RETURNING,
+                max_stack_height: 0,
+                code: vec![0x00],
+            },
+            CodeSection {
+                inputs: 0,
+                outputs: 0,
+                max_stack_height: 0,
+                code: vec![0xE4],
+            },
+        ],
+        vec![],
+        vec![],
+    );
+
+    let cases = [
+        (vec![0xEF, 0x01, 0x01], EofError::InvalidMagic),
+        (version_2, EofError::UnsupportedVersion(2)),
+        (
+            valid[..10].to_vec(),
+            EofError::InvalidHeader("the header is truncated"),
+        ),
+        (
+            trailing,
+            EofError::InvalidSize {
+                expected: valid.len(),
+                actual: valid.len() + 1,
+            },
+        ),
+        (
+            returning_first_section.to_bytecode(),
+            EofError::InvalidType {
+                section: 0,
+                reason: "the first section must take no inputs and not return",
+            },
+        ),
+        (
+            unreachable_section.to_bytecode(),
+            EofError::UnreachableSection { section: 1 },
+        ),
+    ];
+    for (bytecode, error) in cases {
+        assert_eq!(Container::from_bytecode(&bytecode), Err(error));
+    }
+}
+
+#[test]
+fn eof_invalid_code() {
+    let (section, pc) = (0, 0);
+    let mut overflowing = vec![0x5F; 1025];
+    overflowing.push(0x00);
+
+    let cases = [
+        (
+            vec![0x5F, 0x56],
+            1,
+            EofError::UndefinedInstruction {
+                section,
+                pc: 1,
+                opcode: 0x56,
+            },
+        ),
+        (vec![0x60], 0, EofError::TruncatedImmediate { section, pc }),
+        (
+            vec![0xE0, 0x00, 0x01, 0x00],
+            0,
+            EofError::InvalidJumpDestination { section, pc },
+        ),
+        (
+            vec![0x60, 0x00, 0xE0, 0xFF, 0xFC],
+            1,
+            EofError::InvalidJumpDestination { section, pc: 2 },
+        ),
+        (
+            vec![0xE3, 0x00, 0x05, 0x00],
+            0,
+            EofError::InvalidSectionIndex { section, pc },
+        ),
+        (
+            vec![0xD1, 0x00, 0x00, 0x50, 0x00],
+            1,
+            EofError::DataLoadOutOfBounds { section, pc },
+        ),
+        (vec![0x5F], 1, EofError::MissingTerminator { section }),
+        (
+            vec![0x00, 0x00],
+            0,
+            EofError::UnreachableInstruction { section, pc: 1 },
+        ),
+        (
+            vec![0x01, 0x00],
+            0,
+            EofError::StackUnderflow { section, pc },
+        ),
+        (
+            overflowing,
+            1023,
+            EofError::StackOverflow { section, pc: 1024 },
+        ),
+        (
+            vec![0x5F, 0xE0, 0xFF, 0xFC],
+            1,
+            EofError::StackHeightMismatch { section, pc: 1 },
+        ),
+        (vec![0xE4], 0, EofError::ReturningMismatch { section }),
+        (
+            vec![0x5F, 0x50, 0x00],
+            0,
+            EofError::MaxStackHeightMismatch {
+                section,
+                declared: 0,
+                computed: 1,
+            },
+        ),
+    ];
+    for (code, max_stack_height, error) in cases {
+        let bytecode = eof_container(code, max_stack_height);
+        assert_eq!(Container::from_bytecode(&bytecode), Err(error));
+    }
+}
+
+#[test]
+fn eof_code_section_decodes_into_program() {
+    // Counts down from 3, jumping back with RJUMPI
+    let code = vec![
+        0x60, 0x03, 0x60, 0x01, 0x90, 0x03, 0x80, 0xE1, 0xFF, 0xF8, 0x50, 0x00,
+    ];
+    let container = Container::from_bytecode(&eof_container(code.clone(), 2)).unwrap();
+
+    let program = container.program(0).unwrap();
+
+    let expected = Program::from(vec![
+        Operation::Push(BigUint::from(3_u8)),
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Swap(1),
+        Operation::Sub,
+        Operation::Dup(1),
+        Operation::Rjumpi(-8),
+        Operation::Pop,
+        Operation::Stop,
+    ]);
+    assert_eq!(program, expected);
+    assert_eq!(program.to_bytecode(), code);
+
+    // EOFCREATE can't be compiled yet
+    let code = vec![0x5F, 0xEC, 0x00, 0x00];
+    let container = Container::new(
+        vec![CodeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 2,
+            code,
+        }],
+        vec![],
+        vec![],
+    );
+    assert_eq!(
+        container.program(0),
+        Err(EofError::UnsupportedInstruction {
+            section: 0,
+            pc: 1,
+            opcode: 0xEC
+        })
+    );
+}
+
+#[test]
+fn eof_functions_decode_into_programs() {
+    let code_sections = vec![
+        CodeSection {
+            inputs: 0,
+            outputs: NON_RETURNING,
+            max_stack_height: 1,
+            // PUSH1 1, CALLF 1, JUMPF 2
+            code: vec![0x60, 0x01, 0xE3, 0x00, 0x01, 0xE5, 0x00, 0x02],
+        },
+        CodeSection {
+            inputs: 1,
+            outputs: 1,
+            max_stack_height: 2,
+            // DUP1, ADD, RETF
+            code: vec![0x80, 0x01, 0xE4],
+        },
+        CodeSection {
+            inputs: 1,
+            outputs: NON_RETURNING,
+            max_stack_height: 1,
+            // POP, STOP
+            code: vec![0x50, 0x00],
+        },
+    ];
+    let bytecode = Container::new(code_sections, vec![], vec![]).to_bytecode();
+    let container = Container::from_bytecode(&bytecode).unwrap();
+
+    let programs = container.programs().unwrap();
+
+    let callee = SectionType {
+        inputs: 1,
+        outputs: 1,
+        max_stack_height: 2,
+    };
+    let jumped_to = SectionType {
+        inputs: 1,
+        outputs: NON_RETURNING,
+        max_stack_height: 1,
+    };
+    assert_eq!(
+        programs[0],
+        Program::from(vec![
+            Operation::Push(BigUint::from(1_u8)),
+            Operation::Callf(1, callee),
+            Operation::Jumpf(2, jumped_to),
+        ])
+    );
+    assert_eq!(
+        programs[1],
+        Program::from(vec![Operation::Dup(1), Operation::Add, Operation::Retf])
+    );
+    assert_eq!(programs[0].to_bytecode(), container.code_sections()[0].code);
+    assert_eq!(programs[0].operations()[1].to_string(), "CALLF 0x0001");
+    assert_eq!(Operation::Retf.to_string(), "RETF");
+
+    // A CALLF takes the inputs of its callee and leaves its outputs, and leaving the
+    // section ends a block
+    let effect = programs[0].operations()[1].stack_effect();
+    assert_eq!((effect.inputs, effect.outputs), (1, 1));
+    let cfg = ControlFlowGraph::new(&programs[0]);
+    assert_eq!(cfg.blocks().len(), 1);
+    assert_eq!(cfg.blocks()[0].exit, Exit::Halt);
+    let cfg = ControlFlowGraph::new(&programs[1]);
+    assert_eq!(cfg.blocks()[0].exit, Exit::Halt);
+}
+
+#[test]
+fn eof_data_instructions_decode_into_programs() {
+    let code_section = CodeSection {
+        inputs: 0,
+        outputs: NON_RETURNING,
+        max_stack_height: 3,
+        // DATASIZE, PUSH0, PUSH0, DATACOPY, DATALOADN 0, DATALOAD, STOP
+        code: vec![0xD2, 0x5F, 0x5F, 0xD3, 0xD1, 0x00, 0x00, 0xD0, 0x00],
+    };
+    let bytecode = Container::new(vec![code_section], vec![], vec![0; 32]).to_bytecode();
+    let container = Container::from_bytecode(&bytecode).unwrap();
+
+    let program = container.program(0).unwrap();
+
+    assert_eq!(
+        program,
+        Program::from(vec![
+            Operation::Datasize,
+            Operation::Push0,
+            Operation::Push0,
+            Operation::Datacopy,
+            Operation::Dataloadn(0),
+            Operation::Dataload,
+            Operation::Stop,
+        ])
+    );
+    assert_eq!(program.to_bytecode(), container.code_sections()[0].code);
+    assert_eq!(program.operations()[4].to_string(), "DATALOADN 0x0000");
+}
+
+#[test]
+fn eof_deep_stack_instructions_decode_into_programs() {
+    // PUSH0 20 times, DUPN 19, SWAPN 19, EXCHANGE 0x12, STOP
+    let mut code = vec![0x5F; 20];
+    code.extend([0xE6, 0x13, 0xE7, 0x13, 0xE8, 0x12, 0x00]);
+    let code_section = CodeSection {
+        inputs: 0,
+        outputs: NON_RETURNING,
+        max_stack_height: 21,
+        code: code.clone(),
+    };
+    let bytecode = Container::new(vec![code_section], vec![], vec![]).to_bytecode();
+    let container = Container::from_bytecode(&bytecode).unwrap();
+
+    let program = container.program(0).unwrap();
+
+    let operations = &program.operations()[20..];
+    assert_eq!(
+        operations,
+        [
+            Operation::Dupn(19),
+            Operation::Swapn(19),
+            Operation::Exchange(0x12),
+            Operation::Stop,
+        ]
+    );
+    assert_eq!(program.to_bytecode(), code);
+    assert_eq!(operations[0].to_string(), "DUPN 0x13");
+
+    // They take all the items they reach, as DUP and SWAP do
+    let effects: Vec<_> = operations[..3]
+        .iter()
+        .map(|operation| {
+            let effect = operation.stack_effect();
+            (effect.inputs, effect.outputs)
+        })
+        .collect();
+    assert_eq!(effects, [(20, 21), (21, 21), (6, 6)]);
+}
@@ -0,0 +1,87 @@
+#![cfg(feature = "proptest")]
+
+use std::collections::HashMap;
+
+use evm_mlir::{
+    constants::ExitStatusCode,
+    executor::Executor,
+    program::{Opcode, Operation, Program},
+    spec::SpecId,
+    strategies::stack_neutral_program,
+    syscall::SyscallContext,
+};
+use proptest::prelude::*;
+
+/// Far more gas than any generated program spends
+const GAS: u64 = 30_000_000;
+
+/// Returns the operations of the program by their offset in the bytecode
+fn operations_by_pc(program: &Program) -> HashMap<u64, &Operation> {
+    let mut pc = 0;
+    let mut operations = HashMap::new();
+    for operation in program.operations() {
+        operations.insert(pc, operation);
+        pc += operation.to_bytecode().len() as u64;
+    }
+    operations
+}
+
+proptest! {
+    // Each case compiles a program, which is slow
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn generated_programs_compile(program in any::<Program>()) {
+        prop_assert!(Executor::compile(&program, SpecId::default()).is_ok());
+    }
+
+    #[test]
+    fn generated_programs_round_trip_through_bytecode(program in any::<Program>()) {
+        let bytecode = program.to_bytecode();
+
+        prop_assert_eq!(Program::from_bytecode(&bytecode).unwrap(), program);
+    }
+
+    #[test]
+    fn generated_programs_run_to_their_end(program in any::<Program>()) {
+        let executor = Executor::compile(&program, SpecId::default()).unwrap();
+        let mut context = SyscallContext::default();
+
+        let result = executor.execute(&mut context, GAS);
+
+        prop_assert_eq!(result, ExitStatusCode::Stop.to_u8());
+    }
+
+    #[test]
+    fn stack_neutral_programs_stop_with_an_empty_stack(program in stack_neutral_program()) {
+        let executor = Executor::compile_instrumented(&program, SpecId::default()).unwrap();
+        let mut context = SyscallContext::default();
+        context.enable_tracing();
+
+        let result = executor.execute(&mut context, GAS);
+
+        prop_assert_eq!(result, ExitStatusCode::Stop.to_u8());
+        let last_step = context.trace().last().unwrap();
+        prop_assert_eq!(last_step.op, Opcode::STOP as u8);
+        prop_assert!(last_step.stack.is_empty());
+    }
+
+    #[test]
+    fn stack_effects_match_the_execution(program in any::<Program>()) {
+        let executor = Executor::compile_instrumented(&program, SpecId::default()).unwrap();
+        let mut context = SyscallContext::default();
+        context.enable_tracing();
+        executor.execute(&mut context, GAS);
+
+        let operations = operations_by_pc(&program);
+        let steps = context.trace();
+        for (index, step) in steps.iter().enumerate() {
+            let effect = operations[&step.pc].stack_effect();
+            prop_assert!(step.stack.len() >= effect.inputs);
+            if let Some(next_step) = steps.get(index + 1) {
+                let expected_len = step.stack.len() as isize + effect.difference();
+                prop_assert_eq!(next_step.stack.len() as isize, expected_len);
+            }
+        }
+    }
+}
@@ -0,0 +1,198 @@
+#![cfg(feature = "revm")]
+
+use evm_mlir::{
+    db::{AccountInfo, Host},
+    env::{Address, BlockEnv, Env},
+    executor::Executor,
+    program::{Operation, Program},
+    revm_db::RevmDatabaseHost,
+    spec::SpecId,
+    syscall::{SyscallContext, U256},
+};
+use num_bigint::BigUint;
+use revm::{
+    db::{CacheDB, EmptyDB},
+    primitives::{
+        AccountInfo as RevmAccountInfo, Address as RevmAddress, Bytecode, Bytes, B256,
+        U256 as RevmU256,
+    },
+};
+use std::{cell::RefCell, rc::Rc};
+
+const ADDRESS: Address = [0x10; 20];
+const OTHER_ADDRESS: Address = [0x20; 20];
+
+fn run(program: Vec<Operation>, env: Env, host: &Rc<RefCell<RevmDatabaseHost<CacheDB<EmptyDB>>>>) {
+    let program = Program::from(program);
+    let executor = Executor::compile(&program, SpecId::default()).expect("failed to compile");
+    let mut context = SyscallContext::with_host(env, Vec::new(), host.clone());
+    let result = executor.run(&mut context, 1e7 as _);
+    assert!(result.is_success(), "{result:?}");
+}
+
+fn env_at(address: Address) -> Env {
+    let mut env = Env::default();
+    env.call.address = address;
+    env
+}
+
+#[test]
+fn storage_is_read_from_the_database() {
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.insert_account_storage(
+        RevmAddress::from(ADDRESS),
+        RevmU256::from(1),
+        RevmU256::from(42),
+    )
+    .unwrap();
+    let host = RevmDatabaseHost::new(db);
+
+    assert_eq!(host.sload(&ADDRESS, &U256::from(1)), U256::from(42));
+    assert_eq!(host.sload(&ADDRESS, &U256::from(2)), U256::default());
+}
+
+#[test]
+fn cleared_storage_hides_the_database() {
+    let mut db = CacheDB::new(EmptyDB::default());
+    for key in [1, 2] {
+        db.insert_account_storage(
+            RevmAddress::from(ADDRESS),
+            RevmU256::from(key),
+            RevmU256::from(42),
+        )
+        .unwrap();
+    }
+    let mut host = RevmDatabaseHost::new(db);
+    host.sstore(ADDRESS, U256::from(1), U256::from(7));
+
+    host.clear_storage(&ADDRESS);
+    host.sstore(ADDRESS, U256::from(3), U256::from(9));
+
+    assert_eq!(host.sload(&ADDRESS, &U256::from(1)), U256::default());
+    assert_eq!(host.sload(&ADDRESS, &U256::from(2)), U256::default());
+    assert_eq!(host.sload(&ADDRESS, &U256::from(3)), U256::from(9));
+    assert!(host.cleared_storage().contains(&ADDRESS));
+}
+
+#[test]
+fn accounts_are_read_from_the_database() {
+    let code = vec![0x60, 0x01, 0x00];
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.insert_account_info(
+        RevmAddress::from(ADDRESS),
+        RevmAccountInfo::new(
+            RevmU256::from(1000),
+            3,
+            B256::ZERO,
+            Bytecode::new_raw(Bytes::from(code.clone())),
+        ),
+    );
+    let host = RevmDatabaseHost::new(db);
+
+    let expected = AccountInfo {
+        balance: U256::from(1000),
+        nonce: 3,
+        code,
+    };
+    assert_eq!(host.account(&ADDRESS), Some(expected));
+    assert_eq!(host.account(&OTHER_ADDRESS), None);
+}
+
+#[test]
+fn block_hashes_are_read_from_the_database() {
+    let hash = B256::repeat_byte(0xAB);
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.block_hashes.insert(RevmU256::from(9), hash);
+    let host = RevmDatabaseHost::new(db);
+
+    assert_eq!(host.block_hash(&U256::from(9)), U256::from_be_bytes(hash.0));
+}
+
+#[test]
+fn program_changes_are_kept_in_the_host() {
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.insert_account_storage(
+        RevmAddress::from(ADDRESS),
+        RevmU256::from(1),
+        RevmU256::from(42),
+    )
+    .unwrap();
+    let host = Rc::new(RefCell::new(RevmDatabaseHost::new(db)));
+    // Copies slot 1 into slot 2
+    let program = vec![
+        Operation::Push(BigUint::from(1_u8)),
+        Operation::Sload,
+        Operation::Push(BigUint::from(2_u8)),
+        Operation::Sstore,
+    ];
+
+    run(program, env_at(ADDRESS), &host);
+
+    let host = host.borrow();
+    assert_eq!(host.sload(&ADDRESS, &U256::from(2)), U256::from(42));
+    assert_eq!(
+        host.changed_storage().get(&(ADDRESS, U256::from(2))),
+        Some(&U256::from(42))
+    );
+}
+
+#[test]
+fn program_reads_block_hashes_from_the_database() {
+    let mut db = CacheDB::new(EmptyDB::default());
+    db.block_hashes
+        .insert(RevmU256::from(9), B256::repeat_byte(0xAB));
+    let host = Rc::new(RefCell::new(RevmDatabaseHost::new(db)));
+    let env = Env {
+        block: BlockEnv {
+            number: U256::from(10),
+            ..Default::default()
+        },
+        ..env_at(ADDRESS)
+    };
+    // Stores the hash of block 9 in slot 0
+    let program = vec![
+        Operation::Push(BigUint::from(9_u8)),
+        Operation::Blockhash,
+        Operation::Push0,
+        Operation::Sstore,
+    ];
+
+    run(program, env, &host);
+
+    let stored = host.borrow().sload(&ADDRESS, &U256::default());
+    assert_eq!(stored, U256::from_be_bytes([0xAB; 32]));
+}
+
+#[test]
+fn database_errors_are_recorded() {
+    #[derive(Debug)]
+    struct FailingDB;
+
+    impl revm::Database for FailingDB {
+        type Error = &'static str;
+
+        fn basic(&mut self, _: RevmAddress) -> Result<Option<RevmAccountInfo>, Self::Error> {
+            Err("basic")
+        }
+
+        fn code_by_hash(&mut self, _: B256) -> Result<Bytecode, Self::Error> {
+            Err("code_by_hash")
+        }
+
+        fn storage(&mut self, _: RevmAddress, _: RevmU256) -> Result<RevmU256, Self::Error> {
+            Err("storage")
+        }
+
+        fn block_hash(&mut self, _: RevmU256) -> Result<B256, Self::Error> {
+            Err("block_hash")
+        }
+    }
+
+    let mut host = RevmDatabaseHost::new(FailingDB);
+
+    assert_eq!(host.sload(&ADDRESS, &U256::from(1)), U256::default());
+    assert_eq!(host.account(&ADDRESS), None);
+    // Only the first error is kept
+    assert_eq!(host.take_error(), Some("storage"));
+    assert_eq!(host.take_error(), None);
+}
@@ -0,0 +1,49 @@
+#![cfg(feature = "serde")]
+
+use evm_mlir::program::{Operation, Program};
+use num_bigint::BigUint;
+
+#[test]
+fn program_serializes_as_its_operations() {
+    let program = Program::from(vec![
+        Operation::Push(BigUint::from(42_u8)),
+        Operation::PushN(2, BigUint::from(4_u8)),
+        Operation::Jump,
+        Operation::Jumpdest { pc: 4 },
+        Operation::Dup(1),
+        Operation::Label("end".to_string()),
+    ]);
+
+    let json = serde_json::to_string(&program).unwrap();
+
+    let expected = concat!(
+        r#"[{"Push":"0x2a"},{"PushN":[2,"0x4"]},"Jump","#,
+        r#"{"Jumpdest":{"pc":4}},{"Dup":1},{"Label":"end"}]"#,
+    );
+    assert_eq!(json, expected);
+    assert_eq!(serde_json::from_str::<Program>(&json).unwrap(), program);
+}
+
+#[test]
+fn program_round_trips_through_json() {
+    let mut bytecode = vec![0x7F];
+    bytecode.extend([0xFF; 32]);
+    bytecode.extend([0x5F, 0x61, 0x00, 0x0A, 0x56, 0x00, 0x5B, 0x58, 0xA2, 0x00]);
+    let program = Program::from_bytecode(&bytecode).unwrap();
+
+    let json = serde_json::to_string(&program).unwrap();
+
+    assert_eq!(serde_json::from_str::<Program>(&json).unwrap(), program);
+}
+
+#[test]
+fn invalid_push_values_are_rejected() {
+    for json in [
+        r#"[{"Push":"2a"}]"#,
+        r#"[{"Push":"0xzz"}]"#,
+        r#"[{"Push":"0x"}]"#,
+    ] {
+        let error = serde_json::from_str::<Program>(json).unwrap_err();
+        assert!(error.to_string().contains("invalid hex value"), "{error}");
+    }
+}